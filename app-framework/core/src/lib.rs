@@ -16,8 +16,8 @@ use tab_client::{
 use tab_client::{TabClient, TabClientConfig, TabClientError, TabSwapchain};
 use tab_protocol::{BufferIndex, ButtonState, InputEventPayload, KeyState, TouchContact};
 use thiserror::Error;
-use tracing::{debug, info};
-pub use tab_protocol::{SessionCreatedPayload, SessionInfo, SessionRole};
+use tracing::{debug, info, warn};
+pub use tab_protocol::{PresentMode, SessionCreatedPayload, SessionInfo, SessionRole};
 
 const BTN_LEFT: u32 = 272;
 
@@ -37,6 +37,7 @@ pub struct Config {
 	socket_path: PathBuf,
 	render_node_path: Option<PathBuf>,
 	render_mode: RenderMode,
+	present_mode: PresentMode,
 	opengl_version: (u8, u8),
 }
 
@@ -48,6 +49,7 @@ impl Config {
 			socket_path: tab_protocol::DEFAULT_SOCKET_PATH.into(),
 			render_node_path: None,
 			render_mode: RenderMode::Scheduled,
+			present_mode: PresentMode::Fifo,
 			opengl_version: (3, 3),
 		}
 	}
@@ -85,6 +87,14 @@ impl Config {
 		self
 	}
 
+	/// Sets how Shift queues frames submitted faster than the display refreshes.
+	///
+	/// `Mailbox` always shows the newest frame, which lowers latency for interactive sessions.
+	pub fn set_present_mode(&mut self, mode: PresentMode) -> &mut Self {
+		self.present_mode = mode;
+		self
+	}
+
 	/// Requests a specific OpenGL/OpenGL ES version.
 	pub fn opengl_version(&mut self, major: u8, minor: u8) -> &mut Self {
 		self.opengl_version = (major, minor);
@@ -202,6 +212,40 @@ fn current_layout(monitors: &HashMap<String, MonitorRuntime>) -> Vec<MonitorPlac
 		.collect()
 }
 
+/// Layout position of an absolute pointer or touch position. shift scales those from 0 to 65535
+/// across `monitor_id`; without a monitor they span the whole layout.
+fn absolute_to_layout(
+	placements: &[MonitorPlacement],
+	monitor_id: Option<&str>,
+	mut x: f64,
+	mut y: f64,
+) -> (f64, f64) {
+	if x > 1.0 || y > 1.0 {
+		x /= 65535.0;
+		y /= 65535.0;
+	}
+	if let Some(monitor) = monitor_id.and_then(|id| placements.iter().find(|m| m.id == id)) {
+		return clamp_point_to_layout(
+			placements,
+			monitor.x as f64 + x * monitor.width as f64,
+			monitor.y as f64 + y * monitor.height as f64,
+		);
+	}
+	let max_x = placements
+		.iter()
+		.map(|m| m.x.saturating_add(m.width))
+		.max()
+		.unwrap_or(0)
+		.max(1) as f64;
+	let max_y = placements
+		.iter()
+		.map(|m| m.y.saturating_add(m.height))
+		.max()
+		.unwrap_or(0)
+		.max(1) as f64;
+	clamp_point_to_layout(placements, x * max_x, y * max_y)
+}
+
 /// Render callback payload containing the acquired client buffer.
 #[derive(Debug, Clone)]
 pub struct RenderEvent {
@@ -239,6 +283,13 @@ pub struct MonitorAddedEvent {
 	pub monitor: Monitor,
 }
 
+/// Emitted when a monitor changes mode. Its buffers have already been recreated at the new size.
+#[derive(Debug, Clone)]
+pub struct MonitorChangedEvent {
+	/// Updated monitor metadata.
+	pub monitor: Monitor,
+}
+
 /// Emitted when a monitor is removed.
 #[derive(Debug, Clone)]
 pub struct MonitorRemovedEvent {
@@ -280,6 +331,8 @@ pub struct KeyEvent {
 	pub key: u32,
 	/// Key state.
 	pub state: KeyState,
+	/// Sent again by shift while the key is held.
+	pub repeat: bool,
 }
 
 impl KeyEvent {
@@ -526,6 +579,8 @@ pub trait Application: Sized + 'static {
 	fn init(ctx: &mut InitContext<Self>) -> anyhow::Result<Self>;
 
 	/// Called after a buffer is acquired and ready to be rendered into.
+	/// Call [`Context::mark_frame_unchanged`] to skip submitting it; schedule a new frame once
+	/// something changes.
 	fn on_render(&mut self, _ctx: &mut Context<Self>, _ev: RenderEvent) {}
 	/// Called when a previously rendered buffer is presented/released.
 	fn on_present(&mut self, _ctx: &mut Context<Self>, _ev: PresentEvent) {}
@@ -533,6 +588,8 @@ pub trait Application: Sized + 'static {
 	fn on_monitor_added(&mut self, _ctx: &mut Context<Self>, _ev: MonitorAddedEvent) {}
 	/// Called when a monitor is removed.
 	fn on_monitor_removed(&mut self, _ctx: &mut Context<Self>, _ev: MonitorRemovedEvent) {}
+	/// Called when a monitor changes resolution or refresh rate.
+	fn on_monitor_changed(&mut self, _ctx: &mut Context<Self>, _ev: MonitorChangedEvent) {}
 	/// Called when session state changes.
 	fn on_session_state(&mut self, _ctx: &mut Context<Self>, _ev: SessionEvent) {}
 	/// Called for every raw input event.
@@ -570,6 +627,7 @@ pub struct Context<'a, A: Application> {
 	scheduled: &'a mut HashSet<String>,
 	watched_fds: &'a mut HashSet<RawFd>,
 	next_acquire_fence: &'a mut Option<OwnedFd>,
+	frame_unchanged: &'a mut bool,
 	cursor_position: &'a mut (f64, f64),
 	exiting: &'a mut bool,
 	_marker: PhantomData<A>,
@@ -664,6 +722,11 @@ impl<'a, A: Application> Context<'a, A> {
 		*self.next_acquire_fence = Some(fence_fd);
 	}
 
+	/// Marks the frame being rendered as identical to the previous one, so it is not submitted.
+	pub fn mark_frame_unchanged(&mut self) {
+		*self.frame_unchanged = true;
+	}
+
 	/// Returns current authenticated session information.
 	pub fn session(&self) -> &SessionInfo {
 		self.client.session()
@@ -721,6 +784,7 @@ pub struct TabAppFramework<A: Application> {
 	event_queue: Rc<RefCell<VecDeque<QueuedEvent>>>,
 	exiting: bool,
 	next_acquire_fence: Option<OwnedFd>,
+	frame_unchanged: bool,
 	stats: LoopStats,
 	cursor_position: (f64, f64),
 	touch_contacts: HashMap<i32, (f64, f64)>,
@@ -736,7 +800,9 @@ impl<A: Application> TabAppFramework<A> {
 			.map_err(|e| FrameworkError::Config(format!("app init failed: {e:#}")))?;
 
 		let cfg = init_ctx.config().clone();
-		let mut client_cfg = TabClientConfig::new(cfg.token()).socket_path(cfg.socket_path.clone());
+		let mut client_cfg = TabClientConfig::new(cfg.token())
+			.socket_path(cfg.socket_path.clone())
+			.present_mode(cfg.present_mode);
 		if let Some(render_node) = cfg.render_node_path {
 			client_cfg = client_cfg.render_node(render_node);
 		}
@@ -783,6 +849,7 @@ impl<A: Application> TabAppFramework<A> {
 				event_queue: queue,
 				exiting: false,
 				next_acquire_fence: None,
+				frame_unchanged: false,
 				stats: LoopStats::new(),
 				cursor_position: initial_cursor,
 				touch_contacts: HashMap::new(),
@@ -925,6 +992,35 @@ impl<A: Application> TabAppFramework<A> {
 							)
 						});
 					}
+					TabMonitorEvent::Changed(state) => {
+						let monitor = Monitor::from_tab_monitor(&state);
+						let swapchain = self.client.create_swapchain(&monitor.id)?;
+						self.scheduled.remove(&monitor.id);
+						if self.render_mode == RenderMode::Eager {
+							self.scheduled.insert(monitor.id.clone());
+						}
+						self.monitors.insert(
+							monitor.id.clone(),
+							MonitorRuntime::new(monitor.clone(), swapchain),
+						);
+						recompute_layout(&mut self.monitors);
+						let placements = current_layout(&self.monitors);
+						self.cursor_position =
+							clamp_point_to_layout(&placements, self.cursor_position.0, self.cursor_position.1);
+						let monitor = self
+							.monitors
+							.get(&state.info.id)
+							.map(|m| m.monitor.clone())
+							.unwrap_or(monitor);
+						self.call_app(|app, ctx| {
+							app.on_monitor_changed(
+								ctx,
+								MonitorChangedEvent {
+									monitor: monitor.clone(),
+								},
+							)
+						});
+					}
 					TabMonitorEvent::Removed { monitor_id, name } => {
 						self.monitors.remove(&monitor_id);
 						recompute_layout(&mut self.monitors);
@@ -943,13 +1039,69 @@ impl<A: Application> TabAppFramework<A> {
 						});
 					}
 				},
-				QueuedEvent::Render(ev) => {
+				// Reallocated in a format shift listed; to the app that looks like a mode change.
+				QueuedEvent::Render(TabRenderEvent::FramebufferLinkFailed {
+					monitor_id, reason, ..
+				}) => {
+					info!(%monitor_id, %reason, "framebuffer link failed, reallocating swapchain");
+					let Some(monitor) = self.monitors.get(&monitor_id).map(|m| m.monitor.clone()) else {
+						continue;
+					};
+					let swapchain = self.client.create_swapchain(&monitor_id)?;
+					self.monitors.insert(
+						monitor_id.clone(),
+						MonitorRuntime::new(monitor.clone(), swapchain),
+					);
+					if self.render_mode == RenderMode::Eager {
+						self.scheduled.insert(monitor_id.clone());
+					}
+					self.call_app(|app, ctx| {
+						app.on_monitor_changed(
+							ctx,
+							MonitorChangedEvent {
+								monitor: monitor.clone(),
+							},
+						)
+					});
+				}
+				// shift dropped its import to save GPU memory, after releasing every buffer it held.
+				QueuedEvent::Render(TabRenderEvent::RelinkRequested { monitor_id }) => {
+					let Some(monitor) = self.monitors.get(&monitor_id) else {
+						continue;
+					};
+					info!(%monitor_id, "relinking swapchain evicted by shift");
+					self.client.framebuffer_link(&monitor.swapchain)?;
+					if self.render_mode == RenderMode::Eager {
+						self.scheduled.insert(monitor_id);
+					}
+				}
+				// shift lost its imports, e.g. over suspend; the client linked the swapchains again but
+				// what they showed is gone, even for apps that only draw on demand.
+				QueuedEvent::Render(TabRenderEvent::BuffersInvalidated { monitor_ids }) => {
+					info!(?monitor_ids, "redrawing monitors whose buffers shift lost");
+					for monitor_id in monitor_ids {
+						if self.monitors.contains_key(&monitor_id) {
+							self.scheduled.insert(monitor_id);
+						}
+					}
+				}
+				QueuedEvent::Render(TabRenderEvent::FramesDropped { count, .. }) => {
+					self.stats.frames_dropped += u64::from(count);
+				}
+				QueuedEvent::Render(TabRenderEvent::PerformanceWarning {
+					monitor_id,
+					kind,
+					duration_ms,
+					frames,
+				}) => {
+					warn!(%monitor_id, ?kind, duration_ms, frames, "shift reports a rendering problem");
+				}
+				QueuedEvent::Render(TabRenderEvent::BufferReleased {
+					monitor_id,
+					buffer,
+					release_fence_fd,
+				}) => {
 					self.stats.buffer_release_events += 1;
-					let TabRenderEvent::BufferReleased {
-						monitor_id,
-						buffer,
-						release_fence_fd,
-					} = ev;
 					self.stats.instant_log(&format!(
 						"buffer_release event monitor={monitor_id} buffer={} fence={}",
 						buffer as u8,
@@ -985,7 +1137,9 @@ impl<A: Application> TabAppFramework<A> {
 					}
 				}
 				QueuedEvent::Input(ev) => {
-					let TabInputEvent::Event(payload) = ev;
+					let TabInputEvent::Event(payload) = ev else {
+						continue;
+					};
 					self.call_app(|app, ctx| {
 						app.on_input(
 							ctx,
@@ -1000,6 +1154,7 @@ impl<A: Application> TabAppFramework<A> {
 								time_usec,
 								key,
 								state,
+								repeat,
 							} => {
 								self.call_app(|app, ctx| {
 									app.on_key(
@@ -1009,6 +1164,7 @@ impl<A: Application> TabAppFramework<A> {
 											time_usec,
 											key,
 											state,
+											repeat,
 										},
 									)
 								});
@@ -1072,12 +1228,17 @@ impl<A: Application> TabAppFramework<A> {
 								time_usec,
 								x_transformed,
 								y_transformed,
+								monitor_id,
 								..
 							} => {
 								let old_position = self.cursor_position;
 								let placements = current_layout(&self.monitors);
-								self.cursor_position =
-									clamp_point_to_layout(&placements, x_transformed, y_transformed);
+								self.cursor_position = absolute_to_layout(
+									&placements,
+									monitor_id.as_deref(),
+									x_transformed,
+									y_transformed,
+								);
 								self.emit_cursor_move(
 									PointerMoveEvent {
 										device,
@@ -1132,27 +1293,13 @@ impl<A: Application> TabAppFramework<A> {
 								contact,
 							} => {
 								let placements = current_layout(&self.monitors);
-								let mut x = contact.x_transformed;
-								let mut y = contact.y_transformed;
-								if x > 1.0 || y > 1.0 {
-									x /= 65535.0;
-									y /= 65535.0;
-								}
-								let max_x = placements
-									.iter()
-									.map(|m| m.x.saturating_add(m.width))
-									.max()
-									.unwrap_or(0)
-									.max(1) as f64;
-								let max_y = placements
-									.iter()
-									.map(|m| m.y.saturating_add(m.height))
-									.max()
-									.unwrap_or(0)
-									.max(1) as f64;
 								let old_position = self.cursor_position;
-								self.cursor_position =
-									clamp_point_to_layout(&placements, x * max_x, y * max_y);
+								self.cursor_position = absolute_to_layout(
+									&placements,
+									contact.monitor_id.as_deref(),
+									contact.x_transformed,
+									contact.y_transformed,
+								);
 								self.touch_contacts
 									.insert(contact.id, self.cursor_position);
 								self.emit_touch(TouchEvent::Down {
@@ -1190,26 +1337,12 @@ impl<A: Application> TabAppFramework<A> {
 								contact,
 							} => {
 								let placements = current_layout(&self.monitors);
-								let mut x = contact.x_transformed;
-								let mut y = contact.y_transformed;
-								if x > 1.0 || y > 1.0 {
-									x /= 65535.0;
-									y /= 65535.0;
-								}
-								let max_x = placements
-									.iter()
-									.map(|m| m.x.saturating_add(m.width))
-									.max()
-									.unwrap_or(0)
-									.max(1) as f64;
-								let max_y = placements
-									.iter()
-									.map(|m| m.y.saturating_add(m.height))
-									.max()
-									.unwrap_or(0)
-									.max(1) as f64;
-								let next =
-									clamp_point_to_layout(&placements, x * max_x, y * max_y);
+								let next = absolute_to_layout(
+									&placements,
+									contact.monitor_id.as_deref(),
+									contact.x_transformed,
+									contact.y_transformed,
+								);
 								self.touch_contacts.insert(contact.id, next);
 								self.emit_touch(TouchEvent::Motion {
 									device,
@@ -1404,16 +1537,26 @@ impl<A: Application> TabAppFramework<A> {
 				continue;
 			};
 			self.next_acquire_fence = None;
+			self.frame_unchanged = false;
 			self.call_app(|app, ctx| app.on_render(ctx, render_ev.clone()));
-			let acquire_fence = self
-				.next_acquire_fence
-				.as_ref()
-				.map(|fd| fd.as_raw_fd());
+			if self.frame_unchanged {
+				self.stats.frames_skipped += 1;
+				if let Some(monitor_rt) = self.monitors.get_mut(&monitor_id) {
+					monitor_rt.swapchain.rollback();
+				}
+				if let Err(err) = self.client.skip_frame(&monitor_id) {
+					let ferr: FrameworkError = err.into();
+					self.call_app(|app, ctx| app.on_error(ctx, &ferr));
+				}
+				continue;
+			}
+			let acquire_fence = self.next_acquire_fence.take();
 			self.stats.instant_log(&format!(
 				"request_buffer send monitor={monitor_id} buffer={} fence={}",
 				buffer_idx as u8,
 				acquire_fence
-					.map(|fd| fd.to_string())
+					.as_ref()
+					.map(|fd| fd.as_raw_fd().to_string())
 					.unwrap_or_else(|| "none".to_string())
 			));
 
@@ -1576,6 +1719,7 @@ impl<A: Application> TabAppFramework<A> {
 			scheduled: &mut self.scheduled,
 			watched_fds: &mut self.watched_fds,
 			next_acquire_fence: &mut self.next_acquire_fence,
+			frame_unchanged: &mut self.frame_unchanged,
 			cursor_position: &mut self.cursor_position,
 			exiting: &mut self.exiting,
 			_marker: PhantomData,
@@ -1592,6 +1736,8 @@ struct LoopStats {
 	acquire_miss: u64,
 	request_ok: u64,
 	request_err: u64,
+	frames_skipped: u64,
+	frames_dropped: u64,
 	buffer_release_events: u64,
 	release_fence_signaled: u64,
 	present_callbacks: u64,
@@ -1610,6 +1756,8 @@ impl LoopStats {
 			acquire_miss: 0,
 			request_ok: 0,
 			request_err: 0,
+			frames_skipped: 0,
+			frames_dropped: 0,
 			buffer_release_events: 0,
 			release_fence_signaled: 0,
 			present_callbacks: 0,
@@ -1626,6 +1774,8 @@ impl LoopStats {
 			acquire_miss = self.acquire_miss,
 			request_ok = self.request_ok,
 			request_err = self.request_err,
+			frames_skipped = self.frames_skipped,
+			frames_dropped = self.frames_dropped,
 			releases = self.buffer_release_events,
 			fence_ready = self.release_fence_signaled,
 			present = self.present_callbacks,
@@ -1636,6 +1786,8 @@ impl LoopStats {
 		self.acquire_miss = 0;
 		self.request_ok = 0;
 		self.request_err = 0;
+		self.frames_skipped = 0;
+		self.frames_dropped = 0;
 		self.buffer_release_events = 0;
 		self.release_fence_signaled = 0;
 		self.present_callbacks = 0;
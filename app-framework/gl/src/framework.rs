@@ -34,6 +34,13 @@ pub trait GlApplication: Sized + 'static {
 		_ev: core::MonitorRemovedEvent,
 	) {
 	}
+	/// Called when a monitor changes mode.
+	fn on_monitor_changed(
+		&mut self,
+		_ctx: &mut GlEventContext<'_, '_, Self>,
+		_ev: core::MonitorChangedEvent,
+	) {
+	}
 	/// Called when session state updates arrive.
 	fn on_session_state(&mut self, _ctx: &mut GlEventContext<'_, '_, Self>, _ev: core::SessionEvent) {
 	}
@@ -295,8 +302,9 @@ impl<A: GlApplication> core::Application for GlBridge<A> {
 			gl: &mut self.gl,
 		};
 		self.app.on_render(&mut ctx, ev);
-		match ctx.gl.create_acquire_fence_fd() {
-			Ok(fence_fd) => ctx.core.set_next_acquire_fence(fence_fd),
+		match ctx.gl.finish_frame() {
+			Ok(Some(fence_fd)) => ctx.core.set_next_acquire_fence(fence_fd),
+			Ok(None) => {}
 			Err(err) => {
 				let ferr = core::FrameworkError::Config(format!("create acquire fence failed: {err}"));
 				self.app.on_error(&mut ctx, &ferr);
@@ -329,6 +337,15 @@ impl<A: GlApplication> core::Application for GlBridge<A> {
 		self.app.on_monitor_removed(&mut ctx, ev);
 	}
 
+	fn on_monitor_changed(&mut self, ctx: &mut core::Context<Self>, ev: core::MonitorChangedEvent) {
+		self.gl.release_monitor_targets(&ev.monitor.id);
+		let mut ctx = GlEventContext {
+			core: ctx,
+			gl: &mut self.gl,
+		};
+		self.app.on_monitor_changed(&mut ctx, ev);
+	}
+
 	fn on_session_state(&mut self, ctx: &mut core::Context<Self>, ev: core::SessionEvent) {
 		let mut ctx = GlEventContext {
 			core: ctx,
@@ -5,7 +5,7 @@ mod egl;
 mod framework;
 
 use std::collections::HashMap;
-use std::ffi::{CString, c_void};
+use std::ffi::{CStr, CString, c_void};
 use std::fs::OpenOptions;
 use std::os::fd::{FromRawFd, OwnedFd};
 use std::path::{Path, PathBuf};
@@ -60,13 +60,15 @@ pub enum GlError {
 	ContextCreationFailed(String),
 	#[error("eglMakeCurrent failed (error={0:#X})")]
 	MakeCurrentFailed(i32),
+	#[error("eglQueryContext failed (error={0:#X})")]
+	QueryContextFailed(i32),
 	#[error(
 		"required EGL image entrypoints are unavailable (need eglCreateImageKHR or eglCreateImage, and eglDestroyImageKHR or eglDestroyImage)"
 	)]
 	MissingEglImageExt,
 	#[error("missing glEGLImageTargetTexture2DOES")]
 	MissingGlEglImageTarget,
-	#[error("missing eglDupNativeFenceFDANDROID")]
+	#[error("missing EGL_ANDROID_native_fence_sync")]
 	MissingEglDupNativeFenceFd,
 	#[error("eglCreateSync failed (error={0:#X})")]
 	CreateSyncFailed(i32),
@@ -78,16 +80,40 @@ pub enum GlError {
 
 type GlEglImageTargetTexture2DOes = unsafe extern "system" fn(u32, *const c_void);
 
+/// Logs a `GL_KHR_debug` message at a level going by its severity, errors always as errors.
+fn log_debug_message(_source: u32, kind: u32, id: u32, severity: u32, message: &str) {
+	let message = message.trim_end();
+	let severity = if kind == glow::DEBUG_TYPE_ERROR {
+		glow::DEBUG_SEVERITY_HIGH
+	} else {
+		severity
+	};
+	match severity {
+		glow::DEBUG_SEVERITY_HIGH => tracing::error!(target: "gl", id, "{message}"),
+		glow::DEBUG_SEVERITY_MEDIUM => tracing::warn!(target: "gl", id, "{message}"),
+		glow::DEBUG_SEVERITY_LOW => tracing::info!(target: "gl", id, "{message}"),
+		_ => tracing::debug!(target: "gl", id, "{message}"),
+	}
+}
+
 /// OpenGL/EGL context and DMA-BUF render-target cache.
+///
+/// Like any EGL context it is current on at most one thread at a time, and GL calls only
+/// reach the context current on the calling thread. The type is `!Send`; when mixing it with
+/// other contexts on one thread, call [`GlContext::make_current`] before using it.
 pub struct GlContext {
 	egl: egl::Egl,
 	display: egl::types::EGLDisplay,
 	context: egl::types::EGLContext,
-	_gbm_device: GbmDevice<std::fs::File>,
+	/// False for [`GlContext::new_shared`], where the display belongs to the caller.
+	owns_display: bool,
+	_gbm_device: Option<GbmDevice<std::fs::File>>,
 	egl_lib: libloading::Library,
 	gl_lib: libloading::Library,
 	glow: glow::Context,
 	version: GlVersion,
+	/// Whether the display has `EGL_ANDROID_native_fence_sync`, see [`GlContext::finish_frame`].
+	native_fence_sync: bool,
 	egl_image_target_texture_2d_oes: GlEglImageTargetTexture2DOes,
 	dmabuf_targets: HashMap<RenderTargetKey, DmabufTarget>,
 }
@@ -95,10 +121,7 @@ pub struct GlContext {
 impl GlContext {
 	/// Creates a surfaceless EGL context backed by a GBM render node.
 	pub fn new(version: GlVersion, render_node: Option<&Path>) -> Result<Self, GlError> {
-		let egl_lib = unsafe { libloading::Library::new("libEGL.so.1") }
-			.map_err(|e| GlError::LoadEglLibrary(e.to_string()))?;
-		let gl_lib = unsafe { libloading::Library::new("libGL.so.1") }
-			.map_err(|e| GlError::LoadGlLibrary(e.to_string()))?;
+		let (egl_lib, gl_lib) = load_libraries()?;
 
 		// Bootstrap with dlsym first so we can use eglGetProcAddress for extension entrypoints.
 		let egl_boot =
@@ -135,99 +158,50 @@ impl GlContext {
 		if ok == 0 {
 			return Err(GlError::InitializeFailed(unsafe { egl_boot.GetError() }));
 		}
-		let egl = egl::Egl::load_with(|name| {
-			if let Some(sym) = load_symbol(&egl_lib, name) {
-				return sym;
-			}
-			if !egl_boot.GetProcAddress.is_loaded() {
-				return ptr::null();
-			}
-			let Ok(c_name) = CString::new(name) else {
-				return ptr::null();
-			};
-			let ptr = unsafe { egl_boot.GetProcAddress(c_name.as_ptr()) };
-			if ptr.is_null() { ptr::null() } else { ptr.cast() }
-		});
-
-		const EGL_CONTEXT_MAJOR_VERSION: i32 = 0x3098;
-		const EGL_CONTEXT_MINOR_VERSION: i32 = 0x30FB;
-		const EGL_OPENGL_ES2_BIT: i32 = 0x0004;
-		const EGL_OPENGL_ES3_BIT_KHR: i32 = 0x0040;
-
-		let mut last_error = String::new();
-		let (config, context) = if unsafe { egl.BindAPI(egl::OPENGL_API as u32) } != 0 {
-			let gl_config = choose_config(&egl, display, egl::OPENGL_BIT as i32)?;
-			let gl_ctx_attribs = [
-				EGL_CONTEXT_MAJOR_VERSION,
-				version.major as i32,
-				EGL_CONTEXT_MINOR_VERSION,
-				version.minor as i32,
-				egl::NONE as i32,
-			];
-			let context = unsafe {
-				egl.CreateContext(
-					display,
-					gl_config,
-					egl::NO_CONTEXT,
-					gl_ctx_attribs.as_ptr() as *const _,
-				)
-			};
-			if context.is_null() {
-				last_error = format!("OpenGL context failed eglError={:#X}", unsafe {
-					egl.GetError()
-				});
-				(ptr::null(), ptr::null())
-			} else {
-				(gl_config, context)
-			}
-		} else {
-			last_error = format!("OpenGL BindAPI failed eglError={:#X}", unsafe {
-				egl.GetError()
-			});
-			(ptr::null(), ptr::null())
-		};
-
-		let (_config, context) = if context.is_null() {
-			if unsafe { egl.BindAPI(egl::OPENGL_ES_API as u32) } == 0 {
-				return Err(GlError::ContextCreationFailed(format!(
-					"{last_error}; OpenGL ES BindAPI failed eglError={:#X}",
-					unsafe { egl.GetError() }
-				)));
-			}
+		let egl = load_egl(&egl_lib, &egl_boot);
+		let context = create_context(&egl, display, version, egl::NO_CONTEXT)?;
+		Self::from_context(
+			egl,
+			display,
+			context,
+			Some(gbm_device),
+			egl_lib,
+			gl_lib,
+			version,
+		)
+	}
 
-			let es_bits = if version.major >= 3 {
-				EGL_OPENGL_ES3_BIT_KHR
-			} else {
-				EGL_OPENGL_ES2_BIT
-			};
-			let es_config = choose_config(&egl, display, es_bits)?;
-			let es_major = version.major.max(2);
-			let es_ctx_attribs = [
-				EGL_CONTEXT_MAJOR_VERSION,
-				es_major as i32,
-				EGL_CONTEXT_MINOR_VERSION,
-				version.minor as i32,
-				egl::NONE as i32,
-			];
-			let es_context = unsafe {
-				egl.CreateContext(
-					display,
-					es_config,
-					egl::NO_CONTEXT,
-					es_ctx_attribs.as_ptr() as *const _,
-				)
-			};
-			if es_context.is_null() {
-				return Err(GlError::ContextCreationFailed(format!(
-					"{last_error}; OpenGL ES context failed eglError={:#X}",
-					unsafe { egl.GetError() }
-				)));
-			}
-			(es_config, es_context)
-		} else {
-			(config, context)
-		};
+	/// Creates a context on the caller's EGL display that shares textures, buffers and
+	/// programs with `share_context`, for apps that already render with their own EGL context.
+	///
+	/// The display is borrowed: it is not terminated when this context is dropped.
+	/// The new context is left current on the calling thread.
+	///
+	/// # Safety
+	/// `display` must be an initialized `EGLDisplay` and `share_context` an `EGLContext`
+	/// created on it, both valid for as long as the returned context lives.
+	pub unsafe fn new_shared(
+		version: GlVersion,
+		display: *const c_void,
+		share_context: *const c_void,
+	) -> Result<Self, GlError> {
+		let (egl_lib, gl_lib) = load_libraries()?;
+		let egl_boot =
+			egl::Egl::load_with(|name| load_symbol(&egl_lib, name).unwrap_or(ptr::null()));
+		let egl = load_egl(&egl_lib, &egl_boot);
+		let context = create_context(&egl, display, version, share_context)?;
+		Self::from_context(egl, display, context, None, egl_lib, gl_lib, version)
+	}
 
+	fn from_context(
+		egl: egl::Egl,
+		display: egl::types::EGLDisplay,
+		context: egl::types::EGLContext,
+		gbm_device: Option<GbmDevice<std::fs::File>>,
+		egl_lib: libloading::Library,
+		gl_lib: libloading::Library,
+		version: GlVersion,
+	) -> Result<Self, GlError> {
 		let make_current_ok =
 			unsafe { egl.MakeCurrent(display, egl::NO_SURFACE, egl::NO_SURFACE, context) };
 		if make_current_ok == 0 {
@@ -245,26 +219,40 @@ impl GlContext {
 		let egl_image_target_texture_2d_oes: GlEglImageTargetTexture2DOes =
 			unsafe { std::mem::transmute(image_target_ptr) };
 
-		let glow = unsafe {
+		let mut glow = unsafe {
 			glow::Context::from_loader_function(|name| {
 				load_proc_raw(&egl, &egl_lib, &gl_lib, name).unwrap_or(ptr::null()) as *const _
 			})
 		};
+		if glow.supports_debug() {
+			// The context is current, and glow keeps the callback for as long as it lives.
+			unsafe {
+				glow.enable(glow::DEBUG_OUTPUT);
+				glow.debug_message_callback(log_debug_message);
+			}
+		}
+
+		let native_fence_sync = egl.DupNativeFenceFDANDROID.is_loaded()
+			&& has_egl_extension(&egl, display, "EGL_ANDROID_native_fence_sync");
+		if !native_fence_sync {
+			tracing::info!("EGL_ANDROID_native_fence_sync unavailable, waiting for frames with glFinish");
+		}
 
 		Ok(Self {
 			egl,
 			display,
 			context,
+			owns_display: gbm_device.is_some(),
 			_gbm_device: gbm_device,
 			egl_lib,
 			gl_lib,
 			glow,
 			version,
+			native_fence_sync,
 			egl_image_target_texture_2d_oes,
 			dmabuf_targets: HashMap::new(),
 		})
 	}
-
 	/// Returns the actual GL version requested for this context.
 	pub fn version(&self) -> GlVersion {
 		self.version
@@ -283,6 +271,32 @@ impl GlContext {
 		Ok(())
 	}
 
+	/// Unbinds any context from the calling thread, so another thread can make this one current.
+	pub fn release_current(&self) -> Result<(), GlError> {
+		let ok = unsafe {
+			self.egl.MakeCurrent(
+				self.display,
+				egl::NO_SURFACE,
+				egl::NO_SURFACE,
+				egl::NO_CONTEXT,
+			)
+		};
+		if ok == 0 {
+			return Err(GlError::MakeCurrentFailed(unsafe { self.egl.GetError() }));
+		}
+		Ok(())
+	}
+
+	/// The raw `EGLDisplay`, for sharing with contexts created elsewhere.
+	pub fn egl_display(&self) -> *const c_void {
+		self.display
+	}
+
+	/// The raw `EGLContext`, usable as the share context of other contexts on [`Self::egl_display`].
+	pub fn egl_context(&self) -> *const c_void {
+		self.context
+	}
+
 	/// Resolves an OpenGL/EGL symbol by name.
 	pub fn load_proc(&self, name: &str) -> Result<*const c_void, GlError> {
 		if name.as_bytes().contains(&0) {
@@ -301,9 +315,29 @@ impl GlContext {
 		unsafe { self.glow.get_parameter_i32(glow::FRAMEBUFFER_BINDING) }
 	}
 
+	/// Whether [`Self::create_acquire_fence_fd`] can export fences.
+	pub fn supports_acquire_fences(&self) -> bool {
+		self.native_fence_sync
+	}
+
+	/// Ends the frame rendered so far. Returns an acquire fence to send with the buffer request,
+	/// so Shift waits for the GPU instead of this thread. Without `EGL_ANDROID_native_fence_sync`
+	/// this waits for the rendering with `glFinish` and returns no fence; so does a failed export,
+	/// which still returns the error.
+	pub fn finish_frame(&self) -> Result<Option<OwnedFd>, GlError> {
+		if !self.native_fence_sync {
+			unsafe { self.glow.finish() };
+			return Ok(None);
+		}
+		self
+			.create_acquire_fence_fd()
+			.map(Some)
+			.inspect_err(|_| unsafe { self.glow.finish() })
+	}
+
 	/// Creates an EGL native fence FD representing queued GL work.
 	pub fn create_acquire_fence_fd(&self) -> Result<OwnedFd, GlError> {
-		if !self.egl.DupNativeFenceFDANDROID.is_loaded() {
+		if !self.native_fence_sync {
 			return Err(GlError::MissingEglDupNativeFenceFd);
 		}
 		let attribs: [egl::types::EGLAttrib; 3] = [
@@ -528,7 +562,7 @@ impl Drop for GlContext {
 			if !self.context.is_null() {
 				self.egl.DestroyContext(self.display, self.context);
 			}
-			if !self.display.is_null() {
+			if self.owns_display && !self.display.is_null() {
 				self.egl.Terminate(self.display);
 			}
 		}
@@ -602,6 +636,144 @@ fn load_proc_raw(
 	None
 }
 
+fn has_egl_extension(egl: &egl::Egl, display: egl::types::EGLDisplay, name: &str) -> bool {
+	let extensions = unsafe { egl.QueryString(display, egl::EXTENSIONS as i32) };
+	if extensions.is_null() {
+		return false;
+	}
+	unsafe { CStr::from_ptr(extensions) }
+		.to_str()
+		.is_ok_and(|extensions| extensions.split_whitespace().any(|ext| ext == name))
+}
+
+fn load_libraries() -> Result<(libloading::Library, libloading::Library), GlError> {
+	let egl_lib = unsafe { libloading::Library::new("libEGL.so.1") }
+		.map_err(|e| GlError::LoadEglLibrary(e.to_string()))?;
+	let gl_lib = unsafe { libloading::Library::new("libGL.so.1") }
+		.map_err(|e| GlError::LoadGlLibrary(e.to_string()))?;
+	Ok((egl_lib, gl_lib))
+}
+
+fn load_egl(egl_lib: &libloading::Library, egl_boot: &egl::Egl) -> egl::Egl {
+	egl::Egl::load_with(|name| {
+		if let Some(sym) = load_symbol(egl_lib, name) {
+			return sym;
+		}
+		if !egl_boot.GetProcAddress.is_loaded() {
+			return ptr::null();
+		}
+		let Ok(c_name) = CString::new(name) else {
+			return ptr::null();
+		};
+		let ptr = unsafe { egl_boot.GetProcAddress(c_name.as_ptr()) };
+		if ptr.is_null() { ptr::null() } else { ptr.cast() }
+	})
+}
+
+/// Creates an OpenGL context, falling back to OpenGL ES. With a `share_context`, only the
+/// client API of that context is tried, since EGL can't share across APIs.
+fn create_context(
+	egl: &egl::Egl,
+	display: egl::types::EGLDisplay,
+	version: GlVersion,
+	share_context: egl::types::EGLContext,
+) -> Result<egl::types::EGLContext, GlError> {
+	const EGL_CONTEXT_MAJOR_VERSION: i32 = 0x3098;
+	const EGL_CONTEXT_MINOR_VERSION: i32 = 0x30FB;
+	const EGL_OPENGL_ES2_BIT: i32 = 0x0004;
+	const EGL_OPENGL_ES3_BIT_KHR: i32 = 0x0040;
+
+	let share_api = if share_context.is_null() {
+		None
+	} else {
+		let mut api = 0;
+		let ok = unsafe {
+			egl.QueryContext(
+				display,
+				share_context,
+				egl::CONTEXT_CLIENT_TYPE as i32,
+				&mut api,
+			)
+		};
+		if ok == 0 {
+			return Err(GlError::QueryContextFailed(unsafe { egl.GetError() }));
+		}
+		Some(api as u32)
+	};
+
+	let mut last_error = String::new();
+	if share_api.is_none_or(|api| api == egl::OPENGL_API) {
+		if unsafe { egl.BindAPI(egl::OPENGL_API as u32) } != 0 {
+			let gl_config = choose_config(egl, display, egl::OPENGL_BIT as i32)?;
+			let gl_ctx_attribs = [
+				EGL_CONTEXT_MAJOR_VERSION,
+				version.major as i32,
+				EGL_CONTEXT_MINOR_VERSION,
+				version.minor as i32,
+				egl::NONE as i32,
+			];
+			let context = unsafe {
+				egl.CreateContext(
+					display,
+					gl_config,
+					share_context,
+					gl_ctx_attribs.as_ptr() as *const _,
+				)
+			};
+			if !context.is_null() {
+				return Ok(context);
+			}
+			last_error = format!("OpenGL context failed eglError={:#X}", unsafe {
+				egl.GetError()
+			});
+		} else {
+			last_error = format!("OpenGL BindAPI failed eglError={:#X}", unsafe {
+				egl.GetError()
+			});
+		}
+		if share_api.is_some() {
+			return Err(GlError::ContextCreationFailed(last_error));
+		}
+	}
+
+	if unsafe { egl.BindAPI(egl::OPENGL_ES_API as u32) } == 0 {
+		return Err(GlError::ContextCreationFailed(format!(
+			"{last_error}; OpenGL ES BindAPI failed eglError={:#X}",
+			unsafe { egl.GetError() }
+		)));
+	}
+
+	let es_bits = if version.major >= 3 {
+		EGL_OPENGL_ES3_BIT_KHR
+	} else {
+		EGL_OPENGL_ES2_BIT
+	};
+	let es_config = choose_config(egl, display, es_bits)?;
+	let es_major = version.major.max(2);
+	let es_ctx_attribs = [
+		EGL_CONTEXT_MAJOR_VERSION,
+		es_major as i32,
+		EGL_CONTEXT_MINOR_VERSION,
+		version.minor as i32,
+		egl::NONE as i32,
+	];
+	let es_context = unsafe {
+		egl.CreateContext(
+			display,
+			es_config,
+			share_context,
+			es_ctx_attribs.as_ptr() as *const _,
+		)
+	};
+	if es_context.is_null() {
+		return Err(GlError::ContextCreationFailed(format!(
+			"{last_error}; OpenGL ES context failed eglError={:#X}",
+			unsafe { egl.GetError() }
+		)));
+	}
+	Ok(es_context)
+}
+
 fn choose_config(
 	egl: &egl::Egl,
 	display: egl::types::EGLDisplay,
@@ -14,11 +14,11 @@ pub use monitor_layout_engine as monitor_layout;
 
 /// Re-exported core runtime types.
 pub use tab_app_framework_core::{
-	Application, CharEvent, Config, Context, FdReadyEvent, FrameworkError, GestureEvent,
-	InitContext, InputEvent, KeyEvent, Monitor, MonitorAddedEvent, MonitorRemovedEvent, MouseDownEvent,
-	MouseMoveEvent, MouseUpEvent, PointerDownEvent, PointerMoveEvent, PointerType, PointerUpEvent,
-	PresentEvent, RenderEvent, RenderMode, SessionCreatedPayload, SessionEvent, SessionInfo,
-	SessionRole, TabAppFramework, TouchEvent,
+	Application, CharEvent, Config, Context, FdReadyEvent, FrameworkError, GestureEvent, InitContext,
+	InputEvent, KeyEvent, Monitor, MonitorAddedEvent, MonitorChangedEvent, MonitorRemovedEvent,
+	MouseDownEvent, MouseMoveEvent, MouseUpEvent, PointerDownEvent, PointerMoveEvent, PointerType,
+	PointerUpEvent, PresentEvent, RenderEvent, RenderMode, SessionCreatedPayload, SessionEvent,
+	SessionInfo, SessionRole, TabAppFramework, TouchEvent,
 };
 /// Re-exported GL runtime types.
 pub use tab_app_framework_gl::{
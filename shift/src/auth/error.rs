@@ -4,4 +4,6 @@ pub enum Error {
 	InvalidToken,
 	#[error("no session was found that matches the requested token")]
 	NotFound,
+	#[error("peer is not trusted to authenticate without a token")]
+	PeerNotTrusted,
 }
@@ -1,4 +1,5 @@
 pub mod error;
+pub mod peer;
 mod token;
 pub use token::Error as TokenError;
 pub use token::Token;
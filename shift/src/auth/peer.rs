@@ -0,0 +1,144 @@
+//! Authenticating local helpers by who they are instead of a token, with `auth {token: "peer"}`.
+//! Off unless `SHIFT_PEER_AUTH` lists who may, comma separated:
+//! - `spawned`: a process shift launched for a session, or any of its children, takes that
+//!   session
+//! - a uid: any process of that user takes the initial admin session
+//!
+//! The peer is told apart by `SO_PEERCRED`, so this only works over the Unix socket.
+
+use std::{collections::HashSet, fs, io, os::fd::RawFd};
+
+/// The `token` that asks for peer authentication.
+pub const PEER_TOKEN: &str = "peer";
+/// How many parents up a spawned session process is looked for.
+const MAX_ANCESTORS: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PeerAuth {
+	spawned: bool,
+	uids: HashSet<u32>,
+}
+
+impl PeerAuth {
+	pub fn from_env() -> Self {
+		std::env::var("SHIFT_PEER_AUTH")
+			.map(|value| Self::parse(&value))
+			.unwrap_or_default()
+	}
+
+	fn parse(value: &str) -> Self {
+		let mut auth = Self::default();
+		for entry in value
+			.split(',')
+			.map(str::trim)
+			.filter(|entry| !entry.is_empty())
+		{
+			match entry {
+				"spawned" => auth.spawned = true,
+				uid => match uid.parse() {
+					Ok(uid) => {
+						auth.uids.insert(uid);
+					}
+					Err(_) => tracing::warn!(entry, "ignoring invalid SHIFT_PEER_AUTH entry"),
+				},
+			}
+		}
+		auth
+	}
+
+	pub fn trusts_spawned(&self) -> bool {
+		self.spawned
+	}
+
+	pub fn trusts_uid(&self, uid: u32) -> bool {
+		self.uids.contains(&uid)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+	pub pid: u32,
+	pub uid: u32,
+}
+
+impl PeerCredentials {
+	/// Who is on the other end of the Unix socket `fd`, as of when it connected.
+	pub fn of(fd: RawFd) -> io::Result<Self> {
+		let mut cred = libc::ucred {
+			pid: 0,
+			uid: 0,
+			gid: 0,
+		};
+		let mut len = size_of::<libc::ucred>() as libc::socklen_t;
+		// SAFETY: `cred` is a `ucred` as large as `len` says, which is what SO_PEERCRED fills in.
+		let result = unsafe {
+			libc::getsockopt(
+				fd,
+				libc::SOL_SOCKET,
+				libc::SO_PEERCRED,
+				(&raw mut cred).cast(),
+				&mut len,
+			)
+		};
+		if result < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(Self {
+			pid: cred.pid as u32,
+			uid: cred.uid,
+		})
+	}
+
+	/// The peer's pid, then its parent's and so on, for matching it to a process shift spawned.
+	pub fn lineage(&self) -> Vec<u32> {
+		let mut lineage = vec![self.pid];
+		while lineage.len() < MAX_ANCESTORS {
+			let pid = lineage[lineage.len() - 1];
+			let Some(parent) = fs::read_to_string(format!("/proc/{pid}/stat"))
+				.ok()
+				.and_then(|stat| parent_pid(&stat))
+				.filter(|parent| *parent > 1)
+			else {
+				break;
+			};
+			lineage.push(parent);
+		}
+		lineage
+	}
+}
+
+/// The parent pid in a `/proc/<pid>/stat` line. The process name before it is in parentheses and
+/// may hold spaces and parentheses itself.
+fn parent_pid(stat: &str) -> Option<u32> {
+	let (_, after_name) = stat.rsplit_once(')')?;
+	after_name.split_whitespace().nth(1)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::{fd::AsRawFd, unix::net::UnixStream};
+
+	use super::*;
+
+	#[test]
+	fn peers_are_trusted_as_configured() {
+		let auth = PeerAuth::parse(" spawned, 1000,nobody");
+		assert!(auth.trusts_spawned());
+		assert!(auth.trusts_uid(1000));
+		assert!(!auth.trusts_uid(0));
+		assert_eq!(PeerAuth::parse(""), PeerAuth::default());
+		assert!(!PeerAuth::default().trusts_spawned());
+
+		assert_eq!(parent_pid("4242 (a (weird) name) S 17 4242 ..."), Some(17));
+		assert_eq!(parent_pid("garbage"), None);
+	}
+
+	#[test]
+	fn credentials_name_the_peer_process() {
+		let (ours, _theirs) = UnixStream::pair().unwrap();
+		let cred = PeerCredentials::of(ours.as_raw_fd()).unwrap();
+		assert_eq!(cred.pid, std::process::id());
+		assert_eq!(cred.uid, nix::unistd::getuid().as_raw());
+		assert_eq!(cred.lineage()[0], std::process::id());
+	}
+}
@@ -1,81 +1,137 @@
 use std::{
 	fmt::{Debug, Display},
-	os::{fd::AsRawFd, unix::net::UnixStream},
+	os::fd::{AsRawFd, OwnedFd},
 	sync::Arc,
 };
 
+use base64::{Engine, prelude::BASE64_STANDARD};
 use tab_protocol::{
-	AuthErrorPayload, AuthOkPayload, ErrorPayload, MonitorAddedPayload, MonitorRemovedPayload,
-	SessionActivePayload, SessionAwakePayload, SessionCreatedPayload, SessionInfo,
-	SessionSleepPayload, SessionStatePayload, TabMessage, TabMessageFrame, TabMessageFrameReader,
-	message_header,
+	AuthErrorPayload, AuthOkPayload, BacklightsPayload, BufferIndex, BufferReleasePayload,
+	BufferReleasesPayload, BufferUploadPayload, BuffersInvalidatedPayload, DeviceConfigsPayload,
+	ErrorPayload, FocusPayload, FramebufferLinkFailedPayload, FramesDroppedPayload,
+	InputEventPayload, KeyRepeatInfo, LidClosedPayload, LogRecordsPayload, PerformanceWarningPayload,
+	PointerLockStatePayload, ProtocolError, RelinkRequestPayload, ScreenshotDataPayload,
+	SelectionDataPayload, SessionActivePayload, SessionAwakePayload, SessionCrashedPayload,
+	SessionCreatedPayload, SessionInactivePayload, SessionInfo, SessionSleepPayload,
+	SessionStatePayload, SessionUnresponsivePayload, SessionVisibilityPayload, SessionsPayload,
+	SharedFrame, ShortcutTriggeredPayload, TabMessage, TabMessageFrame, TabMessageFrameReader,
+	TabletModePayload, TransitionsPayload, compression, message_header,
+	transport::{AnyTransport, Transport},
+};
+use tokio::{
+	io::unix::AsyncFd,
+	task::JoinHandle,
+	time::{Instant, sleep_until},
 };
-use tokio::{io::unix::AsyncFd, task::JoinHandle};
 use tracing::{Instrument, Span};
 
 use crate::{
-	auth::Token,
-	client_layer::client_view::{self, ChannelsClientEnd, ClientView},
+	auth::{Token, peer::PEER_TOKEN},
+	client_layer::{
+		client_view::{self, ChannelsClientEnd, ClientView},
+		connection_state::{ConnectionState, HandshakeTimeouts, Rejection},
+		wire_tap::{Direction, WireTap},
+	},
 	comms::{
-		client2server::{C2SMsg, C2STx},
-		server2client::S2CMsg,
+		client2server::{AuthRequest, C2SMsg, C2STx},
+		correlation::{Correlated, CorrelationId},
+		server2client::{BufferRelease, S2CMsg},
 	},
 	define_id_type,
+	error::Error,
+	ids::Id,
 	monitor::{Monitor, MonitorId},
 	sessions::{Role, Session, SessionId},
 };
-pub type AsyncUnixStream = AsyncFd<UnixStream>;
+pub type AsyncTransport = AsyncFd<AnyTransport>;
 
 pub struct Client {
 	id: ClientId,
-	socket: AsyncUnixStream,
+	socket: AsyncTransport,
 	frame_reader: TabMessageFrameReader,
 	channel_client_end: ChannelsClientEnd,
 	connected_session: Option<Arc<Session>>,
+	state: ConnectionState,
+	handshake_timeouts: HandshakeTimeouts,
+	/// When the connection is closed unless it takes the next handshake step, see
+	/// [`HandshakeTimeouts`].
+	handshake_deadline: Option<Instant>,
 	shutdown: bool,
 	initial_monitors: Vec<Monitor>,
+	compress_payloads: bool,
+	key_repeat: KeyRepeatInfo,
+	/// Set by `auth.local_key_repeat`: the client repeats keys itself.
+	local_key_repeat: bool,
+	/// Set by `auth.coalesce_releases`: a flip's releases go out as one `buffer_releases`.
+	coalesce_releases: bool,
+	/// Set by an admin's `wire_tap`.
+	wire_tap: Option<WireTap>,
 }
 
 impl Client {
+	/// `queue_capacity` bounds the messages waiting in either direction, see `SHIFT_CLIENT_QUEUE`.
+	/// The server mints `id` so it can't collide with a connected client.
 	pub fn wrap_socket(
-		socket: AsyncUnixStream,
+		id: ClientId,
+		socket: AsyncTransport,
 		initial_monitors: Vec<Monitor>,
+		queue_capacity: usize,
+		key_repeat: KeyRepeatInfo,
+		handshake_timeouts: HandshakeTimeouts,
 	) -> (Self, ClientView) {
-		let channels = client_view::Channels::new();
-		let client = Self {
+		let channels = client_view::Channels::new(queue_capacity);
+		let mut client = Self {
 			socket,
 			frame_reader: TabMessageFrameReader::new(),
-			id: ClientId::rand(),
+			id,
 			channel_client_end: channels.client_end,
 			connected_session: None,
+			state: ConnectionState::AwaitingAuth,
+			handshake_timeouts,
+			handshake_deadline: None,
 			shutdown: false,
 			initial_monitors,
+			compress_payloads: false,
+			key_repeat,
+			local_key_repeat: false,
+			coalesce_releases: false,
+			wire_tap: None,
 		};
+		client.handshake_step();
 		let client_view = ClientView::from_client(&client, channels.server_end);
 		(client, client_view)
 	}
 	pub fn id(&self) -> ClientId {
 		self.id
 	}
-	#[tracing::instrument(level = "error", skip(self), fields(client.id = self.id().to_string()))]
-	async fn send_error(&self, code: &str, error: Option<impl Display + Debug>) {
-		tracing::warn!("sending error to the client");
+	/// Sends a frame, compressing large payloads if the client opted in during auth.
+	async fn send_frame(&self, frame: TabMessageFrame) -> Result<(), ProtocolError> {
+		if let Some(tap) = &self.wire_tap {
+			tap.record(Direction::Out, &frame);
+		}
+		let frame = if self.compress_payloads {
+			frame.compressed()?
+		} else {
+			frame
+		};
+		frame.send_frame_to_async_fd(&self.socket).await
+	}
+	#[tracing::instrument(level = "error", skip(self), fields(client.id = %self.id().short()))]
+	async fn send_error(&self, error: &Error) {
+		tracing::warn!(kind = ?error.kind(), "sending error to the client");
 		let tab_message = TabMessageFrame::json(
 			message_header::ERROR,
 			ErrorPayload {
-				code: code.into(),
-				message: error.as_ref().map(|e| e.to_string()),
+				code: error.code().into(),
+				message: error.message(),
 			},
 		);
-		let result = tab_message.send_frame_to_async_fd(&self.socket).await;
+		let result = self.send_frame(tab_message).await;
 		if let Err(e) = result {
-			tracing::warn!(
-				"failed to send error message to client {:?}: {e}",
-				error.map(|e| e.to_string())
-			);
+			tracing::warn!("failed to send error message to client ({error}): {e}");
 		}
 	}
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
 	async fn send_auth_error(&mut self, cause: impl Display + Debug) {
 		let tab_message = TabMessageFrame::json(
 			message_header::AUTH_ERROR,
@@ -84,7 +140,7 @@ impl Client {
 			},
 		);
 
-		let result = tab_message.send_frame_to_async_fd(&self.socket).await;
+		let result = self.send_frame(tab_message).await;
 		if let Err(e) = result {
 			tracing::warn!(
 				"failed to send auth error message to client ({}): {e}",
@@ -93,53 +149,69 @@ impl Client {
 		}
 	}
 
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
+	/// Starts the clock on the handshake step the connection is at now.
+	fn handshake_step(&mut self) {
+		self.handshake_deadline = self
+			.handshake_timeouts
+			.limit(self.state)
+			.map(|limit| Instant::now() + limit);
+	}
+
+	/// Uploaded pixels were already copied, so stream transports don't need the fence.
+	fn release_fence(&self, fence: Option<OwnedFd>) -> Option<OwnedFd> {
+		fence.filter(|_| self.socket.get_ref().supports_fd_passing())
+	}
+	/// `buffer_releases` frames for the buffers of one flip, as few as their fences fit in.
+	fn releases_frames(&self, buffers: Vec<BufferRelease>) -> Vec<TabMessageFrame> {
+		BufferReleasesPayload::frames(buffers.into_iter().map(|buffer| {
+			let release = BufferReleasePayload {
+				monitor_id: buffer.monitor_id.to_string(),
+				buffer: buffer.buffer,
+				generation: buffer.generation,
+			};
+			(release, self.release_fence(buffer.release_fence))
+		}))
+	}
+
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
 	async fn handle_unknown_msg(&mut self, message_name: impl Display + Debug) {
-		self.send_error("unknown_message", Some(message_name)).await;
+		self
+			.send_error(&Error::UnknownMessage(message_name.to_string()))
+			.await;
 		self.schedule_client_shutdown().await;
 	}
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
-	async fn handle_packet(&mut self, tab_message: TabMessage) {
-		macro_rules! check_admin {
-			($action:literal) => {
-				if !self
-					.connected_session
-					.as_deref()
-					.is_some_and(|session| session.role() == Role::Admin)
-				{
-					self
-						.send_error(
-							"forbidden",
-							Some(format!(
-								"you need to authenticate as an admin client before being able to {}",
-								$action
-							)),
-						)
-						.await;
-					return;
-				};
-			};
+	/// Refuses messages the connection state doesn't allow, before parsing them. `corr` goes
+	/// with everything the frame leads to, see [`CorrelationId`].
+	#[tracing::instrument(skip_all, fields(client.id = %self.id().short(), header = %frame.header.0, corr = %corr))]
+	async fn handle_frame(&mut self, corr: CorrelationId, frame: TabMessageFrame) {
+		if let Some(tap) = &self.wire_tap {
+			tap.record(Direction::In, &frame);
 		}
-
-		macro_rules! check_session {
-			($action:literal, $var:ident) => {
-				let Some($var) = self.connected_session.as_deref() else {
-					self
-						.send_error(
-							"forbidden",
-							Some(format!(
-								"you need to authenticate before being able to {}",
-								$action
-							)),
-						)
-						.await;
-					return;
-				};
-			};
+		match self.state.check(&frame.header.0) {
+			Ok(()) => {}
+			Err(Rejection::NotAClientMessage(header)) => return self.handle_unknown_msg(header).await,
+			Err(rejection) => {
+				tracing::debug!(state = %self.state, ?rejection, "refusing message");
+				return self.send_error(&rejection.into()).await;
+			}
+		}
+		match TabMessage::try_from(frame) {
+			Ok(packet) => self.handle_packet(corr, packet).await,
+			Err(e) => {
+				self.send_error(&e.into()).await;
+				self.schedule_client_shutdown().await;
+			}
 		}
+	}
+	#[tracing::instrument(skip(self, corr), fields(client.id = %self.id().short()))]
+	async fn handle_packet(&mut self, corr: CorrelationId, tab_message: TabMessage) {
 		macro_rules! send_server_msg {
 			($send:expr) => {
-				let send_result = self.channel_client_end.to_server().send($send).await;
+				let send_result = self
+					.channel_client_end
+					.to_server()
+					.send(Correlated::new(Some(corr), $send))
+					.await;
 				if send_result.is_err() {
 					tracing::debug!("C2S channel closed, terminating client");
 					self.schedule_client_shutdown().await;
@@ -149,22 +221,34 @@ impl Client {
 		}
 		match tab_message {
 			TabMessage::Auth(auth) => {
-				let token = auth.token.parse::<Token>();
-				let token = match token {
-					Ok(token) => token,
-					Err(error) => {
-						return self
-							.send_auth_error(format!("token parse error: {error:?}"))
-							.await;
+				self.compress_payloads = auth
+					.compression
+					.as_deref()
+					.is_some_and(|algo| compression::supported().iter().any(|s| s == algo));
+				self.local_key_repeat = auth.local_key_repeat;
+				self.coalesce_releases = auth.coalesce_releases;
+				let request = if auth.token == PEER_TOKEN {
+					AuthRequest::Peer
+				} else {
+					match auth.token.parse::<Token>() {
+						Ok(token) => AuthRequest::Token(token),
+						Err(error) => {
+							return self
+								.send_auth_error(format!("token parse error: {error:?}"))
+								.await;
+						}
 					}
 				};
-				tracing::info!(?token, "sending auth request to the server");
-				send_server_msg!(C2SMsg::Auth(token));
+				tracing::info!(?request, "sending auth request to the server");
+				send_server_msg!(C2SMsg::Auth(request));
+				self.state.auth_sent();
 			}
 			TabMessage::SessionSwitch(session_switch_payload) => {
-				check_admin!("switch session");
 				send_server_msg!(C2SMsg::SwitchSession(session_switch_payload));
 			}
+			TabMessage::TransitionDefine(payload) => {
+				send_server_msg!(C2SMsg::TransitionDefine(payload));
+			}
 			TabMessage::BufferRequest {
 				payload,
 				acquire_fence,
@@ -173,29 +257,28 @@ impl Client {
 				let monitor_id = match monitor_id {
 					Ok(monitor_id) => monitor_id,
 					Err(error) => {
-						return self
-							.send_error(
-								"unknown_monitor",
-								Some(format!("monitor id parse error: {error:?}")),
-							)
-							.await;
+						return self.send_error(&error.into()).await;
 					}
 				};
 				send_server_msg!(C2SMsg::BufferRequest {
 					monitor_id: monitor_id,
 					buffer: payload.buffer,
+					generation: payload.generation,
 					acquire_fence,
+					present_group: payload.present_group,
 				});
 			}
+			TabMessage::PresentGroup(payload) => {
+				send_server_msg!(C2SMsg::PresentGroup(payload));
+			}
 			TabMessage::SessionCreate(session_create_req) => {
-				check_admin!("create a session");
 				send_server_msg!(C2SMsg::CreateSession(session_create_req));
 			}
 			TabMessage::Ping => {
 				tracing::debug!("received ping");
 
-				let send_result = TabMessageFrame::no_payload(message_header::PONG)
-					.send_frame_to_async_fd(&self.socket)
+				let send_result = self
+					.send_frame(TabMessageFrame::no_payload(message_header::PONG))
 					.await;
 				if let Err(e) = send_result {
 					tracing::warn!("failed to send pong message back: {e}");
@@ -207,20 +290,82 @@ impl Client {
 				dma_bufs,
 			} => {
 				tracing::debug!(?fb_info, ?dma_bufs, "received link framebuffer request");
-				check_session!("link framebuffer", _session);
 				send_server_msg!(C2SMsg::FramebufferLink {
 					payload: fb_info,
 					dma_bufs
 				});
+				self.state.buffers_linked();
+				self.handshake_step();
+			}
+			TabMessage::FramesSkipped(payload) => {
+				send_server_msg!(C2SMsg::FramesSkipped {
+					count: payload.count
+				});
+			}
+			TabMessage::BufferUpload(payload) => {
+				let monitor_id = match payload.monitor_id.parse::<MonitorId>() {
+					Ok(monitor_id) => monitor_id,
+					Err(error) => {
+						return self.send_error(&error.into()).await;
+					}
+				};
+				let buffer = match payload.buffer {
+					0 => BufferIndex::Zero,
+					1 => BufferIndex::One,
+					_ => {
+						return self
+							.send_error(&Error::InvalidBufferUpload("buffer must be 0 or 1".into()))
+							.await;
+					}
+				};
+				let mut pixels = match BASE64_STANDARD.decode(payload.data.as_bytes()) {
+					Ok(pixels) => pixels,
+					Err(error) => {
+						return self
+							.send_error(&Error::InvalidBufferUpload(error.to_string()))
+							.await;
+					}
+				};
+				let Some(len) = uploaded_rows_len(&payload, pixels.len()) else {
+					return self
+						.send_error(&Error::InvalidBufferUpload(
+							"dimensions don't match the uploaded pixels".into(),
+						))
+						.await;
+				};
+				pixels.truncate(len);
+				send_server_msg!(C2SMsg::BufferUpload {
+					monitor_id,
+					buffer,
+					width: payload.width,
+					height: payload.height,
+					stride: payload.stride,
+					y: payload.y,
+					pixels,
+				});
+				self.state.buffers_linked();
+				self.handshake_step();
 			}
 
 			TabMessage::Hello(_hello_payload) => self.handle_unknown_msg("Hello").await,
 			TabMessage::AuthOk(_auth_ok_payload) => self.handle_unknown_msg("AuthOk").await,
 			TabMessage::AuthError(_auth_error_payload) => self.handle_unknown_msg("AuthError").await,
 			TabMessage::BufferRelease { .. } => self.handle_unknown_msg("BufferRelease").await,
+			TabMessage::BufferReleases { .. } => self.handle_unknown_msg("BufferReleases").await,
 			TabMessage::BufferRequestAck(_buffer_request_ack_payload) => {
 				self.handle_unknown_msg("BufferRequestAck").await
 			}
+			TabMessage::FramebufferLinkFailed(_payload) => {
+				self.handle_unknown_msg("FramebufferLinkFailed").await
+			}
+			TabMessage::RelinkRequest(_payload) => self.handle_unknown_msg("RelinkRequest").await,
+			TabMessage::BuffersInvalidated(_payload) => {
+				self.handle_unknown_msg("BuffersInvalidated").await
+			}
+			TabMessage::FramesDropped(_payload) => self.handle_unknown_msg("FramesDropped").await,
+			TabMessage::PerformanceWarning(_payload) => {
+				self.handle_unknown_msg("PerformanceWarning").await
+			}
 			TabMessage::InputEvent(_input_event_payload) => self.handle_unknown_msg("InputEvent").await,
 			TabMessage::MonitorAdded(_monitor_added_payload) => {
 				self.handle_unknown_msg("MonitorAdded").await
@@ -228,6 +373,9 @@ impl Client {
 			TabMessage::MonitorRemoved(_monitor_removed_payload) => {
 				self.handle_unknown_msg("MonitorRemoved").await
 			}
+			TabMessage::MonitorChanged(_monitor_changed_payload) => {
+				self.handle_unknown_msg("MonitorChanged").await
+			}
 			TabMessage::SessionCreated(_session_created_payload) => {
 				self.handle_unknown_msg("SessionCreated").await
 			}
@@ -240,8 +388,160 @@ impl Client {
 			TabMessage::SessionActive(_session_active_payload) => {
 				self.handle_unknown_msg("SessionActive").await
 			}
+			TabMessage::SessionInactive(_payload) => self.handle_unknown_msg("SessionInactive").await,
+			TabMessage::SplashEnded => self.handle_unknown_msg("SplashEnded").await,
+			TabMessage::Resumed => self.handle_unknown_msg("Resumed").await,
 			TabMessage::SessionAwake(_payload) => self.handle_unknown_msg("SessionAwake").await,
 			TabMessage::SessionSleep(_payload) => self.handle_unknown_msg("SessionSleep").await,
+			TabMessage::SessionVisibility(_payload) => self.handle_unknown_msg("SessionVisibility").await,
+			TabMessage::SessionPip(session_pip_payload) => {
+				send_server_msg!(C2SMsg::SessionPip(session_pip_payload));
+			}
+			TabMessage::MonitorLayout(monitor_layout_payload) => {
+				send_server_msg!(C2SMsg::MonitorLayout(monitor_layout_payload));
+			}
+			TabMessage::MonitorHdr(monitor_hdr_payload) => {
+				send_server_msg!(C2SMsg::MonitorHdr(monitor_hdr_payload));
+			}
+			TabMessage::MonitorMirror(monitor_mirror_payload) => {
+				send_server_msg!(C2SMsg::MonitorMirror(monitor_mirror_payload));
+			}
+			TabMessage::MonitorFilter(monitor_filter_payload) => {
+				send_server_msg!(C2SMsg::MonitorFilter(monitor_filter_payload));
+			}
+			TabMessage::ZoomSet(zoom_set_payload) => {
+				send_server_msg!(C2SMsg::ZoomSet(zoom_set_payload));
+			}
+			TabMessage::PointerLock(pointer_lock_payload) => {
+				send_server_msg!(C2SMsg::PointerLock {
+					enable: pointer_lock_payload.enable,
+				});
+			}
+			TabMessage::ShortcutRegister(shortcut_register_payload) => {
+				send_server_msg!(C2SMsg::ShortcutRegister(shortcut_register_payload));
+			}
+			TabMessage::ShortcutUnregister(shortcut_unregister_payload) => {
+				send_server_msg!(C2SMsg::ShortcutUnregister(shortcut_unregister_payload));
+			}
+			TabMessage::ShortcutTriggered(_payload) => self.handle_unknown_msg("ShortcutTriggered").await,
+			TabMessage::DebugHud(debug_hud_payload) => {
+				send_server_msg!(C2SMsg::DebugHud {
+					enabled: debug_hud_payload.enabled,
+				});
+			}
+			TabMessage::BackgroundSet(background_set_payload) => {
+				send_server_msg!(C2SMsg::BackgroundSet(background_set_payload));
+			}
+			TabMessage::LogLevel(log_level_payload) => {
+				send_server_msg!(C2SMsg::LogLevel(log_level_payload));
+			}
+			TabMessage::LogDump(log_dump_payload) => {
+				send_server_msg!(C2SMsg::LogDump(log_dump_payload));
+			}
+			TabMessage::LogRecords(_payload) => self.handle_unknown_msg("LogRecords").await,
+			TabMessage::WireTap(payload) => {
+				send_server_msg!(C2SMsg::WireTap(payload));
+			}
+			TabMessage::SessionList => {
+				send_server_msg!(C2SMsg::SessionList);
+			}
+			TabMessage::Sessions(_payload) => self.handle_unknown_msg("Sessions").await,
+			TabMessage::TransitionsList => {
+				send_server_msg!(C2SMsg::TransitionsList);
+			}
+			TabMessage::Transitions(_payload) => self.handle_unknown_msg("Transitions").await,
+			TabMessage::StatsRequest => {
+				send_server_msg!(C2SMsg::StatsRequest);
+			}
+			TabMessage::Stats(_payload) => self.handle_unknown_msg("Stats").await,
+			TabMessage::StateSyncRequest => {
+				send_server_msg!(C2SMsg::StateSyncRequest);
+			}
+			TabMessage::StateSync(_payload) => self.handle_unknown_msg("StateSync").await,
+			TabMessage::CompositorHealthSubscribe(payload) => {
+				send_server_msg!(C2SMsg::CompositorHealthSubscribe {
+					enabled: payload.enabled,
+				});
+			}
+			TabMessage::CompositorHealth(_payload) => self.handle_unknown_msg("CompositorHealth").await,
+			TabMessage::SessionUnresponsive(_payload) => {
+				self.handle_unknown_msg("SessionUnresponsive").await
+			}
+			TabMessage::SessionCrashed(_payload) => self.handle_unknown_msg("SessionCrashed").await,
+			TabMessage::Screenshot(payload) => {
+				if !self.socket.get_ref().supports_fd_passing() {
+					return self
+						.send_error(&Error::Unsupported(
+							"screenshots need a transport that can pass FDs",
+						))
+						.await;
+				}
+				let monitor_id = match payload.monitor_id.parse::<MonitorId>() {
+					Ok(monitor_id) => monitor_id,
+					Err(error) => {
+						return self.send_error(&error.into()).await;
+					}
+				};
+				send_server_msg!(C2SMsg::Screenshot { monitor_id });
+			}
+			TabMessage::ScreenshotData { .. } => self.handle_unknown_msg("ScreenshotData").await,
+			TabMessage::SelectionOffer { payload, data } => {
+				send_server_msg!(C2SMsg::SelectionOffer {
+					mime_type: payload.mime_type,
+					data,
+				});
+			}
+			TabMessage::SelectionRequest => {
+				if !self.socket.get_ref().supports_fd_passing() {
+					return self
+						.send_error(&Error::Unsupported(
+							"selections need a transport that can pass FDs",
+						))
+						.await;
+				}
+				send_server_msg!(C2SMsg::SelectionRequest);
+			}
+			TabMessage::SelectionData { .. } => self.handle_unknown_msg("SelectionData").await,
+			TabMessage::SelectionPolicy(payload) => {
+				send_server_msg!(C2SMsg::SelectionPolicy(payload));
+			}
+			TabMessage::BacklightGet => {
+				send_server_msg!(C2SMsg::BacklightGet);
+			}
+			TabMessage::Backlights(_payload) => self.handle_unknown_msg("Backlights").await,
+			TabMessage::BacklightSet(payload) => {
+				send_server_msg!(C2SMsg::BacklightSet(payload));
+			}
+			TabMessage::DeviceCalibration(payload) => {
+				send_server_msg!(C2SMsg::DeviceCalibration(payload));
+			}
+			TabMessage::DeviceConfigure(payload) => {
+				send_server_msg!(C2SMsg::DeviceConfigure(payload));
+			}
+			TabMessage::DeviceGrab(payload) => {
+				send_server_msg!(C2SMsg::DeviceGrab(payload));
+			}
+			TabMessage::DeviceConfigGet => {
+				send_server_msg!(C2SMsg::DeviceConfigGet);
+			}
+			TabMessage::DeviceConfigs(_payload) => self.handle_unknown_msg("DeviceConfigs").await,
+			TabMessage::InputInject(payload) => {
+				send_server_msg!(C2SMsg::InputInject(payload));
+			}
+			TabMessage::SwitchEventsSubscribe(payload) => {
+				send_server_msg!(C2SMsg::SwitchEventsSubscribe {
+					enabled: payload.enabled,
+				});
+			}
+			TabMessage::LidClosed(_payload) => self.handle_unknown_msg("LidClosed").await,
+			TabMessage::TabletMode(_payload) => self.handle_unknown_msg("TabletMode").await,
+			TabMessage::ServerShutdown(_payload) => self.handle_unknown_msg("ServerShutdown").await,
+			TabMessage::PointerLockState(_payload) => self.handle_unknown_msg("PointerLockState").await,
+			TabMessage::FocusIn(_payload) => self.handle_unknown_msg("FocusIn").await,
+			TabMessage::FocusOut(_payload) => self.handle_unknown_msg("FocusOut").await,
+			TabMessage::SessionAssignMonitor(assign_payload) => {
+				send_server_msg!(C2SMsg::AssignMonitor(assign_payload));
+			}
 			TabMessage::Error(_error_payload) => self.handle_unknown_msg("Error").await,
 			TabMessage::Pong => self.handle_unknown_msg("Pong").await,
 			TabMessage::Unknown(tab_message_frame) => {
@@ -249,7 +549,7 @@ impl Client {
 			}
 		}
 	}
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
 	async fn handle_server_layer_msg(&mut self, s2c_message: Option<S2CMsg>) {
 		let Some(s2c_message) = s2c_message else {
 			self.schedule_client_shutdown().await;
@@ -261,6 +561,7 @@ impl Client {
 					?e,
 					"server says authentication didn't work, forwarding it to the client"
 				);
+				self.state.auth_failed();
 				self.send_auth_error(e).await;
 			}
 			S2CMsg::BindToSession(session) => {
@@ -276,6 +577,7 @@ impl Client {
 							.iter()
 							.map(|m| m.to_protocol_info())
 							.collect(), // TODO: add monitors,
+						key_repeat: Some(self.key_repeat),
 						session: SessionInfo {
 							display_name: Some(session.display_name().to_string()),
 							id: session.id().to_string(),
@@ -285,11 +587,15 @@ impl Client {
 							} else {
 								tab_protocol::SessionLifecycle::Loading
 							},
+							attached: None,
+							visible: None,
 						},
 					},
 				);
+				self.state.authenticated(session.role());
+				self.handshake_step();
 				self.connected_session = Some(session);
-				let send_result = auth_ok.send_frame_to_async_fd(&self.socket).await;
+				let send_result = self.send_frame(auth_ok).await;
 
 				if let Err(e) = send_result {
 					tracing::warn!("failed to send auth ok message to client: {e}");
@@ -302,84 +608,222 @@ impl Client {
 					?token,
 					"server says it created a new session sucessfully"
 				);
-				let send_result = TabMessageFrame::json(
-					message_header::SESSION_CREATED,
-					SessionCreatedPayload {
-						session: SessionInfo {
-							display_name: session.display_name().map(String::from),
-							id: session.id().to_string(),
-							role: session.role().into(),
-							state: tab_protocol::SessionLifecycle::Pending,
+				let send_result = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_CREATED,
+						SessionCreatedPayload {
+							session: SessionInfo {
+								display_name: session.display_name().map(String::from),
+								id: session.id().to_string(),
+								role: session.role().into(),
+								state: tab_protocol::SessionLifecycle::Pending,
+								attached: None,
+								visible: None,
+							},
+							token: token.to_string(),
 						},
-						token: token.to_string(),
-					},
-				)
-				.send_frame_to_async_fd(&self.socket)
-				.await;
+					))
+					.await;
 				if let Err(e) = send_result {
 					tracing::warn!("failed to send session created message to client: {e}");
 					return;
 				}
 			}
-			S2CMsg::Error {
-				code,
-				error,
-				shutdown,
-			} => {
-				self.send_error(&code, error.as_deref()).await;
+			S2CMsg::Error { error, shutdown } => {
+				self.send_error(&error).await;
 				if shutdown {
 					self.schedule_client_shutdown().await;
 				}
 			}
+			S2CMsg::BufferRelease { buffers } if self.coalesce_releases && buffers.len() > 1 => {
+				for frame in self.releases_frames(buffers) {
+					if let Err(e) = self.send_frame(frame).await {
+						tracing::warn!("failed to send buffer_releases: {e}");
+						break;
+					}
+				}
+			}
 			S2CMsg::BufferRelease { buffers } => {
 				for buffer in buffers {
-					let payload = format!("{} {}", buffer.monitor_id, buffer.buffer as u8);
+					let (monitor_id, index) = (buffer.monitor_id, buffer.buffer);
+					let payload =
+						tab_protocol::buffer_args(&monitor_id.to_string(), index, buffer.generation);
 					let mut frame = TabMessageFrame::raw(message_header::BUFFER_RELEASE, payload);
-					if let Some(fd) = buffer.release_fence.as_ref() {
-						frame.fds.push(fd.as_raw_fd());
-					}
-					let send_result = frame.send_frame_to_async_fd(&self.socket).await;
+					frame.fds.extend(self.release_fence(buffer.release_fence));
+					let send_result = self.send_frame(frame).await;
 					if let Err(e) = send_result {
-						tracing::warn!(monitor_id = %buffer.monitor_id, buffer = buffer.buffer as u8, "failed to send buffer_release: {e}");
+						tracing::warn!(%monitor_id, buffer = index as u8, "failed to send buffer_release: {e}");
 						break;
 					}
 				}
 			}
-			S2CMsg::BufferRequestAck { monitor_id, buffer } => {
-				let payload = format!("{monitor_id} {}", buffer as u8);
-				if let Err(e) = TabMessageFrame::raw(message_header::BUFFER_REQUEST_ACK, payload)
-					.send_frame_to_async_fd(&self.socket)
+			S2CMsg::BufferRequestAck {
+				monitor_id,
+				buffer,
+				generation,
+			} => {
+				let payload = tab_protocol::buffer_args(&monitor_id.to_string(), buffer, generation);
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::raw(
+						message_header::BUFFER_REQUEST_ACK,
+						payload,
+					))
 					.await
 				{
 					tracing::warn!(%monitor_id, buffer = buffer as u8, "failed to send buffer_request_ack: {e}");
 				}
 			}
+			S2CMsg::FramebufferLinkFailed {
+				monitor_id,
+				reason,
+				supported_formats,
+			} => {
+				let payload = FramebufferLinkFailedPayload {
+					monitor_id: monitor_id.to_string(),
+					reason,
+					supported_formats,
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::FRAMEBUFFER_LINK_FAILED,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(%monitor_id, "failed to send framebuffer_link_failed: {e}");
+				}
+			}
+			S2CMsg::RelinkRequest { monitor_id } => {
+				let payload = RelinkRequestPayload {
+					monitor_id: monitor_id.to_string(),
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::RELINK_REQUEST,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(%monitor_id, "failed to send relink_request: {e}");
+				}
+			}
+			S2CMsg::BuffersInvalidated { monitor_ids } => {
+				let payload = BuffersInvalidatedPayload {
+					monitor_ids: monitor_ids.iter().map(ToString::to_string).collect(),
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::BUFFERS_INVALIDATED,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(?monitor_ids, "failed to send buffers_invalidated: {e}");
+				}
+			}
+			S2CMsg::FramesDropped {
+				monitor_id,
+				count,
+				reason,
+			} => {
+				let payload = FramesDroppedPayload {
+					monitor_id: monitor_id.to_string(),
+					count,
+					reason,
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::FRAMES_DROPPED,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(%monitor_id, "failed to send frames_dropped: {e}");
+				}
+			}
+			S2CMsg::PerformanceWarning {
+				monitor_id,
+				kind,
+				duration,
+				frames,
+			} => {
+				let payload = PerformanceWarningPayload {
+					monitor_id: monitor_id.to_string(),
+					kind,
+					duration_ms: duration.as_millis() as u64,
+					frames,
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::PERFORMANCE_WARNING,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(%monitor_id, "failed to send performance_warning: {e}");
+				}
+			}
 			S2CMsg::SessionAwake { session_id } => {
 				let payload = SessionAwakePayload {
 					session_id: session_id.to_string(),
 				};
-				if let Err(e) = TabMessageFrame::json(message_header::SESSION_AWAKE, payload)
-					.send_frame_to_async_fd(&self.socket)
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_AWAKE,
+						payload,
+					))
 					.await
 				{
 					tracing::warn!("failed to send session awake: {e}");
 				}
 			}
-			S2CMsg::SessionActive { session_id } => {
+			S2CMsg::SessionActive { session_id, epoch } => {
 				let payload = SessionActivePayload {
 					session_id: session_id.to_string(),
+					epoch: Some(epoch),
 				};
-				if let Err(e) = TabMessageFrame::json(message_header::SESSION_ACTIVE, payload)
-					.send_frame_to_async_fd(&self.socket)
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_ACTIVE,
+						payload,
+					))
 					.await
 				{
 					tracing::warn!("failed to send session active: {e}");
 				}
 			}
-			S2CMsg::SessionState { session } => {
-				let payload = SessionStatePayload { session };
-				if let Err(e) = TabMessageFrame::json(message_header::SESSION_STATE, payload)
-					.send_frame_to_async_fd(&self.socket)
+			S2CMsg::SessionInactive { session_id } => {
+				let payload = SessionInactivePayload {
+					session_id: session_id.to_string(),
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_INACTIVE,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!("failed to send session inactive: {e}");
+				}
+			}
+			S2CMsg::SplashEnded => {
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::no_payload(message_header::SPLASH_ENDED))
+					.await
+				{
+					tracing::warn!("failed to send splash ended: {e}");
+				}
+			}
+			S2CMsg::SessionState { session, epoch } => {
+				let payload = SessionStatePayload {
+					session,
+					epoch: Some(epoch),
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_STATE,
+						payload,
+					))
 					.await
 				{
 					tracing::warn!("failed to send session state: {e}");
@@ -389,77 +833,348 @@ impl Client {
 				let payload = SessionSleepPayload {
 					session_id: session_id.to_string(),
 				};
-				if let Err(e) = TabMessageFrame::json(message_header::SESSION_SLEEP, payload)
-					.send_frame_to_async_fd(&self.socket)
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_SLEEP,
+						payload,
+					))
 					.await
 				{
 					tracing::warn!("failed to send session sleep: {e}");
 				}
 			}
+			S2CMsg::SessionVisibility { visible } => {
+				let payload = SessionVisibilityPayload { visible };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_VISIBILITY,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!("failed to send session visibility: {e}");
+				}
+			}
 			S2CMsg::InputEvent { event } => {
-				if let Err(e) = TabMessageFrame::json(message_header::INPUT_EVENT, event)
-					.send_frame_to_async_fd(&self.socket)
+				if self.local_key_repeat && matches!(event, InputEventPayload::Key { repeat: true, .. }) {
+					return;
+				}
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::INPUT_EVENT, event))
 					.await
 				{
 					tracing::warn!("failed to send input event: {e}");
 				}
 			}
-			S2CMsg::MonitorAdded { monitor } => {
-				let payload = MonitorAddedPayload {
-					monitor: monitor.to_protocol_info(),
+			S2CMsg::Focus {
+				session_id,
+				target,
+				focused,
+			} => {
+				let header = if focused {
+					message_header::FOCUS_IN
+				} else {
+					message_header::FOCUS_OUT
+				};
+				let payload = FocusPayload {
+					session_id: session_id.to_string(),
+					target,
 				};
-				if let Err(e) = TabMessageFrame::json(message_header::MONITOR_ADDED, payload)
-					.send_frame_to_async_fd(&self.socket)
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(header, payload))
 					.await
 				{
-					tracing::warn!("failed to send monitor added: {e}");
+					tracing::warn!("failed to send focus change: {e}");
 				}
 			}
-			S2CMsg::MonitorRemoved { monitor_id, name } => {
-				let payload = MonitorRemovedPayload {
-					monitor_id: monitor_id.to_string(),
-					name: name.to_string(),
+			S2CMsg::PointerLock { locked } => {
+				let payload = PointerLockStatePayload { locked };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::POINTER_LOCK_STATE,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!("failed to send pointer lock state: {e}");
+				}
+			}
+			S2CMsg::ShortcutTriggered { id } => {
+				let payload = ShortcutTriggeredPayload { id: id.to_string() };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SHORTCUT_TRIGGERED,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!("failed to send shortcut triggered: {e}");
+				}
+			}
+			S2CMsg::LogRecords { records, more } => {
+				let payload = LogRecordsPayload { records, more };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::LOG_RECORDS, payload))
+					.await
+				{
+					tracing::warn!("failed to send log records: {e}");
+				}
+			}
+			S2CMsg::Sessions { sessions } => {
+				let payload = SessionsPayload { sessions };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::SESSIONS, payload))
+					.await
+				{
+					tracing::warn!("failed to send session list: {e}");
+				}
+			}
+			S2CMsg::Transitions { transitions } => {
+				let payload = TransitionsPayload { transitions };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::TRANSITIONS, payload))
+					.await
+				{
+					tracing::warn!("failed to send transition list: {e}");
+				}
+			}
+			S2CMsg::Stats(payload) => {
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::STATS, payload))
+					.await
+				{
+					tracing::warn!("failed to send stats: {e}");
+				}
+			}
+			S2CMsg::StateSync(payload) => {
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::STATE_SYNC, payload))
+					.await
+				{
+					tracing::warn!("failed to send state sync: {e}");
+				}
+			}
+			S2CMsg::CompositorHealth(payload) => {
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::COMPOSITOR_HEALTH,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!("failed to send compositor health: {e}");
+				}
+			}
+			S2CMsg::SessionUnresponsive {
+				session_id,
+				unresponsive,
+				idle,
+			} => {
+				let payload = SessionUnresponsivePayload {
+					session_id: session_id.to_string(),
+					unresponsive,
+					idle_ms: idle.as_millis() as u64,
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_UNRESPONSIVE,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(%session_id, "failed to send session unresponsive: {e}");
+				}
+			}
+			S2CMsg::SessionCrashed { session_id, grace } => {
+				let payload = SessionCrashedPayload {
+					session_id: session_id.to_string(),
+					grace_ms: grace.as_millis() as u64,
+				};
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::SESSION_CRASHED,
+						payload,
+					))
+					.await
+				{
+					tracing::warn!(%session_id, "failed to send session crashed: {e}");
+				}
+			}
+			S2CMsg::Screenshot(screenshot) => {
+				let payload = ScreenshotDataPayload {
+					monitor_id: screenshot.monitor_id.to_string(),
+					width: screenshot.width,
+					height: screenshot.height,
+					stride: screenshot.stride,
+					fourcc: screenshot.fourcc,
 				};
-				if let Err(e) = TabMessageFrame::json(message_header::MONITOR_REMOVED, payload)
-					.send_frame_to_async_fd(&self.socket)
+				let mut frame = TabMessageFrame::json(message_header::SCREENSHOT_DATA, payload);
+				frame.fds.push(screenshot.pixels);
+				if let Err(e) = self.send_frame(frame).await {
+					tracing::warn!(monitor_id = %screenshot.monitor_id, "failed to send screenshot: {e}");
+				}
+			}
+			S2CMsg::Backlights { backlights } => {
+				let payload = BacklightsPayload { backlights };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::BACKLIGHTS, payload))
+					.await
+				{
+					tracing::warn!("failed to send backlights: {e}");
+				}
+			}
+			S2CMsg::DeviceConfigs { devices } => {
+				let payload = DeviceConfigsPayload { devices };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(
+						message_header::DEVICE_CONFIGS,
+						payload,
+					))
 					.await
 				{
-					tracing::warn!("failed to send monitor removed: {e}");
+					tracing::warn!("failed to send device configs: {e}");
+				}
+			}
+			S2CMsg::LidClosed { closed } => {
+				let payload = LidClosedPayload { closed };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::LID_CLOSED, payload))
+					.await
+				{
+					tracing::warn!("failed to send lid_closed: {e}");
+				}
+			}
+			S2CMsg::TabletMode { enabled } => {
+				let payload = TabletModePayload { enabled };
+				if let Err(e) = self
+					.send_frame(TabMessageFrame::json(message_header::TABLET_MODE, payload))
+					.await
+				{
+					tracing::warn!("failed to send tablet_mode: {e}");
+				}
+			}
+			S2CMsg::Selection {
+				mime_type,
+				size,
+				source,
+				data,
+			} => {
+				let payload = SelectionDataPayload {
+					mime_type,
+					size,
+					source_session_id: source.to_string(),
+				};
+				let mut frame = TabMessageFrame::json(message_header::SELECTION_DATA, payload);
+				frame.fds.push(data);
+				if let Err(e) = self.send_frame(frame).await {
+					tracing::warn!("failed to send selection: {e}");
+				}
+			}
+			S2CMsg::WireTap(config) => {
+				self.wire_tap = config.and_then(|config| match WireTap::open(self.id, config) {
+					Ok(tap) => Some(tap),
+					Err(e) => {
+						tracing::warn!("failed to open the wire tap capture: {e}");
+						None
+					}
+				});
+				match &self.wire_tap {
+					Some(tap) => tracing::info!(capture = ?tap.capture_path(), "wire tap on"),
+					None => tracing::info!("wire tap off"),
+				}
+			}
+			S2CMsg::Broadcast(frame) => {
+				// Broadcasts tend to come in bursts, e.g. a hotplug, so whatever is already queued goes
+				// out in the same write.
+				let mut frames = vec![frame];
+				let mut next = None;
+				while let Ok(msg) = self.channel_client_end.from_server().try_recv() {
+					match msg {
+						S2CMsg::Broadcast(frame) => frames.push(frame),
+						msg => {
+							next = Some(msg);
+							break;
+						}
+					}
+				}
+				if let Some(tap) = &self.wire_tap {
+					for frame in &frames {
+						if let Ok(Some((frame, _))) = TabMessageFrame::parse_from_bytes(frame.bytes(false)) {
+							tap.record(Direction::Out, &frame);
+						}
+					}
+				}
+				if let Err(e) =
+					SharedFrame::send_all_to_async_fd(&frames, self.compress_payloads, &self.socket).await
+				{
+					tracing::warn!("failed to send {} broadcast frames: {e}", frames.len());
+				}
+				if let Some(msg) = next {
+					Box::pin(self.handle_server_layer_msg(Some(msg))).await;
 				}
 			}
 		}
 	}
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
 	async fn schedule_client_shutdown(&mut self) {
 		tracing::info!("terminating client");
 		let _ = self
 			.channel_client_end
 			.to_server()
-			.send(C2SMsg::Shutdown)
+			.send(Correlated::untagged(C2SMsg::Shutdown))
 			.await;
 		self.shutdown = true;
 	}
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
+	async fn handshake_timed_out(&mut self) {
+		let error = Error::HandshakeTimeout(format!("handshake timed out while {}", self.state));
+		self.send_error(&error).await;
+		self.schedule_client_shutdown().await;
+	}
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
 	async fn run(mut self) {
+		let status = self.channel_client_end.status_from_server();
 		loop {
 			tokio::select! {
-					read_frame_result = self.frame_reader.read_frame_from_async_fd(&self.socket) => match read_frame_result.and_then(TabMessage::try_from) {
-							Ok(packet) => self.handle_packet(packet).await,
+					read_frame_result = self.frame_reader.read_frame_from_async_fd(&self.socket) => match read_frame_result {
+							Ok(frame) => self.handle_frame(CorrelationId::mint(), frame).await,
 							Err(e) => {
-									self.send_error("protocol_violation", Some(e)).await;
+									self.send_error(&e.into()).await;
 									self.schedule_client_shutdown().await;
 							}
 					},
-					server_layer_message = self.channel_client_end.from_server().recv() => self.handle_server_layer_msg(server_layer_message).await
+					server_layer_message = self.channel_client_end.from_server().recv() => self.handle_server_layer_msg(server_layer_message).await,
+					status = status.recv() => self.handle_server_layer_msg(Some(status)).await,
+					_ = handshake_expired(self.handshake_deadline) => self.handshake_timed_out().await,
 			}
 			if self.shutdown {
 				return;
 			}
 		}
 	}
-	#[tracing::instrument(skip(self), fields(client.id = self.id().to_string()))]
+	#[tracing::instrument(skip(self), fields(client.id = %self.id().short()))]
 	pub async fn spawn(self) -> JoinHandle<()> {
 		tokio::spawn(self.run().instrument(Span::current()))
 	}
 }
 define_id_type!(Client, "cl_");
+
+/// Bytes of the whole rows among `len` decoded ones, if they fit in the buffer `payload` describes.
+fn uploaded_rows_len(payload: &BufferUploadPayload, len: usize) -> Option<usize> {
+	if payload.width <= 0 || payload.height <= 0 || payload.stride <= 0 || payload.y < 0 {
+		return None;
+	}
+	let row_bytes = usize::try_from(payload.width).ok()?.checked_mul(4)?;
+	let stride = usize::try_from(payload.stride).ok()?;
+	let rows = len / stride;
+	let end = usize::try_from(payload.y).ok()?.checked_add(rows)?;
+	(stride >= row_bytes && rows > 0 && end <= usize::try_from(payload.height).ok()?)
+		.then(|| rows * stride)
+}
+
+async fn handshake_expired(deadline: Option<Instant>) {
+	match deadline {
+		Some(deadline) => sleep_until(deadline).await,
+		None => std::future::pending().await,
+	}
+}
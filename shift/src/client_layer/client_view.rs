@@ -1,19 +1,44 @@
-use std::{rc::Rc, sync::Arc};
+use std::{
+	os::fd::OwnedFd,
+	rc::Rc,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::Duration,
+};
+
+use tokio::sync::mpsc::error::TrySendError;
 
 use crate::{
 	auth::{self, Token},
-	client_layer::client::{Client, ClientId},
+	client_layer::{
+		client::{Client, ClientId},
+		wire_tap::WireTapConfig,
+	},
 	comms::{
 		client2server::{C2SMsg, C2SRx, C2STx, C2SWeakTx},
+		correlation::Correlated,
+		queues::{self, LossyQueue, Overflow},
+		render2server::Screenshot,
 		server2client::{BufferRelease, S2CMsg, S2CRx, S2CTx},
 	},
+	error::Error,
 	monitor::{Monitor, MonitorId},
 	sessions::{PendingSession, Session, SessionId},
 };
-use tab_protocol::{InputEventPayload, SessionInfo};
+use tab_protocol::{
+	BacklightInfo, CompositorHealthPayload, DeviceConfig, FocusTarget, InputEventPayload,
+	SessionInfo, SharedFrame, StateSyncPayload, StatsPayload, TransitionInfo,
+};
+
+/// Default of `SHIFT_CLIENT_QUEUE`, for each direction.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 5000;
+/// Status messages only the latest of matters, see [`S2CMsg::overflow`].
+const STATUS_QUEUE_CAPACITY: usize = 8;
 
 #[derive(Debug)]
-pub struct ChannelsServerEnd(C2SRx, S2CTx);
+pub struct ChannelsServerEnd(C2SRx, S2CTx, Arc<LossyQueue<S2CMsg>>);
 
 impl ChannelsServerEnd {
 	pub fn to_client(&self) -> &S2CTx {
@@ -24,7 +49,7 @@ impl ChannelsServerEnd {
 	}
 }
 #[derive(Debug)]
-pub struct ChannelsClientEnd(S2CRx, C2STx);
+pub struct ChannelsClientEnd(S2CRx, C2STx, Arc<LossyQueue<S2CMsg>>);
 
 impl ChannelsClientEnd {
 	pub fn to_server(&self) -> &C2STx {
@@ -33,6 +58,9 @@ impl ChannelsClientEnd {
 	pub fn from_server(&mut self) -> &mut S2CRx {
 		&mut self.0
 	}
+	pub fn status_from_server(&self) -> Arc<LossyQueue<S2CMsg>> {
+		Arc::clone(&self.2)
+	}
 }
 #[derive(Debug)]
 pub struct Channels {
@@ -40,12 +68,13 @@ pub struct Channels {
 	pub server_end: ChannelsServerEnd,
 }
 impl Channels {
-	pub(super) fn new() -> Self {
-		let c2s = tokio::sync::mpsc::channel(5000);
-		let s2c = tokio::sync::mpsc::channel(5000);
+	pub(super) fn new(capacity: usize) -> Self {
+		let c2s = tokio::sync::mpsc::channel(capacity);
+		let s2c = tokio::sync::mpsc::channel(capacity);
+		let status = Arc::new(LossyQueue::new(STATUS_QUEUE_CAPACITY));
 		Self {
-			client_end: ChannelsClientEnd(s2c.1, c2s.0),
-			server_end: ChannelsServerEnd(c2s.1, s2c.0),
+			client_end: ChannelsClientEnd(s2c.1, c2s.0, Arc::clone(&status)),
+			server_end: ChannelsServerEnd(c2s.1, s2c.0, status),
 		}
 	}
 }
@@ -54,6 +83,19 @@ pub struct ClientView {
 	id: ClientId,
 	pub(super) channels: ChannelsServerEnd,
 	session_id: Option<SessionId>,
+	/// Sends that waited for room in the client's queue.
+	blocked: AtomicU64,
+}
+
+/// How full one client's queues are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientQueues {
+	pub to_server: usize,
+	pub to_client: usize,
+	pub to_client_blocked: u64,
+	pub status: usize,
+	pub status_capacity: usize,
+	pub status_dropped: u64,
 }
 
 impl ClientView {
@@ -62,13 +104,59 @@ impl ClientView {
 			id: client.id(),
 			channels,
 			session_id: None,
+			blocked: AtomicU64::new(0),
+		}
+	}
+
+	/// A client living in shift rather than behind a socket, e.g. the DBus service. It drives the
+	/// returned end itself.
+	#[cfg(feature = "dbus")]
+	pub fn in_process(id: ClientId, queue_capacity: usize) -> (ChannelsClientEnd, ClientView) {
+		let channels = Channels::new(queue_capacity);
+		let client_view = Self {
+			id,
+			channels: channels.server_end,
+			session_id: None,
+			blocked: AtomicU64::new(0),
+		};
+		(channels.client_end, client_view)
+	}
+
+	/// Queues a message for the client, waiting for room or dropping older status as
+	/// [`S2CMsg::overflow`] says.
+	async fn send(&self, msg: S2CMsg) -> bool {
+		if msg.overflow() == Overflow::DropOldest {
+			if self.channels.2.push(msg) {
+				tracing::debug!(client_id = %self.id, "client status queue full, dropped the oldest");
+			}
+			return !self.channels.1.is_closed();
+		}
+		match self.channels.1.try_send(msg) {
+			Ok(()) => true,
+			Err(TrySendError::Closed(_)) => false,
+			Err(TrySendError::Full(msg)) => {
+				tracing::warn!(client_id = %self.id, "client queue full, waiting for room");
+				self.blocked.fetch_add(1, Ordering::Relaxed);
+				self.channels.1.send(msg).await.is_ok()
+			}
+		}
+	}
+
+	pub fn queues(&self) -> ClientQueues {
+		ClientQueues {
+			to_server: self.channels.0.len(),
+			to_client: queues::depth(&self.channels.1),
+			to_client_blocked: self.blocked.load(Ordering::Relaxed),
+			status: self.channels.2.len(),
+			status_capacity: self.channels.2.capacity(),
+			status_dropped: self.channels.2.dropped(),
 		}
 	}
 
 	pub fn id(&self) -> ClientId {
 		self.id
 	}
-	pub async fn read_message(&mut self) -> Option<C2SMsg> {
+	pub async fn read_message(&mut self) -> Option<Correlated<C2SMsg>> {
 		self.channels.from_client().recv().await
 	}
 	pub fn running(&self) -> bool {
@@ -78,47 +166,18 @@ impl ClientView {
 		!self.channels.0.is_closed() || !self.channels.0.is_empty()
 	}
 	pub async fn notify_auth_error(&self, reason: auth::error::Error) -> bool {
-		self
-			.channels
-			.1
-			.send(S2CMsg::AuthError(reason))
-			.await
-			.is_ok()
+		self.send(S2CMsg::AuthError(reason)).await
 	}
 	pub async fn notify_auth_success(&mut self, session: &Arc<Session>) -> bool {
 		self.session_id = Some(session.id());
-		self
-			.channels
-			.1
-			.send(S2CMsg::BindToSession(Arc::clone(&session)))
-			.await
-			.is_ok()
+		self.send(S2CMsg::BindToSession(Arc::clone(&session))).await
 	}
 	pub async fn notify_session_created(&mut self, token: Token, session: PendingSession) -> bool {
-		self
-			.channels
-			.1
-			.send(S2CMsg::SessionCreated(token, session))
-			.await
-			.is_ok()
+		self.send(S2CMsg::SessionCreated(token, session)).await
 	}
 
-	pub async fn notify_error(
-		&mut self,
-		code: Arc<str>,
-		error: Option<Arc<str>>,
-		shutdown: bool,
-	) -> bool {
-		self
-			.channels
-			.1
-			.send(S2CMsg::Error {
-				code,
-				error,
-				shutdown,
-			})
-			.await
-			.is_ok()
+	pub async fn notify_error(&mut self, error: Error, shutdown: bool) -> bool {
+		self.send(S2CMsg::Error { error, shutdown }).await
 	}
 
 	pub fn authenticated_session(&self) -> Option<SessionId> {
@@ -126,87 +185,221 @@ impl ClientView {
 	}
 
 	pub async fn notify_buffer_release(&mut self, buffers: Vec<BufferRelease>) -> bool {
-		self
-			.channels
-			.1
-			.send(S2CMsg::BufferRelease { buffers })
-			.await
-			.is_ok()
+		self.send(S2CMsg::BufferRelease { buffers }).await
 	}
 
 	pub async fn notify_buffer_request_ack(
 		&mut self,
 		monitor_id: MonitorId,
 		buffer: tab_protocol::BufferIndex,
+		generation: u64,
 	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::BufferRequestAck { monitor_id, buffer })
+			.send(S2CMsg::BufferRequestAck {
+				monitor_id,
+				buffer,
+				generation,
+			})
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_monitor_added(&mut self, monitor: Monitor) -> bool {
+	pub async fn notify_broadcast(&mut self, frame: SharedFrame) -> bool {
+		self.send(S2CMsg::Broadcast(frame)).await
+	}
+
+	pub async fn notify_session_awake(&mut self, session_id: SessionId) -> bool {
+		self.send(S2CMsg::SessionAwake { session_id }).await
+	}
+
+	pub async fn notify_session_active(&mut self, session_id: SessionId, epoch: u64) -> bool {
+		self.send(S2CMsg::SessionActive { session_id, epoch }).await
+	}
+
+	pub async fn notify_session_inactive(&mut self, session_id: SessionId) -> bool {
+		self.send(S2CMsg::SessionInactive { session_id }).await
+	}
+
+	pub async fn notify_splash_ended(&mut self) -> bool {
+		self.send(S2CMsg::SplashEnded).await
+	}
+
+	pub async fn notify_session_state(&mut self, session: SessionInfo, epoch: u64) -> bool {
+		self.send(S2CMsg::SessionState { session, epoch }).await
+	}
+
+	pub async fn notify_session_sleep(&mut self, session_id: SessionId) -> bool {
+		self.send(S2CMsg::SessionSleep { session_id }).await
+	}
+
+	pub async fn notify_session_visibility(&mut self, visible: bool) -> bool {
+		self.send(S2CMsg::SessionVisibility { visible }).await
+	}
+
+	pub async fn notify_focus_change(
+		&mut self,
+		session_id: SessionId,
+		target: FocusTarget,
+		focused: bool,
+	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::MonitorAdded { monitor })
+			.send(S2CMsg::Focus {
+				session_id,
+				target,
+				focused,
+			})
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_monitor_removed(&mut self, monitor_id: MonitorId, name: Arc<str>) -> bool {
+	pub async fn notify_pointer_lock(&mut self, locked: bool) -> bool {
+		self.send(S2CMsg::PointerLock { locked }).await
+	}
+
+	pub async fn notify_log_records(&mut self, records: Vec<String>, more: bool) -> bool {
+		self.send(S2CMsg::LogRecords { records, more }).await
+	}
+
+	pub async fn notify_sessions(&mut self, sessions: Vec<SessionInfo>) -> bool {
+		self.send(S2CMsg::Sessions { sessions }).await
+	}
+
+	pub async fn notify_transitions(&mut self, transitions: Vec<TransitionInfo>) -> bool {
+		self.send(S2CMsg::Transitions { transitions }).await
+	}
+
+	pub async fn notify_framebuffer_link_failed(
+		&mut self,
+		monitor_id: MonitorId,
+		reason: String,
+		supported_formats: Vec<i32>,
+	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::MonitorRemoved { monitor_id, name })
+			.send(S2CMsg::FramebufferLinkFailed {
+				monitor_id,
+				reason,
+				supported_formats,
+			})
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_session_awake(&mut self, session_id: SessionId) -> bool {
+	pub async fn notify_relink_request(&mut self, monitor_id: MonitorId) -> bool {
+		self.send(S2CMsg::RelinkRequest { monitor_id }).await
+	}
+
+	pub async fn notify_buffers_invalidated(&mut self, monitor_ids: Vec<MonitorId>) -> bool {
+		self.send(S2CMsg::BuffersInvalidated { monitor_ids }).await
+	}
+
+	pub async fn notify_frames_dropped(
+		&mut self,
+		monitor_id: MonitorId,
+		count: u32,
+		reason: tab_protocol::FrameDropReason,
+	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::SessionAwake { session_id })
+			.send(S2CMsg::FramesDropped {
+				monitor_id,
+				count,
+				reason,
+			})
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_session_active(&mut self, session_id: SessionId) -> bool {
+	pub async fn notify_performance_warning(
+		&mut self,
+		monitor_id: MonitorId,
+		kind: tab_protocol::PerformanceWarningKind,
+		duration: Duration,
+		frames: u32,
+	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::SessionActive { session_id })
+			.send(S2CMsg::PerformanceWarning {
+				monitor_id,
+				kind,
+				duration,
+				frames,
+			})
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_session_state(&mut self, session: SessionInfo) -> bool {
+	pub async fn notify_stats(&mut self, stats: StatsPayload) -> bool {
+		self.send(S2CMsg::Stats(stats)).await
+	}
+
+	pub async fn notify_state_sync(&mut self, state: StateSyncPayload) -> bool {
+		self.send(S2CMsg::StateSync(state)).await
+	}
+
+	pub async fn notify_compositor_health(&mut self, health: CompositorHealthPayload) -> bool {
+		self.send(S2CMsg::CompositorHealth(health)).await
+	}
+
+	pub async fn notify_session_unresponsive(
+		&mut self,
+		session_id: SessionId,
+		unresponsive: bool,
+		idle: Duration,
+	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::SessionState { session })
+			.send(S2CMsg::SessionUnresponsive {
+				session_id,
+				unresponsive,
+				idle,
+			})
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_session_sleep(&mut self, session_id: SessionId) -> bool {
+	pub async fn notify_session_crashed(&mut self, session_id: SessionId, grace: Duration) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::SessionSleep { session_id })
+			.send(S2CMsg::SessionCrashed { session_id, grace })
 			.await
-			.is_ok()
 	}
 
-	pub async fn notify_input_event(&mut self, event: InputEventPayload) -> bool {
+	pub async fn notify_screenshot(&mut self, screenshot: Screenshot) -> bool {
+		self.send(S2CMsg::Screenshot(screenshot)).await
+	}
+
+	pub async fn notify_backlights(&mut self, backlights: Vec<BacklightInfo>) -> bool {
+		self.send(S2CMsg::Backlights { backlights }).await
+	}
+
+	pub async fn notify_device_configs(&mut self, devices: Vec<DeviceConfig>) -> bool {
+		self.send(S2CMsg::DeviceConfigs { devices }).await
+	}
+
+	pub async fn notify_lid_closed(&mut self, closed: bool) -> bool {
+		self.send(S2CMsg::LidClosed { closed }).await
+	}
+
+	pub async fn notify_tablet_mode(&mut self, enabled: bool) -> bool {
+		self.send(S2CMsg::TabletMode { enabled }).await
+	}
+
+	pub async fn notify_selection(
+		&mut self,
+		mime_type: String,
+		size: u64,
+		source: SessionId,
+		data: OwnedFd,
+	) -> bool {
 		self
-			.channels
-			.1
-			.send(S2CMsg::InputEvent { event })
+			.send(S2CMsg::Selection {
+				mime_type,
+				size,
+				source,
+				data,
+			})
 			.await
-			.is_ok()
+	}
+
+	pub async fn notify_shortcut_triggered(&mut self, id: Arc<str>) -> bool {
+		self.send(S2CMsg::ShortcutTriggered { id }).await
+	}
+
+	pub async fn notify_input_event(&mut self, event: InputEventPayload) -> bool {
+		self.send(S2CMsg::InputEvent { event }).await
+	}
+
+	pub async fn notify_wire_tap(&mut self, config: Option<WireTapConfig>) -> bool {
+		self.send(S2CMsg::WireTap(config)).await
 	}
 }
@@ -0,0 +1,445 @@
+//! Which messages a client connection may send, given how far it got.
+//! - `AwaitingAuth`: `auth`, until the server accepts a token
+//! - `Authenticating`: the token is with the server, nothing else until it answers
+//! - `Authenticated`: role-gated requests; presenting sessions may link or upload buffers
+//! - `Linked`: a presenting session with buffers, which may now request them on screen
+//!
+//! `ping` is legal in every state. Messages only shift sends are never legal.
+//!
+//! Connections that stall in the handshake are closed with `handshake_timeout`, see
+//! [`HandshakeTimeouts`].
+
+use std::{
+	fmt::{self, Display},
+	time::Duration,
+};
+
+use tab_protocol::message_header;
+
+use crate::{error::Error, sessions::Role};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+	AwaitingAuth,
+	Authenticating,
+	Authenticated(Role),
+	Linked(Role),
+}
+
+/// Why a message was refused.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Rejection {
+	/// Out of order for this connection: answered with `invalid_state`.
+	InvalidState {
+		header: String,
+		state: ConnectionState,
+	},
+	/// In order, but not for this session's role: answered with `forbidden`.
+	Forbidden(String),
+	/// A message only shift sends, or no one does: answered with `unknown_message`, and the
+	/// connection is closed.
+	NotAClientMessage(String),
+}
+
+/// Who may send a message, and from when on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+	Anytime,
+	BeforeAuth,
+	/// Sessions that put pixels on screen, i.e. not observers.
+	Presenter,
+	/// Presenters that linked or uploaded buffers.
+	LinkedPresenter,
+	Observer,
+	Admin,
+	ShiftOnly,
+}
+
+fn access(header: &str) -> Access {
+	match header {
+		message_header::PING => Access::Anytime,
+		message_header::AUTH => Access::BeforeAuth,
+		message_header::FRAMEBUFFER_LINK
+		| message_header::BUFFER_UPLOAD
+		| message_header::SESSION_READY
+		| message_header::POINTER_LOCK
+		| message_header::SELECTION_OFFER
+		| message_header::SELECTION_REQUEST
+		| message_header::PRESENT_GROUP => Access::Presenter,
+		message_header::BUFFER_REQUEST | message_header::FRAMES_SKIPPED => Access::LinkedPresenter,
+		message_header::SESSION_LIST
+		| message_header::TRANSITIONS_LIST
+		| message_header::STATS_REQUEST
+		| message_header::STATE_SYNC_REQUEST
+		| message_header::COMPOSITOR_HEALTH_SUBSCRIBE
+		| message_header::SCREENSHOT => Access::Observer,
+		message_header::SESSION_SWITCH
+		| message_header::TRANSITION_DEFINE
+		| message_header::SESSION_CREATE
+		| message_header::SESSION_PIP
+		| message_header::MONITOR_LAYOUT
+		| message_header::MONITOR_HDR
+		| message_header::MONITOR_MIRROR
+		| message_header::MONITOR_FILTER
+		| message_header::ZOOM_SET
+		| message_header::SESSION_ASSIGN_MONITOR
+		| message_header::SHORTCUT_REGISTER
+		| message_header::SHORTCUT_UNREGISTER
+		| message_header::DEBUG_HUD
+		| message_header::BACKGROUND_SET
+		| message_header::LOG_LEVEL
+		| message_header::LOG_DUMP
+		| message_header::WIRE_TAP
+		| message_header::SELECTION_POLICY
+		| message_header::BACKLIGHT_GET
+		| message_header::BACKLIGHT_SET
+		| message_header::DEVICE_CALIBRATION
+		| message_header::DEVICE_CONFIGURE
+		| message_header::DEVICE_CONFIG_GET
+		| message_header::DEVICE_GRAB
+		| message_header::INPUT_INJECT
+		| message_header::SWITCH_EVENTS_SUBSCRIBE => Access::Admin,
+		_ => Access::ShiftOnly,
+	}
+}
+
+impl ConnectionState {
+	/// Checks that `header` may be sent now. Doesn't change the state.
+	pub fn check(&self, header: &str) -> Result<(), Rejection> {
+		let invalid_state = || Rejection::InvalidState {
+			header: header.to_string(),
+			state: *self,
+		};
+		let access = access(header);
+		let role = match (access, self) {
+			(Access::ShiftOnly, _) => return Err(Rejection::NotAClientMessage(header.to_string())),
+			(Access::Anytime, _) | (Access::BeforeAuth, Self::AwaitingAuth) => return Ok(()),
+			(_, Self::AwaitingAuth | Self::Authenticating) | (Access::BeforeAuth, _) => {
+				return Err(invalid_state());
+			}
+			(_, Self::Authenticated(role) | Self::Linked(role)) => *role,
+		};
+		match access {
+			Access::Presenter | Access::LinkedPresenter if role == Role::Observer => Err(
+				Rejection::Forbidden(format!("observer sessions can't send {header}")),
+			),
+			Access::LinkedPresenter if !matches!(self, Self::Linked(_)) => Err(invalid_state()),
+			Access::Observer if !role.can_observe() => Err(Rejection::Forbidden(format!(
+				"{header} needs an admin or observer client"
+			))),
+			Access::Admin if !role.is_admin() => Err(Rejection::Forbidden(format!(
+				"{header} needs an admin client"
+			))),
+			_ => Ok(()),
+		}
+	}
+
+	/// The client sent a token, the server is checking it.
+	pub fn auth_sent(&mut self) {
+		*self = Self::Authenticating;
+	}
+
+	/// The server refused the token; the client may try another.
+	pub fn auth_failed(&mut self) {
+		*self = Self::AwaitingAuth;
+	}
+
+	pub fn authenticated(&mut self, role: Role) {
+		*self = Self::Authenticated(role);
+	}
+
+	/// The client handed shift buffers to present, with `framebuffer_link` or `buffer_upload`.
+	pub fn buffers_linked(&mut self) {
+		if let Self::Authenticated(role) = *self {
+			*self = Self::Linked(role);
+		}
+	}
+}
+
+const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_LINK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a connection may take through the handshake, `None` for no limit:
+/// - `auth`, from connecting to `auth_ok`, `SHIFT_AUTH_TIMEOUT_MS` (default 10s)
+/// - `link`, from `auth_ok` to a normal session's first `framebuffer_link` or `buffer_upload`,
+///   `SHIFT_LINK_TIMEOUT_MS` (default 30s)
+///
+/// A limit of 0 turns it off. Admins and observers don't have to present, so only `auth` bounds
+/// them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HandshakeTimeouts {
+	pub auth: Option<Duration>,
+	pub link: Option<Duration>,
+}
+
+impl HandshakeTimeouts {
+	pub fn from_env() -> Self {
+		Self {
+			auth: timeout_from_env("SHIFT_AUTH_TIMEOUT_MS", DEFAULT_AUTH_TIMEOUT),
+			link: timeout_from_env("SHIFT_LINK_TIMEOUT_MS", DEFAULT_LINK_TIMEOUT),
+		}
+	}
+
+	/// How long a connection that just got to `state` has to take the next step.
+	pub fn limit(&self, state: ConnectionState) -> Option<Duration> {
+		match state {
+			ConnectionState::AwaitingAuth | ConnectionState::Authenticating => self.auth,
+			ConnectionState::Authenticated(Role::Normal) => self.link,
+			ConnectionState::Authenticated(_) | ConnectionState::Linked(_) => None,
+		}
+	}
+}
+
+fn timeout_from_env(name: &str, default: Duration) -> Option<Duration> {
+	let Ok(raw) = std::env::var(name) else {
+		return Some(default);
+	};
+	match raw.trim().parse::<u64>() {
+		Ok(0) => None,
+		Ok(ms) => Some(Duration::from_millis(ms)),
+		Err(e) => {
+			tracing::warn!(value = %raw, "invalid {name}: {e}");
+			Some(default)
+		}
+	}
+}
+
+impl Display for ConnectionState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::AwaitingAuth => write!(f, "not authenticated"),
+			Self::Authenticating => write!(f, "waiting for authentication"),
+			Self::Authenticated(_) => write!(f, "authenticated without linked buffers"),
+			Self::Linked(_) => write!(f, "linked"),
+		}
+	}
+}
+
+impl From<Rejection> for Error {
+	fn from(value: Rejection) -> Self {
+		match value {
+			Rejection::InvalidState { header, state } => {
+				Self::InvalidState(format!("{header} is not allowed while {state}"))
+			}
+			Rejection::Forbidden(reason) => Self::Forbidden(Some(reason)),
+			Rejection::NotAClientMessage(header) => Self::UnknownMessage(header),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const CLIENT_ANYTIME: &[&str] = &[message_header::PING];
+	const CLIENT_BEFORE_AUTH: &[&str] = &[message_header::AUTH];
+	const CLIENT_PRESENTER: &[&str] = &[
+		message_header::FRAMEBUFFER_LINK,
+		message_header::BUFFER_UPLOAD,
+		message_header::SESSION_READY,
+		message_header::POINTER_LOCK,
+		message_header::SELECTION_OFFER,
+		message_header::SELECTION_REQUEST,
+		message_header::PRESENT_GROUP,
+	];
+	const CLIENT_LINKED: &[&str] = &[
+		message_header::BUFFER_REQUEST,
+		message_header::FRAMES_SKIPPED,
+	];
+	const CLIENT_OBSERVER: &[&str] = &[
+		message_header::SESSION_LIST,
+		message_header::TRANSITIONS_LIST,
+		message_header::STATS_REQUEST,
+		message_header::STATE_SYNC_REQUEST,
+		message_header::COMPOSITOR_HEALTH_SUBSCRIBE,
+		message_header::SCREENSHOT,
+	];
+	const CLIENT_ADMIN: &[&str] = &[
+		message_header::SESSION_SWITCH,
+		message_header::TRANSITION_DEFINE,
+		message_header::SESSION_CREATE,
+		message_header::SESSION_PIP,
+		message_header::MONITOR_LAYOUT,
+		message_header::MONITOR_HDR,
+		message_header::MONITOR_MIRROR,
+		message_header::MONITOR_FILTER,
+		message_header::ZOOM_SET,
+		message_header::SESSION_ASSIGN_MONITOR,
+		message_header::SHORTCUT_REGISTER,
+		message_header::SHORTCUT_UNREGISTER,
+		message_header::DEBUG_HUD,
+		message_header::BACKGROUND_SET,
+		message_header::LOG_LEVEL,
+		message_header::LOG_DUMP,
+		message_header::WIRE_TAP,
+		message_header::SELECTION_POLICY,
+		message_header::BACKLIGHT_GET,
+		message_header::BACKLIGHT_SET,
+		message_header::DEVICE_CALIBRATION,
+		message_header::DEVICE_CONFIGURE,
+		message_header::DEVICE_CONFIG_GET,
+		message_header::DEVICE_GRAB,
+		message_header::INPUT_INJECT,
+		message_header::SWITCH_EVENTS_SUBSCRIBE,
+	];
+
+	const STATES: &[ConnectionState] = &[
+		ConnectionState::AwaitingAuth,
+		ConnectionState::Authenticating,
+		ConnectionState::Authenticated(Role::Normal),
+		ConnectionState::Authenticated(Role::Admin),
+		ConnectionState::Authenticated(Role::Observer),
+		ConnectionState::Linked(Role::Normal),
+		ConnectionState::Linked(Role::Admin),
+	];
+
+	#[derive(Debug, PartialEq, Eq)]
+	enum Outcome {
+		Allowed,
+		InvalidState,
+		Forbidden,
+		NotAClientMessage,
+	}
+
+	fn outcome(state: ConnectionState, header: &str) -> Outcome {
+		match state.check(header) {
+			Ok(()) => Outcome::Allowed,
+			Err(Rejection::InvalidState { .. }) => Outcome::InvalidState,
+			Err(Rejection::Forbidden(_)) => Outcome::Forbidden,
+			Err(Rejection::NotAClientMessage(_)) => Outcome::NotAClientMessage,
+		}
+	}
+
+	fn expected(state: ConnectionState, header: &str) -> Outcome {
+		use ConnectionState::*;
+		let role = match state {
+			Authenticated(role) | Linked(role) => Some(role),
+			AwaitingAuth | Authenticating => None,
+		};
+		if CLIENT_ANYTIME.contains(&header) {
+			Outcome::Allowed
+		} else if CLIENT_BEFORE_AUTH.contains(&header) {
+			if state == AwaitingAuth {
+				Outcome::Allowed
+			} else {
+				Outcome::InvalidState
+			}
+		} else if CLIENT_PRESENTER.contains(&header) {
+			match role {
+				None => Outcome::InvalidState,
+				Some(Role::Observer) => Outcome::Forbidden,
+				Some(_) => Outcome::Allowed,
+			}
+		} else if CLIENT_LINKED.contains(&header) {
+			match (state, role) {
+				(_, None) => Outcome::InvalidState,
+				(_, Some(Role::Observer)) => Outcome::Forbidden,
+				(Linked(_), _) => Outcome::Allowed,
+				_ => Outcome::InvalidState,
+			}
+		} else if CLIENT_OBSERVER.contains(&header) {
+			match role {
+				None => Outcome::InvalidState,
+				Some(Role::Admin | Role::Observer) => Outcome::Allowed,
+				Some(Role::Normal) => Outcome::Forbidden,
+			}
+		} else if CLIENT_ADMIN.contains(&header) {
+			match role {
+				None => Outcome::InvalidState,
+				Some(Role::Admin) => Outcome::Allowed,
+				Some(_) => Outcome::Forbidden,
+			}
+		} else {
+			Outcome::NotAClientMessage
+		}
+	}
+
+	#[test]
+	fn every_message_in_every_state() {
+		for &state in STATES {
+			for &header in message_header::ALL {
+				assert_eq!(
+					outcome(state, header),
+					expected(state, header),
+					"{header} in {state:?}"
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn unknown_headers_are_not_client_messages() {
+		for &state in STATES {
+			assert_eq!(
+				outcome(state, "from_the_future"),
+				Outcome::NotAClientMessage
+			);
+		}
+	}
+
+	#[test]
+	fn auth_round_trip() {
+		let mut state = ConnectionState::AwaitingAuth;
+		state.auth_sent();
+		assert_eq!(state, ConnectionState::Authenticating);
+		state.auth_failed();
+		assert_eq!(state, ConnectionState::AwaitingAuth);
+		state.auth_sent();
+		state.authenticated(Role::Normal);
+		assert_eq!(state, ConnectionState::Authenticated(Role::Normal));
+		state.buffers_linked();
+		assert_eq!(state, ConnectionState::Linked(Role::Normal));
+		state.buffers_linked();
+		assert_eq!(state, ConnectionState::Linked(Role::Normal));
+	}
+
+	#[test]
+	fn linking_needs_an_authenticated_session() {
+		let mut state = ConnectionState::AwaitingAuth;
+		state.buffers_linked();
+		assert_eq!(state, ConnectionState::AwaitingAuth);
+	}
+
+	#[test]
+	fn handshake_deadlines_follow_the_state() {
+		let timeouts = HandshakeTimeouts {
+			auth: Some(DEFAULT_AUTH_TIMEOUT),
+			link: Some(DEFAULT_LINK_TIMEOUT),
+		};
+		let mut state = ConnectionState::AwaitingAuth;
+		assert_eq!(timeouts.limit(state), Some(DEFAULT_AUTH_TIMEOUT));
+		state.auth_sent();
+		assert_eq!(timeouts.limit(state), Some(DEFAULT_AUTH_TIMEOUT));
+		state.authenticated(Role::Normal);
+		assert_eq!(timeouts.limit(state), Some(DEFAULT_LINK_TIMEOUT));
+		state.buffers_linked();
+		assert_eq!(timeouts.limit(state), None);
+		for role in [Role::Admin, Role::Observer] {
+			assert_eq!(timeouts.limit(ConnectionState::Authenticated(role)), None);
+		}
+		let off = HandshakeTimeouts {
+			auth: None,
+			link: None,
+		};
+		assert_eq!(off.limit(ConnectionState::AwaitingAuth), None);
+	}
+
+	#[test]
+	fn rejections_map_to_error_codes() {
+		let error: Error = ConnectionState::AwaitingAuth
+			.check(message_header::BUFFER_REQUEST)
+			.unwrap_err()
+			.into();
+		assert_eq!(error.code(), "invalid_state");
+		let error: Error = ConnectionState::Authenticated(Role::Normal)
+			.check(message_header::SESSION_CREATE)
+			.unwrap_err()
+			.into();
+		assert_eq!(error.code(), "forbidden");
+		let error: Error = ConnectionState::Linked(Role::Admin)
+			.check(message_header::AUTH_OK)
+			.unwrap_err()
+			.into();
+		assert_eq!(error.code(), "unknown_message");
+	}
+}
@@ -1,2 +1,4 @@
 pub mod client;
 pub mod client_view;
+pub mod connection_state;
+pub mod wire_tap;
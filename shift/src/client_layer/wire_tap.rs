@@ -0,0 +1,192 @@
+//! A log of one connection's traffic, turned on by admins with `wire_tap` for clients that
+//! misbehave where no debugger can be attached.
+//! - every frame is recorded with its direction, header, payload size and FD count
+//! - payloads only when asked for, with `token` fields redacted and cut off after
+//!   [`MAX_PAYLOAD`] bytes
+//! - records go to the `shift::wire` log target, or when capturing to
+//!   `shift-wire-<client id>.log` next to crash reports
+
+use std::{
+	fs::File,
+	io::{self, Write},
+	os::unix::fs::OpenOptionsExt,
+	path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+use tab_protocol::{TabMessageFrame, message_header};
+
+use crate::client_layer::client::ClientId;
+
+/// Longest payload recorded, in bytes.
+const MAX_PAYLOAD: usize = 4096;
+const REDACTED: &str = "<redacted>";
+/// The only headers with a plain text payload, see `buffer_args` in the protocol schema.
+const TEXT_PAYLOADS: &[&str] = &[
+	message_header::BUFFER_REQUEST,
+	message_header::BUFFER_REQUEST_ACK,
+	message_header::BUFFER_RELEASE,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireTapConfig {
+	pub payloads: bool,
+	pub capture: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+	/// From the client.
+	In,
+	/// To the client.
+	Out,
+}
+
+impl Direction {
+	fn as_str(self) -> &'static str {
+		match self {
+			Self::In => "in",
+			Self::Out => "out",
+		}
+	}
+}
+
+#[derive(Debug)]
+pub struct WireTap {
+	payloads: bool,
+	capture: Option<(PathBuf, File)>,
+}
+
+impl WireTap {
+	/// Creates the capture file, or appends to it when the connection was tapped before. It lives
+	/// in the private [`report_dir`](crate::crash::report_dir), and a symlink in its place is
+	/// refused rather than written through.
+	pub fn open(client_id: ClientId, config: WireTapConfig) -> io::Result<Self> {
+		let capture = match config.capture {
+			true => {
+				let path = crate::crash::report_dir()?.join(format!("shift-wire-{client_id}.log"));
+				let file = File::options()
+					.create(true)
+					.append(true)
+					.mode(0o600)
+					.custom_flags(libc::O_NOFOLLOW)
+					.open(&path)?;
+				Some((path, file))
+			}
+			false => None,
+		};
+		Ok(Self {
+			payloads: config.payloads,
+			capture,
+		})
+	}
+
+	pub fn capture_path(&self) -> Option<&Path> {
+		self.capture.as_ref().map(|(path, _)| path.as_path())
+	}
+
+	/// Records a frame as it is before compression.
+	pub fn record(&self, direction: Direction, frame: &TabMessageFrame) {
+		let header = frame.header.0.as_str();
+		let size = frame.payload.as_ref().map_or(0, String::len);
+		let fds = frame.fds.len();
+		let payload = frame
+			.payload
+			.as_deref()
+			.filter(|_| self.payloads)
+			.map(|payload| redacted_payload(header, payload));
+		let Some((path, file)) = &self.capture else {
+			// The client's span names the connection.
+			tracing::info!(
+				target: "shift::wire",
+				direction = direction.as_str(),
+				header,
+				size,
+				fds,
+				payload,
+				"frame"
+			);
+			return;
+		};
+		let mut line = format!(
+			"{} {} {header} {size}B {fds}fd",
+			chrono::Local::now().format("%H:%M:%S%.3f"),
+			direction.as_str()
+		);
+		if let Some(payload) = payload {
+			line.push(' ');
+			line.push_str(&payload);
+		}
+		line.push('\n');
+		if let Err(e) = (&*file).write_all(line.as_bytes()) {
+			tracing::warn!(path = %path.display(), "failed to write the wire tap capture: {e}");
+		}
+	}
+}
+
+/// The payload with the values of `token` and `*_token` fields replaced. Text that isn't JSON is
+/// left out unless `header` has a text payload, as it may be a token sent wrong.
+fn redacted_payload(header: &str, payload: &str) -> String {
+	let mut payload = match serde_json::from_str::<Value>(payload) {
+		Ok(mut value) => {
+			redact(&mut value);
+			value.to_string()
+		}
+		Err(_) if TEXT_PAYLOADS.contains(&header) => payload.to_string(),
+		Err(_) => return "<invalid json>".into(),
+	};
+	if payload.len() > MAX_PAYLOAD {
+		let total = payload.len();
+		let end = (0..=MAX_PAYLOAD)
+			.rev()
+			.find(|end| payload.is_char_boundary(*end))
+			.unwrap_or(0);
+		payload.truncate(end);
+		payload.push_str(&format!("... ({total} bytes)"));
+	}
+	payload
+}
+
+fn redact(value: &mut Value) {
+	match value {
+		Value::Object(fields) => {
+			for (key, value) in fields {
+				if key == "token" || key.ends_with("_token") {
+					*value = REDACTED.into();
+				} else {
+					redact(value);
+				}
+			}
+		}
+		Value::Array(items) => items.iter_mut().for_each(redact),
+		_ => {}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn payloads_are_redacted_and_cut_off() {
+		let auth = r#"{"token":"s3cret","nested":[{"session_token":"s3cret","id":"se_1"}]}"#;
+		let redacted = redacted_payload(message_header::AUTH, auth);
+		assert!(!redacted.contains("s3cret"));
+		assert!(redacted.contains(r#""token":"<redacted>""#));
+		assert!(redacted.contains(r#""id":"se_1""#));
+
+		assert_eq!(
+			redacted_payload(message_header::AUTH, "token s3cret"),
+			"<invalid json>"
+		);
+		assert_eq!(
+			redacted_payload(message_header::BUFFER_REQUEST, "mo_1 0 3"),
+			"mo_1 0 3"
+		);
+
+		let long = serde_json::json!({ "data": "é".repeat(MAX_PAYLOAD) }).to_string();
+		let cut = redacted_payload(message_header::SELECTION_DATA, &long);
+		assert!(cut.len() <= MAX_PAYLOAD + 32);
+		assert!(cut.ends_with(&format!("... ({} bytes)", long.len())));
+	}
+}
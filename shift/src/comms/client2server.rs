@@ -1,29 +1,110 @@
 use std::os::fd::OwnedFd;
 
 use tab_protocol::{
-	BufferIndex, FramebufferLinkPayload, SessionCreatePayload, SessionReadyPayload,
-	SessionSwitchPayload,
+	BackgroundSetPayload, BacklightSetPayload, BufferIndex, DeviceCalibrationPayload,
+	DeviceConfigurePayload, DeviceGrabPayload, FramebufferLinkPayload, InputInjectPayload,
+	LogDumpPayload, LogLevelPayload, MonitorFilterPayload, MonitorHdrPayload, MonitorLayoutPayload,
+	MonitorMirrorPayload, PresentGroupPayload, SelectionPolicyPayload, SessionAssignMonitorPayload,
+	SessionCreatePayload, SessionPipPayload, SessionReadyPayload, SessionSwitchPayload,
+	ShortcutRegisterPayload, ShortcutUnregisterPayload, TransitionDefinePayload, WireTapPayload,
+	ZoomSetPayload,
 };
 
+use super::correlation::Correlated;
 use crate::{auth::Token, monitor::MonitorId};
+/// What a client authenticates with.
+#[derive(Debug)]
+pub enum AuthRequest {
+	Token(Token),
+	/// Who the client is, see `auth::peer`.
+	Peer,
+}
 #[derive(Debug)]
 pub enum C2SMsg {
 	Shutdown,
-	Auth(Token),
+	Auth(AuthRequest),
 	CreateSession(SessionCreatePayload),
 	SwitchSession(SessionSwitchPayload),
+	TransitionDefine(TransitionDefinePayload),
 	SessionReady(SessionReadyPayload),
+	SessionPip(SessionPipPayload),
+	MonitorLayout(MonitorLayoutPayload),
+	MonitorHdr(MonitorHdrPayload),
+	MonitorMirror(MonitorMirrorPayload),
+	MonitorFilter(MonitorFilterPayload),
+	ZoomSet(ZoomSetPayload),
+	AssignMonitor(SessionAssignMonitorPayload),
+	PointerLock {
+		enable: bool,
+	},
+	ShortcutRegister(ShortcutRegisterPayload),
+	ShortcutUnregister(ShortcutUnregisterPayload),
+	DebugHud {
+		enabled: bool,
+	},
+	BackgroundSet(BackgroundSetPayload),
+	LogLevel(LogLevelPayload),
+	LogDump(LogDumpPayload),
+	WireTap(WireTapPayload),
+	SessionList,
+	TransitionsList,
+	StatsRequest,
+	StateSyncRequest,
+	CompositorHealthSubscribe {
+		enabled: bool,
+	},
+	Screenshot {
+		monitor_id: MonitorId,
+	},
+	SelectionOffer {
+		mime_type: String,
+		data: OwnedFd,
+	},
+	SelectionRequest,
+	SelectionPolicy(SelectionPolicyPayload),
+	BacklightGet,
+	BacklightSet(BacklightSetPayload),
+	DeviceCalibration(DeviceCalibrationPayload),
+	DeviceConfigure(DeviceConfigurePayload),
+	DeviceConfigGet,
+	DeviceGrab(DeviceGrabPayload),
+	InputInject(InputInjectPayload),
+	SwitchEventsSubscribe {
+		enabled: bool,
+	},
+	/// The system is about to sleep (`start`) or woke up, from clients living in shift.
+	PrepareForSleep {
+		start: bool,
+	},
 	BufferRequest {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		/// 0 for the current link.
+		generation: u64,
 		acquire_fence: Option<OwnedFd>,
+		present_group: Option<u32>,
 	},
+	PresentGroup(PresentGroupPayload),
 	FramebufferLink {
 		payload: FramebufferLinkPayload,
 		dma_bufs: [OwnedFd; 2],
 	},
+	FramesSkipped {
+		count: u32,
+	},
+	BufferUpload {
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		width: i32,
+		height: i32,
+		stride: i32,
+		/// Row of the buffer `pixels` starts at.
+		y: i32,
+		/// Whole rows, `stride` bytes each.
+		pixels: Vec<u8>,
+	},
 }
 
-pub type C2SRx = tokio::sync::mpsc::Receiver<C2SMsg>;
-pub type C2STx = tokio::sync::mpsc::Sender<C2SMsg>;
-pub type C2SWeakTx = tokio::sync::mpsc::WeakSender<C2SMsg>;
+pub type C2SRx = tokio::sync::mpsc::Receiver<Correlated<C2SMsg>>;
+pub type C2STx = tokio::sync::mpsc::Sender<Correlated<C2SMsg>>;
+pub type C2SWeakTx = tokio::sync::mpsc::WeakSender<Correlated<C2SMsg>>;
@@ -0,0 +1,54 @@
+//! Ids that follow a client frame through shift, so `rg 'corr=c42\b'` finds all it caused.
+//! - the client task mints one for every frame it reads, see `Client::handle_frame`
+//! - it travels with the `C2SMsg`, the `RenderCmd`s the server sends while handling it and the
+//!   `RenderEvt`s the renderer emits while handling those, including a swap's later release
+//! - each layer handles a correlated message inside a span with a `corr` field
+
+use std::{
+	fmt,
+	num::NonZeroU64,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+static NEXT: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CorrelationId(NonZeroU64);
+
+impl CorrelationId {
+	pub fn mint() -> Self {
+		let id = NEXT.fetch_add(1, Ordering::Relaxed);
+		Self(NonZeroU64::new(id).expect("correlation ids ran out"))
+	}
+}
+
+impl fmt::Display for CorrelationId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "c{}", self.0)
+	}
+}
+
+/// A message between layers and the client frame it goes back to, if any.
+#[derive(Debug)]
+pub struct Correlated<T> {
+	pub corr: Option<CorrelationId>,
+	pub msg: T,
+}
+
+impl<T> Correlated<T> {
+	pub fn new(corr: Option<CorrelationId>, msg: T) -> Self {
+		Self { corr, msg }
+	}
+
+	/// A message no client frame led to, e.g. a page flip.
+	pub fn untagged(msg: T) -> Self {
+		Self::new(None, msg)
+	}
+}
+
+/// The `corr` field of a span, left out when there is no id.
+pub fn span_field(
+	corr: Option<CorrelationId>,
+) -> Option<tracing::field::DisplayValue<CorrelationId>> {
+	corr.map(tracing::field::display)
+}
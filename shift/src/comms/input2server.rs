@@ -1,11 +1,23 @@
 use std::sync::Arc;
 
-use tab_protocol::InputEventPayload;
+use tab_protocol::{DeviceConfig, InputEventPayload};
+
+use crate::error::Error;
 
 #[derive(Debug, Clone)]
 pub enum InputEvt {
 	Event(InputEventPayload),
-	FatalError { reason: Arc<str> },
+	ShortcutTriggered {
+		id: Arc<str>,
+	},
+	/// Answer to [`InputCmd::GetDeviceConfigs`](crate::comms::server2input::InputCmd::GetDeviceConfigs).
+	DeviceConfigs {
+		request: u64,
+		devices: Vec<DeviceConfig>,
+	},
+	FatalError {
+		error: Arc<Error>,
+	},
 }
 
 pub type InputEvtRx = tokio::sync::mpsc::Receiver<InputEvt>;
@@ -1,5 +1,8 @@
 pub mod client2server;
+pub mod correlation;
 pub mod input2server;
+pub mod queues;
 pub mod render2server;
 pub mod server2client;
+pub mod server2input;
 pub mod server2render;
@@ -0,0 +1,117 @@
+//! Sizes and overflow of the queues between shift's layers.
+//! - capacities come from `SHIFT_CLIENT_QUEUE`, `SHIFT_RENDER_QUEUE` and `SHIFT_INPUT_QUEUE`, in
+//!   messages
+//! - most messages wait for room when a queue is full: they hand buffers back, answer requests or
+//!   change state, and losing one would leave a peer stuck. Waiting is logged and counted.
+//! - status a client only needs the latest of, like `compositor_health`, goes through a
+//!   [`LossyQueue`] that drops the oldest entry instead
+//! - depths, drops and waits are reported in `stats`
+
+use std::{
+	collections::VecDeque,
+	sync::{
+		Mutex,
+		atomic::{AtomicU64, Ordering},
+	},
+};
+
+use tokio::sync::Notify;
+
+/// What happens to a message sent to a full queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+	/// The sender waits for room.
+	Block,
+	/// The oldest queued message is dropped to make room.
+	DropOldest,
+}
+
+/// Capacity set in `var`, or `default` if unset or invalid.
+pub fn capacity_from_env(var: &str, default: usize) -> usize {
+	match std::env::var(var) {
+		Ok(raw) => match raw.trim().parse::<usize>() {
+			Ok(capacity) if capacity > 0 => capacity,
+			_ => {
+				tracing::warn!(value = %raw, "invalid {var}, using {default}");
+				default
+			}
+		},
+		Err(_) => default,
+	}
+}
+
+/// Depth of a tokio channel, from its sender.
+pub fn depth<T>(sender: &tokio::sync::mpsc::Sender<T>) -> usize {
+	sender.max_capacity() - sender.capacity()
+}
+
+/// A bounded single-consumer queue that drops its oldest entry instead of waiting for room.
+#[derive(Debug)]
+pub struct LossyQueue<T> {
+	entries: Mutex<VecDeque<T>>,
+	capacity: usize,
+	dropped: AtomicU64,
+	notify: Notify,
+}
+
+impl<T> LossyQueue<T> {
+	pub fn new(capacity: usize) -> Self {
+		Self {
+			entries: Mutex::new(VecDeque::new()),
+			capacity: capacity.max(1),
+			dropped: AtomicU64::new(0),
+			notify: Notify::new(),
+		}
+	}
+
+	/// Queues `entry`, returning whether the oldest one was dropped for it.
+	pub fn push(&self, entry: T) -> bool {
+		let mut entries = self.entries.lock().unwrap();
+		let dropped = entries.len() >= self.capacity && entries.pop_front().is_some();
+		entries.push_back(entry);
+		drop(entries);
+		if dropped {
+			self.dropped.fetch_add(1, Ordering::Relaxed);
+		}
+		self.notify.notify_one();
+		dropped
+	}
+
+	pub async fn recv(&self) -> T {
+		loop {
+			if let Some(entry) = self.entries.lock().unwrap().pop_front() {
+				return entry;
+			}
+			self.notify.notified().await;
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.lock().unwrap().len()
+	}
+
+	pub fn capacity(&self) -> usize {
+		self.capacity
+	}
+
+	/// Entries dropped since the queue was created.
+	pub fn dropped(&self) -> u64 {
+		self.dropped.load(Ordering::Relaxed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn full_lossy_queues_drop_the_oldest_entry() {
+		let queue = LossyQueue::new(2);
+		assert!(!queue.push(1));
+		assert!(!queue.push(2));
+		assert!(queue.push(3));
+		assert_eq!((queue.len(), queue.dropped()), (2, 1));
+		assert_eq!(queue.recv().await, 2);
+		assert_eq!(queue.recv().await, 3);
+	}
+}
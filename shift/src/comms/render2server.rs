@@ -1,13 +1,38 @@
 use std::os::fd::OwnedFd;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tab_protocol::BufferIndex;
+use tab_protocol::{BufferIndex, FrameDropReason, GpuMemoryStats, TransitionInfo};
 
+use super::correlation::Correlated;
 use crate::{
+	error::Error,
 	monitor::{Monitor, MonitorId},
 	sessions::SessionId,
 };
 
+/// Pixels read back from a monitor, in a sealed memfd.
+#[derive(Debug)]
+pub struct Screenshot {
+	pub monitor_id: MonitorId,
+	pub width: i32,
+	pub height: i32,
+	pub stride: i32,
+	pub fourcc: i32,
+	pub pixels: OwnedFd,
+}
+
+/// Render loop counters of the last second, see `compositor_health`.
+#[derive(Debug, Clone)]
+pub struct RenderHealth {
+	pub loops_per_sec: u32,
+	/// Acquire fences still being waited on.
+	pub fence_wait_backlog: usize,
+	pub gpu_memory: GpuMemoryStats,
+	/// Time since each known monitor last flipped, `None` if it never did.
+	pub last_flip_ages: Vec<(MonitorId, Option<Duration>)>,
+}
+
 /// Events emitted by the rendering layer back into the server core.
 #[derive(Debug)]
 pub enum RenderEvt {
@@ -20,8 +45,10 @@ pub enum RenderEvt {
 	MonitorOnline { monitor: Monitor },
 	/// The user unplugged a monitor
 	MonitorOffline { monitor_id: MonitorId },
+	/// A known monitor switched to a different mode
+	MonitorChanged { monitor: Monitor },
 	/// Rendering reported an unrecoverable condition.
-	FatalError { reason: Arc<str> },
+	FatalError { error: Error },
 	/// Some monitors just page flipped and are ready to be commited to again
 	PageFlip { monitors: Vec<MonitorId> },
 	/// Renderer has accepted and applied a buffer request to its internal state.
@@ -29,23 +56,78 @@ pub enum RenderEvt {
 		session_id: SessionId,
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		generation: u64,
 	},
 	/// Renderer switched to a newer buffer and no longer needs the previous one.
 	BufferConsumed {
 		session_id: SessionId,
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		/// Link generation the buffer was swapped in under.
+		generation: u64,
 		release_fence: Option<OwnedFd>,
 	},
+	/// A buffer was replaced before it reached the screen. Its `BufferConsumed` is sent as well.
+	FrameDropped {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		reason: FrameDropReason,
+	},
 	/// Renderer rejected a buffer request after inspecting local state.
 	BufferRequestRejected {
 		session_id: SessionId,
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		generation: u64,
 		reason: Arc<str>,
 	},
+	/// The dma-bufs of a `framebuffer_link` couldn't be imported, nothing was linked.
+	FramebufferLinkFailed {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		reason: String,
+		/// DRM fourccs the renderer can import.
+		supported_formats: Vec<i32>,
+	},
+	/// A session's imported buffers were evicted to save GPU memory; its client has to link the
+	/// swapchains of `monitor_ids` again.
+	RelinkRequested {
+		session_id: SessionId,
+		monitor_ids: Vec<MonitorId>,
+	},
+	/// The renderer rebuilt its GPU state, dropping every import of a session's buffers; its
+	/// client has to link the swapchains of `monitor_ids` again and redraw them.
+	BuffersInvalidated {
+		session_id: SessionId,
+		monitor_ids: Vec<MonitorId>,
+	},
+	/// The animations the renderer can draw changed, sent once at startup too.
+	TransitionsChanged { transitions: Vec<TransitionInfo> },
+	/// Answer to [`RenderCmd::Screenshot`](crate::comms::server2render::RenderCmd::Screenshot).
+	Screenshot {
+		request: u64,
+		result: Result<Screenshot, Arc<str>>,
+	},
+	/// The boot splash finished fading out, sessions are now what's on screen.
+	SplashEnded,
+	/// GPU state was rebuilt after `RenderCmd::Resume`.
+	Resumed,
+	/// The first frame of the active session since it became active was page flipped.
+	SessionShown { session_id: SessionId },
+	/// Periodic heartbeat, emitted about once a second from the render loop.
+	Health(RenderHealth),
+	/// A session kept submitting frames that looked the same for `duration`, see
+	/// `rendering_layer::stuck_frames`.
+	StuckFrames {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		duration: Duration,
+		frames: u32,
+	},
 }
 
-pub type RenderEvtRx = tokio::sync::mpsc::Receiver<RenderEvt>;
-pub type RenderEvtTx = tokio::sync::mpsc::Sender<RenderEvt>;
-pub type RenderEvtWeakTx = tokio::sync::mpsc::WeakSender<RenderEvt>;
+/// Events cross the channel in batches, one per render loop iteration, so a frame's acks,
+/// releases and flips wake the server once.
+pub type RenderEvtRx = tokio::sync::mpsc::Receiver<Vec<Correlated<RenderEvt>>>;
+pub type RenderEvtTx = tokio::sync::mpsc::Sender<Vec<Correlated<RenderEvt>>>;
+pub type RenderEvtWeakTx = tokio::sync::mpsc::WeakSender<Vec<Correlated<RenderEvt>>>;
@@ -1,10 +1,18 @@
 use std::os::fd::OwnedFd;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tab_protocol::{BufferIndex, InputEventPayload, SessionInfo};
+use tab_protocol::{
+	BacklightInfo, BufferIndex, CompositorHealthPayload, DeviceConfig, FocusTarget, FrameDropReason,
+	InputEventPayload, PerformanceWarningKind, SessionInfo, SharedFrame, StateSyncPayload,
+	StatsPayload, TransitionInfo,
+};
 
 use crate::{
 	auth::{self, Token},
+	client_layer::wire_tap::WireTapConfig,
+	comms::{queues::Overflow, render2server::Screenshot},
+	error::Error,
 	monitor::{Monitor, MonitorId},
 	sessions::{PendingSession, Session, SessionId},
 };
@@ -13,6 +21,8 @@ use crate::{
 pub struct BufferRelease {
 	pub monitor_id: MonitorId,
 	pub buffer: BufferIndex,
+	/// Link generation the buffer was requested under.
+	pub generation: u64,
 	pub release_fence: Option<OwnedFd>,
 }
 
@@ -22,8 +32,7 @@ pub enum S2CMsg {
 	AuthError(auth::error::Error),
 	SessionCreated(Token, PendingSession),
 	Error {
-		code: Arc<str>,
-		error: Option<Arc<str>>,
+		error: Error,
 		shutdown: bool,
 	},
 	BufferRelease {
@@ -32,12 +41,41 @@ pub enum S2CMsg {
 	BufferRequestAck {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
+		generation: u64,
+	},
+	FramebufferLinkFailed {
+		monitor_id: MonitorId,
+		reason: String,
+		supported_formats: Vec<i32>,
+	},
+	RelinkRequest {
+		monitor_id: MonitorId,
+	},
+	BuffersInvalidated {
+		monitor_ids: Vec<MonitorId>,
+	},
+	FramesDropped {
+		monitor_id: MonitorId,
+		count: u32,
+		reason: FrameDropReason,
+	},
+	PerformanceWarning {
+		monitor_id: MonitorId,
+		kind: PerformanceWarningKind,
+		duration: Duration,
+		frames: u32,
 	},
 	SessionActive {
 		session_id: SessionId,
+		epoch: u64,
+	},
+	SessionInactive {
+		session_id: SessionId,
 	},
+	SplashEnded,
 	SessionState {
 		session: SessionInfo,
+		epoch: u64,
 	},
 	SessionAwake {
 		session_id: SessionId,
@@ -45,16 +83,81 @@ pub enum S2CMsg {
 	SessionSleep {
 		session_id: SessionId,
 	},
+	SessionVisibility {
+		visible: bool,
+	},
 	InputEvent {
 		event: InputEventPayload,
 	},
-	MonitorAdded {
-		monitor: Monitor,
+	Focus {
+		session_id: SessionId,
+		target: FocusTarget,
+		focused: bool,
 	},
-	MonitorRemoved {
-		monitor_id: MonitorId,
-		name: Arc<str>,
+	PointerLock {
+		locked: bool,
 	},
+	ShortcutTriggered {
+		id: Arc<str>,
+	},
+	/// A frame encoded once for every client, sent as is.
+	Broadcast(SharedFrame),
+	LogRecords {
+		records: Vec<String>,
+		more: bool,
+	},
+	Sessions {
+		sessions: Vec<SessionInfo>,
+	},
+	Transitions {
+		transitions: Vec<TransitionInfo>,
+	},
+	Stats(StatsPayload),
+	StateSync(StateSyncPayload),
+	CompositorHealth(CompositorHealthPayload),
+	SessionUnresponsive {
+		session_id: SessionId,
+		unresponsive: bool,
+		idle: Duration,
+	},
+	/// `grace` is how long the renderer keeps the session's last frame.
+	SessionCrashed {
+		session_id: SessionId,
+		grace: Duration,
+	},
+	Screenshot(Screenshot),
+	Backlights {
+		backlights: Vec<BacklightInfo>,
+	},
+	DeviceConfigs {
+		devices: Vec<DeviceConfig>,
+	},
+	LidClosed {
+		closed: bool,
+	},
+	TabletMode {
+		enabled: bool,
+	},
+	Selection {
+		mime_type: String,
+		size: u64,
+		source: SessionId,
+		data: OwnedFd,
+	},
+	/// Turns the connection's wire tap on, or off with `None`.
+	WireTap(Option<WireTapConfig>),
+}
+
+impl S2CMsg {
+	/// What happens to the message when the client's queue is full, see [`super::queues`].
+	/// Only periodic status is dropped: everything else hands back buffers, answers a request or
+	/// changes state, and a client missing it would get stuck or out of sync.
+	pub fn overflow(&self) -> Overflow {
+		match self {
+			Self::CompositorHealth(_) => Overflow::DropOldest,
+			_ => Overflow::Block,
+		}
+	}
 }
 
 pub type S2CRx = tokio::sync::mpsc::Receiver<S2CMsg>;
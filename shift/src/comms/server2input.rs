@@ -0,0 +1,27 @@
+use std::sync::Arc;
+
+use tab_protocol::{DeviceConfigurePayload, ShortcutModifier};
+
+/// A key chord reserved by an admin session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortcut {
+	pub id: Arc<str>,
+	pub modifiers: Vec<ShortcutModifier>,
+	pub key: u32,
+}
+
+#[derive(Debug)]
+pub enum InputCmd {
+	/// Replace the shortcuts intercepted before input reaches sessions.
+	SetShortcuts(Vec<Shortcut>),
+	/// Calibrate an absolute device's coordinates, see `DeviceCalibrationPayload`.
+	SetCalibration { device: u32, matrix: [f64; 6] },
+	/// Change libinput settings of a device.
+	ConfigureDevice(DeviceConfigurePayload),
+	/// Read the settings of every device, answered with `InputEvt::DeviceConfigs`.
+	GetDeviceConfigs { request: u64 },
+}
+
+pub type InputCmdRx = tokio::sync::mpsc::Receiver<InputCmd>;
+pub type InputCmdTx = tokio::sync::mpsc::Sender<InputCmd>;
+pub type InputCmdWeakTx = tokio::sync::mpsc::WeakSender<InputCmd>;
@@ -1,8 +1,16 @@
+use std::collections::BTreeMap;
 use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
-use tab_protocol::{BufferIndex, FramebufferLinkPayload};
+use tab_protocol::{
+	BackgroundSetPayload, BufferIndex, Easing, FramebufferLinkPayload, MonitorFilter, Rect,
+	TransitionDefinePayload,
+};
+use thiserror::Error;
 
+use super::correlation::Correlated;
 use crate::{monitor::MonitorId, sessions::SessionId};
 
 #[derive(Debug, Clone)]
@@ -10,6 +18,99 @@ pub struct SessionTransition {
 	pub from_session_id: SessionId,
 	pub animation: String,
 	pub duration: Duration,
+	pub easing: Easing,
+	/// Validated against the animation's parameters; missing ones use the default.
+	pub params: BTreeMap<String, f64>,
+}
+
+/// A session drawn scaled into a sub-rectangle of a monitor.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionRegion {
+	pub session_id: SessionId,
+	pub rect: Rect,
+}
+
+/// Server-side counters shown by the debug HUD, sampled once per second.
+#[derive(Debug, Clone, Default)]
+pub struct HudStats {
+	pub active_session: Option<String>,
+	pub connected_clients: usize,
+	pub pending_buffer_requests: usize,
+	pub waiting_flip: usize,
+	pub swap_buffers_per_sec: u64,
+	pub frame_done_per_sec: u64,
+	pub frames_skipped_per_sec: u64,
+}
+
+/// What the renderer draws beneath sessions, and where no session has presented yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Background {
+	/// Opaque RGB color.
+	Color([u8; 3]),
+	/// Image file, scaled to cover each monitor.
+	Image(PathBuf),
+}
+
+#[derive(Debug, Error)]
+pub enum BackgroundParseError {
+	#[error("invalid color {0:?}, expected #rrggbb")]
+	InvalidColor(String),
+	#[error("background image path must be absolute")]
+	RelativeImagePath,
+	#[error("exactly one of color or image must be set")]
+	Ambiguous,
+}
+
+impl Background {
+	pub fn from_payload(payload: BackgroundSetPayload) -> Result<Self, BackgroundParseError> {
+		match (payload.color, payload.image) {
+			(Some(color), None) => Self::parse_color(&color),
+			(None, Some(image)) => Self::image(image),
+			_ => Err(BackgroundParseError::Ambiguous),
+		}
+	}
+
+	fn parse_color(raw: &str) -> Result<Self, BackgroundParseError> {
+		let invalid = || BackgroundParseError::InvalidColor(raw.to_string());
+		let hex = raw.strip_prefix('#').ok_or_else(invalid)?;
+		if hex.len() != 6 {
+			return Err(invalid());
+		}
+		let rgb = u32::from_str_radix(hex, 16).map_err(|_| invalid())?;
+		Ok(Self::Color([
+			(rgb >> 16) as u8,
+			(rgb >> 8) as u8,
+			rgb as u8,
+		]))
+	}
+
+	fn image(path: impl Into<PathBuf>) -> Result<Self, BackgroundParseError> {
+		let path = path.into();
+		if !path.is_absolute() {
+			return Err(BackgroundParseError::RelativeImagePath);
+		}
+		Ok(Self::Image(path))
+	}
+}
+
+impl Default for Background {
+	fn default() -> Self {
+		Self::Color([0, 0, 0])
+	}
+}
+
+/// `SHIFT_BACKGROUND` syntax: `#rrggbb` or an absolute image path.
+impl FromStr for Background {
+	type Err = BackgroundParseError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		if s.starts_with('#') {
+			Self::parse_color(s)
+		} else {
+			Self::image(s)
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -22,22 +123,105 @@ pub enum RenderCmd {
 		dma_bufs: [OwnedFd; 2],
 		session_id: SessionId,
 	},
+	/// Copy CPU-side pixels into a buffer slot, for sessions that can't share dma-bufs.
+	BufferUpload {
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		session_id: SessionId,
+		width: i32,
+		height: i32,
+		stride: i32,
+		pixels: Vec<u8>,
+	},
 	/// Update which session should be displayed globally.
 	SetActiveSession {
 		session_id: Option<SessionId>,
 		transition: Option<SessionTransition>,
 	},
-	/// Drop all GPU resources associated with a disconnected session.
-	SessionRemoved { session_id: SessionId },
+	/// Register a keyframe animation, replacing any animation of the same name.
+	DefineTransition(TransitionDefinePayload),
+	/// Set or clear the picture-in-picture overlay of a monitor.
+	SetPip {
+		monitor_id: MonitorId,
+		overlay: Option<SessionRegion>,
+	},
+	/// Split a monitor between sessions. An empty list restores full-screen output.
+	SetMonitorLayout {
+		monitor_id: MonitorId,
+		regions: Vec<SessionRegion>,
+	},
+	/// Pin a monitor to a session, or make it follow the active session again.
+	AssignMonitor {
+		monitor_id: MonitorId,
+		session_id: Option<SessionId>,
+	},
+	/// Show `source_monitor_id`'s output on `monitor_id`, or its own again with `None`.
+	SetMirror {
+		monitor_id: MonitorId,
+		source_monitor_id: Option<MonitorId>,
+	},
+	/// Change a monitor's color filter, which the renderer also records for later runs.
+	SetMonitorFilter {
+		monitor_id: MonitorId,
+		filter: MonitorFilter,
+	},
+	/// Ease a monitor's magnifier to `factor` around `center`, see `rendering_layer::zoom`.
+	SetZoom {
+		monitor_id: MonitorId,
+		factor: f32,
+		center: [f32; 2],
+	},
+	/// Move the magnifier's center right away, as the pointer it follows moves.
+	ZoomFocus {
+		monitor_id: MonitorId,
+		center: [f32; 2],
+	},
+	/// Replace what is drawn beneath sessions.
+	SetBackground(Background),
+	/// Darken a session's image by `amount` (0..1), or stop with `None`.
+	DimSession {
+		session_id: SessionId,
+		amount: Option<f32>,
+	},
+	/// Show or hide the on-screen debug HUD.
+	SetDebugHud { enabled: bool },
+	/// Latest server counters for the debug HUD.
+	HudStats(HudStats),
+	/// Read back what the next frame on a monitor shows, answered with `RenderEvt::Screenshot`.
+	Screenshot { request: u64, monitor_id: MonitorId },
+	/// The system is about to suspend: stop drawing until `Resume`.
+	Suspend,
+	/// The system woke up: take back DRM master, rebuild GPU state and have sessions relink their
+	/// buffers, then answer with `RenderEvt::Resumed`.
+	Resume,
+	/// Drop all GPU resources associated with a disconnected session. With a non-zero `grace` the
+	/// monitors showing it keep its last frame, dimmed, until the grace runs out or they show
+	/// another session.
+	SessionRemoved {
+		session_id: SessionId,
+		grace: Duration,
+	},
 	/// Present a framebuffer on a given monitor.
 	SwapBuffers {
 		monitor_id: MonitorId,
 		buffer: BufferIndex,
 		session_id: SessionId,
+		/// Generation of the link the buffer belongs to; stale ones are rejected.
+		generation: u64,
 		acquire_fence: Option<OwnedFd>,
+		/// Hold the buffer until every monitor of the session's group has one, then flip them
+		/// together.
+		present_group: Option<u32>,
+	},
+	/// Define which monitors a session's present group spans, or remove it when `monitors` is
+	/// empty. Requests the group held are rejected.
+	PresentGroup {
+		session_id: SessionId,
+		group: u32,
+		monitors: Vec<MonitorId>,
 	},
 }
 
-pub type RenderCmdRx = tokio::sync::mpsc::Receiver<RenderCmd>;
-pub type RenderCmdTx = tokio::sync::mpsc::Sender<RenderCmd>;
-pub type RenderCmdWeakTx = tokio::sync::mpsc::WeakSender<RenderCmd>;
+pub type RenderCmdRx = tokio::sync::mpsc::Receiver<Correlated<RenderCmd>>;
+pub type RenderCmdTx = tokio::sync::mpsc::Sender<Correlated<RenderCmd>>;
+pub type RenderCmdWeakTx = tokio::sync::mpsc::WeakSender<Correlated<RenderCmd>>;
@@ -0,0 +1,227 @@
+//! Crash reports.
+//! - layers keep a cheap snapshot of their state here while running
+//! - the panic hook writes that snapshot, a backtrace and recent log records to the runtime dir
+//! - connected clients get a last `server_shutdown` frame so they don't wait on a dead socket
+
+use std::{
+	backtrace::Backtrace,
+	collections::VecDeque,
+	fmt::Write as _,
+	fs::{DirBuilder, File},
+	io::{self, Write as _},
+	os::{
+		fd::{BorrowedFd, RawFd},
+		unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt},
+	},
+	panic::PanicHookInfo,
+	path::PathBuf,
+	sync::Mutex,
+};
+
+use tab_protocol::{ServerShutdownPayload, TabMessageFrame, message_header};
+
+use crate::logging::LogHandle;
+
+/// Per-second server stats kept for the report.
+const STATS_HISTORY: usize = 60;
+/// Log records included in the report.
+const LOG_RECORDS: usize = 200;
+/// GL debug messages kept for the report.
+const GL_MESSAGES: usize = 50;
+
+/// Driver strings reported by EGL and GL when the renderer started.
+#[derive(Debug, Clone, Default)]
+pub struct GpuInfo {
+	pub egl_vendor: String,
+	pub egl_version: String,
+	pub gl_vendor: String,
+	pub gl_renderer: String,
+	pub gl_version: String,
+}
+
+struct CrashState {
+	gpu: Option<GpuInfo>,
+	monitors: Vec<String>,
+	sessions: Vec<String>,
+	stats: VecDeque<String>,
+	gl_messages: VecDeque<String>,
+	client_fds: Vec<RawFd>,
+}
+
+static STATE: Mutex<CrashState> = Mutex::new(CrashState {
+	gpu: None,
+	monitors: Vec::new(),
+	sessions: Vec::new(),
+	stats: VecDeque::new(),
+	gl_messages: VecDeque::new(),
+	client_fds: Vec::new(),
+});
+
+fn with_state(f: impl FnOnce(&mut CrashState)) {
+	if let Ok(mut state) = STATE.lock() {
+		f(&mut state);
+	}
+}
+
+pub fn set_gpu_info(gpu: GpuInfo) {
+	with_state(|state| state.gpu = Some(gpu));
+}
+
+/// Replaces the server-side snapshot: one line per monitor and per session.
+pub fn set_server_state(monitors: Vec<String>, sessions: Vec<String>, client_fds: Vec<RawFd>) {
+	with_state(|state| {
+		state.monitors = monitors;
+		state.sessions = sessions;
+		state.client_fds = client_fds;
+	});
+}
+
+pub fn push_stats(line: String) {
+	with_state(|state| {
+		if state.stats.len() == STATS_HISTORY {
+			state.stats.pop_front();
+		}
+		state.stats.push_back(line);
+	});
+}
+
+/// Keeps a GL debug message, see [`crate::rendering_layer::gl_debug`].
+pub fn push_gl_message(line: String) {
+	with_state(|state| {
+		if state.gl_messages.len() == GL_MESSAGES {
+			state.gl_messages.pop_front();
+		}
+		state.gl_messages.push_back(line);
+	});
+}
+
+/// Chains a panic hook that writes a crash report and says goodbye to clients.
+pub fn install(logging: LogHandle) {
+	let previous = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |info| {
+		previous(info);
+		match write_report(info, &logging) {
+			Ok(path) => eprintln!("shift crashed, report written to {}", path.display()),
+			Err(e) => eprintln!("shift crashed, failed to write crash report: {e}"),
+		}
+		notify_clients();
+	}));
+}
+
+/// The runtime dir, or without one a `shift-<uid>` directory in the temp dir that only the user
+/// running shift can enter, so nobody else can read reports or plant files where they go.
+pub(crate) fn report_dir() -> io::Result<PathBuf> {
+	if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+		return Ok(dir.into());
+	}
+	let uid = nix::unistd::getuid().as_raw();
+	let dir = std::env::temp_dir().join(format!("shift-{uid}"));
+	match DirBuilder::new().mode(0o700).create(&dir) {
+		Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+		_ => {}
+	}
+	// Someone else may have made it first, or left a symlink there.
+	let metadata = std::fs::symlink_metadata(&dir)?;
+	if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+		return Err(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			format!("{} isn't a private directory", dir.display()),
+		));
+	}
+	Ok(dir)
+}
+
+fn write_report(info: &PanicHookInfo<'_>, logging: &LogHandle) -> io::Result<PathBuf> {
+	let now = chrono::Local::now();
+	let mut report = String::new();
+	let _ = writeln!(report, "shift crash report, {}", now.to_rfc3339());
+	let _ = writeln!(
+		report,
+		"thread: {}",
+		std::thread::current().name().unwrap_or("<unnamed>")
+	);
+	let _ = writeln!(report, "{info}");
+	let _ = writeln!(report, "\n== backtrace\n{}", Backtrace::force_capture());
+
+	// The panic may have happened with the state locked, in which case it is left out.
+	match STATE.try_lock() {
+		Ok(state) => {
+			let _ = writeln!(report, "== gpu");
+			match &state.gpu {
+				Some(gpu) => {
+					let _ = writeln!(report, "egl vendor: {}", gpu.egl_vendor);
+					let _ = writeln!(report, "egl version: {}", gpu.egl_version);
+					let _ = writeln!(report, "gl vendor: {}", gpu.gl_vendor);
+					let _ = writeln!(report, "gl renderer: {}", gpu.gl_renderer);
+					let _ = writeln!(report, "gl version: {}", gpu.gl_version);
+				}
+				None => {
+					let _ = writeln!(report, "renderer not initialized");
+				}
+			}
+			write_section(&mut report, "monitors", &state.monitors);
+			write_section(&mut report, "sessions", &state.sessions);
+			write_section(&mut report, "server stats (oldest first)", &state.stats);
+			write_section(
+				&mut report,
+				"gl debug messages (oldest first)",
+				&state.gl_messages,
+			);
+		}
+		Err(_) => {
+			let _ = writeln!(report, "== server state unavailable");
+		}
+	}
+
+	let _ = writeln!(report, "\n== recent log records");
+	match logging.try_dump(Some(LOG_RECORDS)) {
+		Some(records) => {
+			for record in records {
+				let _ = writeln!(report, "{record}");
+			}
+		}
+		None => {
+			let _ = writeln!(report, "log buffer unavailable");
+		}
+	}
+
+	let path = report_dir()?.join(format!("shift-crash-{}.log", now.format("%Y%m%d-%H%M%S")));
+	File::options()
+		.write(true)
+		.create_new(true)
+		.mode(0o600)
+		.custom_flags(libc::O_NOFOLLOW)
+		.open(&path)?
+		.write_all(report.as_bytes())?;
+	Ok(path)
+}
+
+fn write_section<'a>(
+	report: &mut String,
+	title: &str,
+	lines: impl IntoIterator<Item = &'a String>,
+) {
+	let _ = writeln!(report, "\n== {title}");
+	for line in lines {
+		let _ = writeln!(report, "{line}");
+	}
+}
+
+fn notify_clients() {
+	let Ok(state) = STATE.try_lock() else {
+		return;
+	};
+	let frame = TabMessageFrame::json(
+		message_header::SERVER_SHUTDOWN,
+		ServerShutdownPayload {
+			reason: "crash".into(),
+		},
+	);
+	for fd in &state.client_fds {
+		// SAFETY: the borrow only lives for this write. Clients leave the crash state when they
+		// disconnect, and the process is going down anyway.
+		let fd = unsafe { BorrowedFd::borrow_raw(*fd) };
+		// Best effort: the sockets are non-blocking and the process is going down anyway.
+		let _ = frame.encode_and_send(&fd);
+	}
+}
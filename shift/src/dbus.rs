@@ -0,0 +1,395 @@
+//! DBus control interface, for desktop components that speak DBus rather than Tab, like power
+//! or display settings. Only built with the `dbus` feature, and started with
+//! `SHIFT_DBUS=session` or `SHIFT_DBUS=system`.
+//! - owns `org.hyprside.Shift` and serves `org.hyprside.Shift1` at `/org/hyprside/Shift`
+//! - an admin client living in shift: method calls become [`C2SMsg`]s and the [`S2CMsg`]s coming
+//!   back become replies and signals, so every request goes through the same checks as Tab
+//! - it never presents, so it is never the active session
+//! - `PrepareForSleep` does what the `SIGUSR1`/`SIGUSR2` hook of `server_layer::suspend` does
+
+use std::{
+	collections::{BTreeMap, VecDeque},
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use serde::Serialize;
+use tab_protocol::{
+	BacklightInfo, BacklightSetPayload, MonitorInfo, SessionInfo, SessionSwitchPayload, TabMessage,
+	TabMessageFrame,
+};
+use tokio::{
+	sync::{mpsc, oneshot},
+	task::JoinHandle,
+};
+use zbus::{fdo, interface, object_server::SignalEmitter};
+
+use crate::{
+	auth::Token,
+	client_layer::client_view::ChannelsClientEnd,
+	comms::{
+		client2server::{AuthRequest, C2SMsg, C2STx},
+		correlation::Correlated,
+		server2client::S2CMsg,
+	},
+	monitor::Monitor,
+	server_layer::visibility_hooks::VisibilityChange,
+};
+
+const NAME: &str = "org.hyprside.Shift";
+const PATH: &str = "/org/hyprside/Shift";
+/// How long a method call waits for the server's answer.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bus {
+	Session,
+	System,
+}
+
+impl Bus {
+	/// `SHIFT_DBUS`, `None` when the service is off.
+	pub fn from_env() -> Option<Self> {
+		let raw = std::env::var("SHIFT_DBUS").ok()?;
+		match raw.trim() {
+			"" | "0" | "off" => None,
+			"session" => Some(Self::Session),
+			"system" => Some(Self::System),
+			_ => {
+				tracing::warn!(value = %raw, "invalid SHIFT_DBUS, expected session or system");
+				None
+			}
+		}
+	}
+}
+
+/// A method call waiting for the server's answer.
+enum Query {
+	Sessions(oneshot::Sender<Vec<SessionInfo>>),
+	Backlights(oneshot::Sender<Vec<BacklightInfo>>),
+}
+
+/// Authenticates with `token`, an admin session's, and serves the bus until the server drops
+/// the client. `visibility` is the service's visibility hook.
+pub fn spawn(
+	bus: Bus,
+	token: Token,
+	channels: ChannelsClientEnd,
+	monitors: Vec<Monitor>,
+	visibility: mpsc::UnboundedReceiver<VisibilityChange>,
+) -> JoinHandle<()> {
+	tokio::spawn(async move {
+		if let Err(e) = run(bus, token, channels, monitors, visibility).await {
+			tracing::error!(?bus, "dbus service stopped: {e}");
+		}
+	})
+}
+
+async fn run(
+	bus: Bus,
+	token: Token,
+	mut channels: ChannelsClientEnd,
+	monitors: Vec<Monitor>,
+	mut visibility: mpsc::UnboundedReceiver<VisibilityChange>,
+) -> zbus::Result<()> {
+	let to_server = channels.to_server().clone();
+	for msg in [
+		C2SMsg::Auth(AuthRequest::Token(token)),
+		C2SMsg::SwitchEventsSubscribe { enabled: true },
+	] {
+		if to_server.send(Correlated::untagged(msg)).await.is_err() {
+			return Ok(());
+		}
+	}
+	let monitors = Arc::new(Mutex::new(
+		monitors
+			.iter()
+			.map(|monitor| (monitor.id.to_string(), monitor.to_protocol_info()))
+			.collect::<BTreeMap<_, _>>(),
+	));
+	let (query_tx, mut query_rx) = mpsc::channel(16);
+	let service = ShiftService {
+		to_server: to_server.clone(),
+		queries: query_tx,
+		monitors: Arc::clone(&monitors),
+	};
+	let builder = match bus {
+		Bus::Session => zbus::connection::Builder::session()?,
+		Bus::System => zbus::connection::Builder::system()?,
+	};
+	let connection = builder.name(NAME)?.serve_at(PATH, service)?.build().await?;
+	let service = connection
+		.object_server()
+		.interface::<_, ShiftService>(PATH)
+		.await?;
+	let emitter = service.signal_emitter();
+	tracing::info!(?bus, name = NAME, "dbus service ready");
+
+	let mut sessions = VecDeque::new();
+	let mut backlights = VecDeque::new();
+	loop {
+		tokio::select! {
+			query = query_rx.recv() => {
+				// The interface holds the sender for as long as the connection lives.
+				let Some(query) = query else { return Ok(()) };
+				let msg = match query {
+					Query::Sessions(reply) => {
+						sessions.push_back(reply);
+						C2SMsg::SessionList
+					}
+					Query::Backlights(reply) => {
+						backlights.push_back(reply);
+						C2SMsg::BacklightGet
+					}
+				};
+				if to_server.send(Correlated::untagged(msg)).await.is_err() {
+					return Ok(());
+				}
+			}
+			change = visibility.recv() => {
+				// The server holds the sender for as long as it runs.
+				let Some(change) = change else { return Ok(()) };
+				ShiftService::session_visibility_changed(
+					emitter,
+					&change.session_id.to_string(),
+					change.visible,
+				)
+				.await?;
+			}
+			msg = channels.from_server().recv() => {
+				let Some(msg) = msg else { return Ok(()) };
+				match msg {
+					S2CMsg::AuthError(e) => {
+						tracing::error!("dbus service failed to authenticate: {e}");
+						return Ok(());
+					}
+					S2CMsg::Error { error, shutdown } => {
+						tracing::warn!(kind = ?error.kind(), "dbus request failed: {error}");
+						if shutdown {
+							return Ok(());
+						}
+					}
+					S2CMsg::Sessions { sessions: list } => {
+						if let Some(reply) = sessions.pop_front() {
+							reply.send(list).ok();
+						}
+					}
+					S2CMsg::Backlights { backlights: list } => {
+						if let Some(reply) = backlights.pop_front() {
+							reply.send(list).ok();
+						}
+					}
+					S2CMsg::Broadcast(frame) => {
+						handle_broadcast(&monitors, emitter, frame.bytes(false)).await?;
+					}
+					S2CMsg::SessionActive { session_id, .. } => {
+						ShiftService::session_active(emitter, &session_id.to_string()).await?;
+					}
+					S2CMsg::SessionState { session, .. } => {
+						ShiftService::session_state_changed(emitter, &session.id, &wire_name(session.state))
+							.await?;
+					}
+					S2CMsg::LidClosed { closed } => ShiftService::lid_closed(emitter, closed).await?,
+					S2CMsg::TabletMode { enabled } => ShiftService::tablet_mode(emitter, enabled).await?,
+					_ => {}
+				}
+			}
+		}
+	}
+}
+
+/// Follows the monitor broadcasts, which come already encoded for Tab clients.
+async fn handle_broadcast(
+	monitors: &Mutex<BTreeMap<String, MonitorInfo>>,
+	emitter: &SignalEmitter<'_>,
+	bytes: &[u8],
+) -> zbus::Result<()> {
+	let message = match TabMessageFrame::parse_from_bytes(bytes) {
+		Ok(Some((frame, _))) => TabMessage::parse_message_frame(frame),
+		Ok(None) => return Ok(()),
+		Err(e) => Err(e),
+	};
+	let message = match message {
+		Ok(message) => message,
+		Err(e) => {
+			tracing::warn!("dbus service failed to parse a broadcast: {e}");
+			return Ok(());
+		}
+	};
+	match message {
+		TabMessage::MonitorAdded(payload) => {
+			let id = payload.monitor.id.clone();
+			monitors.lock().unwrap().insert(id.clone(), payload.monitor);
+			ShiftService::monitor_added(emitter, &id).await
+		}
+		TabMessage::MonitorChanged(payload) => {
+			let id = payload.monitor.id.clone();
+			monitors.lock().unwrap().insert(id.clone(), payload.monitor);
+			ShiftService::monitor_changed(emitter, &id).await
+		}
+		TabMessage::MonitorRemoved(payload) => {
+			monitors.lock().unwrap().remove(&payload.monitor_id);
+			ShiftService::monitor_removed(emitter, &payload.monitor_id).await
+		}
+		_ => Ok(()),
+	}
+}
+
+/// The name a protocol enum has on the wire, e.g. `occupied`.
+fn wire_name(value: impl Serialize) -> String {
+	match serde_json::to_value(value) {
+		Ok(serde_json::Value::String(name)) => name,
+		_ => String::new(),
+	}
+}
+
+struct ShiftService {
+	to_server: C2STx,
+	queries: mpsc::Sender<Query>,
+	monitors: Arc<Mutex<BTreeMap<String, MonitorInfo>>>,
+}
+
+impl ShiftService {
+	async fn send(&self, msg: C2SMsg) -> fdo::Result<()> {
+		self
+			.to_server
+			.send(Correlated::untagged(msg))
+			.await
+			.map_err(|_| fdo::Error::Failed("shift is shutting down".into()))
+	}
+
+	async fn query<T>(&self, query: impl FnOnce(oneshot::Sender<T>) -> Query) -> fdo::Result<T> {
+		let (reply_tx, reply_rx) = oneshot::channel();
+		self
+			.queries
+			.send(query(reply_tx))
+			.await
+			.map_err(|_| fdo::Error::Failed("shift is shutting down".into()))?;
+		match tokio::time::timeout(REPLY_TIMEOUT, reply_rx).await {
+			Ok(Ok(reply)) => Ok(reply),
+			_ => Err(fdo::Error::Failed("shift didn't answer".into())),
+		}
+	}
+}
+
+#[interface(name = "org.hyprside.Shift1")]
+impl ShiftService {
+	/// Connected monitors as (id, name, width, height, refresh rate in Hz).
+	async fn list_monitors(&self) -> Vec<(String, String, i32, i32, i32)> {
+		self
+			.monitors
+			.lock()
+			.unwrap()
+			.values()
+			.map(|monitor| {
+				(
+					monitor.id.clone(),
+					monitor.name.clone(),
+					monitor.width,
+					monitor.height,
+					monitor.refresh_rate,
+				)
+			})
+			.collect()
+	}
+
+	/// Sessions as (id, role, display name, state), like `session_list`.
+	async fn list_sessions(&self) -> fdo::Result<Vec<(String, String, String, String)>> {
+		let sessions = self.query(Query::Sessions).await?;
+		Ok(
+			sessions
+				.into_iter()
+				.map(|session| {
+					(
+						session.id,
+						wire_name(session.role),
+						session.display_name.unwrap_or_default(),
+						wire_name(session.state),
+					)
+				})
+				.collect(),
+		)
+	}
+
+	/// Makes `session_id` active. An empty `animation` switches without one.
+	async fn switch_session(
+		&self,
+		session_id: String,
+		animation: String,
+		duration_ms: u32,
+	) -> fdo::Result<()> {
+		let animation = Some(animation).filter(|animation| !animation.is_empty());
+		let payload = SessionSwitchPayload::new(
+			session_id,
+			animation,
+			Duration::from_millis(duration_ms.into()),
+		);
+		self.send(C2SMsg::SwitchSession(payload)).await
+	}
+
+	/// Backlights as (name, monitor id or empty, level from 0 to 1).
+	async fn list_backlights(&self) -> fdo::Result<Vec<(String, String, f64)>> {
+		let backlights = self.query(Query::Backlights).await?;
+		Ok(
+			backlights
+				.into_iter()
+				.map(|backlight| {
+					(
+						backlight.name,
+						backlight.monitor_id.unwrap_or_default(),
+						backlight.level,
+					)
+				})
+				.collect(),
+		)
+	}
+
+	/// Sets the backlight of `monitor_id`, `level` from 0 to 1.
+	async fn set_brightness(&self, monitor_id: String, level: f64) -> fdo::Result<()> {
+		self
+			.send(C2SMsg::BacklightSet(BacklightSetPayload {
+				monitor_id,
+				level,
+			}))
+			.await
+	}
+
+	/// Like logind's signal of the same name: `true` before the system sleeps, `false` once it
+	/// woke up.
+	async fn prepare_for_sleep(&self, start: bool) -> fdo::Result<()> {
+		self.send(C2SMsg::PrepareForSleep { start }).await
+	}
+
+	#[zbus(signal)]
+	async fn monitor_added(emitter: &SignalEmitter<'_>, monitor_id: &str) -> zbus::Result<()>;
+
+	#[zbus(signal)]
+	async fn monitor_changed(emitter: &SignalEmitter<'_>, monitor_id: &str) -> zbus::Result<()>;
+
+	#[zbus(signal)]
+	async fn monitor_removed(emitter: &SignalEmitter<'_>, monitor_id: &str) -> zbus::Result<()>;
+
+	#[zbus(signal)]
+	async fn session_active(emitter: &SignalEmitter<'_>, session_id: &str) -> zbus::Result<()>;
+
+	#[zbus(signal)]
+	async fn session_state_changed(
+		emitter: &SignalEmitter<'_>,
+		session_id: &str,
+		state: &str,
+	) -> zbus::Result<()>;
+
+	/// The session went on or off screen, see `session_visibility`.
+	#[zbus(signal)]
+	async fn session_visibility_changed(
+		emitter: &SignalEmitter<'_>,
+		session_id: &str,
+		visible: bool,
+	) -> zbus::Result<()>;
+
+	#[zbus(signal)]
+	async fn lid_closed(emitter: &SignalEmitter<'_>, closed: bool) -> zbus::Result<()>;
+
+	#[zbus(signal)]
+	async fn tablet_mode(emitter: &SignalEmitter<'_>, enabled: bool) -> zbus::Result<()>;
+}
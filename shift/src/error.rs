@@ -0,0 +1,221 @@
+//! Errors shared by the server, client and rendering layers.
+//! - every layer error converts into [`Error`] with `?` / `From`
+//! - [`Error::kind`] groups them for logging and fatal error handling
+//! - [`Error::code`] is the `error` code clients see on the wire, and must stay stable
+
+use std::{io, sync::Arc};
+
+use tab_protocol::ProtocolError;
+use thiserror::Error;
+use tokio::sync::mpsc::error::SendError;
+
+use crate::{
+	auth,
+	comms::{
+		correlation::Correlated,
+		server2render::{BackgroundParseError, RenderCmd},
+	},
+	input_layer::InputError,
+	logging::LogError,
+	monitor::MonitorIdParseError,
+	rendering_layer::{RenderError, dmabuf_import::DmaBufImportError},
+	sessions::{SessionIdParseError, launch::LaunchError},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+	/// The peer sent something malformed or out of place.
+	Protocol,
+	/// The renderer, EGL/GL or DRM failed, or went away.
+	Gpu,
+	/// The request is well formed but not allowed in the current session state.
+	Session,
+	/// Sockets, files and devices.
+	Io,
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+	#[error("{0}")]
+	Protocol(#[from] ProtocolError),
+	#[error("{0}")]
+	UnknownMessage(String),
+	#[error("monitor id parse error: {0}")]
+	MonitorIdParse(#[from] MonitorIdParseError),
+	#[error("monitor does not exist")]
+	UnknownMonitor,
+	#[error("{0}")]
+	SessionIdParse(#[from] SessionIdParseError),
+	#[error("{0}")]
+	SessionIdMismatch(&'static str),
+	#[error("rect must have a positive size")]
+	InvalidRect,
+	#[error("{0}")]
+	InvalidShortcut(&'static str),
+	#[error("shortcut id or key chord is already registered")]
+	ShortcutConflict,
+	#[error("{0}")]
+	InvalidBufferUpload(String),
+	#[error("{0}")]
+	InvalidLogLevel(#[from] LogError),
+	#[error("{0}")]
+	InvalidBackground(#[from] BackgroundParseError),
+	#[error("{0}")]
+	InvalidLaunch(#[from] LaunchError),
+	#[error("{0}")]
+	Unsupported(&'static str),
+	#[error("{0}")]
+	InvalidState(String),
+	#[error("{}", .0.as_deref().unwrap_or("forbidden"))]
+	Forbidden(Option<String>),
+	#[error("{0}")]
+	Auth(#[from] auth::error::Error),
+	#[error("{0}")]
+	UnknownSession(&'static str),
+	#[error("{0}")]
+	SessionLoading(&'static str),
+	#[error("session is not awake")]
+	SessionSleeping,
+	#[error("{0}")]
+	InvalidTransition(&'static str),
+	#[error("{0}")]
+	InvalidAnimation(String),
+	#[error("{0}")]
+	NotFocused(&'static str),
+	#[error("{0}")]
+	OwnershipViolation(&'static str),
+	#[error("monitor already has an in-flight buffer request")]
+	BufferRequestInflight,
+	#[error("{0}")]
+	BufferRequestRejected(Arc<str>),
+	#[error("renderer unavailable")]
+	RenderUnavailable,
+	#[error("{0}")]
+	ScreenshotFailed(Arc<str>),
+	#[error("{0}")]
+	InvalidSelection(String),
+	#[error("nothing was offered to paste")]
+	NoSelection,
+	#[error("{0}")]
+	InvalidBacklight(&'static str),
+	#[error("{0}")]
+	InvalidCalibration(&'static str),
+	#[error("{0}")]
+	InvalidDeviceConfig(&'static str),
+	#[error("{0}")]
+	InvalidZoom(&'static str),
+	#[error("device is grabbed by another session")]
+	DeviceGrabbed,
+	#[error("{0}")]
+	HandshakeTimeout(String),
+	#[error("{0}")]
+	Render(#[from] RenderError),
+	#[error("{0}")]
+	DmaBufImport(#[from] DmaBufImportError),
+	#[error("{0}")]
+	Input(#[from] InputError),
+	#[error("{0}")]
+	Io(#[from] io::Error),
+}
+
+impl From<SendError<Correlated<RenderCmd>>> for Error {
+	fn from(_: SendError<Correlated<RenderCmd>>) -> Self {
+		Self::RenderUnavailable
+	}
+}
+
+impl Error {
+	pub fn kind(&self) -> ErrorKind {
+		match self {
+			Self::Protocol(ProtocolError::Io(_)) => ErrorKind::Io,
+			Self::Protocol(_)
+			| Self::UnknownMessage(_)
+			| Self::MonitorIdParse(_)
+			| Self::UnknownMonitor
+			| Self::SessionIdParse(_)
+			| Self::SessionIdMismatch(_)
+			| Self::InvalidRect
+			| Self::InvalidShortcut(_)
+			| Self::InvalidBufferUpload(_)
+			| Self::InvalidLogLevel(_)
+			| Self::InvalidBackground(_)
+			| Self::InvalidLaunch(_)
+			| Self::Unsupported(_)
+			| Self::InvalidState(_)
+			| Self::InvalidSelection(_)
+			| Self::InvalidBacklight(_)
+			| Self::InvalidCalibration(_)
+			| Self::InvalidDeviceConfig(_)
+			| Self::InvalidZoom(_)
+			| Self::HandshakeTimeout(_) => ErrorKind::Protocol,
+			Self::ShortcutConflict
+			| Self::DeviceGrabbed
+			| Self::Forbidden(_)
+			| Self::Auth(_)
+			| Self::UnknownSession(_)
+			| Self::SessionLoading(_)
+			| Self::SessionSleeping
+			| Self::InvalidTransition(_)
+			| Self::InvalidAnimation(_)
+			| Self::NotFocused(_)
+			| Self::OwnershipViolation(_)
+			| Self::BufferRequestInflight
+			| Self::BufferRequestRejected(_)
+			| Self::NoSelection => ErrorKind::Session,
+			Self::RenderUnavailable
+			| Self::ScreenshotFailed(_)
+			| Self::Render(_)
+			| Self::DmaBufImport(_) => ErrorKind::Gpu,
+			Self::Input(_) | Self::Io(_) => ErrorKind::Io,
+		}
+	}
+
+	/// Code sent to clients in the `error` message.
+	pub fn code(&self) -> &'static str {
+		match self {
+			Self::Protocol(_) => "protocol_violation",
+			Self::UnknownMessage(_) => "unknown_message",
+			Self::MonitorIdParse(_) | Self::UnknownMonitor => "unknown_monitor",
+			Self::SessionIdParse(_) | Self::SessionIdMismatch(_) => "invalid_session_id",
+			Self::InvalidRect => "invalid_rect",
+			Self::InvalidShortcut(_) => "invalid_shortcut",
+			Self::ShortcutConflict => "shortcut_conflict",
+			Self::DeviceGrabbed => "device_grabbed",
+			Self::HandshakeTimeout(_) => "handshake_timeout",
+			Self::InvalidBufferUpload(_) => "invalid_buffer_upload",
+			Self::InvalidLogLevel(_) => "invalid_log_level",
+			Self::InvalidBackground(_) => "invalid_background",
+			Self::InvalidLaunch(_) => "invalid_launch",
+			Self::Unsupported(_) => "unsupported",
+			Self::InvalidState(_) => "invalid_state",
+			Self::Forbidden(_) => "forbidden",
+			Self::Auth(_) => "auth_failed",
+			Self::UnknownSession(_) => "unknown_session",
+			Self::SessionLoading(_) => "session_loading",
+			Self::SessionSleeping => "session_sleeping",
+			Self::InvalidTransition(_) | Self::InvalidAnimation(_) => "invalid_transition",
+			Self::NotFocused(_) => "not_focused",
+			Self::OwnershipViolation(_) => "ownership_violation",
+			Self::BufferRequestInflight => "buffer_request_inflight",
+			Self::BufferRequestRejected(_) => "buffer_request_rejected",
+			Self::RenderUnavailable => "render_unavailable",
+			Self::ScreenshotFailed(_) => "screenshot_failed",
+			Self::InvalidSelection(_) => "invalid_selection",
+			Self::NoSelection => "no_selection",
+			Self::InvalidBacklight(_) => "invalid_backlight",
+			Self::InvalidCalibration(_) => "invalid_calibration",
+			Self::InvalidDeviceConfig(_) => "invalid_device_config",
+			Self::InvalidZoom(_) => "invalid_zoom",
+			Self::Render(_) | Self::DmaBufImport(_) => "gpu_error",
+			Self::Input(_) | Self::Io(_) => "io_error",
+		}
+	}
+
+	/// Human readable detail sent along with [`Self::code`], if any.
+	pub fn message(&self) -> Option<String> {
+		match self {
+			Self::Forbidden(None) => None,
+			_ => Some(self.to_string()),
+		}
+	}
+}
@@ -0,0 +1,94 @@
+//! Faults injected on purpose, to exercise recovery paths. Only built with the `fault-injection`
+//! feature, never enable it in production.
+//! - `SHIFT_FAULT_FENCE_DELAY_MS` delays handling every signalled acquire fence, like a slow GPU
+//! - `SHIFT_FAULT_REJECT_RATE` (0..1) rejects that share of `buffer_request`s with
+//!   `injected_fault`
+//! - `SHIFT_FAULT_DROP_RELEASE_RATE` (0..1) loses that share of `buffer_release`s (frame done).
+//!   The buffer stays with shift until the session links its swapchain again
+//! - `SHIFT_FAULT_SEED` makes the random picks repeat across runs
+
+use std::time::Duration;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+#[derive(Debug)]
+pub struct FaultInjection {
+	fence_delay: Option<Duration>,
+	reject_rate: f64,
+	drop_release_rate: f64,
+	rng: StdRng,
+}
+
+impl Default for FaultInjection {
+	/// Injects nothing.
+	fn default() -> Self {
+		Self::new(None, 0.0, 0.0, rand::random())
+	}
+}
+
+impl FaultInjection {
+	pub fn from_env() -> Self {
+		let fence_delay = env_parse::<u64>("SHIFT_FAULT_FENCE_DELAY_MS")
+			.filter(|ms| *ms > 0)
+			.map(Duration::from_millis);
+		let seed = env_parse("SHIFT_FAULT_SEED").unwrap_or_else(rand::random);
+		Self::new(
+			fence_delay,
+			env_rate("SHIFT_FAULT_REJECT_RATE"),
+			env_rate("SHIFT_FAULT_DROP_RELEASE_RATE"),
+			seed,
+		)
+	}
+
+	pub fn new(
+		fence_delay: Option<Duration>,
+		reject_rate: f64,
+		drop_release_rate: f64,
+		seed: u64,
+	) -> Self {
+		Self {
+			fence_delay,
+			reject_rate: reject_rate.clamp(0.0, 1.0),
+			drop_release_rate: drop_release_rate.clamp(0.0, 1.0),
+			rng: StdRng::seed_from_u64(seed),
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		self.fence_delay.is_some() || self.reject_rate > 0.0 || self.drop_release_rate > 0.0
+	}
+
+	/// How long to sit on a signalled acquire fence before acting on it.
+	pub fn fence_delay(&self) -> Option<Duration> {
+		self.fence_delay
+	}
+
+	/// Whether to reject the `buffer_request` at hand.
+	pub fn reject_buffer_request(&mut self) -> bool {
+		self.roll(self.reject_rate)
+	}
+
+	/// Whether to lose the `buffer_release` at hand.
+	pub fn drop_release(&mut self) -> bool {
+		self.roll(self.drop_release_rate)
+	}
+
+	fn roll(&mut self, rate: f64) -> bool {
+		rate > 0.0 && self.rng.random_bool(rate)
+	}
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+	let raw = std::env::var(name).ok()?;
+	let parsed = raw.trim().parse().ok();
+	if parsed.is_none() {
+		tracing::warn!(value = %raw, "invalid {name}");
+	}
+	parsed
+}
+
+fn env_rate(name: &str) -> f64 {
+	env_parse::<f64>(name)
+		.filter(|rate| (0.0..=1.0).contains(rate))
+		.unwrap_or(0.0)
+}
@@ -1,3 +1,127 @@
+//! Ids of sessions, clients and monitors: a per-type prefix followed by hex, e.g. `se_1f3a…`.
+//! - `SHIFT_ID_SCHEME=random` (the default) mints 64 random bits, like shift always did
+//! - `SHIFT_ID_SCHEME=uuid7` mints UUIDv7s, which sort by creation time and stay unique across
+//!   restarts and between servers sharing logs or admin tools
+//!
+//! Registries mint through [`Id::unused`] or check with [`Id::or_unused`], so an id colliding
+//! with a live one is replaced rather than silently shadowing it. Logs use [`Id::short`].
+
+use std::{
+	fmt,
+	sync::OnceLock,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How new ids are minted, see the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdScheme {
+	#[default]
+	Random,
+	Uuid7,
+}
+
+impl IdScheme {
+	fn from_env() -> Self {
+		match std::env::var("SHIFT_ID_SCHEME").as_deref() {
+			Ok("uuid7") => Self::Uuid7,
+			Ok("random") | Err(_) => Self::Random,
+			Ok(other) => {
+				tracing::warn!(value = other, "unknown SHIFT_ID_SCHEME, using random ids");
+				Self::Random
+			}
+		}
+	}
+
+	/// The scheme of this process, read once from `SHIFT_ID_SCHEME`.
+	pub fn current() -> Self {
+		static SCHEME: OnceLock<IdScheme> = OnceLock::new();
+		*SCHEME.get_or_init(Self::from_env)
+	}
+
+	pub fn mint(self) -> u128 {
+		match self {
+			Self::Random => u128::from(rand::random::<u64>()),
+			Self::Uuid7 => {
+				let ms = SystemTime::now()
+					.duration_since(UNIX_EPOCH)
+					.map_or(0, |since| since.as_millis() as u64);
+				uuid7(ms, rand::random())
+			}
+		}
+	}
+}
+
+/// A UUIDv7 (RFC 9562): 48 bits of unix milliseconds, then the version, 12 random bits, the
+/// variant and 62 more random bits.
+fn uuid7(unix_ms: u64, random: u128) -> u128 {
+	const MS_MASK: u128 = (1 << 48) - 1;
+	const RAND_A: u128 = 0xfff << 64;
+	const RAND_B: u128 = (1 << 62) - 1;
+	((u128::from(unix_ms) & MS_MASK) << 80)
+		| (0x7 << 76)
+		| (random & RAND_A)
+		| (0b10 << 62)
+		| (random & RAND_B)
+}
+
+/// Formats a raw id after its prefix. UUIDs keep all 32 digits so they sort like they were minted.
+pub fn fmt_raw(raw: u128, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+	if raw > u128::from(u64::MAX) {
+		write!(f, "{raw:032x}")
+	} else {
+		write!(f, "{raw:x}")
+	}
+}
+
+/// The prefix and last 8 hex digits of an id, enough to tell ids apart in a log.
+#[derive(Debug, Clone, Copy)]
+pub struct ShortId {
+	prefix: &'static str,
+	low: u32,
+}
+
+impl fmt::Display for ShortId {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}{:08x}", self.prefix, self.low)
+	}
+}
+
+/// Implemented by every [`define_id_type!`] id.
+pub trait Id: Copy + fmt::Display {
+	const PREFIX: &'static str;
+
+	fn from_raw(raw: u128) -> Self;
+
+	fn raw(self) -> u128;
+
+	/// A new id in the [`IdScheme::current`] scheme.
+	fn mint() -> Self {
+		Self::from_raw(IdScheme::current().mint())
+	}
+
+	/// A new id `taken` doesn't know yet.
+	fn unused(taken: impl Fn(Self) -> bool) -> Self {
+		Self::mint().or_unused(taken)
+	}
+
+	/// `self`, or a new id if `taken` already knows it.
+	fn or_unused(self, taken: impl Fn(Self) -> bool) -> Self {
+		let mut id = self;
+		while taken(id) {
+			tracing::warn!(%id, "id collides with one in use, minting another");
+			id = Self::mint();
+		}
+		id
+	}
+
+	fn short(self) -> ShortId {
+		ShortId {
+			prefix: Self::PREFIX,
+			low: self.raw() as u32,
+		}
+	}
+}
+
 #[macro_export]
 macro_rules! define_id_type {
 	(
@@ -7,23 +131,34 @@ macro_rules! define_id_type {
 		paste::paste! {
 
 				#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
-				pub struct [<$name Id>](u64);
+				pub struct [<$name Id>](u128);
 
 				impl [<$name Id>] {
+						/// A new id, see `Id::unused` for one that must not collide with ids in use.
 						#[inline]
 						pub fn rand() -> Self {
-								Self(rand::random::<u64>())
+								<Self as $crate::ids::Id>::mint()
 						}
+				}
+
+				impl $crate::ids::Id for [<$name Id>] {
+						const PREFIX: &'static str = $prefix;
 
 						#[inline]
-						pub fn raw(self) -> u64 {
+						fn from_raw(raw: u128) -> Self {
+								Self(raw)
+						}
+
+						#[inline]
+						fn raw(self) -> u128 {
 								self.0
 						}
 				}
 
 				impl std::fmt::Display for [<$name Id>] {
 						fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-								f.write_fmt(format_args!(concat!($prefix, "{:x}"), self.0))
+								f.write_str($prefix)?;
+								$crate::ids::fmt_raw(self.0, f)
 						}
 				}
 
@@ -50,7 +185,7 @@ macro_rules! define_id_type {
 								}
 
 								let s = &s[$prefix.len()..];
-								u64::from_str_radix(s, 16)
+								u128::from_str_radix(s, 16)
 										.map(Self)
 										.map_err(Self::Err::InvalidHex)
 						}
@@ -58,3 +193,33 @@ macro_rules! define_id_type {
 		}
 	};
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	define_id_type!(Test, "t_");
+
+	#[test]
+	fn uuid7_ids_sort_by_time_and_round_trip() {
+		let earlier = TestId(uuid7(1_700_000_000_000, u128::MAX));
+		let later = TestId(uuid7(1_700_000_000_001, 0));
+		assert_eq!(earlier.raw() >> 76 & 0xf, 0x7);
+		assert_eq!(earlier.raw() >> 62 & 0b11, 0b10);
+		assert!(earlier.to_string() < later.to_string());
+		assert_eq!(earlier.to_string().len(), "t_".len() + 32);
+		for id in [earlier, later, TestId(0x1f3a)] {
+			assert_eq!(id.to_string().parse::<TestId>().unwrap(), id);
+		}
+		assert_eq!(TestId(0x1f3a).to_string(), "t_1f3a");
+		assert_eq!(later.short().to_string().len(), "t_".len() + 8);
+	}
+
+	#[test]
+	fn colliding_ids_are_minted_again() {
+		let taken = TestId(1);
+		let id = taken.or_unused(|id| id == taken);
+		assert_ne!(id, taken);
+		assert_eq!(id.or_unused(|id| id == taken), id);
+	}
+}
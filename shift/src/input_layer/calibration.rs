@@ -0,0 +1,215 @@
+//! Calibration of absolute devices: touchscreens, tablets and absolute pointers.
+//! - a `device_calibration` matrix maps a device's coordinates, normalized to 0..1, before the
+//!   server maps them to a monitor, e.g. to flip a touchscreen mounted upside down
+//! - matrices are recorded in `SHIFT_INPUT_CALIBRATION` (default
+//!   `$XDG_STATE_HOME/shift/input-calibration.json`) by USB ids and device name, so they apply
+//!   again when the device is plugged back in or shift restarts
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	fs,
+	path::PathBuf,
+};
+
+use tab_protocol::{DeviceCalibrationPayload, InputEventPayload, TouchContact};
+
+const DEFAULT_STATE_DIR: &str = "/var/lib";
+/// Range of `x_transformed` and `y_transformed`.
+const TRANSFORMED_RANGE: f64 = 65535.0;
+
+/// Width and height in mm, which `x` and `y` of tablet and touch events are in.
+type Size = (f64, f64);
+
+#[derive(Debug)]
+struct Device {
+	key: String,
+	size: Option<Size>,
+}
+
+#[derive(Debug)]
+pub struct Calibrations {
+	path: PathBuf,
+	saved: BTreeMap<String, [f64; 6]>,
+	devices: HashMap<u32, Device>,
+}
+
+impl Calibrations {
+	/// Reads the matrices recorded by earlier runs.
+	pub fn load() -> Self {
+		let path = std::env::var_os("SHIFT_INPUT_CALIBRATION")
+			.map(PathBuf::from)
+			.unwrap_or_else(|| {
+				std::env::var_os("XDG_STATE_HOME")
+					.map_or_else(|| PathBuf::from(DEFAULT_STATE_DIR), PathBuf::from)
+					.join("shift/input-calibration.json")
+			});
+		Self::load_from(path)
+	}
+
+	fn load_from(path: PathBuf) -> Self {
+		let saved = match fs::read(&path) {
+			Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+				tracing::warn!(path = %path.display(), "ignoring malformed input calibration: {e}");
+				BTreeMap::new()
+			}),
+			Err(_) => BTreeMap::new(),
+		};
+		Self {
+			path,
+			saved,
+			devices: HashMap::new(),
+		}
+	}
+
+	/// Records a device libinput added, `key` naming it across replugs and restarts.
+	pub fn device_added(&mut self, id: u32, key: String, size: Option<Size>) {
+		self.devices.insert(id, Device { key, size });
+	}
+
+	pub fn device_removed(&mut self, id: u32) {
+		self.devices.remove(&id);
+	}
+
+	/// Calibrates `device`. The identity matrix removes its calibration.
+	pub fn set(&mut self, device: u32, matrix: [f64; 6]) {
+		let Some(Device { key, .. }) = self.devices.get(&device) else {
+			tracing::warn!(device, "ignoring calibration of an unknown input device");
+			return;
+		};
+		if matrix == DeviceCalibrationPayload::IDENTITY {
+			self.saved.remove(key);
+		} else {
+			self.saved.insert(key.clone(), matrix);
+		}
+		if let Err(e) = self.save() {
+			tracing::warn!("failed to save input calibration: {e}");
+		}
+	}
+
+	/// Applies the calibration of the event's device to its coordinates.
+	pub fn apply(&self, payload: &mut InputEventPayload) {
+		match payload {
+			InputEventPayload::PointerMotionAbsolute {
+				device,
+				x,
+				y,
+				x_transformed,
+				y_transformed,
+				..
+			} => {
+				let Some((matrix, size)) = self.calibration(*device) else {
+					return;
+				};
+				let (nx, ny) = transform(
+					matrix,
+					*x_transformed / TRANSFORMED_RANGE,
+					*y_transformed / TRANSFORMED_RANGE,
+				);
+				(*x_transformed, *y_transformed) = (nx * TRANSFORMED_RANGE, ny * TRANSFORMED_RANGE);
+				if let Some((width, height)) = size {
+					(*x, *y) = (nx * width, ny * height);
+				}
+			}
+			InputEventPayload::TouchDown {
+				device, contact, ..
+			}
+			| InputEventPayload::TouchMotion {
+				device, contact, ..
+			} => {
+				if let Some((matrix, size)) = self.calibration(*device) {
+					calibrate_contact(contact, matrix, size);
+				}
+			}
+			InputEventPayload::TabletToolAxis { device, axes, .. } => {
+				let Some((matrix, Some((width, height)))) = self.calibration(*device) else {
+					return;
+				};
+				let (nx, ny) = transform(matrix, axes.x / width, axes.y / height);
+				(axes.x, axes.y) = (nx * width, ny * height);
+			}
+			_ => {}
+		}
+	}
+
+	fn calibration(&self, device: u32) -> Option<(&[f64; 6], Option<Size>)> {
+		let device = self.devices.get(&device)?;
+		Some((self.saved.get(&device.key)?, device.size))
+	}
+
+	fn save(&self) -> std::io::Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(&self.path, serde_json::to_vec_pretty(&self.saved)?)
+	}
+}
+
+fn calibrate_contact(contact: &mut TouchContact, matrix: &[f64; 6], size: Option<Size>) {
+	let (nx, ny) = transform(
+		matrix,
+		contact.x_transformed / TRANSFORMED_RANGE,
+		contact.y_transformed / TRANSFORMED_RANGE,
+	);
+	contact.x_transformed = nx * TRANSFORMED_RANGE;
+	contact.y_transformed = ny * TRANSFORMED_RANGE;
+	if let Some((width, height)) = size {
+		(contact.x, contact.y) = (nx * width, ny * height);
+	}
+}
+
+fn transform([a, b, c, d, e, f]: &[f64; 6], x: f64, y: f64) -> (f64, f64) {
+	(a * x + b * y + c, d * x + e * y + f)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn touch_down(device: u32, x: f64, y: f64) -> InputEventPayload {
+		InputEventPayload::TouchDown {
+			device,
+			time_usec: 0,
+			contact: TouchContact {
+				id: 0,
+				x: x * 200.0,
+				y: y * 100.0,
+				x_transformed: x * TRANSFORMED_RANGE,
+				y_transformed: y * TRANSFORMED_RANGE,
+				monitor_id: None,
+			},
+		}
+	}
+
+	#[test]
+	fn calibrations_apply_to_replugged_devices_and_survive_restarts() {
+		let path =
+			std::env::temp_dir().join(format!("shift-input-calibration-{}", rand::random::<u64>()));
+		let upside_down = [-1.0, 0.0, 1.0, 0.0, -1.0, 1.0];
+		let mut calibrations = Calibrations::load_from(path.clone());
+		calibrations.set(7, upside_down);
+		calibrations.device_added(7, "04f3:2a1c Touchscreen".into(), Some((200.0, 100.0)));
+		let mut event = touch_down(7, 0.25, 0.5);
+		calibrations.apply(&mut event);
+		assert_eq!(
+			event,
+			touch_down(7, 0.25, 0.5),
+			"calibrating devices before they were added does nothing"
+		);
+
+		calibrations.set(7, upside_down);
+		calibrations.device_removed(7);
+		let mut restarted = Calibrations::load_from(path.clone());
+		restarted.device_added(3, "04f3:2a1c Touchscreen".into(), Some((200.0, 100.0)));
+		let mut event = touch_down(3, 0.25, 0.5);
+		restarted.apply(&mut event);
+		let InputEventPayload::TouchDown { contact, .. } = &event else {
+			unreachable!();
+		};
+		assert_eq!((contact.x, contact.y), (150.0, 50.0));
+		assert_eq!(contact.x_transformed, 0.75 * TRANSFORMED_RANGE);
+
+		restarted.set(3, DeviceCalibrationPayload::IDENTITY);
+		assert!(Calibrations::load_from(path.clone()).saved.is_empty());
+		fs::remove_file(path).unwrap();
+	}
+}
@@ -1,34 +1,43 @@
-use crate::comms::input2server::{InputEvtRx, InputEvtTx};
+use crate::comms::{
+	input2server::{InputEvtRx, InputEvtTx},
+	queues,
+	server2input::{InputCmdRx, InputCmdTx},
+};
 
 const DEFAULT_CHANNEL_CAPACITY: usize = 4096;
 
 #[derive(Debug)]
 pub struct ServerEnd {
 	input_events: InputEvtRx,
+	input_commands: InputCmdTx,
 }
 
 impl ServerEnd {
-	pub fn new(input_events: InputEvtRx) -> Self {
-		Self { input_events }
+	pub fn new(input_events: InputEvtRx, input_commands: InputCmdTx) -> Self {
+		Self {
+			input_events,
+			input_commands,
+		}
 	}
 
-	pub fn into_parts(self) -> InputEvtRx {
-		self.input_events
+	pub fn into_parts(self) -> (InputEvtRx, InputCmdTx) {
+		(self.input_events, self.input_commands)
 	}
 }
 
 #[derive(Debug)]
 pub struct InputEnd {
 	events: InputEvtTx,
+	commands: InputCmdRx,
 }
 
 impl InputEnd {
-	pub fn new(events: InputEvtTx) -> Self {
-		Self { events }
+	pub fn new(events: InputEvtTx, commands: InputCmdRx) -> Self {
+		Self { events, commands }
 	}
 
-	pub fn into_parts(self) -> InputEvtTx {
-		self.events
+	pub fn into_parts(self) -> (InputEvtTx, InputCmdRx) {
+		(self.events, self.commands)
 	}
 }
 
@@ -42,11 +51,20 @@ impl Channels {
 		Self::with_capacity(DEFAULT_CHANNEL_CAPACITY)
 	}
 
+	/// Capacity from `SHIFT_INPUT_QUEUE`, see [`queues`].
+	pub fn from_env() -> Self {
+		Self::with_capacity(queues::capacity_from_env(
+			"SHIFT_INPUT_QUEUE",
+			DEFAULT_CHANNEL_CAPACITY,
+		))
+	}
+
 	pub fn with_capacity(capacity: usize) -> Self {
 		let (evt_tx, evt_rx) = tokio::sync::mpsc::channel(capacity);
+		let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(capacity);
 		Self {
-			server_end: ServerEnd::new(evt_rx),
-			input_end: InputEnd::new(evt_tx),
+			server_end: ServerEnd::new(evt_rx, cmd_tx),
+			input_end: InputEnd::new(evt_tx, cmd_rx),
 		}
 	}
 
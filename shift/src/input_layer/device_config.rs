@@ -0,0 +1,114 @@
+//! libinput settings of the connected devices, changed by `device_configure` and read by
+//! `device_config_get`.
+//! - settings a device doesn't support are left out of its `DeviceSettings`, and ignored when set
+//! - they last until the device is unplugged; a replugged device starts from the defaults again
+
+use std::collections::BTreeMap;
+
+use input::{AccelProfile as LibinputAccelProfile, Device};
+use tab_protocol::{AccelProfile, DeviceConfig, DeviceConfigurePayload, DeviceSettings};
+
+use super::apply_config_result;
+
+#[derive(Default)]
+pub struct Devices {
+	devices: BTreeMap<u32, Device>,
+}
+
+impl Devices {
+	pub fn added(&mut self, id: u32, device: Device) {
+		self.devices.insert(id, device);
+	}
+
+	pub fn removed(&mut self, id: u32) {
+		self.devices.remove(&id);
+	}
+
+	pub fn configure(&mut self, payload: DeviceConfigurePayload) {
+		let Some(device) = self.devices.get_mut(&payload.device) else {
+			tracing::warn!(
+				device = payload.device,
+				"ignoring settings of an unknown input device"
+			);
+			return;
+		};
+		let name = device.name().to_string();
+		let settings = payload.settings;
+		if let Some(profile) = settings.accel_profile {
+			let profile = match profile {
+				AccelProfile::Flat => LibinputAccelProfile::Flat,
+				AccelProfile::Adaptive => LibinputAccelProfile::Adaptive,
+			};
+			apply_config_result(
+				device.config_accel_set_profile(profile),
+				&name,
+				"accel_profile",
+			);
+		}
+		if let Some(speed) = settings.accel_speed {
+			apply_config_result(device.config_accel_set_speed(speed), &name, "accel_speed");
+		}
+		if let Some(enabled) = settings.natural_scroll {
+			apply_config_result(
+				device.config_scroll_set_natural_scroll_enabled(enabled),
+				&name,
+				"natural_scroll",
+			);
+		}
+		if let Some(enabled) = settings.tap_to_click {
+			apply_config_result(
+				device.config_tap_set_enabled(enabled),
+				&name,
+				"tap_to_click",
+			);
+		}
+		if let Some(enabled) = settings.left_handed {
+			apply_config_result(device.config_left_handed_set(enabled), &name, "left_handed");
+		}
+	}
+
+	/// Current settings of every device, by id.
+	pub fn configs(&self) -> Vec<DeviceConfig> {
+		self
+			.devices
+			.iter()
+			.map(|(id, device)| DeviceConfig {
+				device: *id,
+				name: device.name().to_string(),
+				accel_profiles: device
+					.config_accel_profiles()
+					.into_iter()
+					.filter_map(map_accel_profile)
+					.collect(),
+				settings: settings(device),
+			})
+			.collect()
+	}
+}
+
+fn settings(device: &Device) -> DeviceSettings {
+	let accel = device.config_accel_is_available();
+	DeviceSettings {
+		accel_profile: accel
+			.then(|| device.config_accel_profile())
+			.flatten()
+			.and_then(map_accel_profile),
+		accel_speed: accel.then(|| device.config_accel_speed()),
+		natural_scroll: device
+			.config_scroll_has_natural_scroll()
+			.then(|| device.config_scroll_natural_scroll_enabled()),
+		tap_to_click: (device.config_tap_finger_count() > 0).then(|| device.config_tap_enabled()),
+		left_handed: device
+			.config_left_handed_is_available()
+			.then(|| device.config_left_handed()),
+	}
+}
+
+fn map_accel_profile(profile: LibinputAccelProfile) -> Option<AccelProfile> {
+	match profile {
+		LibinputAccelProfile::Flat => Some(AccelProfile::Flat),
+		LibinputAccelProfile::Adaptive => Some(AccelProfile::Adaptive),
+		#[allow(unreachable_patterns)]
+		_ => None,
+	}
+}
@@ -1,4 +1,8 @@
+mod calibration;
 pub mod channels;
+mod device_config;
+pub mod repeat;
+mod shortcuts;
 
 use std::{
 	fs::{File, OpenOptions},
@@ -9,6 +13,7 @@ use std::{
 	},
 	path::Path,
 	sync::Arc,
+	time::Instant,
 };
 
 use input::{
@@ -29,13 +34,23 @@ use input::{
 	},
 };
 use tab_protocol::{
-	AxisOrientation, AxisSource, ButtonState, InputEventPayload, KeyState, SwitchState, SwitchType,
-	TabletTool, TabletToolAxes, TabletToolCapability, TabletToolType, TipState as ProtoTipState,
-	TouchContact,
+	AxisOrientation, AxisSource, ButtonState, InputEventPayload, KeyRepeatInfo, KeyState,
+	SwitchState, SwitchType, TabletTool, TabletToolAxes, TabletToolCapability, TabletToolType,
+	TipState as ProtoTipState, TouchContact,
 };
 use thiserror::Error;
 
-use crate::comms::input2server::{InputEvt, InputEvtTx};
+use crate::comms::{
+	input2server::{InputEvt, InputEvtTx},
+	server2input::{InputCmd, InputCmdRx},
+};
+use calibration::Calibrations;
+use device_config::Devices;
+use repeat::KeyRepeater;
+use shortcuts::{ShortcutMatch, ShortcutMatcher};
+
+/// Longest a command waits while no input arrives, as commands don't wake up `poll`.
+const POLL_TIMEOUT_MS: i32 = 100;
 
 #[derive(Debug, Error)]
 pub enum InputError {
@@ -47,16 +62,18 @@ pub enum InputError {
 
 pub struct InputLayer {
 	event_tx: InputEvtTx,
+	commands: InputCmdRx,
 	seat: String,
 	tap_to_click: bool,
 	tap_drag: bool,
 	tap_drag_lock: bool,
 	tap_button_map: TapButtonMap,
+	key_repeat: KeyRepeatInfo,
 }
 
 impl InputLayer {
 	pub fn init(channels: channels::InputEnd) -> Self {
-		let event_tx = channels.into_parts();
+		let (event_tx, commands) = channels.into_parts();
 		let seat = std::env::var("SHIFT_INPUT_SEAT").unwrap_or_else(|_| "seat0".to_string());
 		let tap_to_click = env_bool("SHIFT_INPUT_TAP_TO_CLICK", true);
 		let tap_drag = env_bool("SHIFT_INPUT_TAP_DRAG", true);
@@ -71,24 +88,28 @@ impl InputLayer {
 		};
 		Self {
 			event_tx,
+			commands,
 			seat,
 			tap_to_click,
 			tap_drag,
 			tap_drag_lock,
 			tap_button_map,
+			key_repeat: repeat::settings_from_env(),
 		}
 	}
 
 	pub async fn run(self) -> Result<(), InputError> {
 		let seat = self.seat.clone();
 		let tx = self.event_tx;
+		let commands = self.commands;
 		let input_config = InputConfig {
 			tap_to_click: self.tap_to_click,
 			tap_drag: self.tap_drag,
 			tap_drag_lock: self.tap_drag_lock,
 			tap_button_map: self.tap_button_map,
+			key_repeat: self.key_repeat,
 		};
-		tokio::task::spawn_blocking(move || run_blocking(tx, seat, input_config))
+		tokio::task::spawn_blocking(move || run_blocking(tx, commands, seat, input_config))
 			.await
 			.map_err(|e| io::Error::other(format!("input task join error: {e}")))?
 	}
@@ -100,6 +121,7 @@ struct InputConfig {
 	tap_drag: bool,
 	tap_drag_lock: bool,
 	tap_button_map: TapButtonMap,
+	key_repeat: KeyRepeatInfo,
 }
 
 fn env_bool(name: &str, default: bool) -> bool {
@@ -112,8 +134,19 @@ fn env_bool(name: &str, default: bool) -> bool {
 	}
 }
 
+fn env_u32(name: &str, default: u32) -> u32 {
+	match std::env::var(name) {
+		Ok(raw) => raw.trim().parse().unwrap_or_else(|_| {
+			tracing::warn!(value = %raw, "invalid {name}, using {default}");
+			default
+		}),
+		Err(_) => default,
+	}
+}
+
 fn run_blocking(
 	event_tx: InputEvtTx,
+	mut commands: InputCmdRx,
 	seat: String,
 	input_config: InputConfig,
 ) -> Result<(), InputError> {
@@ -121,41 +154,95 @@ fn run_blocking(
 	input
 		.udev_assign_seat(&seat)
 		.map_err(|_| InputError::AssignSeat { seat: seat.clone() })?;
+	let mut shortcuts = ShortcutMatcher::default();
+	let mut calibrations = Calibrations::load();
+	let mut devices = Devices::default();
+	let mut repeater = KeyRepeater::new(input_config.key_repeat);
 	loop {
 		let mut pollfd = libc::pollfd {
 			fd: input.as_raw_fd(),
 			events: libc::POLLIN,
 			revents: 0,
 		};
-		let poll_res = unsafe { libc::poll(&mut pollfd as *mut libc::pollfd, 1, 1000) };
+		let timeout = repeater
+			.timeout(Instant::now())
+			.map_or(POLL_TIMEOUT_MS, |due| {
+				(due.as_micros().div_ceil(1000) as i32).min(POLL_TIMEOUT_MS)
+			});
+		let poll_res = unsafe { libc::poll(&mut pollfd as *mut libc::pollfd, 1, timeout) };
 		if poll_res < 0 {
 			let err = io::Error::last_os_error();
 			if err.kind() == io::ErrorKind::Interrupted {
 				continue;
 			}
 			let _ = event_tx.blocking_send(InputEvt::FatalError {
-				reason: Arc::<str>::from(format!("poll failed: {err}")),
+				error: Arc::new(io::Error::new(err.kind(), format!("poll failed: {err}")).into()),
 			});
 			return Err(err.into());
 		}
+		while let Ok(command) = commands.try_recv() {
+			match command {
+				InputCmd::SetShortcuts(list) => shortcuts.set_shortcuts(list),
+				InputCmd::SetCalibration { device, matrix } => calibrations.set(device, matrix),
+				InputCmd::ConfigureDevice(payload) => devices.configure(payload),
+				InputCmd::GetDeviceConfigs { request } => {
+					let evt = InputEvt::DeviceConfigs {
+						request,
+						devices: devices.configs(),
+					};
+					if event_tx.blocking_send(evt).is_err() {
+						return Ok(());
+					}
+				}
+			}
+		}
+		if let Some(repeat) = repeater.due(Instant::now())
+			&& event_tx.blocking_send(InputEvt::Event(repeat)).is_err()
+		{
+			return Ok(());
+		}
 		if poll_res == 0 {
 			continue;
 		}
 		if let Err(e) = input.dispatch() {
 			let _ = event_tx.blocking_send(InputEvt::FatalError {
-				reason: Arc::<str>::from(format!("dispatch failed: {e}")),
+				error: Arc::new(io::Error::new(e.kind(), format!("dispatch failed: {e}")).into()),
 			});
 			return Err(e.into());
 		}
 		for event in &mut input {
-			if let Event::Device(DeviceEvent::Added(added)) = &event {
-				let mut device = added.device();
-				configure_device_tap(&mut device, input_config);
+			match &event {
+				Event::Device(DeviceEvent::Added(added)) => {
+					let mut device = added.device();
+					configure_device_tap(&mut device, input_config);
+					let key = format!(
+						"{:04x}:{:04x} {}",
+						device.id_vendor(),
+						device.id_product(),
+						device.name()
+					);
+					calibrations.device_added(device_id(added), key, device.size());
+					devices.added(device_id(added), device);
+				}
+				Event::Device(DeviceEvent::Removed(removed)) => {
+					calibrations.device_removed(device_id(removed));
+					devices.removed(device_id(removed));
+				}
+				_ => {}
 			}
-			let Some(payload) = map_event(event) else {
+			let Some(mut payload) = map_event(event) else {
 				continue;
 			};
-			if event_tx.blocking_send(InputEvt::Event(payload)).is_err() {
+			calibrations.apply(&mut payload);
+			let evt = match shortcuts.process(&payload) {
+				ShortcutMatch::Forward => {
+					repeater.track(&payload, Instant::now());
+					InputEvt::Event(payload)
+				}
+				ShortcutMatch::Swallow => continue,
+				ShortcutMatch::Triggered(id) => InputEvt::ShortcutTriggered { id },
+			};
+			if event_tx.blocking_send(evt).is_err() {
 				return Ok(());
 			}
 		}
@@ -228,6 +315,7 @@ fn map_event(event: Event) -> Option<InputEventPayload> {
 			time_usec: key.time_usec(),
 			key: key.key(),
 			state: map_key_state(key.key_state()),
+			repeat: false,
 		}),
 		Event::Pointer(pointer) => map_pointer_event(pointer),
 		Event::Touch(touch) => map_touch_event(touch),
@@ -274,6 +362,7 @@ fn map_pointer_event(event: PointerEvent) -> Option<InputEventPayload> {
 			y: motion.absolute_y(),
 			x_transformed: motion.absolute_x_transformed(65535),
 			y_transformed: motion.absolute_y_transformed(65535),
+			monitor_id: None,
 		}),
 		PointerEvent::Button(button) => Some(InputEventPayload::PointerButton {
 			device: device_id(&button),
@@ -329,6 +418,7 @@ fn map_touch_event(event: TouchEvent) -> Option<InputEventPayload> {
 				y: down.y(),
 				x_transformed: down.x_transformed(65535),
 				y_transformed: down.y_transformed(65535),
+				monitor_id: None,
 			},
 		}),
 		TouchEvent::Up(up) => Some(InputEventPayload::TouchUp {
@@ -345,6 +435,7 @@ fn map_touch_event(event: TouchEvent) -> Option<InputEventPayload> {
 				y: motion.y(),
 				x_transformed: motion.x_transformed(65535),
 				y_transformed: motion.y_transformed(65535),
+				monitor_id: None,
 			},
 		}),
 		TouchEvent::Frame(frame) => Some(InputEventPayload::TouchFrame {
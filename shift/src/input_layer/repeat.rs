@@ -0,0 +1,145 @@
+//! Key repeat: while a key is held, shift sends it again as `Key { repeat: true, .. }`.
+//! - `SHIFT_KEY_REPEAT_DELAY` ms after the press (default 600), then `SHIFT_KEY_REPEAT_RATE`
+//!   times a second (default 25); a rate of 0 turns repeat off
+//! - only the last key pressed repeats, and modifiers never do
+//! - clients learn the settings from `auth_ok.key_repeat`; those repeating keys themselves opt out
+//!   with `auth.local_key_repeat`
+
+use std::time::{Duration, Instant};
+
+use tab_protocol::{InputEventPayload, KeyRepeatInfo, KeyState};
+
+use super::{env_u32, shortcuts::is_modifier};
+
+const DEFAULT_RATE: u32 = 25;
+const DEFAULT_DELAY_MS: u32 = 600;
+
+pub fn settings_from_env() -> KeyRepeatInfo {
+	KeyRepeatInfo {
+		rate: env_u32("SHIFT_KEY_REPEAT_RATE", DEFAULT_RATE),
+		delay_ms: env_u32("SHIFT_KEY_REPEAT_DELAY", DEFAULT_DELAY_MS),
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Held {
+	device: u32,
+	key: u32,
+	pressed_usec: u64,
+	pressed_at: Instant,
+	next: Instant,
+}
+
+#[derive(Debug)]
+pub struct KeyRepeater {
+	settings: KeyRepeatInfo,
+	held: Option<Held>,
+}
+
+impl KeyRepeater {
+	pub fn new(settings: KeyRepeatInfo) -> Self {
+		Self {
+			settings,
+			held: None,
+		}
+	}
+
+	/// Follows a key event that was forwarded to sessions.
+	pub fn track(&mut self, event: &InputEventPayload, now: Instant) {
+		let InputEventPayload::Key {
+			device,
+			time_usec,
+			key,
+			state,
+			repeat: false,
+		} = event
+		else {
+			return;
+		};
+		match state {
+			KeyState::Pressed if self.settings.rate > 0 && !is_modifier(*key) => {
+				self.held = Some(Held {
+					device: *device,
+					key: *key,
+					pressed_usec: *time_usec,
+					pressed_at: now,
+					next: now + Duration::from_millis(self.settings.delay_ms.into()),
+				});
+			}
+			KeyState::Released if self.held.is_some_and(|held| held.key == *key) => {
+				self.held = None;
+			}
+			_ => {}
+		}
+	}
+
+	/// Time left until the next repeat, if a key is held.
+	pub fn timeout(&self, now: Instant) -> Option<Duration> {
+		self
+			.held
+			.map(|held| held.next.saturating_duration_since(now))
+	}
+
+	/// The repeat due at `now`, if any. Repeats missed while the input thread was busy are
+	/// skipped rather than sent in a burst.
+	pub fn due(&mut self, now: Instant) -> Option<InputEventPayload> {
+		let held = self.held.as_mut().filter(|held| held.next <= now)?;
+		let interval = Duration::from_secs(1) / self.settings.rate;
+		held.next += interval;
+		if held.next <= now {
+			held.next = now + interval;
+		}
+		Some(InputEventPayload::Key {
+			device: held.device,
+			time_usec: held.pressed_usec + (now - held.pressed_at).as_micros() as u64,
+			key: held.key,
+			state: KeyState::Pressed,
+			repeat: true,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key(key: u32, state: KeyState) -> InputEventPayload {
+		InputEventPayload::Key {
+			device: 1,
+			time_usec: 1_000,
+			key,
+			state,
+			repeat: false,
+		}
+	}
+
+	#[test]
+	fn held_keys_repeat_after_the_delay_until_released() {
+		let start = Instant::now();
+		let ms = |ms| start + Duration::from_millis(ms);
+		let mut repeater = KeyRepeater::new(KeyRepeatInfo {
+			rate: 25,
+			delay_ms: 600,
+		});
+		repeater.track(&key(42, KeyState::Pressed), start);
+		assert_eq!(repeater.timeout(start), None, "modifiers don't repeat");
+
+		repeater.track(&key(30, KeyState::Pressed), start);
+		assert_eq!(repeater.due(ms(599)), None);
+		let Some(InputEventPayload::Key {
+			key: 30,
+			time_usec: 601_000,
+			repeat: true,
+			..
+		}) = repeater.due(ms(600))
+		else {
+			panic!("expected a repeat of the held key");
+		};
+		assert_eq!(repeater.timeout(ms(600)), Some(Duration::from_millis(40)));
+		assert!(repeater.due(ms(2000)).is_some());
+		assert_eq!(repeater.due(ms(2001)), None, "missed repeats are skipped");
+
+		repeater.track(&key(30, KeyState::Released), ms(2010));
+		assert_eq!(repeater.timeout(ms(2010)), None);
+	}
+}
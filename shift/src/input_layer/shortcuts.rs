@@ -0,0 +1,89 @@
+use std::{collections::HashSet, sync::Arc};
+
+use tab_protocol::{InputEventPayload, KeyState, ShortcutModifier};
+
+use crate::comms::server2input::Shortcut;
+
+const MODIFIERS: [ShortcutModifier; 4] = [
+	ShortcutModifier::Ctrl,
+	ShortcutModifier::Alt,
+	ShortcutModifier::Shift,
+	ShortcutModifier::Super,
+];
+
+/// evdev key codes for the left and right variant of a modifier.
+fn modifier_keys(modifier: ShortcutModifier) -> [u32; 2] {
+	match modifier {
+		ShortcutModifier::Ctrl => [29, 97],
+		ShortcutModifier::Alt => [56, 100],
+		ShortcutModifier::Shift => [42, 54],
+		ShortcutModifier::Super => [125, 126],
+	}
+}
+
+/// Whether `key` is one of the modifiers shortcuts can use.
+pub fn is_modifier(key: u32) -> bool {
+	MODIFIERS
+		.iter()
+		.any(|modifier| modifier_keys(*modifier).contains(&key))
+}
+
+pub enum ShortcutMatch {
+	Forward,
+	Swallow,
+	Triggered(Arc<str>),
+}
+
+/// Matches key events against registered shortcuts before they are forwarded to sessions.
+#[derive(Debug, Default)]
+pub struct ShortcutMatcher {
+	shortcuts: Vec<Shortcut>,
+	held: HashSet<u32>,
+	swallowed: HashSet<u32>,
+}
+
+impl ShortcutMatcher {
+	pub fn set_shortcuts(&mut self, shortcuts: Vec<Shortcut>) {
+		self.shortcuts = shortcuts;
+	}
+
+	fn modifier_held(&self, modifier: ShortcutModifier) -> bool {
+		modifier_keys(modifier)
+			.iter()
+			.any(|key| self.held.contains(key))
+	}
+
+	/// Matching presses are reported as triggered; the press and its release are both swallowed.
+	pub fn process(&mut self, event: &InputEventPayload) -> ShortcutMatch {
+		let InputEventPayload::Key { key, state, .. } = event else {
+			return ShortcutMatch::Forward;
+		};
+		match state {
+			KeyState::Pressed => {
+				let matched = self.shortcuts.iter().find(|shortcut| {
+					shortcut.key == *key
+						&& MODIFIERS
+							.iter()
+							.all(|m| shortcut.modifiers.contains(m) == self.modifier_held(*m))
+				});
+				let matched = matched.map(|shortcut| Arc::clone(&shortcut.id));
+				self.held.insert(*key);
+				match matched {
+					Some(id) => {
+						self.swallowed.insert(*key);
+						ShortcutMatch::Triggered(id)
+					}
+					None => ShortcutMatch::Forward,
+				}
+			}
+			KeyState::Released => {
+				self.held.remove(key);
+				if self.swallowed.remove(key) {
+					ShortcutMatch::Swallow
+				} else {
+					ShortcutMatch::Forward
+				}
+			}
+		}
+	}
+}
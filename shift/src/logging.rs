@@ -0,0 +1,238 @@
+//! Log output for shift.
+//! - `SHIFT_LOG_FORMAT=json` switches stdout to one JSON object per line
+//! - levels can be changed at runtime by admin clients (`log_level`)
+//! - the most recent records are kept in memory so they can be fetched with `log_dump`
+
+use std::{
+	collections::{BTreeMap, VecDeque},
+	fmt,
+	io::Write,
+	str::FromStr,
+	sync::{Arc, Mutex},
+};
+
+use serde_json::{Map, Value};
+use thiserror::Error;
+use tracing::{
+	Event, Subscriber,
+	field::{Field, Visit},
+	level_filters::LevelFilter,
+};
+use tracing_subscriber::{
+	EnvFilter, Layer, Registry,
+	filter::ParseError,
+	layer::{Context, SubscriberExt},
+	registry::LookupSpan,
+	reload,
+	util::SubscriberInitExt,
+};
+
+const DEFAULT_FILTER: &str = "debug";
+const DEFAULT_RING_CAPACITY: usize = 2048;
+
+#[derive(Debug, Error)]
+pub enum LogError {
+	#[error("invalid log level: {0}")]
+	InvalidLevel(String),
+	#[error("invalid log target: {0}")]
+	InvalidTarget(String),
+	#[error("invalid filter directive: {0}")]
+	InvalidDirective(#[from] ParseError),
+	#[error("failed to reload log filter: {0}")]
+	Reload(#[from] reload::Error),
+}
+
+/// Most recent log records, oldest first, already encoded as JSON lines.
+struct RingBuffer {
+	records: VecDeque<String>,
+	capacity: usize,
+}
+
+impl RingBuffer {
+	fn push(&mut self, record: String) {
+		if self.capacity == 0 {
+			return;
+		}
+		if self.records.len() == self.capacity {
+			self.records.pop_front();
+		}
+		self.records.push_back(record);
+	}
+}
+
+struct Levels {
+	default: String,
+	targets: BTreeMap<String, LevelFilter>,
+}
+
+impl Levels {
+	fn directives(&self) -> String {
+		let mut directives = self.default.clone();
+		for (target, level) in &self.targets {
+			directives.push_str(&format!(",{target}={level}"));
+		}
+		directives
+	}
+}
+
+/// Runtime control over the installed logger.
+#[derive(Clone)]
+pub struct LogHandle {
+	filter: reload::Handle<EnvFilter, Registry>,
+	levels: Arc<Mutex<Levels>>,
+	ring: Arc<Mutex<RingBuffer>>,
+}
+
+impl fmt::Debug for LogHandle {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("LogHandle").finish_non_exhaustive()
+	}
+}
+
+impl LogHandle {
+	/// Sets the level of one target, or the default level when `target` is `None`.
+	pub fn set_level(&self, target: Option<&str>, level: &str) -> Result<(), LogError> {
+		let level =
+			LevelFilter::from_str(level).map_err(|_| LogError::InvalidLevel(level.to_string()))?;
+		let mut levels = self.levels.lock().unwrap();
+		match target {
+			None => levels.default = level.to_string(),
+			Some(target)
+				if target.is_empty()
+					|| !target
+						.chars()
+						.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':') =>
+			{
+				return Err(LogError::InvalidTarget(target.to_string()));
+			}
+			Some(target) => {
+				levels.targets.insert(target.to_string(), level);
+			}
+		}
+		let filter = EnvFilter::try_new(levels.directives())?;
+		self.filter.reload(filter)?;
+		tracing::info!(directives = %levels.directives(), "log levels changed");
+		Ok(())
+	}
+
+	/// Up to `limit` of the most recent records, oldest first.
+	pub fn dump(&self, limit: Option<usize>) -> Vec<String> {
+		let ring = self.ring.lock().unwrap();
+		let skip = limit.map_or(0, |limit| ring.records.len().saturating_sub(limit));
+		ring.records.iter().skip(skip).cloned().collect()
+	}
+
+	/// Like [`Self::dump`], but gives up instead of waiting if the buffer is locked.
+	pub fn try_dump(&self, limit: Option<usize>) -> Option<Vec<String>> {
+		let ring = self.ring.try_lock().ok()?;
+		let skip = limit.map_or(0, |limit| ring.records.len().saturating_sub(limit));
+		Some(ring.records.iter().skip(skip).cloned().collect())
+	}
+}
+
+/// Installs the global subscriber. Call once, before anything logs.
+pub fn init() -> LogHandle {
+	let default = std::env::var("RUST_LOG")
+		.ok()
+		.filter(|v| EnvFilter::try_new(v).is_ok())
+		.unwrap_or_else(|| DEFAULT_FILTER.to_string());
+	let (filter, filter_handle) = reload::Layer::new(EnvFilter::new(&default));
+	let json = std::env::var("SHIFT_LOG_FORMAT").is_ok_and(|v| v.trim() == "json");
+	let capacity = std::env::var("SHIFT_LOG_RING_SIZE")
+		.ok()
+		.and_then(|v| v.parse::<usize>().ok())
+		.unwrap_or(DEFAULT_RING_CAPACITY);
+	let ring = Arc::new(Mutex::new(RingBuffer {
+		records: VecDeque::with_capacity(capacity),
+		capacity,
+	}));
+
+	Registry::default()
+		.with(filter)
+		.with((!json).then(|| {
+			tracing_subscriber::fmt::layer()
+				.with_target(false)
+				.with_ansi(false)
+		}))
+		.with(JsonLayer {
+			ring: Arc::clone(&ring),
+			stdout: json,
+		})
+		// .with(tracing_tracy::TracyLayer::new(tracing_tracy::DefaultConfig::default()))
+		.init();
+
+	LogHandle {
+		filter: filter_handle,
+		levels: Arc::new(Mutex::new(Levels {
+			default,
+			targets: BTreeMap::new(),
+		})),
+		ring,
+	}
+}
+
+/// Encodes every event as a JSON line into the ring buffer, and to stdout when asked to.
+struct JsonLayer {
+	ring: Arc<Mutex<RingBuffer>>,
+	stdout: bool,
+}
+
+impl<S> Layer<S> for JsonLayer
+where
+	S: Subscriber + for<'a> LookupSpan<'a>,
+{
+	fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+		let metadata = event.metadata();
+		let mut fields = JsonFields(Map::new());
+		event.record(&mut fields);
+
+		let mut record = Map::new();
+		record.insert(
+			"timestamp".into(),
+			chrono::Utc::now()
+				.to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+				.into(),
+		);
+		record.insert("level".into(), metadata.level().as_str().into());
+		record.insert("target".into(), metadata.target().into());
+		if let Some(scope) = ctx.event_scope(event) {
+			let spans = scope
+				.from_root()
+				.map(|span| Value::from(span.name()))
+				.collect::<Vec<_>>();
+			record.insert("spans".into(), Value::Array(spans));
+		}
+		record.insert("fields".into(), Value::Object(fields.0));
+		let line = Value::Object(record).to_string();
+
+		if self.stdout {
+			let _ = writeln!(std::io::stdout().lock(), "{line}");
+		}
+		self.ring.lock().unwrap().push(line);
+	}
+}
+
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+	fn record_f64(&mut self, field: &Field, value: f64) {
+		self.0.insert(field.name().into(), value.into());
+	}
+	fn record_i64(&mut self, field: &Field, value: i64) {
+		self.0.insert(field.name().into(), value.into());
+	}
+	fn record_u64(&mut self, field: &Field, value: u64) {
+		self.0.insert(field.name().into(), value.into());
+	}
+	fn record_bool(&mut self, field: &Field, value: bool) {
+		self.0.insert(field.name().into(), value.into());
+	}
+	fn record_str(&mut self, field: &Field, value: &str) {
+		self.0.insert(field.name().into(), value.into());
+	}
+	fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+		self
+			.0
+			.insert(field.name().into(), format!("{value:?}").into());
+	}
+}
@@ -1,18 +1,23 @@
 use std::path::PathBuf;
 
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt, util::SubscriberInitExt};
-
 use crate::{
 	input_layer::{InputLayer, channels::Channels as InputChannels},
-	rendering_layer::{RenderingLayer, channels::Channels as RenderChannels},
+	rendering_layer::{channels::Channels as RenderChannels, engine::EngineKind},
 	server_layer::ShiftServer,
 };
 
 mod auth;
 mod client_layer;
 mod comms;
+mod crash;
+#[cfg(feature = "dbus")]
+mod dbus;
+mod error;
+#[cfg(feature = "fault-injection")]
+mod faults;
 mod ids;
 mod input_layer;
+mod logging;
 mod monitor;
 mod rendering_layer;
 mod server_layer;
@@ -20,16 +25,8 @@ mod sessions;
 #[tokio::main]
 async fn main() {
 	// ---- logging/tracing ----
-	let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("debug"));
-	Registry::default()
-		.with(env_filter)
-		.with(
-			tracing_subscriber::fmt::layer()
-				.with_target(false)
-				.with_ansi(false),
-		)
-		// .with(tracing_tracy::TracyLayer::new(tracing_tracy::DefaultConfig::default()))
-		.init();
+	let logging = logging::init();
+	crash::install(logging.clone());
 
 	// ---- socket path ----
 	let socket_path = std::env::var_os("SHIFT_SOCKET")
@@ -37,16 +34,17 @@ async fn main() {
 		.unwrap_or_else(|| "/tmp/shift.sock".into());
 
 	// ---- create inter-layer channels ----
-	let render_channels = RenderChannels::new();
+	let render_channels = RenderChannels::from_env();
 	let (server_render_channels, rendering_render_channels) = render_channels.split();
-	let input_channels = InputChannels::new();
+	let input_channels = InputChannels::from_env();
 	let (server_input_channels, input_layer_channels) = input_channels.split();
 
 	// ---- create server ----
 	let mut server = match ShiftServer::bind(
 		&socket_path,
 		server_render_channels,
-		server_input_channels.into_parts(),
+		server_input_channels,
+		logging,
 	)
 	.await
 	{
@@ -57,18 +55,20 @@ async fn main() {
 		}
 	};
 	server.add_initial_session();
+	#[cfg(feature = "dbus")]
+	server.attach_dbus();
 	tracing::info!("starting ShiftServer on {:?}", socket_path);
 
 	// ---- create rendering ----
-	let rendering = match RenderingLayer::init(rendering_render_channels) {
-		Ok(r) => r,
+	let rendering = match EngineKind::from_env().init(rendering_render_channels) {
+		Ok(engine) => engine.run(),
 		Err(e) => {
 			tracing::error!("failed to init rendering layer: {e}");
 			return;
 		}
 	};
 	let input = InputLayer::init(input_layer_channels);
-	let result = tokio::join!(server.start(), rendering.run(), input.run());
+	let result = tokio::join!(server.start(), rendering, input.run());
 	if let Err(e) = result.1 {
 		tracing::error!("rendering thread ended with error: {e}");
 	}
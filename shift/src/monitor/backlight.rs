@@ -0,0 +1,202 @@
+//! Backlights, as exposed by the kernel in `/sys/class/backlight`.
+//! - a backlight whose device is a DRM connector belongs to that connector's monitor
+//! - any other backlight (ACPI, platform drivers, GPUs) belongs to the built-in panel: the eDP,
+//!   LVDS or DSI connector
+//!
+//! When several fit one monitor, the kernel's documented preference applies: firmware, then
+//! platform, then raw.
+
+use std::{
+	fs, io,
+	path::{Path, PathBuf},
+};
+
+use super::{DRM_DIR, connector_dir};
+
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+const BUILT_IN_CONNECTORS: [&str; 3] = ["eDP", "LVDS", "DSI"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backlight {
+	/// Directory name in `/sys/class/backlight`, e.g. `intel_backlight`.
+	pub name: String,
+	pub max_brightness: u32,
+	path: PathBuf,
+	/// 0 for firmware, 1 for platform, 2 for raw.
+	preference: u8,
+	/// Canonical sysfs path of the DRM connector the backlight is attached to.
+	connector: Option<PathBuf>,
+}
+
+impl Backlight {
+	/// Every backlight the kernel exposes, sorted by name.
+	pub fn all() -> Vec<Self> {
+		Self::read_all(Path::new(BACKLIGHT_DIR))
+	}
+
+	/// The backlight of a DRM connector's monitor, if it has one.
+	pub fn for_connector(connector_id: u32) -> Option<Self> {
+		let connector = connector_dir(Path::new(DRM_DIR), connector_id)?;
+		Self::pick(Self::all(), &connector)
+	}
+
+	fn read_all(dir: &Path) -> Vec<Self> {
+		let Ok(entries) = fs::read_dir(dir) else {
+			return Vec::new();
+		};
+		let mut backlights = entries
+			.flatten()
+			.filter_map(|entry| Self::read(&entry.path()))
+			.collect::<Vec<_>>();
+		backlights.sort_by(|a, b| a.name.cmp(&b.name));
+		backlights
+	}
+
+	fn read(path: &Path) -> Option<Self> {
+		let preference = match fs::read_to_string(path.join("type")).ok()?.trim() {
+			"firmware" => 0,
+			"platform" => 1,
+			"raw" => 2,
+			_ => return None,
+		};
+		let max_brightness = read_u32(&path.join("max_brightness")).ok()?;
+		let device = path.join("device");
+		let connector = device
+			.join("connector_id")
+			.exists()
+			.then(|| fs::canonicalize(&device).ok())
+			.flatten();
+		Some(Self {
+			name: path.file_name()?.to_string_lossy().into_owned(),
+			max_brightness,
+			path: path.to_path_buf(),
+			preference,
+			connector,
+		})
+	}
+
+	/// Picks the preferred backlight of the connector at `connector`, a directory in
+	/// `/sys/class/drm`.
+	fn pick(backlights: Vec<Self>, connector: &Path) -> Option<Self> {
+		let built_in = is_built_in(connector);
+		let connector = fs::canonicalize(connector).ok();
+		backlights
+			.into_iter()
+			.filter(|backlight| match &backlight.connector {
+				Some(attached) => Some(attached) == connector.as_ref(),
+				None => built_in,
+			})
+			.min_by_key(|backlight| backlight.preference)
+	}
+
+	/// Whether this lights a built-in panel rather than an external monitor.
+	pub fn is_built_in(&self) -> bool {
+		self.connector.as_deref().is_none_or(is_built_in)
+	}
+
+	/// Current brightness, from 0 to [`Self::max_brightness`].
+	pub fn brightness(&self) -> io::Result<u32> {
+		read_u32(&self.path.join("actual_brightness"))
+			.or_else(|_| read_u32(&self.path.join("brightness")))
+	}
+
+	/// Sets the brightness, clamped to [`Self::max_brightness`].
+	pub fn set_brightness(&self, brightness: u32) -> io::Result<()> {
+		fs::write(
+			self.path.join("brightness"),
+			brightness.min(self.max_brightness).to_string(),
+		)
+	}
+}
+
+/// Whether a `/sys/class/drm` connector directory, e.g. `card0-eDP-1`, is a built-in panel.
+fn is_built_in(connector: &Path) -> bool {
+	connector
+		.file_name()
+		.and_then(|name| name.to_str())
+		.and_then(|name| name.split_once('-'))
+		.is_some_and(|(_, name)| {
+			BUILT_IN_CONNECTORS
+				.iter()
+				.any(|kind| name.strip_prefix(kind).is_some_and(|n| n.starts_with('-')))
+		})
+}
+
+fn read_u32(path: &Path) -> io::Result<u32> {
+	fs::read_to_string(path)?
+		.trim()
+		.parse()
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::os::unix::fs::symlink;
+
+	use super::*;
+
+	struct Sysfs(PathBuf);
+
+	impl Sysfs {
+		fn new() -> Self {
+			let root = std::env::temp_dir().join(format!("shift-backlight-{}", rand::random::<u64>()));
+			fs::create_dir_all(root.join("drm")).unwrap();
+			fs::create_dir_all(root.join("backlight")).unwrap();
+			fs::create_dir_all(root.join("gpu")).unwrap();
+			Self(root)
+		}
+
+		fn connector(&self, name: &str, connector_id: u32) -> PathBuf {
+			let path = self.0.join("drm").join(name);
+			fs::create_dir_all(&path).unwrap();
+			fs::write(path.join("connector_id"), format!("{connector_id}\n")).unwrap();
+			path
+		}
+
+		fn backlight(&self, name: &str, kind: &str, device: &Path) {
+			let path = self.0.join("backlight").join(name);
+			fs::create_dir_all(&path).unwrap();
+			fs::write(path.join("type"), format!("{kind}\n")).unwrap();
+			fs::write(path.join("max_brightness"), "255\n").unwrap();
+			symlink(device, path.join("device")).unwrap();
+		}
+
+		fn pick(&self, connector_id: u32) -> Option<String> {
+			let connector = connector_dir(&self.0.join("drm"), connector_id)?;
+			let backlights = Backlight::read_all(&self.0.join("backlight"));
+			Backlight::pick(backlights, &connector).map(|backlight| backlight.name)
+		}
+	}
+
+	impl Drop for Sysfs {
+		fn drop(&mut self) {
+			let _ = fs::remove_dir_all(&self.0);
+		}
+	}
+
+	#[test]
+	fn maps_backlights_to_their_connector_or_the_built_in_panel() {
+		let sysfs = Sysfs::new();
+		let panel = sysfs.connector("card0-eDP-1", 40);
+		let external = sysfs.connector("card0-HDMI-A-1", 50);
+		sysfs.connector("card0-DP-2", 60);
+		let gpu = sysfs.0.join("gpu");
+		sysfs.backlight("intel_backlight", "raw", &panel);
+		sysfs.backlight("ddcci1", "raw", &external);
+		assert_eq!(sysfs.pick(40).as_deref(), Some("intel_backlight"));
+		assert_eq!(sysfs.pick(50).as_deref(), Some("ddcci1"));
+		assert_eq!(sysfs.pick(60), None);
+
+		sysfs.backlight("acpi_video0", "firmware", &gpu);
+		assert_eq!(sysfs.pick(40).as_deref(), Some("acpi_video0"));
+		assert_eq!(sysfs.pick(50).as_deref(), Some("ddcci1"));
+		assert_eq!(sysfs.pick(60), None);
+
+		let built_in = Backlight::read_all(&sysfs.0.join("backlight"))
+			.into_iter()
+			.filter(Backlight::is_built_in)
+			.map(|backlight| backlight.name)
+			.collect::<Vec<_>>();
+		assert_eq!(built_in, ["acpi_video0", "intel_backlight"]);
+	}
+}
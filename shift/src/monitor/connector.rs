@@ -0,0 +1,335 @@
+//! DRM connector properties shift watches, read with `DRM_IOCTL_MODE_OBJ_GETPROPERTIES` on the
+//! card EasyDRM has open.
+//! - `link-status` goes `Bad` when the kernel had to drop the link, e.g. a DisplayPort cable that
+//!   needs retraining. Userspace has to modeset again, see [`LinkRetrains`]
+//! - `non-desktop` marks VR headsets and similar that a desktop shouldn't extend onto
+//! - `panel orientation` tells how a built-in panel is mounted in its case
+//!
+//! Property ids are looked up once per connector; values are read again on every monitor sync.
+
+use std::{
+	collections::HashMap,
+	fs, io,
+	os::fd::RawFd,
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use tab_protocol::PanelOrientation;
+
+use super::{DRM_DIR, connector_dir};
+
+const DRM_MODE_OBJECT_CONNECTOR: u32 = 0xc0c0_c0c0;
+const DRM_MODE_PROP_ENUM: u32 = 1 << 3;
+const DRM_PROP_NAME_LEN: usize = 32;
+/// The properties shift reads, the others are skipped when looking up ids.
+const WATCHED: [&str; 3] = ["link-status", "non-desktop", "panel orientation"];
+/// How long to wait before modesetting a connector whose link is still bad again.
+const LINK_RETRAIN_BACKOFF: Duration = Duration::from_secs(5);
+
+/// `struct drm_mode_obj_get_properties`.
+#[repr(C)]
+#[derive(Default)]
+struct ObjGetProperties {
+	props_ptr: u64,
+	prop_values_ptr: u64,
+	count_props: u32,
+	obj_id: u32,
+	obj_type: u32,
+}
+
+/// `struct drm_mode_get_property`.
+#[repr(C)]
+struct GetProperty {
+	values_ptr: u64,
+	enum_blob_ptr: u64,
+	prop_id: u32,
+	flags: u32,
+	name: [u8; DRM_PROP_NAME_LEN],
+	count_values: u32,
+	count_enum_blobs: u32,
+}
+
+/// `struct drm_mode_property_enum`.
+#[repr(C)]
+struct PropertyEnum {
+	value: u64,
+	name: [u8; DRM_PROP_NAME_LEN],
+}
+
+/// `_IOWR('d', nr, T)`.
+const fn iowr<T>(nr: u8) -> libc::c_ulong {
+	(3 << 30)
+		| ((size_of::<T>() as libc::c_ulong) << 16)
+		| ((b'd' as libc::c_ulong) << 8)
+		| nr as libc::c_ulong
+}
+
+const DRM_IOCTL_MODE_GETPROPERTY: libc::c_ulong = iowr::<GetProperty>(0xAA);
+const DRM_IOCTL_MODE_OBJ_GETPROPERTIES: libc::c_ulong = iowr::<ObjGetProperties>(0xB9);
+/// `DRM_IOCTL_SET_MASTER`, `_IO('d', 0x1e)`.
+const DRM_IOCTL_SET_MASTER: libc::c_ulong = 0x641e;
+
+/// What the watched properties of a connector are set to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ConnectorProps {
+	pub link_bad: bool,
+	pub non_desktop: bool,
+	pub panel_orientation: Option<PanelOrientation>,
+}
+
+impl ConnectorProps {
+	/// Reads `(name, value, enum name of the value)` of a connector's properties.
+	pub fn from_properties<'a>(
+		props: impl IntoIterator<Item = (&'a str, u64, Option<&'a str>)>,
+	) -> Self {
+		let mut parsed = Self::default();
+		for (name, value, enum_name) in props {
+			match name {
+				"link-status" => parsed.link_bad = enum_name == Some("Bad"),
+				"non-desktop" => parsed.non_desktop = value != 0,
+				"panel orientation" => {
+					parsed.panel_orientation = match enum_name {
+						Some("Normal") => Some(PanelOrientation::Normal),
+						Some("Upside Down") => Some(PanelOrientation::UpsideDown),
+						Some("Left Side Up") => Some(PanelOrientation::LeftSideUp),
+						Some("Right Side Up") => Some(PanelOrientation::RightSideUp),
+						_ => None,
+					}
+				}
+				_ => {}
+			}
+		}
+		parsed
+	}
+}
+
+#[derive(Debug)]
+struct Property {
+	name: &'static str,
+	/// Names of the values of an enum property.
+	enums: HashMap<u64, String>,
+}
+
+/// A connector on one of the cards shift has open, with the ids of its watched properties.
+#[derive(Debug)]
+pub struct Connector {
+	fd: RawFd,
+	connector_id: u32,
+	properties: HashMap<u32, Property>,
+}
+
+impl Connector {
+	/// Finds the card `connector_id` is on and looks up its properties. `None` when no open card
+	/// has it.
+	pub fn open(connector_id: u32) -> Option<Self> {
+		open_cards().into_iter().find_map(|(_, fd)| {
+			let (ids, _) = object_properties(fd, connector_id).ok()?;
+			let properties = ids
+				.into_iter()
+				.filter_map(|prop_id| Some((prop_id, property(fd, prop_id).ok()??)))
+				.collect();
+			Some(Self {
+				fd,
+				connector_id,
+				properties,
+			})
+		})
+	}
+
+	/// The current values of the watched properties, the defaults if they can't be read.
+	pub fn props(&self) -> ConnectorProps {
+		let (ids, values) = match object_properties(self.fd, self.connector_id) {
+			Ok(props) => props,
+			Err(e) => {
+				tracing::debug!(
+					connector_id = self.connector_id,
+					"failed to read connector properties: {e}"
+				);
+				return ConnectorProps::default();
+			}
+		};
+		ConnectorProps::from_properties(ids.iter().zip(&values).filter_map(|(id, value)| {
+			let property = self.properties.get(id)?;
+			Some((
+				property.name,
+				*value,
+				property.enums.get(value).map(String::as_str),
+			))
+		}))
+	}
+}
+
+/// Connectors whose link went bad and when each was last modeset again for it.
+#[derive(Debug, Default)]
+pub struct LinkRetrains {
+	last: HashMap<u32, Instant>,
+}
+
+impl LinkRetrains {
+	/// Modesets `connector_id` again, unless that was tried less than [`LINK_RETRAIN_BACKOFF`]
+	/// ago. EasyDRM doesn't take modeset requests, so the connector is forced off and detected
+	/// again through sysfs, which it handles like an unplug and replug.
+	pub fn retrain(&mut self, connector_id: u32, now: Instant) {
+		if self
+			.last
+			.get(&connector_id)
+			.is_some_and(|at| now.saturating_duration_since(*at) < LINK_RETRAIN_BACKOFF)
+		{
+			return;
+		}
+		self.last.insert(connector_id, now);
+		tracing::warn!(
+			connector_id,
+			"connector link status is bad, modesetting it again"
+		);
+		let Some(connector) = connector_dir(Path::new(DRM_DIR), connector_id) else {
+			tracing::warn!(
+				connector_id,
+				"no sysfs directory to retrain the link through"
+			);
+			return;
+		};
+		let status = connector.join("status");
+		if let Err(e) = fs::write(&status, "off").and_then(|_| fs::write(&status, "detect")) {
+			tracing::warn!(path = %status.display(), "failed to retrain the link: {e}");
+		}
+	}
+}
+
+/// Sets DRM master on every card shift has open, returning the ones that refused. The VT
+/// switches around suspend can leave shift without it.
+pub fn reacquire_drm_master() -> Vec<(PathBuf, io::Error)> {
+	let mut failed = Vec::new();
+	for (target, fd) in open_cards() {
+		// SAFETY: the fd is open for as long as EasyDRM is, and SET_MASTER takes no argument.
+		if unsafe { libc::ioctl(fd, DRM_IOCTL_SET_MASTER as _, 0) } < 0 {
+			failed.push((target, io::Error::last_os_error()));
+		}
+	}
+	failed
+}
+
+/// Every DRM card shift has open, by its path and fd.
+pub fn open_cards() -> Vec<(PathBuf, RawFd)> {
+	let Ok(entries) = fs::read_dir("/proc/self/fd") else {
+		return Vec::new();
+	};
+	entries
+		.flatten()
+		.filter_map(|entry| {
+			let target = fs::read_link(entry.path()).ok()?;
+			let is_card = target.starts_with("/dev/dri")
+				&& target
+					.file_name()
+					.is_some_and(|name| name.to_string_lossy().starts_with("card"));
+			let fd = entry.file_name().to_str()?.parse::<RawFd>().ok()?;
+			is_card.then_some((target, fd))
+		})
+		.collect()
+}
+
+/// The property ids and values of a connector.
+fn object_properties(fd: RawFd, connector_id: u32) -> io::Result<(Vec<u32>, Vec<u64>)> {
+	let mut req = ObjGetProperties {
+		obj_id: connector_id,
+		obj_type: DRM_MODE_OBJECT_CONNECTOR,
+		..Default::default()
+	};
+	ioctl(fd, DRM_IOCTL_MODE_OBJ_GETPROPERTIES, &mut req)?;
+	let mut ids = vec![0u32; req.count_props as usize];
+	let mut values = vec![0u64; req.count_props as usize];
+	req.props_ptr = ids.as_mut_ptr() as u64;
+	req.prop_values_ptr = values.as_mut_ptr() as u64;
+	ioctl(fd, DRM_IOCTL_MODE_OBJ_GETPROPERTIES, &mut req)?;
+	// Properties aren't added to a connector after it's created, but stay within what was allocated.
+	let count = (req.count_props as usize).min(ids.len());
+	ids.truncate(count);
+	values.truncate(count);
+	Ok((ids, values))
+}
+
+/// The name and enum values of `prop_id`, `None` when it isn't one of [`WATCHED`].
+fn property(fd: RawFd, prop_id: u32) -> io::Result<Option<Property>> {
+	let mut req = GetProperty {
+		values_ptr: 0,
+		enum_blob_ptr: 0,
+		prop_id,
+		flags: 0,
+		name: [0; DRM_PROP_NAME_LEN],
+		count_values: 0,
+		count_enum_blobs: 0,
+	};
+	ioctl(fd, DRM_IOCTL_MODE_GETPROPERTY, &mut req)?;
+	let Some(name) = WATCHED.into_iter().find(|name| c_name(&req.name) == *name) else {
+		return Ok(None);
+	};
+	let mut enums = HashMap::new();
+	if req.flags & DRM_MODE_PROP_ENUM != 0 && req.count_enum_blobs > 0 {
+		let mut blobs = (0..req.count_enum_blobs)
+			.map(|_| PropertyEnum {
+				value: 0,
+				name: [0; DRM_PROP_NAME_LEN],
+			})
+			.collect::<Vec<_>>();
+		// Only the enum entries are wanted, a zero `count_values` skips copying the values.
+		req.count_values = 0;
+		req.enum_blob_ptr = blobs.as_mut_ptr() as u64;
+		ioctl(fd, DRM_IOCTL_MODE_GETPROPERTY, &mut req)?;
+		blobs.truncate(req.count_enum_blobs as usize);
+		enums = blobs
+			.iter()
+			.map(|blob| (blob.value, c_name(&blob.name).to_owned()))
+			.collect();
+	}
+	Ok(Some(Property { name, enums }))
+}
+
+fn c_name(name: &[u8; DRM_PROP_NAME_LEN]) -> &str {
+	let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+	std::str::from_utf8(&name[..len]).unwrap_or_default()
+}
+
+fn ioctl<T>(fd: RawFd, request: libc::c_ulong, arg: &mut T) -> io::Result<()> {
+	// SAFETY: `request` is an ioctl taking a `T`, whose pointers point at buffers as large as the
+	// counts next to them say, and the fd is open for as long as EasyDRM is.
+	if unsafe { libc::ioctl(fd, request as _, arg as *mut T) } < 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn watched_properties_are_parsed() {
+		let props = ConnectorProps::from_properties([
+			("EDID", 42, None),
+			("link-status", 1, Some("Bad")),
+			("non-desktop", 1, None),
+			("panel orientation", 3, Some("Right Side Up")),
+		]);
+		assert_eq!(
+			props,
+			ConnectorProps {
+				link_bad: true,
+				non_desktop: true,
+				panel_orientation: Some(PanelOrientation::RightSideUp),
+			}
+		);
+		let props = ConnectorProps::from_properties([
+			("link-status", 0, Some("Good")),
+			("panel orientation", 0, Some("Normal")),
+		]);
+		assert!(!props.link_bad);
+		assert_eq!(props.panel_orientation, Some(PanelOrientation::Normal));
+		assert_eq!(
+			ConnectorProps::from_properties([]),
+			ConnectorProps::default()
+		);
+		assert_eq!(DRM_IOCTL_MODE_OBJ_GETPROPERTIES, 0xC020_64B9);
+		assert_eq!(DRM_IOCTL_MODE_GETPROPERTY, 0xC040_64AA);
+	}
+}
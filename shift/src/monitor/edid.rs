@@ -0,0 +1,162 @@
+//! EDID of connected monitors, as exposed by the kernel in sysfs.
+//! - manufacturer PNP id, model name and serial, for telling monitors apart
+//! - physical size, so clients can pick a sensible DPI
+
+use std::{fs, path::Path};
+
+use super::{DRM_DIR, connector_dir};
+
+const HEADER: [u8; 8] = [0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00];
+const DESCRIPTOR_OFFSETS: [usize; 4] = [54, 72, 90, 108];
+const DESCRIPTOR_SERIAL: u8 = 0xff;
+const DESCRIPTOR_NAME: u8 = 0xfc;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+	/// Three letter PNP id, e.g. `DEL`.
+	pub manufacturer: String,
+	/// The monitor name descriptor, or the product code in hex.
+	pub model: String,
+	pub serial: Option<String>,
+	pub width_mm: Option<u32>,
+	pub height_mm: Option<u32>,
+}
+
+impl Edid {
+	/// Reads the EDID of a DRM connector. Returns `None` for connectors without a display, or
+	/// kernels that don't expose `connector_id` in sysfs.
+	pub fn for_connector(connector_id: u32) -> Option<Self> {
+		let connector = connector_dir(Path::new(DRM_DIR), connector_id)?;
+		Self::read(&connector.join("edid"))
+	}
+
+	fn read(path: &Path) -> Option<Self> {
+		let bytes = fs::read(path).ok()?;
+		let edid = Self::parse(&bytes);
+		if edid.is_none() && !bytes.is_empty() {
+			tracing::debug!(path = %path.display(), len = bytes.len(), "ignoring malformed EDID");
+		}
+		edid
+	}
+
+	/// Parses the 128 byte EDID base block. Extension blocks are ignored.
+	pub fn parse(bytes: &[u8]) -> Option<Self> {
+		let block = bytes.get(..128)?;
+		if block[..8] != HEADER || block.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) != 0 {
+			return None;
+		}
+		let vendor = u16::from_be_bytes([block[8], block[9]]);
+		let manufacturer = [10, 5, 0]
+			.iter()
+			.map(|shift| char::from(b'@' + ((vendor >> shift) & 0x1f) as u8))
+			.collect();
+		let product_code = u16::from_le_bytes([block[10], block[11]]);
+		let serial_number = u32::from_le_bytes([block[12], block[13], block[14], block[15]]);
+
+		let mut name = None;
+		let mut serial = None;
+		let mut size_mm = None;
+		for offset in DESCRIPTOR_OFFSETS {
+			let descriptor = &block[offset..offset + 18];
+			if descriptor[0] != 0 || descriptor[1] != 0 {
+				// Detailed timing; the first one is the preferred mode and has the most exact size.
+				let width = u32::from(descriptor[12]) | (u32::from(descriptor[14] & 0xf0) << 4);
+				let height = u32::from(descriptor[13]) | (u32::from(descriptor[14] & 0x0f) << 8);
+				if size_mm.is_none() && width > 0 && height > 0 {
+					size_mm = Some((width, height));
+				}
+				continue;
+			}
+			match descriptor[3] {
+				DESCRIPTOR_NAME => name = descriptor_text(descriptor),
+				DESCRIPTOR_SERIAL => serial = descriptor_text(descriptor),
+				_ => {}
+			}
+		}
+		// Without a usable timing, fall back to the size in centimeters. A zero means unknown, or
+		// that the bytes hold an aspect ratio for projectors.
+		let size_mm = size_mm.or_else(|| {
+			(block[21] > 0 && block[22] > 0)
+				.then(|| (u32::from(block[21]) * 10, u32::from(block[22]) * 10))
+		});
+
+		Some(Self {
+			manufacturer,
+			model: name.unwrap_or_else(|| format!("{product_code:04x}")),
+			serial: serial.or_else(|| (serial_number != 0).then(|| serial_number.to_string())),
+			width_mm: size_mm.map(|(width, _)| width),
+			height_mm: size_mm.map(|(_, height)| height),
+		})
+	}
+}
+
+/// Text of a display descriptor: up to 13 bytes, ended by a newline and padded with spaces.
+fn descriptor_text(descriptor: &[u8]) -> Option<String> {
+	let text = &descriptor[5..18];
+	let end = text.iter().position(|b| *b == b'\n').unwrap_or(text.len());
+	let text = String::from_utf8_lossy(&text[..end]).trim().to_string();
+	(!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn base_block() -> [u8; 128] {
+		let mut block = [0u8; 128];
+		block[..8].copy_from_slice(&HEADER);
+		// "DEL", product 0xa0c4, serial 0x12345678
+		block[8..16].copy_from_slice(&[0x10, 0xac, 0xc4, 0xa0, 0x78, 0x56, 0x34, 0x12]);
+		block[21] = 53;
+		block[22] = 30;
+		block
+	}
+
+	fn with_checksum(mut block: [u8; 128]) -> [u8; 128] {
+		block[127] = 0;
+		let sum = block.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+		block[127] = 0u8.wrapping_sub(sum);
+		block
+	}
+
+	#[test]
+	fn parses_descriptors_and_timing_size() {
+		let mut block = base_block();
+		// Detailed timing: 527 x 296 mm
+		block[54] = 0x01;
+		block[54 + 12] = 0x0f;
+		block[54 + 13] = 0x28;
+		block[54 + 14] = 0x21;
+		block[72 + 3] = DESCRIPTOR_NAME;
+		block[72 + 5..72 + 18].copy_from_slice(b"DELL U2415\n  ");
+		block[90 + 3] = DESCRIPTOR_SERIAL;
+		block[90 + 5..90 + 18].copy_from_slice(b"7MT0184Q1CJL\n");
+		let edid = Edid::parse(&with_checksum(block)).unwrap();
+		assert_eq!(
+			edid,
+			Edid {
+				manufacturer: "DEL".into(),
+				model: "DELL U2415".into(),
+				serial: Some("7MT0184Q1CJL".into()),
+				width_mm: Some(527),
+				height_mm: Some(296),
+			}
+		);
+	}
+
+	#[test]
+	fn falls_back_to_product_code_serial_number_and_centimeters() {
+		let edid = Edid::parse(&with_checksum(base_block())).unwrap();
+		assert_eq!(edid.model, "a0c4");
+		assert_eq!(edid.serial.as_deref(), Some("305419896"));
+		assert_eq!((edid.width_mm, edid.height_mm), (Some(530), Some(300)));
+	}
+
+	#[test]
+	fn rejects_bad_checksum_and_short_blocks() {
+		let mut block = with_checksum(base_block());
+		block[127] = block[127].wrapping_add(1);
+		assert_eq!(Edid::parse(&block), None);
+		assert_eq!(Edid::parse(&block[..64]), None);
+	}
+}
@@ -0,0 +1,174 @@
+//! Monitor ids that stay the same across reconnects and shift restarts.
+//! - monitors with an EDID serial are keyed by connector type and EDID manufacturer, model and
+//!   serial, so they keep their id on another port of the same kind
+//! - without a serial, the connector stands in for it; without an EDID, the connector alone
+//! - ids are a hash of the key, recorded in `SHIFT_MONITOR_IDS` (default
+//!   `$XDG_STATE_HOME/shift/monitor-ids.json`) so they don't change with how they're derived
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	fs,
+	path::{Path, PathBuf},
+};
+
+use super::{DRM_DIR, Edid, MonitorId, connector_dir};
+
+const DEFAULT_STATE_DIR: &str = "/var/lib";
+
+#[derive(Debug)]
+pub struct MonitorIds {
+	path: PathBuf,
+	known: BTreeMap<String, MonitorId>,
+}
+
+impl MonitorIds {
+	/// Reads the ids recorded by earlier runs.
+	pub fn load() -> Self {
+		let path = std::env::var_os("SHIFT_MONITOR_IDS")
+			.map(PathBuf::from)
+			.unwrap_or_else(|| {
+				std::env::var_os("XDG_STATE_HOME")
+					.map_or_else(|| PathBuf::from(DEFAULT_STATE_DIR), PathBuf::from)
+					.join("shift/monitor-ids.json")
+			});
+		Self::load_from(path)
+	}
+
+	fn load_from(path: PathBuf) -> Self {
+		let known = match fs::read(&path) {
+			Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+				tracing::warn!(path = %path.display(), "ignoring malformed monitor ids: {e}");
+				BTreeMap::new()
+			}),
+			Err(_) => BTreeMap::new(),
+		};
+		Self { path, known }
+	}
+
+	/// The id of the monitor on a DRM connector, other than the ids in `in_use`.
+	pub fn for_connector(&mut self, connector_id: u32, in_use: &HashSet<MonitorId>) -> MonitorId {
+		let connector = connector_dir(Path::new(DRM_DIR), connector_id);
+		let connector = connector
+			.as_deref()
+			.and_then(|path| path.file_name()?.to_str()?.split_once('-'))
+			.map(|(_card, name)| name.to_string())
+			.unwrap_or_else(|| format!("connector-{connector_id}"));
+		let edid = Edid::for_connector(connector_id);
+		self.resolve(&monitor_key(&connector, edid.as_ref()), in_use)
+	}
+
+	fn resolve(&mut self, key: &str, in_use: &HashSet<MonitorId>) -> MonitorId {
+		// Two connected monitors with the same key, e.g. identical EDIDs, get `key#2` and so on.
+		for n in 1u32.. {
+			let key = match n {
+				1 => key.to_string(),
+				n => format!("{key}#{n}"),
+			};
+			let id = match self.known.get(&key) {
+				Some(id) => *id,
+				None => self.record(key),
+			};
+			if !in_use.contains(&id) {
+				return id;
+			}
+		}
+		unreachable!("every monitor key is in use")
+	}
+
+	fn record(&mut self, key: String) -> MonitorId {
+		let taken = self.known.values().copied().collect::<HashSet<_>>();
+		let mut id = MonitorId(u128::from(fnv1a(key.as_bytes())));
+		while taken.contains(&id) {
+			id = MonitorId(id.0.wrapping_add(1));
+		}
+		self.known.insert(key, id);
+		if let Err(e) = self.save() {
+			tracing::warn!("failed to save monitor ids: {e}");
+		}
+		id
+	}
+
+	fn save(&self) -> std::io::Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(&self.path, serde_json::to_vec_pretty(&self.known)?)
+	}
+}
+
+fn monitor_key(connector: &str, edid: Option<&Edid>) -> String {
+	let kind = connector
+		.rsplit_once('-')
+		.map_or(connector, |(kind, _index)| kind);
+	match edid {
+		Some(Edid {
+			manufacturer,
+			model,
+			serial: Some(serial),
+			..
+		}) => format!("{kind}/{manufacturer}/{model}/{serial}"),
+		Some(Edid {
+			manufacturer,
+			model,
+			..
+		}) => format!("{connector}/{manufacturer}/{model}"),
+		None => connector.to_string(),
+	}
+}
+
+/// 64-bit FNV-1a, which unlike `std`'s hashers is guaranteed to stay the same.
+fn fnv1a(bytes: &[u8]) -> u64 {
+	bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+		(hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn edid(serial: Option<&str>) -> Edid {
+		Edid {
+			manufacturer: "DEL".into(),
+			model: "DELL U2415".into(),
+			serial: serial.map(Into::into),
+			width_mm: None,
+			height_mm: None,
+		}
+	}
+
+	#[test]
+	fn keys_follow_the_monitor_when_it_has_a_serial() {
+		let serial = edid(Some("7MT0184Q1CJL"));
+		assert_eq!(
+			monitor_key("HDMI-A-1", Some(&serial)),
+			monitor_key("HDMI-A-2", Some(&serial))
+		);
+		assert_ne!(
+			monitor_key("HDMI-A-1", Some(&serial)),
+			monitor_key("DP-1", Some(&serial))
+		);
+		assert_eq!(
+			monitor_key("DP-2", Some(&edid(None))),
+			"DP-2/DEL/DELL U2415"
+		);
+		assert_eq!(monitor_key("eDP-1", None), "eDP-1");
+	}
+
+	#[test]
+	fn ids_survive_restarts_and_stay_unique() {
+		let path = std::env::temp_dir().join(format!("shift-monitor-ids-{}", rand::random::<u64>()));
+		let mut ids = MonitorIds::load_from(path.clone());
+		let first = ids.resolve("DP/DEL/U2415/1", &HashSet::new());
+		let twin = ids.resolve("DP/DEL/U2415/1", &HashSet::from([first]));
+		assert_ne!(first, twin);
+
+		let mut restarted = MonitorIds::load_from(path.clone());
+		assert_eq!(restarted.resolve("DP/DEL/U2415/1", &HashSet::new()), first);
+		assert_eq!(
+			restarted.resolve("DP/DEL/U2415/1", &HashSet::from([first])),
+			twin
+		);
+		fs::remove_file(path).unwrap();
+	}
+}
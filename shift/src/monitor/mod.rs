@@ -1,6 +1,22 @@
+use std::{
+	fs,
+	path::{Path, PathBuf},
+};
+
 use crate::define_id_type;
 use tab_protocol::MonitorInfo as ProtocolMonitorInfo;
 
+pub mod backlight;
+pub mod connector;
+pub mod edid;
+pub mod identity;
+pub use backlight::Backlight;
+pub use connector::{Connector, ConnectorProps};
+pub use edid::Edid;
+pub use identity::MonitorIds;
+
+const DRM_DIR: &str = "/sys/class/drm";
+
 define_id_type!(Monitor, "mon_");
 #[derive(Debug, Clone)]
 pub struct Monitor {
@@ -9,6 +25,9 @@ pub struct Monitor {
 	pub height: i32,
 	pub refresh_rate: u32,
 	pub name: String,
+	pub edid: Option<Edid>,
+	pub backlight: Option<Backlight>,
+	pub connector: ConnectorProps,
 }
 
 impl Monitor {
@@ -19,6 +38,23 @@ impl Monitor {
 			height: self.height,
 			refresh_rate: self.refresh_rate as i32,
 			name: self.name.clone(),
+			manufacturer: self.edid.as_ref().map(|edid| edid.manufacturer.clone()),
+			model: self.edid.as_ref().map(|edid| edid.model.clone()),
+			serial: self.edid.as_ref().and_then(|edid| edid.serial.clone()),
+			width_mm: self.edid.as_ref().and_then(|edid| edid.width_mm),
+			height_mm: self.edid.as_ref().and_then(|edid| edid.height_mm),
+			non_desktop: self.connector.non_desktop,
+			panel_orientation: self.connector.panel_orientation,
 		}
 	}
 }
+
+/// The `/sys/class/drm` directory of a connector, e.g. `card0-eDP-1`. `None` for kernels that
+/// don't expose `connector_id` in sysfs.
+fn connector_dir(drm: &Path, connector_id: u32) -> Option<PathBuf> {
+	fs::read_dir(drm).ok()?.flatten().find_map(|entry| {
+		let path = entry.path();
+		let id = fs::read_to_string(path.join("connector_id")).ok()?;
+		(id.trim().parse() == Ok(connector_id)).then_some(path)
+	})
+}
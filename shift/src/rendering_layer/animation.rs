@@ -1,22 +1,37 @@
+use std::collections::{BTreeMap, HashMap};
+
 use skia_safe::{
 	Canvas, FilterMode, Image, MipmapMode, Paint, Rect, SamplingOptions, TileMode, image_filters,
 };
+use tab_protocol::{TransitionDefinePayload, TransitionInfo, TransitionParam};
+
+use super::keyframes::KeyframeLayer;
 
 pub trait Animation: Send + Sync {
+	/// Numbers `session_switch` may tune, listed by `transitions_list`.
+	fn params(&self) -> Vec<TransitionParam> {
+		Vec::new()
+	}
+
+	/// `params` holds a value for every entry of [`Animation::params`].
+	#[allow(clippy::too_many_arguments)]
 	fn draw(
 		&self,
 		canvas: &Canvas,
 		old_image: &Image,
 		new_image: &Image,
 		progress: f64,
+		params: &BTreeMap<String, f64>,
 		width: f32,
 		height: f32,
 	);
 }
 
+/// Animations by the name `session_switch` uses. Registering under a taken name replaces the
+/// animation; the renderer reports the new catalog to the server after every change.
 #[derive(Default)]
 pub struct AnimationRegistry {
-	animations: std::collections::HashMap<String, Box<dyn Animation>>,
+	animations: HashMap<String, Box<dyn Animation>>,
 }
 
 impl AnimationRegistry {
@@ -34,6 +49,39 @@ impl AnimationRegistry {
 	pub fn get(&self, name: &str) -> Option<&dyn Animation> {
 		self.animations.get(name).map(|v| v.as_ref())
 	}
+
+	/// Every animation with its parameters, sorted by name.
+	pub fn catalog(&self) -> Vec<TransitionInfo> {
+		let mut catalog = self
+			.animations
+			.iter()
+			.map(|(name, animation)| TransitionInfo {
+				name: name.clone(),
+				params: animation.params(),
+			})
+			.collect::<Vec<_>>();
+		catalog.sort_by(|a, b| a.name.cmp(&b.name));
+		catalog
+	}
+
+	/// `overrides` on top of the defaults of `name`'s parameters. Unknown names are dropped.
+	pub fn resolve_params(
+		&self,
+		name: &str,
+		overrides: &BTreeMap<String, f64>,
+	) -> BTreeMap<String, f64> {
+		let Some(animation) = self.get(name) else {
+			return BTreeMap::new();
+		};
+		animation
+			.params()
+			.into_iter()
+			.map(|param| {
+				let value = overrides.get(&param.name).copied().unwrap_or(param.default);
+				(param.name, value)
+			})
+			.collect()
+	}
 }
 
 #[derive(Default)]
@@ -46,6 +94,7 @@ impl Animation for SlideLeftAnimation {
 		old_image: &Image,
 		new_image: &Image,
 		progress: f64,
+		_params: &BTreeMap<String, f64>,
 		width: f32,
 		height: f32,
 	) {
@@ -63,49 +112,91 @@ impl Animation for SlideLeftAnimation {
 	}
 }
 
+const BLUR_RADIUS: f64 = 60.0;
+
 #[derive(Default)]
 struct BlurBlendAnimation;
 
 impl Animation for BlurBlendAnimation {
+	fn params(&self) -> Vec<TransitionParam> {
+		vec![TransitionParam {
+			name: "radius".into(),
+			description: "blur radius at the midpoint, in pixels".into(),
+			default: BLUR_RADIUS,
+			min: 0.0,
+			max: 200.0,
+		}]
+	}
+
 	fn draw(
 		&self,
 		canvas: &Canvas,
 		old_image: &Image,
 		new_image: &Image,
 		progress: f64,
+		params: &BTreeMap<String, f64>,
 		width: f32,
 		height: f32,
 	) {
+		let radius = params.get("radius").copied().unwrap_or(BLUR_RADIUS) as f32;
 		let t = progress.clamp(0.0, 1.0) as f32;
 		let phase = if t < 0.5 { 0 } else { 1 };
 		let local_t = if phase == 0 { t * 2.0 } else { (t - 0.5) * 2.0 };
 
+		let rect = Rect::from_wh(width, height);
 		if phase == 0 {
 			// Blur the old frame out.
-			draw_blurred_image(canvas, old_image, width, height, 60.0 * local_t, 1.0);
+			draw_blurred_image(canvas, old_image, rect, radius * local_t, 1.0);
 		} else {
 			// Bring in the new frame blurred, then sharpen it.
-			draw_blurred_image(
-				canvas,
-				new_image,
+			draw_blurred_image(canvas, new_image, rect, radius * (1.0 - local_t), 1.0);
+		}
+	}
+}
+
+/// An animation defined at runtime with `transition_define`.
+pub struct KeyframeAnimation {
+	from: KeyframeLayer,
+	to: KeyframeLayer,
+}
+
+impl KeyframeAnimation {
+	pub fn new(payload: TransitionDefinePayload) -> Self {
+		Self {
+			from: KeyframeLayer::new(payload.from),
+			to: KeyframeLayer::new(payload.to),
+		}
+	}
+}
+
+impl Animation for KeyframeAnimation {
+	fn draw(
+		&self,
+		canvas: &Canvas,
+		old_image: &Image,
+		new_image: &Image,
+		progress: f64,
+		_params: &BTreeMap<String, f64>,
+		width: f32,
+		height: f32,
+	) {
+		for (layer, image) in [(&self.from, old_image), (&self.to, new_image)] {
+			let state = layer.sample(progress);
+			if state.opacity <= 0.0 {
+				continue;
+			}
+			let rect = Rect::from_xywh(
+				state.translate_x * width,
+				state.translate_y * height,
 				width,
 				height,
-				60.0 * (1.0 - local_t),
-				1.0,
 			);
+			draw_blurred_image(canvas, image, rect, state.blur, state.opacity);
 		}
 	}
 }
 
-fn draw_blurred_image(
-	canvas: &Canvas,
-	image: &Image,
-	width: f32,
-	height: f32,
-	radius: f32,
-	alpha: f32,
-) {
-	let rect = Rect::from_wh(width, height);
+fn draw_blurred_image(canvas: &Canvas, image: &Image, rect: Rect, radius: f32, alpha: f32) {
 	let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::Linear);
 	let clamped_alpha = alpha.clamp(0.0, 1.0);
 	let mut paint = Paint::default();
@@ -117,3 +208,9 @@ fn draw_blurred_image(
 	}
 	canvas.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
 }
+
+/// Smoothstep easing for progress values in `0.0..=1.0`.
+pub fn ease_in_out(t: f32) -> f32 {
+	let t = t.clamp(0.0, 1.0);
+	t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,63 @@
+use skia_safe::{Canvas, Color, Data, Image, Paint, Rect, SamplingOptions};
+
+use crate::comms::server2render::Background;
+
+/// Drawn first on every monitor, beneath session buffers.
+pub struct BackgroundLayer {
+	color: Color,
+	/// Decoded once when set, then scaled to cover each monitor.
+	image: Option<Image>,
+}
+
+impl BackgroundLayer {
+	pub fn new() -> Self {
+		Self {
+			color: Color::BLACK,
+			image: None,
+		}
+	}
+
+	pub fn set(&mut self, background: Background) {
+		match background {
+			Background::Color([r, g, b]) => {
+				self.color = Color::from_rgb(r, g, b);
+				self.image = None;
+			}
+			Background::Image(path) => {
+				let image = std::fs::read(&path)
+					.map_err(|e| e.to_string())
+					.and_then(|bytes| {
+						Image::from_encoded(Data::new_copy(&bytes)).ok_or_else(|| "unsupported image".into())
+					});
+				match image {
+					Ok(image) => self.image = Some(image),
+					// The previous background stays up rather than flashing to black.
+					Err(e) => tracing::warn!(path = %path.display(), "failed to load background image: {e}"),
+				}
+			}
+		}
+	}
+
+	pub fn draw(&self, canvas: &Canvas, width: f32, height: f32) {
+		canvas.clear(self.color);
+		let Some(image) = &self.image else {
+			return;
+		};
+		// Cover: scale to fill the monitor, keep the aspect ratio, crop the overflow evenly.
+		let scale = (width / image.width() as f32).max(height / image.height() as f32);
+		let (draw_width, draw_height) = (image.width() as f32 * scale, image.height() as f32 * scale);
+		let rect = Rect::from_xywh(
+			(width - draw_width) / 2.0,
+			(height - draw_height) / 2.0,
+			draw_width,
+			draw_height,
+		);
+		canvas.draw_image_rect_with_sampling_options(
+			image,
+			None,
+			rect,
+			SamplingOptions::default(),
+			&Paint::default(),
+		);
+	}
+}
@@ -1,4 +1,5 @@
 use crate::comms::{
+	queues,
 	render2server::{RenderEvtRx, RenderEvtTx},
 	server2render::{RenderCmdRx, RenderCmdTx},
 };
@@ -66,6 +67,14 @@ impl Channels {
 		Self::with_capacity(DEFAULT_CHANNEL_CAPACITY)
 	}
 
+	/// Capacity from `SHIFT_RENDER_QUEUE`, see [`queues`].
+	pub fn from_env() -> Self {
+		Self::with_capacity(queues::capacity_from_env(
+			"SHIFT_RENDER_QUEUE",
+			DEFAULT_CHANNEL_CAPACITY,
+		))
+	}
+
 	pub fn with_capacity(capacity: usize) -> Self {
 		let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(capacity);
 		let (evt_tx, evt_rx) = tokio::sync::mpsc::channel(capacity);
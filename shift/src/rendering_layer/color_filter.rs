@@ -0,0 +1,143 @@
+//! Accessibility color filters of each monitor, set by `monitor_filter`.
+//! - a filter is a color matrix applied to everything the monitor shows but the debug HUD
+//! - filters are recorded in `SHIFT_MONITOR_FILTERS` (default
+//!   `$XDG_STATE_HOME/shift/monitor-filters.json`) by monitor id, so they apply again when the
+//!   monitor is plugged back in or shift restarts
+
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use tab_protocol::MonitorFilter;
+
+use crate::monitor::MonitorId;
+
+const DEFAULT_STATE_DIR: &str = "/var/lib";
+
+#[derive(Debug)]
+pub struct ColorFilters {
+	path: PathBuf,
+	/// Filter of each monitor by its id, leaving out monitors without one.
+	saved: BTreeMap<String, MonitorFilter>,
+}
+
+impl ColorFilters {
+	/// Reads the filters recorded by earlier runs.
+	pub fn load() -> Self {
+		let path = std::env::var_os("SHIFT_MONITOR_FILTERS")
+			.map(PathBuf::from)
+			.unwrap_or_else(|| {
+				std::env::var_os("XDG_STATE_HOME")
+					.map_or_else(|| PathBuf::from(DEFAULT_STATE_DIR), PathBuf::from)
+					.join("shift/monitor-filters.json")
+			});
+		Self::load_from(path)
+	}
+
+	fn load_from(path: PathBuf) -> Self {
+		let saved = match fs::read(&path) {
+			Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+				tracing::warn!(path = %path.display(), "ignoring malformed monitor filters: {e}");
+				BTreeMap::new()
+			}),
+			Err(_) => BTreeMap::new(),
+		};
+		Self { path, saved }
+	}
+
+	pub fn get(&self, monitor_id: MonitorId) -> MonitorFilter {
+		self
+			.saved
+			.get(&monitor_id.to_string())
+			.copied()
+			.unwrap_or(MonitorFilter::None)
+	}
+
+	pub fn set(&mut self, monitor_id: MonitorId, filter: MonitorFilter) {
+		if filter == MonitorFilter::None {
+			self.saved.remove(&monitor_id.to_string());
+		} else {
+			self.saved.insert(monitor_id.to_string(), filter);
+		}
+		if let Err(e) = self.save() {
+			tracing::warn!("failed to save monitor filters: {e}");
+		}
+	}
+
+	fn save(&self) -> std::io::Result<()> {
+		if let Some(dir) = self.path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(&self.path, serde_json::to_vec_pretty(&self.saved)?)
+	}
+}
+
+/// Row-major 4x5 RGBA color matrix of `filter`, on colors and offsets from 0 to 1.
+pub fn color_matrix(filter: MonitorFilter) -> Option<[f32; 20]> {
+	#[rustfmt::skip]
+	let matrix = match filter {
+		MonitorFilter::None => return None,
+		// Rec. 709 luma.
+		MonitorFilter::Grayscale => [
+			0.2126, 0.7152, 0.0722, 0.0, 0.0,
+			0.2126, 0.7152, 0.0722, 0.0, 0.0,
+			0.2126, 0.7152, 0.0722, 0.0, 0.0,
+			0.0, 0.0, 0.0, 1.0, 0.0,
+		],
+		MonitorFilter::Invert => [
+			-1.0, 0.0, 0.0, 0.0, 1.0,
+			0.0, -1.0, 0.0, 0.0, 1.0,
+			0.0, 0.0, -1.0, 0.0, 1.0,
+			0.0, 0.0, 0.0, 1.0, 0.0,
+		],
+		// Machado, Oliveira and Fernandes (2009), severity 1.
+		MonitorFilter::Deuteranopia => [
+			0.367322, 0.860646, -0.227968, 0.0, 0.0,
+			0.280085, 0.672501, 0.047413, 0.0, 0.0,
+			-0.011820, 0.042940, 0.968881, 0.0, 0.0,
+			0.0, 0.0, 0.0, 1.0, 0.0,
+		],
+	};
+	Some(matrix)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn apply(matrix: &[f32; 20], rgba: [f32; 4]) -> [f32; 4] {
+		std::array::from_fn(|row| {
+			let m = &matrix[row * 5..row * 5 + 5];
+			m[0] * rgba[0] + m[1] * rgba[1] + m[2] * rgba[2] + m[3] * rgba[3] + m[4]
+		})
+	}
+
+	#[test]
+	fn filters_keep_white_and_alpha_where_they_should() {
+		let white = [1.0, 1.0, 1.0, 1.0];
+		for filter in [MonitorFilter::Grayscale, MonitorFilter::Deuteranopia] {
+			let out = apply(&color_matrix(filter).unwrap(), white);
+			assert!(
+				out.iter().all(|c| (c - 1.0).abs() < 1e-4),
+				"{filter:?}: {out:?}"
+			);
+		}
+		let invert = color_matrix(MonitorFilter::Invert).unwrap();
+		assert_eq!(apply(&invert, [1.0, 0.25, 0.0, 0.5]), [0.0, 0.75, 1.0, 0.5]);
+		assert_eq!(color_matrix(MonitorFilter::None), None);
+	}
+
+	#[test]
+	fn filters_survive_restarts() {
+		let path =
+			std::env::temp_dir().join(format!("shift-monitor-filters-{}", rand::random::<u64>()));
+		let (laptop, projector) = (MonitorId::rand(), MonitorId::rand());
+		let mut filters = ColorFilters::load_from(path.clone());
+		filters.set(laptop, MonitorFilter::Grayscale);
+		filters.set(projector, MonitorFilter::Invert);
+		filters.set(projector, MonitorFilter::None);
+
+		let restarted = ColorFilters::load_from(path.clone());
+		assert_eq!(restarted.get(laptop), MonitorFilter::Grayscale);
+		assert_eq!(restarted.get(projector), MonitorFilter::None);
+		fs::remove_file(path).unwrap();
+	}
+}
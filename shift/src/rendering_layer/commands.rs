@@ -3,15 +3,21 @@ use std::{
 	sync::Arc,
 };
 
-use crate::comms::server2render::RenderCmd;
+use skia_safe::{AlphaType, ColorType, Data, ImageInfo, images};
 
-use super::dmabuf_import::{DmaBufTexture, ImportParams as DmaBufImportParams};
-use super::state::BufferSlot;
+use crate::comms::{correlation, server2render::RenderCmd};
+
+use super::animation::KeyframeAnimation;
+use super::dmabuf_import::{self, DmaBufTexture, ImportParams as DmaBufImportParams};
+use super::present_group::HeldSwap;
+use super::skia_texture::SkiaDmaBufTexture;
+use super::state::{BufferSlot, DeferredLink, SlotOwner};
+use super::zoom::ZoomView;
 use super::{RenderError, RenderEvt, RenderingLayer, SlotKey};
 
 impl RenderingLayer {
 	#[tracing::instrument(skip_all, fields(session_id = %session_id, monitor_id = %payload.monitor_id))]
-	pub(super) fn import_framebuffers(
+	pub(super) async fn import_framebuffers(
 		&mut self,
 		payload: tab_protocol::FramebufferLinkPayload,
 		dma_bufs: [OwnedFd; 2],
@@ -21,68 +27,209 @@ impl RenderingLayer {
 			tracing::warn!(monitor_id = %payload.monitor_id, "invalid monitor id in framebuffer link");
 			return;
 		};
+		// Like the server, the generation moves on even if the import fails below. The relink hands
+		// both buffers back, so releases still queued for the previous link are dropped.
+		self
+			.link_generations
+			.insert((monitor_id, session_id), payload.generation);
+		self
+			.ownership
+			.drop_deferred_releases(monitor_id, session_id);
 
-		let mut imported = Vec::new();
-		let mut found_monitor = false;
-		let egl_context = self.drm.egl_context();
-		for mon in self.drm.monitors_mut() {
-			if mon.context().id != monitor_id {
-				continue;
-			}
-			found_monitor = true;
-			if let Err(e) = mon.make_current() {
-				tracing::warn!(%monitor_id, "failed to make monitor current: {e:?}");
-				break;
-			}
-			let gl = mon.context().gl.clone();
-			let proc_loader = |symbol: &str| {
-				egl_context
-					.lock()
-					.map(|ctx| ctx.get_proc_address(symbol))
-					.unwrap_or(std::ptr::null())
-			};
-			for (idx, fd) in dma_bufs.into_iter().enumerate() {
-				let Some(slot) = BufferSlot::from_index(idx) else {
-					continue;
-				};
-				let params = DmaBufImportParams {
-					width: payload.width,
-					height: payload.height,
-					stride: payload.stride,
-					offset: payload.offset,
-					fourcc: payload.fourcc,
-					fd,
-				};
-				match DmaBufTexture::import(&gl, &proc_loader, params).and_then(|texture| {
-					texture.to_skia(format!(
-						"session_{}_monitor_{}_buffer_{}",
-						session_id, monitor_id, idx
-					))
-				}) {
-					Ok(texture) => imported.push((slot, texture)),
-					Err(e) => {
-						tracing::warn!(%monitor_id, ?slot, "failed to import dmabuf: {e:?}");
-					}
-				}
-			}
-			break;
+		if self.known_monitors.contains_key(&monitor_id) && !self.shown_sessions().contains(&session_id)
+		{
+			self.defer_link(monitor_id, session_id, payload, dma_bufs);
+			return;
 		}
-
-		if !found_monitor {
+		let Some(result) = self.import_textures(monitor_id, session_id, &payload, dma_bufs) else {
 			tracing::warn!(%monitor_id, "framebuffer link for unknown monitor");
 			return;
-		}
+		};
+		// Link both buffers or neither, so the client can reallocate the whole swapchain.
+		let imported = match result {
+			Ok(imported) => imported,
+			Err((reason, supported_formats)) => {
+				self.emit_event(RenderEvt::FramebufferLinkFailed {
+					session_id,
+					monitor_id,
+					reason,
+					supported_formats,
+				});
+				return;
+			}
+		};
 
+		self.deferred_links.remove(&(monitor_id, session_id));
 		for (slot, texture) in imported {
 			let key = SlotKey::new(monitor_id, session_id, slot);
+			self.uploaded_slots.remove(&key);
 			self.slots.insert(key, texture);
 			self.ownership.mark_slot_client_owned(key);
 		}
+		self
+			.ownership
+			.set_present_mode(monitor_id, session_id, payload.present_mode);
+		self.gpu_budget.touch(session_id, std::time::Instant::now());
+	}
+
+	/// Keeps the dma-bufs of a session that isn't on screen without importing them. The link takes
+	/// effect right away, the EGL import happens in [`Self::import_deferred_links`]. Until then a
+	/// retained front buffer keeps its old import, so switching back still shows it.
+	fn defer_link(
+		&mut self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+		payload: tab_protocol::FramebufferLinkPayload,
+		dma_bufs: [OwnedFd; 2],
+	) {
+		let retained = self.retained_fronts();
+		for slot in [BufferSlot::Zero, BufferSlot::One] {
+			let key = SlotKey::new(monitor_id, session_id, slot);
+			if !retained.contains(&key) {
+				self.slots.remove(&key);
+			}
+			self.uploaded_slots.remove(&key);
+			self.ownership.mark_slot_client_owned(key);
+		}
+		self
+			.ownership
+			.set_present_mode(monitor_id, session_id, payload.present_mode);
+		tracing::debug!(%monitor_id, %session_id, "deferring dmabuf import until the session is shown");
+		self
+			.deferred_links
+			.insert((monitor_id, session_id), DeferredLink { payload, dma_bufs });
+	}
+
+	/// Imports the deferred links of sessions that are now drawn. Import failures are reported
+	/// with `framebuffer_link_failed` as if the link had just been sent.
+	pub(super) async fn import_deferred_links(&mut self) {
+		if self.deferred_links.is_empty() {
+			return;
+		}
+		let shown = self.shown_sessions();
+		let ready = self
+			.deferred_links
+			.keys()
+			.filter(|(_, session_id)| shown.contains(session_id))
+			.copied()
+			.collect::<Vec<_>>();
+		for (monitor_id, session_id) in ready {
+			let Some(link) = self.deferred_links.remove(&(monitor_id, session_id)) else {
+				continue;
+			};
+			match self.import_textures(monitor_id, session_id, &link.payload, link.dma_bufs) {
+				// Ownership was settled at link time and may have moved on since.
+				Some(Ok(imported)) => {
+					for (slot, texture) in imported {
+						self
+							.slots
+							.insert(SlotKey::new(monitor_id, session_id, slot), texture);
+					}
+					self.gpu_budget.touch(session_id, std::time::Instant::now());
+				}
+				Some(Err((reason, supported_formats))) => {
+					self.emit_event(RenderEvt::FramebufferLinkFailed {
+						session_id,
+						monitor_id,
+						reason,
+						supported_formats,
+					});
+				}
+				None => {}
+			}
+		}
+	}
+
+	/// Imports both dma-bufs of a link as textures of `monitor_id`. `None` if the monitor is gone,
+	/// otherwise the textures or why they couldn't be imported, with the formats that can.
+	fn import_textures(
+		&mut self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+		payload: &tab_protocol::FramebufferLinkPayload,
+		dma_bufs: [OwnedFd; 2],
+	) -> Option<Result<Vec<(BufferSlot, SkiaDmaBufTexture)>, (String, Vec<i32>)>> {
+		let egl_context = self.drm.egl_context();
+		let mon = self
+			.drm
+			.monitors_mut()
+			.find(|mon| mon.context().id == monitor_id)?;
+		let mut imported = Vec::new();
+		if let Err(e) = mon.make_current() {
+			tracing::warn!(%monitor_id, "failed to make monitor current: {e:?}");
+			return Some(Ok(imported));
+		}
+		let gl = mon.context().gl.clone();
+		let proc_loader = |symbol: &str| {
+			egl_context
+				.lock()
+				.map(|ctx| ctx.get_proc_address(symbol))
+				.unwrap_or(std::ptr::null())
+		};
+		for (idx, fd) in dma_bufs.into_iter().enumerate() {
+			let Some(slot) = BufferSlot::from_index(idx) else {
+				continue;
+			};
+			let params = DmaBufImportParams {
+				width: payload.width,
+				height: payload.height,
+				stride: payload.stride,
+				offset: payload.offset,
+				fourcc: payload.fourcc,
+				modifier: payload.modifier,
+				fd,
+			};
+			match DmaBufTexture::import(&gl, &proc_loader, params).and_then(|texture| {
+				texture.to_skia(format!(
+					"session_{}_monitor_{}_buffer_{}",
+					session_id, monitor_id, idx
+				))
+			}) {
+				Ok(texture) => imported.push((slot, texture)),
+				Err(e) => {
+					tracing::warn!(%monitor_id, ?slot, fourcc = payload.fourcc, "failed to import dmabuf: {e:?}");
+					return Some(Err((
+						e.to_string(),
+						dmabuf_import::supported_formats(&proc_loader),
+					)));
+				}
+			}
+		}
+		Some(Ok(imported))
+	}
+
+	/// Hands back a buffer that was superseded before it was ever shown, so no fence is needed.
+	pub(super) async fn release_unshown(
+		&mut self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+		buffer: BufferSlot,
+	) {
+		let key = SlotKey::new(monitor_id, session_id, buffer);
+		self.ownership.mark_slot_client_owned(key);
+		self.emit_frame_dropped(monitor_id, session_id);
+		self.emit_event(self.buffer_consumed(key, None));
+	}
+
+	fn emit_frame_dropped(
+		&mut self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+	) {
+		self.emit_event(RenderEvt::FrameDropped {
+			session_id,
+			monitor_id,
+			reason: self.ownership.drop_reason(monitor_id, session_id),
+		});
 	}
 
 	pub(super) async fn process_deferred_releases(&mut self, release_fence: i32) {
 		for item in self.ownership.take_deferred_releases() {
 			let key = SlotKey::new(item.monitor_id, item.session_id, item.buffer);
+			// A retained front buffer a relink already handed back.
+			if self.ownership.owner(key) == Some(SlotOwner::ClientOwned) {
+				continue;
+			}
 			self.ownership.mark_slot_client_owned(key);
 			let release_fence = if release_fence >= 0 {
 				let dup_fd = unsafe { libc::dup(release_fence) };
@@ -94,18 +241,11 @@ impl RenderingLayer {
 			} else {
 				None
 			};
-			self
-				.emit_event(RenderEvt::BufferConsumed {
-					session_id: item.session_id,
-					monitor_id: item.monitor_id,
-					buffer: item.buffer.into(),
-					release_fence,
-				})
-				.await;
+			self.emit_event(self.buffer_consumed(key, release_fence));
 		}
 	}
 
-	#[tracing::instrument(skip_all)]
+	#[tracing::instrument(skip_all, fields(corr = correlation::span_field(self.corr)))]
 	pub(super) async fn handle_command(&mut self, cmd: RenderCmd) -> Result<bool, RenderError> {
 		match cmd {
 			RenderCmd::Shutdown => {
@@ -117,7 +257,39 @@ impl RenderingLayer {
 				dma_bufs,
 				session_id,
 			} => {
-				self.import_framebuffers(payload, dma_bufs, session_id);
+				self
+					.import_framebuffers(payload, dma_bufs, session_id)
+					.await;
+			}
+			RenderCmd::BufferUpload {
+				monitor_id,
+				buffer,
+				session_id,
+				width,
+				height,
+				stride,
+				pixels,
+			} => {
+				if !self.known_monitors.contains_key(&monitor_id) {
+					tracing::warn!(%monitor_id, "buffer upload for unknown monitor");
+					return Ok(true);
+				}
+				let info = ImageInfo::new(
+					(width, height),
+					ColorType::BGRA8888,
+					AlphaType::Premul,
+					None,
+				);
+				let Some(image) = images::raster_from_data(&info, Data::new_copy(&pixels), stride as usize)
+				else {
+					tracing::warn!(%monitor_id, width, height, stride, "failed to wrap uploaded pixels");
+					return Ok(true);
+				};
+				let key = SlotKey::new(monitor_id, session_id, BufferSlot::from(buffer));
+				self.slots.remove(&key);
+				if self.uploaded_slots.insert(key, image).is_none() {
+					self.ownership.mark_slot_client_owned(key);
+				}
 			}
 			RenderCmd::SetActiveSession {
 				session_id,
@@ -127,75 +299,313 @@ impl RenderingLayer {
 				if let Some(to_session_id) = session_id
 					&& let Some(transition) = transition
 				{
-					self.active_transition = super::ActiveTransition::from_cmd(to_session_id, transition);
+					self.active_transition =
+						super::ActiveTransition::from_cmd(to_session_id, transition, &self.animations);
 				}
 				self.ownership.set_current_session(session_id);
 			}
-			RenderCmd::SessionRemoved { session_id } => {
-				self.cleanup_session_slots(session_id);
+			RenderCmd::DefineTransition(payload) => {
+				tracing::info!(name = %payload.name, "transition defined");
+				self.animations.register(
+					payload.name.clone(),
+					Box::new(KeyframeAnimation::new(payload)),
+				);
+				self.publish_transitions();
+			}
+			RenderCmd::SetPip {
+				monitor_id,
+				overlay,
+			} => match overlay {
+				Some(overlay) => {
+					self.pip_overlays.insert(monitor_id, overlay);
+				}
+				None => {
+					self.pip_overlays.remove(&monitor_id);
+				}
+			},
+			RenderCmd::SetMonitorLayout {
+				monitor_id,
+				regions,
+			} => {
+				if regions.is_empty() {
+					self.monitor_layouts.remove(&monitor_id);
+				} else {
+					self.monitor_layouts.insert(monitor_id, regions);
+				}
+			}
+			RenderCmd::AssignMonitor {
+				monitor_id,
+				session_id,
+			} => {
+				self.ownership.set_monitor_session(monitor_id, session_id);
+			}
+			RenderCmd::SetZoom {
+				monitor_id,
+				factor,
+				center,
+			} => {
+				let view = ZoomView { factor, center };
+				self.zooms.set(monitor_id, view, std::time::Instant::now());
+			}
+			RenderCmd::SetMonitorFilter { monitor_id, filter } => {
+				self.color_filters.set(monitor_id, filter);
+			}
+			RenderCmd::ZoomFocus { monitor_id, center } => {
+				self.zooms.focus(monitor_id, center);
+			}
+			RenderCmd::SetMirror {
+				monitor_id,
+				source_monitor_id,
+			} => {
+				self.mirrors.set(monitor_id, source_monitor_id);
+				self
+					.mirror_frames
+					.retain(|source, _| self.mirrors.is_source(*source));
+			}
+			RenderCmd::SetBackground(background) => {
+				self.background.set(background);
+			}
+			RenderCmd::DimSession { session_id, amount } => match amount {
+				Some(amount) => {
+					self.dimmed_sessions.insert(session_id, amount);
+				}
+				None => {
+					self.dimmed_sessions.remove(&session_id);
+				}
+			},
+			RenderCmd::SetDebugHud { enabled } => {
+				self.hud.set_enabled(enabled);
+			}
+			RenderCmd::HudStats(stats) => {
+				self.hud.set_stats(stats);
+			}
+			RenderCmd::Screenshot {
+				request,
+				monitor_id,
+			} => {
+				if !self.known_monitors.contains_key(&monitor_id) {
+					self.emit_event(RenderEvt::Screenshot {
+						request,
+						result: Err("monitor is not being rendered".into()),
+					});
+					return Ok(true);
+				}
+				self
+					.pending_screenshots
+					.entry(monitor_id)
+					.or_default()
+					.push(request);
+			}
+			RenderCmd::Suspend => self.suspend(),
+			RenderCmd::Resume => self.resume().await,
+			RenderCmd::SessionRemoved { session_id, grace } => {
+				let showing = self
+					.known_monitors
+					.keys()
+					.copied()
+					.filter(|monitor_id| {
+						self.ownership.session_for_monitor(*monitor_id) == Some(session_id)
+							&& self
+								.ownership
+								.current_slot_key_for_session(*monitor_id, session_id)
+								.is_some()
+					})
+					.collect::<Vec<_>>();
 				if self.ownership.current_session() == Some(session_id) {
 					self.ownership.set_current_session(None);
 				}
+				if self
+					.crash_grace
+					.freeze(session_id, showing, grace, std::time::Instant::now())
+				{
+					tracing::info!(%session_id, grace_ms = grace.as_millis() as u64, "keeping the crashed session's last frame");
+					self.freeze_session(session_id);
+				} else {
+					self.cleanup_session_slots(session_id);
+					self.dimmed_sessions.remove(&session_id);
+				}
 			}
 			RenderCmd::SwapBuffers {
 				monitor_id,
 				buffer,
 				session_id,
+				generation,
 				acquire_fence,
+				present_group,
 			} => {
-				let slot = BufferSlot::from(buffer);
-				let monitor_known = self.known_monitors.contains_key(&monitor_id);
-				let slot_key = SlotKey::new(monitor_id, session_id, slot);
-				let slot_known = self.slots.contains_key(&slot_key);
-				if !monitor_known || !slot_known {
-					let reason: Arc<str> = if !monitor_known {
-						"unknown_monitor"
-					} else {
-						"unlinked_buffer"
-					}
-					.into();
-					self
-						.emit_event(RenderEvt::BufferRequestRejected {
-							session_id,
-							monitor_id,
-							buffer,
-							reason,
-						})
-						.await;
-				} else {
-					let has_acquire_fence = acquire_fence.is_some();
-					let transition =
-						self
-							.ownership
-							.apply_swap_request(monitor_id, session_id, slot, has_acquire_fence);
-					if let Some(pending) = transition.canceled_pending {
-						let pending_key = SlotKey::new(monitor_id, session_id, pending);
-						self.cancel_fence_wait(pending_key);
-						self
-							.ownership
-							.queue_buffer_release(monitor_id, session_id, pending);
-					}
-					if let Some(fence_fd) = acquire_fence {
-						self.spawn_acquire_fence_waiter(slot_key, fence_fd);
-					} else {
-						self.cancel_fence_wait(slot_key);
-					}
-					if let Some(previous) = transition.previous_to_release {
-						self
-							.ownership
-							.queue_buffer_release(monitor_id, session_id, previous);
-					}
+				if let Some(reason) = self.swap_rejection(monitor_id, session_id, buffer, generation) {
+					self.emit_event(RenderEvt::BufferRequestRejected {
+						session_id,
+						monitor_id,
+						buffer,
+						generation,
+						reason,
+					});
+					return Ok(true);
+				}
+				let Some(group) = present_group else {
 					self
-						.emit_event(RenderEvt::BufferRequestAck {
-							session_id,
-							monitor_id,
-							buffer,
-						})
+						.apply_swap(monitor_id, session_id, buffer, generation, acquire_fence)
 						.await;
+					return Ok(true);
+				};
+				let swap = HeldSwap {
+					monitor_id,
+					buffer,
+					generation,
+					acquire_fence,
+					corr: self.corr,
+				};
+				match self.present_groups.hold(session_id, group, swap) {
+					Ok(None) => {}
+					Ok(Some(swaps)) => self.flip_group(session_id, swaps).await,
+					Err(swap) => self.reject_held(session_id, swap, "unknown_present_group"),
+				}
+			}
+			RenderCmd::PresentGroup {
+				session_id,
+				group,
+				monitors,
+			} => {
+				for swap in self.present_groups.define(session_id, group, monitors) {
+					self.reject_held(session_id, swap, "present_group_changed");
 				}
 			}
 		}
 
 		Ok(true)
 	}
+
+	/// Why a swap can't be taken, if it can't.
+	fn swap_rejection(
+		&self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+		buffer: tab_protocol::BufferIndex,
+		generation: u64,
+	) -> Option<Arc<str>> {
+		let slot_key = SlotKey::new(monitor_id, session_id, BufferSlot::from(buffer));
+		let reason = if !self.known_monitors.contains_key(&monitor_id) {
+			"unknown_monitor"
+		} else if generation != self.link_generation(monitor_id, session_id) {
+			"stale_generation"
+		} else if !self.slots.contains_key(&slot_key)
+			&& !self.uploaded_slots.contains_key(&slot_key)
+			&& !self.deferred_links.contains_key(&(monitor_id, session_id))
+		{
+			"unlinked_buffer"
+		} else {
+			return None;
+		};
+		Some(reason.into())
+	}
+
+	async fn apply_swap(
+		&mut self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+		buffer: tab_protocol::BufferIndex,
+		generation: u64,
+		acquire_fence: Option<OwnedFd>,
+	) {
+		let slot = BufferSlot::from(buffer);
+		let slot_key = SlotKey::new(monitor_id, session_id, slot);
+		let has_acquire_fence = acquire_fence.is_some();
+		let transition =
+			self
+				.ownership
+				.apply_swap_request(monitor_id, session_id, slot, has_acquire_fence);
+		if let Some(pending) = transition.canceled_pending {
+			let pending_key = SlotKey::new(monitor_id, session_id, pending);
+			self.cancel_fence_wait(pending_key);
+			self.emit_frame_dropped(monitor_id, session_id);
+			self
+				.ownership
+				.queue_buffer_release(monitor_id, session_id, pending);
+		}
+		self.stuck_frames.record_submission(monitor_id, session_id);
+		if let Some(corr) = self.corr {
+			self.swap_corrs.insert(slot_key, corr);
+		}
+		self.swap_generations.insert(slot_key, generation);
+		if let Some(fence_fd) = acquire_fence {
+			self.spawn_acquire_fence_waiter(slot_key, fence_fd);
+		} else {
+			self.cancel_fence_wait(slot_key);
+		}
+		if let Some(previous) = transition.previous_to_release {
+			self
+				.ownership
+				.queue_buffer_release(monitor_id, session_id, previous);
+		}
+		if let Some(superseded) = transition.release_now {
+			self
+				.release_unshown(monitor_id, session_id, superseded)
+				.await;
+		}
+		self.emit_event(RenderEvt::BufferRequestAck {
+			session_id,
+			monitor_id,
+			buffer,
+			generation,
+		});
+	}
+
+	/// Swaps in a complete present group at once, and holds its monitors back until all of them
+	/// can show it, see [`super::present_group`].
+	async fn flip_group(&mut self, session_id: crate::sessions::SessionId, swaps: Vec<HeldSwap>) {
+		let corr = self.corr;
+		let mut slots = Vec::with_capacity(swaps.len());
+		for swap in swaps {
+			// A relink may have come in while it was held.
+			if let Some(reason) =
+				self.swap_rejection(swap.monitor_id, session_id, swap.buffer, swap.generation)
+			{
+				self.corr = swap.corr;
+				self.emit_event(RenderEvt::BufferRequestRejected {
+					session_id,
+					monitor_id: swap.monitor_id,
+					buffer: swap.buffer,
+					generation: swap.generation,
+					reason,
+				});
+				continue;
+			}
+			self.corr = swap.corr;
+			slots.push(SlotKey::new(
+				swap.monitor_id,
+				session_id,
+				BufferSlot::from(swap.buffer),
+			));
+			self
+				.apply_swap(
+					swap.monitor_id,
+					session_id,
+					swap.buffer,
+					swap.generation,
+					swap.acquire_fence,
+				)
+				.await;
+		}
+		self.corr = corr;
+		self.present_groups.lock(slots, std::time::Instant::now());
+	}
+
+	/// Fails a request a present group held, which stayed pending with the server.
+	pub(super) fn reject_held(
+		&mut self,
+		session_id: crate::sessions::SessionId,
+		swap: HeldSwap,
+		reason: &str,
+	) {
+		let corr = std::mem::replace(&mut self.corr, swap.corr);
+		self.emit_event(RenderEvt::BufferRequestRejected {
+			session_id,
+			monitor_id: swap.monitor_id,
+			buffer: swap.buffer,
+			generation: swap.generation,
+			reason: reason.into(),
+		});
+		self.corr = corr;
+	}
 }
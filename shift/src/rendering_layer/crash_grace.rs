@@ -0,0 +1,120 @@
+//! Monitors still showing the last frame of a session whose client crashed, see
+//! `SHIFT_CRASH_GRACE_MS`. The session's buffers stay imported until no monitor shows it.
+
+use std::{
+	collections::{HashMap, HashSet},
+	time::{Duration, Instant},
+};
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+/// How much the frozen frame is darkened, like a dimmed unresponsive session.
+pub const CRASH_DIM: f32 = 0.6;
+
+struct Frozen {
+	session_id: SessionId,
+	until: Instant,
+}
+
+#[derive(Default)]
+pub struct CrashGrace {
+	frozen: HashMap<MonitorId, Frozen>,
+	/// Crashed sessions whose buffers are kept, including ones no monitor shows any more.
+	sessions: HashSet<SessionId>,
+}
+
+impl CrashGrace {
+	/// Keeps `session_id`'s last frame on `monitor_ids` for `grace`. Returns false if there is
+	/// nothing to keep, so the caller can drop the session right away.
+	pub fn freeze(
+		&mut self,
+		session_id: SessionId,
+		monitor_ids: impl IntoIterator<Item = MonitorId>,
+		grace: Duration,
+		now: Instant,
+	) -> bool {
+		if grace.is_zero() {
+			return false;
+		}
+		let until = now + grace;
+		let mut frozen = false;
+		for monitor_id in monitor_ids {
+			self.frozen.insert(monitor_id, Frozen { session_id, until });
+			frozen = true;
+		}
+		if frozen {
+			self.sessions.insert(session_id);
+		}
+		frozen
+	}
+
+	/// The crashed session `monitor_id` keeps showing.
+	pub fn frozen_session(&self, monitor_id: MonitorId) -> Option<SessionId> {
+		self.frozen.get(&monitor_id).map(|frozen| frozen.session_id)
+	}
+
+	pub fn is_frozen(&self, session_id: SessionId) -> bool {
+		self.sessions.contains(&session_id)
+	}
+
+	/// Ends the grace of monitors past it, or that `shows_session` says moved on to another
+	/// session. Returns the crashed sessions no monitor shows any more, whose buffers can go.
+	pub fn expire(
+		&mut self,
+		now: Instant,
+		shows_session: impl Fn(MonitorId) -> bool,
+	) -> Vec<SessionId> {
+		self
+			.frozen
+			.retain(|monitor_id, frozen| now < frozen.until && !shows_session(*monitor_id));
+		let shown = self
+			.frozen
+			.values()
+			.map(|frozen| frozen.session_id)
+			.collect::<HashSet<_>>();
+		let released = self
+			.sessions
+			.iter()
+			.filter(|session_id| !shown.contains(session_id))
+			.copied()
+			.collect::<Vec<_>>();
+		for session_id in &released {
+			self.sessions.remove(session_id);
+		}
+		released
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.frozen.remove(&monitor_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn frames_stay_until_the_grace_ends_or_another_session_shows() {
+		let now = Instant::now();
+		let grace = Duration::from_secs(3);
+		let [left, right] = [0, 0].map(|_| MonitorId::rand());
+		let session_id = SessionId::rand();
+		let mut crash_grace = CrashGrace::default();
+		assert!(!crash_grace.freeze(session_id, [left], Duration::ZERO, now));
+		assert!(!crash_grace.freeze(session_id, [], grace, now));
+		assert!(crash_grace.freeze(session_id, [left, right], grace, now));
+
+		assert!(
+			crash_grace
+				.expire(now, |monitor_id| monitor_id == right)
+				.is_empty()
+		);
+		assert_eq!(crash_grace.frozen_session(left), Some(session_id));
+		assert_eq!(crash_grace.frozen_session(right), None);
+		assert!(crash_grace.is_frozen(session_id));
+
+		assert_eq!(crash_grace.expire(now + grace, |_| false), [session_id]);
+		assert_eq!(crash_grace.frozen_session(left), None);
+		assert!(!crash_grace.is_frozen(session_id));
+	}
+}
@@ -1,13 +1,12 @@
 #![allow(dead_code)]
 
 use std::{
-	ffi::c_void,
+	ffi::{CStr, c_void},
 	os::fd::{IntoRawFd, OwnedFd},
 };
 
 use easydrm::gl;
 use nix::unistd::close;
-use skia_safe::{Image, gpu};
 use thiserror::Error;
 
 use crate::rendering_layer::egl;
@@ -20,6 +19,8 @@ pub struct ImportParams {
 	pub stride: i32,
 	pub offset: i32,
 	pub fourcc: i32,
+	/// DRM format modifier, `None` leaves it to the driver to assume one.
+	pub modifier: Option<u64>,
 	pub fd: OwnedFd,
 }
 
@@ -37,6 +38,166 @@ pub enum DmaBufImportError {
 	TextureAllocationFailed,
 	#[error("glEGLImageTargetTexture2DOES failed (error={0:#X})")]
 	ImageBindFailed(u32),
+	#[error("unsupported buffer format {0:#010x}")]
+	UnsupportedFormat(i32),
+	#[error("buffer has modifier {0:#x} but EGL_EXT_image_dma_buf_import_modifiers is unavailable")]
+	ModifiersUnsupported(u64),
+	#[error("modifier {modifier:#x} can't be imported with format {fourcc:#010x}")]
+	UnsupportedModifier { fourcc: i32, modifier: u64 },
+}
+
+/// `DRM_FORMAT_MOD_INVALID`, the same as sending no modifier.
+const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+const IMPORT_MODIFIERS_EXTENSION: &str = "EGL_EXT_image_dma_buf_import_modifiers";
+
+/// How deep the channels of an imported texture are, which is all engines need to know to sample
+/// it. EGL swizzles channel order on import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelDepth {
+	Rgba8,
+	Rgb10A2,
+	Rgba16F,
+}
+
+const fn fourcc(code: &[u8; 4]) -> i32 {
+	i32::from_le_bytes(*code)
+}
+
+/// DRM formats shift can present.
+const FORMATS: &[(i32, ChannelDepth)] = &[
+	(fourcc(b"XR24"), ChannelDepth::Rgba8),
+	(fourcc(b"AR24"), ChannelDepth::Rgba8),
+	(fourcc(b"XB24"), ChannelDepth::Rgba8),
+	(fourcc(b"AB24"), ChannelDepth::Rgba8),
+	(fourcc(b"XR30"), ChannelDepth::Rgb10A2),
+	(fourcc(b"AR30"), ChannelDepth::Rgb10A2),
+	(fourcc(b"XB30"), ChannelDepth::Rgb10A2),
+	(fourcc(b"AB30"), ChannelDepth::Rgb10A2),
+	(fourcc(b"XB4H"), ChannelDepth::Rgba16F),
+	(fourcc(b"AB4H"), ChannelDepth::Rgba16F),
+];
+
+fn channel_depth(fourcc: i32) -> Option<ChannelDepth> {
+	FORMATS
+		.iter()
+		.find(|(code, _)| *code == fourcc)
+		.map(|(_, depth)| *depth)
+}
+
+/// DRM fourccs the current EGL display can import and shift can present, empty if the driver
+/// can't tell.
+pub fn supported_formats(proc_resolver: &dyn Fn(&str) -> *const c_void) -> Vec<i32> {
+	let egl = egl::Egl::load_with(|name| proc_resolver(name));
+	if !egl.QueryDmaBufFormatsEXT.is_loaded() {
+		return Vec::new();
+	}
+	let display = unsafe { egl.GetCurrentDisplay() };
+	if display.is_null() {
+		return Vec::new();
+	}
+	let mut count = 0;
+	if unsafe { egl.QueryDmaBufFormatsEXT(display, 0, std::ptr::null_mut(), &mut count) } == 0 {
+		return Vec::new();
+	}
+	let mut formats = vec![0; count.max(0) as usize];
+	if unsafe { egl.QueryDmaBufFormatsEXT(display, count, formats.as_mut_ptr(), &mut count) } == 0 {
+		return Vec::new();
+	}
+	formats.truncate(count.max(0) as usize);
+	formats.retain(|format| channel_depth(*format).is_some());
+	formats
+}
+
+/// Whether `display` advertises the EGL extension `name`.
+fn has_extension(egl: &egl::Egl, display: egl::types::EGLDisplay, name: &str) -> bool {
+	let extensions = unsafe { egl.QueryString(display, egl::EXTENSIONS as i32) };
+	if extensions.is_null() {
+		return false;
+	}
+	unsafe { CStr::from_ptr(extensions) }
+		.to_str()
+		.is_ok_and(|extensions| extensions.split_whitespace().any(|ext| ext == name))
+}
+
+/// Modifiers `display` can import `fourcc` with, each with whether it can only be sampled as
+/// an external texture. `None` if the driver can't tell.
+fn supported_modifiers(
+	egl: &egl::Egl,
+	display: egl::types::EGLDisplay,
+	fourcc: i32,
+) -> Option<Vec<(u64, bool)>> {
+	if !egl.QueryDmaBufModifiersEXT.is_loaded() {
+		return None;
+	}
+	let mut count = 0;
+	let queried = unsafe {
+		egl.QueryDmaBufModifiersEXT(
+			display,
+			fourcc,
+			0,
+			std::ptr::null_mut(),
+			std::ptr::null_mut(),
+			&mut count,
+		)
+	};
+	if queried == 0 {
+		return None;
+	}
+	let mut modifiers = vec![0; count.max(0) as usize];
+	let mut external_only = vec![0; count.max(0) as usize];
+	let queried = unsafe {
+		egl.QueryDmaBufModifiersEXT(
+			display,
+			fourcc,
+			count,
+			modifiers.as_mut_ptr(),
+			external_only.as_mut_ptr(),
+			&mut count,
+		)
+	};
+	if queried == 0 {
+		return None;
+	}
+	modifiers.truncate(count.max(0) as usize);
+	Some(
+		modifiers
+			.into_iter()
+			.zip(external_only)
+			.map(|(modifier, external_only)| (modifier, external_only != 0))
+			.collect(),
+	)
+}
+
+/// The explicit modifier to import `params` with, `None` when the driver should pick.
+fn import_modifier(
+	egl: &egl::Egl,
+	display: egl::types::EGLDisplay,
+	params: &ImportParams,
+) -> Result<Option<u64>, DmaBufImportError> {
+	let Some(modifier) = params
+		.modifier
+		.filter(|modifier| *modifier != DRM_FORMAT_MOD_INVALID)
+	else {
+		return Ok(None);
+	};
+	if !has_extension(egl, display, IMPORT_MODIFIERS_EXTENSION) {
+		return Err(DmaBufImportError::ModifiersUnsupported(modifier));
+	}
+	// Textures are bound as GL_TEXTURE_2D, external-only modifiers can't be sampled that way. An
+	// empty list means the driver doesn't enumerate modifiers, so the import itself has to tell.
+	let unsupported = supported_modifiers(egl, display, params.fourcc).is_some_and(|supported| {
+		!supported.is_empty()
+			&& !supported
+				.iter()
+				.any(|(supported, external_only)| *supported == modifier && !external_only)
+	});
+	if unsupported {
+		return Err(DmaBufImportError::UnsupportedModifier {
+			fourcc: params.fourcc,
+			modifier,
+		});
+	}
+	Ok(Some(modifier))
 }
 
 /// RAII wrapper owning the imported GL texture + EGL image.
@@ -48,16 +209,20 @@ pub struct DmaBufTexture {
 	texture_id: gl::types::GLuint,
 	pub width: i32,
 	pub height: i32,
+	pub stride: i32,
 	pub fourcc: i32,
+	depth: ChannelDepth,
 }
 
 impl DmaBufTexture {
-	#[tracing::instrument(skip_all, fields(width = params.width, height = params.height, fourcc = params.fourcc))]
+	#[tracing::instrument(skip_all, fields(width = params.width, height = params.height, fourcc = params.fourcc, modifier = params.modifier))]
 	pub fn import(
 		gl: &gl::Gles2,
 		proc_resolver: &dyn Fn(&str) -> *const c_void,
 		params: ImportParams,
 	) -> Result<Self, DmaBufImportError> {
+		let depth =
+			channel_depth(params.fourcc).ok_or(DmaBufImportError::UnsupportedFormat(params.fourcc))?;
 		let resolver = |name: &'static str| (proc_resolver)(name);
 		let egl = egl::Egl::load_with(|name| resolver(name));
 		if !(egl.CreateImageKHR.is_loaded() && egl.DestroyImageKHR.is_loaded()) {
@@ -72,8 +237,9 @@ impl DmaBufTexture {
 		if context.is_null() {
 			return Err(DmaBufImportError::MissingContext);
 		}
+		let modifier = import_modifier(&egl, display, &params)?;
 		let raw_fd = params.fd.into_raw_fd();
-		let mut attrs = [
+		let mut attrs = vec![
 			egl::LINUX_DRM_FOURCC_EXT as i32,
 			params.fourcc,
 			egl::DMA_BUF_PLANE0_FD_EXT as i32,
@@ -86,8 +252,16 @@ impl DmaBufTexture {
 			params.width,
 			egl::HEIGHT as i32,
 			params.height,
-			egl::NONE as i32,
 		];
+		if let Some(modifier) = modifier {
+			attrs.extend([
+				egl::DMA_BUF_PLANE0_MODIFIER_LO_EXT as i32,
+				modifier as u32 as i32,
+				egl::DMA_BUF_PLANE0_MODIFIER_HI_EXT as i32,
+				(modifier >> 32) as u32 as i32,
+			]);
+		}
+		attrs.push(egl::NONE as i32);
 
 		let image = unsafe {
 			egl.CreateImageKHR(
@@ -158,33 +332,17 @@ impl DmaBufTexture {
 			texture_id: texture,
 			width: params.width,
 			height: params.height,
+			stride: params.stride,
 			fourcc: params.fourcc,
+			depth,
 		})
 	}
-	fn skia_tex_info(&self) -> gpu::gl::TextureInfo {
-		gpu::gl::TextureInfo {
-			target: gl::TEXTURE_2D as gpu::gl::Enum,
-			id: self.texture_id as gpu::gl::Enum,
-			format: gpu::gl::Format::RGBA8.into(),
-			protected: gpu::Protected::No,
-		}
+	pub fn texture_id(&self) -> gl::types::GLuint {
+		self.texture_id
 	}
-	#[tracing::instrument(skip_all)]
-	pub fn to_skia(self, label: impl AsRef<str>) -> Result<SkiaDmaBufTexture, DmaBufImportError> {
-		let backend_texture = unsafe {
-			gpu::backend_textures::make_gl(
-				(self.width, self.height),
-				gpu::Mipmapped::No,
-				self.skia_tex_info(),
-				label,
-			)
-		};
 
-		Ok(SkiaDmaBufTexture {
-			backend_texture,
-			source: self,
-			cached_image: None,
-		})
+	pub fn depth(&self) -> ChannelDepth {
+		self.depth
 	}
 }
 
@@ -198,53 +356,3 @@ impl Drop for DmaBufTexture {
 		}
 	}
 }
-
-/// Helper struct that keeps the GL/EGL resources alive for as long as Skia needs them.
-pub struct SkiaDmaBufTexture {
-	pub backend_texture: gpu::BackendTexture,
-	source: DmaBufTexture,
-	cached_image: Option<Image>,
-}
-
-impl SkiaDmaBufTexture {
-	pub fn texture(&self) -> &gpu::BackendTexture {
-		&self.backend_texture
-	}
-
-	pub fn image<'a>(&'a mut self, gr: &mut gpu::DirectContext) -> Option<&'a Image> {
-		if self.cached_image.is_none() {
-			self.cached_image = Image::from_texture(
-				gr,
-				&self.backend_texture,
-				gpu::SurfaceOrigin::TopLeft,
-				skia_safe::ColorType::RGBA8888,
-				skia_safe::AlphaType::Opaque,
-				None,
-			);
-		}
-		self.cached_image.as_ref()
-	}
-	/// Splits into the skia texture and inner opengl texture
-	///
-	/// # Safety
-	/// The caller is responsible for keeping `DmaBufTexture` alive while `BackendTexture` is alive.
-	pub unsafe fn into_inner(self) -> (gpu::BackendTexture, DmaBufTexture) {
-		(self.backend_texture, self.source)
-	}
-
-	pub fn update(&mut self) {
-		self.backend_texture = unsafe {
-			gpu::backend_textures::make_gl(
-				(self.source.width, self.source.height),
-				gpu::Mipmapped::No,
-				self.source.skia_tex_info(),
-				self.backend_texture.label(),
-			)
-		};
-		self.cached_image = None;
-	}
-
-	pub fn gl_texture_id(&self) -> gl::types::GLuint {
-		self.source.texture_id
-	}
-}
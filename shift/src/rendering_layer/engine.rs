@@ -0,0 +1,126 @@
+//! Render engines: what turns the server layer's `RenderCmd`s into pixels.
+//! - an engine owns the rendering end of the channels. It imports linked buffers, swaps in
+//!   requested ones and plays transitions, and answers with `RenderEvt`s for acks, consumed
+//!   buffers and page flips, so the server layer works the same whichever one runs
+//! - `SHIFT_RENDER_ENGINE` picks one at startup: `skia`, with the default `skia` feature,
+//!   composites on the GPU with Skia; `gles`, with the `gles` feature, draws sessions full screen
+//!   with plain GLES; `sim`, with the `sim` feature, flips a virtual monitor and shows nothing.
+//!   `SHIFT_SIM_RENDERER` also selects `sim`
+//! - unset, or naming one that isn't built in, it's the first of those that is
+
+use futures::{FutureExt, future::LocalBoxFuture};
+
+#[cfg(feature = "skia")]
+use super::RenderingLayer;
+use super::{RenderError, channels::RenderingEnd};
+
+#[cfg(not(any(feature = "skia", feature = "gles", feature = "sim")))]
+compile_error!("shift needs a render engine, enable the `skia`, `gles` or `sim` feature");
+
+/// A renderer the server layer can drive through the render channels.
+pub trait RenderEngine {
+	/// Handles commands until `RenderCmd::Shutdown` or the server layer hangs up.
+	fn run(self: Box<Self>) -> LocalBoxFuture<'static, Result<(), RenderError>>;
+}
+
+#[cfg(feature = "skia")]
+impl RenderEngine for RenderingLayer {
+	fn run(self: Box<Self>) -> LocalBoxFuture<'static, Result<(), RenderError>> {
+		RenderingLayer::run(*self).boxed_local()
+	}
+}
+
+#[cfg(feature = "gles")]
+impl RenderEngine for super::gles::GlesEngine {
+	fn run(self: Box<Self>) -> LocalBoxFuture<'static, Result<(), RenderError>> {
+		super::gles::GlesEngine::run(*self).boxed_local()
+	}
+}
+
+#[cfg(feature = "sim")]
+struct SimEngine {
+	sim: super::sim::SimRenderer,
+	channels: RenderingEnd,
+}
+
+#[cfg(feature = "sim")]
+impl RenderEngine for SimEngine {
+	fn run(self: Box<Self>) -> LocalBoxFuture<'static, Result<(), RenderError>> {
+		let Self { sim, channels } = *self;
+		sim.run(channels).boxed_local()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineKind {
+	#[cfg(feature = "skia")]
+	Skia,
+	#[cfg(feature = "gles")]
+	Gles,
+	#[cfg(feature = "sim")]
+	Sim,
+}
+
+impl Default for EngineKind {
+	fn default() -> Self {
+		DEFAULT_ENGINE
+	}
+}
+
+#[cfg(feature = "skia")]
+const DEFAULT_ENGINE: EngineKind = EngineKind::Skia;
+#[cfg(all(not(feature = "skia"), feature = "gles"))]
+const DEFAULT_ENGINE: EngineKind = EngineKind::Gles;
+#[cfg(all(not(feature = "skia"), not(feature = "gles"), feature = "sim"))]
+const DEFAULT_ENGINE: EngineKind = EngineKind::Sim;
+
+impl EngineKind {
+	pub fn from_env() -> Self {
+		#[cfg(feature = "sim")]
+		if std::env::var_os("SHIFT_SIM_RENDERER").is_some() {
+			return Self::Sim;
+		}
+		match std::env::var("SHIFT_RENDER_ENGINE")
+			.unwrap_or_default()
+			.trim()
+			.to_ascii_lowercase()
+			.as_str()
+		{
+			"" => DEFAULT_ENGINE,
+			#[cfg(feature = "skia")]
+			"skia" => Self::Skia,
+			#[cfg(feature = "gles")]
+			"gles" => Self::Gles,
+			#[cfg(feature = "sim")]
+			"sim" => Self::Sim,
+			other => {
+				tracing::warn!(
+					engine = other,
+					fallback = ?DEFAULT_ENGINE,
+					"unknown or not built in SHIFT_RENDER_ENGINE"
+				);
+				DEFAULT_ENGINE
+			}
+		}
+	}
+
+	/// Sets up the engine, e.g. takes over DRM for `skia`.
+	pub fn init(self, channels: RenderingEnd) -> Result<Box<dyn RenderEngine>, RenderError> {
+		tracing::info!(engine = ?self, "starting render engine");
+		Ok(match self {
+			#[cfg(feature = "skia")]
+			Self::Skia => Box::new(RenderingLayer::init(channels)?),
+			#[cfg(feature = "gles")]
+			Self::Gles => Box::new(super::gles::GlesEngine::init(channels)?),
+			#[cfg(feature = "sim")]
+			Self::Sim => {
+				use super::sim::SimRenderer;
+				tracing::warn!("the sim render engine shows nothing on screen");
+				Box::new(SimEngine {
+					sim: SimRenderer::new(vec![SimRenderer::default_monitor()]),
+					channels,
+				})
+			}
+		})
+	}
+}
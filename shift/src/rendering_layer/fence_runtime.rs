@@ -1,12 +1,64 @@
 use std::os::fd::{AsFd, OwnedFd};
 
+use super::state::BufferSlot;
 use super::{FenceEvent, FenceWaitMode, RenderEvt, RenderingLayer, SlotKey};
+use crate::comms::correlation::Correlated;
 
 impl RenderingLayer {
-	#[tracing::instrument(skip_all)]
-	pub(super) async fn emit_event(&self, event: RenderEvt) {
-		if let Err(e) = self.event_tx.send(event).await {
-			tracing::warn!("failed to send renderer event to server: {e}");
+	/// Queues an event for the server, sent with the rest of the loop iteration's. It carries the
+	/// correlation id of the command being handled or, for a release, of the swap that queued it.
+	pub(super) fn emit_event(&mut self, event: RenderEvt) {
+		let corr = match &event {
+			RenderEvt::BufferConsumed {
+				session_id,
+				monitor_id,
+				buffer,
+				..
+			} => {
+				let key = SlotKey::new(*monitor_id, *session_id, BufferSlot::from(*buffer));
+				self.swap_corrs.remove(&key).or(self.corr)
+			}
+			_ => self.corr,
+		};
+		self.outbox.push(Correlated::new(corr, event));
+	}
+
+	/// Hands `key` back, tagged with the link generation it was swapped in under.
+	pub(super) fn buffer_consumed(&self, key: SlotKey, release_fence: Option<OwnedFd>) -> RenderEvt {
+		let generation = self
+			.swap_generations
+			.get(&key)
+			.copied()
+			.unwrap_or_else(|| self.link_generation(key.monitor_id, key.session_id));
+		RenderEvt::BufferConsumed {
+			session_id: key.session_id,
+			monitor_id: key.monitor_id,
+			buffer: key.buffer.into(),
+			generation,
+			release_fence,
+		}
+	}
+
+	pub(super) fn link_generation(
+		&self,
+		monitor_id: crate::monitor::MonitorId,
+		session_id: crate::sessions::SessionId,
+	) -> u64 {
+		self
+			.link_generations
+			.get(&(monitor_id, session_id))
+			.copied()
+			.unwrap_or(0)
+	}
+
+	#[tracing::instrument(skip_all, fields(events = self.outbox.len()))]
+	pub(super) async fn flush_events(&mut self) {
+		if self.outbox.is_empty() {
+			return;
+		}
+		let batch = std::mem::take(&mut self.outbox);
+		if let Err(e) = self.event_tx.send(batch).await {
+			tracing::warn!("failed to send renderer events to server: {e}");
 		}
 	}
 
@@ -43,11 +95,19 @@ impl RenderingLayer {
 		match event {
 			FenceEvent::Signaled { key } => {
 				self.fence_tasks.remove(&key);
-				if let Some(previous) = self.ownership.apply_acquire_fence_signaled(key) {
+				let Some(ready) = self.ownership.apply_acquire_fence_signaled(key) else {
+					return;
+				};
+				if let Some(previous) = ready.deferred {
 					self
 						.ownership
 						.queue_buffer_release(key.monitor_id, key.session_id, previous);
 				}
+				if let Some(superseded) = ready.release_now {
+					self
+						.release_unshown(key.monitor_id, key.session_id, superseded)
+						.await;
+				}
 			}
 		}
 	}
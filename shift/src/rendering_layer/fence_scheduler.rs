@@ -3,6 +3,7 @@ use std::{
 	io::ErrorKind,
 	os::fd::{AsRawFd, OwnedFd},
 	sync::{Arc, Mutex},
+	time::Duration,
 };
 
 use futures::future::{join_all, select_all};
@@ -31,6 +32,8 @@ pub(super) struct FenceScheduler {
 	callbacks: HashMap<FenceTaskHandle, SharedCallback>,
 	tx: mpsc::UnboundedSender<CompletedTask>,
 	rx: mpsc::UnboundedReceiver<CompletedTask>,
+	/// Added after every wait, only with the `fault-injection` feature.
+	delay: Option<Duration>,
 }
 
 impl FenceScheduler {
@@ -42,6 +45,7 @@ impl FenceScheduler {
 			callbacks: HashMap::new(),
 			tx,
 			rx,
+			delay: injected_delay(),
 		}
 	}
 
@@ -54,7 +58,14 @@ impl FenceScheduler {
 		let handle = FenceTaskHandle(self.next_id);
 		self.next_id = self.next_id.saturating_add(1);
 		let callback = Arc::new(Mutex::new(Some(callback)));
-		let task = spawn_wait_task(handle, fences, mode, Arc::clone(&callback), self.tx.clone());
+		let task = spawn_wait_task(
+			handle,
+			fences,
+			mode,
+			self.delay,
+			Arc::clone(&callback),
+			self.tx.clone(),
+		);
 		self.tasks.insert(handle, task);
 		self.callbacks.insert(handle, callback);
 		handle
@@ -72,7 +83,7 @@ impl FenceScheduler {
 		if let Some(task) = self.tasks.remove(&handle) {
 			task.abort();
 		}
-		let task = spawn_wait_task(handle, fences, mode, callback, self.tx.clone());
+		let task = spawn_wait_task(handle, fences, mode, self.delay, callback, self.tx.clone());
 		self.tasks.insert(handle, task);
 		true
 	}
@@ -99,15 +110,29 @@ impl FenceScheduler {
 	}
 }
 
+#[cfg(feature = "fault-injection")]
+fn injected_delay() -> Option<Duration> {
+	crate::faults::FaultInjection::from_env().fence_delay()
+}
+
+#[cfg(not(feature = "fault-injection"))]
+fn injected_delay() -> Option<Duration> {
+	None
+}
+
 fn spawn_wait_task(
 	handle: FenceTaskHandle,
 	fences: Vec<OwnedFd>,
 	mode: FenceWaitMode,
+	delay: Option<Duration>,
 	callback: SharedCallback,
 	tx: mpsc::UnboundedSender<CompletedTask>,
 ) -> JoinHandle<()> {
 	tokio::spawn(async move {
 		let wait_ok = wait_many_fences(fences, mode).await;
+		if let Some(delay) = delay {
+			tokio::time::sleep(delay).await;
+		}
 		if wait_ok {
 			let _ = tx.send(CompletedTask { handle, callback });
 		}
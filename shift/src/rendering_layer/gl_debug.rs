@@ -0,0 +1,204 @@
+//! GL debug output (`GL_KHR_debug`) of the renderer's contexts, for GL errors the few
+//! `glGetError` checks miss.
+//! - driver messages are logged under the `shift::gl` target, at a level going by their severity
+//! - the last ones above notification severity go into crash reports, see [`crate::crash`]
+//! - `SHIFT_GL_STRICT=1` drops any frame that raised a GL error instead of presenting it, for CI.
+//!   Messages are then delivered synchronously, so they land in the frame that caused them
+//!
+//! Every context needs it turned on, see [`GlDebug::enable_current`].
+
+use std::{
+	ffi::{CStr, c_char, c_void},
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+use tracing::Level;
+
+const GL_EXTENSIONS: u32 = 0x1F03;
+const GL_DEBUG_OUTPUT: u32 = 0x92E0;
+const GL_DEBUG_OUTPUT_SYNCHRONOUS: u32 = 0x8242;
+const GL_DEBUG_TYPE_ERROR: u32 = 0x824C;
+const GL_DEBUG_TYPE_DEPRECATED_BEHAVIOR: u32 = 0x824D;
+const GL_DEBUG_TYPE_UNDEFINED_BEHAVIOR: u32 = 0x824E;
+const GL_DEBUG_TYPE_PORTABILITY: u32 = 0x824F;
+const GL_DEBUG_TYPE_PERFORMANCE: u32 = 0x8250;
+const GL_DEBUG_SEVERITY_HIGH: u32 = 0x9146;
+const GL_DEBUG_SEVERITY_MEDIUM: u32 = 0x9147;
+const GL_DEBUG_SEVERITY_LOW: u32 = 0x9148;
+const GL_DEBUG_SEVERITY_NOTIFICATION: u32 = 0x826B;
+
+type GlDebugProc = extern "system" fn(u32, u32, u32, u32, i32, *const c_char, *mut c_void);
+type GlDebugMessageCallback = unsafe extern "system" fn(Option<GlDebugProc>, *const c_void);
+type GlEnable = unsafe extern "system" fn(u32);
+type GlGetString = unsafe extern "system" fn(u32) -> *const u8;
+
+/// GL errors reported since the last [`GlDebug::take_errors`], by any context.
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// The entry points debug output needs, resolved once for every context.
+#[derive(Debug, Clone, Copy)]
+pub struct GlDebug {
+	enable: Option<GlEnable>,
+	get_string: Option<GlGetString>,
+	message_callback: Option<GlDebugMessageCallback>,
+	strict: bool,
+}
+
+impl GlDebug {
+	pub fn load(proc_resolver: &dyn Fn(&str) -> *const c_void) -> Self {
+		let resolve = |name: &str| Some(proc_resolver(name)).filter(|ptr| !ptr.is_null());
+		// SAFETY: each symbol is transmuted to the signature the GL spec gives it.
+		unsafe {
+			Self {
+				enable: resolve("glEnable").map(|ptr| std::mem::transmute::<_, GlEnable>(ptr)),
+				get_string: resolve("glGetString").map(|ptr| std::mem::transmute::<_, GlGetString>(ptr)),
+				message_callback: resolve("glDebugMessageCallbackKHR")
+					.or_else(|| resolve("glDebugMessageCallback"))
+					.map(|ptr| std::mem::transmute::<_, GlDebugMessageCallback>(ptr)),
+				strict: std::env::var("SHIFT_GL_STRICT").is_ok_and(|v| v.trim() == "1"),
+			}
+		}
+	}
+
+	pub fn is_strict(&self) -> bool {
+		self.strict
+	}
+
+	/// Turns debug output on for the current context. Returns false when the driver doesn't
+	/// have `GL_KHR_debug`.
+	pub fn enable_current(&self) -> bool {
+		let (Some(enable), Some(get_string), Some(message_callback)) =
+			(self.enable, self.get_string, self.message_callback)
+		else {
+			return false;
+		};
+		// SAFETY: a context is current, and GL_EXTENSIONS is a static NUL-terminated string.
+		let extensions = unsafe { get_string(GL_EXTENSIONS) };
+		let has_debug = !extensions.is_null()
+			&& unsafe { CStr::from_ptr(extensions.cast()) }
+				.to_str()
+				.is_ok_and(|extensions| {
+					extensions
+						.split_whitespace()
+						.any(|ext| ext == "GL_KHR_debug")
+				});
+		if !has_debug {
+			return false;
+		}
+		// SAFETY: `on_message` matches GLDEBUGPROC and uses no user pointer.
+		unsafe {
+			enable(GL_DEBUG_OUTPUT);
+			if self.strict {
+				enable(GL_DEBUG_OUTPUT_SYNCHRONOUS);
+			}
+			message_callback(Some(on_message), std::ptr::null());
+		}
+		true
+	}
+
+	/// Counts an error found with `glGetError`, for drivers without debug output.
+	pub fn record_error(&self, error: u32) {
+		record(
+			GL_DEBUG_TYPE_ERROR,
+			GL_DEBUG_SEVERITY_HIGH,
+			error,
+			"glGetError",
+		);
+	}
+
+	/// GL errors since the last call, from debug output or [`Self::record_error`].
+	pub fn take_errors(&self) -> u64 {
+		ERRORS.swap(0, Ordering::Relaxed)
+	}
+}
+
+extern "system" fn on_message(
+	_source: u32,
+	kind: u32,
+	id: u32,
+	severity: u32,
+	length: i32,
+	message: *const c_char,
+	_user_param: *mut c_void,
+) {
+	if message.is_null() {
+		return;
+	}
+	// SAFETY: the driver passes `length` bytes of message, or a NUL-terminated one when negative.
+	let message = unsafe {
+		match usize::try_from(length) {
+			Ok(length) => String::from_utf8_lossy(std::slice::from_raw_parts(message.cast(), length)),
+			Err(_) => CStr::from_ptr(message).to_string_lossy(),
+		}
+	};
+	record(kind, severity, id, message.trim_end());
+}
+
+fn record(kind: u32, severity: u32, id: u32, message: &str) {
+	if kind == GL_DEBUG_TYPE_ERROR {
+		ERRORS.fetch_add(1, Ordering::Relaxed);
+	}
+	let kind = kind_name(kind);
+	match level(kind, severity) {
+		Level::ERROR => tracing::error!(target: "shift::gl", kind, id, "{message}"),
+		Level::WARN => tracing::warn!(target: "shift::gl", kind, id, "{message}"),
+		Level::INFO => tracing::info!(target: "shift::gl", kind, id, "{message}"),
+		_ => tracing::debug!(target: "shift::gl", kind, id, "{message}"),
+	}
+	if severity != GL_DEBUG_SEVERITY_NOTIFICATION {
+		crate::crash::push_gl_message(format!(
+			"{} {kind} {id:#x}: {message}",
+			chrono::Local::now().format("%H:%M:%S%.3f")
+		));
+	}
+}
+
+fn level(kind: &str, severity: u32) -> Level {
+	if kind == "error" {
+		return Level::ERROR;
+	}
+	match severity {
+		GL_DEBUG_SEVERITY_HIGH => Level::ERROR,
+		GL_DEBUG_SEVERITY_MEDIUM => Level::WARN,
+		GL_DEBUG_SEVERITY_LOW => Level::INFO,
+		_ => Level::DEBUG,
+	}
+}
+
+fn kind_name(kind: u32) -> &'static str {
+	match kind {
+		GL_DEBUG_TYPE_ERROR => "error",
+		GL_DEBUG_TYPE_DEPRECATED_BEHAVIOR => "deprecated",
+		GL_DEBUG_TYPE_UNDEFINED_BEHAVIOR => "undefined behavior",
+		GL_DEBUG_TYPE_PORTABILITY => "portability",
+		GL_DEBUG_TYPE_PERFORMANCE => "performance",
+		_ => "other",
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn messages_are_logged_by_severity_and_errors_counted() {
+		assert_eq!(level("error", GL_DEBUG_SEVERITY_LOW), Level::ERROR);
+		assert_eq!(level("performance", GL_DEBUG_SEVERITY_HIGH), Level::ERROR);
+		assert_eq!(level("performance", GL_DEBUG_SEVERITY_MEDIUM), Level::WARN);
+		assert_eq!(level("portability", GL_DEBUG_SEVERITY_LOW), Level::INFO);
+		assert_eq!(level("other", GL_DEBUG_SEVERITY_NOTIFICATION), Level::DEBUG);
+
+		let debug = GlDebug::load(&|_| std::ptr::null());
+		assert!(!debug.enable_current());
+		debug.take_errors();
+		record(
+			GL_DEBUG_TYPE_PERFORMANCE,
+			GL_DEBUG_SEVERITY_MEDIUM,
+			7,
+			"slow path",
+		);
+		debug.record_error(0x502);
+		assert_eq!(debug.take_errors(), 1);
+		assert_eq!(debug.take_errors(), 0);
+	}
+}
@@ -0,0 +1,814 @@
+//! A compositor engine on plain GLES, for GPUs or builds where Skia is too heavy.
+//! - built with the `gles` feature and picked with `SHIFT_RENDER_ENGINE=gles`, or by default in
+//!   builds without the `skia` one, like `--no-default-features --features gles`
+//! - each monitor shows its session full screen, sampled straight from the imported dma-buf.
+//!   Every `session_switch` transition is drawn as a crossfade, the only one it offers
+//! - buffers follow the Skia engine's contract: acked once queued, released with the page flip's
+//!   fence once a newer one was shown. Uploads, layouts, PiP, backgrounds, dimming, the HUD and
+//!   screenshots aren't supported; buffers those would need are never linked, so swaps of them
+//!   are rejected
+
+mod monitor;
+mod shader;
+
+use std::{
+	collections::{HashMap, HashSet},
+	os::fd::{AsFd, FromRawFd, OwnedFd},
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+use easydrm::{
+	EasyDRM,
+	gl::{NO_ERROR, types::GLuint},
+};
+use tab_protocol::{Easing, PanelOrientation, TransitionInfo};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use super::{
+	RenderError,
+	channels::RenderingEnd,
+	dmabuf_import::{self, DmaBufTexture, ImportParams},
+	fence_scheduler::{FenceScheduler, FenceTaskHandle, FenceWaitMode},
+	gl_debug::GlDebug,
+	ownership::OwnershipManager,
+	state::{BufferSlot, FenceEvent, SlotKey, SlotOwner},
+};
+use crate::{
+	comms::{
+		correlation::{self, Correlated, CorrelationId},
+		render2server::{RenderEvt, RenderEvtTx},
+		server2render::{RenderCmd, RenderCmdRx, SessionTransition},
+	},
+	monitor::{
+		Monitor as ServerLayerMonitor, MonitorId, MonitorIds,
+		connector::{LinkRetrains, reacquire_drm_master},
+	},
+	sessions::SessionId,
+};
+use monitor::{Frame, GlesMonitor};
+
+/// The one transition this engine draws, whatever `session_switch` asked for.
+const CROSSFADE: &str = "crossfade";
+
+#[derive(Debug, Clone, Copy)]
+struct Crossfade {
+	from_session_id: SessionId,
+	to_session_id: SessionId,
+	started_at: Instant,
+	duration: Duration,
+	easing: Easing,
+}
+
+impl Crossfade {
+	fn from_cmd(to_session_id: SessionId, transition: SessionTransition) -> Option<Self> {
+		if transition.duration.is_zero() {
+			return None;
+		}
+		Some(Self {
+			from_session_id: transition.from_session_id,
+			to_session_id,
+			started_at: Instant::now(),
+			duration: transition.duration,
+			easing: transition.easing,
+		})
+	}
+
+	/// Linear progress through the duration, before easing.
+	fn progress(&self, now: Instant) -> f64 {
+		let elapsed = now.saturating_duration_since(self.started_at);
+		(elapsed.as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0)
+	}
+}
+
+pub struct GlesEngine {
+	drm: EasyDRM<GlesMonitor>,
+	command_rx: Option<RenderCmdRx>,
+	event_tx: RenderEvtTx,
+	/// Events of the current loop iteration, see [`Self::flush_events`].
+	outbox: Vec<Correlated<RenderEvt>>,
+	/// The client frame behind the command being handled.
+	corr: Option<CorrelationId>,
+	known_monitors: HashMap<MonitorId, ServerLayerMonitor>,
+	monitor_ids: MonitorIds,
+	link_retrains: LinkRetrains,
+	ownership: OwnershipManager,
+	textures: HashMap<SlotKey, DmaBufTexture>,
+	/// `generation` of each session's latest link, by monitor and session.
+	link_generations: HashMap<(MonitorId, SessionId), u64>,
+	/// Link generation each slot was last swapped in under, for its release.
+	swap_generations: HashMap<SlotKey, u64>,
+	fence_event_tx: mpsc::UnboundedSender<FenceEvent>,
+	fence_event_rx: mpsc::UnboundedReceiver<FenceEvent>,
+	fence_scheduler: FenceScheduler,
+	fence_tasks: HashMap<SlotKey, FenceTaskHandle>,
+	crossfade: Option<Crossfade>,
+	/// Between `RenderCmd::Suspend` and `RenderCmd::Resume`, when nothing is drawn.
+	suspended: bool,
+	/// Active session once a frame of it was page flipped, see `RenderEvt::SessionShown`.
+	active_shown: Option<SessionId>,
+	gl_debug: GlDebug,
+}
+
+impl GlesEngine {
+	#[tracing::instrument(skip_all)]
+	pub fn init(channels: RenderingEnd) -> Result<Self, RenderError> {
+		let (command_rx, event_tx) = channels.into_parts();
+		let drm = EasyDRM::init(|req| GlesMonitor::new(req).expect("GlesMonitor::new failed"))?;
+		let gl_debug = GlDebug::load(&|s| drm.get_proc_address(s));
+		let (fence_event_tx, fence_event_rx) = mpsc::unbounded_channel();
+		Ok(Self {
+			drm,
+			command_rx: Some(command_rx),
+			event_tx,
+			outbox: Vec::new(),
+			corr: None,
+			known_monitors: HashMap::new(),
+			monitor_ids: MonitorIds::load(),
+			link_retrains: LinkRetrains::default(),
+			ownership: OwnershipManager::new(),
+			textures: HashMap::new(),
+			link_generations: HashMap::new(),
+			swap_generations: HashMap::new(),
+			fence_event_tx,
+			fence_event_rx,
+			fence_scheduler: FenceScheduler::new(),
+			fence_tasks: HashMap::new(),
+			crossfade: None,
+			suspended: false,
+			active_shown: None,
+			gl_debug,
+		})
+	}
+
+	#[tracing::instrument(skip_all)]
+	pub async fn run(mut self) -> Result<(), RenderError> {
+		let mut command_rx = self
+			.command_rx
+			.take()
+			.expect("render command channel missing");
+		self.identify_monitors();
+		let current = self.collect_monitors();
+		self.emit_event(RenderEvt::Started {
+			monitors: current.clone(),
+		});
+		self.known_monitors = current.into_iter().map(|m| (m.id, m)).collect();
+		self.emit_event(RenderEvt::TransitionsChanged {
+			transitions: vec![TransitionInfo {
+				name: CROSSFADE.into(),
+				params: Vec::new(),
+			}],
+		});
+		self.flush_events().await;
+
+		'e: loop {
+			let committed_any = !self.suspended && self.render_and_commit()?;
+
+			'l: loop {
+				self.flush_events().await;
+				tokio::select! {
+					cmd = command_rx.recv() => {
+						let Some(Correlated { corr, msg: cmd }) = cmd else {
+							warn!("server→renderer channel closed, shutting down renderer");
+							break 'e;
+						};
+						self.corr = corr;
+						let keep_running = self.handle_command(cmd);
+						self.corr = None;
+						if !keep_running {
+							break 'e;
+						}
+					}
+					result = self.drm.poll_events_async() => {
+						result?;
+						self.sync_monitors();
+						break 'l;
+					}
+					Some(FenceEvent::Signaled { key }) = self.fence_event_rx.recv() => {
+						self.acquire_fence_signaled(key);
+					}
+					scheduler_ok = self.fence_scheduler.recv_and_run() => {
+						if !scheduler_ok {
+							warn!("fence scheduler channel closed");
+						}
+					}
+					_ = tokio::time::sleep(Duration::from_millis(2)), if !committed_any && !self.suspended => {
+						break 'l;
+					}
+				}
+			}
+		}
+
+		self.flush_events().await;
+		warn!("shutting down renderer");
+		Ok(())
+	}
+
+	fn emit_event(&mut self, event: RenderEvt) {
+		self.outbox.push(Correlated::new(self.corr, event));
+	}
+
+	/// Sends the events of the loop iteration as one batch.
+	async fn flush_events(&mut self) {
+		if self.outbox.is_empty() {
+			return;
+		}
+		let batch = std::mem::take(&mut self.outbox);
+		if let Err(e) = self.event_tx.send(batch).await {
+			warn!("failed to send renderer events to server: {e}");
+		}
+	}
+
+	/// Gives monitors EasyDRM just set up their stable id, before anything else sees them.
+	fn identify_monitors(&mut self) {
+		let mut in_use = self
+			.drm
+			.monitors()
+			.filter(|mon| mon.context().is_identified())
+			.map(|mon| mon.context().id)
+			.collect::<HashSet<_>>();
+		for mon in self.drm.monitors_mut() {
+			if mon.context().is_identified() {
+				continue;
+			}
+			let id = self
+				.monitor_ids
+				.for_connector(u32::from(mon.connector_id()), &in_use);
+			in_use.insert(id);
+			mon.context_mut().identify(id);
+		}
+	}
+
+	fn collect_monitors(&self) -> Vec<ServerLayerMonitor> {
+		self
+			.drm
+			.monitors()
+			.map(GlesMonitor::get_server_layer_monitor)
+			.collect()
+	}
+
+	fn sync_monitors(&mut self) {
+		self.identify_monitors();
+		let mut current = HashMap::new();
+		for monitor in self.collect_monitors() {
+			match self.known_monitors.get(&monitor.id) {
+				None => self.emit_event(RenderEvt::MonitorOnline {
+					monitor: monitor.clone(),
+				}),
+				Some(known)
+					if (
+						known.width,
+						known.height,
+						known.refresh_rate,
+						known.connector,
+					) != (
+						monitor.width,
+						monitor.height,
+						monitor.refresh_rate,
+						monitor.connector,
+					) =>
+				{
+					self.emit_event(RenderEvt::MonitorChanged {
+						monitor: monitor.clone(),
+					});
+				}
+				Some(_) => {}
+			}
+			current.insert(monitor.id, monitor);
+		}
+		let removed = self
+			.known_monitors
+			.keys()
+			.filter(|monitor_id| !current.contains_key(monitor_id))
+			.copied()
+			.collect::<Vec<_>>();
+		for monitor_id in removed {
+			self.emit_event(RenderEvt::MonitorOffline { monitor_id });
+			self.forget_slots(|key| key.monitor_id == monitor_id);
+			self
+				.link_generations
+				.retain(|(monitor, _), _| *monitor != monitor_id);
+			self.ownership.cleanup_monitor(monitor_id);
+		}
+		let now = Instant::now();
+		for mon in self.drm.monitors() {
+			if current
+				.get(&mon.context().id)
+				.is_some_and(|monitor| monitor.connector.link_bad)
+			{
+				self
+					.link_retrains
+					.retrain(u32::from(mon.connector_id()), now);
+			}
+		}
+		self.known_monitors = current;
+	}
+
+	/// Drops the textures, fence waits and generations of the matching slots.
+	fn forget_slots(&mut self, matches: impl Fn(&SlotKey) -> bool) {
+		self.textures.retain(|key, _| !matches(key));
+		self.swap_generations.retain(|key, _| !matches(key));
+		let waiting = self
+			.fence_tasks
+			.keys()
+			.filter(|key| matches(key))
+			.copied()
+			.collect::<Vec<_>>();
+		for key in waiting {
+			self.cancel_fence_wait(key);
+		}
+	}
+
+	fn link_generation(&self, monitor_id: MonitorId, session_id: SessionId) -> u64 {
+		self
+			.link_generations
+			.get(&(monitor_id, session_id))
+			.copied()
+			.unwrap_or(0)
+	}
+
+	/// Hands `key` back, tagged with the link generation it was swapped in under.
+	fn buffer_consumed(&self, key: SlotKey, release_fence: Option<OwnedFd>) -> RenderEvt {
+		RenderEvt::BufferConsumed {
+			session_id: key.session_id,
+			monitor_id: key.monitor_id,
+			buffer: key.buffer.into(),
+			generation: self
+				.swap_generations
+				.get(&key)
+				.copied()
+				.unwrap_or_else(|| self.link_generation(key.monitor_id, key.session_id)),
+			release_fence,
+		}
+	}
+
+	/// Hands back a buffer that was superseded before it was ever shown, so no fence is needed.
+	fn release_unshown(&mut self, monitor_id: MonitorId, session_id: SessionId, buffer: BufferSlot) {
+		let key = SlotKey::new(monitor_id, session_id, buffer);
+		self.ownership.mark_slot_client_owned(key);
+		self.emit_frame_dropped(monitor_id, session_id);
+		self.emit_event(self.buffer_consumed(key, None));
+	}
+
+	fn emit_frame_dropped(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self.emit_event(RenderEvt::FrameDropped {
+			session_id,
+			monitor_id,
+			reason: self.ownership.drop_reason(monitor_id, session_id),
+		});
+	}
+
+	fn cancel_fence_wait(&mut self, key: SlotKey) {
+		if let Some(handle) = self.fence_tasks.remove(&key) {
+			self.fence_scheduler.cancel(handle);
+		}
+	}
+
+	fn wait_for_acquire_fence(&mut self, key: SlotKey, fence_fd: OwnedFd) {
+		if let Some(existing) = self.fence_tasks.get(&key).copied() {
+			if let Ok(cloned_fd) = fence_fd.as_fd().try_clone_to_owned()
+				&& self
+					.fence_scheduler
+					.reschedule(existing, vec![cloned_fd], FenceWaitMode::All)
+			{
+				return;
+			}
+			self.fence_tasks.remove(&key);
+		}
+		let tx = self.fence_event_tx.clone();
+		let handle = self.fence_scheduler.schedule(
+			vec![fence_fd],
+			FenceWaitMode::All,
+			Box::new(move || {
+				let _ = tx.send(FenceEvent::Signaled { key });
+			}),
+		);
+		self.fence_tasks.insert(key, handle);
+	}
+
+	fn acquire_fence_signaled(&mut self, key: SlotKey) {
+		self.fence_tasks.remove(&key);
+		let Some(ready) = self.ownership.apply_acquire_fence_signaled(key) else {
+			return;
+		};
+		if let Some(previous) = ready.deferred {
+			self
+				.ownership
+				.queue_buffer_release(key.monitor_id, key.session_id, previous);
+		}
+		if let Some(superseded) = ready.release_now {
+			self.release_unshown(key.monitor_id, key.session_id, superseded);
+		}
+	}
+
+	/// Returns whether to keep running.
+	#[tracing::instrument(skip_all, fields(corr = correlation::span_field(self.corr)))]
+	fn handle_command(&mut self, cmd: RenderCmd) -> bool {
+		match cmd {
+			RenderCmd::Shutdown => {
+				warn!("received shutdown request from server");
+				return false;
+			}
+			RenderCmd::FramebufferLink {
+				payload,
+				dma_bufs,
+				session_id,
+			} => self.link(payload, dma_bufs, session_id),
+			RenderCmd::SetActiveSession {
+				session_id,
+				transition,
+			} => {
+				self.crossfade = session_id
+					.zip(transition)
+					.and_then(|(to, transition)| Crossfade::from_cmd(to, transition));
+				self.ownership.set_current_session(session_id);
+			}
+			RenderCmd::AssignMonitor {
+				monitor_id,
+				session_id,
+			} => self.ownership.set_monitor_session(monitor_id, session_id),
+			RenderCmd::Screenshot { request, .. } => {
+				self.emit_event(RenderEvt::Screenshot {
+					request,
+					result: Err("the gles render engine can't take screenshots".into()),
+				});
+			}
+			RenderCmd::Suspend => {
+				tracing::info!("system is suspending, rendering paused");
+				self.suspended = true;
+			}
+			RenderCmd::Resume => self.resume(),
+			RenderCmd::SessionRemoved { session_id, .. } => {
+				self.forget_slots(|key| key.session_id == session_id);
+				self
+					.link_generations
+					.retain(|(_, session), _| *session != session_id);
+				self.ownership.cleanup_session(session_id);
+				if self.ownership.current_session() == Some(session_id) {
+					self.ownership.set_current_session(None);
+				}
+			}
+			// Without present groups every monitor flips on its own.
+			RenderCmd::SwapBuffers {
+				monitor_id,
+				buffer,
+				session_id,
+				generation,
+				acquire_fence,
+				present_group: _,
+			} => self.swap(monitor_id, session_id, buffer, generation, acquire_fence),
+			RenderCmd::BufferUpload { session_id, .. } => {
+				warn!(%session_id, "the gles render engine doesn't support buffer_upload");
+			}
+			other @ (RenderCmd::DefineTransition(_)
+			| RenderCmd::SetPip { .. }
+			| RenderCmd::SetMonitorLayout { .. }
+			| RenderCmd::SetMirror { .. }
+			| RenderCmd::SetZoom { .. }
+			| RenderCmd::SetMonitorFilter { .. }
+			| RenderCmd::ZoomFocus { .. }
+			| RenderCmd::SetBackground(_)
+			| RenderCmd::DimSession { .. }
+			| RenderCmd::SetDebugHud { .. }
+			| RenderCmd::HudStats(_)
+			| RenderCmd::PresentGroup { .. }) => {
+				tracing::debug!(cmd = ?other, "not supported by the gles render engine, ignoring");
+			}
+		}
+		true
+	}
+
+	/// Imports both dma-bufs of a link, or neither so the client can reallocate the swapchain.
+	#[tracing::instrument(skip_all, fields(session_id = %session_id, monitor_id = %payload.monitor_id))]
+	fn link(
+		&mut self,
+		payload: tab_protocol::FramebufferLinkPayload,
+		dma_bufs: [OwnedFd; 2],
+		session_id: SessionId,
+	) {
+		let Ok(monitor_id) = payload.monitor_id.parse::<MonitorId>() else {
+			warn!(monitor_id = %payload.monitor_id, "invalid monitor id in framebuffer link");
+			return;
+		};
+		self
+			.link_generations
+			.insert((monitor_id, session_id), payload.generation);
+		self
+			.ownership
+			.drop_deferred_releases(monitor_id, session_id);
+		let egl_context = self.drm.egl_context();
+		let Some(mon) = self
+			.drm
+			.monitors_mut()
+			.find(|mon| mon.context().id == monitor_id)
+		else {
+			warn!(%monitor_id, "framebuffer link for unknown monitor");
+			return;
+		};
+		if let Err(e) = mon.make_current() {
+			warn!(%monitor_id, "failed to make monitor current: {e:?}");
+			return;
+		}
+		let gl = mon.context().gl.clone();
+		let proc_loader = |symbol: &str| {
+			egl_context
+				.lock()
+				.map(|ctx| ctx.get_proc_address(symbol))
+				.unwrap_or(std::ptr::null())
+		};
+		let mut imported = Vec::new();
+		for (idx, fd) in dma_bufs.into_iter().enumerate() {
+			let Some(slot) = BufferSlot::from_index(idx) else {
+				continue;
+			};
+			let params = ImportParams {
+				width: payload.width,
+				height: payload.height,
+				stride: payload.stride,
+				offset: payload.offset,
+				fourcc: payload.fourcc,
+				modifier: payload.modifier,
+				fd,
+			};
+			match DmaBufTexture::import(&gl, &proc_loader, params) {
+				Ok(texture) => imported.push((slot, texture)),
+				Err(e) => {
+					warn!(%monitor_id, ?slot, fourcc = payload.fourcc, "failed to import dmabuf: {e:?}");
+					let supported_formats = dmabuf_import::supported_formats(&proc_loader);
+					self.emit_event(RenderEvt::FramebufferLinkFailed {
+						session_id,
+						monitor_id,
+						reason: e.to_string(),
+						supported_formats,
+					});
+					return;
+				}
+			}
+		}
+		for (slot, texture) in imported {
+			let key = SlotKey::new(monitor_id, session_id, slot);
+			self.textures.insert(key, texture);
+			self.ownership.mark_slot_client_owned(key);
+		}
+		self
+			.ownership
+			.set_present_mode(monitor_id, session_id, payload.present_mode);
+	}
+
+	fn swap(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		buffer: tab_protocol::BufferIndex,
+		generation: u64,
+		acquire_fence: Option<OwnedFd>,
+	) {
+		let slot = BufferSlot::from(buffer);
+		let slot_key = SlotKey::new(monitor_id, session_id, slot);
+		let reason = if !self.known_monitors.contains_key(&monitor_id) {
+			Some("unknown_monitor")
+		} else if generation != self.link_generation(monitor_id, session_id) {
+			Some("stale_generation")
+		} else if !self.textures.contains_key(&slot_key) {
+			Some("unlinked_buffer")
+		} else {
+			None
+		};
+		if let Some(reason) = reason {
+			self.emit_event(RenderEvt::BufferRequestRejected {
+				session_id,
+				monitor_id,
+				buffer,
+				generation,
+				reason: Arc::from(reason),
+			});
+			return;
+		}
+		let transition =
+			self
+				.ownership
+				.apply_swap_request(monitor_id, session_id, slot, acquire_fence.is_some());
+		if let Some(pending) = transition.canceled_pending {
+			self.cancel_fence_wait(SlotKey::new(monitor_id, session_id, pending));
+			self.emit_frame_dropped(monitor_id, session_id);
+			self
+				.ownership
+				.queue_buffer_release(monitor_id, session_id, pending);
+		}
+		self.swap_generations.insert(slot_key, generation);
+		match acquire_fence {
+			Some(fence_fd) => self.wait_for_acquire_fence(slot_key, fence_fd),
+			None => self.cancel_fence_wait(slot_key),
+		}
+		if let Some(previous) = transition.previous_to_release {
+			self
+				.ownership
+				.queue_buffer_release(monitor_id, session_id, previous);
+		}
+		if let Some(superseded) = transition.release_now {
+			self.release_unshown(monitor_id, session_id, superseded);
+		}
+		self.emit_event(RenderEvt::BufferRequestAck {
+			session_id,
+			monitor_id,
+			buffer,
+			generation,
+		});
+	}
+
+	/// Takes back DRM master and has every session link its buffers again, as imports may not
+	/// have survived suspend.
+	fn resume(&mut self) {
+		self.suspended = false;
+		for (card, e) in reacquire_drm_master() {
+			warn!(card = %card.display(), "failed to take back DRM master: {e}");
+		}
+		self.sync_monitors();
+		let mut relink = HashMap::<SessionId, Vec<MonitorId>>::new();
+		let keys = self.textures.keys().copied().collect::<Vec<_>>();
+		for key in &keys {
+			let monitors = relink.entry(key.session_id).or_default();
+			if !monitors.contains(&key.monitor_id) {
+				monitors.push(key.monitor_id);
+			}
+		}
+		self.forget_slots(|_| true);
+		for key in self.ownership.evict_slots(&keys) {
+			self.emit_event(self.buffer_consumed(key, None));
+		}
+		for (session_id, monitor_ids) in relink {
+			self.emit_event(RenderEvt::BuffersInvalidated {
+				session_id,
+				monitor_ids,
+			});
+		}
+		tracing::info!("resumed from suspend");
+		self.emit_event(RenderEvt::Resumed);
+	}
+
+	/// The texture of the buffer a session shows on a monitor, if shift holds it.
+	fn shown_texture(&self, monitor_id: MonitorId, session_id: SessionId) -> Option<GLuint> {
+		let key = self
+			.ownership
+			.current_slot_key_for_session(monitor_id, session_id)?;
+		if self.ownership.owner(key) != Some(SlotOwner::ShiftOwned) {
+			return None;
+		}
+		self.textures.get(&key).map(DmaBufTexture::texture_id)
+	}
+
+	fn frame(&self, monitor_id: MonitorId, now: Instant) -> Frame {
+		if let Some(crossfade) = self.crossfade
+			&& !self.ownership.is_monitor_pinned(monitor_id)
+		{
+			let from = self.shown_texture(monitor_id, crossfade.from_session_id);
+			let to = self.shown_texture(monitor_id, crossfade.to_session_id);
+			return match (from, to) {
+				(Some(from), Some(to)) => Frame::Crossfade {
+					from,
+					to,
+					mix: crossfade.easing.apply(crossfade.progress(now)) as f32,
+				},
+				(_, Some(to)) => Frame::Texture(to),
+				_ => Frame::Black,
+			};
+		}
+		self
+			.ownership
+			.session_for_monitor(monitor_id)
+			.and_then(|session_id| self.shown_texture(monitor_id, session_id))
+			.map_or(Frame::Black, Frame::Texture)
+	}
+
+	fn render_and_commit(&mut self) -> Result<bool, RenderError> {
+		let monitor_ids = self
+			.drm
+			.monitors()
+			.map(|mon| mon.context().id)
+			.collect::<Vec<_>>();
+		self.ownership.ensure_current_session_monitors(&monitor_ids);
+		let now = Instant::now();
+		let mut frames = HashMap::new();
+		for monitor_id in monitor_ids {
+			frames.insert(monitor_id, self.frame(monitor_id, now));
+		}
+		let mut resized = Vec::new();
+		for mon in self.drm.monitors_mut() {
+			if !mon.can_render() {
+				continue;
+			}
+			if let Err(e) = mon.make_current() {
+				warn!(monitor_id = %mon.context().id, "make_current failed: {e:?}");
+				continue;
+			}
+			if !mon.context().gl_debug_checked {
+				mon.context_mut().gl_debug_checked = true;
+				self.gl_debug.enable_current();
+			}
+			let (width, height) = mon.active_mode().size();
+			let orientation = self
+				.known_monitors
+				.get(&mon.context().id)
+				.and_then(|monitor| monitor.connector.panel_orientation)
+				.unwrap_or(PanelOrientation::Normal);
+			let context = mon.context_mut();
+			if context.resize(width as usize, height as usize, orientation) {
+				// Buffers linked at the old size must not be drawn again, not even this frame.
+				resized.push(context.id);
+				context.draw(Frame::Black);
+				continue;
+			}
+			context.draw(frames.get(&context.id).copied().unwrap_or(Frame::Black));
+			if self.gl_debug.is_strict() {
+				let error = unsafe { context.gl.GetError() };
+				if error != NO_ERROR {
+					self.gl_debug.record_error(error);
+				}
+			}
+		}
+		if self
+			.crossfade
+			.is_some_and(|crossfade| crossfade.progress(now) >= 1.0)
+		{
+			self.crossfade = None;
+		}
+		for monitor_id in resized {
+			self.invalidate_monitor_slots(monitor_id);
+		}
+		if self.gl_debug.is_strict() {
+			let errors = self.gl_debug.take_errors();
+			if errors > 0 {
+				error!(errors, "GL errors while drawing, dropping the frame");
+				return Ok(false);
+			}
+		}
+
+		let page_flipped = self
+			.drm
+			.monitors()
+			.filter(|mon| mon.was_drawn())
+			.map(|mon| mon.context().id)
+			.collect::<Vec<_>>();
+		let swap_result = self.drm.swap_buffers_with_result()?;
+		self.ownership.mark_presented(&page_flipped);
+		self.report_active_shown(&page_flipped);
+		self.release_deferred(swap_result.render_fence);
+		self.emit_event(RenderEvt::PageFlip {
+			monitors: page_flipped,
+		});
+		Ok(!swap_result.committed_connectors.is_empty())
+	}
+
+	/// Forgets what sessions linked for a monitor that changed size. Buffers shift held go back
+	/// after the next commit; clients link new ones on `monitor_changed`.
+	fn invalidate_monitor_slots(&mut self, monitor_id: MonitorId) {
+		tracing::info!(%monitor_id, "monitor resized, dropping buffers linked at the old size");
+		self.textures.retain(|key, _| key.monitor_id != monitor_id);
+		let waiting = self
+			.fence_tasks
+			.keys()
+			.filter(|key| key.monitor_id == monitor_id)
+			.copied()
+			.collect::<Vec<_>>();
+		for key in waiting {
+			self.cancel_fence_wait(key);
+		}
+		for key in self.ownership.reset_monitor(monitor_id) {
+			self
+				.ownership
+				.queue_buffer_release(key.monitor_id, key.session_id, key.buffer);
+		}
+	}
+
+	/// Hands back the buffers replaced on screen, each with its own copy of the commit's fence.
+	fn release_deferred(&mut self, render_fence: i32) {
+		for item in self.ownership.take_deferred_releases() {
+			let key = SlotKey::new(item.monitor_id, item.session_id, item.buffer);
+			self.ownership.mark_slot_client_owned(key);
+			let release_fence = (render_fence >= 0)
+				.then(|| unsafe { libc::dup(render_fence) })
+				.filter(|fd| *fd >= 0)
+				.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+			self.emit_event(self.buffer_consumed(key, release_fence));
+		}
+	}
+
+	fn report_active_shown(&mut self, page_flipped: &[MonitorId]) {
+		let current = self.ownership.current_session();
+		if current == self.active_shown {
+			return;
+		}
+		let Some(session_id) = current else {
+			self.active_shown = None;
+			return;
+		};
+		if page_flipped
+			.iter()
+			.any(|monitor_id| self.ownership.is_presented(*monitor_id, session_id))
+		{
+			self.active_shown = current;
+			self.emit_event(RenderEvt::SessionShown { session_id });
+		}
+	}
+}
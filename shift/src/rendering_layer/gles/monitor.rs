@@ -0,0 +1,219 @@
+use std::cell::OnceCell;
+
+use easydrm::{
+	Monitor, MonitorContextCreationRequest,
+	gl::{self, types::GLuint},
+};
+
+use tab_protocol::PanelOrientation;
+
+use crate::monitor::{Backlight, Connector, Edid, Monitor as ServerLayerMonitor, MonitorId};
+
+use super::shader::{QuadBuffer, Shader, ShaderError};
+
+const VERTEX_SHADER: &std::ffi::CStr = c"
+uniform mat2 u_rotation;
+attribute vec2 a_position;
+attribute vec2 a_tex_coord;
+varying vec2 v_tex_coord;
+void main() {
+	v_tex_coord = a_tex_coord;
+	gl_Position = vec4(u_rotation * a_position, 0.0, 1.0);
+}
+";
+
+/// Blends two textures, `u_mix` of the way from `u_from` to `u_to`. Sessions are opaque.
+const FRAGMENT_SHADER: &std::ffi::CStr = c"
+precision mediump float;
+uniform sampler2D u_from;
+uniform sampler2D u_to;
+uniform float u_mix;
+varying vec2 v_tex_coord;
+void main() {
+	vec3 from = texture2D(u_from, v_tex_coord).rgb;
+	vec3 to = texture2D(u_to, v_tex_coord).rgb;
+	gl_FragColor = vec4(mix(from, to, u_mix), 1.0);
+}
+";
+
+/// What a monitor shows for one frame.
+#[derive(Debug, Clone, Copy)]
+pub enum Frame {
+	Black,
+	Texture(GLuint),
+	Crossfade { from: GLuint, to: GLuint, mix: f32 },
+}
+
+/// Per-monitor state of the GLES engine: the program and quad drawing sessions into the
+/// monitor's framebuffer.
+pub struct GlesMonitor {
+	pub gl: gl::Gles2,
+	pub width: usize,
+	pub height: usize,
+	/// Random until [`Self::identify`], which happens before the monitor is reported.
+	pub id: MonitorId,
+	identified: bool,
+	/// Whether [`crate::rendering_layer::gl_debug::GlDebug::enable_current`] ran on this
+	/// monitor's context.
+	pub gl_debug_checked: bool,
+	shader: Shader,
+	quad: QuadBuffer,
+	u_from: i32,
+	u_to: i32,
+	u_mix: i32,
+	u_rotation: i32,
+	orientation: PanelOrientation,
+	/// Read from sysfs the first time the monitor is reported.
+	edid: OnceCell<Option<Edid>>,
+	backlight: OnceCell<Option<Backlight>>,
+	/// Found the first time the monitor is reported, its properties are read on every sync.
+	connector: OnceCell<Option<Connector>>,
+}
+
+impl GlesMonitor {
+	#[tracing::instrument(skip_all)]
+	pub fn new(req: &MonitorContextCreationRequest<'_>) -> Result<Self, ShaderError> {
+		let shader = Shader::new(
+			req.gl,
+			VERTEX_SHADER,
+			FRAGMENT_SHADER,
+			&[
+				(QuadBuffer::POSITION, c"a_position"),
+				(QuadBuffer::TEX_COORD, c"a_tex_coord"),
+			],
+		)?;
+		Ok(Self {
+			gl: req.gl.clone(),
+			width: req.width,
+			height: req.height,
+			id: MonitorId::rand(),
+			identified: false,
+			gl_debug_checked: false,
+			u_from: shader.uniform(c"u_from"),
+			u_to: shader.uniform(c"u_to"),
+			u_mix: shader.uniform(c"u_mix"),
+			u_rotation: shader.uniform(c"u_rotation"),
+			orientation: PanelOrientation::Normal,
+			shader,
+			quad: QuadBuffer::new(req.gl),
+			edid: OnceCell::new(),
+			backlight: OnceCell::new(),
+			connector: OnceCell::new(),
+		})
+	}
+
+	pub fn is_identified(&self) -> bool {
+		self.identified
+	}
+
+	/// Replaces the placeholder id with the monitor's stable one, see [`crate::monitor::MonitorIds`].
+	pub fn identify(&mut self, id: MonitorId) {
+		self.id = id;
+		self.identified = true;
+	}
+
+	/// Records the size of the current mode and how the panel is mounted, returning whether the
+	/// upright picture changed size since the last frame.
+	pub fn resize(&mut self, width: usize, height: usize, orientation: PanelOrientation) -> bool {
+		let upright = |(width, height): (usize, usize), orientation: PanelOrientation| {
+			if orientation.is_sideways() {
+				(height, width)
+			} else {
+				(width, height)
+			}
+		};
+		let changed =
+			upright((self.width, self.height), self.orientation) != upright((width, height), orientation);
+		(self.width, self.height, self.orientation) = (width, height, orientation);
+		changed
+	}
+
+	/// Draws `frame` over the whole framebuffer bound by `make_current`.
+	pub fn draw(&self, frame: Frame) {
+		let gl = &self.gl;
+		unsafe {
+			gl.Viewport(0, 0, self.width as i32, self.height as i32);
+			gl.ClearColor(0.0, 0.0, 0.0, 1.0);
+			gl.Clear(gl::COLOR_BUFFER_BIT);
+		}
+		let (from, to, mix) = match frame {
+			Frame::Black => return,
+			Frame::Texture(texture) => (texture, texture, 0.0),
+			Frame::Crossfade { from, to, mix } => (from, to, mix),
+		};
+		self.shader.bind();
+		unsafe {
+			gl.ActiveTexture(gl::TEXTURE0);
+			gl.BindTexture(gl::TEXTURE_2D, from);
+			gl.ActiveTexture(gl::TEXTURE1);
+			gl.BindTexture(gl::TEXTURE_2D, to);
+			gl.Uniform1i(self.u_from, 0);
+			gl.Uniform1i(self.u_to, 1);
+			gl.Uniform1f(self.u_mix, mix);
+			gl.UniformMatrix2fv(
+				self.u_rotation,
+				1,
+				gl::FALSE,
+				rotation(self.orientation).as_ptr(),
+			);
+		}
+		self.quad.draw();
+		unsafe {
+			gl.BindTexture(gl::TEXTURE_2D, 0);
+			gl.ActiveTexture(gl::TEXTURE0);
+			gl.BindTexture(gl::TEXTURE_2D, 0);
+			gl.UseProgram(0);
+			gl.Flush();
+		}
+	}
+
+	pub fn get_server_layer_monitor(monitor: &Monitor<Self>) -> ServerLayerMonitor {
+		let connector_id = u32::from(monitor.connector_id());
+		let edid = monitor
+			.context()
+			.edid
+			.get_or_init(|| Edid::for_connector(connector_id))
+			.clone();
+		let backlight = monitor
+			.context()
+			.backlight
+			.get_or_init(|| Backlight::for_connector(connector_id))
+			.clone();
+		let connector = monitor
+			.context()
+			.connector
+			.get_or_init(|| Connector::open(connector_id))
+			.as_ref()
+			.map(Connector::props)
+			.unwrap_or_default();
+		let (width, height) = monitor.size();
+		let sideways = connector
+			.panel_orientation
+			.is_some_and(PanelOrientation::is_sideways);
+		let (width, height) = if sideways {
+			(height, width)
+		} else {
+			(width, height)
+		};
+		ServerLayerMonitor {
+			height: height as _,
+			width: width as _,
+			id: monitor.context().id,
+			name: format!("Monitor {connector_id}"),
+			refresh_rate: monitor.active_mode().vrefresh(),
+			edid,
+			backlight,
+			connector,
+		}
+	}
+}
+
+/// Column-major matrix turning the upright picture's clip space onto the panel's.
+fn rotation(orientation: PanelOrientation) -> [f32; 4] {
+	match orientation {
+		PanelOrientation::Normal => [1.0, 0.0, 0.0, 1.0],
+		PanelOrientation::UpsideDown => [-1.0, 0.0, 0.0, -1.0],
+		PanelOrientation::LeftSideUp => [0.0, 1.0, -1.0, 0.0],
+		PanelOrientation::RightSideUp => [0.0, -1.0, 1.0, 0.0],
+	}
+}
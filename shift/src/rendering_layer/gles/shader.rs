@@ -0,0 +1,174 @@
+use std::ffi::CStr;
+
+use easydrm::gl::{self, types::GLuint};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ShaderError {
+	#[error("shader compilation failed: {0}")]
+	Compile(String),
+	#[error("program link failed: {0}")]
+	Link(String),
+}
+
+/// A linked GLES program, deleted on drop.
+pub struct Shader {
+	gl: gl::Gles2,
+	program: GLuint,
+}
+
+impl Shader {
+	/// Compiles and links `vertex` and `fragment`, with `attributes` bound to their locations.
+	pub fn new(
+		gl: &gl::Gles2,
+		vertex: &CStr,
+		fragment: &CStr,
+		attributes: &[(GLuint, &CStr)],
+	) -> Result<Self, ShaderError> {
+		let vertex = compile(gl, gl::VERTEX_SHADER, vertex)?;
+		let fragment = match compile(gl, gl::FRAGMENT_SHADER, fragment) {
+			Ok(fragment) => fragment,
+			Err(e) => {
+				unsafe { gl.DeleteShader(vertex) };
+				return Err(e);
+			}
+		};
+		unsafe {
+			let program = gl.CreateProgram();
+			gl.AttachShader(program, vertex);
+			gl.AttachShader(program, fragment);
+			for (location, name) in attributes {
+				gl.BindAttribLocation(program, *location, name.as_ptr());
+			}
+			gl.LinkProgram(program);
+			// The program keeps them alive for as long as it needs them.
+			gl.DeleteShader(vertex);
+			gl.DeleteShader(fragment);
+			let mut linked = 0;
+			gl.GetProgramiv(program, gl::LINK_STATUS, &mut linked);
+			if linked == 0 {
+				let log = info_log(|len, written, buf| gl.GetProgramInfoLog(program, len, written, buf));
+				gl.DeleteProgram(program);
+				return Err(ShaderError::Link(log));
+			}
+			Ok(Self {
+				gl: gl.clone(),
+				program,
+			})
+		}
+	}
+
+	pub fn bind(&self) {
+		unsafe { self.gl.UseProgram(self.program) };
+	}
+
+	/// Location of a uniform, -1 if the program doesn't use it.
+	pub fn uniform(&self, name: &CStr) -> i32 {
+		unsafe { self.gl.GetUniformLocation(self.program, name.as_ptr()) }
+	}
+}
+
+impl Drop for Shader {
+	fn drop(&mut self) {
+		unsafe { self.gl.DeleteProgram(self.program) };
+	}
+}
+
+fn compile(gl: &gl::Gles2, kind: gl::types::GLenum, source: &CStr) -> Result<GLuint, ShaderError> {
+	unsafe {
+		let shader = gl.CreateShader(kind);
+		gl.ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
+		gl.CompileShader(shader);
+		let mut compiled = 0;
+		gl.GetShaderiv(shader, gl::COMPILE_STATUS, &mut compiled);
+		if compiled == 0 {
+			let log = info_log(|len, written, buf| gl.GetShaderInfoLog(shader, len, written, buf));
+			gl.DeleteShader(shader);
+			return Err(ShaderError::Compile(log));
+		}
+		Ok(shader)
+	}
+}
+
+fn info_log(read: impl FnOnce(i32, *mut i32, *mut gl::types::GLchar)) -> String {
+	let mut buf = vec![0u8; 1024];
+	let mut written = 0;
+	read(buf.len() as i32, &mut written, buf.as_mut_ptr().cast());
+	buf.truncate(written.max(0) as usize);
+	String::from_utf8_lossy(&buf).into_owned()
+}
+
+/// A vertex buffer holding a quad that covers the viewport, as a triangle strip of position and
+/// texture coordinates. Row 0 of a texture lands at the top.
+pub struct QuadBuffer {
+	gl: gl::Gles2,
+	vbo: GLuint,
+}
+
+impl QuadBuffer {
+	pub const POSITION: GLuint = 0;
+	pub const TEX_COORD: GLuint = 1;
+
+	#[rustfmt::skip]
+	const VERTICES: [f32; 16] = [
+		-1.0, -1.0, 0.0, 1.0,
+		1.0, -1.0, 1.0, 1.0,
+		-1.0, 1.0, 0.0, 0.0,
+		1.0, 1.0, 1.0, 0.0,
+	];
+
+	pub fn new(gl: &gl::Gles2) -> Self {
+		let mut vbo = 0;
+		unsafe {
+			gl.GenBuffers(1, &mut vbo);
+			gl.BindBuffer(gl::ARRAY_BUFFER, vbo);
+			gl.BufferData(
+				gl::ARRAY_BUFFER,
+				size_of_val(&Self::VERTICES) as isize,
+				Self::VERTICES.as_ptr().cast(),
+				gl::STATIC_DRAW,
+			);
+			gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+		}
+		Self {
+			gl: gl.clone(),
+			vbo,
+		}
+	}
+
+	/// Draws the quad with the bound program.
+	pub fn draw(&self) {
+		let stride = (4 * size_of::<f32>()) as i32;
+		unsafe {
+			self.gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+			self.gl.VertexAttribPointer(
+				Self::POSITION,
+				2,
+				gl::FLOAT,
+				gl::FALSE,
+				stride,
+				std::ptr::null(),
+			);
+			self.gl.VertexAttribPointer(
+				Self::TEX_COORD,
+				2,
+				gl::FLOAT,
+				gl::FALSE,
+				stride,
+				(2 * size_of::<f32>()) as *const _,
+			);
+			self.gl.EnableVertexAttribArray(Self::POSITION);
+			self.gl.EnableVertexAttribArray(Self::TEX_COORD);
+			self.gl.DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+			self.gl.DisableVertexAttribArray(Self::POSITION);
+			self.gl.DisableVertexAttribArray(Self::TEX_COORD);
+			self.gl.BindBuffer(gl::ARRAY_BUFFER, 0);
+		}
+	}
+}
+
+impl Drop for QuadBuffer {
+	fn drop(&mut self) {
+		unsafe { self.gl.DeleteBuffers(1, &self.vbo) };
+	}
+}
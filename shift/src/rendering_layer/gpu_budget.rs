@@ -0,0 +1,178 @@
+//! Estimated GPU memory held by imported session buffers, and which sessions give it back.
+//! - sessions off screen for longer than `idle_after` are always evicted
+//! - while over the budget, the longest-hidden sessions go first
+//!
+//! Sessions on screen are never evicted, even if they alone exceed the budget. Unless
+//! `SHIFT_FRONT_BUFFER_RETENTION=evict`, eviction also keeps the buffer each hidden session last
+//! showed on a monitor, so switching back to it doesn't wait for the client to draw.
+
+use std::{
+	collections::{HashMap, HashSet},
+	time::{Duration, Instant},
+};
+
+use tab_protocol::GpuMemoryStats;
+
+use crate::sessions::SessionId;
+
+const DEFAULT_BUDGET_MB: u64 = 512;
+const DEFAULT_IDLE_AFTER: Duration = Duration::from_secs(300);
+
+/// What eviction does with the buffer a hidden session last showed on a monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrontBufferRetention {
+	/// Keeps it imported and held by shift, outside the budget, until the session shows another.
+	#[default]
+	Keep,
+	/// Evicts it with the rest, so the session's monitors show the background until it draws.
+	Evict,
+}
+
+impl FrontBufferRetention {
+	fn from_env() -> Self {
+		match std::env::var("SHIFT_FRONT_BUFFER_RETENTION").as_deref() {
+			Ok("evict") => Self::Evict,
+			Ok("keep") | Err(_) => Self::Keep,
+			Ok(other) => {
+				tracing::warn!(
+					value = other,
+					"unknown SHIFT_FRONT_BUFFER_RETENTION, keeping front buffers"
+				);
+				Self::Keep
+			}
+		}
+	}
+}
+
+pub struct GpuBudget {
+	budget_bytes: u64,
+	idle_after: Duration,
+	front_retention: FrontBufferRetention,
+	/// When each session was last on screen, or linked buffers.
+	last_used: HashMap<SessionId, Instant>,
+	evictions: u64,
+}
+
+impl GpuBudget {
+	pub fn new(budget_bytes: u64, idle_after: Duration) -> Self {
+		Self {
+			budget_bytes,
+			idle_after,
+			front_retention: FrontBufferRetention::default(),
+			last_used: HashMap::new(),
+			evictions: 0,
+		}
+	}
+
+	/// Reads `SHIFT_GPU_BUDGET_MB`, `SHIFT_GPU_IDLE_EVICT_SECS` and `SHIFT_FRONT_BUFFER_RETENTION`.
+	pub fn from_env() -> Self {
+		let budget_mb = std::env::var("SHIFT_GPU_BUDGET_MB")
+			.ok()
+			.and_then(|v| v.parse::<u64>().ok())
+			.unwrap_or(DEFAULT_BUDGET_MB);
+		let idle_after = std::env::var("SHIFT_GPU_IDLE_EVICT_SECS")
+			.ok()
+			.and_then(|v| v.parse::<u64>().ok())
+			.map_or(DEFAULT_IDLE_AFTER, Duration::from_secs);
+		Self {
+			front_retention: FrontBufferRetention::from_env(),
+			..Self::new(budget_mb.saturating_mul(1024 * 1024), idle_after)
+		}
+	}
+
+	pub fn front_retention(&self) -> FrontBufferRetention {
+		self.front_retention
+	}
+
+	pub fn touch(&mut self, session_id: SessionId, now: Instant) {
+		self.last_used.insert(session_id, now);
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.last_used.remove(&session_id);
+	}
+
+	/// Picks the sessions whose buffers to evict, given the bytes each one holds. Sessions in
+	/// `shown` count as used now.
+	pub fn select_evictions(
+		&mut self,
+		usage: &HashMap<SessionId, u64>,
+		shown: &HashSet<SessionId>,
+		now: Instant,
+	) -> Vec<SessionId> {
+		for session_id in shown {
+			self.touch(*session_id, now);
+		}
+		let mut total = usage.values().sum::<u64>();
+		let mut candidates = usage
+			.iter()
+			.filter(|(session_id, _)| !shown.contains(session_id))
+			.map(|(session_id, bytes)| {
+				let last_used = self.last_used.get(session_id).copied();
+				(*session_id, *bytes, last_used)
+			})
+			.collect::<Vec<_>>();
+		// Never touched sorts first: nothing has looked at it since shift started tracking.
+		candidates.sort_by_key(|(_, _, last_used)| *last_used);
+
+		let mut evicted = Vec::new();
+		for (session_id, bytes, last_used) in candidates {
+			let idle = last_used.is_none_or(|at| now.saturating_duration_since(at) >= self.idle_after);
+			if idle || total > self.budget_bytes {
+				total = total.saturating_sub(bytes);
+				evicted.push(session_id);
+			}
+		}
+		self.evictions += evicted.len() as u64;
+		evicted
+	}
+
+	pub fn stats(&self, used_bytes: u64) -> GpuMemoryStats {
+		GpuMemoryStats {
+			used_bytes,
+			budget_bytes: self.budget_bytes,
+			evictions: self.evictions,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const MB: u64 = 1024 * 1024;
+
+	#[test]
+	fn evicts_longest_hidden_sessions_until_under_budget() {
+		let start = Instant::now();
+		let mut budget = GpuBudget::new(20 * MB, Duration::from_secs(60));
+		let [shown, old, recent] = [0, 0, 0].map(|_| SessionId::rand());
+		budget.touch(old, start);
+		budget.touch(recent, start + Duration::from_secs(10));
+		let usage = HashMap::from([(shown, 16 * MB), (old, 8 * MB), (recent, 8 * MB)]);
+		let evicted = budget.select_evictions(
+			&usage,
+			&HashSet::from([shown]),
+			start + Duration::from_secs(20),
+		);
+		assert_eq!(evicted, vec![old, recent]);
+		assert_eq!(budget.stats(0).evictions, 2);
+	}
+
+	#[test]
+	fn evicts_idle_sessions_even_under_budget() {
+		let start = Instant::now();
+		let mut budget = GpuBudget::new(64 * MB, Duration::from_secs(60));
+		let [shown, idle, fresh] = [0, 0, 0].map(|_| SessionId::rand());
+		budget.touch(shown, start);
+		budget.touch(idle, start);
+		budget.touch(fresh, start + Duration::from_secs(30));
+		let usage = HashMap::from([(shown, 32 * MB), (idle, MB), (fresh, MB)]);
+		let evicted = budget.select_evictions(
+			&usage,
+			&HashSet::from([shown]),
+			start + Duration::from_secs(61),
+		);
+		assert_eq!(evicted, vec![idle]);
+	}
+}
@@ -0,0 +1,73 @@
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use tab_protocol::GpuMemoryStats;
+
+use crate::{comms::render2server::RenderHealth, monitor::MonitorId};
+
+/// How often [`HealthCounters::take_report`] produces a report.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Render loop counters behind the `compositor_health` heartbeat.
+pub struct HealthCounters {
+	window_start: Instant,
+	loops: u32,
+	last_flips: HashMap<MonitorId, Instant>,
+}
+
+impl HealthCounters {
+	pub fn new() -> Self {
+		Self {
+			window_start: Instant::now(),
+			loops: 0,
+			last_flips: HashMap::new(),
+		}
+	}
+
+	pub fn record_loop(&mut self) {
+		self.loops = self.loops.saturating_add(1);
+	}
+
+	pub fn record_page_flips(&mut self, monitor_ids: &[MonitorId], now: Instant) {
+		for monitor_id in monitor_ids {
+			self.last_flips.insert(*monitor_id, now);
+		}
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.last_flips.remove(&monitor_id);
+	}
+
+	/// Closes the current window once it is [`REPORT_INTERVAL`] old.
+	pub fn take_report(
+		&mut self,
+		monitor_ids: impl Iterator<Item = MonitorId>,
+		fence_wait_backlog: usize,
+		gpu_memory: GpuMemoryStats,
+		now: Instant,
+	) -> Option<RenderHealth> {
+		let elapsed = now.saturating_duration_since(self.window_start);
+		if elapsed < REPORT_INTERVAL {
+			return None;
+		}
+		let loops_per_sec = (self.loops as f64 / elapsed.as_secs_f64()).round() as u32;
+		self.window_start = now;
+		self.loops = 0;
+		Some(RenderHealth {
+			loops_per_sec,
+			fence_wait_backlog,
+			gpu_memory,
+			last_flip_ages: monitor_ids
+				.map(|id| {
+					let age = self
+						.last_flips
+						.get(&id)
+						.map(|flip| now.saturating_duration_since(*flip));
+					(id, age)
+				})
+				.collect(),
+		})
+	}
+}
@@ -0,0 +1,183 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	fmt::Write,
+	time::{Duration, Instant},
+};
+
+use skia_safe::{
+	Canvas, Color, FontMgr, Paint, Point, Rect,
+	textlayout::{FontCollection, ParagraphBuilder, ParagraphStyle, TextStyle},
+};
+
+use crate::{comms::server2render::HudStats, monitor::MonitorId};
+
+/// How far back page flips are kept for the FPS and frame time figures.
+const FRAME_WINDOW: Duration = Duration::from_secs(2);
+const MARGIN: f32 = 12.0;
+const PADDING: f32 = 8.0;
+const MAX_WIDTH: f32 = 520.0;
+
+/// On-screen overlay with pacing numbers, drawn over every monitor while enabled.
+pub struct DebugHud {
+	enabled: bool,
+	fonts: FontCollection,
+	page_flips: HashMap<MonitorId, VecDeque<Instant>>,
+	stats: HudStats,
+}
+
+impl DebugHud {
+	pub fn new() -> Self {
+		let mut fonts = FontCollection::new();
+		fonts.set_default_font_manager(FontMgr::new(), None);
+		Self {
+			enabled: false,
+			fonts,
+			page_flips: HashMap::new(),
+			stats: HudStats::default(),
+		}
+	}
+
+	pub fn is_enabled(&self) -> bool {
+		self.enabled
+	}
+
+	pub fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+		if !enabled {
+			self.page_flips.clear();
+		}
+	}
+
+	pub fn set_stats(&mut self, stats: HudStats) {
+		self.stats = stats;
+	}
+
+	pub fn record_page_flips(&mut self, monitor_ids: &[MonitorId], now: Instant) {
+		if !self.enabled {
+			return;
+		}
+		for monitor_id in monitor_ids {
+			let flips = self.page_flips.entry(*monitor_id).or_default();
+			flips.push_back(now);
+			while flips
+				.front()
+				.is_some_and(|t| now.saturating_duration_since(*t) > FRAME_WINDOW)
+			{
+				flips.pop_front();
+			}
+		}
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.page_flips.remove(&monitor_id);
+	}
+
+	fn text(&self, monitor_id: MonitorId, name: &str, fence_waits: usize) -> String {
+		let mut frame_times = self
+			.page_flips
+			.get(&monitor_id)
+			.map(|flips| {
+				flips
+					.iter()
+					.zip(flips.iter().skip(1))
+					.map(|(a, b)| b.saturating_duration_since(*a).as_secs_f64() * 1000.0)
+					.collect::<Vec<_>>()
+			})
+			.unwrap_or_default();
+		frame_times.sort_by(f64::total_cmp);
+		let fps = self
+			.page_flips
+			.get(&monitor_id)
+			.and_then(|flips| {
+				let last = flips.back()?;
+				Some(
+					flips
+						.iter()
+						.filter(|t| last.saturating_duration_since(**t) < Duration::from_secs(1))
+						.count(),
+				)
+			})
+			.unwrap_or(0);
+		let stats = &self.stats;
+
+		let mut text = String::new();
+		let _ = writeln!(text, "{name} ({monitor_id})  {fps} fps");
+		let _ = writeln!(
+			text,
+			"frame time p50 {:.1} ms  p95 {:.1} ms  p99 {:.1} ms",
+			percentile(&frame_times, 0.50),
+			percentile(&frame_times, 0.95),
+			percentile(&frame_times, 0.99),
+		);
+		let _ = writeln!(
+			text,
+			"active session {}",
+			stats.active_session.as_deref().unwrap_or("none")
+		);
+		let _ = writeln!(
+			text,
+			"clients {}  pending requests {}  waiting flip {}  fence waits {fence_waits}",
+			stats.connected_clients, stats.pending_buffer_requests, stats.waiting_flip,
+		);
+		let _ = write!(
+			text,
+			"swaps/s {}  frame_done/s {}  skipped/s {}",
+			stats.swap_buffers_per_sec, stats.frame_done_per_sec, stats.frames_skipped_per_sec,
+		);
+		text
+	}
+
+	pub fn draw(&self, canvas: &Canvas, monitor_id: MonitorId, name: &str, fence_waits: usize) {
+		let mut text_style = TextStyle::new();
+		text_style.set_color(Color::WHITE);
+		text_style.set_font_size(14.0);
+		text_style.set_font_families(&["monospace"]);
+		let mut paragraph_style = ParagraphStyle::new();
+		paragraph_style.set_text_style(&text_style);
+
+		let mut builder = ParagraphBuilder::new(&paragraph_style, self.fonts.clone());
+		builder.add_text(self.text(monitor_id, name, fence_waits));
+		let mut paragraph = builder.build();
+		paragraph.layout(MAX_WIDTH);
+
+		let background = Rect::from_xywh(
+			MARGIN,
+			MARGIN,
+			paragraph.longest_line() + PADDING * 2.0,
+			paragraph.height() + PADDING * 2.0,
+		);
+		let mut paint = Paint::default();
+		paint.set_argb(170, 0, 0, 0);
+		canvas.draw_rect(background, &paint);
+		paragraph.paint(canvas, Point::new(MARGIN + PADDING, MARGIN + PADDING));
+	}
+
+	/// Draws `text` large in the middle of a `width` by `height` canvas, whether or not the HUD
+	/// is enabled.
+	pub fn draw_notice(&self, canvas: &Canvas, text: &str, width: f32, height: f32) {
+		let mut text_style = TextStyle::new();
+		text_style.set_color(Color::WHITE);
+		text_style.set_font_size(32.0);
+		let mut paragraph_style = ParagraphStyle::new();
+		paragraph_style.set_text_style(&text_style);
+
+		let mut builder = ParagraphBuilder::new(&paragraph_style, self.fonts.clone());
+		builder.add_text(text);
+		let mut paragraph = builder.build();
+		paragraph.layout(width);
+		let origin = Point::new(
+			(width - paragraph.longest_line()) / 2.0,
+			(height - paragraph.height()) / 2.0,
+		);
+		paragraph.paint(canvas, origin);
+	}
+}
+
+/// Nearest-rank percentile of already sorted samples, 0 when there are none.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+	if sorted.is_empty() {
+		return 0.0;
+	}
+	let rank = (p * sorted.len() as f64).ceil() as usize;
+	sorted[rank.clamp(1, sorted.len()) - 1]
+}
@@ -0,0 +1,105 @@
+//! Sampling of `transition_define` keyframes, separate from drawing so it can be tested.
+//! - properties without a track keep their default value
+//! - between two keyframes the value follows the later keyframe's easing
+
+use tab_protocol::{Keyframe, KeyframeProperty, KeyframeTrack};
+
+/// How one session is drawn at some point of a transition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerState {
+	pub blur: f32,
+	pub opacity: f32,
+	/// Fractions of the monitor size.
+	pub translate_x: f32,
+	pub translate_y: f32,
+}
+
+/// The tracks of one session, `from` or `to`, validated by the server.
+#[derive(Debug, Clone, Default)]
+pub struct KeyframeLayer {
+	tracks: Vec<KeyframeTrack>,
+}
+
+impl KeyframeLayer {
+	pub fn new(tracks: Vec<KeyframeTrack>) -> Self {
+		Self { tracks }
+	}
+
+	pub fn sample(&self, progress: f64) -> LayerState {
+		let value = |property: KeyframeProperty| {
+			self
+				.tracks
+				.iter()
+				.find(|track| track.property == property)
+				.map_or(property.default_value(), |track| {
+					sample_track(&track.keyframes, progress)
+				}) as f32
+		};
+		LayerState {
+			blur: value(KeyframeProperty::Blur),
+			opacity: value(KeyframeProperty::Opacity),
+			translate_x: value(KeyframeProperty::TranslateX),
+			translate_y: value(KeyframeProperty::TranslateY),
+		}
+	}
+}
+
+fn sample_track(keyframes: &[Keyframe], progress: f64) -> f64 {
+	let Some(next) = keyframes
+		.iter()
+		.position(|keyframe| keyframe.at >= progress)
+	else {
+		return keyframes.last().map_or(0.0, |keyframe| keyframe.value);
+	};
+	let to = keyframes[next];
+	let Some(from) = next.checked_sub(1).map(|previous| keyframes[previous]) else {
+		return to.value;
+	};
+	let t = (progress - from.at) / (to.at - from.at);
+	from.value + (to.value - from.value) * to.easing.apply(t)
+}
+
+#[cfg(test)]
+mod tests {
+	use tab_protocol::Easing;
+
+	use super::*;
+
+	fn keyframe(at: f64, value: f64, easing: Easing) -> Keyframe {
+		Keyframe { at, value, easing }
+	}
+
+	#[test]
+	fn holds_the_ends_and_interpolates_between_keyframes() {
+		let keyframes = [
+			keyframe(0.25, 0.0, Easing::Linear),
+			keyframe(0.75, 100.0, Easing::Linear),
+			keyframe(1.0, 50.0, Easing::EaseIn),
+		];
+		assert_eq!(sample_track(&keyframes, 0.0), 0.0);
+		assert_eq!(sample_track(&keyframes, 0.5), 50.0);
+		assert_eq!(sample_track(&keyframes, 0.75), 100.0);
+		assert_eq!(sample_track(&keyframes, 0.875), 93.75);
+		assert_eq!(sample_track(&keyframes, 1.0), 50.0);
+	}
+
+	#[test]
+	fn unanimated_properties_keep_their_defaults() {
+		let layer = KeyframeLayer::new(vec![KeyframeTrack {
+			property: KeyframeProperty::TranslateX,
+			keyframes: vec![
+				keyframe(0.0, 1.0, Easing::Linear),
+				keyframe(1.0, 0.0, Easing::Linear),
+			],
+		}]);
+		assert_eq!(
+			layer.sample(0.5),
+			LayerState {
+				blur: 0.0,
+				opacity: 1.0,
+				translate_x: 0.5,
+				translate_y: 0.0,
+			}
+		);
+	}
+}
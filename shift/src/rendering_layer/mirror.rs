@@ -0,0 +1,102 @@
+//! Monitors showing another monitor's output, see `monitor_mirror`. The source's frame is
+//! snapshotted before the HUD and drawn on its targets the next time they render.
+
+use std::collections::HashMap;
+
+use tab_protocol::Rect;
+
+use crate::monitor::MonitorId;
+
+#[derive(Default)]
+pub struct Mirrors {
+	/// Source each mirroring monitor shows, by target.
+	sources: HashMap<MonitorId, MonitorId>,
+}
+
+impl Mirrors {
+	pub fn set(&mut self, monitor_id: MonitorId, source: Option<MonitorId>) {
+		match source {
+			Some(source) => self.sources.insert(monitor_id, source),
+			None => self.sources.remove(&monitor_id),
+		};
+	}
+
+	/// The monitor `monitor_id` mirrors.
+	pub fn source_of(&self, monitor_id: MonitorId) -> Option<MonitorId> {
+		self.sources.get(&monitor_id).copied()
+	}
+
+	/// Whether any monitor mirrors `monitor_id`, so its frames must be kept.
+	pub fn is_source(&self, monitor_id: MonitorId) -> bool {
+		self.sources.values().any(|source| *source == monitor_id)
+	}
+
+	/// Ends every mirror from or onto `monitor_id`.
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self
+			.sources
+			.retain(|target, source| *target != monitor_id && *source != monitor_id);
+	}
+}
+
+/// Where a `source`-sized frame goes on a `target`-sized monitor: as large as fits without
+/// changing its aspect ratio, centered, leaving black bars on two sides.
+pub fn letterbox(source: (usize, usize), target: (usize, usize)) -> Rect {
+	let (source_w, source_h) = (source.0.max(1) as f64, source.1.max(1) as f64);
+	let (target_w, target_h) = (target.0 as f64, target.1 as f64);
+	let scale = (target_w / source_w).min(target_h / source_h);
+	let (width, height) = (
+		(source_w * scale).round() as i32,
+		(source_h * scale).round() as i32,
+	);
+	Rect {
+		x: (target.0 as i32 - width) / 2,
+		y: (target.1 as i32 - height) / 2,
+		width,
+		height,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn frames_keep_their_aspect_ratio() {
+		let rect = |x, y, width, height| Rect {
+			x,
+			y,
+			width,
+			height,
+		};
+		// A 16:10 laptop panel on a 16:9 projector gets bars left and right.
+		assert_eq!(
+			letterbox((2560, 1600), (1920, 1080)),
+			rect(96, 0, 1728, 1080)
+		);
+		// A 4:3 source on a wider target, and a wider source on a 4:3 target.
+		assert_eq!(
+			letterbox((1024, 768), (1920, 1080)),
+			rect(240, 0, 1440, 1080)
+		);
+		assert_eq!(letterbox((1920, 1080), (1024, 768)), rect(0, 96, 1024, 576));
+		assert_eq!(letterbox((1920, 1080), (1280, 720)), rect(0, 0, 1280, 720));
+	}
+
+	#[test]
+	fn mirrors_end_with_either_monitor() {
+		let [laptop, projector, tv] = [0, 0, 0].map(|_| MonitorId::rand());
+		let mut mirrors = Mirrors::default();
+		mirrors.set(projector, Some(laptop));
+		mirrors.set(tv, Some(laptop));
+		assert_eq!(mirrors.source_of(projector), Some(laptop));
+		assert!(mirrors.is_source(laptop));
+
+		mirrors.forget_monitor(projector);
+		assert_eq!(mirrors.source_of(projector), None);
+		assert!(mirrors.is_source(laptop));
+		mirrors.forget_monitor(laptop);
+		assert_eq!(mirrors.source_of(tv), None);
+		assert!(!mirrors.is_source(laptop));
+	}
+}
@@ -1,45 +1,97 @@
 #![allow(dead_code)]
 
+// The Skia engine, `RenderingLayer`, is this module and the ones gated on `skia`. Ownership,
+// fences and dma-buf import are shared with the other engines and build without it.
+#[cfg(feature = "skia")]
 mod animation;
+#[cfg(feature = "skia")]
+mod background;
 pub mod channels;
+mod color_filter;
+#[cfg(feature = "skia")]
 mod commands;
+mod crash_grace;
 pub mod dmabuf_import;
 mod egl;
+pub mod engine;
+#[cfg(feature = "skia")]
 mod fence_runtime;
 mod fence_scheduler;
+pub mod gl_debug;
+#[cfg(feature = "gles")]
+pub mod gles;
+mod gpu_budget;
+mod health;
+#[cfg(feature = "skia")]
+mod hud;
+mod keyframes;
+mod mirror;
 mod ownership;
+mod present_group;
+#[cfg(feature = "skia")]
 mod render_core;
+#[cfg(feature = "skia")]
+mod resume;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(feature = "skia")]
+mod skia_texture;
+#[cfg(feature = "skia")]
+mod splash;
 mod state;
+mod stuck_frames;
+#[cfg(feature = "skia")]
 mod surface_cache;
+mod zoom;
 
-use easydrm::EasyDRM;
-use skia_safe::gpu;
-use std::{
-	collections::HashMap,
-	time::{Duration, Instant as StdInstant},
-};
-#[cfg(debug_assertions)]
-use std::{fs, time::Instant};
 use thiserror::Error;
-use tokio::sync::mpsc;
-use tracing::warn;
-
-use crate::comms::server2render::SessionTransition;
-use crate::{
-	comms::{
-		render2server::{RenderEvt, RenderEvtTx},
-		server2render::RenderCmdRx,
+
+#[cfg(all(feature = "skia", debug_assertions))]
+use std::{fs, time::Instant};
+#[cfg(feature = "skia")]
+use {
+	crate::comms::server2render::{SessionRegion, SessionTransition},
+	crate::crash::GpuInfo,
+	crate::{
+		comms::{
+			correlation::{Correlated, CorrelationId},
+			render2server::{RenderEvt, RenderEvtTx, Screenshot},
+			server2render::RenderCmdRx,
+		},
+		monitor::{Monitor as ServerLayerMonitor, MonitorId, MonitorIds, connector::LinkRetrains},
+		sessions::SessionId,
+	},
+	animation::AnimationRegistry,
+	background::BackgroundLayer,
+	channels::RenderingEnd,
+	color_filter::ColorFilters,
+	crash_grace::{CRASH_DIM, CrashGrace},
+	easydrm::EasyDRM,
+	fence_scheduler::{FenceScheduler, FenceTaskHandle, FenceWaitMode},
+	gl_debug::GlDebug,
+	gpu_budget::{FrontBufferRetention, GpuBudget},
+	health::HealthCounters,
+	hud::DebugHud,
+	mirror::Mirrors,
+	ownership::OwnershipManager,
+	present_group::PresentGroups,
+	skia_safe::gpu,
+	skia_texture::SkiaDmaBufTexture,
+	splash::Splash,
+	state::{DeferredLink, FenceEvent, SlotKey},
+	std::{
+		collections::{BTreeMap, HashMap, HashSet},
+		ffi::CStr,
+		sync::Arc,
+		time::{Duration, Instant as StdInstant},
 	},
-	monitor::{Monitor as ServerLayerMonitor, MonitorId},
-	sessions::SessionId,
+	stuck_frames::StuckFrames,
+	surface_cache::{MonitorRenderState, current_framebuffer_binding},
+	tab_protocol::Easing,
+	tokio::sync::mpsc,
+	tracing::warn,
+	zoom::Zooms,
 };
-use animation::AnimationRegistry;
-use channels::RenderingEnd;
-use dmabuf_import::SkiaDmaBufTexture;
-use fence_scheduler::{FenceScheduler, FenceTaskHandle, FenceWaitMode};
-use ownership::OwnershipManager;
-use state::{FenceEvent, SlotKey};
-use surface_cache::{MonitorRenderState, current_framebuffer_binding};
 
 #[derive(Debug, Error)]
 pub enum RenderError {
@@ -60,26 +112,70 @@ pub enum RenderError {
 	OpenFdGuardExceeded { count: usize, limit: usize },
 }
 
+#[cfg(feature = "skia")]
 pub struct RenderingLayer {
 	drm: EasyDRM<MonitorRenderState>,
 	gr: gpu::DirectContext,
 	command_rx: Option<RenderCmdRx>,
 	event_tx: RenderEvtTx,
+	/// Events of the current loop iteration, see [`Self::flush_events`].
+	outbox: Vec<Correlated<RenderEvt>>,
+	/// The client frame behind the command being handled, see [`Self::emit_event`].
+	corr: Option<CorrelationId>,
+	/// The client frame behind each queued swap, for its release.
+	swap_corrs: HashMap<SlotKey, CorrelationId>,
+	/// `generation` of each session's latest link, by monitor and session.
+	link_generations: HashMap<(MonitorId, SessionId), u64>,
+	/// Link generation each slot was last swapped in under, for its release.
+	swap_generations: HashMap<SlotKey, u64>,
 	known_monitors: HashMap<MonitorId, ServerLayerMonitor>,
+	monitor_ids: MonitorIds,
+	link_retrains: LinkRetrains,
 	ownership: OwnershipManager,
 	slots: HashMap<SlotKey, SkiaDmaBufTexture>,
+	gpu_budget: GpuBudget,
+	/// Links of sessions that weren't on screen yet, by monitor and session.
+	deferred_links: HashMap<(MonitorId, SessionId), DeferredLink>,
+	/// Raster images for slots filled through `buffer_upload` instead of a dma-buf.
+	uploaded_slots: HashMap<SlotKey, skia_safe::Image>,
 	fence_event_tx: mpsc::UnboundedSender<FenceEvent>,
 	fence_event_rx: mpsc::UnboundedReceiver<FenceEvent>,
 	fence_scheduler: FenceScheduler,
 	fence_tasks: HashMap<SlotKey, FenceTaskHandle>,
 	animations: AnimationRegistry,
 	active_transition: Option<ActiveTransition>,
+	pip_overlays: HashMap<MonitorId, SessionRegion>,
+	monitor_layouts: HashMap<MonitorId, Vec<SessionRegion>>,
+	mirrors: Mirrors,
+	/// Last frame of each mirrored monitor, taken before the HUD.
+	mirror_frames: HashMap<MonitorId, skia_safe::Image>,
+	zooms: Zooms,
+	color_filters: ColorFilters,
+	/// Sessions the server's watchdog found frozen, with how much to darken them.
+	dimmed_sessions: HashMap<SessionId, f32>,
+	/// Crashed sessions whose last frame is still on screen.
+	crash_grace: CrashGrace,
+	background: BackgroundLayer,
+	hud: DebugHud,
+	health: HealthCounters,
+	stuck_frames: StuckFrames,
+	present_groups: PresentGroups,
+	splash: Splash,
+	/// Screenshot requests waiting for the next frame drawn on each monitor.
+	pending_screenshots: HashMap<MonitorId, Vec<u64>>,
+	finished_screenshots: Vec<(u64, Result<Screenshot, Arc<str>>)>,
+	/// Between `RenderCmd::Suspend` and `RenderCmd::Resume`, when nothing is drawn.
+	suspended: bool,
+	/// Active session once a frame of it was page flipped, see `RenderEvt::SessionShown`.
+	active_shown: Option<SessionId>,
+	gl_debug: GlDebug,
 	#[cfg(debug_assertions)]
 	fd_guard_limit: usize,
 	#[cfg(debug_assertions)]
 	fd_guard_last_check: Instant,
 }
 
+#[cfg(feature = "skia")]
 #[derive(Debug, Clone)]
 struct ActiveTransition {
 	from_session_id: SessionId,
@@ -87,22 +183,33 @@ struct ActiveTransition {
 	animation: String,
 	started_at: StdInstant,
 	duration: Duration,
+	easing: Easing,
+	/// Every parameter of the animation, defaults filled in.
+	params: BTreeMap<String, f64>,
 }
 
+#[cfg(feature = "skia")]
 impl ActiveTransition {
-	fn from_cmd(to_session_id: SessionId, transition: SessionTransition) -> Option<Self> {
+	fn from_cmd(
+		to_session_id: SessionId,
+		transition: SessionTransition,
+		animations: &AnimationRegistry,
+	) -> Option<Self> {
 		if transition.duration.is_zero() {
 			return None;
 		}
 		Some(Self {
 			from_session_id: transition.from_session_id,
 			to_session_id,
+			params: animations.resolve_params(&transition.animation, &transition.params),
 			animation: transition.animation,
 			started_at: StdInstant::now(),
 			duration: transition.duration,
+			easing: transition.easing,
 		})
 	}
 
+	/// Linear progress through the duration, before easing.
 	fn progress(&self, now: StdInstant) -> f64 {
 		if self.duration.is_zero() {
 			return 1.0;
@@ -110,8 +217,13 @@ impl ActiveTransition {
 		let elapsed = now.saturating_duration_since(self.started_at);
 		(elapsed.as_secs_f64() / self.duration.as_secs_f64()).clamp(0.0, 1.0)
 	}
+
+	fn eased_progress(&self, now: StdInstant) -> f64 {
+		self.easing.apply(self.progress(now))
+	}
 }
 
+#[cfg(feature = "skia")]
 impl RenderingLayer {
 	#[tracing::instrument(skip_all)]
 	pub fn init(channels: RenderingEnd) -> Result<Self, RenderError> {
@@ -121,10 +233,13 @@ impl RenderingLayer {
 		drm
 			.make_current()
 			.map_err(|_| RenderError::SkiaGlInterface)?;
+		let gl_debug = GlDebug::load(&|s| drm.get_proc_address(s));
+		gl_debug.enable_current();
 		let interface = gpu::gl::Interface::new_load_with(|s| drm.get_proc_address(s))
 			.ok_or(RenderError::SkiaGlInterface)?;
 		let gr =
 			gpu::direct_contexts::make_gl(interface, None).ok_or(RenderError::SkiaDirectContext)?;
+		crate::crash::set_gpu_info(Self::query_gpu_info(&drm));
 		let (fence_event_tx, fence_event_rx) = mpsc::unbounded_channel();
 
 		Ok(Self {
@@ -132,15 +247,44 @@ impl RenderingLayer {
 			gr,
 			command_rx: Some(command_rx),
 			event_tx,
+			outbox: Vec::new(),
+			corr: None,
+			swap_corrs: HashMap::new(),
+			link_generations: HashMap::new(),
+			swap_generations: HashMap::new(),
 			known_monitors: HashMap::new(),
+			monitor_ids: MonitorIds::load(),
+			link_retrains: LinkRetrains::default(),
 			ownership: OwnershipManager::new(),
 			slots: HashMap::new(),
+			gpu_budget: GpuBudget::from_env(),
+			deferred_links: HashMap::new(),
+			uploaded_slots: HashMap::new(),
 			fence_event_tx,
 			fence_event_rx,
 			fence_scheduler: FenceScheduler::new(),
 			fence_tasks: HashMap::new(),
 			animations: AnimationRegistry::new(),
 			active_transition: None,
+			pip_overlays: HashMap::new(),
+			monitor_layouts: HashMap::new(),
+			mirrors: Mirrors::default(),
+			mirror_frames: HashMap::new(),
+			zooms: Zooms::default(),
+			color_filters: ColorFilters::load(),
+			dimmed_sessions: HashMap::new(),
+			crash_grace: CrashGrace::default(),
+			background: BackgroundLayer::new(),
+			hud: DebugHud::new(),
+			health: HealthCounters::new(),
+			stuck_frames: StuckFrames::from_env(),
+			present_groups: PresentGroups::default(),
+			splash: Splash::from_env(),
+			pending_screenshots: HashMap::new(),
+			finished_screenshots: Vec::new(),
+			suspended: false,
+			active_shown: None,
+			gl_debug,
 			#[cfg(debug_assertions)]
 			fd_guard_limit: std::env::var("SHIFT_MAX_OPEN_FDS")
 				.ok()
@@ -157,24 +301,30 @@ impl RenderingLayer {
 			.command_rx
 			.take()
 			.expect("render command channel missing");
+		self.identify_monitors();
 		let current = self.collect_monitors();
-		self
-			.emit_event(RenderEvt::Started {
-				monitors: current.clone(),
-			})
-			.await;
+		self.emit_event(RenderEvt::Started {
+			monitors: current.clone(),
+		});
 		self.known_monitors = current.into_iter().map(|m| (m.id, m)).collect();
+		self.publish_transitions();
+		self.flush_events().await;
 
 		'e: loop {
 			#[cfg(debug_assertions)]
 			self.check_open_fd_guard()?;
-			let committed_any = self.render_and_commit().await?;
+			let committed_any = !self.suspended && self.render_and_commit().await?;
 
 			'l: loop {
+				// Everything the last wakeup (or frame) emitted goes out as one batch.
+				self.flush_events().await;
 				tokio::select! {
 					cmd = command_rx.recv() => {
-						if let Some(cmd) = cmd {
-							if !self.handle_command(cmd).await? {
+						if let Some(Correlated { corr, msg: cmd }) = cmd {
+							self.corr = corr;
+							let keep_running = self.handle_command(cmd).await?;
+							self.corr = None;
+							if !keep_running {
 								break 'e;
 							}
 						} else {
@@ -197,17 +347,43 @@ impl RenderingLayer {
 							warn!("fence scheduler channel closed");
 						}
 					}
-					_ = tokio::time::sleep(Duration::from_millis(2)), if !committed_any => {
+					_ = tokio::time::sleep(Duration::from_millis(2)), if !committed_any && !self.suspended => {
 						break 'l;
 					}
 				}
 			}
 		}
 
+		self.flush_events().await;
 		warn!("shutting down renderer");
 		Ok(())
 	}
 
+	/// EGL/GL driver strings of the current context, for crash reports.
+	fn query_gpu_info(drm: &EasyDRM<MonitorRenderState>) -> GpuInfo {
+		fn to_string(ptr: *const std::ffi::c_char) -> String {
+			if ptr.is_null() {
+				return "unknown".into();
+			}
+			// SAFETY: EGL and GL return static NUL-terminated strings.
+			unsafe { CStr::from_ptr(ptr) }
+				.to_string_lossy()
+				.into_owned()
+		}
+		let egl = egl::Egl::load_with(|s| drm.get_proc_address(s));
+		let gl = easydrm::gl::Gles2::load_with(|s| drm.get_proc_address(s));
+		unsafe {
+			let display = egl.GetCurrentDisplay();
+			GpuInfo {
+				egl_vendor: to_string(egl.QueryString(display, egl::VENDOR as i32)),
+				egl_version: to_string(egl.QueryString(display, egl::VERSION as i32)),
+				gl_vendor: to_string(gl.GetString(easydrm::gl::VENDOR).cast()),
+				gl_renderer: to_string(gl.GetString(easydrm::gl::RENDERER).cast()),
+				gl_version: to_string(gl.GetString(easydrm::gl::VERSION).cast()),
+			}
+		}
+	}
+
 	#[cfg(debug_assertions)]
 	fn check_open_fd_guard(&mut self) -> Result<(), RenderError> {
 		const FD_GUARD_INTERVAL: Duration = Duration::from_secs(1);
@@ -242,6 +418,26 @@ impl RenderingLayer {
 		&mut self.drm
 	}
 
+	/// Gives monitors EasyDRM just set up their stable id, before anything else sees them.
+	fn identify_monitors(&mut self) {
+		let mut in_use = self
+			.drm
+			.monitors()
+			.filter(|mon| mon.context().is_identified())
+			.map(|mon| mon.context().id)
+			.collect::<HashSet<_>>();
+		for mon in self.drm.monitors_mut() {
+			if mon.context().is_identified() {
+				continue;
+			}
+			let id = self
+				.monitor_ids
+				.for_connector(u32::from(mon.connector_id()), &in_use);
+			in_use.insert(id);
+			mon.context_mut().identify(id);
+		}
+	}
+
 	fn collect_monitors(&self) -> Vec<ServerLayerMonitor> {
 		self
 			.drm
@@ -252,15 +448,34 @@ impl RenderingLayer {
 
 	#[tracing::instrument(skip_all)]
 	async fn sync_monitors(&mut self) {
+		self.identify_monitors();
 		let current_list = self.collect_monitors();
 		let mut current_map = HashMap::new();
 		for monitor in current_list {
-			if !self.known_monitors.contains_key(&monitor.id) {
-				self
-					.emit_event(RenderEvt::MonitorOnline {
+			match self.known_monitors.get(&monitor.id) {
+				None => {
+					self.emit_event(RenderEvt::MonitorOnline {
 						monitor: monitor.clone(),
-					})
-					.await;
+					});
+				}
+				Some(known)
+					if (
+						known.width,
+						known.height,
+						known.refresh_rate,
+						known.connector,
+					) != (
+						monitor.width,
+						monitor.height,
+						monitor.refresh_rate,
+						monitor.connector,
+					) =>
+				{
+					self.emit_event(RenderEvt::MonitorChanged {
+						monitor: monitor.clone(),
+					});
+				}
+				Some(_) => {}
 			}
 			current_map.insert(monitor.id, monitor);
 		}
@@ -271,18 +486,52 @@ impl RenderingLayer {
 			.copied()
 			.collect::<Vec<_>>();
 		for removed_id in removed_ids {
-			self
-				.emit_event(RenderEvt::MonitorOffline {
-					monitor_id: removed_id,
-				})
-				.await;
+			self.emit_event(RenderEvt::MonitorOffline {
+				monitor_id: removed_id,
+			});
 			self.cleanup_monitor_slots(removed_id);
 		}
+		let now = StdInstant::now();
+		for mon in self.drm.monitors() {
+			if current_map
+				.get(&mon.context().id)
+				.is_some_and(|monitor| monitor.connector.link_bad)
+			{
+				self
+					.link_retrains
+					.retrain(u32::from(mon.connector_id()), now);
+			}
+		}
 		self.known_monitors = current_map;
 	}
 
 	fn cleanup_monitor_slots(&mut self, monitor_id: MonitorId) {
 		self.slots.retain(|key, _| key.monitor_id != monitor_id);
+		self
+			.link_generations
+			.retain(|(monitor, _), _| *monitor != monitor_id);
+		self
+			.swap_generations
+			.retain(|key, _| key.monitor_id != monitor_id);
+		self
+			.deferred_links
+			.retain(|(monitor, _), _| *monitor != monitor_id);
+		self.pip_overlays.remove(&monitor_id);
+		self.monitor_layouts.remove(&monitor_id);
+		self.hud.forget_monitor(monitor_id);
+		self.health.forget_monitor(monitor_id);
+		self.stuck_frames.forget_monitor(monitor_id);
+		for (session_id, swap) in self.present_groups.forget_monitor(monitor_id) {
+			self.reject_held(session_id, swap, "unknown_monitor");
+		}
+		self.crash_grace.forget_monitor(monitor_id);
+		self.mirrors.forget_monitor(monitor_id);
+		self.zooms.forget_monitor(monitor_id);
+		self
+			.mirror_frames
+			.retain(|source, _| self.mirrors.is_source(*source));
+		// The server fails these itself when it sees the monitor go offline.
+		self.pending_screenshots.remove(&monitor_id);
 		self.ownership.cleanup_monitor(monitor_id);
 		let remove = self
 			.fence_tasks
@@ -295,9 +544,95 @@ impl RenderingLayer {
 		}
 	}
 
+	/// Tells the server which animations `session_switch` may use now.
+	fn publish_transitions(&mut self) {
+		self.emit_event(RenderEvt::TransitionsChanged {
+			transitions: self.animations.catalog(),
+		});
+	}
+
+	/// Forgets what sessions linked for a monitor that changed size, once its textures are gone.
+	/// Buffers shift held go back after the next commit; clients link new ones on
+	/// `monitor_changed`.
+	fn invalidate_monitor_slots(&mut self, monitor_id: MonitorId) {
+		tracing::info!(%monitor_id, "monitor resized, dropping buffers linked at the old size");
+		self
+			.deferred_links
+			.retain(|(monitor, _), _| *monitor != monitor_id);
+		let waiting = self
+			.fence_tasks
+			.keys()
+			.filter(|key| key.monitor_id == monitor_id)
+			.copied()
+			.collect::<Vec<_>>();
+		for key in waiting {
+			self.cancel_fence_wait(key);
+		}
+		for key in self.ownership.reset_monitor(monitor_id) {
+			self
+				.ownership
+				.queue_buffer_release(key.monitor_id, key.session_id, key.buffer);
+		}
+	}
+
+	/// Leaves a crashed session only on the monitors keeping its last frame, dimmed. Its buffers
+	/// go once [`CrashGrace::expire`] returns it.
+	fn freeze_session(&mut self, session_id: SessionId) {
+		self
+			.pip_overlays
+			.retain(|_, overlay| overlay.session_id != session_id);
+		for regions in self.monitor_layouts.values_mut() {
+			regions.retain(|region| region.session_id != session_id);
+		}
+		self
+			.monitor_layouts
+			.retain(|_, regions| !regions.is_empty());
+		self.ownership.unpin_session(session_id);
+		self.dimmed_sessions.insert(session_id, CRASH_DIM);
+	}
+
+	/// Drops the crashed sessions whose grace ended on every monitor.
+	fn expire_crash_grace(&mut self, now: StdInstant) {
+		let ownership = &self.ownership;
+		let released = self.crash_grace.expire(now, |monitor_id| {
+			ownership.current_slot_key(monitor_id).is_some()
+		});
+		for session_id in released {
+			self.cleanup_session_slots(session_id);
+			self.dimmed_sessions.remove(&session_id);
+		}
+	}
+
 	fn cleanup_session_slots(&mut self, session_id: SessionId) {
 		self.slots.retain(|key, _| key.session_id != session_id);
+		self
+			.swap_corrs
+			.retain(|key, _| key.session_id != session_id);
+		self
+			.link_generations
+			.retain(|(_, session), _| *session != session_id);
+		self
+			.swap_generations
+			.retain(|key, _| key.session_id != session_id);
+		self
+			.deferred_links
+			.retain(|(_, session), _| *session != session_id);
+		self
+			.uploaded_slots
+			.retain(|key, _| key.session_id != session_id);
+		self
+			.pip_overlays
+			.retain(|_, overlay| overlay.session_id != session_id);
+		for regions in self.monitor_layouts.values_mut() {
+			regions.retain(|region| region.session_id != session_id);
+		}
+		self
+			.monitor_layouts
+			.retain(|_, regions| !regions.is_empty());
+		self.stuck_frames.forget_session(session_id);
+		self.present_groups.forget_session(session_id);
 		self.ownership.cleanup_session(session_id);
+		self.gpu_budget.forget_session(session_id);
 		let remove = self
 			.fence_tasks
 			.keys()
@@ -308,4 +643,105 @@ impl RenderingLayer {
 			self.cancel_fence_wait(key);
 		}
 	}
+
+	/// Sessions drawn on any monitor: assigned, the active one, PiP, layout regions, or part of a
+	/// running transition.
+	fn shown_sessions(&self) -> HashSet<SessionId> {
+		let mut shown = self
+			.known_monitors
+			.keys()
+			.filter_map(|monitor_id| self.ownership.session_for_monitor(*monitor_id))
+			.collect::<HashSet<_>>();
+		shown.extend(self.pip_overlays.values().map(|overlay| overlay.session_id));
+		shown.extend(
+			self
+				.monitor_layouts
+				.values()
+				.flatten()
+				.map(|region| region.session_id),
+		);
+		if let Some(transition) = &self.active_transition {
+			shown.insert(transition.from_session_id);
+			shown.insert(transition.to_session_id);
+		}
+		shown.extend(
+			self
+				.known_monitors
+				.keys()
+				.filter_map(|monitor_id| self.crash_grace.frozen_session(*monitor_id)),
+		);
+		shown
+	}
+
+	fn gpu_usage(&self) -> HashMap<SessionId, u64> {
+		let mut usage = HashMap::new();
+		for (key, texture) in &self.slots {
+			*usage.entry(key.session_id).or_default() += texture.estimated_bytes();
+		}
+		usage
+	}
+
+	/// Front buffers eviction leaves imported, see [`FrontBufferRetention`].
+	fn retained_fronts(&self) -> HashSet<SlotKey> {
+		match self.gpu_budget.front_retention() {
+			FrontBufferRetention::Keep => self.ownership.front_slots().collect(),
+			FrontBufferRetention::Evict => HashSet::new(),
+		}
+	}
+
+	/// Like [`Self::gpu_usage`], leaving out the front buffers eviction keeps.
+	fn evictable_usage(&self) -> HashMap<SessionId, u64> {
+		let retained = self.retained_fronts();
+		let mut usage = HashMap::new();
+		for (key, texture) in &self.slots {
+			if !retained.contains(key) {
+				*usage.entry(key.session_id).or_default() += texture.estimated_bytes();
+			}
+		}
+		usage
+	}
+
+	async fn enforce_gpu_budget(&mut self, now: StdInstant) {
+		let usage = self.evictable_usage();
+		let shown = self.shown_sessions();
+		for session_id in self.gpu_budget.select_evictions(&usage, &shown, now) {
+			tracing::info!(%session_id, "evicting session buffers to stay within the GPU budget");
+			let monitor_ids = self.drop_session_imports(session_id).await;
+			if !monitor_ids.is_empty() {
+				self.emit_event(RenderEvt::RelinkRequested {
+					session_id,
+					monitor_ids,
+				});
+			}
+		}
+	}
+
+	/// Drops a session's imported dma-bufs and hands back the ones shift held, returning the
+	/// monitors its client has to link again. Uploaded buffers live in system memory and are kept,
+	/// and so are retained front buffers, which stay shift's until the client links again.
+	async fn drop_session_imports(&mut self, session_id: SessionId) -> Vec<MonitorId> {
+		let retained = self.retained_fronts();
+		let keys = self
+			.slots
+			.keys()
+			.filter(|key| key.session_id == session_id && !retained.contains(key))
+			.copied()
+			.collect::<Vec<_>>();
+		if keys.is_empty() {
+			return Vec::new();
+		}
+		let mut monitor_ids = Vec::new();
+		for key in &keys {
+			self.slots.remove(key);
+			self.cancel_fence_wait(*key);
+			if !monitor_ids.contains(&key.monitor_id) {
+				monitor_ids.push(key.monitor_id);
+			}
+		}
+		for key in self.ownership.evict_slots(&keys) {
+			self.emit_event(self.buffer_consumed(key, None));
+		}
+		tracing::info!(%session_id, monitors = monitor_ids.len(), "evicted session buffers");
+		monitor_ids
+	}
 }
@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 
+use tab_protocol::{FrameDropReason, PresentMode};
+
 use crate::{monitor::MonitorId, sessions::SessionId};
 
 use super::state::{BufferSlot, DeferredRelease, MonitorSurfaceState, SlotKey, SlotOwner};
@@ -7,10 +9,55 @@ use super::state::{BufferSlot, DeferredRelease, MonitorSurfaceState, SlotKey, Sl
 pub(super) struct SwapApplyResult {
 	pub canceled_pending: Option<BufferSlot>,
 	pub previous_to_release: Option<BufferSlot>,
+	/// Buffer that never reached the screen and can go back to the client right away.
+	pub release_now: Option<BufferSlot>,
+}
+
+/// Buffers freed by a slot becoming ready to show.
+#[derive(Default)]
+pub(super) struct ReadyResult {
+	/// Was shown, so it is released after the next commit.
+	pub deferred: Option<BufferSlot>,
+	/// Never shown, so it can be released immediately.
+	pub release_now: Option<BufferSlot>,
+}
+
+impl MonitorSurfaceState {
+	fn make_ready(&mut self, slot: BufferSlot) -> ReadyResult {
+		let previous = self.current_buffer;
+		match previous {
+			Some(current) if current != slot && !self.current_presented => match self.present_mode {
+				PresentMode::Mailbox => {
+					self.current_buffer = Some(slot);
+					ReadyResult {
+						deferred: None,
+						release_now: Some(current),
+					}
+				}
+				PresentMode::Fifo => {
+					let replaced = self.queued_buffer.replace(slot);
+					ReadyResult {
+						deferred: None,
+						release_now: replaced.filter(|queued| *queued != slot),
+					}
+				}
+			},
+			_ => {
+				self.current_buffer = Some(slot);
+				self.current_presented = false;
+				ReadyResult {
+					deferred: previous.filter(|prev| *prev != slot),
+					release_now: None,
+				}
+			}
+		}
+	}
 }
 
 pub(super) struct OwnershipManager {
 	current_session: Option<SessionId>,
+	/// Monitors pinned to a session instead of following `current_session`.
+	monitor_sessions: HashMap<MonitorId, SessionId>,
 	monitor_state: HashMap<(MonitorId, SessionId), MonitorSurfaceState>,
 	slot_ownership: HashMap<SlotKey, SlotOwner>,
 	deferred_releases: Vec<DeferredRelease>,
@@ -20,6 +67,7 @@ impl OwnershipManager {
 	pub fn new() -> Self {
 		Self {
 			current_session: None,
+			monitor_sessions: HashMap::new(),
 			monitor_state: HashMap::new(),
 			slot_ownership: HashMap::new(),
 			deferred_releases: Vec::new(),
@@ -34,9 +82,39 @@ impl OwnershipManager {
 		self.current_session = session_id;
 	}
 
+	pub fn set_monitor_session(&mut self, monitor_id: MonitorId, session_id: Option<SessionId>) {
+		match session_id {
+			Some(session_id) => {
+				self.monitor_sessions.insert(monitor_id, session_id);
+			}
+			None => {
+				self.monitor_sessions.remove(&monitor_id);
+			}
+		}
+	}
+
+	pub fn unpin_session(&mut self, session_id: SessionId) {
+		self
+			.monitor_sessions
+			.retain(|_, pinned| *pinned != session_id);
+	}
+
+	pub fn is_monitor_pinned(&self, monitor_id: MonitorId) -> bool {
+		self.monitor_sessions.contains_key(&monitor_id)
+	}
+
+	/// Session shown on a monitor: its pinned session, or the globally active one.
+	pub fn session_for_monitor(&self, monitor_id: MonitorId) -> Option<SessionId> {
+		self
+			.monitor_sessions
+			.get(&monitor_id)
+			.copied()
+			.or(self.current_session)
+	}
+
 	pub fn ensure_current_session_monitors(&mut self, monitor_ids: &[MonitorId]) {
-		if let Some(session_id) = self.current_session {
-			for monitor_id in monitor_ids {
+		for monitor_id in monitor_ids {
+			if let Some(session_id) = self.session_for_monitor(*monitor_id) {
 				self
 					.monitor_state
 					.entry((*monitor_id, session_id))
@@ -46,7 +124,7 @@ impl OwnershipManager {
 	}
 
 	pub fn current_slot_key(&self, monitor_id: MonitorId) -> Option<SlotKey> {
-		let session_id = self.current_session?;
+		let session_id = self.session_for_monitor(monitor_id)?;
 		self.current_slot_key_for_session(monitor_id, session_id)
 	}
 
@@ -79,6 +157,30 @@ impl OwnershipManager {
 			.or_default()
 	}
 
+	pub fn set_present_mode(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		mode: PresentMode,
+	) {
+		let state = self.state_entry(monitor_id, session_id);
+		state.present_mode = mode;
+		if mode == PresentMode::Mailbox
+			&& let Some(queued) = state.queued_buffer.take()
+		{
+			state.current_buffer = Some(queued);
+			state.current_presented = false;
+		}
+	}
+
+	/// Why a buffer of the session replaced before reaching the screen counts as dropped.
+	pub fn drop_reason(&self, monitor_id: MonitorId, session_id: SessionId) -> FrameDropReason {
+		match self.monitor_state.get(&(monitor_id, session_id)) {
+			Some(state) if state.present_mode == PresentMode::Mailbox => FrameDropReason::Mailbox,
+			_ => FrameDropReason::Queue,
+		}
+	}
+
 	pub fn owner(&self, key: SlotKey) -> Option<SlotOwner> {
 		self.slot_ownership.get(&key).copied()
 	}
@@ -107,32 +209,66 @@ impl OwnershipManager {
 		self.mark_slot_shift_owned(SlotKey::new(monitor_id, session_id, slot));
 
 		let state = self.state_entry(monitor_id, session_id);
-		let previous = state.current_buffer;
 		state.pending_buffer = Some(slot);
 
-		let previous_to_release = if has_acquire_fence {
-			None
+		let ready = if has_acquire_fence {
+			ReadyResult::default()
 		} else {
-			state.current_buffer = Some(slot);
 			state.pending_buffer = None;
-			previous.filter(|prev| *prev != slot)
+			state.make_ready(slot)
 		};
 
 		SwapApplyResult {
 			canceled_pending,
-			previous_to_release,
+			previous_to_release: ready.deferred,
+			release_now: ready.release_now,
 		}
 	}
 
-	pub fn apply_acquire_fence_signaled(&mut self, key: SlotKey) -> Option<BufferSlot> {
+	pub fn apply_acquire_fence_signaled(&mut self, key: SlotKey) -> Option<ReadyResult> {
 		let state = self.state_mut(key.monitor_id, key.session_id)?;
 		if state.pending_buffer != Some(key.buffer) {
 			return None;
 		}
-		let previous = state.current_buffer;
-		state.current_buffer = Some(key.buffer);
 		state.pending_buffer = None;
-		previous.filter(|prev| *prev != key.buffer)
+		Some(state.make_ready(key.buffer))
+	}
+
+	/// Whether the slot was swapped in and still waits for its acquire fence.
+	pub fn is_pending(&self, key: SlotKey) -> bool {
+		self
+			.monitor_state
+			.get(&(key.monitor_id, key.session_id))
+			.is_some_and(|state| state.pending_buffer == Some(key.buffer))
+	}
+
+	/// Whether `session_id` is what `monitor_id` shows, and its current buffer was page flipped.
+	pub fn is_presented(&self, monitor_id: MonitorId, session_id: SessionId) -> bool {
+		self.session_for_monitor(monitor_id) == Some(session_id)
+			&& self
+				.monitor_state
+				.get(&(monitor_id, session_id))
+				.is_some_and(|state| state.current_presented)
+	}
+
+	/// Records a page flip on `monitor_ids` and promotes buffers queued behind the one just shown.
+	pub fn mark_presented(&mut self, monitor_ids: &[MonitorId]) {
+		let mut released = Vec::new();
+		for ((monitor_id, session_id), state) in &mut self.monitor_state {
+			if !monitor_ids.contains(monitor_id) || state.current_buffer.is_none() {
+				continue;
+			}
+			state.current_presented = true;
+			if let Some(queued) = state.queued_buffer.take() {
+				if let Some(previous) = state.current_buffer.replace(queued) {
+					released.push((*monitor_id, *session_id, previous));
+				}
+				state.current_presented = false;
+			}
+		}
+		for (monitor_id, session_id, buffer) in released {
+			self.queue_buffer_release(monitor_id, session_id, buffer);
+		}
 	}
 
 	pub fn queue_buffer_release(
@@ -153,6 +289,14 @@ impl OwnershipManager {
 		});
 	}
 
+	/// Forgets the releases queued for a session's buffers on a monitor, which a relink hands
+	/// back anyway.
+	pub fn drop_deferred_releases(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		self
+			.deferred_releases
+			.retain(|item| (item.monitor_id, item.session_id) != (monitor_id, session_id));
+	}
+
 	pub fn take_deferred_releases(&mut self) -> Vec<DeferredRelease> {
 		self.deferred_releases.drain(..).collect()
 	}
@@ -165,6 +309,69 @@ impl OwnershipManager {
 			.deferred_releases
 			.retain(|item| item.monitor_id != monitor_id);
 		self.monitor_state.retain(|(mon, _), _| *mon != monitor_id);
+		self.monitor_sessions.remove(&monitor_id);
+	}
+
+	/// The buffer every session last made current on each monitor.
+	pub fn front_slots(&self) -> impl Iterator<Item = SlotKey> + '_ {
+		self
+			.monitor_state
+			.iter()
+			.filter_map(|((monitor_id, session_id), state)| {
+				Some(SlotKey::new(
+					*monitor_id,
+					*session_id,
+					state.current_buffer?,
+				))
+			})
+	}
+
+	/// Forgets the given slots as if they were never linked, keeping monitor pins. A monitor
+	/// whose front buffer isn't among them keeps showing it. Returns the ones shift held, which
+	/// the caller must release to the client.
+	pub fn evict_slots(&mut self, keys: &[SlotKey]) -> Vec<SlotKey> {
+		let held = keys
+			.iter()
+			.filter(|key| self.owner(**key) == Some(SlotOwner::ShiftOwned))
+			.copied()
+			.collect();
+		let mut forgotten = Vec::new();
+		for key in keys {
+			self.slot_ownership.remove(key);
+			let pair = (key.monitor_id, key.session_id);
+			let Some(state) = self.monitor_state.get_mut(&pair) else {
+				continue;
+			};
+			if state.current_buffer == Some(key.buffer) {
+				self.monitor_state.remove(&pair);
+				forgotten.push(pair);
+				continue;
+			}
+			if state.pending_buffer == Some(key.buffer) {
+				state.pending_buffer = None;
+			}
+			if state.queued_buffer == Some(key.buffer) {
+				state.queued_buffer = None;
+			}
+		}
+		self.deferred_releases.retain(|item| {
+			let pair = (item.monitor_id, item.session_id);
+			!forgotten.contains(&pair)
+				&& !keys.contains(&SlotKey::new(item.monitor_id, item.session_id, item.buffer))
+		});
+		held
+	}
+
+	/// Forgets every slot of a monitor, keeping its pin. Returns the ones shift held, which the
+	/// caller must release to their clients.
+	pub fn reset_monitor(&mut self, monitor_id: MonitorId) -> Vec<SlotKey> {
+		let keys = self
+			.slot_ownership
+			.keys()
+			.filter(|key| key.monitor_id == monitor_id)
+			.copied()
+			.collect::<Vec<_>>();
+		self.evict_slots(&keys)
 	}
 
 	pub fn cleanup_session(&mut self, session_id: SessionId) {
@@ -177,5 +384,35 @@ impl OwnershipManager {
 		self
 			.deferred_releases
 			.retain(|item| item.session_id != session_id);
+		self
+			.monitor_sessions
+			.retain(|_, pinned| *pinned != session_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn evicting_the_back_buffer_keeps_the_front_shown_and_held() {
+		let (monitor_id, session_id) = (MonitorId::rand(), SessionId::rand());
+		let front = SlotKey::new(monitor_id, session_id, BufferSlot::Zero);
+		let back = SlotKey::new(monitor_id, session_id, BufferSlot::One);
+		let mut ownership = OwnershipManager::new();
+		ownership.set_current_session(Some(session_id));
+		ownership.apply_swap_request(monitor_id, session_id, BufferSlot::One, false);
+		ownership.mark_presented(&[monitor_id]);
+		ownership.apply_swap_request(monitor_id, session_id, BufferSlot::Zero, false);
+		ownership.queue_buffer_release(monitor_id, session_id, BufferSlot::One);
+
+		assert_eq!(ownership.front_slots().collect::<Vec<_>>(), [front]);
+		assert_eq!(ownership.evict_slots(&[back]), [back]);
+		assert_eq!(ownership.current_slot_key(monitor_id), Some(front));
+		assert_eq!(ownership.owner(front), Some(SlotOwner::ShiftOwned));
+		assert!(ownership.take_deferred_releases().is_empty());
+
+		assert_eq!(ownership.evict_slots(&[front]), [front]);
+		assert_eq!(ownership.current_slot_key(monitor_id), None);
 	}
 }
@@ -0,0 +1,231 @@
+//! Monitors whose buffers a session wants flipped together, see `present_group`.
+//! - a tagged `buffer_request` is held, unacked, until every monitor of its group has one; then
+//!   they are all swapped in at once
+//! - the monitors of such a flip aren't drawn until each buffer's acquire fence signalled and
+//!   each monitor can take a page flip, so they are drawn in the same loop iteration and go out
+//!   in one commit. Whether that lands on the same vblank is up to the CRTCs' timing
+//! - a flip still waiting after [`FLIP_TIMEOUT`] is given up on, its monitors are drawn on their
+//!   own again
+
+use std::{
+	collections::{HashMap, HashSet},
+	os::fd::OwnedFd,
+	time::{Duration, Instant},
+};
+
+use tab_protocol::BufferIndex;
+
+use crate::{comms::correlation::CorrelationId, monitor::MonitorId, sessions::SessionId};
+
+use super::state::SlotKey;
+
+/// Longest the monitors of a flip wait for each other.
+pub const FLIP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// A `buffer_request` waiting for the rest of its group.
+#[derive(Debug)]
+pub(super) struct HeldSwap {
+	pub monitor_id: MonitorId,
+	pub buffer: BufferIndex,
+	pub generation: u64,
+	pub acquire_fence: Option<OwnedFd>,
+	/// The client frame behind the request, for its ack.
+	pub corr: Option<CorrelationId>,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+	monitors: Vec<MonitorId>,
+	held: HashMap<MonitorId, HeldSwap>,
+}
+
+/// Buffers swapped in together, not drawn until all of them can be.
+#[derive(Debug)]
+struct Flip {
+	slots: Vec<SlotKey>,
+	since: Instant,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct PresentGroups {
+	groups: HashMap<(SessionId, u32), Group>,
+	flips: Vec<Flip>,
+}
+
+impl PresentGroups {
+	/// Sets the monitors of a session's group, removing it when `monitors` is empty. Returns the
+	/// requests it held.
+	pub fn define(
+		&mut self,
+		session_id: SessionId,
+		group: u32,
+		monitors: Vec<MonitorId>,
+	) -> Vec<HeldSwap> {
+		let held = self
+			.groups
+			.remove(&(session_id, group))
+			.map(|group| group.held.into_values().collect())
+			.unwrap_or_default();
+		if !monitors.is_empty() {
+			self.groups.insert(
+				(session_id, group),
+				Group {
+					monitors,
+					held: HashMap::new(),
+				},
+			);
+		}
+		held
+	}
+
+	/// Holds `swap` back. Returns all of the group's requests once each monitor has one, or
+	/// `swap` itself when the group doesn't span its monitor.
+	pub fn hold(
+		&mut self,
+		session_id: SessionId,
+		group: u32,
+		swap: HeldSwap,
+	) -> Result<Option<Vec<HeldSwap>>, HeldSwap> {
+		let Some(entry) = self
+			.groups
+			.get_mut(&(session_id, group))
+			.filter(|entry| entry.monitors.contains(&swap.monitor_id))
+		else {
+			return Err(swap);
+		};
+		// Only after a relink, which dropped the server's pending request for the old one.
+		if let Some(replaced) = entry.held.insert(swap.monitor_id, swap) {
+			tracing::debug!(%session_id, group, monitor_id = %replaced.monitor_id, "replacing a held request");
+		}
+		if entry.held.len() < entry.monitors.len() {
+			return Ok(None);
+		}
+		Ok(Some(entry.held.drain().map(|(_, swap)| swap).collect()))
+	}
+
+	/// Keeps the monitors of `slots` from being drawn until they can all show them.
+	pub fn lock(&mut self, slots: Vec<SlotKey>, now: Instant) {
+		if !slots.is_empty() {
+			self.flips.push(Flip { slots, since: now });
+		}
+	}
+
+	/// Monitors not to draw this time, as a flip they're part of waits for a `pending` acquire
+	/// fence or a monitor that can't `flip` yet. Flips that can go out now are done with.
+	pub fn blocked_monitors(
+		&mut self,
+		pending: impl Fn(SlotKey) -> bool,
+		flip: impl Fn(MonitorId) -> bool,
+		now: Instant,
+	) -> HashSet<MonitorId> {
+		let mut blocked = HashSet::new();
+		self.flips.retain(|entry| {
+			if entry
+				.slots
+				.iter()
+				.all(|slot| !pending(*slot) && flip(slot.monitor_id))
+			{
+				return false;
+			}
+			if now.saturating_duration_since(entry.since) >= FLIP_TIMEOUT {
+				tracing::warn!(slots = ?entry.slots, "present group flip timed out, flipping its monitors on their own");
+				return false;
+			}
+			blocked.extend(entry.slots.iter().map(|slot| slot.monitor_id));
+			true
+		});
+		blocked
+	}
+
+	/// Drops the session's groups with what they held, and its flips.
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.groups.retain(|(session, _), _| *session != session_id);
+		for entry in &mut self.flips {
+			entry.slots.retain(|slot| slot.session_id != session_id);
+		}
+		self.flips.retain(|entry| !entry.slots.is_empty());
+	}
+
+	/// Takes the monitor out of every group and flip. Returns what the groups spanning it held, as
+	/// they can't complete the way they were defined.
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) -> Vec<(SessionId, HeldSwap)> {
+		let mut dropped = Vec::new();
+		for ((session_id, _), group) in &mut self.groups {
+			if group.monitors.contains(&monitor_id) {
+				group.monitors.retain(|monitor| *monitor != monitor_id);
+				dropped.extend(group.held.drain().map(|(_, swap)| (*session_id, swap)));
+			}
+		}
+		self.groups.retain(|_, group| !group.monitors.is_empty());
+		for entry in &mut self.flips {
+			entry.slots.retain(|slot| slot.monitor_id != monitor_id);
+		}
+		self.flips.retain(|entry| !entry.slots.is_empty());
+		dropped
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::super::state::BufferSlot;
+	use super::*;
+
+	fn swap(monitor_id: MonitorId) -> HeldSwap {
+		HeldSwap {
+			monitor_id,
+			buffer: BufferIndex::Zero,
+			generation: 0,
+			acquire_fence: None,
+			corr: None,
+		}
+	}
+
+	#[test]
+	fn groups_flip_once_every_monitor_is_ready() {
+		let (left, right) = (MonitorId::rand(), MonitorId::rand());
+		let session_id = SessionId::rand();
+		let mut groups = PresentGroups::default();
+		assert!(groups.hold(session_id, 1, swap(left)).is_err());
+
+		assert!(groups.define(session_id, 1, vec![left, right]).is_empty());
+		assert!(matches!(groups.hold(session_id, 1, swap(left)), Ok(None)));
+		assert_eq!(groups.define(session_id, 1, vec![left, right]).len(), 1);
+		assert!(matches!(groups.hold(session_id, 1, swap(left)), Ok(None)));
+		let Ok(Some(swaps)) = groups.hold(session_id, 1, swap(right)) else {
+			panic!("expected the complete group");
+		};
+		assert_eq!(swaps.len(), 2);
+
+		let start = Instant::now();
+		let slots = [left, right].map(|monitor| SlotKey::new(monitor, session_id, BufferSlot::Zero));
+		groups.lock(slots.to_vec(), start);
+		let fenced = slots[1];
+		assert_eq!(
+			groups.blocked_monitors(|slot| slot == fenced, |_| true, start),
+			HashSet::from([left, right])
+		);
+		assert_eq!(
+			groups.blocked_monitors(|_| false, |monitor| monitor != left, start),
+			HashSet::from([left, right])
+		);
+		assert!(
+			groups
+				.blocked_monitors(|_| false, |_| true, start)
+				.is_empty()
+		);
+
+		groups.lock(slots.to_vec(), start);
+		assert!(
+			groups
+				.blocked_monitors(|_| true, |_| true, start + FLIP_TIMEOUT)
+				.is_empty()
+		);
+
+		assert!(matches!(groups.hold(session_id, 1, swap(right)), Ok(None)));
+		let dropped = groups.forget_monitor(left);
+		assert_eq!(dropped.len(), 1);
+		assert!(groups.hold(session_id, 1, swap(left)).is_err());
+		groups.forget_session(session_id);
+		assert!(groups.hold(session_id, 1, swap(right)).is_err());
+	}
+}
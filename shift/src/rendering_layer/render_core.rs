@@ -1,22 +1,66 @@
-use easydrm::gl::{COLOR_BUFFER_BIT, DEPTH_BUFFER_BIT};
-use skia_safe::{FilterMode, MipmapMode, Paint, SamplingOptions};
-use std::collections::HashMap;
-use tracing::warn;
+use easydrm::gl::{COLOR_BUFFER_BIT, DEPTH_BUFFER_BIT, NO_ERROR};
+use skia_safe::{
+	FilterMode, MipmapMode, Paint, SamplingOptions, color_filters, image::CachingHint,
+};
+use std::{
+	collections::{HashMap, HashSet},
+	sync::Arc,
+};
+use tab_protocol::{PanelOrientation, bulk::BulkPayload};
+use tracing::{error, warn};
 
+use crate::comms::render2server::Screenshot;
+use crate::monitor::MonitorId;
+
+use super::color_filter::color_matrix;
+use super::mirror::letterbox;
 use super::state::SlotOwner;
+use super::stuck_frames::{SAMPLE_ROWS, hash_sample};
+use super::zoom::ZoomView;
 use super::{RenderError, RenderEvt, RenderingLayer, current_framebuffer_binding};
 use super::{SkiaDmaBufTexture, SlotKey};
 
 impl RenderingLayer {
 	fn slot_image(
 		slots: &mut HashMap<SlotKey, SkiaDmaBufTexture>,
+		uploaded_slots: &HashMap<SlotKey, skia_safe::Image>,
 		gr: &mut skia_safe::gpu::DirectContext,
 		key: SlotKey,
 	) -> Option<skia_safe::Image> {
+		if let Some(image) = uploaded_slots.get(&key) {
+			return Some(image.clone());
+		}
 		let texture = slots.get_mut(&key)?;
 		texture.image(gr).cloned()
 	}
 
+	/// Hashes a few rows of a session's buffer, see [`super::stuck_frames`]. Reading them back
+	/// waits for the GPU.
+	fn sample_hash(gr: &mut skia_safe::gpu::DirectContext, image: &skia_safe::Image) -> Option<u64> {
+		let info = skia_safe::ImageInfo::new(
+			(image.width(), 1),
+			skia_safe::ColorType::RGBA8888,
+			skia_safe::AlphaType::Unpremul,
+			None,
+		);
+		let stride = info.min_row_bytes();
+		let mut rows = vec![0u8; stride * SAMPLE_ROWS];
+		for (index, row) in rows.chunks_exact_mut(stride).enumerate() {
+			let y = image.height() * (index as i32 + 1) / (SAMPLE_ROWS as i32 + 1);
+			if !image.read_pixels_with_context(
+				&mut *gr,
+				&info,
+				row,
+				stride,
+				(0, y),
+				CachingHint::Disallow,
+			) {
+				return None;
+			}
+		}
+		Some(hash_sample(&rows))
+	}
+
 	fn draw_image_fullscreen(context: &mut super::MonitorRenderState, image: &skia_safe::Image) {
 		let rect = skia_safe::Rect::from_wh(context.width as f32, context.height as f32);
 		let sampling = SamplingOptions::new(FilterMode::Nearest, MipmapMode::Nearest);
@@ -27,24 +71,100 @@ impl RenderingLayer {
 			.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
 	}
 
+	fn draw_image_in_rect(
+		context: &mut super::MonitorRenderState,
+		image: &skia_safe::Image,
+		rect: tab_protocol::Rect,
+	) {
+		let rect = skia_safe::Rect::from_xywh(
+			rect.x as f32,
+			rect.y as f32,
+			rect.width as f32,
+			rect.height as f32,
+		);
+		let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::None);
+		let mut paint = Paint::default();
+		paint.set_argb(255, 255, 255, 255);
+		context
+			.canvas()
+			.draw_image_rect_with_sampling_options(image, None, rect, sampling, &paint);
+	}
+
+	fn dim_rect(context: &mut super::MonitorRenderState, amount: f32, rect: skia_safe::Rect) {
+		let mut paint = Paint::default();
+		paint.set_argb((amount * 255.0) as u8, 0, 0, 0);
+		context.canvas().draw_rect(rect, &paint);
+	}
+
+	/// Draws what `context` shows so far again, magnified around the zoom center and through
+	/// the monitor's color filter.
+	fn redraw_filtered(
+		context: &mut super::MonitorRenderState,
+		zoom: Option<ZoomView>,
+		color_matrix: Option<[f32; 20]>,
+	) {
+		if zoom.is_none() && color_matrix.is_none() {
+			return;
+		}
+		let Some(frame) = context.snapshot() else {
+			return;
+		};
+		let (width, height) = (context.width as f32, context.height as f32);
+		let sampling = SamplingOptions::new(FilterMode::Linear, MipmapMode::None);
+		let mut paint = Paint::default();
+		if let Some(matrix) = color_matrix {
+			paint.set_color_filter(color_filters::matrix_row_major(&matrix, None));
+		}
+		let canvas = context.canvas();
+		canvas.save();
+		if let Some(view) = zoom {
+			let x = view.center[0].clamp(0.0, width);
+			let y = view.center[1].clamp(0.0, height);
+			canvas.translate((x, y));
+			canvas.scale((view.factor, view.factor));
+			canvas.translate((-x, -y));
+		}
+		canvas.draw_image_with_sampling_options(&frame, (0, 0), sampling, Some(&paint));
+		canvas.restore();
+	}
+
 	pub(super) fn draw_ready_monitors(&mut self) -> Result<(), RenderError> {
 		let monitor_ids: Vec<_> = self.drm.monitors().map(|mon| mon.context().id).collect();
 		self.ownership.ensure_current_session_monitors(&monitor_ids);
 		let now = std::time::Instant::now();
+		self.expire_crash_grace(now);
+		let can_flip = self
+			.drm
+			.monitors()
+			.filter(|mon| mon.can_render())
+			.map(|mon| mon.context().id)
+			.collect::<HashSet<_>>();
+		let ownership = &self.ownership;
+		let held_back = self.present_groups.blocked_monitors(
+			|slot| ownership.is_pending(slot),
+			|monitor_id| can_flip.contains(&monitor_id),
+			now,
+		);
 		let transition_snapshot = self.active_transition.clone();
 		let transition_done = transition_snapshot
 			.as_ref()
 			.map(|transition| transition.progress(now) >= 1.0)
 			.unwrap_or(false);
+		let mut resized = Vec::new();
 
 		for mon in self.drm.monitors_mut() {
-			if !mon.can_render() {
+			// Held back monitors wait for the rest of a present group flip.
+			if !mon.can_render() || held_back.contains(&mon.context().id) {
 				continue;
 			}
 			if let Err(e) = mon.make_current() {
 				warn!(monitor_id = %mon.context().id, "make_current failed: {e:?}");
 				continue;
 			}
+			if !mon.context().gl_debug_checked {
+				mon.context_mut().gl_debug_checked = true;
+				self.gl_debug.enable_current();
+			}
 
 			unsafe {
 				mon.gl().ClearColor(0.0, 0.0, 0.0, 1.0);
@@ -53,13 +173,45 @@ impl RenderingLayer {
 
 			let monitor_id = mon.context().id;
 			let mode = mon.active_mode();
-			let (w, h) = (mode.size().0 as usize, mode.size().1 as usize);
+			let (mode_w, mode_h) = (mode.size().0 as usize, mode.size().1 as usize);
+			let orientation = self
+				.known_monitors
+				.get(&monitor_id)
+				.and_then(|monitor| monitor.connector.panel_orientation)
+				.unwrap_or(PanelOrientation::Normal);
 			let context = mon.context_mut();
 			let target_fbo = current_framebuffer_binding(&context.gl);
-			context.ensure_surface_target(&mut self.gr, w, h, target_fbo)?;
+			let resized_now =
+				context.ensure_surface_target(&mut self.gr, mode_w, mode_h, orientation, target_fbo)?;
+			// Everything below draws the upright picture.
+			let (w, h) = (context.width, context.height);
+			if resized_now {
+				// Buffers linked at the old size must not be drawn again, not even this frame.
+				self.slots.retain(|key, _| key.monitor_id != monitor_id);
+				self
+					.uploaded_slots
+					.retain(|key, _| key.monitor_id != monitor_id);
+				resized.push(monitor_id);
+			}
+			let mirror_source = self.mirrors.source_of(monitor_id);
+			if mirror_source.is_none() {
+				self.background.draw(context.canvas(), w as f32, h as f32);
+			}
 
 			let mut drew = false;
-			if let Some(transition) = transition_snapshot.as_ref()
+			// Only real session buffers count, a region without one still shows the splash.
+			let mut session_drawn = false;
+			if let Some(source) = mirror_source {
+				// Black until the source drew its first frame.
+				if let Some(frame) = self.mirror_frames.get(&source) {
+					let size = (frame.width() as usize, frame.height() as usize);
+					Self::draw_image_in_rect(context, frame, letterbox(size, (w, h)));
+				}
+				drew = true;
+			}
+			if !drew
+				&& let Some(transition) = transition_snapshot.as_ref()
+				&& !self.ownership.is_monitor_pinned(monitor_id)
 				&& let Some(animation) = self.animations.get(&transition.animation)
 			{
 				let old_key = self
@@ -70,10 +222,14 @@ impl RenderingLayer {
 					.current_slot_key_for_session(monitor_id, transition.to_session_id);
 				let old_image = old_key
 					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
-					.and_then(|key| Self::slot_image(&mut self.slots, &mut self.gr, key));
+					.and_then(|key| {
+						Self::slot_image(&mut self.slots, &self.uploaded_slots, &mut self.gr, key)
+					});
 				let new_image = new_key
 					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
-					.and_then(|key| Self::slot_image(&mut self.slots, &mut self.gr, key));
+					.and_then(|key| {
+						Self::slot_image(&mut self.slots, &self.uploaded_slots, &mut self.gr, key)
+					});
 				match (old_image, new_image) {
 					(Some(old_image), Some(new_image)) => {
 						let width = context.width as f32;
@@ -82,42 +238,233 @@ impl RenderingLayer {
 							context.canvas(),
 							&old_image,
 							&new_image,
-							transition.progress(now),
+							transition.eased_progress(now),
+							&transition.params,
 							width,
 							height,
 						);
 						drew = true;
+						session_drawn = true;
 					}
 					(_, Some(new_image)) => {
 						Self::draw_image_fullscreen(context, &new_image);
 						drew = true;
+						session_drawn = true;
 					}
 					_ => {}
 				}
 			}
 
+			if !drew && let Some(regions) = self.monitor_layouts.get(&monitor_id) {
+				for region in regions {
+					let image = self
+						.ownership
+						.current_slot_key_for_session(monitor_id, region.session_id)
+						.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
+						.and_then(|key| {
+							Self::slot_image(&mut self.slots, &self.uploaded_slots, &mut self.gr, key)
+						});
+					if let Some(image) = image {
+						if self
+							.stuck_frames
+							.should_sample(monitor_id, region.session_id)
+							&& let Some(hash) = Self::sample_hash(&mut self.gr, &image)
+						{
+							self
+								.stuck_frames
+								.record_sample(monitor_id, region.session_id, hash, now);
+						}
+						Self::draw_image_in_rect(context, &image, region.rect);
+						if let Some(amount) = self.dimmed_sessions.get(&region.session_id) {
+							let rect = region.rect;
+							let rect = skia_safe::Rect::from_xywh(
+								rect.x as f32,
+								rect.y as f32,
+								rect.width as f32,
+								rect.height as f32,
+							);
+							Self::dim_rect(context, *amount, rect);
+						}
+						session_drawn = true;
+					}
+				}
+				drew = true;
+			}
+
 			if !drew {
-				let key = self.ownership.current_slot_key(monitor_id);
+				let frozen = self.crash_grace.frozen_session(monitor_id);
+				let key = self.ownership.current_slot_key(monitor_id).or_else(|| {
+					frozen.and_then(|session_id| {
+						self
+							.ownership
+							.current_slot_key_for_session(monitor_id, session_id)
+					})
+				});
 				let image = key
 					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
-					.and_then(|key| Self::slot_image(&mut self.slots, &mut self.gr, key));
+					.and_then(|key| {
+						Self::slot_image(&mut self.slots, &self.uploaded_slots, &mut self.gr, key)
+					});
 				if let Some(image) = image {
+					if let Some(key) = key
+						&& self.stuck_frames.should_sample(monitor_id, key.session_id)
+						&& let Some(hash) = Self::sample_hash(&mut self.gr, &image)
+					{
+						self
+							.stuck_frames
+							.record_sample(monitor_id, key.session_id, hash, now);
+					}
 					Self::draw_image_fullscreen(context, &image);
+					if let Some(amount) = key.and_then(|key| self.dimmed_sessions.get(&key.session_id)) {
+						let rect = skia_safe::Rect::from_wh(context.width as f32, context.height as f32);
+						Self::dim_rect(context, *amount, rect);
+					}
+					if key.is_some_and(|key| Some(key.session_id) == frozen) {
+						self
+							.hud
+							.draw_notice(context.canvas(), "Session crashed", w as f32, h as f32);
+					}
+					session_drawn = true;
 				}
 			}
 
+			if mirror_source.is_none()
+				&& let Some(overlay) = self.pip_overlays.get(&monitor_id).copied()
+				&& self.ownership.session_for_monitor(monitor_id) != Some(overlay.session_id)
+			{
+				let image = self
+					.ownership
+					.current_slot_key_for_session(monitor_id, overlay.session_id)
+					.filter(|key| self.ownership.owner(*key) == Some(SlotOwner::ShiftOwned))
+					.and_then(|key| {
+						Self::slot_image(&mut self.slots, &self.uploaded_slots, &mut self.gr, key)
+					});
+				if let Some(image) = image {
+					Self::draw_image_in_rect(context, &image, overlay.rect);
+				}
+			}
+
+			// Read back before the HUD so screenshots only show what sessions drew.
+			if let Some(requests) = self.pending_screenshots.remove(&monitor_id) {
+				let pixels = context.read_pixels_rgba();
+				for request in requests {
+					let result = Self::screenshot_from_pixels(monitor_id, context, pixels.as_ref());
+					self.finished_screenshots.push((request, result));
+				}
+			}
+
+			if session_drawn {
+				self.splash.finish(now);
+			}
+			if self.splash.is_active() {
+				self
+					.splash
+					.draw(context.canvas(), &self.background, w as f32, h as f32, now);
+			}
+			if self.mirrors.is_source(monitor_id)
+				&& let Some(frame) = context.snapshot()
+			{
+				self.mirror_frames.insert(monitor_id, frame);
+			}
+			Self::redraw_filtered(
+				context,
+				self.zooms.view(monitor_id, now),
+				color_matrix(self.color_filters.get(monitor_id)),
+			);
+
+			if self.hud.is_enabled() {
+				let name = self
+					.known_monitors
+					.get(&monitor_id)
+					.map(|monitor| monitor.name.as_str())
+					.unwrap_or("monitor");
+				self
+					.hud
+					.draw(context.canvas(), monitor_id, name, self.fence_tasks.len());
+			}
+
 			context.flush(&mut self.gr);
+			if self.gl_debug.is_strict() {
+				let error = unsafe { context.gl.GetError() };
+				if error != NO_ERROR {
+					self.gl_debug.record_error(error);
+				}
+			}
 		}
 
 		if transition_done {
 			self.active_transition = None;
 		}
+		for monitor_id in resized {
+			self.invalidate_monitor_slots(monitor_id);
+		}
 
 		Ok(())
 	}
 
+	fn screenshot_from_pixels(
+		monitor_id: MonitorId,
+		context: &super::MonitorRenderState,
+		pixels: Option<&(Vec<u8>, usize)>,
+	) -> Result<Screenshot, Arc<str>> {
+		let Some((pixels, stride)) = pixels else {
+			return Err("failed to read back monitor pixels".into());
+		};
+		let (_, fd) = BulkPayload::seal(c"shift-screenshot", pixels)
+			.map_err(|e| Arc::<str>::from(format!("failed to store screenshot: {e}")))?;
+		Ok(Screenshot {
+			monitor_id,
+			width: context.width as i32,
+			height: context.height as i32,
+			stride: *stride as i32,
+			fourcc: tab_protocol::ScreenshotDataPayload::FOURCC_ABGR8888,
+			pixels: fd,
+		})
+	}
+
+	fn report_active_shown(&mut self, page_flipped_monitors: &[MonitorId]) {
+		let current = self.ownership.current_session();
+		if current == self.active_shown {
+			return;
+		}
+		let Some(session_id) = current else {
+			self.active_shown = None;
+			return;
+		};
+		if page_flipped_monitors
+			.iter()
+			.any(|monitor_id| self.ownership.is_presented(*monitor_id, session_id))
+		{
+			self.active_shown = current;
+			self.emit_event(RenderEvt::SessionShown { session_id });
+		}
+	}
+
 	pub(super) async fn render_and_commit(&mut self) -> Result<bool, RenderError> {
+		self.health.record_loop();
+		self.import_deferred_links().await;
 		self.draw_ready_monitors()?;
+		for (request, result) in std::mem::take(&mut self.finished_screenshots) {
+			self.emit_event(RenderEvt::Screenshot { request, result });
+		}
+		for stuck in self.stuck_frames.take_warnings() {
+			self.emit_event(RenderEvt::StuckFrames {
+				session_id: stuck.session_id,
+				monitor_id: stuck.monitor_id,
+				duration: stuck.duration,
+				frames: stuck.frames,
+			});
+		}
+		if self.splash.advance(std::time::Instant::now()) {
+			self.emit_event(RenderEvt::SplashEnded);
+		}
+		if self.gl_debug.is_strict() {
+			let errors = self.gl_debug.take_errors();
+			if errors > 0 {
+				error!(errors, "GL errors while drawing, dropping the frame");
+				return Ok(false);
+			}
+		}
 
 		let page_flipped_monitors = self
 			.drm
@@ -128,14 +475,28 @@ impl RenderingLayer {
 
 		let swap_result = self.drm.swap_buffers_with_result()?;
 		let committed_any = !swap_result.committed_connectors.is_empty();
+		self.ownership.mark_presented(&page_flipped_monitors);
+		self.report_active_shown(&page_flipped_monitors);
+		let now = std::time::Instant::now();
+		self.hud.record_page_flips(&page_flipped_monitors, now);
+		self.health.record_page_flips(&page_flipped_monitors, now);
+		let gpu_memory = self.gpu_budget.stats(self.gpu_usage().values().sum());
+		if let Some(health) = self.health.take_report(
+			self.known_monitors.keys().copied(),
+			self.fence_tasks.len(),
+			gpu_memory,
+			now,
+		) {
+			self.emit_event(RenderEvt::Health(health));
+			// Checked with the heartbeat, once a second.
+			self.enforce_gpu_budget(now).await;
+		}
 		self
 			.process_deferred_releases(swap_result.render_fence)
 			.await;
-		self
-			.emit_event(RenderEvt::PageFlip {
-				monitors: page_flipped_monitors,
-			})
-			.await;
+		self.emit_event(RenderEvt::PageFlip {
+			monitors: page_flipped_monitors,
+		});
 
 		Ok(committed_any)
 	}
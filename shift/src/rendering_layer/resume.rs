@@ -0,0 +1,47 @@
+//! Coming back from system suspend, see `RenderCmd::Resume`.
+//! - the VT switches around suspend can leave shift without DRM master, so it takes it back
+//! - GL state and the surfaces wrapping each monitor's framebuffers are rebuilt on the next frame
+//! - imported dma-bufs may not have survived, so they are dropped and every session is told with
+//!   `buffers_invalidated`
+
+use std::collections::HashSet;
+
+use super::{RenderEvt, RenderingLayer};
+use crate::monitor::connector::reacquire_drm_master;
+
+impl RenderingLayer {
+	pub(super) fn suspend(&mut self) {
+		tracing::info!("system is suspending, rendering paused");
+		self.suspended = true;
+	}
+
+	#[tracing::instrument(skip_all)]
+	pub(super) async fn resume(&mut self) {
+		self.suspended = false;
+		for (card, e) in reacquire_drm_master() {
+			tracing::warn!(card = %card.display(), "failed to take back DRM master: {e}");
+		}
+		self.gr.reset(None);
+		self.mirror_frames.clear();
+		for mon in self.drm.monitors_mut() {
+			mon.context_mut().surfaces_by_fbo.clear();
+		}
+		self.sync_monitors().await;
+		let sessions = self
+			.slots
+			.keys()
+			.map(|key| key.session_id)
+			.collect::<HashSet<_>>();
+		for session_id in sessions {
+			let monitor_ids = self.drop_session_imports(session_id).await;
+			if !monitor_ids.is_empty() {
+				self.emit_event(RenderEvt::BuffersInvalidated {
+					session_id,
+					monitor_ids,
+				});
+			}
+		}
+		tracing::info!("resumed from suspend");
+		self.emit_event(RenderEvt::Resumed);
+	}
+}
@@ -0,0 +1,479 @@
+//! A renderer without GPU or DRM, for driving the server layer deterministically in tests.
+//! - time only moves in [`SimRenderer::advance`]; page flips, fence signals and hotplugs happen
+//!   when a test scheduled them
+//! - buffers follow the real renderer's contract: acked once their acquire fence signalled, and
+//!   consumed once a newer buffer of the same session and monitor was flipped on screen
+//!
+//! With the `sim` feature, `SHIFT_RENDER_ENGINE=sim` (or `SHIFT_SIM_RENDERER`) runs shift on it,
+//! with one virtual monitor that flips at its refresh rate.
+
+use std::{
+	collections::{BTreeMap, HashMap, VecDeque},
+	os::fd::OwnedFd,
+	sync::Arc,
+	time::Duration,
+};
+
+use tab_protocol::{BufferIndex, FrameDropReason};
+
+use super::{RenderError, channels::RenderingEnd};
+use crate::{
+	comms::{correlation::Correlated, render2server::RenderEvt, server2render::RenderCmd},
+	monitor::{Monitor, MonitorId},
+	sessions::SessionId,
+};
+
+/// Something the simulated hardware does at a scheduled time.
+#[derive(Debug)]
+pub enum SimEvent {
+	/// The monitor shows the buffers acked since its last flip.
+	PageFlip(MonitorId),
+	/// The acquire fence of a session's pending swap on a monitor signals.
+	SignalFence {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+	},
+	Hotplug(Monitor),
+	Unplug(MonitorId),
+	/// Hands an event to the server as is, e.g. to deliver an answer late.
+	Emit(RenderEvt),
+}
+
+#[derive(Debug)]
+struct FencedSwap {
+	buffer: Buffer,
+	/// Kept open until it "signals", like the real renderer's fence wait.
+	_fence: OwnedFd,
+}
+
+type SlotKey = (SessionId, MonitorId);
+/// A buffer and the link generation it was swapped in under.
+type Buffer = (BufferIndex, u64);
+
+pub struct SimRenderer {
+	now: Duration,
+	/// Ordered by time, then by when they were scheduled.
+	script: BTreeMap<(Duration, u64), SimEvent>,
+	next_seq: u64,
+	monitors: HashMap<MonitorId, Monitor>,
+	fenced: HashMap<SlotKey, FencedSwap>,
+	/// Acked buffers waiting for the next flip of their monitor.
+	queued: HashMap<SlotKey, Buffer>,
+	front: HashMap<SlotKey, Buffer>,
+	events: VecDeque<RenderEvt>,
+}
+
+impl SimRenderer {
+	/// Starts with `monitors` connected; the first event is `Started`.
+	pub fn new(monitors: Vec<Monitor>) -> Self {
+		let mut sim = Self {
+			now: Duration::ZERO,
+			script: BTreeMap::new(),
+			next_seq: 0,
+			monitors: monitors
+				.iter()
+				.map(|monitor| (monitor.id, monitor.clone()))
+				.collect(),
+			fenced: HashMap::new(),
+			queued: HashMap::new(),
+			front: HashMap::new(),
+			events: VecDeque::new(),
+		};
+		sim.events.push_back(RenderEvt::Started { monitors });
+		sim
+	}
+
+	/// A 1080p monitor at 60 Hz.
+	pub fn default_monitor() -> Monitor {
+		Monitor {
+			id: MonitorId::rand(),
+			width: 1920,
+			height: 1080,
+			refresh_rate: 60,
+			name: "SIM-1".into(),
+			edid: None,
+			backlight: None,
+			connector: Default::default(),
+		}
+	}
+
+	/// Time since the renderer started.
+	pub fn now(&self) -> Duration {
+		self.now
+	}
+
+	/// Schedules `event` `after` from now. Events due at the same time happen in the order they
+	/// were scheduled.
+	pub fn schedule(&mut self, after: Duration, event: SimEvent) {
+		self.script.insert((self.now + after, self.next_seq), event);
+		self.next_seq += 1;
+	}
+
+	/// Moves the clock forward by `by`, running every event due on the way.
+	pub fn advance(&mut self, by: Duration) {
+		let until = self.now + by;
+		while let Some(entry) = self.script.first_entry() {
+			if entry.key().0 > until {
+				break;
+			}
+			let ((at, _), event) = entry.remove_entry();
+			self.now = at;
+			self.run_event(event);
+		}
+		self.now = until;
+	}
+
+	/// Events for the server, oldest first.
+	pub fn take_events(&mut self) -> Vec<RenderEvt> {
+		self.events.drain(..).collect()
+	}
+
+	pub fn handle_command(&mut self, command: RenderCmd) {
+		match command {
+			// Monitors flip on their own schedule here, present groups included.
+			RenderCmd::SwapBuffers {
+				monitor_id,
+				buffer,
+				session_id,
+				generation,
+				acquire_fence,
+				present_group: _,
+			} => {
+				if !self.monitors.contains_key(&monitor_id) {
+					self.events.push_back(RenderEvt::BufferRequestRejected {
+						session_id,
+						monitor_id,
+						buffer,
+						generation,
+						reason: Arc::from("unknown monitor"),
+					});
+					return;
+				}
+				match acquire_fence {
+					Some(fence) => {
+						self.fenced.insert(
+							(session_id, monitor_id),
+							FencedSwap {
+								buffer: (buffer, generation),
+								_fence: fence,
+							},
+						);
+					}
+					None => self.ack(session_id, monitor_id, (buffer, generation)),
+				}
+			}
+			RenderCmd::FramebufferLink { session_id, .. }
+			| RenderCmd::SessionRemoved { session_id, .. } => {
+				self.forget(|(session, _)| *session == session_id);
+			}
+			RenderCmd::Resume => self.events.push_back(RenderEvt::Resumed),
+			RenderCmd::Screenshot { request, .. } => {
+				self.events.push_back(RenderEvt::Screenshot {
+					request,
+					result: Err(Arc::from("the simulated renderer has no pixels")),
+				});
+			}
+			RenderCmd::Shutdown
+			| RenderCmd::BufferUpload { .. }
+			| RenderCmd::SetActiveSession { .. }
+			| RenderCmd::DefineTransition(_)
+			| RenderCmd::SetPip { .. }
+			| RenderCmd::SetMonitorLayout { .. }
+			| RenderCmd::AssignMonitor { .. }
+			| RenderCmd::SetMirror { .. }
+			| RenderCmd::SetZoom { .. }
+			| RenderCmd::SetMonitorFilter { .. }
+			| RenderCmd::ZoomFocus { .. }
+			| RenderCmd::SetBackground(_)
+			| RenderCmd::DimSession { .. }
+			| RenderCmd::SetDebugHud { .. }
+			| RenderCmd::HudStats(_)
+			| RenderCmd::PresentGroup { .. }
+			| RenderCmd::Suspend => {}
+		}
+	}
+
+	fn run_event(&mut self, event: SimEvent) {
+		match event {
+			SimEvent::PageFlip(monitor_id) => self.flip(monitor_id),
+			SimEvent::SignalFence {
+				session_id,
+				monitor_id,
+			} => {
+				if let Some(swap) = self.fenced.remove(&(session_id, monitor_id)) {
+					self.ack(session_id, monitor_id, swap.buffer);
+				}
+			}
+			SimEvent::Hotplug(monitor) => {
+				self.monitors.insert(monitor.id, monitor.clone());
+				self.events.push_back(RenderEvt::MonitorOnline { monitor });
+			}
+			SimEvent::Unplug(monitor_id) => {
+				if self.monitors.remove(&monitor_id).is_some() {
+					self
+						.events
+						.push_back(RenderEvt::MonitorOffline { monitor_id });
+					self.forget(|(_, monitor)| *monitor == monitor_id);
+				}
+			}
+			SimEvent::Emit(event) => self.events.push_back(event),
+		}
+	}
+
+	fn ack(&mut self, session_id: SessionId, monitor_id: MonitorId, buffer: Buffer) {
+		self.events.push_back(RenderEvt::BufferRequestAck {
+			session_id,
+			monitor_id,
+			buffer: buffer.0,
+			generation: buffer.1,
+		});
+		// A newer buffer replaces one that never made it on screen.
+		if let Some(replaced) = self.queued.insert((session_id, monitor_id), buffer) {
+			self.events.push_back(RenderEvt::FrameDropped {
+				session_id,
+				monitor_id,
+				reason: FrameDropReason::Queue,
+			});
+			self.consumed(session_id, monitor_id, replaced);
+		}
+	}
+
+	fn flip(&mut self, monitor_id: MonitorId) {
+		if !self.monitors.contains_key(&monitor_id) {
+			return;
+		}
+		let shown = self
+			.queued
+			.extract_if(|(_, monitor), _| *monitor == monitor_id)
+			.collect::<Vec<_>>();
+		for ((session_id, monitor_id), buffer) in shown {
+			if let Some(previous) = self.front.insert((session_id, monitor_id), buffer) {
+				self.consumed(session_id, monitor_id, previous);
+			}
+		}
+		self.events.push_back(RenderEvt::PageFlip {
+			monitors: vec![monitor_id],
+		});
+	}
+
+	fn consumed(&mut self, session_id: SessionId, monitor_id: MonitorId, buffer: Buffer) {
+		self.events.push_back(RenderEvt::BufferConsumed {
+			session_id,
+			monitor_id,
+			buffer: buffer.0,
+			generation: buffer.1,
+			release_fence: None,
+		});
+	}
+
+	/// Drops swaps and buffers of the matching slots without releasing them, as the server
+	/// forgets them on its side.
+	fn forget(&mut self, matches: impl Fn(&SlotKey) -> bool) {
+		self.fenced.retain(|key, _| !matches(key));
+		self.queued.retain(|key, _| !matches(key));
+		self.front.retain(|key, _| !matches(key));
+	}
+
+	/// Serves the server until it hangs up: every refresh of the fastest monitor, all pending
+	/// fences signal and every monitor flips.
+	pub async fn run(mut self, channels: RenderingEnd) -> Result<(), RenderError> {
+		let (mut commands, events) = channels.into_parts();
+		let refresh_rate = self
+			.monitors
+			.values()
+			.map(|monitor| monitor.refresh_rate)
+			.max()
+			.unwrap_or(60)
+			.max(1);
+		let frame = Duration::from_secs(1) / refresh_rate;
+		let mut ticker = tokio::time::interval(frame);
+		loop {
+			let batch = self
+				.take_events()
+				.into_iter()
+				.map(Correlated::untagged)
+				.collect::<Vec<_>>();
+			if !batch.is_empty() && events.send(batch).await.is_err() {
+				return Ok(());
+			}
+			tokio::select! {
+				command = commands.recv() => match command.map(|command| command.msg) {
+					Some(RenderCmd::Shutdown) | None => return Ok(()),
+					Some(command) => self.handle_command(command),
+				},
+				_ = ticker.tick() => {
+					for (session_id, monitor_id) in self.fenced.keys().copied().collect::<Vec<_>>() {
+						self.schedule(Duration::ZERO, SimEvent::SignalFence { session_id, monitor_id });
+					}
+					for monitor_id in self.monitors.keys().copied().collect::<Vec<_>>() {
+						self.schedule(Duration::ZERO, SimEvent::PageFlip(monitor_id));
+					}
+					self.advance(frame);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::ops::ControlFlow;
+
+	use super::*;
+	use crate::{
+		client_layer::client::ClientId,
+		server_layer::server_core::{Effect, ServerCore},
+	};
+
+	const FRAME: Duration = Duration::from_millis(16);
+
+	/// Carries render commands and events between the core and the renderer until both are
+	/// idle, like the server loop. Returns the effects meant for clients.
+	fn pump(core: &mut ServerCore, sim: &mut SimRenderer, mut effects: Vec<Effect>) -> Vec<Effect> {
+		let mut for_clients = Vec::new();
+		loop {
+			for effect in effects.drain(..) {
+				match effect {
+					Effect::Render(command) => sim.handle_command(command),
+					other => for_clients.push(other),
+				}
+			}
+			let events = sim.take_events();
+			if events.is_empty() {
+				return for_clients;
+			}
+			for event in events {
+				match core.on_render_event(event) {
+					ControlFlow::Break(more) => effects.extend(more),
+					ControlFlow::Continue(RenderEvt::MonitorOffline { monitor_id }) => {
+						core.forget_monitor(monitor_id);
+					}
+					ControlFlow::Continue(_) => {}
+				}
+			}
+		}
+	}
+
+	fn fence() -> OwnedFd {
+		std::fs::File::open("/dev/null").unwrap().into()
+	}
+
+	#[test]
+	fn fenced_swaps_are_acked_then_released_after_the_next_flip() {
+		let monitor = SimRenderer::default_monitor();
+		let monitor_id = monitor.id;
+		let mut sim = SimRenderer::new(vec![monitor]);
+		let mut core = ServerCore::new();
+		let (client_id, session_id) = (ClientId::rand(), SessionId::rand());
+		pump(&mut core, &mut sim, Vec::new());
+
+		let request = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			Some(fence()),
+			None,
+		);
+		assert!(pump(&mut core, &mut sim, request).is_empty());
+		sim.schedule(
+			FRAME,
+			SimEvent::SignalFence {
+				session_id,
+				monitor_id,
+			},
+		);
+		sim.schedule(FRAME, SimEvent::PageFlip(monitor_id));
+		sim.advance(FRAME);
+		assert!(matches!(
+			pump(&mut core, &mut sim, Vec::new()).as_slice(),
+			[Effect::BufferRequestAck {
+				buffer: BufferIndex::Zero,
+				..
+			}]
+		));
+
+		let request = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::One,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(
+			pump(&mut core, &mut sim, request).as_slice(),
+			[Effect::BufferRequestAck {
+				buffer: BufferIndex::One,
+				..
+			}]
+		));
+		sim.schedule(FRAME, SimEvent::PageFlip(monitor_id));
+		sim.advance(FRAME);
+		assert!(matches!(
+			pump(&mut core, &mut sim, Vec::new()).as_slice(),
+			[Effect::BufferRelease { release, .. }] if release.buffer == BufferIndex::Zero
+		));
+		assert_eq!(sim.now(), 2 * FRAME);
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_ok()
+		);
+	}
+
+	#[test]
+	fn acks_arriving_after_the_monitor_went_away_are_dropped() {
+		let monitor = SimRenderer::default_monitor();
+		let monitor_id = monitor.id;
+		let mut sim = SimRenderer::new(vec![monitor]);
+		let mut core = ServerCore::new();
+		let (client_id, session_id) = (ClientId::rand(), SessionId::rand());
+		pump(&mut core, &mut sim, Vec::new());
+
+		let request = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			Some(fence()),
+			None,
+		);
+		pump(&mut core, &mut sim, request);
+		// The fence wait finished just before the unplug, but its ack only reaches the server
+		// after `MonitorOffline`.
+		sim.schedule(FRAME, SimEvent::Unplug(monitor_id));
+		sim.schedule(
+			FRAME,
+			SimEvent::Emit(RenderEvt::BufferRequestAck {
+				session_id,
+				monitor_id,
+				buffer: BufferIndex::Zero,
+				generation: 0,
+			}),
+		);
+		sim.advance(FRAME);
+		assert!(pump(&mut core, &mut sim, Vec::new()).is_empty());
+		assert!(!core.has_inflight_request(session_id));
+		assert_eq!(core.describe_session(session_id), "");
+		assert_eq!(core.rates().swap_buffers, 0);
+
+		// Requests for the gone monitor are refused by the renderer.
+		let request = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::One,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(
+			pump(&mut core, &mut sim, request).as_slice(),
+			[Effect::Error { client_id: id, .. }] if *id == client_id
+		));
+	}
+}
@@ -0,0 +1,97 @@
+use easydrm::gl;
+use skia_safe::{ColorType, Image, gpu};
+
+use super::dmabuf_import::{ChannelDepth, DmaBufImportError, DmaBufTexture};
+
+/// How Skia samples a texture with channels of `depth`.
+fn skia_format(depth: ChannelDepth) -> (gpu::gl::Format, ColorType) {
+	match depth {
+		ChannelDepth::Rgba8 => (gpu::gl::Format::RGBA8, ColorType::RGBA8888),
+		ChannelDepth::Rgb10A2 => (gpu::gl::Format::RGB10_A2, ColorType::RGBA1010102),
+		ChannelDepth::Rgba16F => (gpu::gl::Format::RGBA16F, ColorType::RGBAF16),
+	}
+}
+
+fn skia_tex_info(texture: &DmaBufTexture) -> gpu::gl::TextureInfo {
+	gpu::gl::TextureInfo {
+		target: gl::TEXTURE_2D as gpu::gl::Enum,
+		id: texture.texture_id() as gpu::gl::Enum,
+		format: skia_format(texture.depth()).0.into(),
+		protected: gpu::Protected::No,
+	}
+}
+
+impl DmaBufTexture {
+	#[tracing::instrument(skip_all)]
+	pub fn to_skia(self, label: impl AsRef<str>) -> Result<SkiaDmaBufTexture, DmaBufImportError> {
+		let backend_texture = unsafe {
+			gpu::backend_textures::make_gl(
+				(self.width, self.height),
+				gpu::Mipmapped::No,
+				skia_tex_info(&self),
+				label,
+			)
+		};
+
+		Ok(SkiaDmaBufTexture {
+			backend_texture,
+			source: self,
+			cached_image: None,
+		})
+	}
+}
+
+/// Helper struct that keeps the GL/EGL resources alive for as long as Skia needs them.
+pub struct SkiaDmaBufTexture {
+	pub backend_texture: gpu::BackendTexture,
+	source: DmaBufTexture,
+	cached_image: Option<Image>,
+}
+
+impl SkiaDmaBufTexture {
+	pub fn texture(&self) -> &gpu::BackendTexture {
+		&self.backend_texture
+	}
+
+	pub fn image<'a>(&'a mut self, gr: &mut gpu::DirectContext) -> Option<&'a Image> {
+		if self.cached_image.is_none() {
+			self.cached_image = Image::from_texture(
+				gr,
+				&self.backend_texture,
+				gpu::SurfaceOrigin::TopLeft,
+				skia_format(self.source.depth()).1,
+				skia_safe::AlphaType::Opaque,
+				None,
+			);
+		}
+		self.cached_image.as_ref()
+	}
+	/// Splits into the skia texture and inner opengl texture
+	///
+	/// # Safety
+	/// The caller is responsible for keeping `DmaBufTexture` alive while `BackendTexture` is alive.
+	pub unsafe fn into_inner(self) -> (gpu::BackendTexture, DmaBufTexture) {
+		(self.backend_texture, self.source)
+	}
+
+	pub fn update(&mut self) {
+		self.backend_texture = unsafe {
+			gpu::backend_textures::make_gl(
+				(self.source.width, self.source.height),
+				gpu::Mipmapped::No,
+				skia_tex_info(&self.source),
+				self.backend_texture.label(),
+			)
+		};
+		self.cached_image = None;
+	}
+
+	pub fn gl_texture_id(&self) -> gl::types::GLuint {
+		self.source.texture_id()
+	}
+
+	/// GPU memory behind the texture, going by the linked stride. Drivers may pad further.
+	pub fn estimated_bytes(&self) -> u64 {
+		u64::from(self.source.stride.unsigned_abs()) * u64::from(self.source.height.unsigned_abs())
+	}
+}
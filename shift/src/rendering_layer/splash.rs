@@ -0,0 +1,172 @@
+use std::{
+	f32::consts::TAU,
+	path::Path,
+	time::{Duration, Instant},
+};
+
+use skia_safe::{Canvas, Color, Data, Image, Paint, Rect, SamplingOptions};
+
+use super::{animation::ease_in_out, background::BackgroundLayer};
+
+const FADE_IN: Duration = Duration::from_millis(400);
+const FADE_OUT: Duration = Duration::from_millis(300);
+/// One full cycle of the pulsing dots.
+const PULSE_PERIOD: Duration = Duration::from_millis(1200);
+const DOT_RADIUS: f32 = 6.0;
+const DOT_SPACING: f32 = 24.0;
+
+#[derive(Clone, Copy)]
+enum SplashState {
+	/// `SHIFT_SPLASH=0`: never drawn, but still reported as ended after the first frame.
+	Disabled,
+	Showing,
+	FadingOut {
+		since: Instant,
+	},
+	Done,
+}
+
+/// Covers every monitor from DRM takeover until the first session frame is on screen,
+/// so startup shows the background and a logo instead of flickering through clears.
+pub struct Splash {
+	state: SplashState,
+	started: Instant,
+	/// Shown instead of the pulsing dots when `SHIFT_SPLASH_LOGO` is set.
+	logo: Option<Image>,
+}
+
+impl Splash {
+	pub fn from_env() -> Self {
+		let enabled = !std::env::var("SHIFT_SPLASH").is_ok_and(|v| v.trim() == "0");
+		let logo = std::env::var_os("SHIFT_SPLASH_LOGO").and_then(|path| load_logo(Path::new(&path)));
+		Self {
+			state: if enabled {
+				SplashState::Showing
+			} else {
+				SplashState::Disabled
+			},
+			started: Instant::now(),
+			logo,
+		}
+	}
+
+	pub fn is_active(&self) -> bool {
+		matches!(
+			self.state,
+			SplashState::Showing | SplashState::FadingOut { .. }
+		)
+	}
+
+	/// Starts the fade-out. Called once a session has presented.
+	pub fn finish(&mut self, now: Instant) {
+		if matches!(self.state, SplashState::Showing) {
+			self.state = SplashState::FadingOut { since: now };
+		}
+	}
+
+	/// Returns true exactly once, when the fade-out has completed.
+	pub fn advance(&mut self, now: Instant) -> bool {
+		let ended = match self.state {
+			SplashState::Disabled => true,
+			SplashState::FadingOut { since } => now.saturating_duration_since(since) >= FADE_OUT,
+			SplashState::Showing | SplashState::Done => false,
+		};
+		if ended {
+			self.state = SplashState::Done;
+		}
+		ended
+	}
+
+	/// Draws over whatever sessions drew, fading the whole splash out once they present.
+	pub fn draw(
+		&self,
+		canvas: &Canvas,
+		background: &BackgroundLayer,
+		width: f32,
+		height: f32,
+		now: Instant,
+	) {
+		let opacity = match self.state {
+			SplashState::Showing => 1.0,
+			SplashState::FadingOut { since } => 1.0 - ease_in_out(progress(now, since, FADE_OUT)),
+			SplashState::Disabled | SplashState::Done => return,
+		};
+		canvas.save_layer_alpha_f(None, opacity);
+		background.draw(canvas, width, height);
+		let elapsed = now.saturating_duration_since(self.started);
+		match &self.logo {
+			Some(logo) => draw_logo(canvas, logo, width, height, elapsed),
+			None => draw_dots(canvas, width / 2.0, height / 2.0, elapsed),
+		}
+		// Rises from black, which is what the takeover leaves on screen.
+		let fade_in = ease_in_out(progress(now, self.started, FADE_IN));
+		if fade_in < 1.0 {
+			let mut paint = Paint::default();
+			paint.set_color(Color::BLACK);
+			paint.set_alpha_f(1.0 - fade_in);
+			canvas.draw_rect(Rect::from_wh(width, height), &paint);
+		}
+		canvas.restore();
+	}
+}
+
+fn progress(now: Instant, since: Instant, duration: Duration) -> f32 {
+	(now.saturating_duration_since(since).as_secs_f32() / duration.as_secs_f32()).min(1.0)
+}
+
+/// 0.0..=1.0 over one [`PULSE_PERIOD`], delayed by `offset` periods.
+fn pulse(elapsed: Duration, offset: f32) -> f32 {
+	let phase = elapsed.as_secs_f32() / PULSE_PERIOD.as_secs_f32() - offset;
+	0.5 - 0.5 * (phase * TAU).cos()
+}
+
+fn draw_dots(canvas: &Canvas, center_x: f32, center_y: f32, elapsed: Duration) {
+	let mut paint = Paint::default();
+	paint.set_anti_alias(true);
+	paint.set_color(Color::WHITE);
+	for i in 0..3 {
+		paint.set_alpha_f(0.25 + 0.75 * pulse(elapsed, i as f32 * 0.15));
+		let x = center_x + (i as f32 - 1.0) * DOT_SPACING;
+		canvas.draw_circle((x, center_y), DOT_RADIUS, &paint);
+	}
+}
+
+fn draw_logo(canvas: &Canvas, logo: &Image, width: f32, height: f32, elapsed: Duration) {
+	// Fit within a third of the shorter side, never upscaled.
+	let max_side = width.min(height) / 3.0;
+	let scale = (max_side / logo.width() as f32)
+		.min(max_side / logo.height() as f32)
+		.min(1.0);
+	let (logo_width, logo_height) = (logo.width() as f32 * scale, logo.height() as f32 * scale);
+	let rect = Rect::from_xywh(
+		(width - logo_width) / 2.0,
+		(height - logo_height) / 2.0,
+		logo_width,
+		logo_height,
+	);
+	let mut paint = Paint::default();
+	paint.set_alpha_f(0.7 + 0.3 * pulse(elapsed, 0.0));
+	canvas.draw_image_rect_with_sampling_options(
+		logo,
+		None,
+		rect,
+		SamplingOptions::default(),
+		&paint,
+	);
+	draw_dots(
+		canvas,
+		width / 2.0,
+		rect.bottom + DOT_SPACING * 2.0,
+		elapsed,
+	);
+}
+
+fn load_logo(path: &Path) -> Option<Image> {
+	let image = std::fs::read(path)
+		.ok()
+		.and_then(|bytes| Image::from_encoded(Data::new_copy(&bytes)));
+	if image.is_none() {
+		tracing::warn!(path = %path.display(), "failed to load splash logo, using the default splash");
+	}
+	image
+}
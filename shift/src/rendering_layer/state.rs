@@ -1,4 +1,6 @@
-use tab_protocol::BufferIndex;
+use std::os::fd::OwnedFd;
+
+use tab_protocol::{BufferIndex, FramebufferLinkPayload, PresentMode};
 
 use crate::{monitor::MonitorId, sessions::SessionId};
 
@@ -6,6 +8,11 @@ use crate::{monitor::MonitorId, sessions::SessionId};
 pub(super) struct MonitorSurfaceState {
 	pub current_buffer: Option<BufferSlot>,
 	pub pending_buffer: Option<BufferSlot>,
+	/// Ready buffer waiting for `current_buffer` to reach the screen (FIFO only).
+	pub queued_buffer: Option<BufferSlot>,
+	/// Whether a page flip happened since `current_buffer` was set.
+	pub current_presented: bool,
+	pub present_mode: PresentMode,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -43,6 +50,13 @@ pub(super) struct DeferredRelease {
 	pub buffer: BufferSlot,
 }
 
+/// A `framebuffer_link` of a session that wasn't on screen, imported once it is.
+#[derive(Debug)]
+pub(super) struct DeferredLink {
+	pub payload: FramebufferLinkPayload,
+	pub dma_bufs: [OwnedFd; 2],
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(super) enum SlotOwner {
 	ClientOwned,
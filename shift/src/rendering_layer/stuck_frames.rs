@@ -0,0 +1,233 @@
+//! Detects sessions that keep submitting frames which all look the same, see
+//! `performance_warning`. Usually the client draws into a buffer other than the one it submits,
+//! or submits before its GPU work is done.
+//! - `SHIFT_STUCK_FRAME_MS` is how long a session's content may stay the same while it submits
+//!   before its client is warned. Unset or 0 turns the check off, as reading back pixels stalls
+//!   the GPU
+//! - every [`SAMPLE_INTERVAL`]th frame drawn with a session's buffer, [`SAMPLE_ROWS`] rows of it
+//!   are read back and hashed
+//! - a session that didn't submit between two samples is idle rather than stuck, and starts over
+
+use std::{
+	collections::HashMap,
+	hash::{DefaultHasher, Hasher},
+	time::{Duration, Instant},
+};
+
+use crate::{monitor::MonitorId, sessions::SessionId};
+
+/// Frames drawn with a session's buffer between two samples.
+pub const SAMPLE_INTERVAL: u32 = 30;
+/// Rows read back per sample, evenly spread over the buffer.
+pub const SAMPLE_ROWS: usize = 3;
+
+/// A session whose content didn't change for `duration`, over `frames` submissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StuckFrame {
+	pub monitor_id: MonitorId,
+	pub session_id: SessionId,
+	pub duration: Duration,
+	pub frames: u32,
+}
+
+#[derive(Debug, Default)]
+struct Track {
+	frames_drawn: u32,
+	/// Hash of the last sample, and when the content last changed.
+	hash: Option<(u64, Instant)>,
+	/// Submissions since the content last changed.
+	submissions: u32,
+	submitted_since_sample: bool,
+	warned: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct StuckFrames {
+	threshold: Option<Duration>,
+	tracks: HashMap<(MonitorId, SessionId), Track>,
+	warnings: Vec<StuckFrame>,
+}
+
+impl StuckFrames {
+	pub fn from_env() -> Self {
+		let threshold =
+			std::env::var("SHIFT_STUCK_FRAME_MS")
+				.ok()
+				.and_then(|raw| match raw.trim().parse::<u64>() {
+					Ok(0) => None,
+					Ok(ms) => Some(Duration::from_millis(ms)),
+					Err(e) => {
+						tracing::warn!(value = %raw, "invalid SHIFT_STUCK_FRAME_MS: {e}");
+						None
+					}
+				});
+		Self::new(threshold)
+	}
+
+	pub fn new(threshold: Option<Duration>) -> Self {
+		Self {
+			threshold,
+			..Default::default()
+		}
+	}
+
+	/// The session swapped in a new buffer on the monitor.
+	pub fn record_submission(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		if self.threshold.is_none() {
+			return;
+		}
+		let track = self.tracks.entry((monitor_id, session_id)).or_default();
+		track.submissions = track.submissions.saturating_add(1);
+		track.submitted_since_sample = true;
+	}
+
+	/// Counts a frame drawn with the session's buffer. Returns true when this one is to be
+	/// sampled.
+	pub fn should_sample(&mut self, monitor_id: MonitorId, session_id: SessionId) -> bool {
+		let Some(track) = self.tracks.get_mut(&(monitor_id, session_id)) else {
+			return false;
+		};
+		track.frames_drawn = track.frames_drawn.wrapping_add(1);
+		track.frames_drawn % SAMPLE_INTERVAL == 0
+	}
+
+	pub fn record_sample(
+		&mut self,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		hash: u64,
+		now: Instant,
+	) {
+		let (Some(threshold), Some(track)) = (
+			self.threshold,
+			self.tracks.get_mut(&(monitor_id, session_id)),
+		) else {
+			return;
+		};
+		let changed = track.hash.is_none_or(|(last, _)| last != hash);
+		if changed || !track.submitted_since_sample {
+			track.hash = Some((hash, now));
+			track.submissions = 0;
+			track.warned = false;
+		}
+		track.submitted_since_sample = false;
+		let Some((_, since)) = track.hash else {
+			return;
+		};
+		let duration = now.saturating_duration_since(since);
+		if !track.warned && track.submissions > 0 && duration >= threshold {
+			track.warned = true;
+			self.warnings.push(StuckFrame {
+				monitor_id,
+				session_id,
+				duration,
+				frames: track.submissions,
+			});
+		}
+	}
+
+	/// Sessions that went past the threshold since the last call, once each until their content
+	/// changes.
+	pub fn take_warnings(&mut self) -> Vec<StuckFrame> {
+		std::mem::take(&mut self.warnings)
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.tracks.retain(|(_, session), _| *session != session_id);
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.tracks.retain(|(monitor, _), _| *monitor != monitor_id);
+	}
+}
+
+/// Hash of the rows read back for a sample.
+pub fn hash_sample(rows: &[u8]) -> u64 {
+	let mut hasher = DefaultHasher::new();
+	hasher.write(rows);
+	hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn sample(
+		stuck: &mut StuckFrames,
+		monitor_id: MonitorId,
+		session_id: SessionId,
+		hash: u64,
+		now: Instant,
+	) {
+		for _ in 1..SAMPLE_INTERVAL {
+			assert!(!stuck.should_sample(monitor_id, session_id));
+		}
+		assert!(stuck.should_sample(monitor_id, session_id));
+		stuck.record_sample(monitor_id, session_id, hash, now);
+	}
+
+	#[test]
+	fn static_content_is_reported_once_while_the_session_submits() {
+		let monitor_id = MonitorId::rand();
+		let session_id = SessionId::rand();
+		let start = Instant::now();
+		let mut stuck = StuckFrames::new(Some(Duration::from_secs(2)));
+		assert!(!stuck.should_sample(monitor_id, session_id));
+
+		stuck.record_submission(monitor_id, session_id);
+		sample(&mut stuck, monitor_id, session_id, 1, start);
+		for second in 1..=3 {
+			stuck.record_submission(monitor_id, session_id);
+			sample(
+				&mut stuck,
+				monitor_id,
+				session_id,
+				1,
+				start + Duration::from_secs(second),
+			);
+		}
+		assert_eq!(
+			stuck.take_warnings(),
+			vec![StuckFrame {
+				monitor_id,
+				session_id,
+				duration: Duration::from_secs(2),
+				frames: 2,
+			}]
+		);
+		assert!(stuck.take_warnings().is_empty());
+
+		// New content starts over, and so does a session that stopped submitting.
+		stuck.record_submission(monitor_id, session_id);
+		sample(
+			&mut stuck,
+			monitor_id,
+			session_id,
+			2,
+			start + Duration::from_secs(4),
+		);
+		sample(
+			&mut stuck,
+			monitor_id,
+			session_id,
+			2,
+			start + Duration::from_secs(7),
+		);
+		stuck.record_submission(monitor_id, session_id);
+		sample(
+			&mut stuck,
+			monitor_id,
+			session_id,
+			2,
+			start + Duration::from_secs(8),
+		);
+		assert!(stuck.take_warnings().is_empty());
+
+		stuck.forget_session(session_id);
+		assert!(!stuck.should_sample(monitor_id, session_id));
+		let mut off = StuckFrames::new(None);
+		off.record_submission(monitor_id, session_id);
+		assert!(!off.should_sample(monitor_id, session_id));
+		assert_ne!(hash_sample(&[1, 2, 3]), hash_sample(&[1, 2, 4]));
+	}
+}
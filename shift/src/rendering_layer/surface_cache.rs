@@ -1,21 +1,36 @@
-use std::collections::HashMap;
+use std::{cell::OnceCell, collections::HashMap};
 
 use easydrm::{Monitor, MonitorContextCreationRequest, gl};
 use skia_safe::{
 	self as skia, FilterMode, MipmapMode, Paint, SamplingOptions, gpu, gpu::gl::FramebufferInfo,
 };
+use tab_protocol::PanelOrientation;
 
-use crate::monitor::{Monitor as ServerLayerMonitor, MonitorId};
+use crate::monitor::{Backlight, Connector, Edid, Monitor as ServerLayerMonitor, MonitorId};
 
-use super::{RenderError, dmabuf_import::SkiaDmaBufTexture};
+use super::{RenderError, skia_texture::SkiaDmaBufTexture};
 
 pub struct MonitorRenderState {
 	pub surfaces_by_fbo: HashMap<i32, skia::Surface>,
+	/// Size of the upright picture, the mode's size turned by [`Self::orientation`].
 	pub width: usize,
 	pub height: usize,
+	pub orientation: PanelOrientation,
+	/// What a rotated panel shows, drawn upright and turned onto the framebuffer by
+	/// [`Self::flush`]. `None` on panels mounted the normal way.
+	upright: Option<skia::Surface>,
 	pub target_fbo: i32,
 	pub gl: gl::Gles2,
+	/// Random until [`Self::identify`], which happens before the monitor is reported.
 	pub id: MonitorId,
+	identified: bool,
+	/// Whether [`super::gl_debug::GlDebug::enable_current`] ran on this monitor's context.
+	pub gl_debug_checked: bool,
+	/// Read from sysfs the first time the monitor is reported.
+	edid: OnceCell<Option<Edid>>,
+	backlight: OnceCell<Option<Backlight>>,
+	/// Found the first time the monitor is reported, its properties are read on every sync.
+	connector: OnceCell<Option<Connector>>,
 }
 
 impl MonitorRenderState {
@@ -27,25 +42,54 @@ impl MonitorRenderState {
 			surfaces_by_fbo: HashMap::new(),
 			width: req.width,
 			height: req.height,
+			orientation: PanelOrientation::Normal,
+			upright: None,
 			target_fbo,
 			gl: req.gl.clone(),
 			id: MonitorId::rand(),
+			identified: false,
+			gl_debug_checked: false,
+			edid: OnceCell::new(),
+			backlight: OnceCell::new(),
+			connector: OnceCell::new(),
 		})
 	}
 
+	pub fn is_identified(&self) -> bool {
+		self.identified
+	}
+
+	/// Replaces the placeholder id with the monitor's stable one, see [`crate::monitor::MonitorIds`].
+	pub fn identify(&mut self, id: MonitorId) {
+		self.id = id;
+		self.identified = true;
+	}
+
+	/// Takes `width` and `height` of the mode and how the panel is mounted. Returns whether the
+	/// upright picture changed size since the last frame.
 	#[tracing::instrument(skip_all, fields(width = width, height = height, fbo = fbo))]
 	pub fn ensure_surface_target(
 		&mut self,
 		gr: &mut gpu::DirectContext,
 		width: usize,
 		height: usize,
+		orientation: PanelOrientation,
 		fbo: i32,
-	) -> Result<(), RenderError> {
-		let size_changed = self.width != width || self.height != height;
+	) -> Result<bool, RenderError> {
+		let (upright_width, upright_height) = if orientation.is_sideways() {
+			(height, width)
+		} else {
+			(width, height)
+		};
+		let size_changed = self.width != upright_width || self.height != upright_height;
 		if size_changed {
 			self.surfaces_by_fbo.clear();
-			self.width = width;
-			self.height = height;
+			self.width = upright_width;
+			self.height = upright_height;
+		}
+		if size_changed || self.orientation != orientation {
+			self.upright = None;
+			self.orientation = orientation;
 		}
 		self.target_fbo = fbo;
 		if !self.surfaces_by_fbo.contains_key(&fbo) {
@@ -53,28 +97,128 @@ impl MonitorRenderState {
 				.surfaces_by_fbo
 				.insert(fbo, skia_surface_for_fbo(gr, width, height, fbo)?);
 		}
-		Ok(())
+		if orientation != PanelOrientation::Normal && self.upright.is_none() {
+			let upright = self
+				.surfaces_by_fbo
+				.get_mut(&fbo)
+				.and_then(|surface| {
+					surface.new_surface_with_dimensions((upright_width as i32, upright_height as i32))
+				})
+				.ok_or(RenderError::SkiaSurface)?;
+			self.upright = Some(upright);
+		}
+		// The framebuffer was cleared to black, the upright picture starts out the same.
+		if let Some(upright) = &mut self.upright {
+			upright.canvas().clear(skia::Color::BLACK);
+		}
+		Ok(size_changed)
+	}
+
+	/// The surface sessions are drawn on: the upright one on rotated panels, else the
+	/// framebuffer's.
+	fn target(&mut self) -> Option<&mut skia::Surface> {
+		match &mut self.upright {
+			Some(upright) => Some(upright),
+			None => self.surfaces_by_fbo.get_mut(&self.target_fbo),
+		}
 	}
 
 	pub fn canvas(&mut self) -> &skia::Canvas {
 		self
-			.surfaces_by_fbo
-			.get_mut(&self.target_fbo)
+			.target()
 			.expect("active target fbo surface missing")
 			.canvas()
 	}
 
+	/// Reads back the active target as tightly packed RGBA, returning the pixels and stride.
+	pub fn read_pixels_rgba(&mut self) -> Option<(Vec<u8>, usize)> {
+		let info = skia::ImageInfo::new(
+			(self.width as i32, self.height as i32),
+			skia::ColorType::RGBA8888,
+			skia::AlphaType::Unpremul,
+			None,
+		);
+		let stride = info.min_row_bytes();
+		let mut pixels = vec![0; stride * self.height];
+		let surface = self.target()?;
+		surface
+			.read_pixels(&info, &mut pixels, stride, (0, 0))
+			.then_some((pixels, stride))
+	}
+
+	/// A copy of what the active target shows so far, for monitors mirroring this one.
+	pub fn snapshot(&mut self) -> Option<skia::Image> {
+		self.target().map(|surface| surface.image_snapshot())
+	}
+
+	/// Turns the upright picture onto a rotated panel, then submits the frame.
 	pub fn flush(&mut self, gr: &mut gpu::DirectContext) {
+		if let Some(upright) = &mut self.upright
+			&& let Some(framebuffer) = self.surfaces_by_fbo.get_mut(&self.target_fbo)
+		{
+			let frame = upright.image_snapshot();
+			let (width, height) = (self.width as f32, self.height as f32);
+			let canvas = framebuffer.canvas();
+			canvas.save();
+			match self.orientation {
+				PanelOrientation::Normal => {}
+				PanelOrientation::UpsideDown => {
+					canvas.translate((width, height));
+					canvas.rotate(180.0, None);
+				}
+				// The upright picture's top edge goes along the panel's left side.
+				PanelOrientation::LeftSideUp => {
+					canvas.translate((0.0, width));
+					canvas.rotate(-90.0, None);
+				}
+				PanelOrientation::RightSideUp => {
+					canvas.translate((height, 0.0));
+					canvas.rotate(90.0, None);
+				}
+			}
+			canvas.draw_image(&frame, (0, 0), None);
+			canvas.restore();
+		}
 		gr.flush(None);
 	}
 
 	pub fn get_server_layer_monitor(monitor: &Monitor<Self>) -> ServerLayerMonitor {
+		let connector_id = u32::from(monitor.connector_id());
+		let edid = monitor
+			.context()
+			.edid
+			.get_or_init(|| Edid::for_connector(connector_id))
+			.clone();
+		let backlight = monitor
+			.context()
+			.backlight
+			.get_or_init(|| Backlight::for_connector(connector_id))
+			.clone();
+		let connector = monitor
+			.context()
+			.connector
+			.get_or_init(|| Connector::open(connector_id))
+			.as_ref()
+			.map(Connector::props)
+			.unwrap_or_default();
+		let (width, height) = monitor.size();
+		let sideways = connector
+			.panel_orientation
+			.is_some_and(PanelOrientation::is_sideways);
+		let (width, height) = if sideways {
+			(height, width)
+		} else {
+			(width, height)
+		};
 		crate::monitor::Monitor {
-			height: monitor.size().1 as _,
-			width: monitor.size().0 as _,
+			height: height as _,
+			width: width as _,
 			id: monitor.context().id,
-			name: format!("Monitor {}", u32::from(monitor.connector_id())),
+			name: format!("Monitor {connector_id}"),
 			refresh_rate: monitor.active_mode().vrefresh(),
+			edid,
+			backlight,
+			connector,
 		}
 	}
 
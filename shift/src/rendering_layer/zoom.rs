@@ -0,0 +1,135 @@
+//! Screen magnifier, see `zoom_set`. Each frame of a zoomed monitor is drawn again scaled by
+//! `factor` around `center`, a monitor pixel that stays in place. Changes ease in over
+//! [`ZOOM_ANIMATION`], except the center following the pointer, which moves with it.
+
+use std::{
+	collections::HashMap,
+	time::{Duration, Instant},
+};
+
+use tab_protocol::Easing;
+
+use crate::monitor::MonitorId;
+
+pub const ZOOM_ANIMATION: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomView {
+	pub factor: f32,
+	pub center: [f32; 2],
+}
+
+impl ZoomView {
+	fn lerp(self, to: Self, t: f32) -> Self {
+		let mix = |a: f32, b: f32| a + (b - a) * t;
+		Self {
+			factor: mix(self.factor, to.factor),
+			center: [
+				mix(self.center[0], to.center[0]),
+				mix(self.center[1], to.center[1]),
+			],
+		}
+	}
+}
+
+struct Zoom {
+	from: ZoomView,
+	to: ZoomView,
+	started_at: Instant,
+}
+
+impl Zoom {
+	fn view(&self, now: Instant) -> ZoomView {
+		let t =
+			now.saturating_duration_since(self.started_at).as_secs_f64() / ZOOM_ANIMATION.as_secs_f64();
+		let t = Easing::EaseOut.apply(t.clamp(0.0, 1.0)) as f32;
+		self.from.lerp(self.to, t)
+	}
+
+	fn is_done(&self, now: Instant) -> bool {
+		now.saturating_duration_since(self.started_at) >= ZOOM_ANIMATION
+	}
+}
+
+#[derive(Default)]
+pub struct Zooms {
+	monitors: HashMap<MonitorId, Zoom>,
+}
+
+impl Zooms {
+	/// Eases `monitor_id` to `to`, from wherever it is now. A factor of 1 zooms back out.
+	pub fn set(&mut self, monitor_id: MonitorId, to: ZoomView, now: Instant) {
+		let from = self.monitors.get(&monitor_id).map_or(
+			ZoomView {
+				factor: 1.0,
+				center: to.center,
+			},
+			|zoom| zoom.view(now),
+		);
+		self.monitors.insert(
+			monitor_id,
+			Zoom {
+				from,
+				to,
+				started_at: now,
+			},
+		);
+	}
+
+	/// Moves the center right away, keeping any factor change going.
+	pub fn focus(&mut self, monitor_id: MonitorId, center: [f32; 2]) {
+		if let Some(zoom) = self.monitors.get_mut(&monitor_id) {
+			zoom.from.center = center;
+			zoom.to.center = center;
+		}
+	}
+
+	/// How `monitor_id` is magnified at `now`, `None` once it is back to 1.
+	pub fn view(&mut self, monitor_id: MonitorId, now: Instant) -> Option<ZoomView> {
+		let zoom = self.monitors.get(&monitor_id)?;
+		if zoom.to.factor <= 1.0 && zoom.is_done(now) {
+			self.monitors.remove(&monitor_id);
+			return None;
+		}
+		Some(zoom.view(now))
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.monitors.remove(&monitor_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn zoom_eases_in_and_back_out() {
+		let now = Instant::now();
+		let monitor_id = MonitorId::rand();
+		let mut zooms = Zooms::default();
+		assert_eq!(zooms.view(monitor_id, now), None);
+
+		let zoomed = ZoomView {
+			factor: 4.0,
+			center: [100.0, 50.0],
+		};
+		zooms.set(monitor_id, zoomed, now);
+		assert_eq!(zooms.view(monitor_id, now).unwrap().factor, 1.0);
+		let halfway = zooms.view(monitor_id, now + ZOOM_ANIMATION / 2).unwrap();
+		assert!(halfway.factor > 1.0 && halfway.factor < 4.0);
+		assert_eq!(zooms.view(monitor_id, now + ZOOM_ANIMATION), Some(zoomed));
+
+		zooms.focus(monitor_id, [10.0, 20.0]);
+		let later = now + ZOOM_ANIMATION;
+		assert_eq!(zooms.view(monitor_id, later).unwrap().center, [10.0, 20.0]);
+
+		let out = ZoomView {
+			factor: 1.0,
+			center: [10.0, 20.0],
+		};
+		zooms.set(monitor_id, out, later);
+		assert_eq!(zooms.view(monitor_id, later).unwrap().factor, 4.0);
+		assert_eq!(zooms.view(monitor_id, later + ZOOM_ANIMATION), None);
+	}
+}
@@ -0,0 +1,82 @@
+//! Input devices grabbed for one session, see `device_grab`. Every event of a grabbed device goes
+//! to that session whatever has focus, e.g. a barcode scanner bound to a kiosk session.
+//! - a device is grabbed by at most one session, others fail until it's released
+//! - grabs end with their session
+
+use std::collections::HashMap;
+
+use tab_protocol::InputEventPayload;
+
+use crate::{error::Error, sessions::SessionId};
+
+#[derive(Debug, Default)]
+pub struct DeviceGrabs {
+	/// Session each grabbed device belongs to, by `device`.
+	grabs: HashMap<u32, SessionId>,
+}
+
+impl DeviceGrabs {
+	/// Grabs `device` for `session_id`. Fails with [`Error::DeviceGrabbed`] if another session
+	/// holds it.
+	pub fn grab(&mut self, device: u32, session_id: SessionId) -> Result<(), Error> {
+		match self.grabs.get(&device) {
+			Some(owner) if *owner != session_id => Err(Error::DeviceGrabbed),
+			_ => {
+				self.grabs.insert(device, session_id);
+				Ok(())
+			}
+		}
+	}
+
+	pub fn release(&mut self, device: u32) {
+		self.grabs.remove(&device);
+	}
+
+	/// Session `event` goes to because its device is grabbed.
+	pub fn target_for(&self, event: &InputEventPayload) -> Option<SessionId> {
+		self.grabs.get(&event.device()?).copied()
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.grabs.retain(|_, owner| *owner != session_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tab_protocol::KeyState;
+
+	use super::*;
+
+	fn key(device: u32) -> InputEventPayload {
+		InputEventPayload::Key {
+			device,
+			time_usec: 0,
+			key: 30,
+			state: KeyState::Pressed,
+			repeat: false,
+		}
+	}
+
+	#[test]
+	fn grabs_conflict_until_released_or_their_session_ends() {
+		let (kiosk, desktop) = (SessionId::rand(), SessionId::rand());
+		let (scanner, keyboard) = (7, 8);
+		let mut grabs = DeviceGrabs::default();
+		grabs.grab(scanner, kiosk).unwrap();
+		grabs.grab(scanner, kiosk).unwrap();
+		assert!(matches!(
+			grabs.grab(scanner, desktop),
+			Err(Error::DeviceGrabbed)
+		));
+		assert_eq!(grabs.target_for(&key(scanner)), Some(kiosk));
+		assert_eq!(grabs.target_for(&key(keyboard)), None);
+
+		grabs.release(scanner);
+		grabs.grab(scanner, desktop).unwrap();
+		grabs.grab(keyboard, desktop).unwrap();
+		grabs.forget_session(desktop);
+		assert_eq!(grabs.target_for(&key(scanner)), None);
+		assert_eq!(grabs.target_for(&key(keyboard)), None);
+	}
+}
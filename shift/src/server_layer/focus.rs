@@ -0,0 +1,359 @@
+use tab_protocol::{FocusTarget, InputEventPayload, PanelOrientation};
+
+use crate::{
+	monitor::{Monitor, MonitorId},
+	sessions::SessionId,
+};
+
+/// How keyboard focus is chosen once sessions can share the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyboardFocusPolicy {
+	/// Keyboard input always goes to the globally active session.
+	FollowsActive,
+	/// Keyboard input goes to whatever session is under the pointer.
+	FollowsPointer,
+}
+
+impl KeyboardFocusPolicy {
+	pub fn from_env() -> Self {
+		match std::env::var("SHIFT_KEYBOARD_FOCUS")
+			.unwrap_or_default()
+			.trim()
+			.to_ascii_lowercase()
+			.as_str()
+		{
+			"pointer" => Self::FollowsPointer,
+			_ => Self::FollowsActive,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusChange {
+	pub session_id: SessionId,
+	pub target: FocusTarget,
+	pub focused: bool,
+}
+
+fn orientation(monitor: &Monitor) -> PanelOrientation {
+	monitor
+		.connector
+		.panel_orientation
+		.unwrap_or(PanelOrientation::Normal)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MonitorSlot {
+	id: MonitorId,
+	width: f64,
+	height: f64,
+	/// Absolute devices on a rotated panel, like its touchscreen, report along its own axes.
+	orientation: PanelOrientation,
+}
+
+/// Absolute devices report positions from 0 to this across their whole area.
+const ABSOLUTE_RANGE: f64 = 65535.0;
+
+/// Tracks the pointer across monitors laid out left to right, and which sessions hold focus.
+#[derive(Debug)]
+pub struct FocusManager {
+	policy: KeyboardFocusPolicy,
+	monitors: Vec<MonitorSlot>,
+	pointer_x: f64,
+	pointer_y: f64,
+	pointer_session: Option<SessionId>,
+	keyboard_session: Option<SessionId>,
+	pointer_locked: bool,
+}
+
+impl FocusManager {
+	pub fn new(policy: KeyboardFocusPolicy) -> Self {
+		Self {
+			policy,
+			monitors: Vec::new(),
+			pointer_x: 0.0,
+			pointer_y: 0.0,
+			pointer_session: None,
+			keyboard_session: None,
+			pointer_locked: false,
+		}
+	}
+
+	pub fn add_monitor(&mut self, monitor: &Monitor) {
+		if self.monitors.iter().any(|slot| slot.id == monitor.id) {
+			return;
+		}
+		self.monitors.push(MonitorSlot {
+			id: monitor.id,
+			width: monitor.width.max(1) as f64,
+			height: monitor.height.max(1) as f64,
+			orientation: orientation(monitor),
+		});
+		self.clamp_pointer();
+	}
+
+	/// Picks up a new mode for a monitor without moving it in the layout.
+	pub fn update_monitor(&mut self, monitor: &Monitor) {
+		let Some(slot) = self.monitors.iter_mut().find(|slot| slot.id == monitor.id) else {
+			return;
+		};
+		slot.width = monitor.width.max(1) as f64;
+		slot.height = monitor.height.max(1) as f64;
+		slot.orientation = orientation(monitor);
+		self.clamp_pointer();
+	}
+
+	pub fn remove_monitor(&mut self, monitor_id: MonitorId) {
+		self.monitors.retain(|slot| slot.id != monitor_id);
+		self.clamp_pointer();
+	}
+
+	fn layout_width(&self) -> f64 {
+		self.monitors.iter().map(|slot| slot.width).sum()
+	}
+
+	fn clamp_pointer(&mut self) {
+		let width = self.layout_width();
+		if width <= 0.0 {
+			self.pointer_x = 0.0;
+			self.pointer_y = 0.0;
+			return;
+		}
+		self.pointer_x = self.pointer_x.clamp(0.0, width - 1.0);
+		let height = self
+			.monitor_at(self.pointer_x)
+			.map(|(slot, _)| slot.height)
+			.unwrap_or(1.0);
+		self.pointer_y = self.pointer_y.clamp(0.0, height - 1.0);
+	}
+
+	fn layout_height(&self) -> f64 {
+		self
+			.monitors
+			.iter()
+			.map(|slot| slot.height)
+			.fold(0.0, f64::max)
+	}
+
+	/// Maps an absolute device position over the whole layout, monitors top aligned. Returns the
+	/// monitor it falls on, that monitor's left edge and the position local to it, turned upright
+	/// on rotated panels.
+	fn locate_absolute(
+		&self,
+		x_transformed: f64,
+		y_transformed: f64,
+	) -> Option<(MonitorSlot, f64, f64, f64)> {
+		let x = x_transformed / ABSOLUTE_RANGE * self.layout_width();
+		let y = y_transformed / ABSOLUTE_RANGE * self.layout_height();
+		let (slot, left) = self.monitor_at(x)?;
+		let (x, y) = slot
+			.orientation
+			.to_upright(((x - left) / slot.width, y / slot.height));
+		Some((
+			slot,
+			left,
+			(x * slot.width).clamp(0.0, slot.width - 1.0),
+			(y * slot.height).clamp(0.0, slot.height - 1.0),
+		))
+	}
+
+	/// Rewrites absolute pointer and touch positions, which devices report across the whole
+	/// layout, to be relative to the monitor they fall on, and names that monitor.
+	pub fn localize(&self, event: &mut InputEventPayload) {
+		let (x_transformed, y_transformed, monitor_id) = match event {
+			InputEventPayload::PointerMotionAbsolute {
+				x_transformed,
+				y_transformed,
+				monitor_id,
+				..
+			} => (x_transformed, y_transformed, monitor_id),
+			InputEventPayload::TouchDown { contact, .. }
+			| InputEventPayload::TouchMotion { contact, .. } => (
+				&mut contact.x_transformed,
+				&mut contact.y_transformed,
+				&mut contact.monitor_id,
+			),
+			_ => return,
+		};
+		let Some((slot, _, x, y)) = self.locate_absolute(*x_transformed, *y_transformed) else {
+			return;
+		};
+		*x_transformed = x / slot.width * ABSOLUTE_RANGE;
+		*y_transformed = y / slot.height * ABSOLUTE_RANGE;
+		*monitor_id = Some(slot.id.to_string());
+	}
+
+	fn monitor_at(&self, x: f64) -> Option<(MonitorSlot, f64)> {
+		let mut left = 0.0;
+		for slot in &self.monitors {
+			if x < left + slot.width {
+				return Some((*slot, left));
+			}
+			left += slot.width;
+		}
+		self.monitors.last().map(|slot| (*slot, left - slot.width))
+	}
+
+	/// Freezes the pointer in place while a session holds a pointer lock.
+	pub fn set_pointer_locked(&mut self, locked: bool) {
+		self.pointer_locked = locked;
+	}
+
+	pub fn is_pointer_locked(&self) -> bool {
+		self.pointer_locked
+	}
+
+	/// Moves the pointer according to a motion event, before [`Self::localize`]. Returns whether
+	/// the pointer moved.
+	pub fn apply_motion(&mut self, event: &InputEventPayload) -> bool {
+		if self.pointer_locked {
+			return false;
+		}
+		match event {
+			InputEventPayload::PointerMotion { dx, dy, .. } => {
+				self.pointer_x += dx;
+				self.pointer_y += dy;
+			}
+			InputEventPayload::PointerMotionAbsolute {
+				x_transformed,
+				y_transformed,
+				..
+			} => {
+				let Some((_, left, x, y)) = self.locate_absolute(*x_transformed, *y_transformed) else {
+					return true;
+				};
+				self.pointer_x = left + x;
+				self.pointer_y = y;
+			}
+			_ => return false,
+		}
+		self.clamp_pointer();
+		true
+	}
+
+	/// Monitor under the pointer and the pointer position local to that monitor.
+	pub fn pointer_location(&self) -> Option<(MonitorId, f64, f64)> {
+		let (slot, left) = self.monitor_at(self.pointer_x)?;
+		Some((slot.id, self.pointer_x - left, self.pointer_y))
+	}
+
+	pub fn pointer_session(&self) -> Option<SessionId> {
+		self.pointer_session
+	}
+
+	/// Session that should receive `event`.
+	pub fn target_for(&self, event: &InputEventPayload) -> Option<SessionId> {
+		match event {
+			InputEventPayload::Key { .. } | InputEventPayload::SwitchToggle { .. } => {
+				self.keyboard_session
+			}
+			_ => self.pointer_session,
+		}
+	}
+
+	/// Updates focus holders and reports the sessions that gained or lost focus.
+	pub fn update(
+		&mut self,
+		pointer_session: Option<SessionId>,
+		active_session: Option<SessionId>,
+	) -> Vec<FocusChange> {
+		let keyboard_session = match self.policy {
+			KeyboardFocusPolicy::FollowsActive => active_session,
+			KeyboardFocusPolicy::FollowsPointer => pointer_session,
+		};
+		let mut changes = Vec::new();
+		for (target, current, next) in [
+			(
+				FocusTarget::Pointer,
+				&mut self.pointer_session,
+				pointer_session,
+			),
+			(
+				FocusTarget::Keyboard,
+				&mut self.keyboard_session,
+				keyboard_session,
+			),
+		] {
+			if *current == next {
+				continue;
+			}
+			if let Some(session_id) = *current {
+				changes.push(FocusChange {
+					session_id,
+					target,
+					focused: false,
+				});
+			}
+			if let Some(session_id) = next {
+				changes.push(FocusChange {
+					session_id,
+					target,
+					focused: true,
+				});
+			}
+			*current = next;
+		}
+		changes
+	}
+
+	/// Drops focus held by a session that went away, without emitting focus_out.
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		if self.pointer_session == Some(session_id) {
+			self.pointer_session = None;
+		}
+		if self.keyboard_session == Some(session_id) {
+			self.keyboard_session = None;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::monitor::ConnectorProps;
+
+	use super::*;
+
+	#[test]
+	fn touches_on_a_rotated_panel_land_upright() {
+		let panel = Monitor {
+			id: MonitorId::rand(),
+			width: 800,
+			height: 1280,
+			refresh_rate: 60,
+			name: "eDP-1".into(),
+			edid: None,
+			backlight: None,
+			connector: ConnectorProps {
+				panel_orientation: Some(PanelOrientation::LeftSideUp),
+				..Default::default()
+			},
+		};
+		let mut focus = FocusManager::new(KeyboardFocusPolicy::FollowsActive);
+		focus.add_monitor(&panel);
+		// The panel's own top left corner is the upright picture's top right.
+		let mut event = InputEventPayload::PointerMotionAbsolute {
+			device: 1,
+			time_usec: 0,
+			x: 0.0,
+			y: 0.0,
+			x_transformed: 0.0,
+			y_transformed: 0.0,
+			monitor_id: None,
+		};
+		assert!(focus.apply_motion(&event));
+		assert_eq!(focus.pointer_location(), Some((panel.id, 799.0, 0.0)));
+		focus.localize(&mut event);
+		let InputEventPayload::PointerMotionAbsolute {
+			x_transformed,
+			y_transformed,
+			..
+		} = event
+		else {
+			unreachable!();
+		};
+		assert_eq!(
+			(x_transformed, y_transformed),
+			(799.0 / 800.0 * ABSOLUTE_RANGE, 0.0)
+		);
+	}
+}
@@ -1,4 +1,13 @@
+mod device_grabs;
+mod focus;
+mod selection;
 mod server;
+pub(crate) mod server_core;
+mod suspend;
+mod switches;
+mod uploads;
+pub(crate) mod visibility_hooks;
+mod watchdog;
 
 pub use server::BindError;
 pub use server::ShiftServer;
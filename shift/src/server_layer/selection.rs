@@ -0,0 +1,178 @@
+//! Clipboard shared between sessions, see `selection_offer`.
+//! - every session is in a clipboard group: the shared default one, unless an admin moved it
+//! - a group holds the latest offer of any of its sessions, which `selection_request` reads
+//! - offers are sealed memfds, so shift hands out the same data without copying it
+
+use std::{
+	collections::HashMap,
+	fs::File,
+	io,
+	os::fd::{AsRawFd, OwnedFd},
+};
+
+use crate::{error::Error, sessions::SessionId};
+
+/// Largest selection shift passes on.
+pub const MAX_SELECTION_BYTES: u64 = 64 * 1024 * 1024;
+const MAX_MIME_TYPE_LEN: usize = 255;
+const REQUIRED_SEALS: i32 = libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE;
+
+#[derive(Debug)]
+pub struct Selection {
+	pub source: SessionId,
+	pub mime_type: String,
+	pub size: u64,
+	data: OwnedFd,
+}
+
+impl Selection {
+	/// A new FD for the data, to send to a client.
+	pub fn data(&self) -> io::Result<OwnedFd> {
+		self.data.try_clone()
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Selections {
+	/// Sessions outside the default group.
+	groups: HashMap<SessionId, String>,
+	/// Latest offer of each group, `None` being the default group.
+	current: HashMap<Option<String>, Selection>,
+}
+
+impl Selections {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn group(&self, session_id: SessionId) -> Option<String> {
+		self.groups.get(&session_id).cloned()
+	}
+
+	/// Makes `data` the selection of the session's group, replacing the previous one.
+	pub fn offer(
+		&mut self,
+		session_id: SessionId,
+		mime_type: String,
+		data: OwnedFd,
+	) -> Result<(), Error> {
+		if mime_type.is_empty() || mime_type.len() > MAX_MIME_TYPE_LEN || !mime_type.contains('/') {
+			return Err(Error::InvalidSelection(format!(
+				"invalid mime type {mime_type:?}"
+			)));
+		}
+		let size = sealed_size(&data).map_err(Error::InvalidSelection)?;
+		self.current.insert(
+			self.group(session_id),
+			Selection {
+				source: session_id,
+				mime_type,
+				size,
+				data,
+			},
+		);
+		Ok(())
+	}
+
+	/// The selection `session_id` may paste, if its group has one.
+	pub fn request(&self, session_id: SessionId) -> Option<&Selection> {
+		self.current.get(&self.group(session_id))
+	}
+
+	/// Moves a session to another group. Selections it offered stay where they were.
+	pub fn set_group(&mut self, session_id: SessionId, group: Option<String>) {
+		match group {
+			Some(group) => self.groups.insert(session_id, group),
+			None => self.groups.remove(&session_id),
+		};
+	}
+
+	/// Drops the session's group and whatever it offered.
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.groups.remove(&session_id);
+		self
+			.current
+			.retain(|_, selection| selection.source != session_id);
+	}
+}
+
+/// Size of a memfd that can't be written or resized anymore.
+fn sealed_size(data: &OwnedFd) -> Result<u64, String> {
+	// SAFETY: plain fcntl on an fd we own.
+	let seals = unsafe { libc::fcntl(data.as_raw_fd(), libc::F_GET_SEALS) };
+	if seals < 0 || seals & REQUIRED_SEALS != REQUIRED_SEALS {
+		return Err("selection data must be a memfd sealed against writes and resizes".into());
+	}
+	let size = File::from(data.try_clone().map_err(|e| e.to_string())?)
+		.metadata()
+		.map_err(|e| e.to_string())?
+		.len();
+	if size > MAX_SELECTION_BYTES {
+		return Err(format!(
+			"selection is {size} bytes, at most {MAX_SELECTION_BYTES} are allowed"
+		));
+	}
+	Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{io::Write, os::fd::FromRawFd};
+
+	use super::*;
+
+	fn memfd(data: &[u8], seals: i32) -> OwnedFd {
+		// SAFETY: the name is a valid C string and the returned fd is checked before use.
+		let fd = unsafe {
+			libc::memfd_create(
+				c"selection-test".as_ptr(),
+				libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING,
+			)
+		};
+		assert!(fd >= 0);
+		// SAFETY: memfd_create just returned this fd and nothing else owns it.
+		let mut file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+		file.write_all(data).unwrap();
+		// SAFETY: plain fcntl on an fd we own.
+		assert!(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } >= 0);
+		file.into()
+	}
+
+	#[test]
+	fn groups_only_see_their_own_selection() {
+		let mut selections = Selections::new();
+		let [browser, terminal, kiosk] = [0, 0, 0].map(|_| SessionId::rand());
+		selections.set_group(kiosk, Some("kiosk".into()));
+		selections
+			.offer(
+				browser,
+				"text/plain".into(),
+				memfd(b"hello", REQUIRED_SEALS),
+			)
+			.unwrap();
+
+		let pasted = selections.request(terminal).unwrap();
+		assert_eq!((pasted.source, pasted.size), (browser, 5));
+		assert!(selections.request(kiosk).is_none());
+
+		selections.set_group(kiosk, None);
+		assert!(selections.request(kiosk).is_some());
+		selections.forget_session(browser);
+		assert!(selections.request(terminal).is_none());
+	}
+
+	#[test]
+	fn rejects_unsealed_data_and_bad_mime_types() {
+		let mut selections = Selections::new();
+		let session_id = SessionId::rand();
+		let unsealed = selections.offer(
+			session_id,
+			"text/plain".into(),
+			memfd(b"hello", libc::F_SEAL_SHRINK),
+		);
+		assert!(matches!(unsealed, Err(Error::InvalidSelection(_))));
+		let untyped = selections.offer(session_id, "text".into(), memfd(b"", REQUIRED_SEALS));
+		assert!(matches!(untyped, Err(Error::InvalidSelection(_))));
+		assert!(selections.request(session_id).is_none());
+	}
+}
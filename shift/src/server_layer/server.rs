@@ -1,69 +1,90 @@
 use std::{
-	collections::{HashMap, HashSet},
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt::Write as _,
 	fs::Permissions,
 	future::pending,
 	io,
-	os::unix::fs::PermissionsExt,
+	ops::ControlFlow,
+	os::{
+		fd::{AsRawFd, RawFd},
+		unix::fs::PermissionsExt,
+	},
 	path::{Path, PathBuf},
-	process::Command,
-	sync::Arc,
+	sync::{
+		Arc,
+		atomic::{AtomicU64, Ordering},
+	},
 	time::Duration,
 };
 
 use futures::future::select_all;
-use tab_protocol::TabMessageFrame;
-use thiserror::Error;
+use tab_protocol::transport::{AnyTransport, StreamListener, TransportAddr};
+use tab_protocol::{
+	MonitorAddedPayload, MonitorChangedPayload, MonitorRemovedPayload, ProtocolError, SharedFrame,
+	TabMessageFrame, message_header,
+};
+use tokio::sync::mpsc::error::SendError;
 use tokio::{
-	io::unix::AsyncFd,
-	net::{UnixListener, UnixStream, unix::SocketAddr},
-	task::JoinHandle as TokioJoinHandle,
-	time::Instant,
+	io::unix::AsyncFd, net::UnixListener, task::JoinHandle as TokioJoinHandle, time::Instant,
 };
-use tracing::error;
+use tracing::{Instrument, error};
 
+use super::device_grabs::DeviceGrabs;
+use super::focus::{FocusManager, KeyboardFocusPolicy};
+use super::selection::Selections;
+use super::server_core::{Effect, FrameRates, HiddenPacing, ServerCore};
+use super::suspend::{SleepEvent, SleepSignals};
+use super::switches::{LidCloseAction, SwitchChange, Switches};
+use super::uploads::{Upload, Uploads};
+use super::visibility_hooks::{VisibilityChange, VisibilityHooks};
+use super::watchdog::Watchdog;
 use crate::auth::error::Error as AuthError;
 use crate::{
-	auth::Token,
+	auth::{
+		Token,
+		peer::{PeerAuth, PeerCredentials},
+	},
 	client_layer::{
 		client::{Client, ClientId},
 		client_view::{self, ClientView},
+		connection_state::HandshakeTimeouts,
+		wire_tap::WireTapConfig,
 	},
 	comms::{
-		client2server::C2SMsg,
+		client2server::{AuthRequest, C2SMsg},
+		correlation::{self, Correlated, CorrelationId},
 		input2server::{InputEvt, InputEvtRx},
+		queues,
 		render2server::{RenderEvt, RenderEvtRx},
 		server2client::BufferRelease,
-		server2render::{RenderCmd, RenderCmdTx, SessionTransition},
+		server2input::{InputCmd, InputCmdTx, Shortcut},
+		server2render::{
+			Background, HudStats, RenderCmd, RenderCmdTx, SessionRegion, SessionTransition,
+		},
 	},
-	monitor::{Monitor, MonitorId},
+	crash,
+	error::Error,
+	ids::Id,
+	input_layer::{channels::ServerEnd as InputServerChannels, repeat},
+	logging::LogHandle,
+	monitor::{Backlight, Monitor, MonitorId},
 	rendering_layer::channels::ServerEnd as RenderServerChannels,
-	sessions::{PendingSession, Role, Session, SessionId},
+	sessions::{PendingSession, Role, Session, SessionId, launch::LaunchDescriptor},
+};
+use tab_protocol::{
+	BacklightInfo, CompositorHealthPayload, Easing, InputEventPayload, KeyRepeatInfo, KeyState,
+	LayoutRegion, LogRecordsPayload, MAX_ZOOM, MonitorHealth, MonitorLayoutPayload, PROTOCOL_VERSION,
+	PerformanceWarningKind, QueueStats, ServerCapabilities, SessionAssignMonitorPayload, SessionInfo,
+	SessionLifecycle, StateSyncPayload, StatsPayload, TransitionInfo, compression,
 };
-use tab_protocol::{InputEventPayload, SessionInfo, SessionLifecycle, SessionRole};
-
-#[derive(Debug, Clone, Copy)]
-struct PendingFlip {
-	session_id: SessionId,
-	monitor_id: MonitorId,
-	buffer: tab_protocol::BufferIndex,
-}
 
-#[derive(Debug, Clone, Copy)]
-struct PendingBufferRequest {
-	client_id: ClientId,
-	session_id: SessionId,
-	monitor_id: MonitorId,
-	buffer: tab_protocol::BufferIndex,
-}
+const DEFAULT_CRASH_GRACE: Duration = Duration::from_secs(3);
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum BufferOwner {
-	Client,
-	Shift,
-}
 struct ConnectedClient {
 	client_view: ClientView,
 	join_handle: TokioJoinHandle<()>,
+	/// `None` for clients living in shift, which never present.
+	socket_fd: Option<RawFd>,
 }
 impl Drop for ConnectedClient {
 	fn drop(&mut self) {
@@ -72,46 +93,128 @@ impl Drop for ConnectedClient {
 }
 pub struct ShiftServer {
 	listener: Option<UnixListener>,
+	remote_listener: Option<AsyncFd<StreamListener>>,
+	sleep_signals: Option<SleepSignals>,
 	current_session: Option<SessionId>,
+	/// Active session clients were told about with `session_active`, once a frame of it was on
+	/// screen.
+	shown_session: Option<SessionId>,
 	pending_sessions: HashMap<Token, PendingSession>,
 	active_sessions: HashMap<SessionId, Arc<Session>>,
 	loading_sessions: HashSet<SessionId>,
 	awake_sessions: HashSet<SessionId>,
 	awake_until: HashMap<SessionId, Instant>,
+	/// Whether each presenting session is on screen, as last sent in `session_visibility`.
+	visibility: HashMap<SessionId, bool>,
+	/// Told about every change to `visibility`, for audio policy and the like.
+	visibility_hooks: VisibilityHooks,
+	/// `buffer_upload`s still waiting for some of their rows.
+	uploads: Uploads,
+	pip_sessions: HashMap<MonitorId, SessionRegion>,
+	monitor_layouts: HashMap<MonitorId, Vec<SessionRegion>>,
+	monitor_sessions: HashMap<MonitorId, SessionId>,
+	/// Bumped with every change to what `state_sync` reports.
+	state_epoch: u64,
+	/// Source each mirroring monitor shows, by target.
+	monitor_mirrors: HashMap<MonitorId, MonitorId>,
+	/// Zoomed monitors whose magnifier follows the pointer, see `zoom_set`.
+	zoom_follow: HashSet<MonitorId>,
 	connected_clients: HashMap<ClientId, ConnectedClient>,
 	render_commands: RenderCmdTx,
 	render_events: RenderEvtRx,
+	/// The client frame behind what is being handled, attached to render commands sent meanwhile.
+	corr: Option<CorrelationId>,
+	/// Render commands that waited for room in the queue.
+	render_blocked: AtomicU64,
+	client_queue_capacity: usize,
+	/// Told to clients in `auth_ok`, see `input_layer::repeat`.
+	key_repeat: KeyRepeatInfo,
+	/// Session the last key press went to. Repeats are dropped once keyboard focus moved on.
+	key_repeat_target: Option<SessionId>,
+	/// Whether admins may `input_inject`, set with `SHIFT_INPUT_INJECT=1`.
+	input_inject: bool,
+	/// How long new connections may take to authenticate and link, see [`HandshakeTimeouts`].
+	handshake_timeouts: HandshakeTimeouts,
+	/// Who may authenticate without a token, set with `SHIFT_PEER_AUTH`.
+	peer_auth: PeerAuth,
+	/// Token of the initial admin session, which trusted users take when they authenticate as a
+	/// peer.
+	initial_token: Option<Token>,
 	input_events: InputEvtRx,
+	input_commands: InputCmdTx,
 	monitors: HashMap<MonitorId, Monitor>,
-	pending_buffer_requests: Vec<PendingBufferRequest>,
-	waiting_flip: Vec<PendingFlip>,
-	front_buffers: HashMap<(SessionId, MonitorId), tab_protocol::BufferIndex>,
-	buffer_ownership: HashMap<(SessionId, MonitorId, tab_protocol::BufferIndex), BufferOwner>,
-	swap_buffers_received: u64,
-	frame_done_emitted: u64,
+	/// Buffer requests, ownership and frame counters.
+	core: ServerCore,
+	/// Counters of the last full second, reported by `stats_request`.
+	last_second: FrameRates,
+	next_screenshot_request: u64,
+	pending_screenshots: HashMap<u64, (ClientId, MonitorId)>,
+	next_device_config_request: u64,
+	/// Admins waiting for `device_configs`.
+	pending_device_configs: HashMap<u64, ClientId>,
 	debug_second_session_cmd: Option<String>,
 	debug_second_session_spawned: bool,
 	debug_admin_session_id: Option<SessionId>,
 	debug_second_session_id: Option<SessionId>,
 	debug_auto_switch_interval: Option<Duration>,
 	pending_input_motion: Option<(SessionId, InputEventPayload)>,
+	focus: FocusManager,
+	pointer_lock: Option<SessionId>,
+	device_grabs: DeviceGrabs,
+	shortcuts: HashMap<Arc<str>, (SessionId, Shortcut)>,
+	debug_hud: bool,
+	background: Background,
+	/// Set once the renderer's boot splash has faded out, so admins that connect later still learn about it.
+	splash_ended: bool,
+	/// Admins that asked for the `compositor_health` heartbeat.
+	health_subscribers: HashSet<ClientId>,
+	/// Animations `session_switch` may ask for, as last reported by the renderer.
+	transitions: BTreeMap<String, TransitionInfo>,
+	/// Clipboard contents offered by sessions, and who may paste them.
+	selections: Selections,
+	/// Lid and tablet mode, as last reported by libinput.
+	switches: Switches,
+	/// Admins that asked for `lid_closed` and `tablet_mode`.
+	switch_subscribers: HashSet<ClientId>,
+	/// Sessions on screen that stopped submitting frames.
+	watchdog: Watchdog,
+	/// How long a crashed session's last frame stays on screen, see `SHIFT_CRASH_GRACE_MS`.
+	crash_grace: Duration,
+	logging: LogHandle,
 }
-#[derive(Error, Debug)]
+#[derive(thiserror::Error, Debug)]
 pub enum BindError {
 	#[error("io error: {0}")]
 	IOError(#[from] std::io::Error),
+	#[error("remote listener: {0}")]
+	RemoteListener(#[from] ProtocolError),
 }
 impl ShiftServer {
 	#[tracing::instrument(level= "info", skip(path), fields(path = ?path.as_ref().display()))]
 	pub async fn bind(
 		path: impl AsRef<Path>,
 		render_channels: RenderServerChannels,
-		input_events: InputEvtRx,
+		input_channels: InputServerChannels,
+		logging: LogHandle,
 	) -> Result<Self, BindError> {
 		std::fs::remove_file(&path).ok();
 		let listener = UnixListener::bind(&path)?;
 		std::fs::set_permissions(&path, Permissions::from_mode(0o7777)).ok();
+		let remote_listener = match std::env::var("SHIFT_REMOTE_LISTEN") {
+			Ok(raw) if !raw.trim().is_empty() => {
+				let addr = raw.trim().parse::<TransportAddr>()?;
+				let listener = addr.bind_stream_listener()?;
+				listener.set_nonblocking(true)?;
+				tracing::info!(%addr, "accepting remote sessions");
+				Some(AsyncFd::new(listener)?)
+			}
+			_ => None,
+		};
+		let sleep_signals = SleepSignals::install()
+			.inspect_err(|e| tracing::warn!("suspend signals unavailable: {e}"))
+			.ok();
 		let (render_events, render_commands) = render_channels.into_parts();
+		let (input_events, input_commands) = input_channels.into_parts();
 		let debug_second_session_cmd = std::env::var("SHIFT_DEBUG_SECOND_SESSION_CMD")
 			.ok()
 			.map(|v| v.trim().to_string())
@@ -129,31 +232,98 @@ impl ShiftServer {
 					None
 				}
 			});
+		let background = match std::env::var("SHIFT_BACKGROUND") {
+			Ok(raw) if !raw.trim().is_empty() => raw.parse::<Background>().unwrap_or_else(|e| {
+				tracing::warn!(value = %raw, "invalid SHIFT_BACKGROUND: {e}");
+				Background::default()
+			}),
+			_ => Background::default(),
+		};
+		let crash_grace = match std::env::var("SHIFT_CRASH_GRACE_MS") {
+			Ok(raw) => raw.trim().parse::<u64>().map_or_else(
+				|e| {
+					tracing::warn!(value = %raw, "invalid SHIFT_CRASH_GRACE_MS: {e}");
+					DEFAULT_CRASH_GRACE
+				},
+				Duration::from_millis,
+			),
+			Err(_) => DEFAULT_CRASH_GRACE,
+		};
+		let mut core = ServerCore::new();
+		core.set_hidden_pacing(HiddenPacing::from_env());
+		#[cfg(feature = "fault-injection")]
+		{
+			let faults = crate::faults::FaultInjection::from_env();
+			if faults.is_active() {
+				tracing::warn!(?faults, "fault injection enabled");
+			}
+			core.set_faults(faults);
+		}
 		Ok(Self {
 			listener: Some(listener),
+			remote_listener,
+			sleep_signals,
 			current_session: Default::default(),
+			shown_session: None,
 			pending_sessions: Default::default(),
 			active_sessions: Default::default(),
 			loading_sessions: Default::default(),
 			awake_sessions: Default::default(),
+			visibility: Default::default(),
+			visibility_hooks: VisibilityHooks::from_env(),
+			uploads: Default::default(),
 			awake_until: Default::default(),
+			pip_sessions: Default::default(),
+			monitor_layouts: Default::default(),
+			monitor_sessions: Default::default(),
+			state_epoch: 0,
+			monitor_mirrors: Default::default(),
+			zoom_follow: Default::default(),
 			connected_clients: Default::default(),
 			render_commands,
 			render_events,
+			corr: None,
+			render_blocked: AtomicU64::new(0),
+			client_queue_capacity: queues::capacity_from_env(
+				"SHIFT_CLIENT_QUEUE",
+				client_view::DEFAULT_QUEUE_CAPACITY,
+			),
+			key_repeat: repeat::settings_from_env(),
+			key_repeat_target: None,
+			input_inject: std::env::var("SHIFT_INPUT_INJECT").is_ok_and(|v| v.trim() == "1"),
+			handshake_timeouts: HandshakeTimeouts::from_env(),
+			peer_auth: PeerAuth::from_env(),
+			initial_token: None,
 			input_events,
+			input_commands,
 			monitors: Default::default(),
-			pending_buffer_requests: Default::default(),
-			waiting_flip: Default::default(),
-			front_buffers: Default::default(),
-			buffer_ownership: Default::default(),
-			swap_buffers_received: 0,
-			frame_done_emitted: 0,
+			core,
+			last_second: Default::default(),
+			next_screenshot_request: 0,
+			pending_screenshots: Default::default(),
+			next_device_config_request: 0,
+			pending_device_configs: Default::default(),
 			debug_second_session_cmd,
 			debug_second_session_spawned: false,
 			debug_admin_session_id: None,
 			debug_second_session_id: None,
 			debug_auto_switch_interval,
 			pending_input_motion: None,
+			focus: FocusManager::new(KeyboardFocusPolicy::from_env()),
+			pointer_lock: None,
+			device_grabs: DeviceGrabs::default(),
+			shortcuts: Default::default(),
+			debug_hud: std::env::var("SHIFT_DEBUG_HUD").is_ok_and(|v| v.trim() == "1"),
+			background,
+			splash_ended: false,
+			health_subscribers: HashSet::new(),
+			transitions: BTreeMap::new(),
+			selections: Selections::new(),
+			switches: Switches::new(LidCloseAction::from_env()),
+			switch_subscribers: HashSet::new(),
+			watchdog: Watchdog::from_env(),
+			crash_grace,
+			logging,
 		})
 	}
 
@@ -167,25 +337,20 @@ impl ShiftServer {
 		self.debug_second_session_spawned = true;
 		self.debug_admin_session_id.get_or_insert(admin_session_id);
 		let (token, pending_session) = PendingSession::normal(Some("Debug Session 2".into()));
+		let pending_session = pending_session
+			.avoiding(|id| Self::session_id_taken(&self.active_sessions, &self.pending_sessions, id));
 		let session_id = pending_session.id();
-		self.pending_sessions.insert(token.clone(), pending_session);
-		let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
-		let mut cmd = Command::new(shell);
-		cmd.args(["-c", &cmdline]);
-		cmd.env("SHIFT_SESSION_TOKEN", token.to_string());
-		match cmd.spawn() {
-			Ok(child) => {
+		match LaunchDescriptor::shell(cmdline).spawn(&token) {
+			Ok(pid) => {
+				self
+					.pending_sessions
+					.insert(token, pending_session.with_pid(pid));
 				self.debug_second_session_id = Some(session_id);
-				tracing::info!(
-					%session_id,
-					pid = child.id(),
-					"spawned SHIFT_DEBUG_SECOND_SESSION_CMD"
-				);
+				tracing::info!(%session_id, ?pid, "spawned SHIFT_DEBUG_SECOND_SESSION_CMD");
 			}
 			Err(e) => {
 				self.debug_second_session_spawned = false;
 				self.debug_second_session_id = None;
-				self.pending_sessions.remove(&token);
 				tracing::error!("failed to spawn SHIFT_DEBUG_SECOND_SESSION_CMD: {e}");
 			}
 		}
@@ -221,6 +386,8 @@ impl ShiftServer {
 				from_session_id,
 				animation: "blur".to_string(),
 				duration: Duration::from_millis(500),
+				easing: Easing::Linear,
+				params: BTreeMap::new(),
 			})
 		});
 		if let Some(from_session_id) = previous
@@ -256,6 +423,49 @@ impl ShiftServer {
 		}
 	}
 
+	async fn check_watchdog(&mut self) {
+		let visibility = &self.visibility;
+		let frozen = self
+			.watchdog
+			.check(Instant::now().into_std(), |session_id| {
+				visibility.get(&session_id).copied().unwrap_or(false)
+			});
+		for (session_id, idle) in frozen {
+			tracing::warn!(%session_id, idle_ms = idle.as_millis() as u64, "session stopped submitting frames");
+			self.set_session_unresponsive(session_id, true, idle).await;
+		}
+	}
+
+	/// Tells admins about a session the watchdog flagged or cleared, and dims it if configured.
+	async fn set_session_unresponsive(
+		&mut self,
+		session_id: SessionId,
+		unresponsive: bool,
+		idle: Duration,
+	) {
+		if let Some(dim) = self.watchdog.dim() {
+			let amount = unresponsive.then_some(dim);
+			if let Err(e) = self
+				.send_render_cmd(RenderCmd::DimSession { session_id, amount })
+				.await
+			{
+				tracing::error!("failed to send session dim to renderer: {e}");
+			}
+		}
+		for id in self.client_ids_with_role(Role::is_admin) {
+			let Some(client) = self.connected_clients.get_mut(&id) else {
+				continue;
+			};
+			if !client
+				.client_view
+				.notify_session_unresponsive(session_id, unresponsive, idle)
+				.await
+			{
+				tracing::warn!(%id, %session_id, "failed to notify session unresponsive");
+			}
+		}
+	}
+
 	async fn prune_expired_awake_sessions(&mut self) {
 		let now = Instant::now();
 		let mut expired = Vec::new();
@@ -264,14 +474,18 @@ impl ShiftServer {
 				expired.push(*session_id);
 			}
 		}
+		if expired.is_empty() {
+			return;
+		}
 		for session_id in expired {
 			self.awake_until.remove(&session_id);
-			if self.current_session != Some(session_id) {
+			if self.current_session != Some(session_id) && !self.is_composited_session(session_id) {
 				if self.awake_sessions.remove(&session_id) {
 					self.notify_session_awake_change(session_id, false).await;
 				}
 			}
 		}
+		self.refresh_visibility().await;
 	}
 
 	async fn set_awake_sessions(&mut self, sessions: impl IntoIterator<Item = SessionId>) {
@@ -284,6 +498,15 @@ impl ShiftServer {
 		for session_id in &self.loading_sessions {
 			self.awake_sessions.insert(*session_id);
 		}
+		for pip in self.pip_sessions.values() {
+			self.awake_sessions.insert(pip.session_id);
+		}
+		for region in self.monitor_layouts.values().flatten() {
+			self.awake_sessions.insert(region.session_id);
+		}
+		for session_id in self.monitor_sessions.values() {
+			self.awake_sessions.insert(*session_id);
+		}
 		for (session_id, deadline) in &self.awake_until {
 			if *deadline > now {
 				self.awake_sessions.insert(*session_id);
@@ -307,6 +530,49 @@ impl ShiftServer {
 		for session_id in woke_up {
 			self.notify_session_awake_change(session_id, true).await;
 		}
+		self.refresh_visibility().await;
+	}
+
+	/// Sends `session_visibility` to sessions that went on or off screen, and lets the core pace
+	/// the hidden ones. The old session of a running transition is still on screen.
+	async fn refresh_visibility(&mut self) {
+		let now = Instant::now();
+		let sessions = self
+			.active_sessions
+			.values()
+			.filter(|session| session.role() != Role::Observer)
+			.map(|session| session.id())
+			.collect::<Vec<_>>();
+		for session_id in sessions {
+			let visible = self.current_session == Some(session_id)
+				|| self.is_composited_session(session_id)
+				|| self
+					.awake_until
+					.get(&session_id)
+					.is_some_and(|deadline| *deadline > now);
+			if self.visibility.insert(session_id, visible) == Some(visible) {
+				continue;
+			}
+			if let Some(session) = self.active_sessions.get(&session_id) {
+				self.visibility_hooks.notify(VisibilityChange {
+					session_id,
+					display_name: session.display_name().to_string(),
+					visible,
+				});
+			}
+			let releases = self.core.set_visible(session_id, visible);
+			self.send_buffer_releases(session_id, releases).await;
+			let Some((client_id, client)) = self
+				.connected_clients
+				.iter_mut()
+				.find(|(_, client)| client.client_view.authenticated_session() == Some(session_id))
+			else {
+				continue;
+			};
+			if !client.client_view.notify_session_visibility(visible).await {
+				tracing::warn!(%client_id, %session_id, visible, "failed to notify session visibility");
+			}
+		}
 	}
 
 	async fn keep_session_awake_for(&mut self, session_id: SessionId, duration: Duration) {
@@ -322,43 +588,329 @@ impl ShiftServer {
 		}
 	}
 
+	/// Whether a session is drawn on some monitor besides being the active session.
+	fn is_composited_session(&self, session_id: SessionId) -> bool {
+		self
+			.pip_sessions
+			.values()
+			.any(|pip| pip.session_id == session_id)
+			|| self.monitor_sessions.values().any(|id| *id == session_id)
+			|| self
+				.monitor_layouts
+				.values()
+				.flatten()
+				.any(|region| region.session_id == session_id)
+	}
+
 	async fn is_session_awake(&mut self, session_id: SessionId) -> bool {
 		self.prune_expired_awake_sessions().await;
 		self.awake_sessions.contains(&session_id)
 	}
 
+	/// Whether an active or pending session already has `session_id`. Takes the registries
+	/// rather than `self` so it can run while a connected client is borrowed.
+	fn session_id_taken(
+		active_sessions: &HashMap<SessionId, Arc<Session>>,
+		pending_sessions: &HashMap<Token, PendingSession>,
+		session_id: SessionId,
+	) -> bool {
+		active_sessions.contains_key(&session_id)
+			|| pending_sessions
+				.values()
+				.any(|pending| pending.id() == session_id)
+	}
+
 	fn session_info_from(session: &Session) -> SessionInfo {
 		SessionInfo {
 			id: session.id().to_string(),
-			role: match session.role() {
-				Role::Admin => SessionRole::Admin,
-				Role::Normal => SessionRole::Session,
-			},
+			role: session.role().into(),
 			display_name: Some(session.display_name().to_string()),
 			state: if session.ready() {
 				SessionLifecycle::Occupied
 			} else {
 				SessionLifecycle::Loading
 			},
+			attached: None,
+			visible: None,
 		}
 	}
 
-	async fn notify_admins_session_state(&mut self, session: &Session) {
-		let info = Self::session_info_from(session);
-		let admin_client_ids = self
+	/// Every active and pending session, sorted by id, as `sessions` reports them.
+	fn session_list(&self) -> Vec<SessionInfo> {
+		let mut sessions = self
+			.active_sessions
+			.values()
+			.map(|session| SessionInfo {
+				attached: Some(
+					self
+						.connected_clients
+						.values()
+						.any(|client| client.client_view.authenticated_session() == Some(session.id())),
+				),
+				visible: Some(self.visibility.get(&session.id()) == Some(&true)),
+				..Self::session_info_from(session)
+			})
+			.chain(self.pending_sessions.values().map(|pending| SessionInfo {
+				id: pending.id().to_string(),
+				role: pending.role().into(),
+				display_name: pending.display_name().map(str::to_string),
+				state: SessionLifecycle::Pending,
+				attached: Some(false),
+				visible: Some(false),
+			}))
+			.collect::<Vec<_>>();
+		sessions.sort_by(|a, b| a.id.cmp(&b.id));
+		sessions
+	}
+
+	/// Moves the state `state_sync` reports on to the next epoch, and returns it.
+	fn state_changed(&mut self) -> u64 {
+		self.state_epoch += 1;
+		self.state_epoch
+	}
+
+	fn state_sync(&self) -> StateSyncPayload {
+		let mut monitors = self
+			.monitors
+			.values()
+			.map(Monitor::to_protocol_info)
+			.collect::<Vec<_>>();
+		monitors.sort_by(|a, b| a.id.cmp(&b.id));
+		let mut layouts = self
+			.monitor_layouts
+			.iter()
+			.map(|(monitor_id, regions)| MonitorLayoutPayload {
+				monitor_id: monitor_id.to_string(),
+				regions: regions
+					.iter()
+					.map(|region| LayoutRegion {
+						session_id: region.session_id.to_string(),
+						rect: region.rect,
+					})
+					.collect(),
+			})
+			.collect::<Vec<_>>();
+		layouts.sort_by(|a, b| a.monitor_id.cmp(&b.monitor_id));
+		let mut assignments = self
+			.monitor_sessions
+			.iter()
+			.map(|(monitor_id, session_id)| SessionAssignMonitorPayload {
+				session_id: Some(session_id.to_string()),
+				monitor_id: monitor_id.to_string(),
+			})
+			.collect::<Vec<_>>();
+		assignments.sort_by(|a, b| a.monitor_id.cmp(&b.monitor_id));
+		StateSyncPayload {
+			epoch: self.state_epoch,
+			monitors,
+			sessions: self.session_list(),
+			active_session: self.shown_session.map(|id| id.to_string()),
+			layouts,
+			assignments,
+			capabilities: ServerCapabilities {
+				protocol: PROTOCOL_VERSION.to_string(),
+				compression: compression::supported(),
+				input_inject: self.input_inject,
+			},
+			more: false,
+		}
+	}
+
+	/// Refreshes the snapshot written to crash reports.
+	fn update_crash_state(&self) {
+		let monitors = self
+			.monitors
+			.values()
+			.map(|m| {
+				format!(
+					"{} \"{}\" {}x{}@{}",
+					m.id, m.name, m.width, m.height, m.refresh_rate
+				)
+			})
+			.collect();
+		let sessions = self
+			.active_sessions
+			.values()
+			.map(|session| {
+				let mut line = format!(
+					"{} \"{}\" role={:?} ready={} active={}",
+					session.id(),
+					session.display_name(),
+					session.role(),
+					session.ready(),
+					self.current_session == Some(session.id()),
+				);
+				line.push_str(&self.core.describe_session(session.id()));
+				line
+			})
+			.collect();
+		let client_fds = self
+			.connected_clients
+			.values()
+			.filter_map(|client| client.socket_fd)
+			.collect();
+		crash::set_server_state(monitors, sessions, client_fds);
+	}
+
+	/// Carries out what [`ServerCore`] decided. `requester` is the client whose message led to
+	/// the effects; it is disconnected if the renderer is gone.
+	async fn apply_effects(&mut self, requester: Option<ClientId>, effects: Vec<Effect>) {
+		// Releases are sent last, one message per session.
+		let mut releases: Vec<(SessionId, Vec<BufferRelease>)> = Vec::new();
+		for effect in effects {
+			match effect {
+				Effect::Render(cmd) => {
+					if let Err(e) = self.send_render_cmd(cmd).await {
+						tracing::error!("failed to forward render command to renderer: {e}");
+						if let Some(client) = requester.and_then(|id| self.connected_clients.get_mut(&id)) {
+							client.client_view.notify_error(e.into(), true).await;
+						}
+					}
+				}
+				Effect::BufferRequestAck {
+					client_id,
+					monitor_id,
+					buffer,
+					generation,
+				} => {
+					let Some(client) = self.connected_clients.get_mut(&client_id) else {
+						continue;
+					};
+					if !client
+						.client_view
+						.notify_buffer_request_ack(monitor_id, buffer, generation)
+						.await
+					{
+						self.disconnect_client(client_id).await;
+					}
+				}
+				Effect::BufferRelease {
+					session_id,
+					release,
+				} => match releases.iter_mut().find(|(id, _)| *id == session_id) {
+					Some((_, session_releases)) => session_releases.push(release),
+					None => releases.push((session_id, vec![release])),
+				},
+				Effect::FramesDropped {
+					session_id,
+					monitor_id,
+					count,
+					reason,
+				} => {
+					let Some(client) = self
+						.connected_clients
+						.values_mut()
+						.find(|c| c.client_view.authenticated_session() == Some(session_id))
+					else {
+						continue;
+					};
+					client
+						.client_view
+						.notify_frames_dropped(monitor_id, count, reason)
+						.await;
+				}
+				Effect::Error { client_id, error } => {
+					self.notify_client_error(client_id, error).await;
+				}
+			}
+		}
+		for (session_id, releases) in releases {
+			self.send_buffer_releases(session_id, releases).await;
+		}
+	}
+
+	/// Hands buffers back to the client of `session_id` in one `buffer_release`.
+	async fn send_buffer_releases(&mut self, session_id: SessionId, releases: Vec<BufferRelease>) {
+		if releases.is_empty() {
+			return;
+		}
+		let Some(client) = self
+			.connected_clients
+			.values_mut()
+			.find(|c| c.client_view.authenticated_session() == Some(session_id))
+		else {
+			return;
+		};
+		let count = releases.len();
+		if !client.client_view.notify_buffer_release(releases).await {
+			tracing::warn!(%session_id, count, "failed to send buffer_release");
+		}
+	}
+
+	/// Returns the requester's session if it is an admin, otherwise notifies `forbidden`.
+	async fn require_admin(&mut self, client_id: ClientId) -> Option<Arc<Session>> {
+		self.require_role(client_id, Role::is_admin).await
+	}
+
+	/// Like [`Self::require_admin`], but also lets observers through, for read-only requests.
+	async fn require_observer(&mut self, client_id: ClientId) -> Option<Arc<Session>> {
+		self.require_role(client_id, Role::can_observe).await
+	}
+
+	/// Checks a `session_switch` animation and its parameters against what the renderer offers.
+	fn validate_transition(
+		&self,
+		animation: &str,
+		params: &BTreeMap<String, f64>,
+	) -> Result<(), String> {
+		let Some(info) = self.transitions.get(animation) else {
+			return Err(format!("unknown animation {animation}"));
+		};
+		info.validate(params)
+	}
+
+	async fn require_role(
+		&mut self,
+		client_id: ClientId,
+		allowed: fn(Role) -> bool,
+	) -> Option<Arc<Session>> {
+		let client = self.connected_clients.get_mut(&client_id)?;
+		let session = client
+			.client_view
+			.authenticated_session()
+			.and_then(|s| self.active_sessions.get(&s))
+			.filter(|s| allowed(s.role()))
+			.map(Arc::clone);
+		if session.is_none() {
+			client
+				.client_view
+				.notify_error(Error::Forbidden(None), false)
+				.await;
+		}
+		session
+	}
+
+	async fn notify_client_error(&mut self, client_id: ClientId, error: Error) {
+		if let Some(client) = self.connected_clients.get_mut(&client_id) {
+			client.client_view.notify_error(error, false).await;
+		}
+	}
+
+	fn client_ids_with_role(&self, allowed: fn(Role) -> bool) -> Vec<ClientId> {
+		self
 			.connected_clients
 			.iter()
 			.filter_map(|(id, client)| {
 				let session_id = client.client_view.authenticated_session()?;
 				let session = self.active_sessions.get(&session_id)?;
-				(session.role() == Role::Admin).then_some(*id)
+				allowed(session.role()).then_some(*id)
 			})
-			.collect::<Vec<_>>();
-		for id in admin_client_ids {
+			.collect()
+	}
+
+	/// Sends `session_state` to admins and observers.
+	async fn notify_session_state_watchers(&mut self, session: &Session) {
+		let info = Self::session_info_from(session);
+		let epoch = self.state_changed();
+		for id in self.client_ids_with_role(Role::can_observe) {
 			let Some(client) = self.connected_clients.get_mut(&id) else {
 				continue;
 			};
-			if !client.client_view.notify_session_state(info.clone()).await {
+			if !client
+				.client_view
+				.notify_session_state(info.clone(), epoch)
+				.await
+			{
 				tracing::warn!(%id, session_id = %session.id(), "failed to notify session state");
 			}
 		}
@@ -367,27 +919,60 @@ impl ShiftServer {
 	#[tracing::instrument(level= "info", skip(self), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
 	pub fn add_initial_session(&mut self) -> Token {
 		let (token, session) = PendingSession::admin(Some("Admin".into()));
+		let session = session
+			.avoiding(|id| Self::session_id_taken(&self.active_sessions, &self.pending_sessions, id));
 		let id = session.id();
-		self.pending_sessions.insert(token.clone(), session);
-
-		let admin_launch_cmd = std::env::var("ADMIN_LAUNCH_CMD").ok();
-		let shell = std::env::var("SHELL").unwrap_or_else(|_| "bash".to_string());
-		if let Some(admin_launch_cmd) = admin_launch_cmd {
-			let mut cmd = Command::new(shell);
-			cmd.args(["-c", &admin_launch_cmd]);
-			cmd.env("SHIFT_SESSION_TOKEN", token.to_string());
-			if let Err(e) = cmd.spawn() {
-				panic!("Failed to start admin session process: {e}");
-			}
-		}
+		let pid = match std::env::var("ADMIN_LAUNCH_CMD") {
+			Ok(admin_launch_cmd) => match LaunchDescriptor::shell(admin_launch_cmd).spawn(&token) {
+				Ok(pid) => pid,
+				Err(e) => panic!("Failed to start admin session process: {e}"),
+			},
+			Err(_) => None,
+		};
+		self
+			.pending_sessions
+			.insert(token.clone(), session.with_pid(pid));
+		self.initial_token = Some(token.clone());
 		tracing::info!(?token, %id, "added initial admin session");
 		token
 	}
+	/// Starts the DBus service if `SHIFT_DBUS` asks for it, as an admin client living in shift.
+	#[cfg(feature = "dbus")]
+	pub fn attach_dbus(&mut self) {
+		let Some(bus) = crate::dbus::Bus::from_env() else {
+			return;
+		};
+		let (token, session) = PendingSession::admin(Some("DBus".into()));
+		let session = session
+			.avoiding(|id| Self::session_id_taken(&self.active_sessions, &self.pending_sessions, id));
+		self.pending_sessions.insert(token.clone(), session);
+		let client_id = ClientId::unused(|id| self.connected_clients.contains_key(&id));
+		let (client_end, client_view) = ClientView::in_process(client_id, self.client_queue_capacity);
+		let monitors = self.monitors.values().cloned().collect();
+		let (visibility_tx, visibility_rx) = tokio::sync::mpsc::unbounded_channel();
+		self.visibility_hooks.push(visibility_tx);
+		self.connected_clients.insert(
+			client_view.id(),
+			ConnectedClient {
+				client_view,
+				join_handle: crate::dbus::spawn(bus, token, client_end, monitors, visibility_rx),
+				socket_fd: None,
+			},
+		);
+	}
 	pub async fn start(mut self) {
 		let listener = self.listener.take().unwrap();
+		let remote_listener = self.remote_listener.take();
+		let sleep_signals = self.sleep_signals.take();
 		let mut stats_tick = tokio::time::interval(std::time::Duration::from_secs(1));
 		let mut debug_auto_switch_tick = self.debug_auto_switch_interval.map(tokio::time::interval);
 		let mut input_flush_tick = tokio::time::interval(std::time::Duration::from_millis(4));
+		if self.background != Background::default() {
+			self.set_background(self.background.clone()).await;
+		}
+		if self.debug_hud {
+			self.set_debug_hud(true).await;
+		}
 		loop {
 			let span = tracing::trace_span!(
 				"server_loop",
@@ -395,27 +980,46 @@ impl ShiftServer {
 				active_sessions = self.active_sessions.len(),
 				pending_sessions = self.pending_sessions.len(),
 				current_session = ?self.current_session,
-				waiting_flip = self.waiting_flip.len(),
+				waiting_flip = self.core.waiting_flip(),
 			);
 			let _span = span.enter();
 			tokio::select! {
-					client_message = Self::read_clients_messages(&mut self.connected_clients) => self.handle_client_message(client_message.0, client_message.1).await,
-					accept_result = listener.accept() => self.handle_accept(accept_result).await,
+					client_message = Self::read_clients_messages(&mut self.connected_clients) => self.handle_correlated_message(client_message.0, client_message.1).await,
+					accept_result = listener.accept() => self.handle_accept(accept_result.and_then(|(socket, _)| socket.into_std()).map(AnyTransport::Unix)).await,
+					accept_result = Self::accept_remote(remote_listener.as_ref()) => self.handle_accept(accept_result).await,
+					sleep_event = Self::recv_sleep_event(sleep_signals.as_ref()) => self.handle_sleep_event(sleep_event).await,
 						_ = stats_tick.tick() => {
 								self.prune_expired_awake_sessions().await;
-								if self.swap_buffers_received > 0 || self.frame_done_emitted > 0 {
+								let rates = self.core.rates();
+								if rates != FrameRates::default() {
 									tracing::trace!(
-											swap_buffers_received = self.swap_buffers_received,
-											frame_done_emitted = self.frame_done_emitted,
+											swap_buffers_received = rates.swap_buffers,
+											frame_done_emitted = rates.frame_done,
+											frames_skipped = rates.frames_skipped,
 											"server stats per second"
 									);
 							}
-							self.swap_buffers_received = 0;
-							self.frame_done_emitted = 0;
+							if self.debug_hud {
+								self.send_hud_stats().await;
+							}
+							crash::push_stats(format!(
+								"swap_buffers={} frame_done={} frames_skipped={} pending_buffer_requests={} waiting_flip={}",
+								rates.swap_buffers,
+								rates.frame_done,
+								rates.frames_skipped,
+								self.core.pending_buffer_requests(),
+								self.core.waiting_flip(),
+							));
+							self.update_crash_state();
+							self.check_watchdog().await;
+							let mut effects = self.core.on_pacing_tick();
+							effects.extend(self.core.take_dropped_frames());
+							self.apply_effects(None, effects).await;
+							self.last_second = self.core.on_tick();
 					}
-					render_event = self.render_events.recv() => {
-							if let Some(event) = render_event {
-									self.handle_render_event(event).await;
+					render_events = self.render_events.recv() => {
+							if let Some(events) = render_events {
+									self.handle_render_events(events).await;
 							} else {
 									tracing::warn!("render layer event channel closed");
 									return;
@@ -444,21 +1048,88 @@ impl ShiftServer {
 		}
 	}
 
+	/// Handles a client message with its correlation id on everything it leads to.
+	async fn handle_correlated_message(&mut self, client_id: ClientId, message: Correlated<C2SMsg>) {
+		let span = tracing::info_span!(
+			"client_message",
+			%client_id,
+			corr = correlation::span_field(message.corr)
+		);
+		self.corr = message.corr;
+		self
+			.handle_client_message(client_id, message.msg)
+			.instrument(span)
+			.await;
+		self.corr = None;
+	}
+
+	/// Sends a render command on behalf of the client frame being handled, if any.
+	async fn send_render_cmd(&self, cmd: RenderCmd) -> Result<(), SendError<Correlated<RenderCmd>>> {
+		if self.render_commands.capacity() == 0 {
+			tracing::warn!("render command queue full, waiting for room");
+			self.render_blocked.fetch_add(1, Ordering::Relaxed);
+		}
+		self
+			.render_commands
+			.send(Correlated::new(self.corr, cmd))
+			.await
+	}
+
+	/// Token of the pending session a client authenticating as a peer takes: the one spawned for
+	/// it or one of its parents, or for a trusted user the initial admin session.
+	fn peer_token(&self, client_id: ClientId) -> Result<Token, AuthError> {
+		let credentials = self
+			.connected_clients
+			.get(&client_id)
+			.and_then(|client| client.socket_fd)
+			.and_then(|fd| {
+				PeerCredentials::of(fd)
+					.inspect_err(|e| tracing::debug!(%client_id, "no peer credentials: {e}"))
+					.ok()
+			})
+			.ok_or(AuthError::PeerNotTrusted)?;
+		if self.peer_auth.trusts_spawned() {
+			let lineage = credentials.lineage();
+			let spawned = self
+				.pending_sessions
+				.iter()
+				.find(|(_, pending)| pending.pid().is_some_and(|pid| lineage.contains(&pid)));
+			if let Some((token, _)) = spawned {
+				return Ok(token.clone());
+			}
+		}
+		if self.peer_auth.trusts_uid(credentials.uid) {
+			return self.initial_token.clone().ok_or(AuthError::NotFound);
+		}
+		tracing::info!(%client_id, ?credentials, "untrusted peer tried to authenticate");
+		Err(AuthError::PeerNotTrusted)
+	}
+
 	#[tracing::instrument(level= "trace", skip(self), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
 	async fn handle_client_message(&mut self, client_id: ClientId, message: C2SMsg) {
 		match message {
 			C2SMsg::Shutdown => {
 				self.disconnect_client(client_id).await;
 			}
-			C2SMsg::Auth(token) => {
-				let Some(pending_session) = self.pending_sessions.remove(&token) else {
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client
-							.client_view
-							.notify_auth_error(AuthError::NotFound)
-							.await;
+			C2SMsg::Auth(request) => {
+				let token = match request {
+					AuthRequest::Token(token) => Ok(token),
+					AuthRequest::Peer => self.peer_token(client_id),
+				};
+				let pending_session = token.and_then(|token| {
+					self
+						.pending_sessions
+						.remove(&token)
+						.ok_or(AuthError::NotFound)
+				});
+				let pending_session = match pending_session {
+					Ok(pending_session) => pending_session,
+					Err(e) => {
+						if let Some(client) = self.connected_clients.get_mut(&client_id) {
+							client.client_view.notify_auth_error(e).await;
+						}
+						return;
 					}
-					return;
 				};
 				let session = Arc::new(pending_session.promote());
 				let notify_succeeded = {
@@ -485,11 +1156,18 @@ impl ShiftServer {
 						.set_awake_sessions(self.current_session.into_iter())
 						.await;
 				}
-				if session.role() == Role::Admin {
+				let in_process = self
+					.connected_clients
+					.get(&client_id)
+					.is_some_and(|client| client.socket_fd.is_none());
+				if session.role() == Role::Admin && !in_process {
 					self.debug_admin_session_id.get_or_insert(session.id());
 					self.maybe_spawn_debug_second_session(session.id());
 				}
-				if session.role() == Role::Admin && self.current_session.is_none() {
+				if session.role() == Role::Observer || in_process {
+					// Observers and clients living in shift never present, so they are neither awake
+					// nor asleep.
+				} else if session.role() == Role::Admin && self.current_session.is_none() {
 					self.update_active_session(Some(session.id()), None).await;
 				} else if self.awake_sessions.contains(&session.id()) {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
@@ -498,29 +1176,47 @@ impl ShiftServer {
 				} else if let Some(client) = self.connected_clients.get_mut(&client_id) {
 					client.client_view.notify_session_sleep(session.id()).await;
 				}
-				if let Some(active_session_id) = self.current_session {
+				self.refresh_visibility().await;
+				if let Some(active_session_id) = self.shown_session {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_session_active(active_session_id)
+							.notify_session_active(active_session_id, self.state_epoch)
 							.await;
 					}
 				}
-				if session.role() == Role::Admin {
+				// Normal sessions get theirs once they report ready.
+				if session.role() == Role::Admin
+					&& let Some(monitor_id) = session.preferred_monitor()
+				{
+					self
+						.assign_preferred_monitor(monitor_id, session.id())
+						.await;
+				}
+				if session.role().can_observe() {
 					let session_infos = self
 						.active_sessions
 						.values()
 						.filter(|s| s.role() == Role::Normal)
 						.map(|s| Self::session_info_from(s))
 						.collect::<Vec<_>>();
+					let epoch = self.state_epoch;
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						for info in session_infos {
-							client.client_view.notify_session_state(info).await;
+							client.client_view.notify_session_state(info, epoch).await;
 						}
 					}
 				}
+				if session.role() == Role::Admin
+					&& self.splash_ended
+					&& let Some(client) = self.connected_clients.get_mut(&client_id)
+				{
+					client.client_view.notify_splash_ended().await;
+				}
 				if session.role() == Role::Normal {
-					self.notify_admins_session_state(&session).await;
+					self.notify_session_state_watchers(&session).await;
+				} else {
+					self.state_changed();
 				}
 			}
 			C2SMsg::CreateSession(req) => {
@@ -538,27 +1234,52 @@ impl ShiftServer {
 					let Some(client_session) = client_session else {
 						connected_client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 						return;
 					};
 					if client_session.role() != Role::Admin {
 						connected_client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 						return;
 					}
-					let (token, pending_session) = PendingSession::new(
-						req.display_name.map(Arc::from),
-						match req.role {
-							tab_protocol::SessionRole::Admin => Role::Admin,
-							tab_protocol::SessionRole::Session => Role::Normal,
-						},
-					);
+					let launch = match LaunchDescriptor::from_payload(&req) {
+						Ok(launch) => launch,
+						Err(e) => {
+							connected_client
+								.client_view
+								.notify_error(e.into(), false)
+								.await;
+							return;
+						}
+					};
+					let (token, pending_session) =
+						PendingSession::new(req.display_name.map(Arc::from), req.role.into());
+					let pending_session = pending_session.with_launch(launch).avoiding(|id| {
+						Self::session_id_taken(&self.active_sessions, &self.pending_sessions, id)
+					});
+					let pending_session = match pending_session.launch().spawn(&token) {
+						Ok(Some(pid)) => {
+							tracing::info!(session_id = %pending_session.id(), pid, "spawned session process");
+							pending_session.with_pid(Some(pid))
+						}
+						Ok(None) => pending_session,
+						Err(e) => {
+							tracing::warn!(session_id = %pending_session.id(), "failed to spawn session process: {e}");
+							connected_client
+								.client_view
+								.notify_error(e.into(), false)
+								.await;
+							return;
+						}
+					};
 					self
 						.pending_sessions
 						.insert(token.clone(), pending_session.clone());
+					// `connected_client` still borrows the clients, so no `state_changed` here.
+					self.state_epoch += 1;
 					if !connected_client
 						.client_view
 						.notify_session_created(token, pending_session)
@@ -577,14 +1298,7 @@ impl ShiftServer {
 					Ok(session_id) => session_id,
 					Err(e) => {
 						if let Some(client) = self.connected_clients.get_mut(&client_id) {
-							client
-								.client_view
-								.notify_error(
-									"invalid_session_id".into(),
-									Some(Arc::<str>::from(e.to_string())),
-									false,
-								)
-								.await;
+							client.client_view.notify_error(e.into(), false).await;
 						}
 						return;
 					}
@@ -602,7 +1316,7 @@ impl ShiftServer {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 					}
 					return;
@@ -611,7 +1325,7 @@ impl ShiftServer {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 					}
 					return;
@@ -620,15 +1334,24 @@ impl ShiftServer {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error(
-								"unknown_session".into(),
-								Some(Arc::<str>::from("target session is not active")),
-								false,
-							)
+							.notify_error(Error::UnknownSession("target session is not active"), false)
 							.await;
 					}
 					return;
 				}
+				if self
+					.active_sessions
+					.get(&target_session)
+					.is_some_and(|target| target.role() == Role::Observer)
+				{
+					self
+						.notify_client_error(
+							client_id,
+							Error::InvalidTransition("observer sessions can't become active"),
+						)
+						.await;
+					return;
+				}
 				if let Some(target) = self.active_sessions.get(&target_session)
 					&& target.role() != Role::Admin
 					&& !target.ready()
@@ -637,16 +1360,21 @@ impl ShiftServer {
 						client
 							.client_view
 							.notify_error(
-								"session_loading".into(),
-								Some(Arc::<str>::from(
-									"target session is still loading and cannot become active",
-								)),
+								Error::SessionLoading("target session is still loading and cannot become active"),
 								false,
 							)
 							.await;
 					}
 					return;
 				}
+				if let Some(animation) = &payload.animation
+					&& let Err(reason) = self.validate_transition(animation, &payload.params)
+				{
+					self
+						.notify_client_error(client_id, Error::InvalidAnimation(reason))
+						.await;
+					return;
+				}
 				let previous = self.current_session;
 				let transition = match (previous, payload.animation.clone()) {
 					(Some(from_session_id), Some(animation))
@@ -659,6 +1387,8 @@ impl ShiftServer {
 							from_session_id,
 							animation,
 							duration: payload.duration,
+							easing: payload.easing.unwrap_or_default(),
+							params: payload.params.clone(),
 						})
 					}
 					_ => None,
@@ -667,41 +1397,936 @@ impl ShiftServer {
 					.update_active_session(Some(target_session), transition)
 					.await;
 			}
-			C2SMsg::SessionReady(payload) => {
-				let Some(connected_client) = self.connected_clients.get(&client_id) else {
-					tracing::warn!("tried handling message from a non-existing client");
-					return;
-				};
-				let requester_session_id = connected_client.client_view.authenticated_session();
-				let Some(requester_session_id) = requester_session_id else {
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client
-							.client_view
-							.notify_error("forbidden".into(), None, false)
-							.await;
-					}
-					return;
-				};
-				if payload.session_id != requester_session_id.to_string() {
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client
-							.client_view
-							.notify_error(
-								"invalid_session_id".into(),
-								Some(Arc::<str>::from(
-									"session_ready session_id does not match authenticated session",
-								)),
-								false,
-							)
-							.await;
-					}
+			C2SMsg::TransitionDefine(payload) => {
+				if self.require_admin(client_id).await.is_none() {
 					return;
 				}
-				let Some(existing) = self.active_sessions.get(&requester_session_id).cloned() else {
+				if let Err(reason) = payload.validate() {
+					self
+						.notify_client_error(client_id, Error::InvalidAnimation(reason))
+						.await;
+					return;
+				}
+				// Switches right after this may use it, before the renderer reports back.
+				self.transitions.insert(
+					payload.name.clone(),
+					TransitionInfo {
+						name: payload.name.clone(),
+						params: Vec::new(),
+					},
+				);
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::DefineTransition(payload))
+					.await
+				{
+					tracing::error!("failed to forward DefineTransition to renderer: {e}");
+				}
+			}
+			C2SMsg::SessionPip(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(monitor_id) = payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.ok()
+					.filter(|id| self.monitors.contains_key(id))
+				else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				let overlay = match payload.rect {
+					None => {
+						self.pip_sessions.remove(&monitor_id);
+						None
+					}
+					Some(rect) => {
+						let session_id = match payload.session_id.parse::<SessionId>() {
+							Ok(session_id) => session_id,
+							Err(e) => {
+								self.notify_client_error(client_id, e.into()).await;
+								return;
+							}
+						};
+						if !self.active_sessions.contains_key(&session_id) {
+							self
+								.notify_client_error(
+									client_id,
+									Error::UnknownSession("target session is not active"),
+								)
+								.await;
+							return;
+						}
+						if rect.width <= 0 || rect.height <= 0 {
+							self
+								.notify_client_error(client_id, Error::InvalidRect)
+								.await;
+							return;
+						}
+						let overlay = SessionRegion { session_id, rect };
+						self.pip_sessions.insert(monitor_id, overlay);
+						Some(overlay)
+					}
+				};
+				self
+					.set_awake_sessions(self.current_session.into_iter())
+					.await;
+				self.refresh_focus().await;
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::SetPip {
+						monitor_id,
+						overlay,
+					})
+					.await
+				{
+					tracing::error!("failed to forward SetPip to renderer: {e}");
+				}
+			}
+			C2SMsg::MonitorHdr(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				if !payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.is_ok_and(|id| self.monitors.contains_key(&id))
+				{
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				}
+				// easydrm builds the atomic commits itself and has no way to set connector
+				// properties such as HDR_OUTPUT_METADATA yet.
+				tracing::info!(monitor_id = %payload.monitor_id, metadata = ?payload.metadata, "refusing monitor_hdr");
+				self
+					.notify_client_error(
+						client_id,
+						Error::Unsupported("HDR output metadata is not supported by the DRM backend yet"),
+					)
+					.await;
+			}
+			C2SMsg::MonitorMirror(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let known = |id: &str| {
+					id.parse::<MonitorId>()
+						.ok()
+						.filter(|id| self.monitors.contains_key(id))
+				};
+				let Some(target) = known(&payload.target_monitor) else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				let source = match payload.source_monitor.as_deref().map(known) {
+					None => None,
+					Some(Some(source)) => Some(source),
+					Some(None) => {
+						self
+							.notify_client_error(client_id, Error::UnknownMonitor)
+							.await;
+						return;
+					}
+				};
+				if let Some(source) = source {
+					let refusal = if source == target {
+						Some("a monitor can't mirror itself")
+					} else if self.monitor_mirrors.contains_key(&source) {
+						Some("the source monitor is itself mirroring another monitor")
+					} else if self.monitor_mirrors.values().any(|id| *id == target) {
+						Some("the target monitor is mirrored onto another monitor")
+					} else {
+						None
+					};
+					if let Some(reason) = refusal {
+						self
+							.notify_client_error(client_id, Error::InvalidTransition(reason))
+							.await;
+						return;
+					}
+				}
+				self.set_mirror(target, source).await;
+			}
+			C2SMsg::MonitorFilter(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(monitor_id) = payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.ok()
+					.filter(|id| self.monitors.contains_key(id))
+				else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::SetMonitorFilter {
+						monitor_id,
+						filter: payload.filter,
+					})
+					.await
+				{
+					tracing::error!("failed to forward SetMonitorFilter to renderer: {e}");
+				}
+			}
+			C2SMsg::ZoomSet(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(monitor) = payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.ok()
+					.and_then(|id| self.monitors.get(&id))
+				else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				let (monitor_id, width, height) = (monitor.id, monitor.width as f32, monitor.height as f32);
+				if !(1.0..=MAX_ZOOM).contains(&payload.factor) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::InvalidZoom("factor must be between 1 and 16"),
+						)
+						.await;
+					return;
+				}
+				let center = payload.center.unwrap_or([width / 2.0, height / 2.0]);
+				if !(0.0..=width).contains(&center[0]) || !(0.0..=height).contains(&center[1]) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::InvalidZoom("center must be on the monitor"),
+						)
+						.await;
+					return;
+				}
+				let pointer = self
+					.focus
+					.pointer_location()
+					.filter(|(id, _, _)| *id == monitor_id)
+					.map(|(_, x, y)| [x as f32, y as f32]);
+				let center = if payload.follow_pointer && payload.factor > 1.0 {
+					self.zoom_follow.insert(monitor_id);
+					pointer.unwrap_or(center)
+				} else {
+					self.zoom_follow.remove(&monitor_id);
+					center
+				};
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::SetZoom {
+						monitor_id,
+						factor: payload.factor,
+						center,
+					})
+					.await
+				{
+					tracing::error!("failed to forward SetZoom to renderer: {e}");
+				}
+			}
+			C2SMsg::MonitorLayout(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(monitor_id) = payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.ok()
+					.filter(|id| self.monitors.contains_key(id))
+				else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				let mut regions = Vec::with_capacity(payload.regions.len());
+				for region in payload.regions {
+					let session_id = match region.session_id.parse::<SessionId>() {
+						Ok(session_id) => session_id,
+						Err(e) => {
+							self.notify_client_error(client_id, e.into()).await;
+							return;
+						}
+					};
+					if !self.active_sessions.contains_key(&session_id) {
+						self
+							.notify_client_error(
+								client_id,
+								Error::UnknownSession("region session is not active"),
+							)
+							.await;
+						return;
+					}
+					if region.rect.width <= 0 || region.rect.height <= 0 {
+						self
+							.notify_client_error(client_id, Error::InvalidRect)
+							.await;
+						return;
+					}
+					regions.push(SessionRegion {
+						session_id,
+						rect: region.rect,
+					});
+				}
+				if regions.is_empty() {
+					self.monitor_layouts.remove(&monitor_id);
+				} else {
+					self.monitor_layouts.insert(monitor_id, regions.clone());
+				}
+				self.state_changed();
+				self
+					.set_awake_sessions(self.current_session.into_iter())
+					.await;
+				self.refresh_focus().await;
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::SetMonitorLayout {
+						monitor_id,
+						regions,
+					})
+					.await
+				{
+					tracing::error!("failed to forward SetMonitorLayout to renderer: {e}");
+				}
+			}
+			C2SMsg::AssignMonitor(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(monitor_id) = payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.ok()
+					.filter(|id| self.monitors.contains_key(id))
+				else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				let session_id = match payload.session_id.as_deref().map(str::parse::<SessionId>) {
+					None => None,
+					Some(Ok(session_id)) => Some(session_id),
+					Some(Err(e)) => {
+						self.notify_client_error(client_id, e.into()).await;
+						return;
+					}
+				};
+				if let Some(session_id) = session_id {
+					let Some(session) = self.active_sessions.get(&session_id) else {
+						self
+							.notify_client_error(
+								client_id,
+								Error::UnknownSession("target session is not active"),
+							)
+							.await;
+						return;
+					};
+					if session.role() == Role::Observer {
+						self
+							.notify_client_error(
+								client_id,
+								Error::InvalidTransition("observer sessions can't be assigned to a monitor"),
+							)
+							.await;
+						return;
+					}
+					if session.role() != Role::Admin && !session.ready() {
+						self
+							.notify_client_error(
+								client_id,
+								Error::SessionLoading("target session is still loading and cannot be assigned"),
+							)
+							.await;
+						return;
+					}
+				}
+				self.assign_monitor(monitor_id, session_id).await;
+			}
+			C2SMsg::PointerLock { enable } => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					self
+						.notify_client_error(
+							client_id,
+							Error::Forbidden(Some("authenticate before locking the pointer".into())),
+						)
+						.await;
+					return;
+				};
+				if !enable {
+					if self.pointer_lock == Some(session_id) {
+						self.set_pointer_lock(None).await;
+					}
+					return;
+				}
+				if self.focus.pointer_session() != Some(session_id) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::NotFocused("pointer lock requires the session to have pointer focus"),
+						)
+						.await;
+					return;
+				}
+				if self.pointer_lock != Some(session_id) {
+					self.set_pointer_lock(Some(session_id)).await;
+				}
+			}
+			C2SMsg::ShortcutRegister(payload) => {
+				let Some(admin) = self.require_admin(client_id).await else {
+					return;
+				};
+				if payload.id.is_empty() {
+					self
+						.notify_client_error(
+							client_id,
+							Error::InvalidShortcut("shortcut id must not be empty"),
+						)
+						.await;
+					return;
+				}
+				let shortcut = Shortcut {
+					id: Arc::from(payload.id),
+					modifiers: payload.modifiers,
+					key: payload.key,
+				};
+				let conflict = self.shortcuts.values().any(|(owner, existing)| {
+					let same_chord = existing.key == shortcut.key
+						&& shortcut
+							.modifiers
+							.iter()
+							.all(|m| existing.modifiers.contains(m))
+						&& existing
+							.modifiers
+							.iter()
+							.all(|m| shortcut.modifiers.contains(m));
+					(existing.id == shortcut.id && *owner != admin.id())
+						|| (same_chord && existing.id != shortcut.id)
+				});
+				if conflict {
+					self
+						.notify_client_error(client_id, Error::ShortcutConflict)
+						.await;
+					return;
+				}
+				self
+					.shortcuts
+					.insert(Arc::clone(&shortcut.id), (admin.id(), shortcut));
+				self.sync_shortcuts().await;
+			}
+			C2SMsg::ShortcutUnregister(payload) => {
+				let Some(admin) = self.require_admin(client_id).await else {
+					return;
+				};
+				let owned = self
+					.shortcuts
+					.get(payload.id.as_str())
+					.is_some_and(|(owner, _)| *owner == admin.id());
+				if owned {
+					self.shortcuts.remove(payload.id.as_str());
+					self.sync_shortcuts().await;
+				}
+			}
+			C2SMsg::DebugHud { enabled } => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				self.set_debug_hud(enabled).await;
+			}
+			C2SMsg::BackgroundSet(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				match Background::from_payload(payload) {
+					Ok(background) => self.set_background(background).await,
+					Err(e) => self.notify_client_error(client_id, e.into()).await,
+				}
+			}
+			C2SMsg::LogLevel(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				if let Err(e) = self
+					.logging
+					.set_level(payload.target.as_deref(), &payload.level)
+				{
+					self.notify_client_error(client_id, e.into()).await;
+				}
+			}
+			C2SMsg::LogDump(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let records = self.logging.dump(payload.limit.map(|limit| limit as usize));
+				self.send_log_records(client_id, records).await;
+			}
+			C2SMsg::WireTap(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let session_id = match payload.session_id.parse::<SessionId>() {
+					Ok(session_id) => session_id,
+					Err(e) => {
+						self.notify_client_error(client_id, e.into()).await;
+						return;
+					}
+				};
+				if !self.active_sessions.contains_key(&session_id) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::UnknownSession("target session is not active"),
+						)
+						.await;
+					return;
+				}
+				let config = payload.enabled.then_some(WireTapConfig {
+					payloads: payload.payloads,
+					capture: payload.capture,
+				});
+				tracing::info!(%session_id, ?config, "setting wire tap");
+				for client in self
+					.connected_clients
+					.values_mut()
+					.filter(|c| c.client_view.authenticated_session() == Some(session_id))
+				{
+					client.client_view.notify_wire_tap(config).await;
+				}
+			}
+			C2SMsg::SessionList => {
+				if self.require_observer(client_id).await.is_none() {
+					return;
+				}
+				let sessions = self.session_list();
+				if let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client.client_view.notify_sessions(sessions).await
+				{
+					tracing::warn!(%client_id, "failed to send session list");
+				}
+			}
+			C2SMsg::TransitionsList => {
+				if self.require_observer(client_id).await.is_none() {
+					return;
+				}
+				let transitions = self.transitions.values().cloned().collect();
+				if let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client.client_view.notify_transitions(transitions).await
+				{
+					tracing::warn!(%client_id, "failed to send transition list");
+				}
+			}
+			C2SMsg::StateSyncRequest => {
+				if self.require_observer(client_id).await.is_none() {
+					return;
+				}
+				let parts = self.state_sync().split();
+				let Some(client) = self.connected_clients.get_mut(&client_id) else {
+					return;
+				};
+				for part in parts {
+					if !client.client_view.notify_state_sync(part).await {
+						tracing::warn!(%client_id, "failed to send state sync");
+						return;
+					}
+				}
+			}
+			C2SMsg::StatsRequest => {
+				if self.require_observer(client_id).await.is_none() {
+					return;
+				}
+				let stats = StatsPayload {
+					active_session: self.current_session.map(|id| id.to_string()),
+					connected_clients: self.connected_clients.len() as u32,
+					sessions: self.active_sessions.len() as u32,
+					pending_sessions: self.pending_sessions.len() as u32,
+					monitors: self.monitors.len() as u32,
+					pending_buffer_requests: self.core.pending_buffer_requests() as u32,
+					waiting_flip: self.core.waiting_flip() as u32,
+					swap_buffers_per_sec: self.last_second.swap_buffers,
+					frame_done_per_sec: self.last_second.frame_done,
+					frames_skipped_per_sec: self.last_second.frames_skipped,
+					queues: self.queue_stats(),
+				};
+				if let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client.client_view.notify_stats(stats).await
+				{
+					tracing::warn!(%client_id, "failed to send stats");
+				}
+			}
+			C2SMsg::CompositorHealthSubscribe { enabled } => {
+				if self.require_observer(client_id).await.is_none() {
+					return;
+				}
+				if enabled {
+					self.health_subscribers.insert(client_id);
+				} else {
+					self.health_subscribers.remove(&client_id);
+				}
+			}
+			C2SMsg::Screenshot { monitor_id } => {
+				if self.require_observer(client_id).await.is_none() {
+					return;
+				}
+				if !self.monitors.contains_key(&monitor_id) {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				}
+				let request = self.next_screenshot_request;
+				self.next_screenshot_request += 1;
+				self
+					.pending_screenshots
+					.insert(request, (client_id, monitor_id));
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::Screenshot {
+						request,
+						monitor_id,
+					})
+					.await
+				{
+					tracing::error!("failed to forward Screenshot to renderer: {e}");
+					self.pending_screenshots.remove(&request);
+					self.notify_client_error(client_id, e.into()).await;
+				}
+			}
+			C2SMsg::SelectionOffer { mime_type, data } => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				if let Err(e) = self.selections.offer(session_id, mime_type, data) {
+					self.notify_client_error(client_id, e).await;
+				}
+			}
+			C2SMsg::SelectionRequest => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					return;
+				};
+				let Some(selection) = self.selections.request(session_id) else {
+					self
+						.notify_client_error(client_id, Error::NoSelection)
+						.await;
+					return;
+				};
+				let (mime_type, size, source) = (
+					selection.mime_type.clone(),
+					selection.size,
+					selection.source,
+				);
+				let data = match selection.data() {
+					Ok(data) => data,
+					Err(e) => {
+						self.notify_client_error(client_id, e.into()).await;
+						return;
+					}
+				};
+				if let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client
+						.client_view
+						.notify_selection(mime_type, size, source, data)
+						.await
+				{
+					tracing::warn!(%client_id, "failed to send selection");
+				}
+			}
+			C2SMsg::SelectionPolicy(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let session_id = match payload.session_id.parse::<SessionId>() {
+					Ok(session_id) => session_id,
+					Err(e) => {
+						self.notify_client_error(client_id, e.into()).await;
+						return;
+					}
+				};
+				if !self.active_sessions.contains_key(&session_id) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::UnknownSession("target session is not active"),
+						)
+						.await;
+					return;
+				}
+				self.selections.set_group(session_id, payload.group);
+			}
+			C2SMsg::BacklightGet => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let backlights = Backlight::all()
+					.into_iter()
+					.filter_map(|backlight| {
+						let brightness = backlight.brightness().ok()?;
+						let monitor_id = self
+							.monitors
+							.values()
+							.find(|monitor| monitor.backlight.as_ref() == Some(&backlight))
+							.map(|monitor| monitor.id.to_string());
+						Some(BacklightInfo {
+							level: f64::from(brightness) / f64::from(backlight.max_brightness.max(1)),
+							name: backlight.name,
+							monitor_id,
+							brightness,
+							max_brightness: backlight.max_brightness,
+						})
+					})
+					.collect::<Vec<_>>();
+				if let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client.client_view.notify_backlights(backlights).await
+				{
+					tracing::warn!(%client_id, "failed to send backlights");
+				}
+			}
+			C2SMsg::BacklightSet(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(monitor) = payload
+					.monitor_id
+					.parse::<MonitorId>()
+					.ok()
+					.and_then(|id| self.monitors.get(&id))
+				else {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+					return;
+				};
+				let result = match &monitor.backlight {
+					_ if !(0.0..=1.0).contains(&payload.level) => {
+						Err(Error::InvalidBacklight("level must be between 0 and 1"))
+					}
+					None => Err(Error::InvalidBacklight("monitor has no backlight")),
+					Some(backlight) => {
+						let brightness = (payload.level * f64::from(backlight.max_brightness)).round();
+						backlight
+							.set_brightness(brightness as u32)
+							.map_err(Error::from)
+					}
+				};
+				if let Err(e) = result {
+					self.notify_client_error(client_id, e).await;
+				}
+			}
+			C2SMsg::DeviceCalibration(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				if !payload.matrix.iter().all(|v| v.is_finite()) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::InvalidCalibration("matrix must only hold finite numbers"),
+						)
+						.await;
+					return;
+				}
+				let command = InputCmd::SetCalibration {
+					device: payload.device,
+					matrix: payload.matrix,
+				};
+				if let Err(e) = self.input_commands.send(command).await {
+					tracing::error!("failed to update input layer calibration: {e}");
+				}
+			}
+			C2SMsg::DeviceConfigure(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				if payload
+					.settings
+					.accel_speed
+					.is_some_and(|speed| !(-1.0..=1.0).contains(&speed))
+				{
+					self
+						.notify_client_error(
+							client_id,
+							Error::InvalidDeviceConfig("accel_speed must be between -1 and 1"),
+						)
+						.await;
+					return;
+				}
+				if let Err(e) = self
+					.input_commands
+					.send(InputCmd::ConfigureDevice(payload))
+					.await
+				{
+					tracing::error!("failed to configure input device: {e}");
+				}
+			}
+			C2SMsg::DeviceGrab(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let Some(session_id) = payload.session_id else {
+					self.device_grabs.release(payload.device);
+					return;
+				};
+				let session_id = match session_id.parse::<SessionId>() {
+					Ok(session_id) => session_id,
+					Err(e) => {
+						self.notify_client_error(client_id, e.into()).await;
+						return;
+					}
+				};
+				if !self.active_sessions.contains_key(&session_id) {
+					self
+						.notify_client_error(
+							client_id,
+							Error::UnknownSession("target session is not active"),
+						)
+						.await;
+					return;
+				}
+				if let Err(e) = self.device_grabs.grab(payload.device, session_id) {
+					self.notify_client_error(client_id, e).await;
+				}
+			}
+			C2SMsg::InputInject(payload) => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				if !self.input_inject {
+					self
+						.notify_client_error(
+							client_id,
+							Error::Forbidden(Some(
+								"input injection is off, see SHIFT_INPUT_INJECT".into(),
+							)),
+						)
+						.await;
+					return;
+				}
+				let injectable = matches!(
+					payload.event,
+					InputEventPayload::PointerMotion { .. }
+						| InputEventPayload::PointerMotionAbsolute { .. }
+						| InputEventPayload::PointerButton { .. }
+						| InputEventPayload::PointerAxis { .. }
+						| InputEventPayload::Key { .. }
+						| InputEventPayload::TouchDown { .. }
+						| InputEventPayload::TouchUp { .. }
+						| InputEventPayload::TouchMotion { .. }
+						| InputEventPayload::TouchFrame { .. }
+						| InputEventPayload::TouchCancel { .. }
+				);
+				if !injectable {
+					self
+						.notify_client_error(
+							client_id,
+							Error::Unsupported("only pointer, key and touch events can be injected"),
+						)
+						.await;
+					return;
+				}
+				self
+					.handle_input_event(InputEvt::Event(payload.event))
+					.await;
+			}
+			C2SMsg::DeviceConfigGet => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let request = self.next_device_config_request;
+				self.next_device_config_request += 1;
+				self.pending_device_configs.insert(request, client_id);
+				if let Err(e) = self
+					.input_commands
+					.send(InputCmd::GetDeviceConfigs { request })
+					.await
+				{
+					tracing::error!("failed to ask the input layer for device configs: {e}");
+					self.pending_device_configs.remove(&request);
+					self
+						.notify_client_error(
+							client_id,
+							Error::Io(std::io::Error::other("input layer stopped")),
+						)
+						.await;
+				}
+			}
+			C2SMsg::SwitchEventsSubscribe { enabled } => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				if !enabled {
+					self.switch_subscribers.remove(&client_id);
+					return;
+				}
+				if self.switch_subscribers.insert(client_id) {
+					for change in self.switches.current() {
+						self.notify_switch_change(client_id, change).await;
+					}
+				}
+			}
+			C2SMsg::PrepareForSleep { start } => {
+				if self.require_admin(client_id).await.is_none() {
+					return;
+				}
+				let event = match start {
+					true => SleepEvent::Suspending,
+					false => SleepEvent::Resumed,
+				};
+				self.handle_sleep_event(Ok(event)).await;
+			}
+			C2SMsg::SessionReady(payload) => {
+				let Some(connected_client) = self.connected_clients.get(&client_id) else {
+					tracing::warn!("tried handling message from a non-existing client");
+					return;
+				};
+				let requester_session_id = connected_client.client_view.authenticated_session();
+				let Some(requester_session_id) = requester_session_id else {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(Error::Forbidden(None), false)
+							.await;
+					}
+					return;
+				};
+				if payload.session_id != requester_session_id.to_string() {
+					if let Some(client) = self.connected_clients.get_mut(&client_id) {
+						client
+							.client_view
+							.notify_error(
+								Error::SessionIdMismatch(
+									"session_ready session_id does not match authenticated session",
+								),
+								false,
+							)
+							.await;
+					}
+					return;
+				}
+				let Some(existing) = self.active_sessions.get(&requester_session_id).cloned() else {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 					}
 					return;
@@ -711,10 +2336,7 @@ impl ShiftServer {
 						client
 							.client_view
 							.notify_error(
-								"invalid_transition".into(),
-								Some(Arc::<str>::from(
-									"admin session does not use loading/ready lifecycle",
-								)),
+								Error::InvalidTransition("admin session does not use loading/ready lifecycle"),
 								false,
 							)
 							.await;
@@ -730,15 +2352,23 @@ impl ShiftServer {
 					.active_sessions
 					.insert(requester_session_id, Arc::clone(&ready_session));
 				self.loading_sessions.remove(&requester_session_id);
-				self.notify_admins_session_state(&ready_session).await;
-				self
-					.set_awake_sessions(self.current_session.into_iter())
-					.await;
+				self.notify_session_state_watchers(&ready_session).await;
+				if let Some(monitor_id) = ready_session.preferred_monitor() {
+					self
+						.assign_preferred_monitor(monitor_id, requester_session_id)
+						.await;
+				} else {
+					self
+						.set_awake_sessions(self.current_session.into_iter())
+						.await;
+				}
 			}
 			C2SMsg::BufferRequest {
 				monitor_id,
 				buffer,
+				generation,
 				acquire_fence,
+				present_group,
 			} => {
 				let Some(connected_client) = self.connected_clients.get(&client_id) else {
 					tracing::warn!("tried handling message from a non-existing client");
@@ -753,7 +2383,7 @@ impl ShiftServer {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 					}
 					return;
@@ -762,95 +2392,33 @@ impl ShiftServer {
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
 						client
 							.client_view
-							.notify_error(
-								"session_sleeping".into(),
-								Some("session is not awake".into()),
-								false,
-							)
-							.await;
-					}
-					return;
-				}
-				let owner_key = (client_session.id(), monitor_id, buffer);
-				let current_owner = self
-					.buffer_ownership
-					.get(&owner_key)
-					.copied()
-					.unwrap_or(BufferOwner::Client);
-				if current_owner != BufferOwner::Client {
-					let other_buffer = if buffer == tab_protocol::BufferIndex::Zero {
-						tab_protocol::BufferIndex::One
-					} else {
-						tab_protocol::BufferIndex::Zero
-					};
-					let other_owner = self
-						.buffer_ownership
-						.get(&(client_session.id(), monitor_id, other_buffer))
-						.copied()
-						.unwrap_or(BufferOwner::Client);
-					tracing::warn!(
-						session_id = %client_session.id(),
-						%monitor_id,
-						requested = buffer as u8,
-						requested_owner = ?current_owner,
-						other = other_buffer as u8,
-						other_owner = ?other_owner,
-						"incoming buffer request for non client-owned buffer"
-					);
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client
-							.client_view
-							.notify_error(
-								"ownership_violation".into(),
-								Some("requested buffer is not client-owned".into()),
-								false,
-							)
-							.await;
-					}
-					return;
-				}
-				if self.pending_buffer_requests.iter().any(|pending| {
-					pending.session_id == client_session.id() && pending.monitor_id == monitor_id
-				}) {
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client
-							.client_view
-							.notify_error(
-								"buffer_request_inflight".into(),
-								Some("monitor already has an in-flight buffer request".into()),
-								false,
-							)
+							.notify_error(Error::SessionSleeping, false)
 							.await;
 					}
 					return;
 				}
-				if let Err(e) = self
-					.render_commands
-					.send(RenderCmd::SwapBuffers {
-						monitor_id,
-						buffer,
-						session_id: client_session.id(),
-						acquire_fence,
-					})
-					.await
+				if let Some(idle) = self
+					.watchdog
+					.on_submission(client_session.id(), Instant::now().into_std())
 				{
-					tracing::error!("failed to forward SwapBuffers to renderer: {e}");
-					let code = Arc::<str>::from("render_unavailable");
-					let detail = Some(Arc::<str>::from("renderer unavailable"));
-					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client.client_view.notify_error(code, detail, true).await;
-					}
-				} else {
-					self.pending_buffer_requests.push(PendingBufferRequest {
-						client_id,
-						session_id: client_session.id(),
-						monitor_id,
-						buffer,
-					});
+					self
+						.set_session_unresponsive(client_session.id(), false, idle)
+						.await;
 				}
+				let effects = self.core.on_buffer_request(
+					client_id,
+					client_session.id(),
+					monitor_id,
+					buffer,
+					generation,
+					acquire_fence,
+					present_group,
+				);
+				self.apply_effects(Some(client_id), effects).await;
 			}
 			C2SMsg::FramebufferLink { payload, dma_bufs } => {
 				let monitor_id_raw = payload.monitor_id.clone();
+				let generation = payload.generation;
 				let session_id = {
 					let Some(client) = self.connected_clients.get_mut(&client_id) else {
 						tracing::warn!("tried handling message from a non-existing client");
@@ -859,15 +2427,14 @@ impl ShiftServer {
 					let Some(session_id) = client.client_view.authenticated_session() else {
 						client
 							.client_view
-							.notify_error("forbidden".into(), None, false)
+							.notify_error(Error::Forbidden(None), false)
 							.await;
 						return;
 					};
 					session_id
 				};
 				if let Err(e) = self
-					.render_commands
-					.send(RenderCmd::FramebufferLink {
+					.send_render_cmd(RenderCmd::FramebufferLink {
 						payload,
 						dma_bufs,
 						session_id,
@@ -875,186 +2442,544 @@ impl ShiftServer {
 					.await
 				{
 					tracing::error!("failed to forward FramebufferLink to renderer: {e}");
-					let code = Arc::<str>::from("render_unavailable");
-					let detail = Some(Arc::<str>::from("renderer unavailable"));
 					if let Some(client) = self.connected_clients.get_mut(&client_id) {
-						client.client_view.notify_error(code, detail, true).await;
+						client.client_view.notify_error(e.into(), true).await;
 					}
 				} else {
 					let Ok(monitor_id) = monitor_id_raw.parse::<MonitorId>() else {
 						return;
 					};
-					self.waiting_flip.retain(|pending| {
-						!(pending.session_id == session_id && pending.monitor_id == monitor_id)
-					});
-					self.pending_buffer_requests.retain(|pending| {
-						!(pending.session_id == session_id && pending.monitor_id == monitor_id)
-					});
-					self.front_buffers.remove(&(session_id, monitor_id));
-					self.buffer_ownership.insert(
-						(session_id, monitor_id, tab_protocol::BufferIndex::Zero),
-						BufferOwner::Client,
-					);
-					self.buffer_ownership.insert(
-						(session_id, monitor_id, tab_protocol::BufferIndex::One),
-						BufferOwner::Client,
-					);
+					self
+						.core
+						.on_framebuffer_link(session_id, monitor_id, generation);
+				}
+			}
+			C2SMsg::FramesSkipped { count } => {
+				self.core.on_frames_skipped(count);
+			}
+			C2SMsg::PresentGroup(payload) => {
+				let Some(session_id) = self
+					.connected_clients
+					.get(&client_id)
+					.and_then(|client| client.client_view.authenticated_session())
+				else {
+					self
+						.notify_client_error(client_id, Error::Forbidden(None))
+						.await;
+					return;
+				};
+				let mut monitors = Vec::with_capacity(payload.monitor_ids.len());
+				for monitor_id in &payload.monitor_ids {
+					let Some(monitor_id) = monitor_id
+						.parse::<MonitorId>()
+						.ok()
+						.filter(|id| self.monitors.contains_key(id))
+					else {
+						self
+							.notify_client_error(client_id, Error::UnknownMonitor)
+							.await;
+						return;
+					};
+					if !monitors.contains(&monitor_id) {
+						monitors.push(monitor_id);
+					}
+				}
+				tracing::debug!(%session_id, group = payload.group, ?monitors, "present group defined");
+				if let Err(e) = self
+					.send_render_cmd(RenderCmd::PresentGroup {
+						session_id,
+						group: payload.group,
+						monitors,
+					})
+					.await
+				{
+					tracing::error!("failed to forward PresentGroup to renderer: {e}");
+				}
+			}
+			C2SMsg::BufferUpload {
+				monitor_id,
+				buffer,
+				width,
+				height,
+				stride,
+				y,
+				pixels,
+			} => {
+				let Some(client) = self.connected_clients.get_mut(&client_id) else {
+					tracing::warn!("tried handling message from a non-existing client");
+					return;
+				};
+				let Some(session_id) = client.client_view.authenticated_session() else {
+					client
+						.client_view
+						.notify_error(Error::Forbidden(None), false)
+						.await;
+					return;
+				};
+				if let Err(e) = self.core.check_upload(session_id, monitor_id, buffer) {
+					client.client_view.notify_error(e, false).await;
+					return;
+				}
+				let Some(monitor) = self.monitors.get(&monitor_id) else {
+					client
+						.client_view
+						.notify_error(Error::UnknownMonitor, false)
+						.await;
+					return;
+				};
+				let rows = Upload {
+					width,
+					height,
+					stride,
+					pixels,
+				};
+				let upload = match self.uploads.add(
+					(session_id, monitor_id, buffer),
+					(monitor.width, monitor.height),
+					y,
+					rows,
+				) {
+					Ok(Some(upload)) => upload,
+					Ok(None) => return,
+					Err(e) => {
+						client.client_view.notify_error(e, false).await;
+						return;
+					}
+				};
+				// Not `send_render_cmd`, `client` still borrows `self`.
+				let upload = RenderCmd::BufferUpload {
+					monitor_id,
+					buffer,
+					session_id,
+					width: upload.width,
+					height: upload.height,
+					stride: upload.stride,
+					pixels: upload.pixels,
+				};
+				if let Err(e) = self
+					.render_commands
+					.send(Correlated::new(self.corr, upload))
+					.await
+				{
+					tracing::error!("failed to forward BufferUpload to renderer: {e}");
+					client.client_view.notify_error(e.into(), true).await;
+				}
+			}
+		}
+	}
+	/// How full the queues between layers are, for `stats`.
+	fn queue_stats(&self) -> Vec<QueueStats> {
+		let queue = |name: &str, depth: usize, capacity: usize| QueueStats {
+			name: name.into(),
+			depth: depth as u32,
+			capacity: capacity as u32,
+			..Default::default()
+		};
+		let mut to_server = queue("client_to_server", 0, self.client_queue_capacity);
+		let mut to_client = queue("server_to_client", 0, self.client_queue_capacity);
+		let mut status = queue("client_status", 0, 0);
+		for client in self.connected_clients.values() {
+			let queues = client.client_view.queues();
+			to_server.depth = to_server.depth.max(queues.to_server as u32);
+			to_client.depth = to_client.depth.max(queues.to_client as u32);
+			to_client.blocked += queues.to_client_blocked;
+			status.depth = status.depth.max(queues.status as u32);
+			status.capacity = queues.status_capacity as u32;
+			status.dropped += queues.status_dropped;
+		}
+		vec![
+			QueueStats {
+				blocked: self.render_blocked.load(Ordering::Relaxed),
+				..queue(
+					"render_commands",
+					queues::depth(&self.render_commands),
+					self.render_commands.max_capacity(),
+				)
+			},
+			queue(
+				"render_events",
+				self.render_events.len(),
+				self.render_events.max_capacity(),
+			),
+			queue(
+				"input_events",
+				self.input_events.len(),
+				self.input_events.max_capacity(),
+			),
+			queue(
+				"input_commands",
+				queues::depth(&self.input_commands),
+				self.input_commands.max_capacity(),
+			),
+			to_server,
+			to_client,
+			status,
+		]
+	}
+
+	/// Handles one render loop iteration's events. The effects of buffer events are applied
+	/// together, so a session's releases of the batch go out as one `buffer_release`.
+	async fn handle_render_events(&mut self, events: Vec<Correlated<RenderEvt>>) {
+		let mut effects = Vec::new();
+		for Correlated { corr, msg: event } in events {
+			let span = tracing::info_span!("render_event", corr = correlation::span_field(corr));
+			match span.in_scope(|| self.core.on_render_event(event)) {
+				ControlFlow::Break(more) => effects.extend(more),
+				// Nothing to do for flips, and they end every frame's batch.
+				ControlFlow::Continue(RenderEvt::PageFlip { .. }) => {}
+				ControlFlow::Continue(event) => {
+					// Keep the order clients see things in, e.g. acks before `monitor_removed`.
+					self.apply_effects(None, std::mem::take(&mut effects)).await;
+					self.corr = corr;
+					self.handle_render_event(event).instrument(span).await;
+					self.corr = None;
 				}
 			}
 		}
+		self.apply_effects(None, effects).await;
 	}
+
 	async fn handle_render_event(&mut self, event: RenderEvt) {
 		match event {
 			RenderEvt::Started { monitors } => {
+				for monitor in &monitors {
+					self.focus.add_monitor(monitor);
+				}
 				self.monitors = monitors.into_iter().map(|m| (m.id, m)).collect();
+				self.refresh_focus().await;
 			}
 			RenderEvt::MonitorOnline { monitor } => {
 				tracing::info!(?monitor, "renderer reports monitor online");
 				self.broadcast_monitor_added(&monitor).await;
+				self.focus.add_monitor(&monitor);
+				self.monitors.insert(monitor.id, monitor);
+				self.refresh_focus().await;
+			}
+			RenderEvt::MonitorChanged { monitor } => {
+				tracing::info!(?monitor, "renderer reports monitor mode change");
+				self.broadcast_monitor_changed(&monitor).await;
+				self.focus.update_monitor(&monitor);
 				self.monitors.insert(monitor.id, monitor);
+				self.refresh_focus().await;
 			}
 			RenderEvt::MonitorOffline { monitor_id } => {
 				tracing::info!(%monitor_id, "renderer reports monitor offline");
 				if let Some(monitor) = self.monitors.remove(&monitor_id) {
 					self.broadcast_monitor_removed(&monitor).await;
 				}
+				self.focus.remove_monitor(monitor_id);
+				let had_layout = self.monitor_layouts.remove(&monitor_id).is_some();
+				let had_assignment = self.monitor_sessions.remove(&monitor_id).is_some();
+				// The renderer ends mirrors of a monitor it lost by itself.
 				self
-					.waiting_flip
-					.retain(|pending| pending.monitor_id != monitor_id);
-				self
-					.pending_buffer_requests
-					.retain(|pending| pending.monitor_id != monitor_id);
-				self.front_buffers.retain(|(_, mon), _| *mon != monitor_id);
-				self
-					.buffer_ownership
-					.retain(|(_, mon, _), _| *mon != monitor_id);
+					.monitor_mirrors
+					.retain(|target, source| *target != monitor_id && *source != monitor_id);
+				self.zoom_follow.remove(&monitor_id);
+				if self.pip_sessions.remove(&monitor_id).is_some() || had_layout || had_assignment {
+					self
+						.set_awake_sessions(self.current_session.into_iter())
+						.await;
+				}
+				self.core.forget_monitor(monitor_id);
+				let failed_screenshots = self
+					.pending_screenshots
+					.extract_if(|_, (_, mon)| *mon == monitor_id)
+					.map(|(_, (client_id, _))| client_id)
+					.collect::<Vec<_>>();
+				for client_id in failed_screenshots {
+					self
+						.notify_client_error(client_id, Error::UnknownMonitor)
+						.await;
+				}
+				self.refresh_focus().await;
 			}
-			RenderEvt::BufferRequestAck {
+			RenderEvt::FramebufferLinkFailed {
 				session_id,
 				monitor_id,
-				buffer,
+				reason,
+				supported_formats,
 			} => {
-				let Some(pos) = self.pending_buffer_requests.iter().position(|pending| {
-					pending.session_id == session_id
-						&& pending.monitor_id == monitor_id
-						&& pending.buffer == buffer
-				}) else {
-					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, "renderer acked unknown pending request");
+				tracing::warn!(%session_id, %monitor_id, %reason, "framebuffer link failed");
+				let Some(client) = self
+					.connected_clients
+					.values_mut()
+					.find(|c| c.client_view.authenticated_session() == Some(session_id))
+				else {
 					return;
 				};
-				let pending = self.pending_buffer_requests.remove(pos);
-				self
-					.buffer_ownership
-					.insert((session_id, monitor_id, buffer), BufferOwner::Shift);
-				self.swap_buffers_received = self.swap_buffers_received.saturating_add(1);
-
-				let mut should_disconnect = false;
-				if let Some(client) = self.connected_clients.get_mut(&pending.client_id) {
-					if !client
-						.client_view
-						.notify_buffer_request_ack(monitor_id, buffer)
-						.await
-					{
-						should_disconnect = true;
-					}
+				if !client
+					.client_view
+					.notify_framebuffer_link_failed(monitor_id, reason, supported_formats)
+					.await
+				{
+					tracing::warn!(%session_id, %monitor_id, "failed to send framebuffer_link_failed");
 				}
-				if should_disconnect {
-					self.disconnect_client(pending.client_id).await;
+			}
+			RenderEvt::TransitionsChanged { transitions } => {
+				self.transitions = transitions
+					.into_iter()
+					.map(|info| (info.name.clone(), info))
+					.collect();
+			}
+			RenderEvt::RelinkRequested {
+				session_id,
+				monitor_ids,
+			} => {
+				let Some(client) = self
+					.connected_clients
+					.values_mut()
+					.find(|c| c.client_view.authenticated_session() == Some(session_id))
+				else {
+					return;
+				};
+				for monitor_id in monitor_ids {
+					if !client.client_view.notify_relink_request(monitor_id).await {
+						tracing::warn!(%session_id, %monitor_id, "failed to send relink_request");
+					}
 				}
 			}
-			RenderEvt::BufferRequestRejected {
+			RenderEvt::BuffersInvalidated {
 				session_id,
-				monitor_id,
-				buffer,
-				reason,
+				monitor_ids,
 			} => {
-				let Some(pos) = self.pending_buffer_requests.iter().position(|pending| {
-					pending.session_id == session_id
-						&& pending.monitor_id == monitor_id
-						&& pending.buffer == buffer
-				}) else {
-					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, %reason, "renderer rejected unknown pending request");
+				let Some(client) = self
+					.connected_clients
+					.values_mut()
+					.find(|c| c.client_view.authenticated_session() == Some(session_id))
+				else {
 					return;
 				};
-				let pending = self.pending_buffer_requests.remove(pos);
-				if let Some(client) = self.connected_clients.get_mut(&pending.client_id) {
-					client
-						.client_view
-						.notify_error("buffer_request_rejected".into(), Some(reason), false)
-						.await;
+				if !client
+					.client_view
+					.notify_buffers_invalidated(monitor_ids)
+					.await
+				{
+					tracing::warn!(%session_id, "failed to send buffers_invalidated");
 				}
 			}
-			RenderEvt::BufferConsumed {
+			RenderEvt::StuckFrames {
 				session_id,
 				monitor_id,
-				buffer,
-				release_fence,
+				duration,
+				frames,
 			} => {
-				self
-					.buffer_ownership
-					.insert((session_id, monitor_id, buffer), BufferOwner::Client);
-				let Some((_id, client)) = self
+				tracing::warn!(
+					%session_id,
+					%monitor_id,
+					duration_ms = duration.as_millis() as u64,
+					frames,
+					"session keeps submitting frames that don't change"
+				);
+				let Some(client) = self
 					.connected_clients
-					.iter_mut()
-					.find(|(_, c)| c.client_view.authenticated_session() == Some(session_id))
+					.values_mut()
+					.find(|c| c.client_view.authenticated_session() == Some(session_id))
 				else {
 					return;
 				};
 				if !client
 					.client_view
-					.notify_buffer_release(vec![BufferRelease {
+					.notify_performance_warning(
 						monitor_id,
-						buffer,
-						release_fence,
-					}])
+						PerformanceWarningKind::StaticContent,
+						duration,
+						frames,
+					)
 					.await
 				{
-					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, "failed to send early buffer_release");
-				} else {
-					self.frame_done_emitted = self.frame_done_emitted.saturating_add(1);
+					tracing::warn!(%session_id, %monitor_id, "failed to send performance_warning");
 				}
 			}
-			RenderEvt::FatalError { reason } => {
-				tracing::error!(?reason, "renderer fatal error");
+			// Answered by the core, or skipped, in `handle_render_events`.
+			RenderEvt::BufferRequestAck { .. }
+			| RenderEvt::BufferRequestRejected { .. }
+			| RenderEvt::BufferConsumed { .. }
+			| RenderEvt::FrameDropped { .. }
+			| RenderEvt::PageFlip { .. } => {}
+			RenderEvt::FatalError { error } => {
+				tracing::error!(kind = ?error.kind(), %error, "renderer fatal error");
 				// TODO: Shutdown server
 			}
-			RenderEvt::PageFlip { monitors } => {
-				let _ = monitors;
+			RenderEvt::Screenshot { request, result } => {
+				let Some((client_id, _)) = self.pending_screenshots.remove(&request) else {
+					return;
+				};
+				let Some(client) = self.connected_clients.get_mut(&client_id) else {
+					return;
+				};
+				match result {
+					Ok(screenshot) => {
+						if !client.client_view.notify_screenshot(screenshot).await {
+							tracing::warn!(%client_id, "failed to send screenshot");
+						}
+					}
+					Err(reason) => {
+						client
+							.client_view
+							.notify_error(Error::ScreenshotFailed(reason), false)
+							.await;
+					}
+				}
+			}
+			RenderEvt::SplashEnded => {
+				tracing::info!("boot splash ended");
+				self.splash_ended = true;
+				for id in self.client_ids_with_role(Role::is_admin) {
+					let Some(client) = self.connected_clients.get_mut(&id) else {
+						continue;
+					};
+					if !client.client_view.notify_splash_ended().await {
+						tracing::warn!(%id, "failed to notify splash ended");
+					}
+				}
+			}
+			RenderEvt::SessionShown { session_id } => {
+				// Already switched away again.
+				if self.current_session != Some(session_id) {
+					return;
+				}
+				self.set_shown_session(session_id).await;
+			}
+			RenderEvt::Resumed => {
+				self.watchdog.restart(Instant::now().into_std());
+				self
+					.broadcast(TabMessageFrame::no_payload(message_header::RESUMED))
+					.await;
+			}
+			RenderEvt::Health(health) => {
+				if self.health_subscribers.is_empty() {
+					return;
+				}
+				let payload = CompositorHealthPayload {
+					render_loops_per_sec: health.loops_per_sec,
+					fence_wait_backlog: health.fence_wait_backlog as u32,
+					monitors: health
+						.last_flip_ages
+						.into_iter()
+						.map(|(monitor_id, age)| MonitorHealth {
+							monitor_id: monitor_id.to_string(),
+							last_flip_age_ms: age.map(|age| age.as_millis() as u64),
+						})
+						.collect(),
+					gpu_memory: health.gpu_memory,
+				};
+				for id in self.health_subscribers.clone() {
+					let Some(client) = self.connected_clients.get_mut(&id) else {
+						continue;
+					};
+					if !client
+						.client_view
+						.notify_compositor_health(payload.clone())
+						.await
+					{
+						tracing::warn!(%id, "failed to send compositor health");
+					}
+				}
 			}
 		}
 	}
 
 	async fn handle_input_event(&mut self, event: InputEvt) {
 		match event {
-			InputEvt::Event(input_event) => {
-				let Some(active_session_id) = self.current_session else {
+			InputEvt::Event(mut input_event) => {
+				if let InputEventPayload::SwitchToggle { switch, state, .. } = &input_event
+					&& let Some(change) = self.switches.toggle(switch, state)
+				{
+					self.handle_switch_change(change).await;
+				}
+				if let Some(session_id) = self.device_grabs.target_for(&input_event) {
+					// A grabbed device doesn't move the pointer, its events skip focus entirely.
+					self.focus.localize(&mut input_event);
+					self.flush_pending_input_motion().await;
+					self
+						.forward_input_event_to_session(session_id, input_event)
+						.await;
+					return;
+				}
+				if self.focus.apply_motion(&input_event) {
+					self.refresh_focus().await;
+					self.follow_pointer_zoom().await;
+				}
+				self.focus.localize(&mut input_event);
+				let Some(target_session_id) = self.focus.target_for(&input_event) else {
 					return;
 				};
+				if let InputEventPayload::Key {
+					state: KeyState::Pressed,
+					repeat,
+					..
+				} = &input_event
+				{
+					if !repeat {
+						self.key_repeat_target = Some(target_session_id);
+					} else if self.key_repeat_target != Some(target_session_id) {
+						return;
+					}
+				}
+				if self.focus.is_pointer_locked() {
+					match input_event {
+						InputEventPayload::PointerMotionAbsolute { .. } => return,
+						InputEventPayload::PointerMotion { .. } => {
+							self.flush_pending_input_motion().await;
+							self
+								.forward_input_event_to_session(target_session_id, input_event)
+								.await;
+							return;
+						}
+						_ => {}
+					}
+				}
 				if Self::is_coalescable_motion(&input_event) {
 					match self.pending_input_motion.as_ref() {
 						Some((pending_session, pending_event))
-							if *pending_session == active_session_id
+							if *pending_session == target_session_id
 								&& Self::same_motion_kind(pending_event, &input_event) =>
 						{
-							self.pending_input_motion = Some((active_session_id, input_event));
+							self.pending_input_motion = Some((target_session_id, input_event));
 						}
 						Some(_) => {
 							self.flush_pending_input_motion().await;
-							self.pending_input_motion = Some((active_session_id, input_event));
+							self.pending_input_motion = Some((target_session_id, input_event));
 						}
 						None => {
-							self.pending_input_motion = Some((active_session_id, input_event));
+							self.pending_input_motion = Some((target_session_id, input_event));
 						}
 					}
 				} else {
 					self.flush_pending_input_motion().await;
 					self
-						.forward_input_event_to_session(active_session_id, input_event)
+						.forward_input_event_to_session(target_session_id, input_event)
 						.await;
 				}
 			}
-			InputEvt::FatalError { reason } => {
-				tracing::error!(%reason, "input layer fatal error");
+			InputEvt::ShortcutTriggered { id } => {
+				let Some((owner, _)) = self.shortcuts.get(&id) else {
+					return;
+				};
+				let owner = *owner;
+				let Some(client) = self
+					.connected_clients
+					.values_mut()
+					.find(|c| c.client_view.authenticated_session() == Some(owner))
+				else {
+					return;
+				};
+				if !client.client_view.notify_shortcut_triggered(id).await {
+					tracing::warn!(session_id = %owner, "failed to send shortcut trigger");
+				}
+			}
+			InputEvt::DeviceConfigs { request, devices } => {
+				let Some(client_id) = self.pending_device_configs.remove(&request) else {
+					return;
+				};
+				if let Some(client) = self.connected_clients.get_mut(&client_id)
+					&& !client.client_view.notify_device_configs(devices).await
+				{
+					tracing::warn!(%client_id, "failed to send device configs");
+				}
+			}
+			InputEvt::FatalError { error } => {
+				tracing::error!(kind = ?error.kind(), %error, "input layer fatal error");
 			}
 		}
 	}
@@ -1083,21 +3008,240 @@ impl ShiftServer {
 		let Some((session_id, event)) = self.pending_input_motion.take() else {
 			return;
 		};
-		if self.current_session != Some(session_id) {
+		if self.focus.pointer_session() != Some(session_id) {
 			return;
 		}
-		if self.has_inflight_buffer_request_for_session(session_id) {
+		if self.core.has_inflight_request(session_id) {
 			self.pending_input_motion = Some((session_id, event));
 			return;
 		}
 		self.forward_input_event_to_session(session_id, event).await;
 	}
 
-	fn has_inflight_buffer_request_for_session(&self, session_id: SessionId) -> bool {
+	/// Session drawn at a monitor-local position, topmost first: PiP overlay, layout region, then the
+	/// monitor's pinned or active session.
+	fn session_at(&self, monitor_id: MonitorId, x: f64, y: f64) -> Option<SessionId> {
+		let contains = |rect: &tab_protocol::Rect| {
+			x >= rect.x as f64
+				&& y >= rect.y as f64
+				&& x < (rect.x + rect.width) as f64
+				&& y < (rect.y + rect.height) as f64
+		};
+		if let Some(pip) = self.pip_sessions.get(&monitor_id)
+			&& contains(&pip.rect)
+		{
+			return Some(pip.session_id);
+		}
 		self
-			.pending_buffer_requests
-			.iter()
-			.any(|pending| pending.session_id == session_id)
+			.monitor_layouts
+			.get(&monitor_id)
+			.and_then(|regions| {
+				regions
+					.iter()
+					.rev()
+					.find(|region| contains(&region.rect))
+					.map(|region| region.session_id)
+			})
+			.or_else(|| self.monitor_sessions.get(&monitor_id).copied())
+			.or(self.current_session)
+	}
+
+	async fn refresh_focus(&mut self) {
+		let pointer_session = self
+			.focus
+			.pointer_location()
+			.and_then(|(monitor_id, x, y)| self.session_at(monitor_id, x, y))
+			.or(self.current_session)
+			.filter(|id| self.active_sessions.contains_key(id));
+		let active_session = self
+			.current_session
+			.filter(|id| self.active_sessions.contains_key(id));
+		let changes = self.focus.update(pointer_session, active_session);
+		if self.pointer_lock.is_some() && self.pointer_lock != self.focus.pointer_session() {
+			self.set_pointer_lock(None).await;
+		}
+		for change in changes {
+			let Some(client) = self
+				.connected_clients
+				.values_mut()
+				.find(|c| c.client_view.authenticated_session() == Some(change.session_id))
+			else {
+				continue;
+			};
+			if !client
+				.client_view
+				.notify_focus_change(change.session_id, change.target, change.focused)
+				.await
+			{
+				tracing::warn!(session_id = %change.session_id, "failed to send focus change");
+			}
+		}
+	}
+
+	async fn sync_shortcuts(&mut self) {
+		let shortcuts = self
+			.shortcuts
+			.values()
+			.map(|(_, shortcut)| shortcut.clone())
+			.collect();
+		if let Err(e) = self
+			.input_commands
+			.send(InputCmd::SetShortcuts(shortcuts))
+			.await
+		{
+			tracing::error!("failed to update input layer shortcuts: {e}");
+		}
+	}
+
+	/// Sends a log dump split into frames that fit, see [`LogRecordsPayload::split`].
+	async fn send_log_records(&mut self, client_id: ClientId, records: Vec<String>) {
+		let Some(client) = self.connected_clients.get_mut(&client_id) else {
+			return;
+		};
+		for part in LogRecordsPayload::split(records) {
+			if !client
+				.client_view
+				.notify_log_records(part.records, part.more)
+				.await
+			{
+				tracing::warn!(%client_id, "failed to send log records");
+				return;
+			}
+		}
+	}
+
+	async fn set_background(&mut self, background: Background) {
+		self.background = background.clone();
+		if let Err(e) = self
+			.send_render_cmd(RenderCmd::SetBackground(background))
+			.await
+		{
+			tracing::error!("failed to forward SetBackground to renderer: {e}");
+		}
+	}
+
+	/// Shows `session_id` on `monitor_id` instead of the active session, or goes back to the
+	/// active session for `None`.
+	async fn assign_monitor(&mut self, monitor_id: MonitorId, session_id: Option<SessionId>) {
+		match session_id {
+			Some(session_id) => self.monitor_sessions.insert(monitor_id, session_id),
+			None => self.monitor_sessions.remove(&monitor_id),
+		};
+		self.state_changed();
+		self
+			.set_awake_sessions(self.current_session.into_iter())
+			.await;
+		self.refresh_focus().await;
+		if let Err(e) = self
+			.send_render_cmd(RenderCmd::AssignMonitor {
+				monitor_id,
+				session_id,
+			})
+			.await
+		{
+			tracing::error!("failed to forward AssignMonitor to renderer: {e}");
+		}
+	}
+
+	/// Moves the magnifier of the monitor under the pointer along with it, see `zoom_set`.
+	async fn follow_pointer_zoom(&mut self) {
+		let Some((monitor_id, x, y)) = self.focus.pointer_location() else {
+			return;
+		};
+		if !self.zoom_follow.contains(&monitor_id) {
+			return;
+		}
+		let center = [x as f32, y as f32];
+		if let Err(e) = self
+			.send_render_cmd(RenderCmd::ZoomFocus { monitor_id, center })
+			.await
+		{
+			tracing::error!("failed to forward ZoomFocus to renderer: {e}");
+		}
+	}
+
+	/// Shows `source`'s output on `monitor_id`, or its own again for `None`.
+	async fn set_mirror(&mut self, monitor_id: MonitorId, source: Option<MonitorId>) {
+		match source {
+			Some(source) => self.monitor_mirrors.insert(monitor_id, source),
+			None => self.monitor_mirrors.remove(&monitor_id),
+		};
+		tracing::info!(%monitor_id, source = ?source, "monitor mirror changed");
+		if let Err(e) = self
+			.send_render_cmd(RenderCmd::SetMirror {
+				monitor_id,
+				source_monitor_id: source,
+			})
+			.await
+		{
+			tracing::error!("failed to forward SetMirror to renderer: {e}");
+		}
+	}
+
+	/// Applies the `monitor_id` of a session's launch descriptor, unless the monitor is gone.
+	async fn assign_preferred_monitor(&mut self, monitor_id: MonitorId, session_id: SessionId) {
+		if !self.monitors.contains_key(&monitor_id) {
+			tracing::warn!(%session_id, %monitor_id, "preferred monitor is not connected, not assigning");
+			self
+				.set_awake_sessions(self.current_session.into_iter())
+				.await;
+			return;
+		}
+		tracing::info!(%session_id, %monitor_id, "assigning session to its preferred monitor");
+		self.assign_monitor(monitor_id, Some(session_id)).await;
+	}
+
+	async fn set_debug_hud(&mut self, enabled: bool) {
+		self.debug_hud = enabled;
+		if let Err(e) = self
+			.send_render_cmd(RenderCmd::SetDebugHud { enabled })
+			.await
+		{
+			tracing::error!("failed to forward SetDebugHud to renderer: {e}");
+			return;
+		}
+		if enabled {
+			self.send_hud_stats().await;
+		}
+	}
+
+	async fn send_hud_stats(&mut self) {
+		let stats = HudStats {
+			active_session: self
+				.current_session
+				.and_then(|id| self.active_sessions.get(&id))
+				.map(|session| format!("{} ({})", session.display_name(), session.id())),
+			connected_clients: self.connected_clients.len(),
+			pending_buffer_requests: self.core.pending_buffer_requests(),
+			waiting_flip: self.core.waiting_flip(),
+			swap_buffers_per_sec: self.core.rates().swap_buffers,
+			frame_done_per_sec: self.core.rates().frame_done,
+			frames_skipped_per_sec: self.core.rates().frames_skipped,
+		};
+		if let Err(e) = self.send_render_cmd(RenderCmd::HudStats(stats)).await {
+			tracing::error!("failed to forward HudStats to renderer: {e}");
+		}
+	}
+
+	/// Moves the pointer lock to `session_id`, telling the previous and new holders.
+	async fn set_pointer_lock(&mut self, session_id: Option<SessionId>) {
+		let previous = std::mem::replace(&mut self.pointer_lock, session_id);
+		self.focus.set_pointer_locked(session_id.is_some());
+		for (holder, locked) in [(previous, false), (session_id, true)] {
+			let Some(holder) = holder else {
+				continue;
+			};
+			let Some(client) = self
+				.connected_clients
+				.values_mut()
+				.find(|c| c.client_view.authenticated_session() == Some(holder))
+			else {
+				continue;
+			};
+			if !client.client_view.notify_pointer_lock(locked).await {
+				tracing::warn!(session_id = %holder, "failed to send pointer lock state");
+			}
+		}
 	}
 
 	async fn forward_input_event_to_session(
@@ -1116,9 +3260,43 @@ impl ShiftServer {
 			tracing::warn!(%session_id, "failed to send input event to active session");
 		}
 	}
+	async fn accept_remote(listener: Option<&AsyncFd<StreamListener>>) -> io::Result<AnyTransport> {
+		let Some(listener) = listener else {
+			return pending().await;
+		};
+		loop {
+			let mut guard = listener.readable().await?;
+			match guard.try_io(|listener| match listener.get_ref().accept() {
+				Err(ProtocolError::WouldBlock) => Err(io::ErrorKind::WouldBlock.into()),
+				result => Ok(result.map_err(io::Error::other)),
+			}) {
+				Ok(result) => return result?,
+				Err(_would_block) => continue,
+			}
+		}
+	}
+	async fn recv_sleep_event(signals: Option<&SleepSignals>) -> io::Result<SleepEvent> {
+		match signals {
+			Some(signals) => signals.recv().await,
+			None => pending().await,
+		}
+	}
+	async fn handle_sleep_event(&mut self, event: io::Result<SleepEvent>) {
+		let cmd = match event {
+			Ok(SleepEvent::Suspending) => RenderCmd::Suspend,
+			Ok(SleepEvent::Resumed) => RenderCmd::Resume,
+			Err(e) => {
+				tracing::warn!("failed to read suspend signals: {e}");
+				return;
+			}
+		};
+		if let Err(e) = self.send_render_cmd(cmd).await {
+			tracing::error!("failed to forward suspend state to renderer: {e}");
+		}
+	}
 	async fn read_clients_messages(
 		connected_clients: &mut HashMap<ClientId, ConnectedClient>,
-	) -> (ClientId, C2SMsg) {
+	) -> (ClientId, Correlated<C2SMsg>) {
 		connected_clients.retain(|_, c| c.client_view.has_messages());
 		let futures = connected_clients
 			.iter_mut()
@@ -1137,9 +3315,9 @@ impl ShiftServer {
 		select_all(futures).await.0
 	}
 	#[tracing::instrument(level= "info", skip(self, accept_result), fields(connected_clients=self.connected_clients.len(), active_sessions=self.active_sessions.len(), pending_sessions = self.pending_sessions.len(), current_session = ?self.current_session))]
-	async fn handle_accept(&mut self, accept_result: io::Result<(UnixStream, SocketAddr)>) {
+	async fn handle_accept(&mut self, accept_result: io::Result<AnyTransport>) {
 		match accept_result {
-			Ok((client_socket, _ip)) => {
+			Ok(client_socket) => {
 				macro_rules! or_continue {
                     ($expr:expr, $fmt:literal $(, $arg:expr)* $(,)?) => {
                         match $expr {
@@ -1152,9 +3330,10 @@ impl ShiftServer {
                     };
                 }
 
+				let socket_fd = client_socket.as_raw_fd();
 				let hellopkt = TabMessageFrame::hello("shift 0.1.0-alpha");
 				let client_async_fd = or_continue!(
-					client_socket.into_std().and_then(AsyncFd::new),
+					AsyncFd::new(client_socket),
 					"failed to accept connection: AsyncFd creation from client_socket failed: {}"
 				);
 
@@ -1162,17 +3341,25 @@ impl ShiftServer {
 					hellopkt.send_frame_to_async_fd(&client_async_fd).await,
 					"failed to send hello packet: {}"
 				);
-				let (new_client, mut new_client_view) =
-					Client::wrap_socket(client_async_fd, self.monitors.values().cloned().collect());
-				let client_id = new_client_view.id();
+				let client_id = ClientId::unused(|id| self.connected_clients.contains_key(&id));
+				let (new_client, mut new_client_view) = Client::wrap_socket(
+					client_id,
+					client_async_fd,
+					self.monitors.values().cloned().collect(),
+					self.client_queue_capacity,
+					self.key_repeat,
+					self.handshake_timeouts,
+				);
 
 				self.connected_clients.insert(
 					new_client_view.id(),
 					ConnectedClient {
 						client_view: new_client_view,
 						join_handle: new_client.spawn().await,
+						socket_fd: Some(socket_fd),
 					},
 				);
+				self.update_crash_state();
 				tracing::info!(%client_id, "client successfully connected");
 			}
 			Err(e) => {
@@ -1181,28 +3368,83 @@ impl ShiftServer {
 		}
 	}
 
-	async fn broadcast_monitor_added(&mut self, monitor: &crate::monitor::Monitor) {
+	/// Sends a frame to every client, encoding it only once.
+	async fn broadcast(&mut self, frame: TabMessageFrame) {
+		let header = frame.header.0.clone();
+		let frame = match SharedFrame::new(frame) {
+			Ok(frame) => frame,
+			Err(e) => {
+				tracing::error!(%header, "failed to encode broadcast: {e}");
+				return;
+			}
+		};
 		for (id, client) in self.connected_clients.iter_mut() {
-			if !client
-				.client_view
-				.notify_monitor_added(monitor.clone())
-				.await
-			{
-				tracing::warn!(%id, "failed to notify monitor added");
+			if !client.client_view.notify_broadcast(frame.clone()).await {
+				tracing::warn!(%id, %header, "failed to broadcast");
 			}
 		}
 	}
 
+	async fn broadcast_monitor_added(&mut self, monitor: &crate::monitor::Monitor) {
+		let payload = MonitorAddedPayload {
+			monitor: monitor.to_protocol_info(),
+			epoch: Some(self.state_changed()),
+		};
+		self
+			.broadcast(TabMessageFrame::json(
+				message_header::MONITOR_ADDED,
+				payload,
+			))
+			.await;
+	}
+
+	async fn broadcast_monitor_changed(&mut self, monitor: &crate::monitor::Monitor) {
+		let payload = MonitorChangedPayload {
+			monitor: monitor.to_protocol_info(),
+			epoch: Some(self.state_changed()),
+		};
+		self
+			.broadcast(TabMessageFrame::json(
+				message_header::MONITOR_CHANGED,
+				payload,
+			))
+			.await;
+	}
+
 	async fn broadcast_monitor_removed(&mut self, monitor: &crate::monitor::Monitor) {
-		let name: Arc<str> = monitor.name.clone().into();
-		for (id, client) in self.connected_clients.iter_mut() {
-			if !client
-				.client_view
-				.notify_monitor_removed(monitor.id, Arc::clone(&name))
-				.await
-			{
-				tracing::warn!(%id, "failed to notify monitor removed");
-			}
+		let payload = MonitorRemovedPayload {
+			monitor_id: monitor.id.to_string(),
+			name: monitor.name.clone(),
+			epoch: Some(self.state_changed()),
+		};
+		self
+			.broadcast(TabMessageFrame::json(
+				message_header::MONITOR_REMOVED,
+				payload,
+			))
+			.await;
+	}
+
+	async fn handle_switch_change(&mut self, change: SwitchChange) {
+		tracing::info!(?change, "switch toggled");
+		if let SwitchChange::Lid { .. } = change {
+			self.switches.apply_lid_close_action(self.monitors.values());
+		}
+		for id in self.switch_subscribers.clone() {
+			self.notify_switch_change(id, change).await;
+		}
+	}
+
+	async fn notify_switch_change(&mut self, client_id: ClientId, change: SwitchChange) {
+		let Some(client) = self.connected_clients.get_mut(&client_id) else {
+			return;
+		};
+		let sent = match change {
+			SwitchChange::Lid { closed } => client.client_view.notify_lid_closed(closed).await,
+			SwitchChange::TabletMode { enabled } => client.client_view.notify_tablet_mode(enabled).await,
+		};
+		if !sent {
+			tracing::warn!(%client_id, "failed to send switch state");
 		}
 	}
 
@@ -1210,32 +3452,127 @@ impl ShiftServer {
 		let Some(client) = self.connected_clients.remove(&client_id) else {
 			return;
 		};
+		self.update_crash_state();
+		self.health_subscribers.remove(&client_id);
+		self.switch_subscribers.remove(&client_id);
+		self
+			.pending_screenshots
+			.retain(|_, (requester, _)| *requester != client_id);
+		self
+			.pending_device_configs
+			.retain(|_, requester| *requester != client_id);
 		if let Some(session_id) = client.client_view.authenticated_session() {
-			self.active_sessions.remove(&session_id);
+			self.state_changed();
+			// Sessions can't quit cleanly, so any that goes away crashed as far as shift can tell.
+			let session = self.active_sessions.remove(&session_id);
+			let crashed = session
+				.as_ref()
+				.is_some_and(|session| session.role() == Role::Normal);
+			let grace = if crashed {
+				self.crash_grace
+			} else {
+				Duration::ZERO
+			};
 			self.loading_sessions.remove(&session_id);
 			self.awake_sessions.remove(&session_id);
 			self.awake_until.remove(&session_id);
+			let was_visible = self.visibility.remove(&session_id) == Some(true);
+			if let Some(session) = session.filter(|_| was_visible) {
+				self.visibility_hooks.notify(VisibilityChange {
+					session_id,
+					display_name: session.display_name().to_string(),
+					visible: false,
+				});
+			}
+			self.watchdog.forget_session(session_id);
+			self.uploads.forget_session(session_id);
+			if self.shown_session == Some(session_id) {
+				self.shown_session = None;
+			}
 			self
-				.pending_buffer_requests
-				.retain(|pending| pending.client_id != client_id && pending.session_id != session_id);
-			self
-				.waiting_flip
-				.retain(|pending| pending.session_id != session_id);
-			self
-				.front_buffers
-				.retain(|(sess, _), _| *sess != session_id);
+				.pip_sessions
+				.retain(|_, pip| pip.session_id != session_id);
+			self.monitor_sessions.retain(|_, id| *id != session_id);
+			for regions in self.monitor_layouts.values_mut() {
+				regions.retain(|region| region.session_id != session_id);
+			}
 			self
-				.buffer_ownership
-				.retain(|(sess, _, _), _| *sess != session_id);
+				.monitor_layouts
+				.retain(|_, regions| !regions.is_empty());
+			self.core.forget_session(client_id, session_id);
 			if let Err(e) = self
-				.render_commands
-				.send(RenderCmd::SessionRemoved { session_id })
+				.send_render_cmd(RenderCmd::SessionRemoved { session_id, grace })
 				.await
 			{
 				tracing::error!("failed to notify renderer about session removal: {e}");
 			}
+			if crashed {
+				tracing::warn!(%session_id, grace_ms = grace.as_millis() as u64, "session crashed");
+				for id in self.client_ids_with_role(Role::is_admin) {
+					let Some(client) = self.connected_clients.get_mut(&id) else {
+						continue;
+					};
+					if !client
+						.client_view
+						.notify_session_crashed(session_id, grace)
+						.await
+					{
+						tracing::warn!(%id, %session_id, "failed to notify session crashed");
+					}
+				}
+			}
+			self.focus.forget_session(session_id);
+			self.selections.forget_session(session_id);
+			self.device_grabs.forget_session(session_id);
+			let shortcut_count = self.shortcuts.len();
+			self.shortcuts.retain(|_, (owner, _)| *owner != session_id);
+			if self.shortcuts.len() != shortcut_count {
+				self.sync_shortcuts().await;
+			}
+			if self.pointer_lock == Some(session_id) {
+				self.pointer_lock = None;
+				self.focus.set_pointer_locked(false);
+			}
 			if self.current_session == Some(session_id) {
 				self.update_active_session(None, None).await;
+			} else {
+				self.refresh_focus().await;
+			}
+		}
+	}
+
+	/// Tells every client that `session_id` is active and on screen, and the previously active
+	/// session that it no longer is.
+	async fn set_shown_session(&mut self, session_id: SessionId) {
+		let previous = self.shown_session.replace(session_id);
+		if previous == Some(session_id) {
+			return;
+		}
+		if let Some(previous) = previous
+			&& let Some((client_id, client)) = self
+				.connected_clients
+				.iter_mut()
+				.find(|(_, client)| client.client_view.authenticated_session() == Some(previous))
+			&& !client.client_view.notify_session_inactive(previous).await
+		{
+			tracing::warn!(%client_id, session_id = %previous, "failed to notify session inactive");
+		}
+		self.notify_session_active(session_id).await;
+	}
+
+	async fn notify_session_active(&mut self, session_id: SessionId) {
+		let epoch = self.state_changed();
+		let target_clients = self
+			.connected_clients
+			.iter()
+			.filter_map(|(id, client)| client.client_view.authenticated_session().map(|_| *id))
+			.collect::<Vec<_>>();
+		for id in target_clients {
+			if let Some(client) = self.connected_clients.get_mut(&id) {
+				client
+					.client_view
+					.notify_session_active(session_id, epoch)
+					.await;
 			}
 		}
 	}
@@ -1246,27 +3583,15 @@ impl ShiftServer {
 		transition: Option<SessionTransition>,
 	) {
 		self.pending_input_motion = None;
+		if self.pointer_lock.is_some() && self.pointer_lock != next {
+			self.set_pointer_lock(None).await;
+		}
 		self.current_session = next;
 		self.prune_expired_awake_sessions().await;
 		self.set_awake_sessions(next.into_iter()).await;
-		if let Some(active_session_id) = next {
-			let target_clients = self
-				.connected_clients
-				.iter()
-				.filter_map(|(id, client)| client.client_view.authenticated_session().map(|_| *id))
-				.collect::<Vec<_>>();
-			for id in target_clients {
-				if let Some(client) = self.connected_clients.get_mut(&id) {
-					client
-						.client_view
-						.notify_session_active(active_session_id)
-						.await;
-				}
-			}
-		}
+		// `session_active` waits for the renderer's `SessionShown`.
 		if let Err(e) = self
-			.render_commands
-			.send(RenderCmd::SetActiveSession {
+			.send_render_cmd(RenderCmd::SetActiveSession {
 				session_id: next,
 				transition,
 			})
@@ -1274,5 +3599,6 @@ impl ShiftServer {
 		{
 			tracing::error!("failed to notify renderer about active session change: {e}");
 		}
+		self.refresh_focus().await;
 	}
 }
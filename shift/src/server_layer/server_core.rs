@@ -0,0 +1,972 @@
+//! Buffer and flip bookkeeping of the server loop, without sockets or channels.
+//! - every `on_*` method takes one input and returns the [`Effect`]s the IO loop carries out
+//! - the loop keeps clients, sessions and monitors, and checks a client may present before
+//!   handing its requests here
+//! - sessions that are awake but not on screen get their buffers back as set by
+//!   `SHIFT_HIDDEN_SESSION_PACING`: `throttle` (default) once a second, `pause` not until they're
+//!   visible again, `full` right away
+//! - frames that never reached the screen are counted per session, monitor and reason, and
+//!   reported to the session once a second with `frames_dropped`
+//! - requests, acks and releases carry the generation of the link they belong to; after a relink,
+//!   late answers about the previous link's buffers are dropped instead of matching new requests
+
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	fmt::Write as _,
+	ops::ControlFlow,
+	os::fd::OwnedFd,
+};
+
+use tab_protocol::{BufferIndex, FrameDropReason};
+
+use crate::{
+	client_layer::client::ClientId,
+	comms::{render2server::RenderEvt, server2client::BufferRelease, server2render::RenderCmd},
+	error::Error,
+	monitor::MonitorId,
+	sessions::SessionId,
+};
+
+/// What the IO loop has to do after the core handled an input.
+#[derive(Debug)]
+pub enum Effect {
+	Render(RenderCmd),
+	/// Answer to a `buffer_request`. The client is disconnected if this can't be sent.
+	BufferRequestAck {
+		client_id: ClientId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		generation: u64,
+	},
+	/// Hand a buffer back to the client of `session_id`.
+	BufferRelease {
+		session_id: SessionId,
+		release: BufferRelease,
+	},
+	/// Tell the client of `session_id` that `count` of its frames never reached the screen.
+	FramesDropped {
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		count: u32,
+		reason: FrameDropReason,
+	},
+	/// A recoverable `error` for one client.
+	Error {
+		client_id: ClientId,
+		error: Error,
+	},
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingBufferRequest {
+	client_id: ClientId,
+	buffer: BufferIndex,
+	generation: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferOwner {
+	Client,
+	Shift,
+}
+
+/// How hidden sessions get their buffers back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HiddenPacing {
+	Full,
+	/// One buffer per monitor each second.
+	#[default]
+	Throttle,
+	Pause,
+}
+
+impl HiddenPacing {
+	pub fn from_env() -> Self {
+		match std::env::var("SHIFT_HIDDEN_SESSION_PACING")
+			.unwrap_or_default()
+			.trim()
+			.to_ascii_lowercase()
+			.as_str()
+		{
+			"full" => Self::Full,
+			"pause" => Self::Pause,
+			_ => Self::Throttle,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameRates {
+	pub swap_buffers: u64,
+	pub frame_done: u64,
+	pub frames_skipped: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ServerCore {
+	/// At most one request per session and monitor, grouped by session so `has_inflight_request`
+	/// doesn't scan. Sessions without requests have no entry.
+	pending_buffer_requests: HashMap<SessionId, HashMap<MonitorId, PendingBufferRequest>>,
+	waiting_flip: HashMap<(SessionId, MonitorId), BufferIndex>,
+	front_buffers: HashMap<(SessionId, MonitorId), BufferIndex>,
+	buffer_ownership: HashMap<(SessionId, MonitorId, BufferIndex), BufferOwner>,
+	/// `generation` of each monitor's latest `framebuffer_link`, 0 when absent.
+	link_generations: HashMap<(SessionId, MonitorId), u64>,
+	hidden_pacing: HiddenPacing,
+	/// Sessions that are awake but not on any monitor.
+	hidden_sessions: HashSet<SessionId>,
+	/// Consumed buffers of hidden sessions, oldest first. Shift keeps owning them until released.
+	withheld_releases: HashMap<(SessionId, MonitorId), VecDeque<BufferRelease>>,
+	/// Counters of the second in progress.
+	rates: FrameRates,
+	/// Frames dropped during the second in progress.
+	dropped_frames: HashMap<(SessionId, MonitorId, FrameDropReason), u32>,
+	#[cfg(feature = "fault-injection")]
+	faults: crate::faults::FaultInjection,
+}
+
+impl ServerCore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn set_hidden_pacing(&mut self, pacing: HiddenPacing) {
+		self.hidden_pacing = pacing;
+	}
+
+	#[cfg(feature = "fault-injection")]
+	pub fn set_faults(&mut self, faults: crate::faults::FaultInjection) {
+		self.faults = faults;
+	}
+
+	fn owner(
+		&self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+	) -> BufferOwner {
+		self
+			.buffer_ownership
+			.get(&(session_id, monitor_id, buffer))
+			.copied()
+			.unwrap_or(BufferOwner::Client)
+	}
+
+	fn link_generation(&self, session_id: SessionId, monitor_id: MonitorId) -> u64 {
+		self
+			.link_generations
+			.get(&(session_id, monitor_id))
+			.copied()
+			.unwrap_or(0)
+	}
+
+	/// A presenting session asked to show `buffer`; `session_id` is awake. A `generation` of 0
+	/// stands for the current link. The renderer holds requests of a `present_group` until the
+	/// group is complete, they stay pending meanwhile.
+	#[allow(clippy::too_many_arguments)]
+	pub fn on_buffer_request(
+		&mut self,
+		client_id: ClientId,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		generation: u64,
+		acquire_fence: Option<OwnedFd>,
+		present_group: Option<u32>,
+	) -> Vec<Effect> {
+		let current = self.link_generation(session_id, monitor_id);
+		if generation != 0 && generation != current {
+			tracing::debug!(%session_id, %monitor_id, generation, current, "buffer request for an earlier link");
+			return vec![Effect::Error {
+				client_id,
+				error: Error::BufferRequestRejected("stale_generation".into()),
+			}];
+		}
+		let generation = current;
+		let current_owner = self.owner(session_id, monitor_id, buffer);
+		if current_owner != BufferOwner::Client {
+			let other_buffer = if buffer == BufferIndex::Zero {
+				BufferIndex::One
+			} else {
+				BufferIndex::Zero
+			};
+			tracing::warn!(
+				%session_id,
+				%monitor_id,
+				requested = buffer as u8,
+				requested_owner = ?current_owner,
+				other = other_buffer as u8,
+				other_owner = ?self.owner(session_id, monitor_id, other_buffer),
+				"incoming buffer request for non client-owned buffer"
+			);
+			return vec![Effect::Error {
+				client_id,
+				error: Error::OwnershipViolation("requested buffer is not client-owned"),
+			}];
+		}
+		#[cfg(feature = "fault-injection")]
+		if self.faults.reject_buffer_request() {
+			tracing::debug!(%session_id, %monitor_id, "injecting a buffer request rejection");
+			return vec![Effect::Error {
+				client_id,
+				error: Error::BufferRequestRejected("injected_fault".into()),
+			}];
+		}
+		let pending = self.pending_buffer_requests.entry(session_id).or_default();
+		if pending.contains_key(&monitor_id) {
+			return vec![Effect::Error {
+				client_id,
+				error: Error::BufferRequestInflight,
+			}];
+		}
+		pending.insert(
+			monitor_id,
+			PendingBufferRequest {
+				client_id,
+				buffer,
+				generation,
+			},
+		);
+		vec![Effect::Render(RenderCmd::SwapBuffers {
+			monitor_id,
+			buffer,
+			session_id,
+			generation,
+			acquire_fence,
+			present_group,
+		})]
+	}
+
+	/// The session linked new buffers for `monitor_id`, both of which start out with the client.
+	pub fn on_framebuffer_link(
+		&mut self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		generation: u64,
+	) {
+		self
+			.link_generations
+			.insert((session_id, monitor_id), generation);
+		self.waiting_flip.remove(&(session_id, monitor_id));
+		self.remove_pending(session_id, monitor_id, |_| true);
+		self.front_buffers.remove(&(session_id, monitor_id));
+		self.withheld_releases.remove(&(session_id, monitor_id));
+		for buffer in [BufferIndex::Zero, BufferIndex::One] {
+			self
+				.buffer_ownership
+				.insert((session_id, monitor_id, buffer), BufferOwner::Client);
+		}
+	}
+
+	/// Whether the session may overwrite `buffer` with `buffer_upload`.
+	pub fn check_upload(
+		&self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+	) -> Result<(), Error> {
+		if self.owner(session_id, monitor_id, buffer) != BufferOwner::Client {
+			return Err(Error::OwnershipViolation(
+				"uploaded buffer is not client-owned",
+			));
+		}
+		Ok(())
+	}
+
+	pub fn on_frames_skipped(&mut self, count: u32) {
+		self.rates.frames_skipped = self.rates.frames_skipped.saturating_add(count as u64);
+	}
+
+	/// Handles the renderer's answers about buffers. Any other event is handed back with
+	/// `Continue`.
+	pub fn on_render_event(&mut self, event: RenderEvt) -> ControlFlow<Vec<Effect>, RenderEvt> {
+		match event {
+			RenderEvt::BufferRequestAck {
+				session_id,
+				monitor_id,
+				buffer,
+				generation,
+			} => {
+				let Some(pending) = self.take_pending(session_id, monitor_id, buffer, generation) else {
+					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, generation, "renderer acked unknown pending request");
+					return ControlFlow::Break(Vec::new());
+				};
+				self
+					.buffer_ownership
+					.insert((session_id, monitor_id, buffer), BufferOwner::Shift);
+				self.rates.swap_buffers = self.rates.swap_buffers.saturating_add(1);
+				if self.hidden_sessions.contains(&session_id) {
+					self.count_dropped_frame(session_id, monitor_id, FrameDropReason::Hidden);
+				}
+				ControlFlow::Break(vec![Effect::BufferRequestAck {
+					client_id: pending.client_id,
+					monitor_id,
+					buffer,
+					generation,
+				}])
+			}
+			RenderEvt::BufferRequestRejected {
+				session_id,
+				monitor_id,
+				buffer,
+				generation,
+				reason,
+			} => {
+				let Some(pending) = self.take_pending(session_id, monitor_id, buffer, generation) else {
+					tracing::warn!(%session_id, %monitor_id, buffer = buffer as u8, generation, %reason, "renderer rejected unknown pending request");
+					return ControlFlow::Break(Vec::new());
+				};
+				ControlFlow::Break(vec![Effect::Error {
+					client_id: pending.client_id,
+					error: Error::BufferRequestRejected(reason),
+				}])
+			}
+			RenderEvt::BufferConsumed {
+				session_id,
+				monitor_id,
+				buffer,
+				generation,
+				release_fence,
+			} => {
+				// The relink already handed both buffers back.
+				if generation != self.link_generation(session_id, monitor_id) {
+					tracing::debug!(%session_id, %monitor_id, buffer = buffer as u8, generation, "dropping release of an earlier link's buffer");
+					return ControlFlow::Break(Vec::new());
+				}
+				let release = BufferRelease {
+					monitor_id,
+					buffer,
+					generation,
+					release_fence,
+				};
+				if self.hidden_pacing != HiddenPacing::Full && self.hidden_sessions.contains(&session_id) {
+					self
+						.withheld_releases
+						.entry((session_id, monitor_id))
+						.or_default()
+						.push_back(release);
+					return ControlFlow::Break(Vec::new());
+				}
+				// Lost on the way, so the buffer stays with shift until the next link.
+				#[cfg(feature = "fault-injection")]
+				if self.faults.drop_release() {
+					tracing::debug!(%session_id, %monitor_id, buffer = buffer as u8, "injecting a lost buffer release");
+					return ControlFlow::Break(Vec::new());
+				}
+				ControlFlow::Break(vec![Effect::BufferRelease {
+					session_id,
+					release: self.release(session_id, release),
+				}])
+			}
+			RenderEvt::FrameDropped {
+				session_id,
+				monitor_id,
+				reason,
+			} => {
+				self.count_dropped_frame(session_id, monitor_id, reason);
+				ControlFlow::Break(Vec::new())
+			}
+			other => ControlFlow::Continue(other),
+		}
+	}
+
+	fn count_dropped_frame(
+		&mut self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		reason: FrameDropReason,
+	) {
+		let count = self
+			.dropped_frames
+			.entry((session_id, monitor_id, reason))
+			.or_default();
+		*count = count.saturating_add(1);
+	}
+
+	/// Called once a second: reports the frames dropped since the last call.
+	pub fn take_dropped_frames(&mut self) -> Vec<Effect> {
+		self
+			.dropped_frames
+			.drain()
+			.map(
+				|((session_id, monitor_id, reason), count)| Effect::FramesDropped {
+					session_id,
+					monitor_id,
+					count,
+					reason,
+				},
+			)
+			.collect()
+	}
+
+	/// Hands a consumed buffer back to its session.
+	fn release(&mut self, session_id: SessionId, release: BufferRelease) -> BufferRelease {
+		self.buffer_ownership.insert(
+			(session_id, release.monitor_id, release.buffer),
+			BufferOwner::Client,
+		);
+		self.rates.frame_done = self.rates.frame_done.saturating_add(1);
+		release
+	}
+
+	/// Whether the session is on some monitor. Sessions that become visible get every withheld
+	/// buffer back, returned here.
+	pub fn set_visible(&mut self, session_id: SessionId, visible: bool) -> Vec<BufferRelease> {
+		if !visible {
+			self.hidden_sessions.insert(session_id);
+			return Vec::new();
+		}
+		self.hidden_sessions.remove(&session_id);
+		let withheld = self
+			.withheld_releases
+			.keys()
+			.filter(|(sess, _)| *sess == session_id)
+			.copied()
+			.collect::<Vec<_>>();
+		let mut releases = Vec::new();
+		for key in withheld {
+			for release in self.withheld_releases.remove(&key).unwrap_or_default() {
+				releases.push(self.release(session_id, release));
+			}
+		}
+		releases
+	}
+
+	/// Called once a second: throttled sessions get one withheld buffer per monitor back.
+	pub fn on_pacing_tick(&mut self) -> Vec<Effect> {
+		if self.hidden_pacing != HiddenPacing::Throttle {
+			return Vec::new();
+		}
+		let mut released = Vec::new();
+		for ((session_id, _), releases) in &mut self.withheld_releases {
+			released.extend(releases.pop_front().map(|release| (*session_id, release)));
+		}
+		self
+			.withheld_releases
+			.retain(|_, releases| !releases.is_empty());
+		released
+			.into_iter()
+			.map(|(session_id, release)| Effect::BufferRelease {
+				session_id,
+				release: self.release(session_id, release),
+			})
+			.collect()
+	}
+
+	fn take_pending(
+		&mut self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		buffer: BufferIndex,
+		generation: u64,
+	) -> Option<PendingBufferRequest> {
+		self.remove_pending(session_id, monitor_id, |pending| {
+			pending.buffer == buffer && pending.generation == generation
+		})
+	}
+
+	/// Removes the session's request for `monitor_id` if `matches` accepts it.
+	fn remove_pending(
+		&mut self,
+		session_id: SessionId,
+		monitor_id: MonitorId,
+		matches: impl FnOnce(&PendingBufferRequest) -> bool,
+	) -> Option<PendingBufferRequest> {
+		let pending = self.pending_buffer_requests.get_mut(&session_id)?;
+		if !matches(pending.get(&monitor_id)?) {
+			return None;
+		}
+		let removed = pending.remove(&monitor_id);
+		if pending.is_empty() {
+			self.pending_buffer_requests.remove(&session_id);
+		}
+		removed
+	}
+
+	/// Ends the current second: returns its counters and starts new ones.
+	pub fn on_tick(&mut self) -> FrameRates {
+		std::mem::take(&mut self.rates)
+	}
+
+	/// Counters of the second in progress.
+	pub fn rates(&self) -> FrameRates {
+		self.rates
+	}
+
+	pub fn forget_monitor(&mut self, monitor_id: MonitorId) {
+		self.waiting_flip.retain(|(_, mon), _| *mon != monitor_id);
+		self.pending_buffer_requests.retain(|_, pending| {
+			pending.remove(&monitor_id);
+			!pending.is_empty()
+		});
+		self.front_buffers.retain(|(_, mon), _| *mon != monitor_id);
+		self
+			.buffer_ownership
+			.retain(|(_, mon, _), _| *mon != monitor_id);
+		self
+			.link_generations
+			.retain(|(_, mon), _| *mon != monitor_id);
+		self
+			.withheld_releases
+			.retain(|(_, mon), _| *mon != monitor_id);
+		self
+			.dropped_frames
+			.retain(|(_, mon, _), _| *mon != monitor_id);
+	}
+
+	pub fn forget_session(&mut self, client_id: ClientId, session_id: SessionId) {
+		self.pending_buffer_requests.remove(&session_id);
+		self.pending_buffer_requests.retain(|_, pending| {
+			pending.retain(|_, request| request.client_id != client_id);
+			!pending.is_empty()
+		});
+		self.waiting_flip.retain(|(sess, _), _| *sess != session_id);
+		self
+			.front_buffers
+			.retain(|(sess, _), _| *sess != session_id);
+		self
+			.buffer_ownership
+			.retain(|(sess, _, _), _| *sess != session_id);
+		self
+			.link_generations
+			.retain(|(sess, _), _| *sess != session_id);
+		self.hidden_sessions.remove(&session_id);
+		self
+			.withheld_releases
+			.retain(|(sess, _), _| *sess != session_id);
+		self
+			.dropped_frames
+			.retain(|(sess, _, _), _| *sess != session_id);
+	}
+
+	pub fn pending_buffer_requests(&self) -> usize {
+		self
+			.pending_buffer_requests
+			.values()
+			.map(HashMap::len)
+			.sum()
+	}
+
+	pub fn waiting_flip(&self) -> usize {
+		self.waiting_flip.len()
+	}
+
+	pub fn has_inflight_request(&self, session_id: SessionId) -> bool {
+		self.pending_buffer_requests.contains_key(&session_id)
+	}
+
+	/// Front buffers and buffer owners of a session, for crash reports. Sorted, so reports of the
+	/// same state read the same.
+	pub fn describe_session(&self, session_id: SessionId) -> String {
+		let mut entries = Vec::new();
+		for ((sess, monitor_id), buffer) in &self.front_buffers {
+			if *sess == session_id {
+				entries.push(format!("{monitor_id}:front={buffer:?}"));
+			}
+		}
+		for ((sess, monitor_id, buffer), owner) in &self.buffer_ownership {
+			if *sess == session_id {
+				entries.push(format!("{monitor_id}:{buffer:?}={owner:?}"));
+			}
+		}
+		entries.sort();
+		entries.iter().fold(String::new(), |mut line, entry| {
+			let _ = write!(line, " {entry}");
+			line
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ids() -> (ClientId, SessionId, MonitorId) {
+		(ClientId::rand(), SessionId::rand(), MonitorId::rand())
+	}
+
+	fn ack(session_id: SessionId, monitor_id: MonitorId, buffer: BufferIndex) -> RenderEvt {
+		RenderEvt::BufferRequestAck {
+			session_id,
+			monitor_id,
+			buffer,
+			generation: 0,
+		}
+	}
+
+	#[test]
+	fn buffer_round_trip_moves_ownership_and_counts_frames() {
+		let mut core = ServerCore::new();
+		let (client_id, session_id, monitor_id) = ids();
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::Render(RenderCmd::SwapBuffers { .. })]
+		));
+		assert!(core.has_inflight_request(session_id));
+		assert_eq!(core.pending_buffer_requests(), 1);
+
+		let effects = core
+			.on_render_event(ack(session_id, monitor_id, BufferIndex::Zero))
+			.break_value()
+			.unwrap();
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::BufferRequestAck { client_id: id, .. }] if *id == client_id
+		));
+		assert!(!core.has_inflight_request(session_id));
+		assert_eq!(core.pending_buffer_requests(), 0);
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_err()
+		);
+
+		let effects = core
+			.on_render_event(RenderEvt::BufferConsumed {
+				session_id,
+				monitor_id,
+				buffer: BufferIndex::Zero,
+				generation: 0,
+				release_fence: None,
+			})
+			.break_value()
+			.unwrap();
+		assert!(matches!(effects.as_slice(), [Effect::BufferRelease { .. }]));
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_ok()
+		);
+		assert_eq!(
+			core.on_tick(),
+			FrameRates {
+				swap_buffers: 1,
+				frame_done: 1,
+				frames_skipped: 0,
+			}
+		);
+		assert_eq!(core.rates(), FrameRates::default());
+	}
+
+	#[test]
+	fn rejects_inflight_and_shift_owned_requests() {
+		let mut core = ServerCore::new();
+		let (client_id, session_id, monitor_id) = ids();
+		core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::One,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::Error {
+				error: Error::BufferRequestInflight,
+				..
+			}]
+		));
+
+		core
+			.on_render_event(ack(session_id, monitor_id, BufferIndex::Zero))
+			.break_value()
+			.unwrap();
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::Error {
+				error: Error::OwnershipViolation(_),
+				..
+			}]
+		));
+
+		// Relinking hands both buffers back to the client.
+		core.on_framebuffer_link(session_id, monitor_id, 0);
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(effects.as_slice(), [Effect::Render(_)]));
+	}
+
+	#[test]
+	fn answers_about_an_earlier_link_are_dropped() {
+		let mut core = ServerCore::new();
+		let (client_id, session_id, monitor_id) = ids();
+		core.on_framebuffer_link(session_id, monitor_id, 1);
+		core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		core.on_framebuffer_link(session_id, monitor_id, 2);
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			2,
+			None,
+			None,
+		);
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::Render(RenderCmd::SwapBuffers { generation: 2, .. })]
+		));
+
+		let stale_ack = RenderEvt::BufferRequestAck {
+			session_id,
+			monitor_id,
+			buffer: BufferIndex::Zero,
+			generation: 1,
+		};
+		assert!(
+			core
+				.on_render_event(stale_ack)
+				.break_value()
+				.unwrap()
+				.is_empty()
+		);
+		assert!(core.has_inflight_request(session_id));
+		let stale_release = RenderEvt::BufferConsumed {
+			session_id,
+			monitor_id,
+			buffer: BufferIndex::One,
+			generation: 1,
+			release_fence: None,
+		};
+		assert!(
+			core
+				.on_render_event(stale_release)
+				.break_value()
+				.unwrap()
+				.is_empty()
+		);
+
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::One,
+			1,
+			None,
+			None,
+		);
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::Error {
+				error: Error::BufferRequestRejected(_),
+				..
+			}]
+		));
+	}
+
+	#[test]
+	fn hidden_sessions_get_one_buffer_back_per_second() {
+		let mut core = ServerCore::new();
+		let (client_id, session_id, monitor_id) = ids();
+		core.set_visible(session_id, false);
+		for buffer in [BufferIndex::Zero, BufferIndex::One] {
+			core.on_buffer_request(client_id, session_id, monitor_id, buffer, 0, None, None);
+			core
+				.on_render_event(ack(session_id, monitor_id, buffer))
+				.break_value()
+				.unwrap();
+			let effects = core
+				.on_render_event(RenderEvt::BufferConsumed {
+					session_id,
+					monitor_id,
+					buffer,
+					generation: 0,
+					release_fence: None,
+				})
+				.break_value()
+				.unwrap();
+			assert!(effects.is_empty());
+		}
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_err()
+		);
+
+		let effects = core.on_pacing_tick();
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::BufferRelease { release, .. }] if release.buffer == BufferIndex::Zero
+		));
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_ok()
+		);
+		let releases = core.set_visible(session_id, true);
+		assert!(matches!(
+			releases.as_slice(),
+			[BufferRelease {
+				buffer: BufferIndex::One,
+				..
+			}]
+		));
+		assert!(core.on_pacing_tick().is_empty());
+		assert_eq!(core.rates().frame_done, 2);
+	}
+
+	#[test]
+	fn dropped_frames_are_reported_per_reason_once_per_second() {
+		let mut core = ServerCore::new();
+		let (client_id, session_id, monitor_id) = ids();
+		for _ in 0..3 {
+			core.on_render_event(RenderEvt::FrameDropped {
+				session_id,
+				monitor_id,
+				reason: FrameDropReason::Mailbox,
+			});
+		}
+		core.set_visible(session_id, false);
+		core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		core.on_render_event(ack(session_id, monitor_id, BufferIndex::Zero));
+
+		let mut reported = core
+			.take_dropped_frames()
+			.into_iter()
+			.map(|effect| match effect {
+				Effect::FramesDropped {
+					session_id: sess,
+					monitor_id: mon,
+					count,
+					reason,
+				} if sess == session_id && mon == monitor_id => (reason, count),
+				other => panic!("unexpected {other:?}"),
+			})
+			.collect::<Vec<_>>();
+		reported.sort_by_key(|(reason, _)| *reason as u8);
+		assert_eq!(
+			reported,
+			[(FrameDropReason::Mailbox, 3), (FrameDropReason::Hidden, 1)]
+		);
+		assert!(core.take_dropped_frames().is_empty());
+	}
+
+	#[cfg(feature = "fault-injection")]
+	#[test]
+	fn injected_faults_reject_requests_and_lose_releases() {
+		use crate::faults::FaultInjection;
+
+		let mut core = ServerCore::new();
+		let (client_id, session_id, monitor_id) = ids();
+		core.set_faults(FaultInjection::new(None, 1.0, 0.0, 7));
+		let effects = core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		assert!(matches!(
+			effects.as_slice(),
+			[Effect::Error { error: Error::BufferRequestRejected(reason), .. }] if &**reason == "injected_fault"
+		));
+		assert!(!core.has_inflight_request(session_id));
+
+		core.set_faults(FaultInjection::new(None, 0.0, 1.0, 7));
+		core.on_buffer_request(
+			client_id,
+			session_id,
+			monitor_id,
+			BufferIndex::Zero,
+			0,
+			None,
+			None,
+		);
+		core.on_render_event(ack(session_id, monitor_id, BufferIndex::Zero));
+		let effects = core
+			.on_render_event(RenderEvt::BufferConsumed {
+				session_id,
+				monitor_id,
+				buffer: BufferIndex::Zero,
+				generation: 0,
+				release_fence: None,
+			})
+			.break_value()
+			.unwrap();
+		assert!(effects.is_empty());
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_err(),
+			"a lost release leaves the buffer with shift"
+		);
+		core.on_framebuffer_link(session_id, monitor_id, 1);
+		assert!(
+			core
+				.check_upload(session_id, monitor_id, BufferIndex::Zero)
+				.is_ok()
+		);
+	}
+
+	#[test]
+	fn unknown_acks_and_other_events_pass_through() {
+		let mut core = ServerCore::new();
+		let (_, session_id, monitor_id) = ids();
+		let effects = core
+			.on_render_event(ack(session_id, monitor_id, BufferIndex::One))
+			.break_value()
+			.unwrap();
+		assert!(effects.is_empty());
+		assert!(
+			core
+				.on_render_event(RenderEvt::PageFlip {
+					monitors: vec![monitor_id],
+				})
+				.is_continue()
+		);
+	}
+}
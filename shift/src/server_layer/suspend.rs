@@ -0,0 +1,90 @@
+//! System suspend, as announced by `SIGUSR1` (about to sleep) and `SIGUSR2` (woke up).
+//! - logind's `PrepareForSleep` isn't reachable without a DBus client, so a systemd-sleep hook
+//!   forwards it, e.g. `/usr/lib/systemd/system-sleep/shift`:
+//!   `case $1 in pre) pkill -USR1 -x shift;; post) pkill -USR2 -x shift;; esac`
+//! - the handler only writes the signal number to a pipe, which the server loop reads
+
+use std::{
+	io,
+	os::fd::{AsRawFd, FromRawFd, IntoRawFd, OwnedFd},
+	sync::atomic::{AtomicI32, Ordering},
+};
+
+use tokio::io::unix::AsyncFd;
+
+/// Write end of the pipe, open for the rest of the process once installed.
+static PIPE_WRITE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+	let byte = signal as u8;
+	// SAFETY: write is async-signal-safe. A full pipe drops the byte, which only happens once
+	// the server stopped reading.
+	unsafe {
+		libc::write(
+			PIPE_WRITE.load(Ordering::Relaxed),
+			(&raw const byte).cast(),
+			1,
+		)
+	};
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepEvent {
+	Suspending,
+	Resumed,
+}
+
+pub struct SleepSignals {
+	pipe: AsyncFd<OwnedFd>,
+}
+
+impl SleepSignals {
+	/// Installs the `SIGUSR1` and `SIGUSR2` handlers. Only one instance may exist.
+	pub fn install() -> io::Result<Self> {
+		let mut fds = [0; 2];
+		// SAFETY: pipe2 writes two fds into an array of two.
+		if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK | libc::O_CLOEXEC) } < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		// SAFETY: pipe2 just created both fds and nothing else owns them.
+		let (read, write) = unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+		PIPE_WRITE.store(write.into_raw_fd(), Ordering::Relaxed);
+		for signal in [libc::SIGUSR1, libc::SIGUSR2] {
+			// SAFETY: an all-zero sigaction is valid, and `on_signal` is async-signal-safe.
+			unsafe {
+				let mut action: libc::sigaction = std::mem::zeroed();
+				action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as usize;
+				action.sa_flags = libc::SA_RESTART;
+				libc::sigemptyset(&mut action.sa_mask);
+				if libc::sigaction(signal, &action, std::ptr::null_mut()) < 0 {
+					return Err(io::Error::last_os_error());
+				}
+			}
+		}
+		Ok(Self {
+			pipe: AsyncFd::new(read)?,
+		})
+	}
+
+	pub async fn recv(&self) -> io::Result<SleepEvent> {
+		loop {
+			let mut guard = self.pipe.readable().await?;
+			let mut byte = 0u8;
+			match guard.try_io(|pipe| {
+				// SAFETY: reads at most one byte into `byte`.
+				let n = unsafe { libc::read(pipe.as_raw_fd(), (&raw mut byte).cast(), 1) };
+				if n < 0 {
+					Err(io::Error::last_os_error())
+				} else {
+					Ok(n)
+				}
+			}) {
+				Ok(Ok(1)) if i32::from(byte) == libc::SIGUSR1 => return Ok(SleepEvent::Suspending),
+				Ok(Ok(1)) => return Ok(SleepEvent::Resumed),
+				Ok(Ok(_)) => return Err(io::ErrorKind::UnexpectedEof.into()),
+				Ok(Err(e)) => return Err(e),
+				Err(_would_block) => continue,
+			}
+		}
+	}
+}
@@ -0,0 +1,136 @@
+//! Lid and tablet mode switches, see `switch_events_subscribe`.
+//! - subscribed admins get `lid_closed` and `tablet_mode` on every change, and the known state
+//!   when they subscribe
+//! - `SHIFT_LID_CLOSE_ACTION` is what shift itself does when the lid closes: `blank` (default)
+//!   turns off the built-in panel's backlight until the lid opens, `none` leaves it to admins
+
+use tab_protocol::{SwitchState, SwitchType};
+
+use crate::monitor::{Backlight, Monitor};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LidCloseAction {
+	Blank,
+	Nothing,
+}
+
+impl LidCloseAction {
+	pub fn from_env() -> Self {
+		match std::env::var("SHIFT_LID_CLOSE_ACTION")
+			.unwrap_or_default()
+			.trim()
+			.to_ascii_lowercase()
+			.as_str()
+		{
+			"none" => Self::Nothing,
+			_ => Self::Blank,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchChange {
+	Lid { closed: bool },
+	TabletMode { enabled: bool },
+}
+
+#[derive(Debug)]
+pub struct Switches {
+	lid_close_action: LidCloseAction,
+	/// `None` until libinput reports the switch.
+	lid_closed: Option<bool>,
+	tablet_mode: Option<bool>,
+	/// Backlights the closed lid turned off, with the brightness to restore.
+	blanked: Vec<(Backlight, u32)>,
+}
+
+impl Switches {
+	pub fn new(lid_close_action: LidCloseAction) -> Self {
+		Self {
+			lid_close_action,
+			lid_closed: None,
+			tablet_mode: None,
+			blanked: Vec::new(),
+		}
+	}
+
+	/// Records a switch toggle. `None` if the switch was already in that state.
+	pub fn toggle(&mut self, switch: &SwitchType, state: &SwitchState) -> Option<SwitchChange> {
+		let on = *state == SwitchState::On;
+		let (known, change) = match switch {
+			SwitchType::Lid => (&mut self.lid_closed, SwitchChange::Lid { closed: on }),
+			SwitchType::TabletMode => (
+				&mut self.tablet_mode,
+				SwitchChange::TabletMode { enabled: on },
+			),
+		};
+		(known.replace(on) != Some(on)).then_some(change)
+	}
+
+	/// The state of every switch libinput reported so far.
+	pub fn current(&self) -> Vec<SwitchChange> {
+		let lid = self.lid_closed.map(|closed| SwitchChange::Lid { closed });
+		let tablet_mode = self
+			.tablet_mode
+			.map(|enabled| SwitchChange::TabletMode { enabled });
+		lid.into_iter().chain(tablet_mode).collect()
+	}
+
+	/// Blanks or restores the built-in panels among `monitors` to match the lid.
+	pub fn apply_lid_close_action<'a>(&mut self, monitors: impl IntoIterator<Item = &'a Monitor>) {
+		if self.lid_close_action == LidCloseAction::Nothing {
+			return;
+		}
+		if self.lid_closed != Some(true) {
+			for (backlight, brightness) in self.blanked.drain(..) {
+				if let Err(e) = backlight.set_brightness(brightness) {
+					tracing::warn!(backlight = %backlight.name, "failed to restore backlight: {e}");
+				}
+			}
+			return;
+		}
+		for backlight in monitors
+			.into_iter()
+			.filter_map(|monitor| monitor.backlight.as_ref())
+			.filter(|backlight| backlight.is_built_in())
+		{
+			if self.blanked.iter().any(|(blanked, _)| blanked == backlight) {
+				continue;
+			}
+			let result = backlight
+				.brightness()
+				.and_then(|brightness| backlight.set_brightness(0).map(|()| brightness));
+			match result {
+				Ok(brightness) => self.blanked.push((backlight.clone(), brightness)),
+				Err(e) => tracing::warn!(backlight = %backlight.name, "failed to blank backlight: {e}"),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_reports_changes() {
+		let mut switches = Switches::new(LidCloseAction::Nothing);
+		assert!(switches.current().is_empty());
+		assert_eq!(
+			switches.toggle(&SwitchType::Lid, &SwitchState::On),
+			Some(SwitchChange::Lid { closed: true })
+		);
+		assert_eq!(switches.toggle(&SwitchType::Lid, &SwitchState::On), None);
+		assert_eq!(
+			switches.toggle(&SwitchType::TabletMode, &SwitchState::Off),
+			Some(SwitchChange::TabletMode { enabled: false })
+		);
+		assert_eq!(
+			switches.current(),
+			[
+				SwitchChange::Lid { closed: true },
+				SwitchChange::TabletMode { enabled: false },
+			]
+		);
+	}
+}
@@ -0,0 +1,117 @@
+//! Buffers uploaded with `buffer_upload`, put back together from the rows each message carries.
+//! - a message starting at row 0 starts the buffer over, any other has to carry the rows right
+//!   after the previous one's, for a buffer of the same size
+//! - buffers can't be bigger than their monitor's mode, which bounds what a session can make
+//!   shift hold
+//! - the renderer gets a buffer once its last row arrived
+
+use std::collections::HashMap;
+
+use tab_protocol::BufferIndex;
+
+use crate::{error::Error, monitor::MonitorId, sessions::SessionId};
+
+/// Bytes rows may be padded with past their pixels, as far as allocators align them.
+const MAX_ROW_PADDING: usize = 256;
+
+/// Rows of a buffer, or all of them once complete.
+#[derive(Debug)]
+pub struct Upload {
+	pub width: i32,
+	pub height: i32,
+	pub stride: i32,
+	/// Whole rows, `stride` bytes each.
+	pub pixels: Vec<u8>,
+}
+
+type Slot = (SessionId, MonitorId, BufferIndex);
+
+#[derive(Debug, Default)]
+pub struct Uploads {
+	partial: HashMap<Slot, Upload>,
+}
+
+impl Uploads {
+	/// Adds `rows`, which start at row `y` of a buffer for a monitor whose mode is `mode`.
+	/// Returns the buffer once its last row arrived. Any error drops what came before.
+	pub fn add(
+		&mut self,
+		slot: Slot,
+		mode: (i32, i32),
+		y: i32,
+		rows: Upload,
+	) -> Result<Option<Upload>, Error> {
+		let invalid = |reason: &str| Err(Error::InvalidBufferUpload(reason.into()));
+		let partial = self.partial.remove(&slot);
+		if rows.width > mode.0 || rows.height > mode.1 {
+			return invalid("buffer is bigger than the monitor");
+		}
+		let (Ok(width), Ok(height), Ok(stride), Ok(y)) = (
+			usize::try_from(rows.width),
+			usize::try_from(rows.height),
+			usize::try_from(rows.stride),
+			usize::try_from(y),
+		) else {
+			return invalid("dimensions must be positive");
+		};
+		if stride > width * 4 + MAX_ROW_PADDING {
+			return invalid("stride pads rows too much");
+		}
+		let upload = match partial {
+			_ if y == 0 => rows,
+			Some(mut partial)
+				if (partial.width, partial.height, partial.stride)
+					== (rows.width, rows.height, rows.stride)
+					&& partial.pixels.len() == y * stride =>
+			{
+				partial.pixels.extend_from_slice(&rows.pixels);
+				partial
+			}
+			_ => return invalid("rows don't follow the previous upload"),
+		};
+		if upload.pixels.len() < stride * height {
+			self.partial.insert(slot, upload);
+			return Ok(None);
+		}
+		Ok(Some(upload))
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self
+			.partial
+			.retain(|(partial_session, _, _), _| *partial_session != session_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rows(stride: i32, rows: usize, value: u8) -> Upload {
+		Upload {
+			width: 2,
+			height: 4,
+			stride,
+			pixels: vec![value; stride as usize * rows],
+		}
+	}
+
+	#[test]
+	fn rows_are_put_back_together_in_order() {
+		let mut uploads = Uploads::default();
+		let slot = (SessionId::rand(), MonitorId::rand(), BufferIndex::One);
+		let mode = (1920, 1080);
+		assert!(uploads.add(slot, mode, 0, rows(8, 1, 1)).unwrap().is_none());
+		assert!(uploads.add(slot, mode, 1, rows(8, 2, 2)).unwrap().is_none());
+		let upload = uploads.add(slot, mode, 3, rows(8, 1, 3)).unwrap().unwrap();
+		assert_eq!(upload.pixels.len(), 8 * 4);
+		assert_eq!(upload.pixels[8..24], [2; 16]);
+
+		assert!(uploads.add(slot, mode, 0, rows(8, 1, 1)).unwrap().is_none());
+		assert!(uploads.add(slot, mode, 2, rows(8, 2, 2)).is_err());
+		assert!(uploads.add(slot, mode, 1, rows(8, 3, 2)).is_err());
+		assert!(uploads.add(slot, (1, 1080), 0, rows(8, 4, 1)).is_err());
+		assert!(uploads.add(slot, mode, 0, rows(1024, 4, 1)).is_err());
+		assert!(uploads.partial.is_empty());
+	}
+}
@@ -0,0 +1,252 @@
+//! Hooks told when sessions go on or off screen, for what shift leaves to others, like PipeWire
+//! policy scripts muting the audio of sessions nobody sees. They hear the same transitions as
+//! `session_visibility`.
+//! - `SHIFT_VISIBILITY_HOOK` is a `/bin/sh` command run on every transition with
+//!   `SHIFT_SESSION_ID`, `SHIFT_SESSION_NAME` and `SHIFT_SESSION_VISIBLE` (`1` or `0`) set. The
+//!   server loop doesn't wait for it. A session's commands run one at a time: transitions that
+//!   come while one runs wait for it, and of those only the latest runs. Commands still running
+//!   after `COMMAND_TIMEOUT` are killed
+//! - the DBus service, when on, emits `SessionVisibilityChanged`
+//! - a session that goes away while on screen goes off screen first
+
+use std::{
+	collections::{HashMap, HashSet},
+	process::Stdio,
+	time::Duration,
+};
+
+use futures::{StreamExt, stream::FuturesUnordered};
+use tokio::{process::Command, sync::mpsc};
+
+use crate::sessions::SessionId;
+
+const COMMAND_ENV: &str = "SHIFT_VISIBILITY_HOOK";
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VisibilityChange {
+	pub session_id: SessionId,
+	pub display_name: String,
+	pub visible: bool,
+}
+
+pub trait VisibilityHook: Send {
+	fn session_visibility(&mut self, change: &VisibilityChange);
+}
+
+/// Hands changes to a task, like the DBus service's.
+impl VisibilityHook for mpsc::UnboundedSender<VisibilityChange> {
+	fn session_visibility(&mut self, change: &VisibilityChange) {
+		// The task is gone along with whatever it served.
+		self.send(change.clone()).ok();
+	}
+}
+
+/// Runs a shell command per transition, on one task that also reaps them.
+#[derive(Debug)]
+pub struct CommandHook {
+	command: String,
+	/// The task running the commands, started with the first transition.
+	runner: Option<mpsc::UnboundedSender<VisibilityChange>>,
+}
+
+impl CommandHook {
+	/// `SHIFT_VISIBILITY_HOOK`, `None` when unset or empty.
+	pub fn from_env() -> Option<Self> {
+		let command = std::env::var(COMMAND_ENV).ok()?;
+		Some(command)
+			.filter(|command| !command.trim().is_empty())
+			.map(Self::new)
+	}
+
+	pub fn new(command: String) -> Self {
+		Self {
+			command,
+			runner: None,
+		}
+	}
+}
+
+/// How `hook` runs for `change`.
+fn command(hook: &str, change: &VisibilityChange) -> Command {
+	let mut cmd = Command::new("/bin/sh");
+	cmd
+		.args(["-c", hook])
+		.env("SHIFT_SESSION_ID", change.session_id.to_string())
+		.env("SHIFT_SESSION_NAME", &change.display_name)
+		.env(
+			"SHIFT_SESSION_VISIBLE",
+			if change.visible { "1" } else { "0" },
+		)
+		.stdin(Stdio::null());
+	cmd
+}
+
+/// Runs `cmd` to completion, or kills it after `COMMAND_TIMEOUT`.
+async fn run_command(mut cmd: Command, session_id: SessionId) -> SessionId {
+	let mut child = match cmd.spawn() {
+		Ok(child) => child,
+		Err(e) => {
+			tracing::warn!(%session_id, "failed to run {COMMAND_ENV}: {e}");
+			return session_id;
+		}
+	};
+	match tokio::time::timeout(COMMAND_TIMEOUT, child.wait()).await {
+		Ok(Ok(status)) if !status.success() => {
+			tracing::warn!(%session_id, %status, "{COMMAND_ENV} failed");
+		}
+		Ok(Ok(_)) => {}
+		Ok(Err(e)) => tracing::warn!(%session_id, "failed to wait for {COMMAND_ENV}: {e}"),
+		Err(_) => {
+			tracing::warn!(%session_id, "{COMMAND_ENV} timed out, killing it");
+			if let Err(e) = child.kill().await {
+				tracing::warn!(%session_id, "failed to kill {COMMAND_ENV}: {e}");
+			}
+		}
+	}
+	session_id
+}
+
+/// Runs `hook` for every change, one at a time per session, until the hook goes away and the
+/// last commands finished.
+async fn run_commands(hook: String, mut changes: mpsc::UnboundedReceiver<VisibilityChange>) {
+	let mut running = FuturesUnordered::new();
+	let mut busy = HashSet::new();
+	let mut waiting = HashMap::<SessionId, VisibilityChange>::new();
+	let mut open = true;
+	loop {
+		tokio::select! {
+			change = changes.recv(), if open => {
+				let Some(change) = change else {
+					open = false;
+					continue;
+				};
+				if busy.insert(change.session_id) {
+					running.push(run_command(command(&hook, &change), change.session_id));
+				} else {
+					waiting.insert(change.session_id, change);
+				}
+			}
+			Some(session_id) = running.next(), if !running.is_empty() => {
+				match waiting.remove(&session_id) {
+					Some(change) => running.push(run_command(command(&hook, &change), session_id)),
+					None => {
+						busy.remove(&session_id);
+					}
+				}
+			}
+			else => break,
+		}
+	}
+}
+
+impl VisibilityHook for CommandHook {
+	fn session_visibility(&mut self, change: &VisibilityChange) {
+		let runner = self.runner.get_or_insert_with(|| {
+			let (tx, rx) = mpsc::unbounded_channel();
+			tokio::spawn(run_commands(self.command.clone(), rx));
+			tx
+		});
+		// The task only ends once this sender is dropped, unless it panicked.
+		runner.send(change.clone()).ok();
+	}
+}
+
+#[derive(Default)]
+pub struct VisibilityHooks {
+	hooks: Vec<Box<dyn VisibilityHook>>,
+}
+
+impl VisibilityHooks {
+	pub fn from_env() -> Self {
+		let mut hooks = Self::default();
+		if let Some(hook) = CommandHook::from_env() {
+			tracing::info!(command = %hook.command, "running {COMMAND_ENV} on session visibility changes");
+			hooks.push(hook);
+		}
+		hooks
+	}
+
+	pub fn push(&mut self, hook: impl VisibilityHook + 'static) {
+		self.hooks.push(Box::new(hook));
+	}
+
+	pub fn notify(&mut self, change: VisibilityChange) {
+		for hook in &mut self.hooks {
+			hook.session_visibility(&change);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::ffi::OsStr;
+
+	use super::*;
+
+	#[test]
+	fn hooks_hear_every_transition() {
+		let (tx, mut rx) = mpsc::unbounded_channel();
+		let mut hooks = VisibilityHooks::default();
+		hooks.push(tx);
+		let change = VisibilityChange {
+			session_id: SessionId::rand(),
+			display_name: "Music".into(),
+			visible: false,
+		};
+		hooks.notify(change.clone());
+		assert_eq!(rx.try_recv().ok(), Some(change.clone()));
+		assert!(rx.try_recv().is_err());
+
+		let cmd = command("wpctl set-mute @DEFAULT_SINK@ 1", &change);
+		let cmd = cmd.as_std();
+		assert_eq!(cmd.get_program(), "/bin/sh");
+		let envs = cmd.get_envs().collect::<Vec<_>>();
+		let session_id = change.session_id.to_string();
+		assert!(envs.contains(&(
+			OsStr::new("SHIFT_SESSION_ID"),
+			Some(OsStr::new(&session_id))
+		)));
+		assert!(envs.contains(&(OsStr::new("SHIFT_SESSION_NAME"), Some(OsStr::new("Music")))));
+		assert!(envs.contains(&(OsStr::new("SHIFT_SESSION_VISIBLE"), Some(OsStr::new("0")))));
+		assert_eq!(
+			cmd.get_args().collect::<Vec<_>>(),
+			["-c", "wpctl set-mute @DEFAULT_SINK@ 1"]
+		);
+	}
+
+	#[tokio::test]
+	async fn hooks_run_one_at_a_time_per_session() {
+		let log = std::env::temp_dir().join(format!("shift-visibility-hook-{}", rand::random::<u64>()));
+		let hook = format!(
+			"echo \"$SHIFT_SESSION_NAME $SHIFT_SESSION_VISIBLE\" >> {}; sleep 0.1",
+			log.display()
+		);
+		let (music, video) = (SessionId::rand(), SessionId::rand());
+		let change = |session_id, display_name: &str, visible| VisibilityChange {
+			session_id,
+			display_name: display_name.into(),
+			visible,
+		};
+		let (tx, rx) = mpsc::unbounded_channel();
+		for change in [
+			change(music, "music", false),
+			change(music, "music", true),
+			change(video, "video", true),
+			change(music, "music", false),
+		] {
+			tx.send(change).unwrap();
+		}
+		drop(tx);
+		run_commands(hook, rx).await;
+
+		let mut lines = std::fs::read_to_string(&log)
+			.unwrap()
+			.lines()
+			.map(str::to_string)
+			.collect::<Vec<_>>();
+		std::fs::remove_file(&log).unwrap();
+		lines.sort();
+		assert_eq!(lines, ["music 0", "music 0", "video 1"]);
+	}
+}
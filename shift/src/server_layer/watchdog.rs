@@ -0,0 +1,147 @@
+//! Detects sessions that stopped submitting frames, see `session_unresponsive`.
+//! - `SHIFT_SESSION_WATCHDOG_MS` is how long an on-screen session may go without a
+//!   `buffer_request` before admins hear about it. Unset or 0 turns the watchdog off, as sessions
+//!   that only draw when something changes look frozen too
+//! - only sessions on screen are watched, and only once they submitted their first frame. Time
+//!   spent hidden or suspended doesn't count
+//! - `SHIFT_SESSION_WATCHDOG_DIM` (0..1) dims frozen sessions by that much until they submit again
+
+use std::{
+	collections::{HashMap, HashSet},
+	time::{Duration, Instant},
+};
+
+use crate::sessions::SessionId;
+
+#[derive(Debug, Default)]
+pub struct Watchdog {
+	threshold: Option<Duration>,
+	dim: Option<f32>,
+	/// Last submission of every session that submitted at least once, or when it came back on
+	/// screen.
+	last_submission: HashMap<SessionId, Instant>,
+	unresponsive: HashSet<SessionId>,
+}
+
+impl Watchdog {
+	pub fn from_env() -> Self {
+		let threshold = std::env::var("SHIFT_SESSION_WATCHDOG_MS")
+			.ok()
+			.and_then(|raw| match raw.trim().parse::<u64>() {
+				Ok(0) => None,
+				Ok(ms) => Some(Duration::from_millis(ms)),
+				Err(e) => {
+					tracing::warn!(value = %raw, "invalid SHIFT_SESSION_WATCHDOG_MS: {e}");
+					None
+				}
+			});
+		let dim = std::env::var("SHIFT_SESSION_WATCHDOG_DIM")
+			.ok()
+			.and_then(|raw| match raw.trim().parse::<f32>() {
+				Ok(dim) if (0.0..=1.0).contains(&dim) => (dim > 0.0).then_some(dim),
+				_ => {
+					tracing::warn!(value = %raw, "invalid SHIFT_SESSION_WATCHDOG_DIM, expected 0..1");
+					None
+				}
+			});
+		Self::new(threshold, dim)
+	}
+
+	pub fn new(threshold: Option<Duration>, dim: Option<f32>) -> Self {
+		Self {
+			threshold,
+			dim,
+			..Default::default()
+		}
+	}
+
+	/// How much frozen sessions are dimmed, if at all.
+	pub fn dim(&self) -> Option<f32> {
+		self.dim
+	}
+
+	/// The session submitted a frame. If it was unresponsive until now, how long it went without
+	/// one.
+	pub fn on_submission(&mut self, session_id: SessionId, now: Instant) -> Option<Duration> {
+		self.threshold?;
+		let last = self.last_submission.insert(session_id, now)?;
+		self
+			.unresponsive
+			.remove(&session_id)
+			.then(|| now.saturating_duration_since(last))
+	}
+
+	/// Sessions that just went past the threshold, with the time since their last submission.
+	/// Hidden sessions start over, as they aren't asked for frames.
+	pub fn check(
+		&mut self,
+		now: Instant,
+		visible: impl Fn(SessionId) -> bool,
+	) -> Vec<(SessionId, Duration)> {
+		let Some(threshold) = self.threshold else {
+			return Vec::new();
+		};
+		let mut frozen = Vec::new();
+		for (session_id, last) in &mut self.last_submission {
+			if !visible(*session_id) {
+				*last = now;
+				continue;
+			}
+			let idle = now.saturating_duration_since(*last);
+			if idle >= threshold && self.unresponsive.insert(*session_id) {
+				frozen.push((*session_id, idle));
+			}
+		}
+		frozen
+	}
+
+	/// Restarts every clock, e.g. after resuming from suspend when nobody could submit.
+	pub fn restart(&mut self, now: Instant) {
+		for last in self.last_submission.values_mut() {
+			*last = now;
+		}
+	}
+
+	pub fn forget_session(&mut self, session_id: SessionId) {
+		self.last_submission.remove(&session_id);
+		self.unresponsive.remove(&session_id);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn reports_visible_sessions_once_until_they_submit_again() {
+		let start = Instant::now();
+		let at = |ms| start + Duration::from_millis(ms);
+		let (shown, hidden) = (SessionId::rand(), SessionId::rand());
+		let mut watchdog = Watchdog::new(Some(Duration::from_millis(500)), None);
+		assert!(
+			watchdog.check(at(1000), |_| true).is_empty(),
+			"nothing submitted yet"
+		);
+
+		watchdog.on_submission(shown, at(0));
+		watchdog.on_submission(hidden, at(0));
+		assert!(watchdog.check(at(400), |_| true).is_empty());
+		assert_eq!(
+			watchdog.check(at(600), |id| id == shown),
+			[(shown, Duration::from_millis(600))]
+		);
+		assert!(watchdog.check(at(900), |id| id == shown).is_empty());
+		assert!(
+			watchdog.check(at(1000), |_| true).is_empty(),
+			"hidden time doesn't count"
+		);
+
+		assert_eq!(
+			watchdog.on_submission(shown, at(1200)),
+			Some(Duration::from_millis(1200))
+		);
+		assert_eq!(watchdog.on_submission(shown, at(1300)), None);
+		watchdog.restart(at(5000));
+		assert_eq!(watchdog.check(at(5600), |_| true).len(), 2);
+	}
+}
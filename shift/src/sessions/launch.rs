@@ -0,0 +1,160 @@
+//! Spawning session processes.
+//! - `session_create` launch descriptors, validated before the session is created
+//! - the `ADMIN_LAUNCH_CMD` and `SHIFT_DEBUG_SECOND_SESSION_CMD` commands
+//!
+//! Commands run through `$SHELL -c`, or `/bin/sh -c` without one, with `SHIFT_SESSION_TOKEN` set
+//! to the session's token. Another user's commands run through their login shell, in an
+//! environment of their own instead of shift's. Processes are reaped once they exit.
+
+use std::{io, path::Path};
+
+use nix::unistd::{Uid, User};
+use tab_protocol::{SessionCreatePayload, SessionRole};
+use thiserror::Error;
+use tokio::process::Command;
+
+use crate::{
+	auth::Token,
+	monitor::{MonitorId, MonitorIdParseError},
+};
+
+const TOKEN_ENV: &str = "SHIFT_SESSION_TOKEN";
+const FALLBACK_SHELL: &str = "/bin/sh";
+/// `PATH` for another user's commands when shift has none.
+const FALLBACK_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+#[derive(Debug, Error)]
+pub enum LaunchError {
+	#[error("env and user need a command to apply to")]
+	NoCommand,
+	#[error("invalid environment variable name {0:?}")]
+	InvalidEnvName(String),
+	#[error("{TOKEN_ENV} is set by shift")]
+	ReservedEnv,
+	#[error("unknown user {0:?}")]
+	UnknownUser(String),
+	#[error("failed to look up user {name:?}: {source}")]
+	UserLookup { name: String, source: nix::Error },
+	#[error("running sessions as another user needs shift to run as root")]
+	UserSwitchNotPermitted,
+	#[error("observer sessions can't be assigned to a monitor")]
+	ObserverMonitor,
+	#[error("invalid monitor id: {0}")]
+	MonitorId(#[from] MonitorIdParseError),
+}
+
+/// How shift starts a session and where it is shown, from `session_create`.
+#[derive(Debug, Clone, Default)]
+pub struct LaunchDescriptor {
+	command: Option<String>,
+	env: Vec<(String, String)>,
+	user: Option<User>,
+	monitor_id: Option<MonitorId>,
+}
+
+impl LaunchDescriptor {
+	/// Runs `command` as shift's own user, with shift's environment.
+	pub fn shell(command: impl Into<String>) -> Self {
+		Self {
+			command: Some(command.into()),
+			..Self::default()
+		}
+	}
+
+	pub fn from_payload(payload: &SessionCreatePayload) -> Result<Self, LaunchError> {
+		if payload.command.is_none() && (!payload.env.is_empty() || payload.user.is_some()) {
+			return Err(LaunchError::NoCommand);
+		}
+		for name in payload.env.keys() {
+			if name.is_empty() || name.contains(['=', '\0']) {
+				return Err(LaunchError::InvalidEnvName(name.clone()));
+			}
+			if name == TOKEN_ENV {
+				return Err(LaunchError::ReservedEnv);
+			}
+		}
+		let user = match &payload.user {
+			Some(name) => {
+				if !Uid::effective().is_root() {
+					return Err(LaunchError::UserSwitchNotPermitted);
+				}
+				let user = User::from_name(name)
+					.map_err(|source| LaunchError::UserLookup {
+						name: name.clone(),
+						source,
+					})?
+					.ok_or_else(|| LaunchError::UnknownUser(name.clone()))?;
+				Some(user)
+			}
+			None => None,
+		};
+		if payload.role == SessionRole::Observer && payload.monitor_id.is_some() {
+			return Err(LaunchError::ObserverMonitor);
+		}
+		let monitor_id = payload
+			.monitor_id
+			.as_deref()
+			.map(str::parse::<MonitorId>)
+			.transpose()?;
+		Ok(Self {
+			command: payload.command.clone(),
+			env: payload
+				.env
+				.iter()
+				.map(|(name, value)| (name.clone(), value.clone()))
+				.collect(),
+			user,
+			monitor_id,
+		})
+	}
+
+	/// Monitor to assign the session to once it is ready.
+	pub fn monitor_id(&self) -> Option<MonitorId> {
+		self.monitor_id
+	}
+
+	/// Starts the command, if there is one, and returns its pid. A different user gets its login
+	/// shell, its home as working directory, no supplementary groups, and only `PATH`,
+	/// `HOME`/`USER`/`LOGNAME` and `XDG_RUNTIME_DIR` of its own before `env`.
+	pub fn spawn(&self, token: &Token) -> io::Result<Option<u32>> {
+		let Some(command) = &self.command else {
+			return Ok(None);
+		};
+		let mut cmd = match &self.user {
+			Some(user) => {
+				let shell = match user.shell.as_os_str().is_empty() {
+					true => Path::new(FALLBACK_SHELL),
+					false => &user.shell,
+				};
+				let mut cmd = Command::new(shell);
+				cmd
+					.env_clear()
+					.env(
+						"PATH",
+						std::env::var_os("PATH").unwrap_or_else(|| FALLBACK_PATH.into()),
+					)
+					.env("HOME", &user.dir)
+					.env("USER", &user.name)
+					.env("LOGNAME", &user.name)
+					.env("XDG_RUNTIME_DIR", format!("/run/user/{}", user.uid))
+					.uid(user.uid.as_raw())
+					.gid(user.gid.as_raw())
+					.current_dir(&user.dir);
+				cmd
+			}
+			None => Command::new(std::env::var_os("SHELL").unwrap_or_else(|| FALLBACK_SHELL.into())),
+		};
+		cmd.args(["-c", command]);
+		cmd.envs(self.env.iter().map(|(name, value)| (name, value)));
+		cmd.env(TOKEN_ENV, token.to_string());
+		let mut child = cmd.spawn()?;
+		let pid = child.id();
+		tokio::spawn(async move {
+			match child.wait().await {
+				Ok(status) => tracing::info!(?pid, %status, "session process exited"),
+				Err(e) => tracing::warn!(?pid, "failed to wait for session process: {e}"),
+			}
+		});
+		Ok(pid)
+	}
+}
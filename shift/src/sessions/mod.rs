@@ -1,5 +1,6 @@
 use crate::define_id_type;
 pub use role::Role;
+pub mod launch;
 mod pending_sessions;
 mod role;
 mod session;
@@ -2,9 +2,9 @@ use std::sync::Arc;
 
 use chrono::{DateTime, Utc};
 
-use crate::{auth::Token, sessions::Session};
+use crate::{auth::Token, ids::Id, sessions::Session};
 
-use super::{Role, SessionId};
+use super::{Role, SessionId, launch::LaunchDescriptor};
 
 #[derive(Debug, Clone)]
 pub struct PendingSession {
@@ -12,6 +12,9 @@ pub struct PendingSession {
 	role: Role,
 	created_at: DateTime<Utc>,
 	display_name: Option<Arc<str>>,
+	launch: LaunchDescriptor,
+	/// Process spawned for the session, which may authenticate as a peer, see `auth::peer`.
+	pid: Option<u32>,
 }
 impl PendingSession {
 	pub fn id(&self) -> SessionId {
@@ -25,6 +28,30 @@ impl PendingSession {
 		self.display_name.as_deref()
 	}
 
+	pub fn launch(&self) -> &LaunchDescriptor {
+		&self.launch
+	}
+
+	pub fn with_launch(mut self, launch: LaunchDescriptor) -> Self {
+		self.launch = launch;
+		self
+	}
+
+	pub fn pid(&self) -> Option<u32> {
+		self.pid
+	}
+
+	pub fn with_pid(mut self, pid: Option<u32>) -> Self {
+		self.pid = pid;
+		self
+	}
+
+	/// Gives the session a new id if `taken` already knows its one.
+	pub fn avoiding(mut self, taken: impl Fn(SessionId) -> bool) -> Self {
+		self.id = self.id.or_unused(taken);
+		self
+	}
+
 	pub fn new(display_name: Option<Arc<str>>, role: Role) -> (Token, Self) {
 		(
 			Token::generate().expect("getrandom to be available"),
@@ -33,6 +60,8 @@ impl PendingSession {
 				role,
 				created_at: Utc::now(),
 				display_name,
+				launch: LaunchDescriptor::default(),
+				pid: None,
 			},
 		)
 	}
@@ -48,12 +77,14 @@ impl PendingSession {
 		Session {
 			id: self.id,
 			role: self.role,
-			ready: self.role == Role::Admin,
+			// Only normal sessions go through loading -> ready.
+			ready: self.role != Role::Normal,
 			display_name: self
 				.display_name
 				.as_ref()
 				.map(Arc::clone)
 				.unwrap_or_else(|| self.default_session_name().into()),
+			preferred_monitor: self.launch.monitor_id(),
 		}
 	}
 	pub fn default_session_name(&self) -> String {
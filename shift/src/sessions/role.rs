@@ -5,6 +5,18 @@ use tab_protocol::SessionRole;
 pub enum Role {
 	Normal = 0,
 	Admin = 1,
+	Observer = 2,
+}
+
+impl Role {
+	pub fn is_admin(self) -> bool {
+		self == Self::Admin
+	}
+
+	/// Whether the role may read server state (session list, stats, screenshots).
+	pub fn can_observe(self) -> bool {
+		matches!(self, Self::Admin | Self::Observer)
+	}
 }
 
 impl From<SessionRole> for Role {
@@ -12,6 +24,7 @@ impl From<SessionRole> for Role {
 		match value {
 			SessionRole::Admin => Self::Admin,
 			SessionRole::Session => Self::Normal,
+			SessionRole::Observer => Self::Observer,
 		}
 	}
 }
@@ -21,6 +34,7 @@ impl From<Role> for SessionRole {
 		match value {
 			Role::Normal => Self::Session,
 			Role::Admin => Self::Admin,
+			Role::Observer => Self::Observer,
 		}
 	}
 }
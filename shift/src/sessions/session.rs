@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use crate::{define_id_type, sessions::Role};
+use crate::{define_id_type, monitor::MonitorId, sessions::Role};
 
 define_id_type!(Session, "se_");
 
@@ -10,6 +10,8 @@ pub struct Session {
 	pub(super) role: Role,
 	pub(super) ready: bool,
 	pub(super) display_name: Arc<str>,
+	/// Monitor requested in `session_create`, assigned once the session is ready.
+	pub(super) preferred_monitor: Option<MonitorId>,
 }
 
 impl Session {
@@ -30,4 +32,7 @@ impl Session {
 	pub fn display_name(&self) -> &str {
 		&self.display_name
 	}
+	pub fn preferred_monitor(&self) -> Option<MonitorId> {
+		self.preferred_monitor
+	}
 }
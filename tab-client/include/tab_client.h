@@ -42,16 +42,16 @@ typedef enum {
     TAB_INPUT_KIND_TABLET_PAD_RING = 17,
     TAB_INPUT_KIND_TABLET_PAD_STRIP = 18,
     TAB_INPUT_KIND_SWITCH_TOGGLE = 19,
-    TAB_INPUT_KIND_GESTURE_SWIPE_BEGIN,
-    TAB_INPUT_KIND_GESTURE_SWIPE_UPDATE,
-    TAB_INPUT_KIND_GESTURE_SWIPE_END,
+    TAB_INPUT_KIND_GESTURE_SWIPE_BEGIN = 20,
+    TAB_INPUT_KIND_GESTURE_SWIPE_UPDATE = 21,
+    TAB_INPUT_KIND_GESTURE_SWIPE_END = 22,
 
-    TAB_INPUT_KIND_GESTURE_PINCH_BEGIN,
-    TAB_INPUT_KIND_GESTURE_PINCH_UPDATE,
-    TAB_INPUT_KIND_GESTURE_PINCH_END,
+    TAB_INPUT_KIND_GESTURE_PINCH_BEGIN = 23,
+    TAB_INPUT_KIND_GESTURE_PINCH_UPDATE = 24,
+    TAB_INPUT_KIND_GESTURE_PINCH_END = 25,
 
-    TAB_INPUT_KIND_GESTURE_HOLD_BEGIN,
-    TAB_INPUT_KIND_GESTURE_HOLD_END,
+    TAB_INPUT_KIND_GESTURE_HOLD_BEGIN = 26,
+    TAB_INPUT_KIND_GESTURE_HOLD_END = 27,
 } TabInputEventKind;
 
 typedef enum {
@@ -134,6 +134,8 @@ typedef struct {
     uint64_t time_usec;
     uint32_t key;
     TabKeyState state;
+    /* Sent again for a key held down. */
+    bool repeat;
 } TabInputKey;
 
 typedef struct {
@@ -342,12 +344,30 @@ typedef struct {
  * ============================================================================
  */
 
+/* How a built-in panel is mounted in its case. */
+typedef enum {
+    TAB_PANEL_ORIENTATION_UNKNOWN = 0,
+    TAB_PANEL_ORIENTATION_NORMAL = 1,
+    TAB_PANEL_ORIENTATION_UPSIDE_DOWN = 2,
+    TAB_PANEL_ORIENTATION_LEFT_SIDE_UP = 3,
+    TAB_PANEL_ORIENTATION_RIGHT_SIDE_UP = 4,
+} TabPanelOrientation;
+
 typedef struct {
     const char *id;
     int32_t width;
     int32_t height;
     int32_t refresh_rate;
     const char *name;
+    /* From the monitor's EDID; NULL / 0 when unknown. */
+    const char *manufacturer;
+    const char *model;
+    const char *serial;
+    uint32_t width_mm;
+    uint32_t height_mm;
+    /* VR headsets and the like, which shouldn't show a desktop. */
+    bool non_desktop;
+    TabPanelOrientation panel_orientation;
 } TabMonitorInfo;
 
 /* ============================================================================
@@ -358,6 +378,8 @@ typedef struct {
 typedef enum {
     TAB_SESSION_ROLE_ADMIN = 0,
     TAB_SESSION_ROLE_SESSION = 1,
+    /* Read-only: can't present or manage sessions. */
+    TAB_SESSION_ROLE_OBSERVER = 2,
 } TabSessionRole;
 
 typedef enum {
@@ -374,6 +396,14 @@ typedef struct {
     TabSessionLifecycle state;
 } TabSessionInfo;
 
+typedef struct {
+    TabSessionInfo session;
+    /* Whether a client is connected for the session. */
+    bool attached;
+    /* Whether the session is on screen. */
+    bool visible;
+} TabSessionListEntry;
+
 /* ============================================================================
  * EVENTS
  * ============================================================================
@@ -395,8 +425,45 @@ typedef enum {
     TAB_EVENT_SESSION_AWAKE = 6,
     TAB_EVENT_SESSION_SLEEP = 7,
     TAB_EVENT_SESSION_ACTIVE = 8,
+    TAB_EVENT_FOCUS_IN = 9,
+    TAB_EVENT_FOCUS_OUT = 10,
+    TAB_EVENT_POINTER_LOCK = 11,
+    TAB_EVENT_SHORTCUT_TRIGGERED = 12,
+    /* The monitor's swapchain was resized to the new mode; reacquire frames for it. */
+    TAB_EVENT_MONITOR_CHANGED = 13,
+    /* Admin only: shift's boot splash faded out. Carries no data. */
+    TAB_EVENT_SPLASH_ENDED = 14,
+    /* The session went on or off screen; hidden sessions get buffers back slowly, if at all. */
+    TAB_EVENT_SESSION_VISIBILITY = 15,
+    /* The system woke up from suspend; redraw every monitor. Carries no data. */
+    TAB_EVENT_RESUMED = 16,
+    /* This session is no longer the active one; the session that replaced it is on screen. */
+    TAB_EVENT_SESSION_INACTIVE = 17,
+    /* shift suspects a bug in how this client renders, see TabPerformanceWarning. */
+    TAB_EVENT_PERFORMANCE_WARNING = 18,
+    /* shift lost the buffers of this monitor, e.g. over suspend, and the client linked them
+       again; redraw it. Carries the monitor id. */
+    TAB_EVENT_BUFFERS_INVALIDATED = 19,
 } TabEventType;
 
+#define TAB_SHORTCUT_MOD_CTRL (1u << 0)
+#define TAB_SHORTCUT_MOD_ALT (1u << 1)
+#define TAB_SHORTCUT_MOD_SHIFT (1u << 2)
+#define TAB_SHORTCUT_MOD_SUPER (1u << 3)
+
+#define TAB_PRESENT_FIFO 0u
+#define TAB_PRESENT_MAILBOX 1u
+
+typedef enum {
+    TAB_FOCUS_POINTER = 0,
+    TAB_FOCUS_KEYBOARD = 1,
+} TabFocusTarget;
+
+typedef struct {
+    const char *session_id;
+    TabFocusTarget target;
+} TabFocusChange;
+
 typedef struct {
     const char *monitor_id;
     uint32_t buffer_index;
@@ -408,16 +475,39 @@ typedef struct {
     const char *name;
 } TabMonitorRemoved;
 
+typedef enum {
+    /* Frames kept coming but showed the same thing: check that the buffer drawn into is the
+       one submitted, and that drawing finished or its fence was passed along. */
+    TAB_PERFORMANCE_WARNING_STATIC_CONTENT = 0,
+} TabPerformanceWarningKind;
+
+typedef struct {
+    const char *monitor_id;
+    TabPerformanceWarningKind kind;
+    /* How long it went on before shift warned. */
+    uint64_t duration_ms;
+    /* Frames submitted meanwhile. */
+    uint32_t frames;
+} TabPerformanceWarning;
+
 typedef union {
     TabBufferRelease buffer_released;
     TabMonitorInfo monitor_added;
     TabMonitorRemoved monitor_removed;
+    TabMonitorInfo monitor_changed;
     TabSessionInfo session_state;
     const char *session_awake;
     const char *session_sleep;
     const char *session_active;
+    const char *session_inactive;
     TabInputEvent input;
     const char *session_created_token;
+    TabFocusChange focus;
+    bool pointer_locked;
+    const char *shortcut_id;
+    bool session_visible;
+    TabPerformanceWarning performance_warning;
+    const char *buffers_invalidated;
 } TabEventData;
 
 typedef struct {
@@ -445,11 +535,19 @@ typedef struct {
     uint32_t buffer_index;
     TabDmabuf dmabuf;
 } TabFrameTarget;
+
+/* Frames that never reached the screen since connecting: skipped with
+ * tab_client_skip_frame(), or submitted and dropped by shift. */
+typedef struct {
+    uint64_t frames_skipped;
+    uint64_t frames_dropped;
+} TabFrameStats;
 /* ============================================================================
  * API
  * ============================================================================
  */
 
+/* socket_path may also be a "tcp:<host>:<port>" or "vsock:<cid>:<port>" address. */
 TabClientHandle *tab_client_connect(const char *socket_path, const char *token);
 TabClientHandle *tab_client_connect_default(const char *token);
 void tab_client_disconnect(TabClientHandle *handle);
@@ -467,6 +565,17 @@ void tab_client_free_monitor_info(TabMonitorInfo *info);
 TabSessionInfo tab_client_get_session(TabClientHandle *handle);
 void tab_client_free_session_info(TabSessionInfo *session_info);
 bool tab_client_send_ready(TabClientHandle *handle);
+bool tab_client_set_pointer_lock(TabClientHandle *handle, bool enable);
+/* Relinks all swapchains; call before acquiring the first frame. */
+bool tab_client_set_present_mode(TabClientHandle *handle, uint32_t mode);
+/* modifiers is a mask of TAB_SHORTCUT_MOD_*, key an evdev key code (admin only). */
+bool tab_client_shortcut_register(
+    TabClientHandle *handle,
+    const char *id,
+    uint32_t modifiers,
+    uint32_t key
+);
+bool tab_client_shortcut_unregister(TabClientHandle *handle, const char *id);
 bool tab_client_session_create(
     TabClientHandle *handle,
     TabSessionRole role,
@@ -478,8 +587,23 @@ bool tab_client_session_switch(
     const char *animation,
     uint32_t duration_ms
 );
+/* Every session shift knows about, sorted by id, with their count in *count (admin or observer
+   only). NULL on error. Free with tab_client_free_session_list. */
+TabSessionListEntry *tab_client_list_sessions(TabClientHandle *handle, size_t *count);
+void tab_client_free_session_list(TabSessionListEntry *sessions, size_t count);
 
 size_t tab_client_poll_events(TabClientHandle *handle);
+/* A single fd for GLib, libevent and similar loops, readable whenever the socket or render node
+   is or events are still queued. Watch it for reading and call tab_client_dispatch_pending when
+   it is. Owned by the handle; -1 on error. */
+int tab_client_get_wakeup_fd(TabClientHandle *handle);
+/* Like tab_client_poll_events, and leaves the wakeup fd readable only while events are queued. */
+size_t tab_client_dispatch_pending(TabClientHandle *handle);
+/* Like tab_client_poll_events, but blocks up to timeout_ms until an event is queued. */
+size_t tab_client_wait_for_event(TabClientHandle *handle, uint32_t timeout_ms);
+/* Blocks up to timeout_ms until shift reports a monitor; true if one is known. Monitors
+   reported while waiting still arrive as TAB_EVENT_MONITOR_ADDED. */
+bool tab_client_wait_for_monitor(TabClientHandle *handle, uint32_t timeout_ms);
 bool tab_client_next_event(TabClientHandle *handle, TabEvent *event);
 void tab_client_free_event_strings(TabEvent *event);
 
@@ -495,6 +619,22 @@ bool tab_client_request_buffer(
     int acquire_fence_fd
 );
 
+/* Reallocates the monitor's buffers at width x height and relinks them, e.g. to render below the
+   monitor's mode. Buffer indices keep their meaning; a frame acquired but not requested is
+   dropped. */
+bool tab_client_resize_output(
+    TabClientHandle *handle,
+    const char *monitor_id,
+    int32_t width,
+    int32_t height
+);
+
+/* Returns an acquired frame unsubmitted because nothing changed; compare
+ * tab_client_frame_hash() of successive frames to decide. */
+bool tab_client_skip_frame(TabClientHandle *handle, const char *monitor_id);
+uint64_t tab_client_frame_hash(const void *data, size_t len);
+TabFrameStats tab_client_get_frame_stats(TabClientHandle *handle);
+
 int tab_client_get_swap_fd(TabClientHandle *handle);
 int tab_client_get_socket_fd(TabClientHandle *handle);
 int tab_client_drm_fd(TabClientHandle *handle);
@@ -0,0 +1,19 @@
+use std::os::fd::RawFd;
+
+use crate::{error::TabClientError, monitor::MonitorState, swapchain::TabBuffer};
+
+/// Allocates the two dma-bufs behind each [`TabSwapchain`](crate::TabSwapchain).
+///
+/// With the default `gbm` feature, [`TabClient`](crate::TabClient) allocates through GBM on a render
+/// node. Vulkan or software clients can install their own backend with
+/// [`TabClient::set_render_backend`](crate::TabClient::set_render_backend), e.g. one that exports
+/// `VkImage` memory with `vkGetMemoryFdKHR` and wraps it with [`TabBuffer::from_dmabuf`].
+pub trait RenderBackend {
+	/// Allocates both buffers for `monitor` at its current size.
+	fn allocate_buffers(&self, monitor: &MonitorState) -> Result<[TabBuffer; 2], TabClientError>;
+
+	/// Render node the buffers come from, for clients that poll it. `-1` if there is none.
+	fn drm_fd(&self) -> RawFd {
+		-1
+	}
+}
@@ -5,7 +5,10 @@ use std::{
 	collections::{HashMap, VecDeque},
 	env,
 	ffi::{CStr, CString},
-	os::raw::{c_char, c_int},
+	os::{
+		fd::BorrowedFd,
+		raw::{c_char, c_int},
+	},
 	ptr,
 	rc::Rc,
 	time::Duration,
@@ -18,10 +21,12 @@ use crate::{
 	events::{InputEvent, MonitorEvent, RenderEvent, SessionEvent},
 	monitor::MonitorState,
 	swapchain::TabSwapchain,
+	wakeup::Wakeup,
 };
+use tab_protocol::transport::TransportAddr;
 use tab_protocol::{
-	AxisOrientation, AxisSource, BufferIndex, ButtonState, InputEventPayload, KeyState, SwitchState,
-	SwitchType, TipState,
+	AxisOrientation, AxisSource, BufferIndex, ButtonState, FocusTarget, InputEventPayload, KeyState,
+	PerformanceWarningKind, PresentMode, ShortcutModifier, SwitchState, SwitchType, TipState,
 };
 
 #[repr(C)]
@@ -44,6 +49,13 @@ pub struct TabFrameTarget {
 	pub dmabuf: TabDmabuf,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TabFrameStats {
+	pub frames_skipped: u64,
+	pub frames_dropped: u64,
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
 pub struct TabBufferRelease {
@@ -60,6 +72,42 @@ pub struct TabMonitorInfo {
 	pub height: i32,
 	pub refresh_rate: i32,
 	pub name: *mut c_char,
+	pub manufacturer: *mut c_char,
+	pub model: *mut c_char,
+	pub serial: *mut c_char,
+	pub width_mm: u32,
+	pub height_mm: u32,
+	pub non_desktop: bool,
+	pub panel_orientation: TabPanelOrientation,
+}
+
+impl TabMonitorInfo {
+	fn empty() -> Self {
+		Self {
+			id: ptr::null_mut(),
+			width: 0,
+			height: 0,
+			refresh_rate: 0,
+			name: ptr::null_mut(),
+			manufacturer: ptr::null_mut(),
+			model: ptr::null_mut(),
+			serial: ptr::null_mut(),
+			width_mm: 0,
+			height_mm: 0,
+			non_desktop: false,
+			panel_orientation: TabPanelOrientation::TAB_PANEL_ORIENTATION_UNKNOWN,
+		}
+	}
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum TabPanelOrientation {
+	TAB_PANEL_ORIENTATION_UNKNOWN = 0,
+	TAB_PANEL_ORIENTATION_NORMAL = 1,
+	TAB_PANEL_ORIENTATION_UPSIDE_DOWN = 2,
+	TAB_PANEL_ORIENTATION_LEFT_SIDE_UP = 3,
+	TAB_PANEL_ORIENTATION_RIGHT_SIDE_UP = 4,
 }
 
 #[repr(C)]
@@ -89,6 +137,54 @@ pub enum TabEventType {
 	TAB_EVENT_SESSION_AWAKE = 6,
 	TAB_EVENT_SESSION_SLEEP = 7,
 	TAB_EVENT_SESSION_ACTIVE = 8,
+	TAB_EVENT_FOCUS_IN = 9,
+	TAB_EVENT_FOCUS_OUT = 10,
+	TAB_EVENT_POINTER_LOCK = 11,
+	TAB_EVENT_SHORTCUT_TRIGGERED = 12,
+	TAB_EVENT_MONITOR_CHANGED = 13,
+	TAB_EVENT_SPLASH_ENDED = 14,
+	TAB_EVENT_SESSION_VISIBILITY = 15,
+	TAB_EVENT_RESUMED = 16,
+	TAB_EVENT_SESSION_INACTIVE = 17,
+	TAB_EVENT_PERFORMANCE_WARNING = 18,
+	TAB_EVENT_BUFFERS_INVALIDATED = 19,
+}
+
+pub const TAB_SHORTCUT_MOD_CTRL: u32 = 1 << 0;
+pub const TAB_SHORTCUT_MOD_ALT: u32 = 1 << 1;
+pub const TAB_SHORTCUT_MOD_SHIFT: u32 = 1 << 2;
+pub const TAB_SHORTCUT_MOD_SUPER: u32 = 1 << 3;
+
+pub const TAB_PRESENT_FIFO: u32 = 0;
+pub const TAB_PRESENT_MAILBOX: u32 = 1;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum TabFocusTarget {
+	TAB_FOCUS_POINTER = 0,
+	TAB_FOCUS_KEYBOARD = 1,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TabFocusChange {
+	pub session_id: *mut c_char,
+	pub target: TabFocusTarget,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum TabPerformanceWarningKind {
+	TAB_PERFORMANCE_WARNING_STATIC_CONTENT = 0,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TabPerformanceWarning {
+	pub monitor_id: *mut c_char,
+	pub kind: TabPerformanceWarningKind,
+	pub duration_ms: u64,
+	pub frames: u32,
 }
 
 #[repr(C)]
@@ -96,6 +192,7 @@ pub enum TabEventType {
 pub enum TabSessionRole {
 	TAB_SESSION_ROLE_ADMIN = 0,
 	TAB_SESSION_ROLE_SESSION = 1,
+	TAB_SESSION_ROLE_OBSERVER = 2,
 }
 
 #[repr(C)]
@@ -116,6 +213,14 @@ pub struct TabSessionInfo {
 	pub state: TabSessionLifecycle,
 }
 
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct TabSessionListEntry {
+	pub session: TabSessionInfo,
+	pub attached: bool,
+	pub visible: bool,
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct TabEvent {
@@ -129,12 +234,20 @@ pub union TabEventData {
 	pub buffer_released: TabBufferRelease,
 	pub monitor_added: TabMonitorInfo,
 	pub monitor_removed: TabMonitorRemoved,
+	pub monitor_changed: TabMonitorInfo,
 	pub session_state: TabSessionInfo,
 	pub session_awake: *mut c_char,
 	pub session_sleep: *mut c_char,
 	pub session_active: *mut c_char,
+	pub session_inactive: *mut c_char,
 	pub input: TabInputEvent,
 	pub session_created_token: *mut c_char,
+	pub focus: TabFocusChange,
+	pub pointer_locked: bool,
+	pub shortcut_id: *mut c_char,
+	pub session_visible: bool,
+	pub performance_warning: TabPerformanceWarning,
+	pub buffers_invalidated: *mut c_char,
 }
 
 #[repr(C)]
@@ -216,6 +329,8 @@ pub struct TabInputKey {
 	pub time_usec: u64,
 	pub key: u32,
 	pub state: u32,
+	/// Sent again for a key held down.
+	pub repeat: bool,
 }
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -459,11 +574,32 @@ enum PendingEvent {
 	BufferReleased(String, BufferIndex, Option<c_int>),
 	MonitorAdded(MonitorState),
 	MonitorRemoved { monitor_id: String, name: String },
+	MonitorChanged(MonitorState),
+	FramebufferLinkFailed(String),
+	RelinkRequested(String),
+	BuffersInvalidated(String),
+	PerformanceWarning {
+		monitor_id: String,
+		kind: PerformanceWarningKind,
+		duration_ms: u64,
+		frames: u32,
+	},
 	SessionState(tab_protocol::SessionInfo),
 	SessionActive(String),
+	SessionInactive(String),
 	SessionAwake(String),
 	SessionSleep(String),
+	SessionVisibility(bool),
 	SessionCreated(String),
+	Focus {
+		session_id: String,
+		target: FocusTarget,
+		focused: bool,
+	},
+	PointerLock(bool),
+	SplashEnded,
+	Resumed,
+	ShortcutTriggered(String),
 	Input(InputEventPayload),
 }
 
@@ -473,6 +609,8 @@ pub struct TabClientHandle {
 	monitors: HashMap<String, MonitorEntry>,
 	monitor_order: Vec<String>,
 	last_error: Option<CString>,
+	/// Created by the first `tab_client_get_wakeup_fd`.
+	wakeup: Option<Wakeup>,
 }
 
 impl TabClientHandle {
@@ -491,6 +629,9 @@ impl TabClientHandle {
 							name: name.clone(),
 						})
 					}
+					MonitorEvent::Changed(state) => {
+						guard.push_back(PendingEvent::MonitorChanged(state.clone()))
+					}
 				}
 			});
 		}
@@ -508,6 +649,30 @@ impl TabClientHandle {
 						*buffer,
 						*release_fence_fd,
 					)),
+					RenderEvent::FramebufferLinkFailed { monitor_id, .. } => {
+						guard.push_back(PendingEvent::FramebufferLinkFailed(monitor_id.clone()))
+					}
+					RenderEvent::RelinkRequested { monitor_id } => {
+						guard.push_back(PendingEvent::RelinkRequested(monitor_id.clone()))
+					}
+					RenderEvent::BuffersInvalidated { monitor_ids } => guard.extend(
+						monitor_ids
+							.iter()
+							.map(|monitor_id| PendingEvent::BuffersInvalidated(monitor_id.clone())),
+					),
+					// Counted in `tab_client_get_frame_stats` instead.
+					RenderEvent::FramesDropped { .. } => {}
+					RenderEvent::PerformanceWarning {
+						monitor_id,
+						kind,
+						duration_ms,
+						frames,
+					} => guard.push_back(PendingEvent::PerformanceWarning {
+						monitor_id: monitor_id.clone(),
+						kind: *kind,
+						duration_ms: *duration_ms,
+						frames: *frames,
+					}),
 				}
 			});
 		}
@@ -519,18 +684,49 @@ impl TabClientHandle {
 					SessionEvent::Active(session_id) => {
 						guard.push_back(PendingEvent::SessionActive(session_id.clone()))
 					}
+					SessionEvent::Inactive(session_id) => {
+						guard.push_back(PendingEvent::SessionInactive(session_id.clone()))
+					}
 					SessionEvent::Awake(session_id) => {
 						guard.push_back(PendingEvent::SessionAwake(session_id.clone()))
 					}
 					SessionEvent::Sleep(session_id) => {
 						guard.push_back(PendingEvent::SessionSleep(session_id.clone()))
 					}
+					SessionEvent::Visibility { visible } => {
+						guard.push_back(PendingEvent::SessionVisibility(*visible))
+					}
 					SessionEvent::State(session) => {
 						guard.push_back(PendingEvent::SessionState(session.clone()))
 					}
 					SessionEvent::Created { token, .. } => {
 						guard.push_back(PendingEvent::SessionCreated(token.clone()))
 					}
+					SessionEvent::FocusIn { session_id, target } => {
+						guard.push_back(PendingEvent::Focus {
+							session_id: session_id.clone(),
+							target: *target,
+							focused: true,
+						})
+					}
+					SessionEvent::FocusOut { session_id, target } => {
+						guard.push_back(PendingEvent::Focus {
+							session_id: session_id.clone(),
+							target: *target,
+							focused: false,
+						})
+					}
+					SessionEvent::PointerLock { locked } => {
+						guard.push_back(PendingEvent::PointerLock(*locked))
+					}
+					SessionEvent::SplashEnded => guard.push_back(PendingEvent::SplashEnded),
+					SessionEvent::Resumed => guard.push_back(PendingEvent::Resumed),
+					// Not exposed over the C ABI.
+					SessionEvent::CompositorHealth(_)
+					| SessionEvent::Unresponsive { .. }
+					| SessionEvent::Crashed { .. }
+					| SessionEvent::LidClosed { .. }
+					| SessionEvent::TabletMode { .. } => {}
 				}
 			});
 		}
@@ -540,6 +736,9 @@ impl TabClientHandle {
 				let mut guard = q.borrow_mut();
 				match evt {
 					InputEvent::Event(event) => guard.push_back(PendingEvent::Input(event.clone())),
+					InputEvent::ShortcutTriggered { id } => {
+						guard.push_back(PendingEvent::ShortcutTriggered(id.clone()))
+					}
 				}
 			});
 		}
@@ -550,6 +749,7 @@ impl TabClientHandle {
 			monitors: HashMap::new(),
 			monitor_order: Vec::new(),
 			last_error: None,
+			wakeup: None,
 		};
 
 		let monitor_ids: Vec<String> = handle
@@ -584,11 +784,37 @@ impl TabClientHandle {
 		Ok(())
 	}
 
+	/// Resizes the monitor's swapchain to its new mode, which relinks it.
+	fn replace_monitor(&mut self, state: MonitorState) -> Result<(), TabClientError> {
+		let id = state.info.id.clone();
+		let Some(entry) = self.monitors.get_mut(&id) else {
+			return self.insert_monitor(state);
+		};
+		entry.swapchain = self
+			.client
+			.resize_output(&id, state.info.width, state.info.height)?;
+		entry.state = state;
+		entry.pending = None;
+		Ok(())
+	}
+
 	fn remove_monitor(&mut self, id: &str) {
 		self.monitors.remove(id);
 		self.monitor_order.retain(|item| item != id);
 	}
 
+	/// Reads what arrived without blocking. Returns how many events are queued.
+	fn dispatch_pending(&mut self) -> usize {
+		if let Err(err) = self.client.dispatch_events() {
+			self.record_error(err);
+		}
+		let queued = self.events.borrow().len();
+		if let Some(wakeup) = &self.wakeup {
+			wakeup.set_pending(queued > 0);
+		}
+		queued
+	}
+
 	fn record_error(&mut self, err: impl ToString) {
 		if let Ok(cs) = CString::new(err.to_string()) {
 			self.last_error = Some(cs);
@@ -602,6 +828,22 @@ fn dup_string(s: &str) -> *mut c_char {
 		.unwrap_or(ptr::null_mut())
 }
 
+/// NULL for `None`.
+fn dup_optional_string(s: Option<&str>) -> *mut c_char {
+	s.map(dup_string).unwrap_or(ptr::null_mut())
+}
+
+/// Frees a string from [`dup_string`] and clears `field`, which may already be NULL.
+///
+/// # Safety
+/// `field` must be NULL or come from [`dup_string`] and not have been freed.
+unsafe fn free_string(field: &mut *mut c_char) {
+	if !field.is_null() {
+		drop(unsafe { CString::from_raw(*field) });
+		*field = ptr::null_mut();
+	}
+}
+
 fn cstring_to_string(ptr: *const c_char) -> Option<String> {
 	if ptr.is_null() {
 		return None;
@@ -623,6 +865,26 @@ fn monitor_info_to_c(state: &MonitorState) -> TabMonitorInfo {
 		height: state.info.height,
 		refresh_rate: state.info.refresh_rate,
 		name: dup_string(&state.info.name),
+		manufacturer: dup_optional_string(state.info.manufacturer.as_deref()),
+		model: dup_optional_string(state.info.model.as_deref()),
+		serial: dup_optional_string(state.info.serial.as_deref()),
+		width_mm: state.info.width_mm.unwrap_or(0),
+		height_mm: state.info.height_mm.unwrap_or(0),
+		non_desktop: state.info.non_desktop,
+		panel_orientation: tab_panel_orientation(state.info.panel_orientation),
+	}
+}
+
+fn tab_panel_orientation(
+	orientation: Option<tab_protocol::PanelOrientation>,
+) -> TabPanelOrientation {
+	use tab_protocol::PanelOrientation;
+	match orientation {
+		None => TabPanelOrientation::TAB_PANEL_ORIENTATION_UNKNOWN,
+		Some(PanelOrientation::Normal) => TabPanelOrientation::TAB_PANEL_ORIENTATION_NORMAL,
+		Some(PanelOrientation::UpsideDown) => TabPanelOrientation::TAB_PANEL_ORIENTATION_UPSIDE_DOWN,
+		Some(PanelOrientation::LeftSideUp) => TabPanelOrientation::TAB_PANEL_ORIENTATION_LEFT_SIDE_UP,
+		Some(PanelOrientation::RightSideUp) => TabPanelOrientation::TAB_PANEL_ORIENTATION_RIGHT_SIDE_UP,
 	}
 }
 
@@ -630,6 +892,7 @@ fn tab_session_role(role: tab_protocol::SessionRole) -> TabSessionRole {
 	match role {
 		tab_protocol::SessionRole::Admin => TabSessionRole::TAB_SESSION_ROLE_ADMIN,
 		tab_protocol::SessionRole::Session => TabSessionRole::TAB_SESSION_ROLE_SESSION,
+		tab_protocol::SessionRole::Observer => TabSessionRole::TAB_SESSION_ROLE_OBSERVER,
 	}
 }
 
@@ -769,6 +1032,7 @@ fn tab_input_from_payload(payload: &InputEventPayload) -> TabInputEvent {
 			y,
 			x_transformed,
 			y_transformed,
+			..
 		} => TabInputEvent {
 			kind: TabInputEventKind::TAB_INPUT_KIND_POINTER_MOTION_ABSOLUTE,
 			data: TabInputEventData {
@@ -823,6 +1087,7 @@ fn tab_input_from_payload(payload: &InputEventPayload) -> TabInputEvent {
 			time_usec,
 			key,
 			state,
+			repeat,
 		} => TabInputEvent {
 			kind: TabInputEventKind::TAB_INPUT_KIND_KEY,
 			data: TabInputEventData {
@@ -831,6 +1096,7 @@ fn tab_input_from_payload(payload: &InputEventPayload) -> TabInputEvent {
 					time_usec: *time_usec,
 					key: *key,
 					state: tab_key_state(state.clone()),
+					repeat: *repeat,
 				},
 			},
 		},
@@ -1173,8 +1439,14 @@ pub unsafe extern "C" fn tab_client_connect(
 		None => return ptr::null_mut(),
 	};
 	let mut config = TabClientConfig::new(token);
-	if let Some(path) = cstring_to_string(socket_path) {
-		config = config.socket_path(path);
+	if let Some(address) = cstring_to_string(socket_path) {
+		match address.parse::<TransportAddr>() {
+			Ok(address) => config = config.address(address),
+			Err(err) => {
+				eprintln!("tab_client_connect failed: {err}");
+				return ptr::null_mut();
+			}
+		}
 	}
 	let client = match TabClient::connect(config) {
 		Ok(client) => client,
@@ -1277,36 +1549,18 @@ pub unsafe extern "C" fn tab_client_get_monitor_info(
 		let handle = match handle.as_ref() {
 			Some(h) => h,
 			None => {
-				return TabMonitorInfo {
-					id: ptr::null_mut(),
-					width: 0,
-					height: 0,
-					refresh_rate: 0,
-					name: ptr::null_mut(),
-				};
+				return TabMonitorInfo::empty();
 			}
 		};
 		let id = match cstring_to_string(monitor_id) {
 			Some(id) => id,
 			None => {
-				return TabMonitorInfo {
-					id: ptr::null_mut(),
-					width: 0,
-					height: 0,
-					refresh_rate: 0,
-					name: ptr::null_mut(),
-				};
+				return TabMonitorInfo::empty();
 			}
 		};
 		match handle.monitors.get(&id) {
 			Some(entry) => monitor_info_to_c(&entry.state),
-			None => TabMonitorInfo {
-				id: ptr::null_mut(),
-				width: 0,
-				height: 0,
-				refresh_rate: 0,
-				name: ptr::null_mut(),
-			},
+			None => TabMonitorInfo::empty(),
 		}
 	}
 }
@@ -1317,13 +1571,17 @@ pub unsafe extern "C" fn tab_client_free_monitor_info(info: *mut TabMonitorInfo)
 		if info.is_null() {
 			return;
 		}
-		if !(*info).id.is_null() {
-			drop(CString::from_raw((*info).id));
-			(*info).id = ptr::null_mut();
-		}
-		if !(*info).name.is_null() {
-			drop(CString::from_raw((*info).name));
-			(*info).name = ptr::null_mut();
+		for field in [
+			&mut (*info).id,
+			&mut (*info).name,
+			&mut (*info).manufacturer,
+			&mut (*info).model,
+			&mut (*info).serial,
+		] {
+			if !field.is_null() {
+				drop(CString::from_raw(*field));
+				*field = ptr::null_mut();
+			}
 		}
 	}
 }
@@ -1346,6 +1604,84 @@ pub unsafe extern "C" fn tab_client_poll_events(handle: *mut TabClientHandle) ->
 	}
 }
 
+/// One fd that is readable whenever the socket or render node is, or events are still queued.
+/// Owned by the handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_get_wakeup_fd(handle: *mut TabClientHandle) -> c_int {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return -1;
+		};
+		let wakeup = match handle.wakeup.take() {
+			Some(wakeup) => wakeup,
+			None => match Wakeup::new(&handle.client.poll_fds()) {
+				Ok(wakeup) => wakeup,
+				Err(err) => {
+					handle.record_error(err);
+					return -1;
+				}
+			},
+		};
+		wakeup.set_pending(!handle.events.borrow().is_empty());
+		handle.wakeup.insert(wakeup).fd()
+	}
+}
+
+/// What to call once the wakeup fd is readable; like `tab_client_poll_events`, but also leaves
+/// the wakeup fd readable only while events are queued.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_dispatch_pending(handle: *mut TabClientHandle) -> usize {
+	unsafe { handle.as_mut().map_or(0, TabClientHandle::dispatch_pending) }
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_wait_for_event(
+	handle: *mut TabClientHandle,
+	timeout_ms: u32,
+) -> usize {
+	unsafe {
+		let handle = match handle.as_mut() {
+			Some(h) => h,
+			None => return 0,
+		};
+		if handle.events.borrow().is_empty() {
+			let events = Rc::clone(&handle.events);
+			let timeout = Duration::from_millis(timeout_ms.into());
+			if let Err(err) = handle
+				.client
+				.wait_for_event(timeout, |_| !events.borrow().is_empty())
+			{
+				handle.record_error(err);
+				return 0;
+			}
+		}
+		handle.events.borrow().len()
+	}
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_wait_for_monitor(
+	handle: *mut TabClientHandle,
+	timeout_ms: u32,
+) -> bool {
+	unsafe {
+		let handle = match handle.as_mut() {
+			Some(h) => h,
+			None => return false,
+		};
+		match handle
+			.client
+			.wait_for_monitor(Duration::from_millis(timeout_ms.into()))
+		{
+			Ok(monitor) => monitor.is_some(),
+			Err(err) => {
+				handle.record_error(err);
+				false
+			}
+		}
+	}
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_next_event(
 	handle: *mut TabClientHandle,
@@ -1400,6 +1736,70 @@ pub unsafe extern "C" fn tab_client_next_event(
 					true
 				}
 			}
+			PendingEvent::MonitorChanged(state) => {
+				if let Err(err) = handle.replace_monitor(state.clone()) {
+					handle.record_error(err);
+					handle
+						.events
+						.borrow_mut()
+						.push_front(PendingEvent::MonitorChanged(state));
+					false
+				} else {
+					(*event).event_type = TabEventType::TAB_EVENT_MONITOR_CHANGED;
+					(*event).data.monitor_changed = monitor_info_to_c(&state);
+					true
+				}
+			}
+			// Reallocated in a format shift listed; to the app that looks like a mode change.
+			PendingEvent::FramebufferLinkFailed(monitor_id) => {
+				let Some(state) = handle.client.monitor(&monitor_id).cloned() else {
+					return false;
+				};
+				if let Err(err) = handle.replace_monitor(state.clone()) {
+					handle.record_error(err);
+					false
+				} else {
+					(*event).event_type = TabEventType::TAB_EVENT_MONITOR_CHANGED;
+					(*event).data.monitor_changed = monitor_info_to_c(&state);
+					true
+				}
+			}
+			// Handled here, the app never sees it: link the same swapchain again and move on.
+			PendingEvent::RelinkRequested(monitor_id) => {
+				let result = handle
+					.monitors
+					.get(&monitor_id)
+					.map(|entry| handle.client.framebuffer_link(&entry.swapchain));
+				if let Some(Err(err)) = result {
+					handle.record_error(err);
+				}
+				tab_client_next_event(handle, event)
+			}
+			// Relinked by the client already, the app only has to redraw.
+			PendingEvent::BuffersInvalidated(monitor_id) => {
+				(*event).event_type = TabEventType::TAB_EVENT_BUFFERS_INVALIDATED;
+				(*event).data.buffers_invalidated = dup_string(&monitor_id);
+				true
+			}
+			PendingEvent::PerformanceWarning {
+				monitor_id,
+				kind,
+				duration_ms,
+				frames,
+			} => {
+				(*event).event_type = TabEventType::TAB_EVENT_PERFORMANCE_WARNING;
+				(*event).data.performance_warning = TabPerformanceWarning {
+					monitor_id: dup_string(&monitor_id),
+					kind: match kind {
+						PerformanceWarningKind::StaticContent => {
+							TabPerformanceWarningKind::TAB_PERFORMANCE_WARNING_STATIC_CONTENT
+						}
+					},
+					duration_ms,
+					frames,
+				};
+				true
+			}
 			PendingEvent::SessionAwake(session_id) => {
 				(*event).event_type = TabEventType::TAB_EVENT_SESSION_AWAKE;
 				(*event).data.session_awake = dup_string(&session_id);
@@ -1410,11 +1810,21 @@ pub unsafe extern "C" fn tab_client_next_event(
 				(*event).data.session_active = dup_string(&session_id);
 				true
 			}
+			PendingEvent::SessionInactive(session_id) => {
+				(*event).event_type = TabEventType::TAB_EVENT_SESSION_INACTIVE;
+				(*event).data.session_inactive = dup_string(&session_id);
+				true
+			}
 			PendingEvent::SessionSleep(session_id) => {
 				(*event).event_type = TabEventType::TAB_EVENT_SESSION_SLEEP;
 				(*event).data.session_sleep = dup_string(&session_id);
 				true
 			}
+			PendingEvent::SessionVisibility(visible) => {
+				(*event).event_type = TabEventType::TAB_EVENT_SESSION_VISIBILITY;
+				(*event).data.session_visible = visible;
+				true
+			}
 			PendingEvent::SessionState(session) => {
 				(*event).event_type = TabEventType::TAB_EVENT_SESSION_STATE;
 				(*event).data.session_state = tab_session_info_to_c(&session);
@@ -1425,6 +1835,43 @@ pub unsafe extern "C" fn tab_client_next_event(
 				(*event).data.session_created_token = dup_string(&token);
 				true
 			}
+			PendingEvent::Focus {
+				session_id,
+				target,
+				focused,
+			} => {
+				(*event).event_type = if focused {
+					TabEventType::TAB_EVENT_FOCUS_IN
+				} else {
+					TabEventType::TAB_EVENT_FOCUS_OUT
+				};
+				(*event).data.focus = TabFocusChange {
+					session_id: dup_string(&session_id),
+					target: match target {
+						FocusTarget::Pointer => TabFocusTarget::TAB_FOCUS_POINTER,
+						FocusTarget::Keyboard => TabFocusTarget::TAB_FOCUS_KEYBOARD,
+					},
+				};
+				true
+			}
+			PendingEvent::PointerLock(locked) => {
+				(*event).event_type = TabEventType::TAB_EVENT_POINTER_LOCK;
+				(*event).data.pointer_locked = locked;
+				true
+			}
+			PendingEvent::SplashEnded => {
+				(*event).event_type = TabEventType::TAB_EVENT_SPLASH_ENDED;
+				true
+			}
+			PendingEvent::Resumed => {
+				(*event).event_type = TabEventType::TAB_EVENT_RESUMED;
+				true
+			}
+			PendingEvent::ShortcutTriggered(id) => {
+				(*event).event_type = TabEventType::TAB_EVENT_SHORTCUT_TRIGGERED;
+				(*event).data.shortcut_id = dup_string(&id);
+				true
+			}
 			PendingEvent::Input(input) => {
 				(*event).event_type = TabEventType::TAB_EVENT_INPUT;
 				(*event).data.input = tab_input_from_payload(&input);
@@ -1442,63 +1889,55 @@ pub unsafe extern "C" fn tab_client_free_event_strings(event: *mut TabEvent) {
 		}
 		match (*event).event_type {
 			TabEventType::TAB_EVENT_BUFFER_RELEASED => {
-				if !(*event).data.buffer_released.monitor_id.is_null() {
-					drop(CString::from_raw((*event).data.buffer_released.monitor_id));
-					(*event).data.buffer_released.monitor_id = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.buffer_released.monitor_id);
 				if (*event).data.buffer_released.release_fence_fd >= 0 {
 					libc::close((*event).data.buffer_released.release_fence_fd);
 					(*event).data.buffer_released.release_fence_fd = -1;
 				}
 			}
 			TabEventType::TAB_EVENT_MONITOR_REMOVED => {
-				if !(*event).data.monitor_removed.monitor_id.is_null() {
-					drop(CString::from_raw((*event).data.monitor_removed.monitor_id));
-					(*event).data.monitor_removed.monitor_id = ptr::null_mut();
-				}
-				if !(*event).data.monitor_removed.name.is_null() {
-					drop(CString::from_raw((*event).data.monitor_removed.name));
-					(*event).data.monitor_removed.name = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.monitor_removed.monitor_id);
+				free_string(&mut (*event).data.monitor_removed.name);
 			}
 			TabEventType::TAB_EVENT_SESSION_CREATED => {
-				if !(*event).data.session_created_token.is_null() {
-					drop(CString::from_raw((*event).data.session_created_token));
-					(*event).data.session_created_token = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.session_created_token);
 			}
 			TabEventType::TAB_EVENT_SESSION_AWAKE => {
-				if !(*event).data.session_awake.is_null() {
-					drop(CString::from_raw((*event).data.session_awake));
-					(*event).data.session_awake = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.session_awake);
 			}
 			TabEventType::TAB_EVENT_SESSION_SLEEP => {
-				if !(*event).data.session_sleep.is_null() {
-					drop(CString::from_raw((*event).data.session_sleep));
-					(*event).data.session_sleep = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.session_sleep);
 			}
 			TabEventType::TAB_EVENT_SESSION_ACTIVE => {
-				if !(*event).data.session_active.is_null() {
-					drop(CString::from_raw((*event).data.session_active));
-					(*event).data.session_active = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.session_active);
+			}
+			TabEventType::TAB_EVENT_SESSION_INACTIVE => {
+				free_string(&mut (*event).data.session_inactive);
+			}
+			TabEventType::TAB_EVENT_PERFORMANCE_WARNING => {
+				free_string(&mut (*event).data.performance_warning.monitor_id);
+			}
+			TabEventType::TAB_EVENT_BUFFERS_INVALIDATED => {
+				free_string(&mut (*event).data.buffers_invalidated);
+			}
+			TabEventType::TAB_EVENT_SHORTCUT_TRIGGERED => {
+				free_string(&mut (*event).data.shortcut_id);
+			}
+			TabEventType::TAB_EVENT_FOCUS_IN | TabEventType::TAB_EVENT_FOCUS_OUT => {
+				free_string(&mut (*event).data.focus.session_id);
 			}
 			TabEventType::TAB_EVENT_SESSION_STATE => {
-				if !(*event).data.session_state.id.is_null() {
-					drop(CString::from_raw((*event).data.session_state.id));
-					(*event).data.session_state.id = ptr::null_mut();
-				}
-				if !(*event).data.session_state.display_name.is_null() {
-					drop(CString::from_raw((*event).data.session_state.display_name));
-					(*event).data.session_state.display_name = ptr::null_mut();
-				}
+				free_string(&mut (*event).data.session_state.id);
+				free_string(&mut (*event).data.session_state.display_name);
 			}
 			TabEventType::TAB_EVENT_MONITOR_ADDED => {
 				let mut info = (*event).data.monitor_added;
 				tab_client_free_monitor_info(&mut info as *mut _);
 			}
+			TabEventType::TAB_EVENT_MONITOR_CHANGED => {
+				let mut info = (*event).data.monitor_changed;
+				tab_client_free_monitor_info(&mut info as *mut _);
+			}
 			_ => {}
 		}
 	}
@@ -1546,6 +1985,40 @@ pub unsafe extern "C" fn tab_client_acquire_frame(
 	}
 }
 
+/// Reallocates the monitor's swapchain at `width`x`height` and relinks it, dropping a frame
+/// acquired but not requested yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_resize_output(
+	handle: *mut TabClientHandle,
+	monitor_id: *const c_char,
+	width: i32,
+	height: i32,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		let Some(id) = cstring_to_string(monitor_id) else {
+			return false;
+		};
+		let Some(entry) = handle.monitors.get_mut(&id) else {
+			handle.record_error(TabClientError::UnknownMonitor(id));
+			return false;
+		};
+		match handle.client.resize_output(&id, width, height) {
+			Ok(swapchain) => {
+				entry.swapchain = swapchain;
+				entry.pending = None;
+				true
+			}
+			Err(err) => {
+				handle.record_error(err);
+				false
+			}
+		}
+	}
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_request_buffer(
 	handle: *mut TabClientHandle,
@@ -1569,8 +2042,15 @@ pub unsafe extern "C" fn tab_client_request_buffer(
 			Some(idx) => idx,
 			None => return false,
 		};
+		// The caller keeps its fence FD, the frame sends a duplicate.
 		let acquire_fence = if acquire_fence_fd >= 0 {
-			Some(acquire_fence_fd)
+			match BorrowedFd::borrow_raw(acquire_fence_fd).try_clone_to_owned() {
+				Ok(fd) => Some(fd),
+				Err(_) => {
+					entry.pending = Some(buffer);
+					return false;
+				}
+			}
 		} else {
 			None
 		};
@@ -1592,6 +2072,59 @@ pub unsafe extern "C" fn tab_client_request_buffer(
 	}
 }
 
+/// Gives back the frame taken by `tab_client_acquire_frame` without submitting it, because its
+/// contents did not change. Shift is told how many frames were skipped.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_skip_frame(
+	handle: *mut TabClientHandle,
+	monitor_id: *const c_char,
+) -> bool {
+	unsafe {
+		let handle = match handle.as_mut() {
+			Some(h) => h,
+			None => return false,
+		};
+		let id = match cstring_to_string(monitor_id) {
+			Some(id) => id,
+			None => return false,
+		};
+		let entry = match handle.monitors.get_mut(&id) {
+			Some(entry) => entry,
+			None => return false,
+		};
+		if entry.pending.take().is_none() {
+			return false;
+		}
+		entry.swapchain.rollback();
+		if let Err(err) = handle.client.skip_frame(&id) {
+			handle.record_error(err.to_string());
+			return false;
+		}
+		true
+	}
+}
+
+/// Frames skipped by this client and dropped by shift since it connected.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_get_frame_stats(handle: *mut TabClientHandle) -> TabFrameStats {
+	let Some(handle) = (unsafe { handle.as_ref() }) else {
+		return TabFrameStats::default();
+	};
+	let stats = handle.client.frame_stats();
+	TabFrameStats {
+		frames_skipped: stats.frames_skipped,
+		frames_dropped: stats.frames_dropped,
+	}
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_frame_hash(data: *const u8, len: usize) -> u64 {
+	if data.is_null() {
+		return 0;
+	}
+	crate::frame_hash(unsafe { std::slice::from_raw_parts(data, len) })
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_get_server_name(_handle: *mut TabClientHandle) -> *mut c_char {
 	ptr::null_mut()
@@ -1650,6 +2183,109 @@ pub unsafe extern "C" fn tab_client_send_ready(handle: *mut TabClientHandle) ->
 	}
 }
 
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_set_pointer_lock(
+	handle: *mut TabClientHandle,
+	enable: bool,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		if let Err(err) = handle.client.set_pointer_lock(enable) {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
+/// Relinks every monitor's swapchain with the new mode, so call it before the first frame.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_set_present_mode(
+	handle: *mut TabClientHandle,
+	mode: u32,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		let mode = match mode {
+			TAB_PRESENT_FIFO => PresentMode::Fifo,
+			TAB_PRESENT_MAILBOX => PresentMode::Mailbox,
+			other => {
+				handle.record_error(format!("unknown present mode {other}"));
+				return false;
+			}
+		};
+		handle.client.set_present_mode(mode);
+		let mut result = Ok(());
+		for entry in handle.monitors.values() {
+			result = handle.client.framebuffer_link(&entry.swapchain);
+			if result.is_err() {
+				break;
+			}
+		}
+		if let Err(err) = result {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_shortcut_register(
+	handle: *mut TabClientHandle,
+	id: *const c_char,
+	modifiers: u32,
+	key: u32,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		let Some(id) = cstring_to_string(id) else {
+			return false;
+		};
+		let modifiers = [
+			(TAB_SHORTCUT_MOD_CTRL, ShortcutModifier::Ctrl),
+			(TAB_SHORTCUT_MOD_ALT, ShortcutModifier::Alt),
+			(TAB_SHORTCUT_MOD_SHIFT, ShortcutModifier::Shift),
+			(TAB_SHORTCUT_MOD_SUPER, ShortcutModifier::Super),
+		]
+		.into_iter()
+		.filter(|(bit, _)| modifiers & bit != 0)
+		.map(|(_, modifier)| modifier)
+		.collect();
+		if let Err(err) = handle.client.register_shortcut(&id, modifiers, key) {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_shortcut_unregister(
+	handle: *mut TabClientHandle,
+	id: *const c_char,
+) -> bool {
+	unsafe {
+		let Some(handle) = handle.as_mut() else {
+			return false;
+		};
+		let Some(id) = cstring_to_string(id) else {
+			return false;
+		};
+		if let Err(err) = handle.client.unregister_shortcut(&id) {
+			handle.record_error(err);
+			return false;
+		}
+		true
+	}
+}
+
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn tab_client_session_create(
 	handle: *mut TabClientHandle,
@@ -1663,6 +2299,7 @@ pub unsafe extern "C" fn tab_client_session_create(
 		let role = match role {
 			TabSessionRole::TAB_SESSION_ROLE_ADMIN => tab_protocol::SessionRole::Admin,
 			TabSessionRole::TAB_SESSION_ROLE_SESSION => tab_protocol::SessionRole::Session,
+			TabSessionRole::TAB_SESSION_ROLE_OBSERVER => tab_protocol::SessionRole::Observer,
 		};
 		let display_name = cstring_to_string(display_name);
 		if let Err(err) = handle.client.create_session(role, display_name) {
@@ -1696,3 +2333,53 @@ pub unsafe extern "C" fn tab_client_session_switch(
 		true
 	}
 }
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_list_sessions(
+	handle: *mut TabClientHandle,
+	count: *mut usize,
+) -> *mut TabSessionListEntry {
+	unsafe {
+		if let Some(count) = count.as_mut() {
+			*count = 0;
+		}
+		let Some(handle) = handle.as_mut() else {
+			return ptr::null_mut();
+		};
+		let sessions = match handle.client.list_sessions() {
+			Ok(sessions) => sessions,
+			Err(err) => {
+				handle.record_error(err);
+				return ptr::null_mut();
+			}
+		};
+		let entries = sessions
+			.iter()
+			.map(|session| TabSessionListEntry {
+				session: tab_session_info_to_c(session),
+				attached: session.attached.unwrap_or(false),
+				visible: session.visible.unwrap_or(false),
+			})
+			.collect::<Box<[_]>>();
+		if let Some(count) = count.as_mut() {
+			*count = entries.len();
+		}
+		Box::into_raw(entries).cast()
+	}
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn tab_client_free_session_list(
+	sessions: *mut TabSessionListEntry,
+	count: usize,
+) {
+	unsafe {
+		if sessions.is_null() {
+			return;
+		}
+		let mut entries = Box::from_raw(ptr::slice_from_raw_parts_mut(sessions, count));
+		for entry in entries.iter_mut() {
+			tab_client_free_session_info(&mut entry.session);
+		}
+	}
+}
@@ -1,26 +1,37 @@
 use std::path::{Path, PathBuf};
 
-use tab_protocol::DEFAULT_SOCKET_PATH;
+use tab_protocol::transport::TransportAddr;
+use tab_protocol::{DEFAULT_SOCKET_PATH, PresentMode};
 
 /// Builder-style configuration for establishing a Tab connection.
 #[derive(Debug, Clone)]
 pub struct TabClientConfig {
-	socket_path: PathBuf,
+	address: TransportAddr,
 	token: String,
 	render_node: Option<PathBuf>,
+	present_mode: PresentMode,
+	local_key_repeat: bool,
 }
 
 impl TabClientConfig {
 	pub fn new(token: impl Into<String>) -> Self {
 		Self {
-			socket_path: PathBuf::from(DEFAULT_SOCKET_PATH),
+			address: TransportAddr::Unix(PathBuf::from(DEFAULT_SOCKET_PATH)),
 			token: token.into(),
 			render_node: None,
+			present_mode: PresentMode::Fifo,
+			local_key_repeat: false,
 		}
 	}
 
 	pub fn socket_path(mut self, path: impl AsRef<Path>) -> Self {
-		self.socket_path = path.as_ref().into();
+		self.address = TransportAddr::Unix(path.as_ref().into());
+		self
+	}
+
+	/// Connects over any transport, e.g. `tcp:host:port` or `vsock:host:port` from inside a VM.
+	pub fn address(mut self, address: TransportAddr) -> Self {
+		self.address = address;
 		self
 	}
 
@@ -29,15 +40,36 @@ impl TabClientConfig {
 		self
 	}
 
+	/// Queueing policy requested for every swapchain this client links.
+	pub fn present_mode(mut self, mode: PresentMode) -> Self {
+		self.present_mode = mode;
+		self
+	}
+
+	/// The client repeats held keys itself, so shift doesn't send it `repeat` key events. Its
+	/// settings are in [`TabClient::key_repeat`](crate::TabClient::key_repeat).
+	pub fn local_key_repeat(mut self, local: bool) -> Self {
+		self.local_key_repeat = local;
+		self
+	}
+
 	pub fn token(&self) -> &str {
 		&self.token
 	}
 
-	pub fn socket_path_ref(&self) -> &Path {
-		&self.socket_path
+	pub fn address_ref(&self) -> &TransportAddr {
+		&self.address
 	}
 
 	pub fn render_node_path(&self) -> Option<&Path> {
 		self.render_node.as_deref()
 	}
+
+	pub fn present_mode_ref(&self) -> PresentMode {
+		self.present_mode
+	}
+
+	pub fn local_key_repeat_ref(&self) -> bool {
+		self.local_key_repeat
+	}
 }
@@ -1,6 +1,5 @@
 use std::path::PathBuf;
 
-use gbm::InvalidFdError;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -15,6 +14,8 @@ pub enum TabClientError {
 	Auth(String),
 	#[error("server rejected request: {0}")]
 	Server(String),
+	#[error("server shut down: {0}")]
+	ServerShutdown(String),
 	#[error("unexpected message: {0}")]
 	Unexpected(&'static str),
 	#[error("failed to open render node {path}: {source}")]
@@ -28,6 +29,18 @@ pub enum TabClientError {
 	InvalidMonitorDimensions,
 	#[error("unknown monitor: {0}")]
 	UnknownMonitor(String),
+	#[cfg(feature = "gbm")]
 	#[error("failed to export dma-buf fd: {0}")]
-	BufferExport(#[from] InvalidFdError),
+	BufferExport(#[from] gbm::InvalidFdError),
+	#[cfg(feature = "vulkan")]
+	#[error("vulkan error: {0}")]
+	Vulkan(#[from] ash::vk::Result),
+	#[error("no render backend to allocate buffers with")]
+	NoRenderBackend,
+	#[error("render backend allocated format {0:#x}, which shift can't import")]
+	UnsupportedFormat(i32),
+	#[error("the TabClientIo half was dropped")]
+	IoHalfDropped,
+	#[error("render worker failed: {0}")]
+	RenderWorker(String),
 }
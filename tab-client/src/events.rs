@@ -1,6 +1,9 @@
 use crate::MonitorState;
-use std::os::fd::RawFd;
-use tab_protocol::{BufferIndex, InputEventPayload, SessionInfo};
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use tab_protocol::{
+	BufferIndex, CompositorHealthPayload, FocusTarget, FrameDropReason, InputEventPayload,
+	PerformanceWarningKind, SessionInfo,
+};
 
 /// Monitor lifecycle event emitted to listeners.
 #[derive(Debug, Clone)]
@@ -10,6 +13,9 @@ pub enum MonitorEvent {
 		monitor_id: String,
 		name: String,
 	},
+	/// A monitor changed mode. [`crate::TabSwapchain`]s created for it have the old size and
+	/// should be recreated.
+	Changed(MonitorState),
 }
 
 /// Rendering-related notifications.
@@ -20,18 +26,109 @@ pub enum RenderEvent {
 		buffer: BufferIndex,
 		release_fence_fd: Option<RawFd>,
 	},
+	/// shift couldn't import the swapchain linked for `monitor_id`. Recreating it with
+	/// [`crate::TabClient::create_swapchain`] allocates one of `supported_formats`.
+	FramebufferLinkFailed {
+		monitor_id: String,
+		reason: String,
+		supported_formats: Vec<i32>,
+	},
+	/// shift evicted the swapchain linked for `monitor_id` to free GPU memory and handed back
+	/// its buffers. Pass the same swapchain to [`crate::TabClient::framebuffer_link`] before
+	/// requesting another buffer on this monitor.
+	RelinkRequested { monitor_id: String },
+	/// shift lost every import of the swapchains linked for `monitor_ids`, e.g. over suspend. The
+	/// client already linked the same ones again; what they showed is gone, so redraw those
+	/// monitors.
+	BuffersInvalidated { monitor_ids: Vec<String> },
+	/// shift didn't show `count` frames submitted on `monitor_id` during the last second. Frames
+	/// dropped for [`FrameDropReason::Queue`] or [`FrameDropReason::Mailbox`] were rendered faster
+	/// than the monitor refreshes; for [`FrameDropReason::Hidden`] while nothing was on screen.
+	FramesDropped {
+		monitor_id: String,
+		count: u32,
+		reason: FrameDropReason,
+	},
+	/// shift suspects a bug in how frames for `monitor_id` are rendered. For
+	/// [`PerformanceWarningKind::StaticContent`], check that the buffer drawn into is the one
+	/// submitted, and that drawing finished or its fence was passed along.
+	PerformanceWarning {
+		monitor_id: String,
+		kind: PerformanceWarningKind,
+		duration_ms: u64,
+		frames: u32,
+	},
 }
 
 #[derive(Debug, Clone)]
 pub enum SessionEvent {
+	/// The session is active and a frame of it is on screen.
 	Active(String),
+	/// Sent to the previously active session once the session replacing it is on screen.
+	Inactive(String),
 	Awake(String),
 	Sleep(String),
+	/// This session went on or off screen. Hidden sessions get their buffers back slowly, if at
+	/// all, so they may as well stop rendering.
+	Visibility { visible: bool },
 	State(SessionInfo),
 	Created { session: SessionInfo, token: String },
+	FocusIn { session_id: String, target: FocusTarget },
+	FocusOut { session_id: String, target: FocusTarget },
+	PointerLock { locked: bool },
+	/// Admin only: shift's boot splash has faded out and sessions are on screen.
+	SplashEnded,
+	/// The system woke up from suspend. What was on screen may be gone, so redraw.
+	Resumed,
+	/// Admin only, after [`crate::TabClient::subscribe_compositor_health`]: renderer heartbeat.
+	CompositorHealth(CompositorHealthPayload),
+	/// Admin only: a session on screen went `idle_ms` without submitting a frame, or submitted
+	/// again after it.
+	Unresponsive {
+		session_id: String,
+		unresponsive: bool,
+		idle_ms: u64,
+	},
+	/// Admin only: a session's client disconnected. Shift keeps its last frame on screen, dimmed,
+	/// for `grace_ms`, in time to start it again.
+	Crashed {
+		session_id: String,
+		grace_ms: u64,
+	},
+	/// Admin only, after [`crate::TabClient::subscribe_switch_events`]: the laptop lid closed or
+	/// opened.
+	LidClosed { closed: bool },
+	/// Admin only, after [`crate::TabClient::subscribe_switch_events`]: a convertible entered or
+	/// left tablet mode.
+	TabletMode { enabled: bool },
+}
+
+/// Any event a listener could get, as returned by [`crate::TabClient::wait_for_event`].
+#[derive(Debug, Clone)]
+pub enum ClientEvent {
+	Monitor(MonitorEvent),
+	Render(RenderEvent),
+	Session(SessionEvent),
+	Input(InputEvent),
+}
+
+impl ClientEvent {
+	/// Closes the release fence of a `BufferReleased` nobody is going to wait on.
+	pub(crate) fn discard(self) {
+		if let Self::Render(RenderEvent::BufferReleased {
+			release_fence_fd: Some(fd),
+			..
+		}) = self
+		{
+			// SAFETY: every listener gets its own duplicate of the fence.
+			drop(unsafe { OwnedFd::from_raw_fd(fd) });
+		}
+	}
 }
 
 #[derive(Debug, Clone)]
 pub enum InputEvent {
 	Event(InputEventPayload),
+	/// A shortcut registered by this (admin) client was pressed.
+	ShortcutTriggered { id: String },
 }
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use tab_protocol::FramesSkippedPayload;
+
+use crate::MonitorId;
+
+/// Cheap, non-cryptographic hash of frame contents, for skipping frames identical to the last one.
+pub fn frame_hash(bytes: &[u8]) -> u64 {
+	const SEED: u64 = 0xcbf2_9ce4_8422_2325;
+	const MUL: u64 = 0x9e37_79b9_7f4a_7c15;
+	let mut hash = SEED ^ bytes.len() as u64;
+	let mut chunks = bytes.chunks_exact(8);
+	for chunk in &mut chunks {
+		let word = u64::from_le_bytes(chunk.try_into().expect("chunk is 8 bytes"));
+		hash = (hash ^ word).wrapping_mul(MUL).rotate_left(29);
+	}
+	for &byte in chunks.remainder() {
+		hash = (hash ^ byte as u64).wrapping_mul(MUL);
+	}
+	hash
+}
+
+/// Frames of this client that never reached the screen, since it connected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+	/// Not submitted because nothing changed, see `skip_frame`.
+	pub frames_skipped: u64,
+	/// Submitted, but dropped by shift before they were shown, see [`crate::RenderEvent`].
+	pub frames_dropped: u64,
+}
+
+/// Per-monitor bookkeeping behind `request_buffer_if_changed` and `skip_frame`.
+#[derive(Debug, Default)]
+pub(crate) struct FrameSkips {
+	/// Hash of the last frame submitted per monitor.
+	last_hashes: HashMap<MonitorId, u64>,
+	/// Skipped frames not yet reported to shift.
+	skipped: HashMap<MonitorId, u32>,
+	stats: FrameStats,
+}
+
+impl FrameSkips {
+	/// Report skipped frames at least this often while a monitor keeps skipping.
+	const REPORT_BATCH: u32 = 60;
+
+	pub(crate) fn is_unchanged(&self, monitor_id: &str, content_hash: u64) -> bool {
+		self.last_hashes.get(monitor_id) == Some(&content_hash)
+	}
+
+	pub(crate) fn set_last_hash(&mut self, monitor_id: &str, content_hash: u64) {
+		self
+			.last_hashes
+			.insert(monitor_id.to_string(), content_hash);
+	}
+
+	pub(crate) fn forget_last_hash(&mut self, monitor_id: &str) {
+		self.last_hashes.remove(monitor_id);
+	}
+
+	/// Counts a skipped frame, returning a report to send once a batch is full.
+	pub(crate) fn skip(&mut self, monitor_id: &str) -> Option<FramesSkippedPayload> {
+		self.stats.frames_skipped += 1;
+		let count = self.skipped.entry(monitor_id.to_string()).or_default();
+		*count += 1;
+		if *count < Self::REPORT_BATCH {
+			return None;
+		}
+		self.take_report(monitor_id)
+	}
+
+	/// Skipped frames not reported yet, flushed before the monitor's next submit.
+	pub(crate) fn take_report(&mut self, monitor_id: &str) -> Option<FramesSkippedPayload> {
+		let count = self.skipped.remove(monitor_id)?;
+		Some(FramesSkippedPayload {
+			monitor_id: monitor_id.to_string(),
+			count,
+		})
+	}
+
+	/// Counts frames shift reported as dropped.
+	pub(crate) fn dropped(&mut self, count: u32) {
+		self.stats.frames_dropped += u64::from(count);
+	}
+
+	pub(crate) fn stats(&self) -> FrameStats {
+		self.stats
+	}
+
+	/// Drops everything about a monitor that went away or changed mode.
+	pub(crate) fn forget(&mut self, monitor_id: &str) {
+		self.last_hashes.remove(monitor_id);
+		self.skipped.remove(monitor_id);
+	}
+}
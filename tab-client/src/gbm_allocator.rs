@@ -8,11 +8,17 @@ use gbm::{BufferObjectFlags, Device, Format};
 use tab_protocol::BufferIndex;
 
 use crate::{
-	error::TabClientError,
-	monitor::MonitorState,
-	swapchain::{TabBuffer, TabSwapchain},
+	backend::RenderBackend, error::TabClientError, monitor::MonitorState, swapchain::TabBuffer,
 };
 
+/// Formats tried, in order, when shift rejected the default one.
+const FALLBACK_FORMATS: &[Format] = &[
+	Format::Xrgb8888,
+	Format::Argb8888,
+	Format::Xbgr8888,
+	Format::Abgr8888,
+];
+
 const DEFAULT_RENDER_NODES: &[&str] = &[
 	"/dev/dri/renderD128",
 	"/dev/dri/renderD129",
@@ -62,48 +68,58 @@ impl GbmAllocator {
 		)
 	}
 
-	pub fn drm_fd(&self) -> RawFd {
+	fn render_node_candidates(configured: Option<&Path>) -> Vec<PathBuf> {
+		if let Some(path) = configured {
+			vec![path.to_path_buf()]
+		} else if let Ok(env) = std::env::var("TAB_CLIENT_RENDER_NODE") {
+			vec![PathBuf::from(env)]
+		} else {
+			DEFAULT_RENDER_NODES
+				.iter()
+				.map(|p| PathBuf::from(p))
+				.collect()
+		}
+	}
+}
+
+impl RenderBackend for GbmAllocator {
+	fn drm_fd(&self) -> RawFd {
 		self.device.as_raw_fd()
 	}
 
-	pub fn create_swapchain(&self, monitor: &MonitorState) -> Result<TabSwapchain, TabClientError> {
+	fn allocate_buffers(&self, monitor: &MonitorState) -> Result<[TabBuffer; 2], TabClientError> {
 		let width =
 			u32::try_from(monitor.info.width).map_err(|_| TabClientError::InvalidMonitorDimensions)?;
 		let height =
 			u32::try_from(monitor.info.height).map_err(|_| TabClientError::InvalidMonitorDimensions)?;
+		let format = if monitor.accepts_format(self.format as u32 as i32) {
+			self.format
+		} else {
+			FALLBACK_FORMATS
+				.iter()
+				.copied()
+				.find(|format| monitor.accepts_format(*format as u32 as i32))
+				.unwrap_or(self.format)
+		};
 		let bo0 = self
 			.device
-			.create_buffer_object::<()>(width, height, self.format, self.preferred_usage)
+			.create_buffer_object::<()>(width, height, format, self.preferred_usage)
 			.or_else(|_| {
 				self
 					.device
-					.create_buffer_object::<()>(width, height, self.format, self.fallback_usage)
+					.create_buffer_object::<()>(width, height, format, self.fallback_usage)
 			})?;
 		let bo1 = self
 			.device
-			.create_buffer_object::<()>(width, height, self.format, self.preferred_usage)
+			.create_buffer_object::<()>(width, height, format, self.preferred_usage)
 			.or_else(|_| {
 				self
 					.device
-					.create_buffer_object::<()>(width, height, self.format, self.fallback_usage)
+					.create_buffer_object::<()>(width, height, format, self.fallback_usage)
 			})?;
-		let buffers = [
+		Ok([
 			TabBuffer::new(BufferIndex::Zero, bo0),
 			TabBuffer::new(BufferIndex::One, bo1),
-		];
-		Ok(TabSwapchain::new(monitor.info.id.clone(), buffers))
-	}
-
-	fn render_node_candidates(configured: Option<&Path>) -> Vec<PathBuf> {
-		if let Some(path) = configured {
-			vec![path.to_path_buf()]
-		} else if let Ok(env) = std::env::var("TAB_CLIENT_RENDER_NODE") {
-			vec![PathBuf::from(env)]
-		} else {
-			DEFAULT_RENDER_NODES
-				.iter()
-				.map(|p| PathBuf::from(p))
-				.collect()
-		}
+		])
 	}
 }
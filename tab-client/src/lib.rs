@@ -1,41 +1,95 @@
 //! Tab client rewrite crate.
 
+mod backend;
 mod c_bindings;
 mod config;
 mod error;
 mod events;
+mod frame_hash;
+#[cfg(feature = "gbm")]
 mod gbm_allocator;
+mod link_generation;
+mod linked_buffers;
 mod monitor;
+mod output_mirror;
+mod output_pool;
+mod runtime;
+mod split;
 mod swapchain;
+#[cfg(feature = "vulkan")]
+mod vulkan_backend;
+mod wakeup;
 
+pub use backend::RenderBackend;
 pub use config::TabClientConfig;
 pub use error::TabClientError;
-pub use events::{InputEvent, MonitorEvent, RenderEvent, SessionEvent};
+pub use events::{ClientEvent, InputEvent, MonitorEvent, RenderEvent, SessionEvent};
+pub use frame_hash::{FrameStats, frame_hash};
 pub use monitor::{MonitorId, MonitorState};
-pub use swapchain::{TabBuffer, TabSwapchain};
+pub use output_mirror::{OutputChange, OutputDescription, OutputMirror};
+pub use output_pool::{FinishedFrame, JobError, OutputPool};
+pub use runtime::{Draw, FrameTarget, Renderer};
+pub use split::{TabClientGfx, TabClientIo};
+pub use swapchain::{DmaBufLayout, TabBuffer, TabSwapchain};
+#[cfg(feature = "vulkan")]
+pub use vulkan_backend::{REQUIRED_DEVICE_EXTENSIONS, VulkanBackend, VulkanImage};
 
 use std::collections::HashMap;
-use std::os::{
-	fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd, RawFd},
-	unix::net::UnixStream,
-};
+use std::io::Read;
+use std::ops::ControlFlow;
+use std::os::fd::{AsFd, AsRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, mpsc};
 use std::time::{Duration, Instant};
 
+use frame_hash::FrameSkips;
+use link_generation::LinkGenerations;
+use linked_buffers::LinkedBuffers;
+use split::{FrameSender, GfxEvent};
+
+use tab_protocol::bulk::{BulkMap, BulkPayload};
 use tab_protocol::message_frame::{TabMessageFrame, TabMessageFrameReader};
 use tab_protocol::message_header;
+use tab_protocol::transport::{AnyTransport, Transport};
 use tab_protocol::{
-	AuthErrorPayload, AuthOkPayload, AuthPayload, BufferIndex, BufferReleasePayload,
-	BufferRequestAckPayload, InputEventPayload, MonitorInfo, SessionActivePayload,
-	SessionAwakePayload, SessionCreatePayload, SessionCreatedPayload, SessionInfo,
-	SessionReadyPayload, SessionRole, SessionSleepPayload, SessionStatePayload,
-	SessionSwitchPayload, TabMessage,
+	AuthErrorPayload, AuthOkPayload, AuthPayload, BackgroundSetPayload, BacklightInfo,
+	BacklightSetPayload, BufferIndex, BufferReleasePayload, BufferRequestAckPayload,
+	BufferUploadPayload, CompositorHealthPayload, CompositorHealthSubscribePayload, DebugHudPayload,
+	DeviceCalibrationPayload, DeviceConfig, DeviceConfigurePayload, DeviceGrabPayload,
+	DeviceSettings, FocusPayload, FocusTarget, FramebufferLinkFailedPayload, FramebufferLinkPayload,
+	FramesDroppedPayload, InputEventPayload, InputInjectPayload, KeyRepeatInfo, LayoutRegion,
+	LidClosedPayload, LogDumpPayload, LogLevelPayload, LogRecordsPayload, MonitorFilter,
+	MonitorFilterPayload, MonitorInfo, MonitorLayoutPayload, MonitorMirrorPayload,
+	PerformanceWarningPayload, PointerLockPayload, PointerLockStatePayload, PresentGroupPayload,
+	PresentMode, Rect, RelinkRequestPayload, ScreenshotDataPayload, ScreenshotPayload, SelectionDataPayload,
+	SelectionOfferPayload, SelectionPolicyPayload, ServerShutdownPayload, SessionActivePayload,
+	SessionAssignMonitorPayload, SessionAwakePayload, SessionCrashedPayload, SessionCreatePayload,
+	SessionCreatedPayload, SessionInactivePayload, SessionInfo, SessionPipPayload,
+	SessionReadyPayload, SessionRole, SessionSleepPayload, SessionStatePayload, SessionSwitchPayload,
+	SessionUnresponsivePayload, SessionVisibilityPayload, ShortcutModifier, ShortcutRegisterPayload,
+	ShortcutTriggeredPayload, ShortcutUnregisterPayload, StateSyncPayload, StatsPayload,
+	SwitchEventsSubscribePayload, TabMessage, TabletModePayload, TransitionDefinePayload,
+	TransitionInfo, WireTapPayload, ZoomSetPayload,
 };
 
-use crate::gbm_allocator::GbmAllocator;
+/// Pixels of a monitor as returned by [`TabClient::screenshot`].
+#[derive(Debug, Clone)]
+pub struct Screenshot {
+	pub info: ScreenshotDataPayload,
+	pub pixels: Vec<u8>,
+}
+
+/// Clipboard contents as returned by [`TabClient::request_selection`].
+#[derive(Debug, Clone)]
+pub struct Selection {
+	pub info: SelectionDataPayload,
+	pub data: Vec<u8>,
+}
 
 /// Primary synchronous Tab client handle.
+///
+/// Not `Send`: use [`TabClient::split`] to pump the socket on one thread and render on another.
 pub struct TabClient {
-	socket: UnixStream,
+	sender: Arc<FrameSender>,
 	reader: TabMessageFrameReader,
 	session: SessionInfo,
 	monitors: HashMap<MonitorId, MonitorState>,
@@ -43,15 +97,28 @@ pub struct TabClient {
 	render_listeners: Vec<Box<dyn Fn(&RenderEvent)>>,
 	session_listeners: Vec<Box<dyn Fn(&SessionEvent)>>,
 	input_listeners: Vec<Box<dyn Fn(&InputEvent)>>,
-	gbm: GbmAllocator,
+	/// Missing without the `gbm` feature until one is installed, and on transports without FD
+	/// passing when there is no usable render node.
+	backend: Option<Box<dyn RenderBackend>>,
+	present_mode: PresentMode,
+	key_repeat: Option<KeyRepeatInfo>,
+	frame_skips: FrameSkips,
+	link_generations: Arc<LinkGenerations>,
+	linked_buffers: Arc<LinkedBuffers>,
+	/// Set by [`TabClient::split`]: render related messages go to the [`TabClientGfx`] instead.
+	gfx_events: Option<mpsc::Sender<GfxEvent>>,
 }
 
 impl TabClient {
 	const BUFFER_REQUEST_ACK_TIMEOUT: Duration = Duration::from_millis(250);
 	const SESSION_CREATE_TIMEOUT: Duration = Duration::from_millis(500);
+	const LOG_DUMP_TIMEOUT: Duration = Duration::from_secs(2);
+	const ADMIN_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+	/// Screenshots are taken on the monitor's next frame.
+	const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(2);
 
 	pub fn connect(config: TabClientConfig) -> Result<Self, TabClientError> {
-		let socket = tab_protocol::unix_socket_utils::connect_seqpacket(config.socket_path_ref())?;
+		let socket = config.address_ref().connect()?;
 		let mut reader = TabMessageFrameReader::new();
 		let hello = Self::read_message(&socket, &mut reader)?;
 		let TabMessage::Hello(payload) = hello else {
@@ -60,23 +127,31 @@ impl TabClient {
 		if payload.protocol != tab_protocol::PROTOCOL_VERSION {
 			return Err(TabClientError::Unexpected("protocol mismatch"));
 		}
+		let compression = payload
+			.compression
+			.into_iter()
+			.find(|algo| tab_protocol::compression::supported().contains(algo));
+		let compress_payloads = compression.is_some();
 		let auth_frame = TabMessageFrame::json(
 			message_header::AUTH,
 			AuthPayload {
 				token: config.token().to_string(),
+				compression,
+				local_key_repeat: config.local_key_repeat_ref(),
+				coalesce_releases: true,
 			},
 		);
-		auth_frame.encode_and_send(&socket)?;
+		socket.send_frame(&auth_frame)?;
 		let auth_ok = Self::wait_for_auth(&socket, &mut reader)?;
 		let monitors = auth_ok
 			.monitors
 			.into_iter()
 			.map(|info| (info.id.clone(), MonitorState::new(info)))
 			.collect();
-		let gbm = GbmAllocator::new(config.render_node_path())?;
+		let backend = Self::default_backend(&config, &socket)?;
 		socket.set_nonblocking(true)?;
 		Ok(Self {
-			socket,
+			sender: Arc::new(FrameSender::new(socket, compress_payloads)),
 			reader,
 			session: auth_ok.session,
 			monitors,
@@ -84,14 +159,95 @@ impl TabClient {
 			render_listeners: Vec::new(),
 			session_listeners: Vec::new(),
 			input_listeners: Vec::new(),
-			gbm,
+			backend,
+			present_mode: config.present_mode_ref(),
+			key_repeat: auth_ok.key_repeat,
+			frame_skips: FrameSkips::default(),
+			link_generations: Arc::default(),
+			linked_buffers: Arc::default(),
+			gfx_events: None,
 		})
 	}
 
+	#[cfg(feature = "gbm")]
+	fn default_backend(
+		config: &TabClientConfig,
+		socket: &AnyTransport,
+	) -> Result<Option<Box<dyn RenderBackend>>, TabClientError> {
+		match gbm_allocator::GbmAllocator::new(config.render_node_path()) {
+			Ok(gbm) => Ok(Some(Box::new(gbm))),
+			Err(_) if !socket.supports_fd_passing() => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	#[cfg(not(feature = "gbm"))]
+	fn default_backend(
+		_config: &TabClientConfig,
+		_socket: &AnyTransport,
+	) -> Result<Option<Box<dyn RenderBackend>>, TabClientError> {
+		Ok(None)
+	}
+
+	/// Replaces the allocator used by [`TabClient::create_swapchain`] from now on.
+	pub fn set_render_backend(&mut self, backend: impl RenderBackend + 'static) {
+		self.backend = Some(Box::new(backend));
+	}
+
+	fn send_frame(&self, frame: TabMessageFrame) -> Result<(), TabClientError> {
+		self.sender.send(frame)
+	}
+
+	/// Splits the client into a `Send` socket half and a thread-affine render half.
+	///
+	/// Listeners registered so far are dropped, since they may not be `Send`: register them again
+	/// on the half that emits their events. The render backend moves to the [`TabClientGfx`].
+	pub fn split(mut self) -> (TabClientIo, TabClientGfx) {
+		let (gfx_events, events) = mpsc::channel();
+		self.gfx_events = Some(gfx_events);
+		self.monitor_listeners.clear();
+		self.render_listeners.clear();
+		self.session_listeners.clear();
+		self.input_listeners.clear();
+		let gfx = TabClientGfx::new(
+			Arc::clone(&self.sender),
+			events,
+			self.backend.take(),
+			self.monitors.clone(),
+			self.present_mode,
+			Arc::clone(&self.link_generations),
+			Arc::clone(&self.linked_buffers),
+		);
+		(TabClientIo::new(self), gfx)
+	}
+
+	fn forward_to_gfx(&self, event: GfxEvent) {
+		if let Some(gfx_events) = &self.gfx_events {
+			// A dropped TabClientGfx just stops receiving.
+			let _ = gfx_events.send(event);
+		}
+	}
+
+	/// Changes the queueing policy of swapchains linked from now on.
+	pub fn set_present_mode(&mut self, mode: PresentMode) {
+		self.present_mode = mode;
+	}
+
+	/// Whether dma-buf swapchains can be shared with shift. When false, present frames with
+	/// [`TabClient::upload_buffer`] instead.
+	pub fn supports_fd_passing(&self) -> bool {
+		self.sender.socket().supports_fd_passing()
+	}
+
 	pub fn session(&self) -> &SessionInfo {
 		&self.session
 	}
 
+	/// How shift repeats held keys, unset for servers that don't.
+	pub fn key_repeat(&self) -> Option<KeyRepeatInfo> {
+		self.key_repeat
+	}
+
 	pub fn monitors(&self) -> impl Iterator<Item = &MonitorState> {
 		self.monitors.values()
 	}
@@ -101,58 +257,297 @@ impl TabClient {
 	}
 
 	pub fn socket_fd(&self) -> RawFd {
-		self.socket.as_raw_fd()
+		self.sender.socket().as_raw_fd()
 	}
 
 	pub fn poll_fds(&self) -> [RawFd; 2] {
-		[self.socket.as_raw_fd(), self.drm_fd()]
+		[self.socket_fd(), self.drm_fd()]
 	}
 
+	/// `-1` when the render backend has no render node open.
 	pub fn drm_fd(&self) -> RawFd {
-		self.gbm.drm_fd()
+		self.backend.as_ref().map_or(-1, |backend| backend.drm_fd())
 	}
 
 	pub fn create_swapchain(&self, monitor_id: &str) -> Result<TabSwapchain, TabClientError> {
-		let monitor = self
-			.monitors
-			.get(monitor_id)
-			.ok_or_else(|| TabClientError::UnknownMonitor(monitor_id.to_string()))?;
-		let swapchain = self.gbm.create_swapchain(monitor)?;
+		let swapchain = Self::allocate_swapchain(
+			&self.sender,
+			self.backend.as_deref(),
+			self.monitors.get(monitor_id),
+			monitor_id,
+		)?;
+		self.framebuffer_link(&swapchain)?;
+		Ok(swapchain)
+	}
+
+	/// Allocates a `width`x`height` swapchain for `monitor_id` and links it in place of the
+	/// current one, without the app tearing down anything it attached to the monitor. The link
+	/// gets a new generation, so releases of the old buffers aren't taken for the new ones, and
+	/// shift shows the old link until the new one is imported. Buffer indices keep their meaning:
+	/// a renderer keyed by monitor and index only needs a new viewport.
+	pub fn resize_output(
+		&self,
+		monitor_id: &str,
+		width: i32,
+		height: i32,
+	) -> Result<TabSwapchain, TabClientError> {
+		let swapchain = Self::allocate_resized_swapchain(
+			&self.sender,
+			self.backend.as_deref(),
+			self.monitors.get(monitor_id),
+			monitor_id,
+			width,
+			height,
+		)?;
 		self.framebuffer_link(&swapchain)?;
 		Ok(swapchain)
 	}
 
+	/// [`Self::allocate_swapchain`] at `width`x`height` instead of the monitor's mode.
+	fn allocate_resized_swapchain(
+		sender: &FrameSender,
+		backend: Option<&dyn RenderBackend>,
+		monitor: Option<&MonitorState>,
+		monitor_id: &str,
+		width: i32,
+		height: i32,
+	) -> Result<TabSwapchain, TabClientError> {
+		if width <= 0 || height <= 0 {
+			return Err(TabClientError::InvalidMonitorDimensions);
+		}
+		let resized = monitor.map(|monitor| {
+			let mut resized = monitor.clone();
+			resized.info.width = width;
+			resized.info.height = height;
+			resized
+		});
+		Self::allocate_swapchain(sender, backend, resized.as_ref(), monitor_id)
+	}
+
+	/// Allocates a swapchain shift can import for `monitor`. Shared with [`TabClientGfx`].
+	fn allocate_swapchain(
+		sender: &FrameSender,
+		backend: Option<&dyn RenderBackend>,
+		monitor: Option<&MonitorState>,
+		monitor_id: &str,
+	) -> Result<TabSwapchain, TabClientError> {
+		let monitor = monitor.ok_or_else(|| TabClientError::UnknownMonitor(monitor_id.to_string()))?;
+		if !sender.socket().supports_fd_passing() {
+			return Err(tab_protocol::ProtocolError::FdPassingUnsupported.into());
+		}
+		let backend = backend.ok_or(TabClientError::NoRenderBackend)?;
+		let swapchain = TabSwapchain::new(monitor.info.id.clone(), backend.allocate_buffers(monitor)?);
+		let fourcc = swapchain.buffers[0].fourcc();
+		if !monitor.accepts_format(fourcc) {
+			return Err(TabClientError::UnsupportedFormat(fourcc));
+		}
+		Ok(swapchain)
+	}
+
 	pub fn framebuffer_link(&self, swapchain: &TabSwapchain) -> Result<(), TabClientError> {
-		let payload = swapchain.framebuffer_link_payload();
+		self.send_frame(Self::framebuffer_link_frame(
+			swapchain,
+			self.present_mode,
+			&self.link_generations,
+			&self.linked_buffers,
+		)?)
+	}
+
+	fn framebuffer_link_frame(
+		swapchain: &TabSwapchain,
+		present_mode: PresentMode,
+		link_generations: &LinkGenerations,
+		linked_buffers: &LinkedBuffers,
+	) -> Result<TabMessageFrame, TabClientError> {
+		let payload = FramebufferLinkPayload {
+			present_mode,
+			generation: link_generations.next(&swapchain.monitor_id),
+			..swapchain.framebuffer_link_payload()
+		};
+		let fds = Vec::from(swapchain.export_fds()?);
+		linked_buffers.record(&payload, &fds)?;
 		let mut frame = TabMessageFrame::json(message_header::FRAMEBUFFER_LINK, payload);
-		let fds = swapchain.export_fds();
-		frame.fds = Vec::from(fds);
-		frame.encode_and_send(&self.socket)?;
+		frame.fds = fds;
+		Ok(frame)
+	}
+
+	/// Record that a frame for `monitor_id` was not submitted because nothing changed.
+	/// Shift counts these in its frame stats.
+	pub fn skip_frame(&mut self, monitor_id: &str) -> Result<(), TabClientError> {
+		if let Some(report) = self.frame_skips.skip(monitor_id) {
+			self.send_frame(TabMessageFrame::json(
+				message_header::FRAMES_SKIPPED,
+				report,
+			))?;
+		}
+		Ok(())
+	}
+
+	/// Frames skipped by this client and dropped by shift so far.
+	pub fn frame_stats(&self) -> FrameStats {
+		self.frame_skips.stats()
+	}
+
+	fn report_skipped_frames(&mut self, monitor_id: &str) -> Result<(), TabClientError> {
+		if let Some(report) = self.frame_skips.take_report(monitor_id) {
+			self.send_frame(TabMessageFrame::json(
+				message_header::FRAMES_SKIPPED,
+				report,
+			))?;
+		}
 		Ok(())
 	}
 
+	/// Like [`TabClient::request_buffer`], but skips the submit when `content_hash` matches the
+	/// last frame submitted on this monitor (see [`frame_hash`]). Returns whether it submitted;
+	/// on a skip the buffer stays client-owned and can be reused for the next frame.
+	pub fn request_buffer_if_changed(
+		&mut self,
+		monitor_id: &str,
+		buffer: BufferIndex,
+		acquire_fence: Option<OwnedFd>,
+		content_hash: u64,
+	) -> Result<bool, TabClientError> {
+		if self.frame_skips.is_unchanged(monitor_id, content_hash) {
+			self.skip_frame(monitor_id)?;
+			return Ok(false);
+		}
+		self.request_buffer(monitor_id, buffer, acquire_fence)?;
+		self.frame_skips.set_last_hash(monitor_id, content_hash);
+		Ok(true)
+	}
+
+	/// Copy ARGB8888 pixels into a buffer slot of `monitor_id`. This is the pixel path for
+	/// transports without FD passing; follow it with [`TabClient::request_buffer`] to present.
+	/// Buffers too big for one frame go out as several.
+	pub fn upload_buffer(
+		&self,
+		monitor_id: &str,
+		buffer: BufferIndex,
+		width: i32,
+		height: i32,
+		stride: i32,
+		pixels: &[u8],
+	) -> Result<(), TabClientError> {
+		for frame in Self::buffer_upload_frames(monitor_id, buffer, width, height, stride, pixels) {
+			self.send_frame(frame)?;
+		}
+		Ok(())
+	}
+
+	fn buffer_upload_frames(
+		monitor_id: &str,
+		buffer: BufferIndex,
+		width: i32,
+		height: i32,
+		stride: i32,
+		pixels: &[u8],
+	) -> Vec<TabMessageFrame> {
+		BufferUploadPayload::split(monitor_id, buffer, width, height, stride, pixels)
+			.into_iter()
+			.map(|payload| TabMessageFrame::json(message_header::BUFFER_UPLOAD, payload))
+			.collect()
+	}
+
+	/// Presents `buffer` with an optional acquire fence, which is closed once sent.
 	pub fn request_buffer(
 		&mut self,
 		monitor_id: &str,
 		buffer: BufferIndex,
-		acquire_fence: Option<RawFd>,
+		acquire_fence: Option<OwnedFd>,
 	) -> Result<(), TabClientError> {
-		let payload = format!("{monitor_id} {}", buffer as u8);
-		let frame = TabMessageFrame {
+		self.report_skipped_frames(monitor_id)?;
+		self.frame_skips.forget_last_hash(monitor_id);
+		let generation = self.link_generations.current(monitor_id);
+		self.send_frame(Self::buffer_request_frame(
+			monitor_id,
+			buffer,
+			generation,
+			acquire_fence,
+			None,
+		))?;
+		self.wait_for_buffer_request_acks(vec![(monitor_id.to_string(), buffer, generation)])?;
+		Ok(())
+	}
+
+	fn buffer_request_frame(
+		monitor_id: &str,
+		buffer: BufferIndex,
+		generation: u64,
+		acquire_fence: Option<OwnedFd>,
+		present_group: Option<u32>,
+	) -> TabMessageFrame {
+		TabMessageFrame {
 			header: message_header::BUFFER_REQUEST.into(),
-			payload: Some(payload),
-			fds: acquire_fence.map_or_else(Vec::new, |fd| vec![fd]),
+			payload: Some(tab_protocol::buffer_request_args(
+				monitor_id,
+				buffer,
+				generation,
+				present_group,
+			)),
+			fds: acquire_fence.into_iter().collect(),
+		}
+	}
+
+	/// Whether `ack` answers the `(monitor_id, buffer, generation)` request. Untagged acks come
+	/// from shift versions without link generations.
+	fn is_ack_for(ack: &BufferRequestAckPayload, request: &(String, BufferIndex, u64)) -> bool {
+		let (monitor_id, buffer, generation) = request;
+		ack.monitor_id == *monitor_id
+			&& ack.buffer == *buffer
+			&& (ack.generation == 0 || ack.generation == *generation)
+	}
+
+	/// Makes `monitor_ids` flip together for [`TabClient::request_present_group`], replacing what
+	/// `group` spanned before. No monitors removes the group.
+	pub fn define_present_group(
+		&self,
+		group: u32,
+		monitor_ids: &[&str],
+	) -> Result<(), TabClientError> {
+		self.send_frame(Self::present_group_frame(group, monitor_ids))
+	}
+
+	fn present_group_frame(group: u32, monitor_ids: &[&str]) -> TabMessageFrame {
+		let payload = PresentGroupPayload {
+			group,
+			monitor_ids: monitor_ids.iter().map(|id| id.to_string()).collect(),
 		};
-		frame.encode_and_send(&self.socket)?;
-		self.wait_for_buffer_request_ack(monitor_id, buffer)?;
-		Ok(())
+		TabMessageFrame::json(message_header::PRESENT_GROUP, payload)
+	}
+
+	/// Presents one buffer on each monitor of `group`, which shift shows on the same page flip
+	/// where the hardware allows. Waits until all of them were acked.
+	pub fn request_present_group(
+		&mut self,
+		group: u32,
+		requests: Vec<(&str, BufferIndex, Option<OwnedFd>)>,
+	) -> Result<(), TabClientError> {
+		let mut expected = Vec::with_capacity(requests.len());
+		for (monitor_id, buffer, acquire_fence) in requests {
+			self.report_skipped_frames(monitor_id)?;
+			self.frame_skips.forget_last_hash(monitor_id);
+			let generation = self.link_generations.current(monitor_id);
+			self.send_frame(Self::buffer_request_frame(
+				monitor_id,
+				buffer,
+				generation,
+				acquire_fence,
+				Some(group),
+			))?;
+			expected.push((monitor_id.to_string(), buffer, generation));
+		}
+		self.wait_for_buffer_request_acks(expected)
 	}
 
 	pub fn send_ready(&self) -> Result<(), TabClientError> {
 		let payload = SessionReadyPayload {
 			session_id: self.session.id.clone(),
 		};
-		TabMessageFrame::json(message_header::SESSION_READY, payload).encode_and_send(&self.socket)?;
+		self.send_frame(TabMessageFrame::json(
+			message_header::SESSION_READY,
+			payload,
+		))?;
 		Ok(())
 	}
 
@@ -161,9 +556,19 @@ impl TabClient {
 		role: SessionRole,
 		display_name: Option<String>,
 	) -> Result<SessionCreatedPayload, TabClientError> {
-		let payload = SessionCreatePayload { role, display_name };
-		TabMessageFrame::json(message_header::SESSION_CREATE, payload)
-			.encode_and_send(&self.socket)?;
+		self.create_session_with(SessionCreatePayload::new(role, display_name))
+	}
+
+	/// Like [`Self::create_session`], with a launch descriptor for shift to spawn the session
+	/// process itself (admin only).
+	pub fn create_session_with(
+		&mut self,
+		payload: SessionCreatePayload,
+	) -> Result<SessionCreatedPayload, TabClientError> {
+		self.send_frame(TabMessageFrame::json(
+			message_header::SESSION_CREATE,
+			payload,
+		))?;
 		self.wait_for_session_created()
 	}
 
@@ -173,13 +578,472 @@ impl TabClient {
 		animation: Option<String>,
 		duration: Duration,
 	) -> Result<(), TabClientError> {
-		let payload = SessionSwitchPayload {
+		self.switch_session_with(SessionSwitchPayload::new(session_id, animation, duration))
+	}
+
+	/// Like [`Self::switch_session`], with an easing and animation parameters from
+	/// [`Self::transitions`] (admin only).
+	pub fn switch_session_with(&self, payload: SessionSwitchPayload) -> Result<(), TabClientError> {
+		self.send_frame(TabMessageFrame::json(
+			message_header::SESSION_SWITCH,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Register a keyframe animation for [`Self::switch_session`], or replace the one named the
+	/// same (admin only).
+	pub fn define_transition(&self, payload: TransitionDefinePayload) -> Result<(), TabClientError> {
+		self.send_frame(TabMessageFrame::json(
+			message_header::TRANSITION_DEFINE,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Show `session_id` scaled into `rect` on top of `monitor_id` (admin only).
+	/// Passing `None` removes the monitor's picture-in-picture overlay.
+	pub fn set_session_pip(
+		&self,
+		session_id: &str,
+		monitor_id: &str,
+		rect: Option<Rect>,
+	) -> Result<(), TabClientError> {
+		let payload = SessionPipPayload {
 			session_id: session_id.to_string(),
-			animation,
-			duration,
+			monitor_id: monitor_id.to_string(),
+			rect,
+		};
+		self.send_frame(TabMessageFrame::json(message_header::SESSION_PIP, payload))?;
+		Ok(())
+	}
+
+	/// Split `monitor_id` into regions, each showing a different session (admin only).
+	/// An empty `regions` list restores the normal full-screen layout.
+	pub fn set_monitor_layout(
+		&self,
+		monitor_id: &str,
+		regions: Vec<LayoutRegion>,
+	) -> Result<(), TabClientError> {
+		let payload = MonitorLayoutPayload {
+			monitor_id: monitor_id.to_string(),
+			regions,
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::MONITOR_LAYOUT,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Show what `source_monitor` shows on `target_monitor` too (admin only), letterboxed if
+	/// their shapes differ. Passing `None` makes the target show its own output again.
+	pub fn mirror_monitor(
+		&self,
+		source_monitor: Option<&str>,
+		target_monitor: &str,
+	) -> Result<(), TabClientError> {
+		let payload = MonitorMirrorPayload {
+			source_monitor: source_monitor.map(str::to_string),
+			target_monitor: target_monitor.to_string(),
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::MONITOR_MIRROR,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Draw `monitor_id` through an accessibility color filter (admin only). Shift remembers it
+	/// for the monitor across restarts.
+	pub fn set_monitor_filter(
+		&self,
+		monitor_id: &str,
+		filter: MonitorFilter,
+	) -> Result<(), TabClientError> {
+		let payload = MonitorFilterPayload {
+			monitor_id: monitor_id.to_string(),
+			filter,
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::MONITOR_FILTER,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Magnify `monitor_id` by `factor` around `center` (admin only), or around the pointer with
+	/// `follow_pointer`. A factor of 1 zooms back out.
+	pub fn set_zoom(
+		&self,
+		monitor_id: &str,
+		factor: f32,
+		center: Option<[f32; 2]>,
+		follow_pointer: bool,
+	) -> Result<(), TabClientError> {
+		let payload = ZoomSetPayload {
+			monitor_id: monitor_id.to_string(),
+			factor,
+			center,
+			follow_pointer,
+		};
+		self.send_frame(TabMessageFrame::json(message_header::ZOOM_SET, payload))?;
+		Ok(())
+	}
+
+	/// Pin `monitor_id` to `session_id` (admin only), independently of the active session.
+	/// Passing `None` makes the monitor follow the active session again.
+	pub fn assign_monitor(
+		&self,
+		monitor_id: &str,
+		session_id: Option<&str>,
+	) -> Result<(), TabClientError> {
+		let payload = SessionAssignMonitorPayload {
+			session_id: session_id.map(str::to_string),
+			monitor_id: monitor_id.to_string(),
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::SESSION_ASSIGN_MONITOR,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Ask for a locked, relative-only pointer. Shift answers with a `SessionEvent::PointerLock`;
+	/// the lock is broken again when the session loses pointer focus.
+	pub fn set_pointer_lock(&self, enable: bool) -> Result<(), TabClientError> {
+		let payload = PointerLockPayload { enable };
+		self.send_frame(TabMessageFrame::json(message_header::POINTER_LOCK, payload))?;
+		Ok(())
+	}
+
+	/// Reserve a key chord (admin only). Shift swallows matching key presses and reports them
+	/// as `InputEvent::ShortcutTriggered` instead of forwarding them to the focused session.
+	pub fn register_shortcut(
+		&self,
+		id: &str,
+		modifiers: Vec<ShortcutModifier>,
+		key: u32,
+	) -> Result<(), TabClientError> {
+		let payload = ShortcutRegisterPayload {
+			id: id.to_string(),
+			modifiers,
+			key,
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::SHORTCUT_REGISTER,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	pub fn unregister_shortcut(&self, id: &str) -> Result<(), TabClientError> {
+		let payload = ShortcutUnregisterPayload { id: id.to_string() };
+		self.send_frame(TabMessageFrame::json(
+			message_header::SHORTCUT_UNREGISTER,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Show or hide shift's on-screen debug HUD (admin only).
+	pub fn set_debug_hud(&self, enabled: bool) -> Result<(), TabClientError> {
+		let payload = DebugHudPayload { enabled };
+		self.send_frame(TabMessageFrame::json(message_header::DEBUG_HUD, payload))?;
+		Ok(())
+	}
+
+	/// Start or stop receiving [`SessionEvent::CompositorHealth`] about once a second (admin only).
+	pub fn subscribe_compositor_health(&self, enabled: bool) -> Result<(), TabClientError> {
+		let payload = CompositorHealthSubscribePayload { enabled };
+		self.send_frame(TabMessageFrame::json(
+			message_header::COMPOSITOR_HEALTH_SUBSCRIBE,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Start or stop receiving [`SessionEvent::LidClosed`] and [`SessionEvent::TabletMode`]
+	/// (admin only). Subscribing also reports the switches' current state.
+	pub fn subscribe_switch_events(&self, enabled: bool) -> Result<(), TabClientError> {
+		let payload = SwitchEventsSubscribePayload { enabled };
+		self.send_frame(TabMessageFrame::json(
+			message_header::SWITCH_EVENTS_SUBSCRIBE,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Draw a solid `#rrggbb` color beneath sessions (admin only).
+	pub fn set_background_color(&self, color: &str) -> Result<(), TabClientError> {
+		let payload = BackgroundSetPayload {
+			color: Some(color.to_string()),
+			image: None,
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::BACKGROUND_SET,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Draw the image at `path`, read by shift, beneath sessions (admin only).
+	pub fn set_background_image(&self, path: &str) -> Result<(), TabClientError> {
+		let payload = BackgroundSetPayload {
+			color: None,
+			image: Some(path.to_string()),
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::BACKGROUND_SET,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Change shift's log level for `target`, or its default level when `target` is `None` (admin only).
+	pub fn set_log_level(&self, target: Option<&str>, level: &str) -> Result<(), TabClientError> {
+		let payload = LogLevelPayload {
+			target: target.map(str::to_string),
+			level: level.to_string(),
+		};
+		self.send_frame(TabMessageFrame::json(message_header::LOG_LEVEL, payload))?;
+		Ok(())
+	}
+
+	/// Fetch shift's most recent log records as JSON lines, oldest first (admin only).
+	pub fn log_dump(&mut self, limit: Option<u32>) -> Result<Vec<String>, TabClientError> {
+		let payload = LogDumpPayload { limit };
+		self.send_frame(TabMessageFrame::json(message_header::LOG_DUMP, payload))?;
+		self.wait_for_log_records()
+	}
+
+	/// Log the traffic of `payload.session_id`'s connections to shift's log or a capture file, to
+	/// debug a client from outside (admin only).
+	pub fn set_wire_tap(&self, payload: WireTapPayload) -> Result<(), TabClientError> {
+		self.send_frame(TabMessageFrame::json(message_header::WIRE_TAP, payload))?;
+		Ok(())
+	}
+
+	/// List every session shift knows about, including pending ones, with whether a client is
+	/// attached to each and whether it is on screen (admin or observer only).
+	pub fn list_sessions(&mut self) -> Result<Vec<SessionInfo>, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(message_header::SESSION_LIST))?;
+		self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"sessions timeout",
+			|message| match message {
+				TabMessage::Sessions(payload) => ControlFlow::Break(payload.sessions),
+				other => ControlFlow::Continue(other),
+			},
+		)
+	}
+
+	/// List the animations `session_switch` accepts, with their parameters (admin only).
+	pub fn transitions(&mut self) -> Result<Vec<TransitionInfo>, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(
+			message_header::TRANSITIONS_LIST,
+		))?;
+		self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"transitions timeout",
+			|message| match message {
+				TabMessage::Transitions(payload) => ControlFlow::Break(payload.transitions),
+				other => ControlFlow::Continue(other),
+			},
+		)
+	}
+
+	/// Fetch shift's current counters (admin only).
+	pub fn stats(&mut self) -> Result<StatsPayload, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(message_header::STATS_REQUEST))?;
+		self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"stats timeout",
+			|message| match message {
+				TabMessage::Stats(payload) => ControlFlow::Break(payload),
+				other => ControlFlow::Continue(other),
+			},
+		)
+	}
+
+	/// Fetch monitors, sessions and layouts as shift has them now, to start over after missing
+	/// events (admin or observer only). Events that change them carry the `epoch` they bring shift
+	/// to; a jump of more than one since the last seen means something was missed.
+	pub fn state_sync(&mut self) -> Result<StateSyncPayload, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(
+			message_header::STATE_SYNC_REQUEST,
+		))?;
+		let mut state = self.wait_for_state_sync_part()?;
+		while state.more {
+			let part = self.wait_for_state_sync_part()?;
+			state.extend(part);
+		}
+		Ok(state)
+	}
+
+	fn wait_for_state_sync_part(&mut self) -> Result<StateSyncPayload, TabClientError> {
+		self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"state sync timeout",
+			|message| match message {
+				TabMessage::StateSync(payload) => ControlFlow::Break(payload),
+				other => ControlFlow::Continue(other),
+			},
+		)
+	}
+
+	/// Read back what shift last drew on `monitor_id` (admin only, needs FD passing).
+	pub fn screenshot(&mut self, monitor_id: &str) -> Result<Screenshot, TabClientError> {
+		let payload = ScreenshotPayload {
+			monitor_id: monitor_id.to_string(),
 		};
-		TabMessageFrame::json(message_header::SESSION_SWITCH, payload)
-			.encode_and_send(&self.socket)?;
+		self.send_frame(TabMessageFrame::json(message_header::SCREENSHOT, payload))?;
+		let (info, pixels) = self.wait_for_reply(
+			Self::SCREENSHOT_TIMEOUT,
+			"screenshot_data timeout",
+			|message| match message {
+				TabMessage::ScreenshotData { payload, pixels } => ControlFlow::Break((payload, pixels)),
+				other => ControlFlow::Continue(other),
+			},
+		)?;
+		let mut data = Vec::new();
+		std::fs::File::from(pixels).read_to_end(&mut data)?;
+		Ok(Screenshot { info, pixels: data })
+	}
+
+	/// List the backlights shift found, with the monitor each one lights (admin only).
+	pub fn backlights(&mut self) -> Result<Vec<BacklightInfo>, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(message_header::BACKLIGHT_GET))?;
+		self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"backlights timeout",
+			|message| match message {
+				TabMessage::Backlights(payload) => ControlFlow::Break(payload.backlights),
+				other => ControlFlow::Continue(other),
+			},
+		)
+	}
+
+	/// Set the backlight of `monitor_id` to `level`, from 0 to 1 (admin only).
+	pub fn set_backlight(&self, monitor_id: &str, level: f64) -> Result<(), TabClientError> {
+		let payload = BacklightSetPayload {
+			monitor_id: monitor_id.to_string(),
+			level,
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::BACKLIGHT_SET,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Calibrate the absolute input device `device` with `matrix`, see
+	/// [`DeviceCalibrationPayload`] (admin only).
+	pub fn set_device_calibration(
+		&self,
+		device: u32,
+		matrix: [f64; 6],
+	) -> Result<(), TabClientError> {
+		let payload = DeviceCalibrationPayload { device, matrix };
+		self.send_frame(TabMessageFrame::json(
+			message_header::DEVICE_CALIBRATION,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// List the input devices with their libinput settings (admin only).
+	pub fn device_configs(&mut self) -> Result<Vec<DeviceConfig>, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(
+			message_header::DEVICE_CONFIG_GET,
+		))?;
+		self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"device_configs timeout",
+			|message| match message {
+				TabMessage::DeviceConfigs(payload) => ControlFlow::Break(payload.devices),
+				other => ControlFlow::Continue(other),
+			},
+		)
+	}
+
+	/// Feed `event` to shift as if a device sent it; needs `SHIFT_INPUT_INJECT=1` (admin only).
+	pub fn inject_input(&self, event: InputEventPayload) -> Result<(), TabClientError> {
+		self.send_frame(TabMessageFrame::json(
+			message_header::INPUT_INJECT,
+			InputInjectPayload { event },
+		))?;
+		Ok(())
+	}
+
+	/// Change the set fields of `settings` on the input device `device` (admin only).
+	pub fn configure_device(
+		&self,
+		device: u32,
+		settings: DeviceSettings,
+	) -> Result<(), TabClientError> {
+		let payload = DeviceConfigurePayload { device, settings };
+		self.send_frame(TabMessageFrame::json(
+			message_header::DEVICE_CONFIGURE,
+			payload,
+		))?;
+		Ok(())
+	}
+
+	/// Send every event of the input device `device` to `session_id` whatever has focus, or
+	/// release the grab with `None` (admin only).
+	pub fn grab_device(&self, device: u32, session_id: Option<&str>) -> Result<(), TabClientError> {
+		let payload = DeviceGrabPayload {
+			device,
+			session_id: session_id.map(str::to_owned),
+		};
+		self.send_frame(TabMessageFrame::json(message_header::DEVICE_GRAB, payload))?;
+		Ok(())
+	}
+
+	/// Put `data` on the clipboard of this session's group (needs FD passing).
+	pub fn offer_selection(&self, mime_type: &str, data: &[u8]) -> Result<(), TabClientError> {
+		let payload = SelectionOfferPayload {
+			mime_type: mime_type.to_string(),
+		};
+		let mut frame = TabMessageFrame::json(message_header::SELECTION_OFFER, payload);
+		frame.fds.push(BulkPayload::seal(c"tab-selection", data)?.1);
+		self.send_frame(frame)?;
+		Ok(())
+	}
+
+	/// Read what was last offered in this session's group (needs FD passing).
+	pub fn request_selection(&mut self) -> Result<Selection, TabClientError> {
+		self.send_frame(TabMessageFrame::no_payload(
+			message_header::SELECTION_REQUEST,
+		))?;
+		let (info, fd) = self.wait_for_reply(
+			Self::ADMIN_QUERY_TIMEOUT,
+			"selection_data timeout",
+			|message| match message {
+				TabMessage::SelectionData { payload, data } => ControlFlow::Break((payload, data)),
+				other => ControlFlow::Continue(other),
+			},
+		)?;
+		let data = BulkMap::map(&fd, BulkPayload::whole(info.size), info.size)?.to_vec();
+		Ok(Selection { info, data })
+	}
+
+	/// Choose which sessions `session_id` shares its clipboard with; `None` is the group every
+	/// session starts in (admin only).
+	pub fn set_selection_group(
+		&self,
+		session_id: &str,
+		group: Option<&str>,
+	) -> Result<(), TabClientError> {
+		let payload = SelectionPolicyPayload {
+			session_id: session_id.to_string(),
+			group: group.map(str::to_string),
+		};
+		self.send_frame(TabMessageFrame::json(
+			message_header::SELECTION_POLICY,
+			payload,
+		))?;
 		Ok(())
 	}
 
@@ -213,7 +1077,7 @@ impl TabClient {
 
 	pub fn dispatch_events(&mut self) -> Result<(), TabClientError> {
 		loop {
-			match self.reader.read_framed(&self.socket) {
+			match self.reader.read_framed(self.sender.socket()) {
 				Ok(frame) => {
 					let message = TabMessage::try_from(frame)?;
 					self.handle_message(message)?;
@@ -225,8 +1089,120 @@ impl TabClient {
 		Ok(())
 	}
 
+	/// Handles messages until one leads to an event `pred` accepts, and returns that event.
+	/// Listeners still get every event. `None` if `timeout` passed first.
+	pub fn wait_for_event(
+		&mut self,
+		timeout: Duration,
+		mut pred: impl FnMut(&ClientEvent) -> bool,
+	) -> Result<Option<ClientEvent>, TabClientError> {
+		let deadline = Instant::now() + timeout;
+		let (tx, rx) = mpsc::channel();
+		let listener_counts = self.capture_events(tx);
+		let result = self.dispatch_until(deadline, || {
+			for event in rx.try_iter() {
+				if pred(&event) {
+					return Some(event);
+				}
+				event.discard();
+			}
+			None
+		});
+		self.stop_capturing_events(listener_counts);
+		rx.try_iter().for_each(ClientEvent::discard);
+		result
+	}
+
+	/// Handles messages until `done` returns something after one of them, or `deadline` passes.
+	fn dispatch_until<T>(
+		&mut self,
+		deadline: Instant,
+		mut done: impl FnMut() -> Option<T>,
+	) -> Result<Option<T>, TabClientError> {
+		loop {
+			match self.reader.read_framed(self.sender.socket()) {
+				Ok(frame) => {
+					let message = TabMessage::try_from(frame)?;
+					self.handle_message(message)?;
+					if let Some(value) = done() {
+						return Ok(Some(value));
+					}
+				}
+				Err(tab_protocol::ProtocolError::WouldBlock) => {
+					if Instant::now() >= deadline {
+						return Ok(None);
+					}
+					self.poll_socket_until(deadline)?;
+				}
+				Err(other) => return Err(other.into()),
+			}
+		}
+	}
+
+	/// Draws every monitor with `renderer` until it returns [`Draw::Stop`], handling events,
+	/// buffer releases and hotplug in between. Listeners still get every event. Needs a render
+	/// backend, so it isn't available after [`TabClient::split`].
+	pub fn run(&mut self, renderer: impl Renderer) -> Result<(), TabClientError> {
+		let (tx, rx) = mpsc::channel();
+		let listener_counts = self.capture_events(tx);
+		let result = runtime::Runtime::new(self, renderer, rx).run();
+		self.stop_capturing_events(listener_counts);
+		result
+	}
+
+	/// Returns a monitor, waiting up to `timeout` for shift to report one if none is known yet.
+	pub fn wait_for_monitor(
+		&mut self,
+		timeout: Duration,
+	) -> Result<Option<MonitorState>, TabClientError> {
+		if let Some(monitor) = self.monitors().next() {
+			return Ok(Some(monitor.clone()));
+		}
+		let event = self.wait_for_event(timeout, |event| {
+			matches!(event, ClientEvent::Monitor(MonitorEvent::Added(_)))
+		})?;
+		Ok(match event {
+			Some(ClientEvent::Monitor(MonitorEvent::Added(monitor))) => Some(monitor),
+			_ => None,
+		})
+	}
+
+	/// Adds listeners sending every event to `tx`, and returns how many listeners there were
+	/// before.
+	fn capture_events(&mut self, tx: mpsc::Sender<ClientEvent>) -> [usize; 4] {
+		let counts = [
+			self.monitor_listeners.len(),
+			self.render_listeners.len(),
+			self.session_listeners.len(),
+			self.input_listeners.len(),
+		];
+		let monitor_tx = tx.clone();
+		self.on_monitor_event(move |event| {
+			let _ = monitor_tx.send(ClientEvent::Monitor(event.clone()));
+		});
+		let render_tx = tx.clone();
+		self.on_render_event(move |event| {
+			let _ = render_tx.send(ClientEvent::Render(event.clone()));
+		});
+		let session_tx = tx.clone();
+		self.on_session_event(move |event| {
+			let _ = session_tx.send(ClientEvent::Session(event.clone()));
+		});
+		self.on_input_event(move |event| {
+			let _ = tx.send(ClientEvent::Input(event.clone()));
+		});
+		counts
+	}
+
+	fn stop_capturing_events(&mut self, [monitor, render, session, input]: [usize; 4]) {
+		self.monitor_listeners.truncate(monitor);
+		self.render_listeners.truncate(render);
+		self.session_listeners.truncate(session);
+		self.input_listeners.truncate(input);
+	}
+
 	fn read_message(
-		socket: &UnixStream,
+		socket: &AnyTransport,
 		reader: &mut TabMessageFrameReader,
 	) -> Result<TabMessage, TabClientError> {
 		let frame = reader.read_framed(socket)?;
@@ -234,7 +1210,7 @@ impl TabClient {
 	}
 
 	fn wait_for_auth(
-		socket: &UnixStream,
+		socket: &AnyTransport,
 		reader: &mut TabMessageFrameReader,
 	) -> Result<AuthOkPayload, TabClientError> {
 		loop {
@@ -262,6 +1238,9 @@ impl TabClient {
 			TabMessage::MonitorRemoved(payload) => {
 				self.handle_monitor_removed(payload.monitor_id, payload.name);
 			}
+			TabMessage::MonitorChanged(payload) => {
+				self.handle_monitor_changed(payload.monitor);
+			}
 			TabMessage::SessionCreated(payload) => {
 				self.handle_session_created(payload.session, payload.token);
 			}
@@ -271,21 +1250,118 @@ impl TabClient {
 			} => {
 				self.handle_buffer_release(payload, release_fence);
 			}
+			TabMessage::BufferReleases {
+				payload,
+				release_fences,
+			} => {
+				for (payload, release_fence) in payload.into_releases(release_fences) {
+					self.handle_buffer_release(payload, release_fence);
+				}
+			}
+			TabMessage::FramebufferLinkFailed(payload) => {
+				self.handle_framebuffer_link_failed(payload);
+			}
+			TabMessage::RelinkRequest(RelinkRequestPayload { monitor_id }) => {
+				self.handle_relink_request(monitor_id);
+			}
+			TabMessage::BuffersInvalidated(payload) => {
+				self.handle_buffers_invalidated(payload.monitor_ids)?;
+			}
+			TabMessage::FramesDropped(payload) => {
+				self.handle_frames_dropped(payload);
+			}
+			TabMessage::PerformanceWarning(payload) => {
+				self.handle_performance_warning(payload);
+			}
 			TabMessage::SessionAwake(SessionAwakePayload { session_id }) => {
 				self.handle_session_awake(session_id);
 			}
 			TabMessage::SessionSleep(SessionSleepPayload { session_id }) => {
 				self.handle_session_sleep(session_id);
 			}
-			TabMessage::SessionActive(SessionActivePayload { session_id }) => {
+			TabMessage::SessionVisibility(SessionVisibilityPayload { visible }) => {
+				self.emit_session_event(SessionEvent::Visibility { visible });
+			}
+			TabMessage::SessionActive(SessionActivePayload { session_id, .. }) => {
 				self.handle_session_active(session_id);
 			}
-			TabMessage::SessionState(SessionStatePayload { session }) => {
+			TabMessage::SessionInactive(SessionInactivePayload { session_id }) => {
+				self.emit_session_event(SessionEvent::Inactive(session_id));
+			}
+			TabMessage::SessionState(SessionStatePayload { session, .. }) => {
 				self.handle_session_state(session);
 			}
+			TabMessage::FocusIn(FocusPayload { session_id, target }) => {
+				self.handle_session_focus(session_id, target, true);
+			}
+			TabMessage::FocusOut(FocusPayload { session_id, target }) => {
+				self.handle_session_focus(session_id, target, false);
+			}
+			TabMessage::PointerLockState(PointerLockStatePayload { locked }) => {
+				self.handle_pointer_lock_state(locked);
+			}
+			TabMessage::SplashEnded => {
+				self.handle_splash_ended();
+			}
+			TabMessage::Resumed => {
+				self.emit_session_event(SessionEvent::Resumed);
+			}
+			TabMessage::CompositorHealth(payload) => {
+				self.handle_compositor_health(payload);
+			}
+			TabMessage::SessionUnresponsive(SessionUnresponsivePayload {
+				session_id,
+				unresponsive,
+				idle_ms,
+			}) => {
+				self.emit_session_event(SessionEvent::Unresponsive {
+					session_id,
+					unresponsive,
+					idle_ms,
+				});
+			}
+			TabMessage::SessionCrashed(SessionCrashedPayload {
+				session_id,
+				grace_ms,
+			}) => {
+				self.emit_session_event(SessionEvent::Crashed {
+					session_id,
+					grace_ms,
+				});
+			}
+			TabMessage::LidClosed(LidClosedPayload { closed }) => {
+				self.emit_session_event(SessionEvent::LidClosed { closed });
+			}
+			TabMessage::TabletMode(TabletModePayload { enabled }) => {
+				self.emit_session_event(SessionEvent::TabletMode { enabled });
+			}
 			TabMessage::InputEvent(payload) => {
 				self.handle_input_event(payload);
 			}
+			TabMessage::ShortcutTriggered(ShortcutTriggeredPayload { id }) => {
+				self.handle_shortcut_triggered(id);
+			}
+			TabMessage::ServerShutdown(ServerShutdownPayload { reason }) => {
+				return Err(TabClientError::ServerShutdown(reason));
+			}
+			TabMessage::BufferRequestAck(BufferRequestAckPayload {
+				monitor_id,
+				buffer,
+				generation,
+			}) => {
+				self.forward_to_gfx(GfxEvent::BufferRequestAck {
+					monitor_id,
+					buffer,
+					generation,
+				});
+			}
+			TabMessage::Error(err) => {
+				let details = err
+					.message
+					.map(|m| format!("{}: {m}", err.code))
+					.unwrap_or(err.code);
+				self.forward_to_gfx(GfxEvent::Error(details));
+			}
 			_ => {}
 		}
 		Ok(())
@@ -298,14 +1374,34 @@ impl TabClient {
 		for listener in &self.monitor_listeners {
 			listener(&event);
 		}
+		self.forward_to_gfx(GfxEvent::Monitor(event));
 	}
 
 	fn handle_monitor_removed(&mut self, monitor_id: String, name: String) {
 		self.monitors.remove(&monitor_id);
+		self.frame_skips.forget(&monitor_id);
+		self.linked_buffers.forget(&monitor_id);
 		let event = MonitorEvent::Removed { monitor_id, name };
 		for listener in &self.monitor_listeners {
 			listener(&event);
 		}
+		self.forward_to_gfx(GfxEvent::Monitor(event));
+	}
+
+	fn handle_monitor_changed(&mut self, info: MonitorInfo) {
+		let mut state = MonitorState::new(info);
+		// What shift can import doesn't depend on the mode.
+		state.supported_formats = self
+			.monitors
+			.get(&state.info.id)
+			.and_then(|monitor| monitor.supported_formats.clone());
+		self.monitors.insert(state.info.id.clone(), state.clone());
+		self.frame_skips.forget(&state.info.id);
+		let event = MonitorEvent::Changed(state);
+		for listener in &self.monitor_listeners {
+			listener(&event);
+		}
+		self.forward_to_gfx(GfxEvent::Monitor(event));
 	}
 
 	fn handle_buffer_release(
@@ -315,6 +1411,21 @@ impl TabClient {
 	) {
 		let monitor_id = payload.monitor_id;
 		let buffer = payload.buffer;
+		// The buffer belongs to a swapchain that was linked again since.
+		if !self
+			.link_generations
+			.is_current(&monitor_id, payload.generation)
+		{
+			return;
+		}
+		if self.gfx_events.is_some() {
+			self.forward_to_gfx(GfxEvent::BufferReleased {
+				monitor_id,
+				buffer,
+				release_fence,
+			});
+			return;
+		}
 		for listener in &self.render_listeners {
 			let release_fence_fd = release_fence
 				.as_ref()
@@ -329,6 +1440,86 @@ impl TabClient {
 		}
 	}
 
+	fn handle_framebuffer_link_failed(&mut self, payload: FramebufferLinkFailedPayload) {
+		if let Some(monitor) = self.monitors.get_mut(&payload.monitor_id) {
+			monitor.supported_formats = Some(payload.supported_formats.clone());
+		}
+		if self.gfx_events.is_some() {
+			self.forward_to_gfx(GfxEvent::FramebufferLinkFailed(payload));
+			return;
+		}
+		let event = RenderEvent::FramebufferLinkFailed {
+			monitor_id: payload.monitor_id,
+			reason: payload.reason,
+			supported_formats: payload.supported_formats,
+		};
+		for listener in &self.render_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_relink_request(&mut self, monitor_id: String) {
+		if self.gfx_events.is_some() {
+			self.forward_to_gfx(GfxEvent::RelinkRequested(monitor_id));
+			return;
+		}
+		let event = RenderEvent::RelinkRequested { monitor_id };
+		for listener in &self.render_listeners {
+			listener(&event);
+		}
+	}
+
+	/// Links the buffers shift lost again before the app hears about it, so it only has to redraw.
+	fn handle_buffers_invalidated(&mut self, monitor_ids: Vec<String>) -> Result<(), TabClientError> {
+		for frame in self
+			.linked_buffers
+			.relink_frames(&monitor_ids, &self.link_generations)?
+		{
+			self.send_frame(frame)?;
+		}
+		if self.gfx_events.is_some() {
+			self.forward_to_gfx(GfxEvent::BuffersInvalidated(monitor_ids));
+			return Ok(());
+		}
+		let event = RenderEvent::BuffersInvalidated { monitor_ids };
+		for listener in &self.render_listeners {
+			listener(&event);
+		}
+		Ok(())
+	}
+
+	fn handle_frames_dropped(&mut self, payload: FramesDroppedPayload) {
+		if self.gfx_events.is_some() {
+			self.forward_to_gfx(GfxEvent::FramesDropped(payload));
+			return;
+		}
+		self.frame_skips.dropped(payload.count);
+		let event = RenderEvent::FramesDropped {
+			monitor_id: payload.monitor_id,
+			count: payload.count,
+			reason: payload.reason,
+		};
+		for listener in &self.render_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_performance_warning(&mut self, payload: PerformanceWarningPayload) {
+		if self.gfx_events.is_some() {
+			self.forward_to_gfx(GfxEvent::PerformanceWarning(payload));
+			return;
+		}
+		let event = RenderEvent::PerformanceWarning {
+			monitor_id: payload.monitor_id,
+			kind: payload.kind,
+			duration_ms: payload.duration_ms,
+			frames: payload.frames,
+		};
+		for listener in &self.render_listeners {
+			listener(&event);
+		}
+	}
+
 	fn handle_session_awake(&mut self, session_id: String) {
 		let event = SessionEvent::Awake(session_id);
 		for listener in &self.session_listeners {
@@ -364,6 +1555,51 @@ impl TabClient {
 		}
 	}
 
+	fn handle_session_focus(&mut self, session_id: String, target: FocusTarget, focused: bool) {
+		let event = if focused {
+			SessionEvent::FocusIn { session_id, target }
+		} else {
+			SessionEvent::FocusOut { session_id, target }
+		};
+		for listener in &self.session_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_pointer_lock_state(&mut self, locked: bool) {
+		let event = SessionEvent::PointerLock { locked };
+		for listener in &self.session_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_splash_ended(&mut self) {
+		let event = SessionEvent::SplashEnded;
+		for listener in &self.session_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_compositor_health(&mut self, payload: CompositorHealthPayload) {
+		let event = SessionEvent::CompositorHealth(payload);
+		for listener in &self.session_listeners {
+			listener(&event);
+		}
+	}
+
+	fn emit_session_event(&mut self, event: SessionEvent) {
+		for listener in &self.session_listeners {
+			listener(&event);
+		}
+	}
+
+	fn handle_shortcut_triggered(&mut self, id: String) {
+		let event = InputEvent::ShortcutTriggered { id };
+		for listener in &self.input_listeners {
+			listener(&event);
+		}
+	}
+
 	fn handle_input_event(&mut self, payload: InputEventPayload) {
 		let event = InputEvent::Event(payload);
 		for listener in &self.input_listeners {
@@ -371,27 +1607,22 @@ impl TabClient {
 		}
 	}
 
-	fn wait_for_buffer_request_ack(
+	/// Waits for the ack of every `(monitor_id, buffer, generation)` request, in any order.
+	fn wait_for_buffer_request_acks(
 		&mut self,
-		monitor_id: &str,
-		buffer: BufferIndex,
+		mut expected: Vec<(String, BufferIndex, u64)>,
 	) -> Result<(), TabClientError> {
 		let deadline = Instant::now() + Self::BUFFER_REQUEST_ACK_TIMEOUT;
-		loop {
+		while !expected.is_empty() {
 			if Instant::now() >= deadline {
 				return Err(TabClientError::Unexpected("buffer_request_ack timeout"));
 			}
-			match self.reader.read_framed(&self.socket) {
+			match self.reader.read_framed(self.sender.socket()) {
 				Ok(frame) => {
 					let message = TabMessage::try_from(frame)?;
 					match message {
-						TabMessage::BufferRequestAck(BufferRequestAckPayload {
-							monitor_id: ack_monitor,
-							buffer: ack_buffer,
-						}) => {
-							if ack_monitor == monitor_id && ack_buffer == buffer {
-								return Ok(());
-							}
+						TabMessage::BufferRequestAck(ack) => {
+							expected.retain(|request| !Self::is_ack_for(&ack, request));
 						}
 						TabMessage::Error(err) => {
 							let details = err
@@ -409,6 +1640,7 @@ impl TabClient {
 				Err(other) => return Err(other.into()),
 			}
 		}
+		Ok(())
 	}
 
 	fn wait_for_session_created(&mut self) -> Result<SessionCreatedPayload, TabClientError> {
@@ -417,7 +1649,7 @@ impl TabClient {
 			if Instant::now() >= deadline {
 				return Err(TabClientError::Unexpected("session_created timeout"));
 			}
-			match self.reader.read_framed(&self.socket) {
+			match self.reader.read_framed(self.sender.socket()) {
 				Ok(frame) => {
 					let message = TabMessage::try_from(frame)?;
 					match message {
@@ -443,6 +1675,77 @@ impl TabClient {
 		}
 	}
 
+	fn wait_for_log_records(&mut self) -> Result<Vec<String>, TabClientError> {
+		let deadline = Instant::now() + Self::LOG_DUMP_TIMEOUT;
+		let mut records = Vec::new();
+		loop {
+			if Instant::now() >= deadline {
+				return Err(TabClientError::Unexpected("log_records timeout"));
+			}
+			match self.reader.read_framed(self.sender.socket()) {
+				Ok(frame) => {
+					let message = TabMessage::try_from(frame)?;
+					match message {
+						TabMessage::LogRecords(LogRecordsPayload {
+							records: mut batch,
+							more,
+						}) => {
+							records.append(&mut batch);
+							if !more {
+								return Ok(records);
+							}
+						}
+						TabMessage::Error(err) => {
+							let details = err
+								.message
+								.map(|m| format!("{}: {m}", err.code))
+								.unwrap_or(err.code);
+							return Err(TabClientError::Server(details));
+						}
+						other => self.handle_message(other)?,
+					}
+				}
+				Err(tab_protocol::ProtocolError::WouldBlock) => {
+					self.poll_socket_until(deadline)?;
+				}
+				Err(other) => return Err(other.into()),
+			}
+		}
+	}
+
+	/// Waits for the reply `pick` accepts, handling everything else as usual. `pick` continues
+	/// with messages it doesn't want.
+	fn wait_for_reply<T>(
+		&mut self,
+		timeout: Duration,
+		timeout_error: &'static str,
+		mut pick: impl FnMut(TabMessage) -> ControlFlow<T, TabMessage>,
+	) -> Result<T, TabClientError> {
+		let deadline = Instant::now() + timeout;
+		loop {
+			if Instant::now() >= deadline {
+				return Err(TabClientError::Unexpected(timeout_error));
+			}
+			match self.reader.read_framed(self.sender.socket()) {
+				Ok(frame) => match pick(TabMessage::try_from(frame)?) {
+					ControlFlow::Break(reply) => return Ok(reply),
+					ControlFlow::Continue(TabMessage::Error(err)) => {
+						let details = err
+							.message
+							.map(|m| format!("{}: {m}", err.code))
+							.unwrap_or(err.code);
+						return Err(TabClientError::Server(details));
+					}
+					ControlFlow::Continue(other) => self.handle_message(other)?,
+				},
+				Err(tab_protocol::ProtocolError::WouldBlock) => {
+					self.poll_socket_until(deadline)?;
+				}
+				Err(other) => return Err(other.into()),
+			}
+		}
+	}
+
 	fn poll_socket_until(&self, deadline: Instant) -> Result<(), TabClientError> {
 		let now = Instant::now();
 		if now >= deadline {
@@ -451,7 +1754,7 @@ impl TabClient {
 		let remaining = deadline.saturating_duration_since(now);
 		let timeout_ms = (remaining.as_millis().max(1).min(i32::MAX as u128)) as i32;
 		let mut pfd = libc::pollfd {
-			fd: self.socket.as_raw_fd(),
+			fd: self.socket_fd(),
 			events: libc::POLLIN | libc::POLLERR | libc::POLLHUP,
 			revents: 0,
 		};
@@ -468,3 +1771,4 @@ impl TabClient {
 		}
 	}
 }
+
@@ -0,0 +1,37 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::MonitorId;
+
+/// `generation` of each monitor's latest `framebuffer_link`. Shared by both halves of a split
+/// client: the render half links, the Io half drops releases meant for an earlier link.
+#[derive(Debug, Default)]
+pub(crate) struct LinkGenerations {
+	latest: Mutex<HashMap<MonitorId, u64>>,
+}
+
+impl LinkGenerations {
+	/// Starts the generation of a link of `monitor_id` about to be sent.
+	pub(crate) fn next(&self, monitor_id: &str) -> u64 {
+		let mut latest = self.latest.lock().unwrap();
+		let generation = latest.entry(monitor_id.to_string()).or_default();
+		*generation += 1;
+		*generation
+	}
+
+	/// 0 until the monitor was linked.
+	pub(crate) fn current(&self, monitor_id: &str) -> u64 {
+		self
+			.latest
+			.lock()
+			.unwrap()
+			.get(monitor_id)
+			.copied()
+			.unwrap_or(0)
+	}
+
+	/// Whether an ack or release tagged `generation` is about the current link. Untagged ones,
+	/// e.g. from an older shift, always are.
+	pub(crate) fn is_current(&self, monitor_id: &str, generation: u64) -> bool {
+		generation == 0 || generation == self.current(monitor_id)
+	}
+}
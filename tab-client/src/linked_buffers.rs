@@ -0,0 +1,62 @@
+use std::{collections::HashMap, io, os::fd::OwnedFd, sync::Mutex};
+
+use tab_protocol::{FramebufferLinkPayload, TabMessageFrame, message_header};
+
+use crate::{MonitorId, link_generation::LinkGenerations};
+
+struct Link {
+	payload: FramebufferLinkPayload,
+	dma_bufs: [OwnedFd; 2],
+}
+
+/// What each monitor's latest `framebuffer_link` sent, to send it again after
+/// `buffers_invalidated` without the app. Shared by both halves of a split client like
+/// [`LinkGenerations`]. Holding duplicates of the dma-buf FDs keeps a swapchain allocated until
+/// another one is linked for its monitor or the monitor goes away.
+#[derive(Default)]
+pub(crate) struct LinkedBuffers {
+	latest: Mutex<HashMap<MonitorId, Link>>,
+}
+
+impl LinkedBuffers {
+	pub(crate) fn record(&self, payload: &FramebufferLinkPayload, fds: &[OwnedFd]) -> io::Result<()> {
+		let [fd0, fd1] = fds else {
+			return Ok(());
+		};
+		let link = Link {
+			payload: payload.clone(),
+			dma_bufs: [fd0.try_clone()?, fd1.try_clone()?],
+		};
+		self
+			.latest
+			.lock()
+			.unwrap()
+			.insert(payload.monitor_id.clone(), link);
+		Ok(())
+	}
+
+	pub(crate) fn forget(&self, monitor_id: &str) {
+		self.latest.lock().unwrap().remove(monitor_id);
+	}
+
+	/// `framebuffer_link` frames linking the latest buffers of each of `monitor_ids` again.
+	/// Monitors never linked are skipped.
+	pub(crate) fn relink_frames(
+		&self,
+		monitor_ids: &[String],
+		link_generations: &LinkGenerations,
+	) -> io::Result<Vec<TabMessageFrame>> {
+		let latest = self.latest.lock().unwrap();
+		let mut frames = Vec::new();
+		for link in monitor_ids.iter().filter_map(|id| latest.get(id)) {
+			let payload = FramebufferLinkPayload {
+				generation: link_generations.next(&link.payload.monitor_id),
+				..link.payload.clone()
+			};
+			let mut frame = TabMessageFrame::json(message_header::FRAMEBUFFER_LINK, payload);
+			frame.fds = vec![link.dma_bufs[0].try_clone()?, link.dma_bufs[1].try_clone()?];
+			frames.push(frame);
+		}
+		Ok(frames)
+	}
+}
@@ -5,10 +5,24 @@ pub type MonitorId = String;
 #[derive(Debug, Clone)]
 pub struct MonitorState {
 	pub info: MonitorInfo,
+	/// Formats shift listed after failing to import a swapchain for this monitor. Backends should
+	/// allocate one of these when set.
+	pub supported_formats: Option<Vec<i32>>,
 }
 
 impl MonitorState {
 	pub fn new(info: MonitorInfo) -> Self {
-		Self { info }
+		Self {
+			info,
+			supported_formats: None,
+		}
+	}
+
+	/// Whether buffers in `fourcc` are worth linking for this monitor.
+	pub fn accepts_format(&self, fourcc: i32) -> bool {
+		self
+			.supported_formats
+			.as_ref()
+			.is_none_or(|formats| formats.is_empty() || formats.contains(&fourcc))
 	}
 }
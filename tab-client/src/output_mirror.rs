@@ -0,0 +1,168 @@
+//! Mirrors shift's monitors as Wayland outputs, for session clients that are compositors.
+//! - [`OutputDescription`] holds what `wl_output` and `xdg_output` announce for a monitor, keyed
+//!   by its shift id so a global survives mode changes
+//! - shift has no monitor arrangement, so outputs sit left to right ordered by id, like
+//!   `layout_horizontal` of the app framework, at logical (scaled) sizes. Scales default to 1
+//! - every change comes back as [`OutputChange`]s: add a global for `Added`, remove it for
+//!   `Removed`, and resend its events then `done` for `Updated`
+
+use std::collections::BTreeMap;
+
+use tab_protocol::MonitorInfo;
+
+use crate::{MonitorEvent, MonitorId, MonitorState};
+
+/// What a compositor announces for one monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDescription {
+	pub monitor_id: MonitorId,
+	/// `wl_output.name` and `xdg_output.name`.
+	pub name: String,
+	/// `wl_output.description`, e.g. "Dell Inc. U2720Q (Monitor 42)".
+	pub description: String,
+	pub make: String,
+	pub model: String,
+	pub serial: Option<String>,
+	/// Physical size in mm, 0 when unknown as `wl_output.geometry` expects.
+	pub physical_width: i32,
+	pub physical_height: i32,
+	/// Current mode in pixels.
+	pub width: i32,
+	pub height: i32,
+	/// Refresh rate in mHz, as `wl_output.mode` expects.
+	pub refresh: i32,
+	pub scale: i32,
+	/// `xdg_output.logical_position`.
+	pub x: i32,
+	pub y: i32,
+	/// `xdg_output.logical_size`: the mode divided by the scale.
+	pub logical_width: i32,
+	pub logical_height: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputChange {
+	Added(OutputDescription),
+	Removed(MonitorId),
+	Updated(OutputDescription),
+}
+
+#[derive(Debug, Default)]
+pub struct OutputMirror {
+	monitors: BTreeMap<MonitorId, MonitorInfo>,
+	scales: BTreeMap<MonitorId, i32>,
+	outputs: BTreeMap<MonitorId, OutputDescription>,
+}
+
+impl OutputMirror {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Replaces every monitor, e.g. with [`crate::TabClient::monitors`] after connecting.
+	pub fn sync<'a>(
+		&mut self,
+		monitors: impl IntoIterator<Item = &'a MonitorState>,
+	) -> Vec<OutputChange> {
+		self.monitors = monitors
+			.into_iter()
+			.map(|monitor| (monitor.info.id.clone(), monitor.info.clone()))
+			.collect();
+		let monitors = &self.monitors;
+		self
+			.scales
+			.retain(|monitor_id, _| monitors.contains_key(monitor_id));
+		self.update()
+	}
+
+	/// Follows a hotplug or mode change.
+	pub fn handle_event(&mut self, event: &MonitorEvent) -> Vec<OutputChange> {
+		match event {
+			MonitorEvent::Added(monitor) | MonitorEvent::Changed(monitor) => {
+				self
+					.monitors
+					.insert(monitor.info.id.clone(), monitor.info.clone());
+			}
+			MonitorEvent::Removed { monitor_id, .. } => {
+				self.monitors.remove(monitor_id);
+				self.scales.remove(monitor_id);
+			}
+		}
+		self.update()
+	}
+
+	/// Sets the integer scale an output announces, moving the outputs right of it along.
+	pub fn set_scale(&mut self, monitor_id: &str, scale: i32) -> Vec<OutputChange> {
+		if self.monitors.contains_key(monitor_id) {
+			self.scales.insert(monitor_id.to_string(), scale.max(1));
+		}
+		self.update()
+	}
+
+	pub fn get(&self, monitor_id: &str) -> Option<&OutputDescription> {
+		self.outputs.get(monitor_id)
+	}
+
+	/// Every output, ordered left to right.
+	pub fn outputs(&self) -> impl Iterator<Item = &OutputDescription> {
+		self.outputs.values()
+	}
+
+	fn update(&mut self) -> Vec<OutputChange> {
+		let mut x = 0;
+		let mut outputs = BTreeMap::new();
+		for (monitor_id, info) in &self.monitors {
+			let scale = self.scales.get(monitor_id).copied().unwrap_or(1);
+			let output = describe(info, scale, x);
+			x += output.logical_width;
+			outputs.insert(monitor_id.clone(), output);
+		}
+		let mut changes = self
+			.outputs
+			.keys()
+			.filter(|monitor_id| !outputs.contains_key(*monitor_id))
+			.map(|monitor_id| OutputChange::Removed(monitor_id.clone()))
+			.collect::<Vec<_>>();
+		for (monitor_id, output) in &outputs {
+			match self.outputs.get(monitor_id) {
+				None => changes.push(OutputChange::Added(output.clone())),
+				Some(previous) if previous != output => {
+					changes.push(OutputChange::Updated(output.clone()));
+				}
+				Some(_) => {}
+			}
+		}
+		self.outputs = outputs;
+		changes
+	}
+}
+
+fn describe(info: &MonitorInfo, scale: i32, x: i32) -> OutputDescription {
+	let make = info
+		.manufacturer
+		.clone()
+		.unwrap_or_else(|| "Unknown".into());
+	let model = info.model.clone().unwrap_or_else(|| "Unknown".into());
+	let description = match (&info.manufacturer, &info.model) {
+		(None, None) => info.name.clone(),
+		_ => format!("{make} {model} ({})", info.name),
+	};
+	OutputDescription {
+		monitor_id: info.id.clone(),
+		name: info.name.clone(),
+		description,
+		make,
+		model,
+		serial: info.serial.clone(),
+		physical_width: info.width_mm.map_or(0, |mm| mm as i32),
+		physical_height: info.height_mm.map_or(0, |mm| mm as i32),
+		width: info.width,
+		height: info.height,
+		refresh: info.refresh_rate * 1000,
+		scale,
+		x,
+		y: 0,
+		logical_width: info.width / scale,
+		logical_height: info.height / scale,
+	}
+}
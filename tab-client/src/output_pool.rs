@@ -0,0 +1,168 @@
+//! Per-output render workers, for clients driving several monitors.
+//! - one thread per monitor, each with its own context made by the caller's factory, e.g. a GL
+//!   context shared with the main one
+//! - jobs for different monitors run in parallel, jobs for one monitor run in order
+//! - finished frames come back to the thread owning the [`TabClientGfx`] to be presented
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::os::fd::OwnedFd;
+use std::sync::{Arc, mpsc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use tab_protocol::BufferIndex;
+
+use crate::{MonitorId, MonitorState, TabClientError, TabClientGfx};
+
+pub type JobError = Box<dyn Error + Send + Sync>;
+
+type Factory<C> = dyn Fn(&MonitorState) -> Result<C, JobError> + Send + Sync;
+type Job<C> = Box<dyn FnOnce(&mut C) -> Result<Option<OwnedFd>, JobError> + Send>;
+
+/// A frame a worker finished drawing, with the acquire fence its job returned.
+pub struct FinishedFrame {
+	pub monitor_id: MonitorId,
+	pub buffer: BufferIndex,
+	pub result: Result<Option<OwnedFd>, JobError>,
+}
+
+impl FinishedFrame {
+	/// Presents the frame, or reports why its job failed. The fence is closed once it is sent.
+	pub fn present(self, gfx: &mut TabClientGfx) -> Result<(), TabClientError> {
+		let fence = self
+			.result
+			.map_err(|e| TabClientError::RenderWorker(e.to_string()))?;
+		gfx.request_buffer(&self.monitor_id, self.buffer, fence)
+	}
+}
+
+struct Worker<C> {
+	jobs: mpsc::Sender<(BufferIndex, Job<C>)>,
+	thread: JoinHandle<()>,
+}
+
+/// Runs draw jobs on one thread per monitor. `C` is whatever each thread renders with, created
+/// on that thread so thread-affine contexts work.
+pub struct OutputPool<C> {
+	factory: Arc<Factory<C>>,
+	workers: HashMap<MonitorId, Worker<C>>,
+	finished_tx: mpsc::Sender<FinishedFrame>,
+	finished_rx: mpsc::Receiver<FinishedFrame>,
+}
+
+impl<C: 'static> OutputPool<C> {
+	pub fn new<F>(factory: F) -> Self
+	where
+		F: Fn(&MonitorState) -> Result<C, JobError> + Send + Sync + 'static,
+	{
+		let (finished_tx, finished_rx) = mpsc::channel();
+		Self {
+			factory: Arc::new(factory),
+			workers: HashMap::new(),
+			finished_tx,
+			finished_rx,
+		}
+	}
+
+	/// Starts a worker for `monitor`, replacing any previous one, and waits for its context.
+	pub fn add_output(&mut self, monitor: &MonitorState) -> Result<(), TabClientError> {
+		self.remove_output(&monitor.info.id);
+		let (jobs, job_rx) = mpsc::channel::<(BufferIndex, Job<C>)>();
+		let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+		let factory = Arc::clone(&self.factory);
+		let finished = self.finished_tx.clone();
+		let monitor = monitor.clone();
+		let monitor_id = monitor.info.id.clone();
+		let thread = thread::Builder::new()
+			.name(format!("tab-output-{monitor_id}"))
+			.spawn(move || {
+				let mut context = match factory(&monitor) {
+					Ok(context) => context,
+					Err(e) => {
+						let _ = ready_tx.send(Err(e.to_string()));
+						return;
+					}
+				};
+				let _ = ready_tx.send(Ok(()));
+				for (buffer, job) in job_rx {
+					let frame = FinishedFrame {
+						monitor_id: monitor.info.id.clone(),
+						buffer,
+						result: job(&mut context),
+					};
+					if finished.send(frame).is_err() {
+						break;
+					}
+				}
+			})?;
+		match ready_rx.recv() {
+			Ok(Ok(())) => {
+				self.workers.insert(monitor_id, Worker { jobs, thread });
+				Ok(())
+			}
+			Ok(Err(reason)) => {
+				let _ = thread.join();
+				Err(TabClientError::RenderWorker(reason))
+			}
+			Err(_) => {
+				let _ = thread.join();
+				Err(TabClientError::RenderWorker(format!(
+					"worker for {monitor_id} exited during setup"
+				)))
+			}
+		}
+	}
+
+	/// Stops the worker of a monitor that went away, after its queued jobs ran.
+	pub fn remove_output(&mut self, monitor_id: &str) {
+		if let Some(worker) = self.workers.remove(monitor_id) {
+			drop(worker.jobs);
+			let _ = worker.thread.join();
+		}
+	}
+
+	pub fn has_output(&self, monitor_id: &str) -> bool {
+		self.workers.contains_key(monitor_id)
+	}
+
+	/// Queues a job drawing into `buffer` of `monitor_id`. It returns the acquire fence to present
+	/// the buffer with, if the renderer made one.
+	pub fn render<F>(
+		&self,
+		monitor_id: &str,
+		buffer: BufferIndex,
+		job: F,
+	) -> Result<(), TabClientError>
+	where
+		F: FnOnce(&mut C) -> Result<Option<OwnedFd>, JobError> + Send + 'static,
+	{
+		let worker = self
+			.workers
+			.get(monitor_id)
+			.ok_or_else(|| TabClientError::UnknownMonitor(monitor_id.to_string()))?;
+		worker
+			.jobs
+			.send((buffer, Box::new(job)))
+			.map_err(|_| TabClientError::RenderWorker(format!("worker for {monitor_id} exited")))
+	}
+
+	/// Frames finished since the last call, in the order workers finished them.
+	pub fn finished(&self) -> impl Iterator<Item = FinishedFrame> + '_ {
+		self.finished_rx.try_iter()
+	}
+
+	/// Waits up to `timeout` for the next finished frame.
+	pub fn wait_finished(&self, timeout: Duration) -> Option<FinishedFrame> {
+		self.finished_rx.recv_timeout(timeout).ok()
+	}
+}
+
+impl<C> Drop for OutputPool<C> {
+	fn drop(&mut self) {
+		for (_, worker) in self.workers.drain() {
+			drop(worker.jobs);
+			let _ = worker.thread.join();
+		}
+	}
+}
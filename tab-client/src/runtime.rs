@@ -0,0 +1,283 @@
+//! [`TabClient::run`]: a render loop for clients that draw every monitor from one callback.
+//! - every monitor gets a swapchain, including ones plugged in later, and a new one when shift
+//!   can't import it. A mode change resizes it, see [`Renderer::monitor_resized`]
+//! - a monitor is drawn whenever one of its buffers is back from shift, so drawing keeps pace with
+//!   shift releasing them. After [`Draw::Skip`] the monitor waits a refresh interval instead
+//! - returns after [`Draw::Stop`], or with the error that ended the connection. Swapchains are
+//!   dropped either way
+
+use std::{
+	collections::HashMap,
+	os::fd::{FromRawFd, OwnedFd},
+	sync::mpsc,
+	time::{Duration, Instant},
+};
+
+use tab_protocol::BufferIndex;
+
+use crate::{
+	ClientEvent, MonitorEvent, MonitorId, MonitorState, RenderEvent, TabBuffer, TabClient,
+	TabClientError, TabSwapchain,
+};
+
+/// How long to block on the socket when no monitor has a deadline.
+const IDLE_WAIT: Duration = Duration::from_secs(1);
+
+/// What to do with a frame drawn by a [`Renderer`].
+#[derive(Debug)]
+pub enum Draw {
+	/// Present the frame, once the acquire fence signals if there is one.
+	Present(Option<OwnedFd>),
+	/// Nothing changed, keep the previous frame on screen.
+	Skip,
+	/// Leave [`TabClient::run`] without presenting.
+	Stop,
+}
+
+/// The buffer a frame is drawn into.
+#[derive(Debug)]
+pub struct FrameTarget<'a> {
+	pub buffer: &'a TabBuffer,
+	pub index: BufferIndex,
+	/// Signals once shift stopped reading the buffer. Wait on it before drawing.
+	pub release_fence: Option<OwnedFd>,
+}
+
+/// Draws the monitors of [`TabClient::run`]. Closures taking the arguments of
+/// [`Renderer::draw`] are renderers that ignore hotplug.
+pub trait Renderer {
+	/// Draws a frame for `monitor`, `dt` after its previous one (zero for the first).
+	fn draw(&mut self, monitor: &MonitorState, target: FrameTarget<'_>, dt: Duration) -> Draw;
+
+	/// `monitor` got a new swapchain: it was plugged in, or is drawn for the first time.
+	fn monitor_added(&mut self, _monitor: &MonitorState) {}
+
+	/// `monitor` changed mode and its swapchain was resized, see [`TabClient::resize_output`].
+	/// Buffer indices keep their meaning, so usually only the viewport needs updating. Unless
+	/// overridden, handled like [`Renderer::monitor_added`].
+	fn monitor_resized(&mut self, monitor: &MonitorState) {
+		self.monitor_added(monitor);
+	}
+
+	/// `monitor_id` was unplugged, and won't be drawn anymore.
+	fn monitor_removed(&mut self, _monitor_id: &str) {}
+}
+
+impl<F> Renderer for F
+where
+	F: FnMut(&MonitorState, FrameTarget<'_>, Duration) -> Draw,
+{
+	fn draw(&mut self, monitor: &MonitorState, target: FrameTarget<'_>, dt: Duration) -> Draw {
+		self(monitor, target, dt)
+	}
+}
+
+struct Output {
+	monitor: MonitorState,
+	swapchain: TabSwapchain,
+	release_fences: [Option<OwnedFd>; 2],
+	last_frame: Option<Instant>,
+	/// After a skipped frame, when to draw again.
+	idle_until: Option<Instant>,
+}
+
+impl Output {
+	fn new(monitor: MonitorState, swapchain: TabSwapchain) -> Self {
+		Self {
+			monitor,
+			swapchain,
+			release_fences: [None, None],
+			last_frame: None,
+			idle_until: None,
+		}
+	}
+
+	fn ready(&self, now: Instant) -> bool {
+		self.swapchain.has_free_buffer() && self.idle_until.is_none_or(|until| until <= now)
+	}
+
+	fn refresh_interval(&self) -> Duration {
+		Duration::from_secs(1) / self.monitor.info.refresh_rate.max(1) as u32
+	}
+}
+
+pub(crate) struct Runtime<'a, R> {
+	client: &'a mut TabClient,
+	renderer: R,
+	events: mpsc::Receiver<ClientEvent>,
+	outputs: HashMap<MonitorId, Output>,
+}
+
+impl<'a, R: Renderer> Runtime<'a, R> {
+	pub(crate) fn new(
+		client: &'a mut TabClient,
+		renderer: R,
+		events: mpsc::Receiver<ClientEvent>,
+	) -> Self {
+		Self {
+			client,
+			renderer,
+			events,
+			outputs: HashMap::new(),
+		}
+	}
+
+	pub(crate) fn run(mut self) -> Result<(), TabClientError> {
+		let result = self.draw_until_stopped();
+		self.events.try_iter().for_each(ClientEvent::discard);
+		result
+	}
+
+	fn draw_until_stopped(&mut self) -> Result<(), TabClientError> {
+		let monitors: Vec<MonitorState> = self.client.monitors().cloned().collect();
+		for monitor in monitors {
+			self.add_output(monitor)?;
+		}
+		loop {
+			if self.draw_ready()? {
+				return Ok(());
+			}
+			let now = Instant::now();
+			if self.outputs.values().any(|output| output.ready(now)) {
+				self.client.dispatch_events()?;
+			} else {
+				let deadline = self
+					.outputs
+					.values()
+					.filter_map(|output| output.idle_until)
+					.min()
+					.unwrap_or(now + IDLE_WAIT);
+				let events = &self.events;
+				if let Some(event) = self
+					.client
+					.dispatch_until(deadline, || events.try_recv().ok())?
+				{
+					self.handle_event(event)?;
+				}
+			}
+			while let Ok(event) = self.events.try_recv() {
+				self.handle_event(event)?;
+			}
+		}
+	}
+
+	/// Draws every monitor with a free buffer. Returns whether the renderer asked to stop.
+	fn draw_ready(&mut self) -> Result<bool, TabClientError> {
+		let now = Instant::now();
+		for (monitor_id, output) in &mut self.outputs {
+			if !output.ready(now) {
+				continue;
+			}
+			let Some((buffer, index)) = output.swapchain.acquire_next() else {
+				continue;
+			};
+			let target = FrameTarget {
+				buffer,
+				index,
+				release_fence: output.release_fences[index as usize].take(),
+			};
+			let dt = output.last_frame.map_or(Duration::ZERO, |last| now - last);
+			let draw = self.renderer.draw(&output.monitor, target, dt);
+			output.last_frame = Some(now);
+			match draw {
+				Draw::Present(acquire_fence) => {
+					output.idle_until = None;
+					if let Err(e) = self.client.request_buffer(monitor_id, index, acquire_fence) {
+						output.swapchain.rollback();
+						return Err(e);
+					}
+					output.swapchain.mark_busy(index);
+				}
+				Draw::Skip => {
+					output.swapchain.rollback();
+					output.idle_until = Some(now + output.refresh_interval());
+					self.client.skip_frame(monitor_id)?;
+				}
+				Draw::Stop => {
+					output.swapchain.rollback();
+					return Ok(true);
+				}
+			}
+		}
+		Ok(false)
+	}
+
+	fn handle_event(&mut self, event: ClientEvent) -> Result<(), TabClientError> {
+		match event {
+			ClientEvent::Monitor(MonitorEvent::Added(monitor)) => self.add_output(monitor)?,
+			ClientEvent::Monitor(MonitorEvent::Changed(monitor)) => self.resize_output(monitor)?,
+			ClientEvent::Monitor(MonitorEvent::Removed { monitor_id, .. }) => {
+				if self.outputs.remove(&monitor_id).is_some() {
+					self.renderer.monitor_removed(&monitor_id);
+				}
+			}
+			ClientEvent::Render(RenderEvent::BufferReleased {
+				monitor_id,
+				buffer,
+				release_fence_fd,
+			}) => {
+				// SAFETY: every listener gets its own duplicate of the fence.
+				let fence = release_fence_fd.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) });
+				if let Some(output) = self.outputs.get_mut(&monitor_id) {
+					output.swapchain.mark_released(buffer);
+					output.release_fences[buffer as usize] = fence;
+				}
+			}
+			ClientEvent::Render(RenderEvent::FramebufferLinkFailed { monitor_id, .. }) => {
+				if let Some(monitor) = self.client.monitor(&monitor_id).cloned() {
+					self.add_output(monitor)?;
+				}
+			}
+			ClientEvent::Render(RenderEvent::RelinkRequested { monitor_id }) => {
+				if let Some(output) = self.outputs.get_mut(&monitor_id) {
+					self.client.framebuffer_link(&output.swapchain)?;
+					for index in [BufferIndex::Zero, BufferIndex::One] {
+						output.swapchain.mark_released(index);
+					}
+					output.release_fences = [None, None];
+				}
+			}
+			// Linked again by the client already, but what was shown is gone.
+			ClientEvent::Render(RenderEvent::BuffersInvalidated { monitor_ids }) => {
+				for monitor_id in &monitor_ids {
+					let Some(output) = self.outputs.get_mut(monitor_id) else {
+						continue;
+					};
+					for index in [BufferIndex::Zero, BufferIndex::One] {
+						output.swapchain.mark_released(index);
+					}
+					output.release_fences = [None, None];
+					output.idle_until = None;
+				}
+			}
+			other => other.discard(),
+		}
+		Ok(())
+	}
+
+	/// Gives `monitor` a new swapchain, replacing the one it had.
+	fn add_output(&mut self, monitor: MonitorState) -> Result<(), TabClientError> {
+		let swapchain = self.client.create_swapchain(&monitor.info.id)?;
+		self.renderer.monitor_added(&monitor);
+		self
+			.outputs
+			.insert(monitor.info.id.clone(), Output::new(monitor, swapchain));
+		Ok(())
+	}
+
+	/// Gives `monitor` a swapchain at its new mode, keeping the rest of its output.
+	fn resize_output(&mut self, monitor: MonitorState) -> Result<(), TabClientError> {
+		let Some(output) = self.outputs.get_mut(&monitor.info.id) else {
+			return self.add_output(monitor);
+		};
+		output.swapchain =
+			self
+				.client
+				.resize_output(&monitor.info.id, monitor.info.width, monitor.info.height)?;
+		output.release_fences = [None, None];
+		output.idle_until = None;
+		self.renderer.monitor_resized(&monitor);
+		output.monitor = monitor;
+		Ok(())
+	}
+}
@@ -0,0 +1,554 @@
+//! [`TabClient::split`]: the socket on one thread, swapchains and presenting on another.
+//! - [`TabClientIo`] owns the read side, dispatches events and sends session and admin requests
+//! - [`TabClientGfx`] owns the render backend and presents buffers from the rendering thread
+//! - both write through a shared [`FrameSender`], the Io half forwards render messages over a channel
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::os::fd::{AsFd, IntoRawFd, OwnedFd, RawFd};
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
+use std::time::{Duration, Instant};
+
+use tab_protocol::message_frame::TabMessageFrame;
+use tab_protocol::message_header;
+use tab_protocol::transport::{AnyTransport, Transport, flush_blocking};
+use tab_protocol::{
+	BufferIndex, BufferRequestAckPayload, FramebufferLinkFailedPayload, FramesDroppedPayload,
+	PerformanceWarningPayload, PresentMode, SessionCreatePayload, SessionCreatedPayload, SessionInfo,
+	SessionRole, StatsPayload,
+};
+
+use crate::{
+	ClientEvent, FrameStats, InputEvent, MonitorEvent, MonitorId, MonitorState, RenderBackend,
+	RenderEvent, Screenshot, SessionEvent, TabClient, TabClientError, TabSwapchain,
+	frame_hash::FrameSkips, link_generation::LinkGenerations, linked_buffers::LinkedBuffers,
+};
+
+/// Write side of the connection. Once split, both halves send through it, so whole frames are
+/// written under a lock and never interleave.
+pub(crate) struct FrameSender {
+	socket: AnyTransport,
+	compress_payloads: bool,
+	lock: Mutex<()>,
+}
+
+impl FrameSender {
+	pub(crate) fn new(socket: AnyTransport, compress_payloads: bool) -> Self {
+		Self {
+			socket,
+			compress_payloads,
+			lock: Mutex::new(()),
+		}
+	}
+
+	pub(crate) fn socket(&self) -> &AnyTransport {
+		&self.socket
+	}
+
+	/// Sends a frame, compressing large payloads when compression was negotiated at connect time.
+	pub(crate) fn send(&self, frame: TabMessageFrame) -> Result<(), TabClientError> {
+		let frame = if self.compress_payloads {
+			frame.compressed()?
+		} else {
+			frame
+		};
+		let _guard = self.lock.lock().unwrap_or_else(PoisonError::into_inner);
+		self.socket.send_frame(&frame)?;
+		// Client threads may block until the rest of a frame fits.
+		flush_blocking(&self.socket)?;
+		Ok(())
+	}
+}
+
+/// What the Io half hands over to the render half.
+pub(crate) enum GfxEvent {
+	Monitor(MonitorEvent),
+	BufferReleased {
+		monitor_id: String,
+		buffer: BufferIndex,
+		release_fence: Option<OwnedFd>,
+	},
+	FramebufferLinkFailed(FramebufferLinkFailedPayload),
+	RelinkRequested(String),
+	/// Relinked already by the Io half.
+	BuffersInvalidated(Vec<String>),
+	FramesDropped(FramesDroppedPayload),
+	PerformanceWarning(PerformanceWarningPayload),
+	BufferRequestAck {
+		monitor_id: String,
+		buffer: BufferIndex,
+		generation: u64,
+	},
+	Error(String),
+}
+
+/// Socket half of a split [`TabClient`]. It is `Send`, so it can pump the connection on its
+/// own thread while another renders.
+///
+/// The [`TabClientGfx`] only learns about monitor changes, releases and acks while this half
+/// keeps calling [`TabClientIo::dispatch_events`]. Read-only and `&self` requests go through
+/// `Deref` to the [`TabClient`].
+pub struct TabClientIo {
+	client: TabClient,
+}
+
+// SAFETY: `TabClient` is `!Send` only because of its listener boxes and render backend.
+// `TabClient::split` clears the listeners and moves the backend out, `TabClientIo` only adds
+// `Send` listeners, and it never hands out `&mut TabClient` that could install others.
+unsafe impl Send for TabClientIo {}
+
+impl TabClientIo {
+	pub(crate) fn new(client: TabClient) -> Self {
+		Self { client }
+	}
+
+	pub fn dispatch_events(&mut self) -> Result<(), TabClientError> {
+		self.client.dispatch_events()
+	}
+
+	pub fn wait_for_event(
+		&mut self,
+		timeout: Duration,
+		pred: impl FnMut(&ClientEvent) -> bool,
+	) -> Result<Option<ClientEvent>, TabClientError> {
+		self.client.wait_for_event(timeout, pred)
+	}
+
+	pub fn wait_for_monitor(
+		&mut self,
+		timeout: Duration,
+	) -> Result<Option<MonitorState>, TabClientError> {
+		self.client.wait_for_monitor(timeout)
+	}
+
+	pub fn create_session(
+		&mut self,
+		role: SessionRole,
+		display_name: Option<String>,
+	) -> Result<SessionCreatedPayload, TabClientError> {
+		self.client.create_session(role, display_name)
+	}
+
+	pub fn create_session_with(
+		&mut self,
+		payload: SessionCreatePayload,
+	) -> Result<SessionCreatedPayload, TabClientError> {
+		self.client.create_session_with(payload)
+	}
+
+	pub fn log_dump(&mut self, limit: Option<u32>) -> Result<Vec<String>, TabClientError> {
+		self.client.log_dump(limit)
+	}
+
+	pub fn list_sessions(&mut self) -> Result<Vec<SessionInfo>, TabClientError> {
+		self.client.list_sessions()
+	}
+
+	pub fn stats(&mut self) -> Result<StatsPayload, TabClientError> {
+		self.client.stats()
+	}
+
+	pub fn screenshot(&mut self, monitor_id: &str) -> Result<Screenshot, TabClientError> {
+		self.client.screenshot(monitor_id)
+	}
+
+	/// Monitor events also reach the [`TabClientGfx`], which keeps its own copy of the monitors.
+	pub fn on_monitor_event<F>(&mut self, listener: F)
+	where
+		F: Fn(&MonitorEvent) + Send + 'static,
+	{
+		self.client.on_monitor_event(listener);
+	}
+
+	pub fn on_session_event<F>(&mut self, listener: F)
+	where
+		F: Fn(&SessionEvent) + Send + 'static,
+	{
+		self.client.on_session_event(listener);
+	}
+
+	pub fn on_input_event<F>(&mut self, listener: F)
+	where
+		F: Fn(&InputEvent) + Send + 'static,
+	{
+		self.client.on_input_event(listener);
+	}
+}
+
+impl Deref for TabClientIo {
+	type Target = TabClient;
+
+	fn deref(&self) -> &TabClient {
+		&self.client
+	}
+}
+
+type Listener<E> = Box<dyn Fn(&E)>;
+
+/// Render half of a split [`TabClient`]: allocates swapchains and presents buffers. Like the GL
+/// or Vulkan context it is used with, it stays on the rendering thread.
+pub struct TabClientGfx {
+	sender: Arc<FrameSender>,
+	events: mpsc::Receiver<GfxEvent>,
+	backend: Option<Box<dyn RenderBackend>>,
+	monitors: HashMap<MonitorId, MonitorState>,
+	present_mode: PresentMode,
+	frame_skips: FrameSkips,
+	link_generations: Arc<LinkGenerations>,
+	linked_buffers: Arc<LinkedBuffers>,
+	monitor_listeners: Vec<Listener<MonitorEvent>>,
+	render_listeners: Vec<Listener<RenderEvent>>,
+}
+
+impl TabClientGfx {
+	pub(crate) fn new(
+		sender: Arc<FrameSender>,
+		events: mpsc::Receiver<GfxEvent>,
+		backend: Option<Box<dyn RenderBackend>>,
+		monitors: HashMap<MonitorId, MonitorState>,
+		present_mode: PresentMode,
+		link_generations: Arc<LinkGenerations>,
+		linked_buffers: Arc<LinkedBuffers>,
+	) -> Self {
+		Self {
+			sender,
+			events,
+			backend,
+			monitors,
+			present_mode,
+			frame_skips: FrameSkips::default(),
+			link_generations,
+			linked_buffers,
+			monitor_listeners: Vec::new(),
+			render_listeners: Vec::new(),
+		}
+	}
+
+	/// Replaces the allocator used by [`TabClientGfx::create_swapchain`] from now on.
+	pub fn set_render_backend(&mut self, backend: impl RenderBackend + 'static) {
+		self.backend = Some(Box::new(backend));
+	}
+
+	/// `-1` when the render backend has no render node open.
+	pub fn drm_fd(&self) -> RawFd {
+		self.backend.as_ref().map_or(-1, |backend| backend.drm_fd())
+	}
+
+	/// Changes the queueing policy of swapchains linked from now on.
+	pub fn set_present_mode(&mut self, mode: PresentMode) {
+		self.present_mode = mode;
+	}
+
+	/// The monitors as of the last [`TabClientGfx::dispatch_events`].
+	pub fn monitors(&self) -> impl Iterator<Item = &MonitorState> {
+		self.monitors.values()
+	}
+
+	pub fn monitor(&self, id: &str) -> Option<&MonitorState> {
+		self.monitors.get(id)
+	}
+
+	pub fn create_swapchain(&self, monitor_id: &str) -> Result<TabSwapchain, TabClientError> {
+		let swapchain = TabClient::allocate_swapchain(
+			&self.sender,
+			self.backend.as_deref(),
+			self.monitors.get(monitor_id),
+			monitor_id,
+		)?;
+		self.framebuffer_link(&swapchain)?;
+		Ok(swapchain)
+	}
+
+	/// See [`TabClient::resize_output`].
+	pub fn resize_output(
+		&self,
+		monitor_id: &str,
+		width: i32,
+		height: i32,
+	) -> Result<TabSwapchain, TabClientError> {
+		let swapchain = TabClient::allocate_resized_swapchain(
+			&self.sender,
+			self.backend.as_deref(),
+			self.monitors.get(monitor_id),
+			monitor_id,
+			width,
+			height,
+		)?;
+		self.framebuffer_link(&swapchain)?;
+		Ok(swapchain)
+	}
+
+	pub fn framebuffer_link(&self, swapchain: &TabSwapchain) -> Result<(), TabClientError> {
+		self.sender.send(TabClient::framebuffer_link_frame(
+			swapchain,
+			self.present_mode,
+			&self.link_generations,
+			&self.linked_buffers,
+		)?)
+	}
+
+	/// See [`TabClient::skip_frame`].
+	pub fn skip_frame(&mut self, monitor_id: &str) -> Result<(), TabClientError> {
+		if let Some(report) = self.frame_skips.skip(monitor_id) {
+			self.sender.send(TabMessageFrame::json(
+				message_header::FRAMES_SKIPPED,
+				report,
+			))?;
+		}
+		Ok(())
+	}
+
+	/// See [`TabClient::frame_stats`].
+	pub fn frame_stats(&self) -> FrameStats {
+		self.frame_skips.stats()
+	}
+
+	/// See [`TabClient::request_buffer_if_changed`].
+	pub fn request_buffer_if_changed(
+		&mut self,
+		monitor_id: &str,
+		buffer: BufferIndex,
+		acquire_fence: Option<OwnedFd>,
+		content_hash: u64,
+	) -> Result<bool, TabClientError> {
+		if self.frame_skips.is_unchanged(monitor_id, content_hash) {
+			self.skip_frame(monitor_id)?;
+			return Ok(false);
+		}
+		self.request_buffer(monitor_id, buffer, acquire_fence)?;
+		self.frame_skips.set_last_hash(monitor_id, content_hash);
+		Ok(true)
+	}
+
+	/// See [`TabClient::upload_buffer`].
+	pub fn upload_buffer(
+		&self,
+		monitor_id: &str,
+		buffer: BufferIndex,
+		width: i32,
+		height: i32,
+		stride: i32,
+		pixels: &[u8],
+	) -> Result<(), TabClientError> {
+		for frame in TabClient::buffer_upload_frames(monitor_id, buffer, width, height, stride, pixels)
+		{
+			self.sender.send(frame)?;
+		}
+		Ok(())
+	}
+
+	/// Presents `buffer` and waits for shift's ack, which the Io half has to dispatch meanwhile.
+	pub fn request_buffer(
+		&mut self,
+		monitor_id: &str,
+		buffer: BufferIndex,
+		acquire_fence: Option<OwnedFd>,
+	) -> Result<(), TabClientError> {
+		if let Some(report) = self.frame_skips.take_report(monitor_id) {
+			self.sender.send(TabMessageFrame::json(
+				message_header::FRAMES_SKIPPED,
+				report,
+			))?;
+		}
+		self.frame_skips.forget_last_hash(monitor_id);
+		let generation = self.link_generations.current(monitor_id);
+		self.sender.send(TabClient::buffer_request_frame(
+			monitor_id,
+			buffer,
+			generation,
+			acquire_fence,
+			None,
+		))?;
+		self.wait_for_buffer_request_acks(vec![(monitor_id.to_string(), buffer, generation)])
+	}
+
+	/// See [`TabClient::define_present_group`].
+	pub fn define_present_group(
+		&self,
+		group: u32,
+		monitor_ids: &[&str],
+	) -> Result<(), TabClientError> {
+		self
+			.sender
+			.send(TabClient::present_group_frame(group, monitor_ids))
+	}
+
+	/// See [`TabClient::request_present_group`].
+	pub fn request_present_group(
+		&mut self,
+		group: u32,
+		requests: Vec<(&str, BufferIndex, Option<OwnedFd>)>,
+	) -> Result<(), TabClientError> {
+		let mut expected = Vec::with_capacity(requests.len());
+		for (monitor_id, buffer, acquire_fence) in requests {
+			if let Some(report) = self.frame_skips.take_report(monitor_id) {
+				self.sender.send(TabMessageFrame::json(
+					message_header::FRAMES_SKIPPED,
+					report,
+				))?;
+			}
+			self.frame_skips.forget_last_hash(monitor_id);
+			let generation = self.link_generations.current(monitor_id);
+			self.sender.send(TabClient::buffer_request_frame(
+				monitor_id,
+				buffer,
+				generation,
+				acquire_fence,
+				Some(group),
+			))?;
+			expected.push((monitor_id.to_string(), buffer, generation));
+		}
+		self.wait_for_buffer_request_acks(expected)
+	}
+
+	pub fn on_monitor_event<F>(&mut self, listener: F)
+	where
+		F: Fn(&MonitorEvent) + 'static,
+	{
+		self.monitor_listeners.push(Box::new(listener));
+	}
+
+	pub fn on_render_event<F>(&mut self, listener: F)
+	where
+		F: Fn(&RenderEvent) + 'static,
+	{
+		self.render_listeners.push(Box::new(listener));
+	}
+
+	/// Handles everything the Io half forwarded so far, without blocking.
+	pub fn dispatch_events(&mut self) -> Result<(), TabClientError> {
+		loop {
+			match self.events.try_recv() {
+				Ok(event) => self.handle_event(event),
+				Err(mpsc::TryRecvError::Empty) => return Ok(()),
+				Err(mpsc::TryRecvError::Disconnected) => return Err(TabClientError::IoHalfDropped),
+			}
+		}
+	}
+
+	/// Waits for the ack of every `(monitor_id, buffer, generation)` request, in any order, as the
+	/// Io half forwards them.
+	fn wait_for_buffer_request_acks(
+		&mut self,
+		mut expected: Vec<(String, BufferIndex, u64)>,
+	) -> Result<(), TabClientError> {
+		let deadline = Instant::now() + TabClient::BUFFER_REQUEST_ACK_TIMEOUT;
+		while !expected.is_empty() {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			if remaining == Duration::ZERO {
+				return Err(TabClientError::Unexpected("buffer_request_ack timeout"));
+			}
+			match self.events.recv_timeout(remaining) {
+				Ok(GfxEvent::BufferRequestAck {
+					monitor_id,
+					buffer,
+					generation,
+				}) => {
+					let ack = BufferRequestAckPayload {
+						monitor_id,
+						buffer,
+						generation,
+					};
+					expected.retain(|request| !TabClient::is_ack_for(&ack, request));
+				}
+				Ok(GfxEvent::Error(details)) => return Err(TabClientError::Server(details)),
+				Ok(other) => self.handle_event(other),
+				Err(mpsc::RecvTimeoutError::Timeout) => {
+					return Err(TabClientError::Unexpected("buffer_request_ack timeout"));
+				}
+				Err(mpsc::RecvTimeoutError::Disconnected) => {
+					return Err(TabClientError::IoHalfDropped);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn handle_event(&mut self, event: GfxEvent) {
+		match event {
+			GfxEvent::Monitor(event) => {
+				match &event {
+					MonitorEvent::Added(state) | MonitorEvent::Changed(state) => {
+						self.monitors.insert(state.info.id.clone(), state.clone());
+						self.frame_skips.forget(&state.info.id);
+					}
+					MonitorEvent::Removed { monitor_id, .. } => {
+						self.monitors.remove(monitor_id);
+						self.frame_skips.forget(monitor_id);
+					}
+				}
+				for listener in &self.monitor_listeners {
+					listener(&event);
+				}
+			}
+			GfxEvent::BufferReleased {
+				monitor_id,
+				buffer,
+				release_fence,
+			} => {
+				for listener in &self.render_listeners {
+					let release_fence_fd = release_fence
+						.as_ref()
+						.and_then(|fd| fd.as_fd().try_clone_to_owned().ok())
+						.map(|fd| fd.into_raw_fd());
+					let event = RenderEvent::BufferReleased {
+						monitor_id: monitor_id.clone(),
+						buffer,
+						release_fence_fd,
+					};
+					listener(&event);
+				}
+			}
+			GfxEvent::FramebufferLinkFailed(payload) => {
+				if let Some(monitor) = self.monitors.get_mut(&payload.monitor_id) {
+					monitor.supported_formats = Some(payload.supported_formats.clone());
+				}
+				let event = RenderEvent::FramebufferLinkFailed {
+					monitor_id: payload.monitor_id,
+					reason: payload.reason,
+					supported_formats: payload.supported_formats,
+				};
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			GfxEvent::RelinkRequested(monitor_id) => {
+				let event = RenderEvent::RelinkRequested { monitor_id };
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			GfxEvent::BuffersInvalidated(monitor_ids) => {
+				let event = RenderEvent::BuffersInvalidated { monitor_ids };
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			GfxEvent::FramesDropped(payload) => {
+				self.frame_skips.dropped(payload.count);
+				let event = RenderEvent::FramesDropped {
+					monitor_id: payload.monitor_id,
+					count: payload.count,
+					reason: payload.reason,
+				};
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			GfxEvent::PerformanceWarning(payload) => {
+				let event = RenderEvent::PerformanceWarning {
+					monitor_id: payload.monitor_id,
+					kind: payload.kind,
+					duration_ms: payload.duration_ms,
+					frames: payload.frames,
+				};
+				for listener in &self.render_listeners {
+					listener(&event);
+				}
+			}
+			// Acks only matter while a request is waiting for them, and errors are answered to
+			// whichever request is waiting on the Io half.
+			GfxEvent::BufferRequestAck { .. } | GfxEvent::Error(_) => {}
+		}
+	}
+}
@@ -1,45 +1,99 @@
+use std::any::Any;
 use std::os::fd::{AsRawFd, OwnedFd, RawFd};
 
-use gbm::BufferObject;
 use tab_protocol::{BufferIndex, FramebufferLinkPayload};
 
+/// `DRM_FORMAT_MOD_INVALID`, what GBM reports when it doesn't know the modifier.
+#[cfg(feature = "gbm")]
+const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// Layout of a single-plane dma-buf.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaBufLayout {
+	pub width: i32,
+	pub height: i32,
+	pub stride: i32,
+	pub offset: i32,
+	pub fourcc: i32,
+	/// DRM format modifier, `None` when the allocator didn't say.
+	pub modifier: Option<u64>,
+}
+
 /// Metadata describing a DMA-BUF-backed buffer.
 #[derive(Debug)]
 pub struct TabBuffer {
 	pub index: BufferIndex,
-	bo: BufferObject<()>,
+	layout: DmaBufLayout,
 	fd: OwnedFd,
+	/// Allocation backing the dma-buf (GBM buffer object, Vulkan memory, ...), kept alive with it.
+	owner: Option<Box<dyn Any>>,
 }
 
 impl TabBuffer {
-	pub fn new(index: BufferIndex, bo: BufferObject<()>) -> Self {
+	#[cfg(feature = "gbm")]
+	pub fn new(index: BufferIndex, bo: gbm::BufferObject<()>) -> Self {
+		let layout = DmaBufLayout {
+			width: bo.width() as i32,
+			height: bo.height() as i32,
+			stride: bo.stride() as i32,
+			offset: bo.offset(0) as i32,
+			fourcc: bo.format() as u32 as i32,
+			modifier: Some(u64::from(bo.modifier()))
+				.filter(|modifier| *modifier != DRM_FORMAT_MOD_INVALID),
+		};
+		Self::from_dmabuf(index, bo.fd().unwrap(), layout).with_owner(bo)
+	}
+
+	/// Wraps a dma-buf exported by any allocator.
+	pub fn from_dmabuf(index: BufferIndex, fd: OwnedFd, layout: DmaBufLayout) -> Self {
 		Self {
 			index,
-			fd: bo.fd().unwrap(),
-			bo,
+			layout,
+			fd,
+			owner: None,
 		}
 	}
 
+	/// Keeps `owner` alive for as long as this buffer, e.g. the image the dma-buf was exported from.
+	pub fn with_owner(mut self, owner: impl Any) -> Self {
+		self.owner = Some(Box::new(owner));
+		self
+	}
+
+	/// The allocation attached with [`TabBuffer::with_owner`], if it is a `T`.
+	pub fn owner<T: Any>(&self) -> Option<&T> {
+		self.owner.as_ref()?.downcast_ref()
+	}
+
+	pub fn layout(&self) -> DmaBufLayout {
+		self.layout
+	}
+
 	pub fn width(&self) -> i32 {
-		self.bo.width() as i32
+		self.layout.width
 	}
 
 	pub fn height(&self) -> i32 {
-		self.bo.height() as i32
+		self.layout.height
 	}
 
 	pub fn stride(&self) -> i32 {
-		self.bo.stride() as i32
+		self.layout.stride
 	}
 
 	pub fn offset(&self) -> i32 {
-		self.bo.offset(0) as i32
+		self.layout.offset
 	}
 
 	pub fn fourcc(&self) -> i32 {
-		self.bo.format() as u32 as i32
+		self.layout.fourcc
 	}
 
+	pub fn modifier(&self) -> Option<u64> {
+		self.layout.modifier
+	}
+
+	/// The dma-buf itself, for importing it into EGL or Vulkan.
 	pub fn fd(&self) -> RawFd {
 		self.fd.as_raw_fd()
 	}
@@ -101,6 +155,11 @@ impl TabSwapchain {
 		self.busy[idx as usize] = false;
 	}
 
+	/// Whether [`TabSwapchain::acquire_next`] would return a buffer.
+	pub fn has_free_buffer(&self) -> bool {
+		self.busy.contains(&false)
+	}
+
 	pub fn framebuffer_link_payload(&self) -> FramebufferLinkPayload {
 		let buffer = &self.buffers[0];
 		FramebufferLinkPayload {
@@ -110,12 +169,16 @@ impl TabSwapchain {
 			stride: buffer.stride(),
 			offset: buffer.offset(),
 			fourcc: buffer.fourcc(),
+			modifier: buffer.modifier(),
+			present_mode: Default::default(),
+			generation: 0,
 		}
 	}
 
-	pub fn export_fds(&self) -> [RawFd; 2] {
-		let fd0 = self.buffers[0].fd();
-		let fd1 = self.buffers[1].fd();
-		[fd0, fd1]
+	/// Duplicates of both dma-buf FDs, to send in `framebuffer_link`.
+	pub fn export_fds(&self) -> std::io::Result<[OwnedFd; 2]> {
+		let fd0 = self.buffers[0].fd.try_clone()?;
+		let fd1 = self.buffers[1].fd.try_clone()?;
+		Ok([fd0, fd1])
 	}
 }
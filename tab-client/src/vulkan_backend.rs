@@ -0,0 +1,238 @@
+use std::ffi::CStr;
+use std::os::fd::{FromRawFd, IntoRawFd, OwnedFd};
+
+use ash::{ext, khr, vk};
+use tab_protocol::BufferIndex;
+
+use crate::{
+	backend::RenderBackend,
+	error::TabClientError,
+	monitor::MonitorState,
+	swapchain::{DmaBufLayout, TabBuffer},
+};
+
+/// `DRM_FORMAT_XRGB8888`, the memory layout of `VK_FORMAT_B8G8R8A8_UNORM`.
+const DRM_FORMAT_XRGB8888: i32 = 0x3432_5258;
+/// `DRM_FORMAT_MOD_LINEAR`, the layout of `VK_IMAGE_TILING_LINEAR` images.
+const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+const DMA_BUF: vk::ExternalMemoryHandleTypeFlags = vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT;
+const SYNC_FD: vk::ExternalSemaphoreHandleTypeFlags = vk::ExternalSemaphoreHandleTypeFlags::SYNC_FD;
+
+/// Device extensions the `VkDevice` handed to [`VulkanBackend::new`] must have enabled.
+pub const REQUIRED_DEVICE_EXTENSIONS: &[&CStr] = &[
+	khr::external_memory_fd::NAME,
+	ext::external_memory_dma_buf::NAME,
+	khr::external_semaphore_fd::NAME,
+];
+
+/// Image behind a [`TabBuffer`] allocated by [`VulkanBackend`], reachable through
+/// [`TabBuffer::owner`]. Destroyed together with the buffer, so the device must outlive it.
+pub struct VulkanImage {
+	device: ash::Device,
+	image: vk::Image,
+	memory: vk::DeviceMemory,
+}
+
+impl VulkanImage {
+	/// Linear `B8G8R8A8_UNORM` image, usable as a color attachment or transfer destination.
+	pub fn image(&self) -> vk::Image {
+		self.image
+	}
+
+	pub fn memory(&self) -> vk::DeviceMemory {
+		self.memory
+	}
+}
+
+impl Drop for VulkanImage {
+	fn drop(&mut self) {
+		unsafe {
+			self.device.destroy_image(self.image, None);
+			self.device.free_memory(self.memory, None);
+		}
+	}
+}
+
+/// Allocates swapchain buffers as exportable Vulkan images, so Vulkan compositors can present
+/// without a GL interop layer.
+///
+/// Fences map onto binary semaphores: export the semaphore a frame's submit signals with
+/// [`VulkanBackend::export_acquire_fence`] and pass it to
+/// [`TabClient::request_buffer`](crate::TabClient::request_buffer); import the fence of
+/// [`RenderEvent::BufferReleased`](crate::RenderEvent::BufferReleased) with
+/// [`VulkanBackend::import_release_fence`] and wait on it before rendering into that buffer again.
+pub struct VulkanBackend {
+	device: ash::Device,
+	memory_properties: vk::PhysicalDeviceMemoryProperties,
+	external_memory_fd: khr::external_memory_fd::Device,
+	external_semaphore_fd: khr::external_semaphore_fd::Device,
+}
+
+impl VulkanBackend {
+	/// `device` must come from `physical_device` with [`REQUIRED_DEVICE_EXTENSIONS`] enabled.
+	pub fn new(
+		instance: &ash::Instance,
+		physical_device: vk::PhysicalDevice,
+		device: &ash::Device,
+	) -> Self {
+		let memory_properties =
+			unsafe { instance.get_physical_device_memory_properties(physical_device) };
+		Self {
+			device: device.clone(),
+			memory_properties,
+			external_memory_fd: khr::external_memory_fd::Device::new(instance, device),
+			external_semaphore_fd: khr::external_semaphore_fd::Device::new(instance, device),
+		}
+	}
+
+	fn memory_type_index(&self, type_bits: u32) -> Option<u32> {
+		let types =
+			&self.memory_properties.memory_types[..self.memory_properties.memory_type_count as usize];
+		let allowed = |index: usize| type_bits & (1 << index) != 0;
+		types
+			.iter()
+			.enumerate()
+			.position(|(index, ty)| {
+				allowed(index)
+					&& ty
+						.property_flags
+						.contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+			})
+			.or_else(|| (0..types.len()).find(|index| allowed(*index)))
+			.map(|index| index as u32)
+	}
+
+	fn allocate_image(
+		&self,
+		width: u32,
+		height: u32,
+	) -> Result<(VulkanImage, OwnedFd, DmaBufLayout), TabClientError> {
+		let mut external = vk::ExternalMemoryImageCreateInfo::default().handle_types(DMA_BUF);
+		let info = vk::ImageCreateInfo::default()
+			.image_type(vk::ImageType::TYPE_2D)
+			.format(vk::Format::B8G8R8A8_UNORM)
+			.extent(vk::Extent3D {
+				width,
+				height,
+				depth: 1,
+			})
+			.mip_levels(1)
+			.array_layers(1)
+			.samples(vk::SampleCountFlags::TYPE_1)
+			// Linear tiling, so a single stride and offset describe the image. Optimal tiling would
+			// need VK_EXT_image_drm_format_modifier to learn the modifier to link with.
+			.tiling(vk::ImageTiling::LINEAR)
+			.usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_DST)
+			.sharing_mode(vk::SharingMode::EXCLUSIVE)
+			.initial_layout(vk::ImageLayout::UNDEFINED)
+			.push_next(&mut external);
+		let image = unsafe { self.device.create_image(&info, None)? };
+		let requirements = unsafe { self.device.get_image_memory_requirements(image) };
+		let Some(memory_type) = self.memory_type_index(requirements.memory_type_bits) else {
+			unsafe { self.device.destroy_image(image, None) };
+			return Err(vk::Result::ERROR_OUT_OF_DEVICE_MEMORY.into());
+		};
+		let mut export = vk::ExportMemoryAllocateInfo::default().handle_types(DMA_BUF);
+		let mut dedicated = vk::MemoryDedicatedAllocateInfo::default().image(image);
+		let alloc = vk::MemoryAllocateInfo::default()
+			.allocation_size(requirements.size)
+			.memory_type_index(memory_type)
+			.push_next(&mut export)
+			.push_next(&mut dedicated);
+		let memory = match unsafe { self.device.allocate_memory(&alloc, None) } {
+			Ok(memory) => memory,
+			Err(err) => {
+				unsafe { self.device.destroy_image(image, None) };
+				return Err(err.into());
+			}
+		};
+		// From here on, dropping `allocation` frees both the image and its memory.
+		let allocation = VulkanImage {
+			device: self.device.clone(),
+			image,
+			memory,
+		};
+		unsafe { self.device.bind_image_memory(image, memory, 0)? };
+		let subresource = unsafe {
+			self.device.get_image_subresource_layout(
+				image,
+				vk::ImageSubresource {
+					aspect_mask: vk::ImageAspectFlags::COLOR,
+					mip_level: 0,
+					array_layer: 0,
+				},
+			)
+		};
+		let fd = unsafe {
+			self.external_memory_fd.get_memory_fd(
+				&vk::MemoryGetFdInfoKHR::default()
+					.memory(memory)
+					.handle_type(DMA_BUF),
+			)?
+		};
+		let layout = DmaBufLayout {
+			width: width as i32,
+			height: height as i32,
+			stride: subresource.row_pitch as i32,
+			offset: subresource.offset as i32,
+			fourcc: DRM_FORMAT_XRGB8888,
+			modifier: Some(DRM_FORMAT_MOD_LINEAR),
+		};
+		Ok((allocation, unsafe { OwnedFd::from_raw_fd(fd) }, layout))
+	}
+
+	/// Creates a binary semaphore whose payload can be exported or imported as a sync_file.
+	pub fn create_exportable_semaphore(&self) -> Result<vk::Semaphore, TabClientError> {
+		let mut export = vk::ExportSemaphoreCreateInfo::default().handle_types(SYNC_FD);
+		let info = vk::SemaphoreCreateInfo::default().push_next(&mut export);
+		Ok(unsafe { self.device.create_semaphore(&info, None)? })
+	}
+
+	/// Exports the pending signal of `semaphore` as an acquire fence. A submit that signals the
+	/// semaphore must already be queued; exporting resets the semaphore.
+	pub fn export_acquire_fence(&self, semaphore: vk::Semaphore) -> Result<OwnedFd, TabClientError> {
+		let fd = unsafe {
+			self.external_semaphore_fd.get_semaphore_fd(
+				&vk::SemaphoreGetFdInfoKHR::default()
+					.semaphore(semaphore)
+					.handle_type(SYNC_FD),
+			)?
+		};
+		Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+	}
+
+	/// Makes the next wait on `semaphore` block until shift has released the buffer.
+	pub fn import_release_fence(
+		&self,
+		semaphore: vk::Semaphore,
+		fence: OwnedFd,
+	) -> Result<(), TabClientError> {
+		let fd = fence.into_raw_fd();
+		let info = vk::ImportSemaphoreFdInfoKHR::default()
+			.semaphore(semaphore)
+			.flags(vk::SemaphoreImportFlags::TEMPORARY)
+			.handle_type(SYNC_FD)
+			.fd(fd);
+		if let Err(err) = unsafe { self.external_semaphore_fd.import_semaphore_fd(&info) } {
+			// The driver only takes ownership of the fd on success.
+			drop(unsafe { OwnedFd::from_raw_fd(fd) });
+			return Err(err.into());
+		}
+		Ok(())
+	}
+}
+
+impl RenderBackend for VulkanBackend {
+	fn allocate_buffers(&self, monitor: &MonitorState) -> Result<[TabBuffer; 2], TabClientError> {
+		let width =
+			u32::try_from(monitor.info.width).map_err(|_| TabClientError::InvalidMonitorDimensions)?;
+		let height =
+			u32::try_from(monitor.info.height).map_err(|_| TabClientError::InvalidMonitorDimensions)?;
+		let (image0, fd0, layout0) = self.allocate_image(width, height)?;
+		let (image1, fd1, layout1) = self.allocate_image(width, height)?;
+		Ok([
+			TabBuffer::from_dmabuf(BufferIndex::Zero, fd0, layout0).with_owner(image0),
+			TabBuffer::from_dmabuf(BufferIndex::One, fd1, layout1).with_owner(image1),
+		])
+	}
+}
@@ -0,0 +1,65 @@
+//! One fd for event loops to watch instead of each fd of the client, see
+//! `tab_client_get_wakeup_fd`.
+//! - an epoll fd is readable whenever one of the fds registered with it is
+//! - an eventfd registered with it stays readable while events wait in the client's queue, so a
+//!   loop that dispatched more than it handled comes back for the rest
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+
+pub(crate) struct Wakeup {
+	epoll: OwnedFd,
+	pending: OwnedFd,
+}
+
+impl Wakeup {
+	/// Watches `sources`, skipping negative ones.
+	pub(crate) fn new(sources: &[RawFd]) -> io::Result<Self> {
+		// SAFETY: plain syscalls; the fds they return are owned from here on.
+		let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+		if epoll < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let epoll = unsafe { OwnedFd::from_raw_fd(epoll) };
+		let pending = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+		if pending < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let pending = unsafe { OwnedFd::from_raw_fd(pending) };
+		for fd in sources
+			.iter()
+			.copied()
+			.filter(|fd| *fd >= 0)
+			.chain([pending.as_raw_fd()])
+		{
+			let mut event = libc::epoll_event {
+				events: libc::EPOLLIN as u32,
+				u64: fd as u64,
+			};
+			// SAFETY: both fds are open and `event` outlives the call.
+			if unsafe { libc::epoll_ctl(epoll.as_raw_fd(), libc::EPOLL_CTL_ADD, fd, &mut event) } < 0 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+		Ok(Self { epoll, pending })
+	}
+
+	pub(crate) fn fd(&self) -> RawFd {
+		self.epoll.as_raw_fd()
+	}
+
+	/// Keeps the fd readable for as long as `pending` is set, even with nothing new to read.
+	pub(crate) fn set_pending(&self, pending: bool) {
+		let mut count = 1u64;
+		let buf = (&raw mut count).cast::<libc::c_void>();
+		// SAFETY: eventfd reads and writes exactly 8 bytes, `count` has them. EAGAIN means the
+		// counter already is empty, or as full as it gets.
+		unsafe {
+			if pending {
+				libc::write(self.pending.as_raw_fd(), buf, size_of::<u64>());
+			} else {
+				libc::read(self.pending.as_raw_fd(), buf, size_of::<u64>());
+			}
+		}
+	}
+}
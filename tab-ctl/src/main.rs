@@ -0,0 +1,331 @@
+//! tab-ctl: one-shot admin requests against a running shift, printed as JSON.
+//!
+//! ```text
+//! tab-ctl [--socket <addr>] [--token <token>] <command>
+//! ```
+//!
+//! The token defaults to `SHIFT_SESSION_TOKEN` and must belong to an admin session, or an
+//! observer session for the read-only commands.
+
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+
+use serde_json::json;
+use tab_client::{TabClient, TabClientConfig, TabClientError};
+use tab_protocol::{
+	AccelProfile, DeviceSettings, Easing, ProtocolError, ScreenshotDataPayload, SessionCreatePayload,
+	SessionRole, SessionSwitchPayload, TransitionDefinePayload, WireTapPayload,
+	transport::TransportAddr,
+};
+use thiserror::Error;
+
+const USAGE: &str = "usage: tab-ctl [--socket <addr>] [--token <token>] <command>
+commands:
+  sessions list
+  session create [--name <name>] [--admin | --observer] [--command <cmd> [--env <k=v>]... [--user <user>]]
+                 [--monitor <monitor_id>]
+  session switch <session_id> [--animation <name>] [--duration-ms <ms>]
+                 [--easing linear|ease_in|ease_out|ease_in_out] [--param <name=value>]...
+  transitions list
+  transition define <definition.json>
+  monitors
+  stats
+  state
+  screenshot <monitor_id> <out.png>
+  selection group <session_id> [<group>]
+  wiretap <session_id> on|off [--payloads] [--capture]
+  backlight list
+  backlight set <monitor_id> <level 0..1>
+  input list
+  input configure <device> [accel_profile=flat|adaptive] [accel_speed=<-1..1>] [natural_scroll=<bool>]
+                  [tap_to_click=<bool>] [left_handed=<bool>]
+  input calibrate <device> <a> <b> <c> <d> <e> <f>
+  input grab <device> [<session_id>]";
+
+#[derive(Debug, Error)]
+enum CtlError {
+	#[error("{0}")]
+	Usage(String),
+	#[error("{0}")]
+	Client(#[from] TabClientError),
+	#[error("invalid socket address: {0}")]
+	Address(#[from] ProtocolError),
+	#[error("json error: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("failed to write png: {0}")]
+	Image(#[from] image::ImageError),
+	#[error("unsupported screenshot format {0:#x}")]
+	ScreenshotFormat(i32),
+}
+
+fn usage(message: impl Into<String>) -> CtlError {
+	CtlError::Usage(format!("{}\n{USAGE}", message.into()))
+}
+
+/// Parses `name=value` pairs of `input configure`.
+fn parse_device_settings(args: &[&str]) -> Result<DeviceSettings, CtlError> {
+	let mut settings = DeviceSettings::default();
+	for arg in args {
+		let invalid = || usage(format!("invalid setting: {arg}"));
+		let (name, value) = arg.split_once('=').ok_or_else(invalid)?;
+		let flag = || value.parse::<bool>().map_err(|_| invalid());
+		match name {
+			"accel_profile" => {
+				settings.accel_profile = Some(match value {
+					"flat" => AccelProfile::Flat,
+					"adaptive" => AccelProfile::Adaptive,
+					_ => return Err(invalid()),
+				});
+			}
+			"accel_speed" => settings.accel_speed = Some(value.parse().map_err(|_| invalid())?),
+			"natural_scroll" => settings.natural_scroll = Some(flag()?),
+			"tap_to_click" => settings.tap_to_click = Some(flag()?),
+			"left_handed" => settings.left_handed = Some(flag()?),
+			_ => return Err(invalid()),
+		}
+	}
+	Ok(settings)
+}
+
+/// Pulls `--flag value` out of `args`, leaving the positional arguments.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Result<Option<String>, CtlError> {
+	let Some(index) = args.iter().position(|arg| arg == flag) else {
+		return Ok(None);
+	};
+	if index + 1 >= args.len() {
+		return Err(usage(format!("{flag} needs a value")));
+	}
+	let value = args.remove(index + 1);
+	args.remove(index);
+	Ok(Some(value))
+}
+
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+	let Some(index) = args.iter().position(|arg| arg == flag) else {
+		return false;
+	};
+	args.remove(index);
+	true
+}
+
+fn connect(args: &mut Vec<String>) -> Result<TabClient, CtlError> {
+	let token = take_option(args, "--token")?
+		.or_else(|| std::env::var("SHIFT_SESSION_TOKEN").ok())
+		.ok_or_else(|| usage("missing --token and SHIFT_SESSION_TOKEN"))?;
+	let mut config = TabClientConfig::new(token);
+	if let Some(address) =
+		take_option(args, "--socket")?.or_else(|| std::env::var("SHIFT_SOCKET").ok())
+	{
+		config = config.address(address.parse::<TransportAddr>()?);
+	}
+	Ok(TabClient::connect(config)?)
+}
+
+fn run() -> Result<serde_json::Value, CtlError> {
+	let mut args = std::env::args().skip(1).collect::<Vec<_>>();
+	let mut client = connect(&mut args)?;
+	let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+	match args.as_slice() {
+		["sessions", "list"] => Ok(json!(client.list_sessions()?)),
+		["transitions", "list"] => Ok(json!(client.transitions()?)),
+		["transition", "define", path] => {
+			let definition =
+				std::fs::read_to_string(path).map_err(|e| usage(format!("can't read {path}: {e}")))?;
+			let payload = serde_json::from_str::<TransitionDefinePayload>(&definition)?;
+			let name = payload.name.clone();
+			client.define_transition(payload)?;
+			// Like session_switch, a rejected definition shows up as an error before the list.
+			let defined = client
+				.transitions()?
+				.into_iter()
+				.any(|info| info.name == name);
+			Ok(json!({ "defined": name, "listed": defined }))
+		}
+		["session", "create", rest @ ..] => {
+			let mut rest = rest.iter().map(|arg| arg.to_string()).collect();
+			let name = take_option(&mut rest, "--name")?;
+			let role = match (
+				take_flag(&mut rest, "--admin"),
+				take_flag(&mut rest, "--observer"),
+			) {
+				(true, true) => return Err(usage("--admin and --observer are exclusive")),
+				(true, false) => SessionRole::Admin,
+				(false, true) => SessionRole::Observer,
+				(false, false) => SessionRole::Session,
+			};
+			let mut payload = SessionCreatePayload::new(role, name);
+			payload.command = take_option(&mut rest, "--command")?;
+			payload.user = take_option(&mut rest, "--user")?;
+			payload.monitor_id = take_option(&mut rest, "--monitor")?;
+			while let Some(var) = take_option(&mut rest, "--env")? {
+				let (key, value) = var
+					.split_once('=')
+					.ok_or_else(|| usage(format!("invalid --env, expected key=value: {var}")))?;
+				payload.env.insert(key.to_string(), value.to_string());
+			}
+			if let Some(arg) = rest.first() {
+				return Err(usage(format!("unknown argument: {arg}")));
+			}
+			Ok(json!(client.create_session_with(payload)?))
+		}
+		["session", "switch", session_id, rest @ ..] => {
+			let mut rest = rest.iter().map(|arg| arg.to_string()).collect();
+			let animation = take_option(&mut rest, "--animation")?;
+			let duration = match take_option(&mut rest, "--duration-ms")? {
+				Some(ms) => Duration::from_millis(
+					ms.parse()
+						.map_err(|_| usage(format!("invalid --duration-ms: {ms}")))?,
+				),
+				None if animation.is_some() => Duration::from_millis(300),
+				None => Duration::ZERO,
+			};
+			let mut payload = SessionSwitchPayload::new(*session_id, animation, duration);
+			if let Some(easing) = take_option(&mut rest, "--easing")? {
+				payload.easing = Some(
+					serde_json::from_value::<Easing>(json!(easing))
+						.map_err(|_| usage(format!("invalid --easing: {easing}")))?,
+				);
+			}
+			while let Some(param) = take_option(&mut rest, "--param")? {
+				let (name, value) = param
+					.split_once('=')
+					.and_then(|(name, value)| Some((name.to_string(), value.parse::<f64>().ok()?)))
+					.ok_or_else(|| usage(format!("invalid --param, expected name=number: {param}")))?;
+				payload.params.insert(name, value);
+			}
+			if let Some(arg) = rest.first() {
+				return Err(usage(format!("unknown argument: {arg}")));
+			}
+			client.switch_session_with(payload)?;
+			// session_switch has no reply. shift answers in order, so a rejected switch shows up
+			// as an error before the stats.
+			client.stats()?;
+			Ok(json!({ "switched": session_id }))
+		}
+		["monitors"] => Ok(json!(
+			client
+				.monitors()
+				.map(|monitor| &monitor.info)
+				.collect::<Vec<_>>()
+		)),
+		["stats"] => Ok(json!(client.stats()?)),
+		["state"] => Ok(json!(client.state_sync()?)),
+		["backlight", "list"] => Ok(json!(client.backlights()?)),
+		["backlight", "set", monitor_id, level] => {
+			let level = level
+				.parse::<f64>()
+				.map_err(|_| usage(format!("invalid level: {level}")))?;
+			client.set_backlight(monitor_id, level)?;
+			// backlight_set has no reply either, see `session switch`.
+			client.stats()?;
+			Ok(json!({ "monitor_id": monitor_id, "level": level }))
+		}
+		["input", "list"] => Ok(json!(client.device_configs()?)),
+		["input", "configure", device, settings @ ..] => {
+			let device = device
+				.parse::<u32>()
+				.map_err(|_| usage(format!("invalid device: {device}")))?;
+			let settings = parse_device_settings(settings)?;
+			client.configure_device(device, settings.clone())?;
+			// device_configure has no reply either, see `session switch`.
+			client.stats()?;
+			Ok(json!({ "device": device, "settings": settings }))
+		}
+		["input", "calibrate", device, values @ ..] if values.len() == 6 => {
+			let device = device
+				.parse::<u32>()
+				.map_err(|_| usage(format!("invalid device: {device}")))?;
+			let mut matrix = [0.0; 6];
+			for (entry, value) in matrix.iter_mut().zip(values) {
+				*entry = value
+					.parse::<f64>()
+					.map_err(|_| usage(format!("invalid matrix entry: {value}")))?;
+			}
+			client.set_device_calibration(device, matrix)?;
+			// device_calibration has no reply either, see `session switch`.
+			client.stats()?;
+			Ok(json!({ "device": device, "matrix": matrix }))
+		}
+		["input", "grab", device, rest @ ..] if rest.len() <= 1 => {
+			let device = device
+				.parse::<u32>()
+				.map_err(|_| usage(format!("invalid device: {device}")))?;
+			let session_id = rest.first().copied();
+			client.grab_device(device, session_id)?;
+			// device_grab has no reply either, see `session switch`.
+			client.stats()?;
+			Ok(json!({ "device": device, "session_id": session_id }))
+		}
+		["selection", "group", session_id, rest @ ..] if rest.len() <= 1 => {
+			let group = rest.first().copied();
+			client.set_selection_group(session_id, group)?;
+			// selection_policy has no reply either, see `session switch`.
+			client.stats()?;
+			Ok(json!({ "session_id": session_id, "group": group }))
+		}
+		["wiretap", session_id, state, rest @ ..] => {
+			let enabled = match *state {
+				"on" => true,
+				"off" => false,
+				_ => return Err(usage(format!("invalid wiretap state: {state}"))),
+			};
+			let mut rest = rest.iter().map(|arg| arg.to_string()).collect();
+			let payload = WireTapPayload {
+				session_id: session_id.to_string(),
+				enabled,
+				payloads: take_flag(&mut rest, "--payloads"),
+				capture: take_flag(&mut rest, "--capture"),
+			};
+			if let Some(arg) = rest.first() {
+				return Err(usage(format!("unknown argument: {arg}")));
+			}
+			client.set_wire_tap(payload.clone())?;
+			// wire_tap has no reply either, see `session switch`.
+			client.stats()?;
+			Ok(json!(payload))
+		}
+		["screenshot", monitor_id, out] => {
+			let screenshot = client.screenshot(monitor_id)?;
+			let info = &screenshot.info;
+			if info.fourcc != ScreenshotDataPayload::FOURCC_ABGR8888 {
+				return Err(CtlError::ScreenshotFormat(info.fourcc));
+			}
+			let (width, height, stride) = (
+				info.width as usize,
+				info.height as usize,
+				info.stride as usize,
+			);
+			let mut rgba = Vec::with_capacity(width * height * 4);
+			for row in screenshot.pixels.chunks(stride).take(height) {
+				rgba.extend_from_slice(&row[..width * 4]);
+			}
+			let out = PathBuf::from(out);
+			image::save_buffer(
+				&out,
+				&rgba,
+				info.width as u32,
+				info.height as u32,
+				image::ColorType::Rgba8,
+			)?;
+			Ok(json!({
+				"monitor_id": info.monitor_id,
+				"width": info.width,
+				"height": info.height,
+				"path": out,
+			}))
+		}
+		_ => Err(CtlError::Usage(USAGE.into())),
+	}
+}
+
+fn main() -> ExitCode {
+	match run() {
+		Ok(output) => {
+			println!("{output:#}");
+			ExitCode::SUCCESS
+		}
+		Err(e) => {
+			eprintln!("tab-ctl: {e}");
+			ExitCode::FAILURE
+		}
+	}
+}
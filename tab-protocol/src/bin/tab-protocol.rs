@@ -0,0 +1,33 @@
+//! tab-protocol: tooling around the protocol definitions.
+//!
+//! ```text
+//! tab-protocol dump-schema > tab-protocol.schema.json
+//! ```
+
+use std::process::ExitCode;
+
+const USAGE: &str = "usage:
+  tab-protocol dump-schema";
+
+fn main() -> ExitCode {
+	let args = std::env::args().skip(1).collect::<Vec<_>>();
+	match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+		["dump-schema"] => {
+			let schema = tab_protocol::schema::protocol_schema();
+			match serde_json::to_string_pretty(&schema) {
+				Ok(schema) => {
+					println!("{schema}");
+					ExitCode::SUCCESS
+				}
+				Err(e) => {
+					eprintln!("json error: {e}");
+					ExitCode::FAILURE
+				}
+			}
+		}
+		_ => {
+			eprintln!("{USAGE}");
+			ExitCode::from(2)
+		}
+	}
+}
@@ -0,0 +1,192 @@
+//! Blobs too large for a JSON payload, like keymaps, screenshots and selections.
+//! - the sender copies the data into a memfd sealed against writes and resizes and attaches it
+//!   with SCM_RIGHTS, see [`BulkPayload::seal`]
+//! - the JSON payload carries a [`BulkPayload`] saying where in the file the data is
+//! - the receiver maps it read-only with [`BulkMap`], which checks the seals and that the range
+//!   fits both the file and the caller's limit, so the sender can neither change nor truncate the
+//!   data while it is read
+
+use std::{
+	ffi::{CStr, c_void},
+	fs::File,
+	io::Write,
+	num::NonZeroUsize,
+	ops::Deref,
+	os::fd::{AsRawFd, OwnedFd},
+	ptr::NonNull,
+};
+
+use nix::{
+	fcntl::{FcntlArg, SealFlag, fcntl},
+	sys::{
+		memfd::{MemFdCreateFlag, memfd_create},
+		mman::{MapFlags, ProtFlags, mmap, munmap},
+	},
+	unistd::{SysconfVar, sysconf},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::ProtocolError;
+
+/// Seals a bulk memfd must carry for the receiver to trust its contents.
+pub const REQUIRED_SEALS: SealFlag = SealFlag::F_SEAL_SHRINK
+	.union(SealFlag::F_SEAL_GROW)
+	.union(SealFlag::F_SEAL_WRITE);
+
+/// Where the data is in the memfd sent along with a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct BulkPayload {
+	/// Bytes to skip at the start of the file.
+	pub offset: u64,
+	pub size: u64,
+}
+
+impl BulkPayload {
+	/// The first `size` bytes of the file.
+	pub fn whole(size: u64) -> Self {
+		Self { offset: 0, size }
+	}
+
+	/// Copies `data` into a memfd named `name` that can no longer be resized or written.
+	pub fn seal(name: &CStr, data: &[u8]) -> Result<(Self, OwnedFd), ProtocolError> {
+		let fd = memfd_create(
+			name,
+			MemFdCreateFlag::MFD_CLOEXEC | MemFdCreateFlag::MFD_ALLOW_SEALING,
+		)?;
+		let mut file = File::from(fd);
+		file.write_all(data)?;
+		fcntl(
+			file.as_raw_fd(),
+			FcntlArg::F_ADD_SEALS(REQUIRED_SEALS | SealFlag::F_SEAL_SEAL),
+		)?;
+		Ok((Self::whole(data.len() as u64), file.into()))
+	}
+}
+
+/// A read-only view of the data a [`BulkPayload`] describes, unmapped on drop.
+#[derive(Debug)]
+pub struct BulkMap {
+	/// Start of the mapping, `None` for empty payloads, which map nothing.
+	mapping: Option<NonNull<c_void>>,
+	mapping_len: usize,
+	/// Where the payload starts within the page-aligned mapping.
+	start: usize,
+	len: usize,
+}
+
+// SAFETY: the mapping is read-only and the file is sealed against writes, so it never changes.
+unsafe impl Send for BulkMap {}
+// SAFETY: see above.
+unsafe impl Sync for BulkMap {}
+
+impl BulkMap {
+	/// Maps what `payload` describes in `fd`, refusing payloads larger than `limit` bytes.
+	pub fn map(fd: &OwnedFd, payload: BulkPayload, limit: u64) -> Result<Self, ProtocolError> {
+		let seals = SealFlag::from_bits_truncate(fcntl(fd.as_raw_fd(), FcntlArg::F_GET_SEALS)?);
+		if !seals.contains(REQUIRED_SEALS) {
+			return Err(ProtocolError::InvalidPayload(
+				"bulk data must be a memfd sealed against writes and resizes".into(),
+			));
+		}
+		if payload.size > limit {
+			return Err(ProtocolError::InvalidPayload(format!(
+				"bulk data is {} bytes, at most {limit} are allowed",
+				payload.size
+			)));
+		}
+		let file_size = File::from(fd.try_clone()?).metadata()?.len();
+		if payload
+			.offset
+			.checked_add(payload.size)
+			.is_none_or(|end| end > file_size)
+		{
+			return Err(ProtocolError::InvalidPayload(format!(
+				"bulk data at {}..+{} is past the end of its {file_size} byte file",
+				payload.offset, payload.size
+			)));
+		}
+		let Some(len) = NonZeroUsize::new(payload.size as usize) else {
+			return Ok(Self {
+				mapping: None,
+				mapping_len: 0,
+				start: 0,
+				len: 0,
+			});
+		};
+		let page = sysconf(SysconfVar::PAGE_SIZE)?.unwrap_or(4096) as u64;
+		let aligned = payload.offset - payload.offset % page;
+		let start = (payload.offset - aligned) as usize;
+		let mapping_len = len.saturating_add(start);
+		// SAFETY: a fresh private read-only mapping, within the file as checked above. The seals
+		// keep it from being truncated, which would fault reads.
+		let mapping = unsafe {
+			mmap(
+				None,
+				mapping_len,
+				ProtFlags::PROT_READ,
+				MapFlags::MAP_PRIVATE,
+				fd,
+				aligned as i64,
+			)?
+		};
+		Ok(Self {
+			mapping: Some(mapping),
+			mapping_len: mapping_len.get(),
+			start,
+			len: len.get(),
+		})
+	}
+}
+
+impl Deref for BulkMap {
+	type Target = [u8];
+
+	fn deref(&self) -> &[u8] {
+		match self.mapping {
+			// SAFETY: the mapping covers `start..start + len` and lives as long as `self`.
+			Some(mapping) => unsafe {
+				std::slice::from_raw_parts(mapping.cast::<u8>().as_ptr().add(self.start), self.len)
+			},
+			None => &[],
+		}
+	}
+}
+
+impl Drop for BulkMap {
+	fn drop(&mut self) {
+		if let Some(mapping) = self.mapping {
+			// SAFETY: mapped in `map` with this length and not handed out past `self`.
+			let _ = unsafe { munmap(mapping, self.mapping_len) };
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn bulk_maps_validate_ranges_and_seals() {
+		let (payload, fd) = BulkPayload::seal(c"bulk-test", b"hello bulk").unwrap();
+		assert_eq!(payload, BulkPayload::whole(10));
+		assert_eq!(&*BulkMap::map(&fd, payload, 10).unwrap(), b"hello bulk");
+		let tail = BulkPayload { offset: 6, size: 4 };
+		assert_eq!(&*BulkMap::map(&fd, tail, 10).unwrap(), b"bulk");
+		assert!(BulkMap::map(&fd, payload, 9).is_err());
+		assert!(BulkMap::map(&fd, BulkPayload { offset: 6, size: 5 }, 10).is_err());
+		assert!(
+			BulkMap::map(
+				&fd,
+				BulkPayload {
+					offset: u64::MAX,
+					size: 1
+				},
+				10
+			)
+			.is_err()
+		);
+
+		let unsealed = memfd_create(c"bulk-test", MemFdCreateFlag::MFD_CLOEXEC).unwrap();
+		assert!(BulkMap::map(&unsealed, BulkPayload::whole(0), 10).is_err());
+	}
+}
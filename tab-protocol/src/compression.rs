@@ -0,0 +1,69 @@
+//! Optional zstd compression for large frame payloads.
+//!
+//! The server advertises supported algorithms in `hello`, the client opts in through `auth`.
+//! Compressed payloads are base64 encoded so they stay a single payload line, and the header
+//! line carries [`ZSTD_FLAG`] so the receiver knows to decompress.
+
+use crate::ProtocolError;
+
+/// Algorithm name used in `hello.compression` / `auth.compression`.
+pub const ZSTD: &str = "zstd";
+/// Suffix appended to the header line of a compressed frame.
+pub const ZSTD_FLAG: &str = " +zstd";
+/// Payloads shorter than this are always sent uncompressed.
+pub const COMPRESSION_THRESHOLD: usize = 16 * 1024;
+/// Upper bound for a single payload, before and after decompression.
+pub const MAX_PAYLOAD_BYTES: usize = 16 * 1024 * 1024;
+#[cfg(feature = "compression")]
+const ZSTD_LEVEL: i32 = 3;
+
+/// Compression algorithms this build can decode.
+pub fn supported() -> Vec<String> {
+	if cfg!(feature = "compression") {
+		vec![ZSTD.to_string()]
+	} else {
+		Vec::new()
+	}
+}
+
+#[cfg(feature = "compression")]
+pub(crate) fn compress(payload: &str) -> Result<String, ProtocolError> {
+	use base64::Engine;
+	let compressed = zstd::bulk::compress(payload.as_bytes(), ZSTD_LEVEL)?;
+	Ok(base64::engine::general_purpose::STANDARD.encode(compressed))
+}
+
+#[cfg(not(feature = "compression"))]
+pub(crate) fn compress(_payload: &str) -> Result<String, ProtocolError> {
+	Err(ProtocolError::UnsupportedCompression)
+}
+
+#[cfg(feature = "compression")]
+pub(crate) fn decompress(payload: &str) -> Result<String, ProtocolError> {
+	use std::io::Read;
+
+	use base64::Engine;
+	let compressed = base64::engine::general_purpose::STANDARD
+		.decode(payload)
+		.map_err(|e| ProtocolError::InvalidPayload(format!("compressed payload: {e}")))?;
+	// One byte past the limit tells an oversized payload apart from a corrupt one.
+	let mut decompressed = Vec::new();
+	zstd::stream::read::Decoder::new(compressed.as_slice())
+		.and_then(|decoder| {
+			decoder
+				.take(MAX_PAYLOAD_BYTES as u64 + 1)
+				.read_to_end(&mut decompressed)
+		})
+		.map_err(|e| ProtocolError::InvalidPayload(format!("compressed payload: {e}")))?;
+	if decompressed.len() > MAX_PAYLOAD_BYTES {
+		return Err(ProtocolError::PayloadTooLarge {
+			limit: MAX_PAYLOAD_BYTES,
+		});
+	}
+	Ok(String::from_utf8(decompressed)?)
+}
+
+#[cfg(not(feature = "compression"))]
+pub(crate) fn decompress(_payload: &str) -> Result<String, ProtocolError> {
+	Err(ProtocolError::UnsupportedCompression)
+}
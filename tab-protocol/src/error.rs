@@ -25,4 +25,12 @@ pub enum ProtocolError {
 		"Expected the received message to contain exactly {expected} attached file descriptors, got {found}"
 	)]
 	ExpectedFds { expected: u32, found: u32 },
+	#[error("payload exceeds the {limit} byte limit")]
+	PayloadTooLarge { limit: usize },
+	#[error("received a compressed payload but compression support is disabled")]
+	UnsupportedCompression,
+	#[error("this transport cannot pass file descriptors")]
+	FdPassingUnsupported,
+	#[error("invalid transport address: {0}")]
+	InvalidAddress(String),
 }
@@ -1,22 +1,28 @@
 //! Shared Tab v1 protocol definitions and helpers for both client and server sides.
-//! - Message framing over Unix domain sockets (sendmsg/recvmsg + SCM_RIGHTS)
+//! - Message framing over Unix domain sockets (sendmsg/recvmsg + SCM_RIGHTS), TCP or vsock
 //! - Raw TabMessageFrame representation (header + payload string + FDs)
 //! - Parsing helpers into typed TabMessage variants
+//! - Sealed memfds for blobs too large for a payload, see [`bulk`]
+//! - JSON Schema of every payload with the `schema` feature, see `schema`
 
 use serde::{Deserialize, Serialize};
 use std::{
-	os::fd::{FromRawFd, OwnedFd},
-	str::FromStr,
-	time::Duration,
+	collections::BTreeMap, ops::RangeInclusive, os::fd::OwnedFd, str::FromStr, time::Duration,
 };
 
+pub mod bulk;
+pub mod compression;
 pub mod message_frame;
+#[cfg(feature = "schema")]
+pub mod schema;
+pub mod transport;
 pub mod unix_socket_utils;
 /// Default Unix domain socket for Tab connections.
 pub const DEFAULT_SOCKET_PATH: &str = "/tmp/shift.sock";
 /// Protocol identifier string expected in `hello` payloads. Used to check if the client and server are compatible.
 pub const PROTOCOL_VERSION: &str = const_str::concat!("tab/v", env!("CARGO_PKG_VERSION"));
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[repr(u8)]
 pub enum BufferIndex {
 	Zero = 0,
@@ -44,29 +50,108 @@ pub enum TabMessage {
 		payload: FramebufferLinkPayload,
 		dma_bufs: [OwnedFd; 2],
 	},
+	FramebufferLinkFailed(FramebufferLinkFailedPayload),
+	RelinkRequest(RelinkRequestPayload),
+	BuffersInvalidated(BuffersInvalidatedPayload),
+	BufferUpload(BufferUploadPayload),
 	BufferRequest {
 		payload: BufferRequestPayload,
 		acquire_fence: Option<OwnedFd>,
 	},
 	BufferRequestAck(BufferRequestAckPayload),
+	FramesSkipped(FramesSkippedPayload),
+	FramesDropped(FramesDroppedPayload),
+	PerformanceWarning(PerformanceWarningPayload),
 	BufferRelease {
 		payload: BufferReleasePayload,
 		release_fence: Option<OwnedFd>,
 	},
+	BufferReleases {
+		payload: BufferReleasesPayload,
+		release_fences: Vec<OwnedFd>,
+	},
+	PresentGroup(PresentGroupPayload),
 	InputEvent(InputEventPayload),
 	MonitorAdded(MonitorAddedPayload),
 	MonitorRemoved(MonitorRemovedPayload),
+	MonitorChanged(MonitorChangedPayload),
 	SessionSwitch(SessionSwitchPayload),
+	TransitionDefine(TransitionDefinePayload),
 	SessionCreate(SessionCreatePayload),
 	SessionCreated(SessionCreatedPayload),
 	SessionReady(SessionReadyPayload),
 	SessionState(SessionStatePayload),
 	SessionActive(SessionActivePayload),
+	SessionInactive(SessionInactivePayload),
 	SessionAwake(SessionAwakePayload),
 	SessionSleep(SessionSleepPayload),
+	SessionVisibility(SessionVisibilityPayload),
+	SessionPip(SessionPipPayload),
+	MonitorLayout(MonitorLayoutPayload),
+	MonitorHdr(MonitorHdrPayload),
+	MonitorMirror(MonitorMirrorPayload),
+	MonitorFilter(MonitorFilterPayload),
+	ZoomSet(ZoomSetPayload),
+	SessionAssignMonitor(SessionAssignMonitorPayload),
+	FocusIn(FocusPayload),
+	FocusOut(FocusPayload),
+	PointerLock(PointerLockPayload),
+	PointerLockState(PointerLockStatePayload),
+	ShortcutRegister(ShortcutRegisterPayload),
+	ShortcutUnregister(ShortcutUnregisterPayload),
+	ShortcutTriggered(ShortcutTriggeredPayload),
+	DebugHud(DebugHudPayload),
+	BackgroundSet(BackgroundSetPayload),
+	SplashEnded,
+	Resumed,
+	LogLevel(LogLevelPayload),
+	LogDump(LogDumpPayload),
+	LogRecords(LogRecordsPayload),
+	WireTap(WireTapPayload),
+	SessionList,
+	Sessions(SessionsPayload),
+	TransitionsList,
+	Transitions(TransitionsPayload),
+	StatsRequest,
+	Stats(StatsPayload),
+	StateSyncRequest,
+	StateSync(StateSyncPayload),
+	CompositorHealthSubscribe(CompositorHealthSubscribePayload),
+	CompositorHealth(CompositorHealthPayload),
+	SessionUnresponsive(SessionUnresponsivePayload),
+	SessionCrashed(SessionCrashedPayload),
+	Screenshot(ScreenshotPayload),
+	ScreenshotData {
+		payload: ScreenshotDataPayload,
+		pixels: OwnedFd,
+	},
+	SelectionOffer {
+		payload: SelectionOfferPayload,
+		data: OwnedFd,
+	},
+	SelectionRequest,
+	SelectionData {
+		payload: SelectionDataPayload,
+		data: OwnedFd,
+	},
+	SelectionPolicy(SelectionPolicyPayload),
+	BacklightGet,
+	Backlights(BacklightsPayload),
+	BacklightSet(BacklightSetPayload),
+	DeviceCalibration(DeviceCalibrationPayload),
+	DeviceConfigure(DeviceConfigurePayload),
+	DeviceConfigGet,
+	DeviceConfigs(DeviceConfigsPayload),
+	DeviceGrab(DeviceGrabPayload),
+	InputInject(InputInjectPayload),
+	SwitchEventsSubscribe(SwitchEventsSubscribePayload),
+	LidClosed(LidClosedPayload),
+	TabletMode(TabletModePayload),
+	ServerShutdown(ServerShutdownPayload),
 	Error(ErrorPayload),
 	Ping,
 	Pong,
+	/// A header this version doesn't know. Its FDs have already been closed.
 	Unknown(TabMessageFrame),
 }
 impl TryFrom<TabMessageFrame> for TabMessage {
@@ -78,9 +163,20 @@ impl TryFrom<TabMessageFrame> for TabMessage {
 
 impl TabMessage {
 	/// Parse the raw TabMessageFrame into a typed `TabMessage` variant.
+	///
+	/// Takes ownership of the frame's FDs: they end up in the returned message, or are closed if
+	/// the frame is rejected or unknown.
 	#[tracing::instrument(skip_all, fields(header = %msg.header.0))]
-	pub fn parse_message_frame(msg: TabMessageFrame) -> Result<Self, ProtocolError> {
+	pub fn parse_message_frame(mut msg: TabMessageFrame) -> Result<Self, ProtocolError> {
+		let mut fds = std::mem::take(&mut msg.fds);
 		let header = msg.header.0.as_str();
+		let allowed = allowed_fds(header);
+		if !allowed.contains(&fds.len()) {
+			return Err(ProtocolError::ExpectedFds {
+				expected: *allowed.end() as u32,
+				found: fds.len() as u32,
+			});
+		}
 
 		match header {
 			message_header::HELLO => {
@@ -101,90 +197,101 @@ impl TabMessage {
 			}
 			message_header::FRAMEBUFFER_LINK => {
 				let payload: FramebufferLinkPayload = msg.expect_payload_json()?;
-				msg.expect_n_fds(2)?;
-				let dma_bufs = unsafe {
-					[
-						OwnedFd::from_raw_fd(msg.fds[0]),
-						OwnedFd::from_raw_fd(msg.fds[1]),
-					]
-				};
+				let dma_bufs = <[OwnedFd; 2]>::try_from(fds).map_err(|fds| ProtocolError::ExpectedFds {
+					expected: 2,
+					found: fds.len() as u32,
+				})?;
 				Ok(TabMessage::FramebufferLink { payload, dma_bufs })
 			}
+			message_header::FRAMEBUFFER_LINK_FAILED => {
+				let payload: FramebufferLinkFailedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FramebufferLinkFailed(payload))
+			}
+			message_header::RELINK_REQUEST => {
+				let payload: RelinkRequestPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::RelinkRequest(payload))
+			}
+			message_header::BUFFERS_INVALIDATED => {
+				let payload: BuffersInvalidatedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::BuffersInvalidated(payload))
+			}
+			message_header::BUFFER_UPLOAD => {
+				let payload: BufferUploadPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::BufferUpload(payload))
+			}
 			message_header::BUFFER_REQUEST => {
-				let payload = msg.payload.clone().ok_or(ProtocolError::ExpectedPayload)?;
-				let err = ProtocolError::InvalidPayload(
-					r#""buffer_request" request requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
-						.into(),
-				);
-				let split = payload.split_ascii_whitespace().collect::<Vec<_>>();
-				let [monitor_id, buffer_index_str] = split[..] else {
-					return Err(err);
-				};
-				let buffer_index = buffer_index_str.parse().map_err(|_| err)?;
+				let (args, present_group) = split_present_group(&msg)?;
+				let (monitor_id, buffer, generation) = parse_buffer_fields(args, "buffer_request")?;
 				let payload = BufferRequestPayload {
-					monitor_id: monitor_id.into(),
-					buffer: buffer_index,
-				};
-				let acquire_fence = match msg.fds.len() {
-					0 => None,
-					1 => Some(unsafe { OwnedFd::from_raw_fd(msg.fds[0]) }),
-					found => {
-						return Err(ProtocolError::ExpectedFds {
-							expected: 1,
-							found: found as u32,
-						});
-					}
+					monitor_id,
+					buffer,
+					generation,
+					present_group,
 				};
+				let acquire_fence = fds.pop();
 				Ok(TabMessage::BufferRequest {
 					payload,
 					acquire_fence,
 				})
 			}
 			message_header::BUFFER_REQUEST_ACK => {
-				let payload = msg.payload.clone().ok_or(ProtocolError::ExpectedPayload)?;
-				let err = ProtocolError::InvalidPayload(
-					r#""buffer_request_ack" event requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
-						.into(),
-				);
-				let split = payload.split_ascii_whitespace().collect::<Vec<_>>();
-				let [monitor_id, buffer_index_str] = split[..] else {
-					return Err(err);
-				};
-				let buffer_index = buffer_index_str.parse().map_err(|_| err)?;
+				let (monitor_id, buffer, generation) = parse_buffer_args(&msg, "buffer_request_ack")?;
 				Ok(TabMessage::BufferRequestAck(BufferRequestAckPayload {
-					monitor_id: monitor_id.into(),
-					buffer: buffer_index,
+					monitor_id,
+					buffer,
+					generation,
 				}))
 			}
 			message_header::BUFFER_RELEASE => {
-				let payload = msg.payload.clone().ok_or(ProtocolError::ExpectedPayload)?;
-				let err = ProtocolError::InvalidPayload(
-					r#""buffer_release" event requires 2 arguments: <monitor_id> <0 or 1 (buffer index)>"#
-						.into(),
-				);
-				let split = payload.split_ascii_whitespace().collect::<Vec<_>>();
-				let [monitor_id, buffer_index_str] = split[..] else {
-					return Err(err);
-				};
-				let buffer_index = buffer_index_str.parse().map_err(|_| err)?;
-				let release_fence = match msg.fds.len() {
-					0 => None,
-					1 => Some(unsafe { OwnedFd::from_raw_fd(msg.fds[0]) }),
-					found => {
-						return Err(ProtocolError::ExpectedFds {
-							expected: 1,
-							found: found as u32,
-						});
-					}
-				};
+				let (monitor_id, buffer, generation) = parse_buffer_args(&msg, "buffer_release")?;
+				let release_fence = fds.pop();
 				Ok(TabMessage::BufferRelease {
 					payload: BufferReleasePayload {
-						monitor_id: monitor_id.into(),
-						buffer: buffer_index,
+						monitor_id,
+						buffer,
+						generation,
 					},
 					release_fence,
 				})
 			}
+			message_header::PRESENT_GROUP => {
+				let payload: PresentGroupPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::PresentGroup(payload))
+			}
+			message_header::BUFFER_RELEASES => {
+				let payload: BufferReleasesPayload = msg.expect_payload_json()?;
+				if payload.fences.len() != fds.len() {
+					return Err(ProtocolError::ExpectedFds {
+						expected: payload.fences.len() as u32,
+						found: fds.len() as u32,
+					});
+				}
+				if payload
+					.fences
+					.iter()
+					.any(|index| *index >= payload.releases.len())
+				{
+					return Err(ProtocolError::InvalidPayload(
+						"buffer_releases fence index out of range".into(),
+					));
+				}
+				Ok(TabMessage::BufferReleases {
+					payload,
+					release_fences: fds,
+				})
+			}
+			message_header::FRAMES_SKIPPED => {
+				let payload: FramesSkippedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FramesSkipped(payload))
+			}
+			message_header::FRAMES_DROPPED => {
+				let payload: FramesDroppedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FramesDropped(payload))
+			}
+			message_header::PERFORMANCE_WARNING => {
+				let payload: PerformanceWarningPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::PerformanceWarning(payload))
+			}
 			message_header::INPUT_EVENT => {
 				let payload: InputEventPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::InputEvent(payload))
@@ -197,10 +304,18 @@ impl TabMessage {
 				let payload: MonitorRemovedPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::MonitorRemoved(payload))
 			}
+			message_header::MONITOR_CHANGED => {
+				let payload: MonitorChangedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorChanged(payload))
+			}
 			message_header::SESSION_SWITCH => {
 				let payload: SessionSwitchPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::SessionSwitch(payload))
 			}
+			message_header::TRANSITION_DEFINE => {
+				let payload: TransitionDefinePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::TransitionDefine(payload))
+			}
 			message_header::SESSION_CREATE => {
 				let payload: SessionCreatePayload = msg.expect_payload_json()?;
 				Ok(TabMessage::SessionCreate(payload))
@@ -221,6 +336,10 @@ impl TabMessage {
 				let payload: SessionActivePayload = msg.expect_payload_json()?;
 				Ok(TabMessage::SessionActive(payload))
 			}
+			message_header::SESSION_INACTIVE => {
+				let payload: SessionInactivePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionInactive(payload))
+			}
 			message_header::SESSION_AWAKE => {
 				let payload: SessionAwakePayload = msg.expect_payload_json()?;
 				Ok(TabMessage::SessionAwake(payload))
@@ -229,46 +348,339 @@ impl TabMessage {
 				let payload: SessionSleepPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::SessionSleep(payload))
 			}
+			message_header::SESSION_VISIBILITY => {
+				let payload: SessionVisibilityPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionVisibility(payload))
+			}
+			message_header::SESSION_PIP => {
+				let payload: SessionPipPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionPip(payload))
+			}
+			message_header::MONITOR_LAYOUT => {
+				let payload: MonitorLayoutPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorLayout(payload))
+			}
+			message_header::MONITOR_HDR => {
+				let payload: MonitorHdrPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorHdr(payload))
+			}
+			message_header::MONITOR_MIRROR => {
+				let payload: MonitorMirrorPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorMirror(payload))
+			}
+			message_header::MONITOR_FILTER => {
+				let payload: MonitorFilterPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::MonitorFilter(payload))
+			}
+			message_header::ZOOM_SET => {
+				let payload: ZoomSetPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ZoomSet(payload))
+			}
+			message_header::SESSION_ASSIGN_MONITOR => {
+				let payload: SessionAssignMonitorPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionAssignMonitor(payload))
+			}
+			message_header::FOCUS_IN => {
+				let payload: FocusPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FocusIn(payload))
+			}
+			message_header::FOCUS_OUT => {
+				let payload: FocusPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::FocusOut(payload))
+			}
+			message_header::POINTER_LOCK => {
+				let payload: PointerLockPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::PointerLock(payload))
+			}
+			message_header::POINTER_LOCK_STATE => {
+				let payload: PointerLockStatePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::PointerLockState(payload))
+			}
+			message_header::SHORTCUT_REGISTER => {
+				let payload: ShortcutRegisterPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ShortcutRegister(payload))
+			}
+			message_header::SHORTCUT_UNREGISTER => {
+				let payload: ShortcutUnregisterPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ShortcutUnregister(payload))
+			}
+			message_header::SHORTCUT_TRIGGERED => {
+				let payload: ShortcutTriggeredPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ShortcutTriggered(payload))
+			}
+			message_header::BACKGROUND_SET => {
+				let payload: BackgroundSetPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::BackgroundSet(payload))
+			}
+			message_header::SPLASH_ENDED => Ok(TabMessage::SplashEnded),
+			message_header::RESUMED => Ok(TabMessage::Resumed),
+			message_header::DEBUG_HUD => {
+				let payload: DebugHudPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::DebugHud(payload))
+			}
+			message_header::LOG_LEVEL => {
+				let payload: LogLevelPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::LogLevel(payload))
+			}
+			message_header::LOG_DUMP => {
+				let payload: LogDumpPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::LogDump(payload))
+			}
+			message_header::LOG_RECORDS => {
+				let payload: LogRecordsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::LogRecords(payload))
+			}
+			message_header::WIRE_TAP => {
+				let payload: WireTapPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::WireTap(payload))
+			}
+			message_header::SESSION_LIST => Ok(TabMessage::SessionList),
+			message_header::SESSIONS => {
+				let payload: SessionsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Sessions(payload))
+			}
+			message_header::TRANSITIONS_LIST => Ok(TabMessage::TransitionsList),
+			message_header::TRANSITIONS => {
+				let payload: TransitionsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Transitions(payload))
+			}
+			message_header::STATS_REQUEST => Ok(TabMessage::StatsRequest),
+			message_header::STATS => {
+				let payload: StatsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Stats(payload))
+			}
+			message_header::STATE_SYNC_REQUEST => Ok(TabMessage::StateSyncRequest),
+			message_header::STATE_SYNC => {
+				let payload: StateSyncPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::StateSync(payload))
+			}
+			message_header::COMPOSITOR_HEALTH_SUBSCRIBE => {
+				let payload: CompositorHealthSubscribePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::CompositorHealthSubscribe(payload))
+			}
+			message_header::COMPOSITOR_HEALTH => {
+				let payload: CompositorHealthPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::CompositorHealth(payload))
+			}
+			message_header::SESSION_UNRESPONSIVE => {
+				let payload: SessionUnresponsivePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionUnresponsive(payload))
+			}
+			message_header::SESSION_CRASHED => {
+				let payload: SessionCrashedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SessionCrashed(payload))
+			}
+			message_header::SCREENSHOT => {
+				let payload: ScreenshotPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Screenshot(payload))
+			}
+			message_header::SCREENSHOT_DATA => {
+				let payload: ScreenshotDataPayload = msg.expect_payload_json()?;
+				let pixels = fds.pop().ok_or(ProtocolError::ExpectedFds {
+					expected: 1,
+					found: 0,
+				})?;
+				Ok(TabMessage::ScreenshotData { payload, pixels })
+			}
+			message_header::SELECTION_OFFER => {
+				let payload: SelectionOfferPayload = msg.expect_payload_json()?;
+				let data = fds.pop().ok_or(ProtocolError::ExpectedFds {
+					expected: 1,
+					found: 0,
+				})?;
+				Ok(TabMessage::SelectionOffer { payload, data })
+			}
+			message_header::SELECTION_REQUEST => Ok(TabMessage::SelectionRequest),
+			message_header::SELECTION_DATA => {
+				let payload: SelectionDataPayload = msg.expect_payload_json()?;
+				let data = fds.pop().ok_or(ProtocolError::ExpectedFds {
+					expected: 1,
+					found: 0,
+				})?;
+				Ok(TabMessage::SelectionData { payload, data })
+			}
+			message_header::SELECTION_POLICY => {
+				let payload: SelectionPolicyPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SelectionPolicy(payload))
+			}
+			message_header::BACKLIGHT_GET => Ok(TabMessage::BacklightGet),
+			message_header::BACKLIGHTS => {
+				let payload: BacklightsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::Backlights(payload))
+			}
+			message_header::BACKLIGHT_SET => {
+				let payload: BacklightSetPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::BacklightSet(payload))
+			}
+			message_header::DEVICE_CALIBRATION => {
+				let payload: DeviceCalibrationPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::DeviceCalibration(payload))
+			}
+			message_header::DEVICE_CONFIGURE => {
+				let payload: DeviceConfigurePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::DeviceConfigure(payload))
+			}
+			message_header::DEVICE_CONFIG_GET => Ok(TabMessage::DeviceConfigGet),
+			message_header::DEVICE_GRAB => {
+				let payload: DeviceGrabPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::DeviceGrab(payload))
+			}
+			message_header::DEVICE_CONFIGS => {
+				let payload: DeviceConfigsPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::DeviceConfigs(payload))
+			}
+			message_header::INPUT_INJECT => {
+				let payload: InputInjectPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::InputInject(payload))
+			}
+			message_header::SWITCH_EVENTS_SUBSCRIBE => {
+				let payload: SwitchEventsSubscribePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::SwitchEventsSubscribe(payload))
+			}
+			message_header::LID_CLOSED => {
+				let payload: LidClosedPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::LidClosed(payload))
+			}
+			message_header::TABLET_MODE => {
+				let payload: TabletModePayload = msg.expect_payload_json()?;
+				Ok(TabMessage::TabletMode(payload))
+			}
+			message_header::SERVER_SHUTDOWN => {
+				let payload: ServerShutdownPayload = msg.expect_payload_json()?;
+				Ok(TabMessage::ServerShutdown(payload))
+			}
 			message_header::ERROR => {
 				let payload: ErrorPayload = msg.expect_payload_json()?;
 				Ok(TabMessage::Error(payload))
 			}
 			message_header::PING => Ok(TabMessage::Ping),
 			message_header::PONG => Ok(TabMessage::Pong),
-			_ => Ok(TabMessage::Unknown(msg)),
+			_ => {
+				if !fds.is_empty() {
+					tracing::debug!(
+						count = fds.len(),
+						"closing FDs attached to an unknown message"
+					);
+				}
+				Ok(TabMessage::Unknown(msg))
+			}
 		}
 	}
 }
 /// Typed payloads
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct HelloPayload {
 	pub server: String,
 	pub protocol: String,
+	/// Payload compression algorithms the server accepts (see [`compression`]).
+	#[serde(default)]
+	pub compression: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AuthPayload {
 	pub token: String,
+	/// Compression algorithm picked from `hello.compression`; both sides may then compress payloads.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub compression: Option<String>,
+	/// The client repeats held keys itself, so shift sends it no `repeat` key events.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub local_key_repeat: bool,
+	/// The client understands `buffer_releases`, so shift sends the releases of one page flip
+	/// together.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub coalesce_releases: bool,
+}
+
+/// How shift repeats held keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct KeyRepeatInfo {
+	/// Repeats per second, 0 when keys don't repeat.
+	pub rate: u32,
+	/// Time from the press to the first repeat.
+	pub delay_ms: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MonitorInfo {
 	pub id: String,
 	pub width: i32,
 	pub height: i32,
 	pub refresh_rate: i32,
 	pub name: String,
+	/// From the monitor's EDID, when it has a readable one.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub manufacturer: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub model: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub serial: Option<String>,
+	/// Physical size of the visible area.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub width_mm: Option<u32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub height_mm: Option<u32>,
+	/// A VR headset or similar display that isn't meant to show a desktop.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub non_desktop: bool,
+	/// How a built-in panel is mounted in its case, when the kernel knows. `width` and `height`
+	/// are already those of the upright picture, which shift rotates onto the panel.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub panel_orientation: Option<PanelOrientation>,
+}
+
+/// Which side of a panel's native top edge points up once it's mounted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PanelOrientation {
+	Normal,
+	UpsideDown,
+	LeftSideUp,
+	RightSideUp,
+}
+
+impl PanelOrientation {
+	/// Whether the panel is mounted on its side, so the upright picture is as wide as the panel
+	/// is tall.
+	pub fn is_sideways(self) -> bool {
+		matches!(self, Self::LeftSideUp | Self::RightSideUp)
+	}
+
+	/// Maps a position on the panel, normalized to `0.0..=1.0` along the panel's own axes, to
+	/// where it is on the upright picture.
+	pub fn to_upright(self, (x, y): (f64, f64)) -> (f64, f64) {
+		match self {
+			Self::Normal => (x, y),
+			Self::UpsideDown => (1.0 - x, 1.0 - y),
+			Self::LeftSideUp => (1.0 - y, x),
+			Self::RightSideUp => (y, 1.0 - x),
+		}
+	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionInfo {
 	pub id: String,
 	pub role: SessionRole,
 	pub display_name: Option<String>,
 	pub state: SessionLifecycle,
+	/// Whether a client is connected for the session. Only set in `sessions`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub attached: Option<bool>,
+	/// Whether the session is on screen, as last sent in `session_visibility`. Only set in
+	/// `sessions`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub visible: Option<bool>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum SessionLifecycle {
 	Pending,
@@ -278,24 +690,33 @@ pub enum SessionLifecycle {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum SessionRole {
 	Admin,
 	Session,
+	/// Read-only: sees monitors, sessions and stats, never presents or manages sessions.
+	Observer,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AuthOkPayload {
 	pub session: SessionInfo,
 	pub monitors: Vec<MonitorInfo>,
+	/// Unset by servers that don't repeat keys.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub key_repeat: Option<KeyRepeatInfo>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct AuthErrorPayload {
 	pub error: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct FramebufferLinkPayload {
 	pub monitor_id: String,
 	pub width: i32,
@@ -303,26 +724,346 @@ pub struct FramebufferLinkPayload {
 	pub stride: i32,
 	pub offset: i32,
 	pub fourcc: i32,
+	/// DRM format modifier of the buffers' tiling and compression. Left out, the driver assumes
+	/// one, which is only right for linear buffers on most hardware.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub modifier: Option<u64>,
+	#[serde(default)]
+	pub present_mode: PresentMode,
+	/// Tags the acks and releases of this link's buffers, so ones meant for an earlier link can
+	/// be told apart. Should grow with every link of the monitor; 0 leaves them untagged.
+	#[serde(default)]
+	pub generation: u64,
+}
+
+/// Sent instead of linking when shift can't import a `framebuffer_link`'s dma-bufs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FramebufferLinkFailedPayload {
+	pub monitor_id: String,
+	pub reason: String,
+	/// DRM fourccs shift can import, to reallocate the swapchain with. Empty if unknown.
+	pub supported_formats: Vec<i32>,
+}
+
+/// shift dropped its import of the swapchain linked for `monitor_id` to free GPU memory. Any
+/// buffers it held were released first; `framebuffer_link` the same buffers again before the
+/// next `buffer_request` on this monitor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct RelinkRequestPayload {
+	pub monitor_id: String,
+}
+
+/// shift dropped its GPU state and with it every import of the session's swapchains linked for
+/// `monitor_ids`. Any buffers it held were released first; `framebuffer_link` them again, and
+/// redraw, before the next `buffer_request` on those monitors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BuffersInvalidatedPayload {
+	pub monitor_ids: Vec<String>,
+}
+
+/// How shift queues buffers a session submits faster than the display refreshes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+	/// Every submitted buffer is shown for at least one refresh, in order.
+	#[default]
+	Fifo,
+	/// The newest buffer replaces any not yet shown one, which is released right away.
+	Mailbox,
+}
+
+/// Most pixel bytes one `buffer_upload` carries. Base64 grows them by a third, which keeps the
+/// frame within [`compression::MAX_PAYLOAD_BYTES`].
+pub const MAX_UPLOAD_CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// Pixels for one buffer slot, sent inline on transports that can't pass dma-bufs. Buffers too
+/// big for one frame are sent as several, each with the rows that follow the last one's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BufferUploadPayload {
+	pub monitor_id: String,
+	/// Buffer slot, 0 or 1.
+	pub buffer: u8,
+	/// Size of the whole buffer, not only of the rows in `data`.
+	pub width: i32,
+	pub height: i32,
+	pub stride: i32,
+	/// Row of the buffer `data` starts at.
+	#[serde(default)]
+	pub y: i32,
+	/// Base64 of whole rows, `stride` bytes each, of little-endian ARGB8888.
+	pub data: String,
+}
+
+impl BufferUploadPayload {
+	/// Uploads of `pixels`, `stride * height` bytes, each small enough for one frame.
+	pub fn split(
+		monitor_id: &str,
+		buffer: BufferIndex,
+		width: i32,
+		height: i32,
+		stride: i32,
+		pixels: &[u8],
+	) -> Vec<Self> {
+		use base64::Engine;
+		let upload = |y: usize, rows: &[u8]| Self {
+			monitor_id: monitor_id.to_string(),
+			buffer: buffer as u8,
+			width,
+			height,
+			stride,
+			y: i32::try_from(y).unwrap_or(i32::MAX),
+			data: base64::engine::general_purpose::STANDARD.encode(rows),
+		};
+		// Invalid strides go out whole, for shift to reject.
+		let row_bytes = usize::try_from(stride).unwrap_or(0).max(1);
+		let rows_per_upload = (MAX_UPLOAD_CHUNK_BYTES / row_bytes).max(1);
+		if pixels.is_empty() {
+			return vec![upload(0, pixels)];
+		}
+		pixels
+			.chunks(rows_per_upload * row_bytes)
+			.enumerate()
+			.map(|(i, rows)| upload(i * rows_per_upload, rows))
+			.collect()
+	}
+}
+
+/// Frames a client chose not to submit because nothing changed since its last buffer_request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FramesSkippedPayload {
+	pub monitor_id: String,
+	pub count: u32,
+}
+
+/// Why shift never showed frames a session submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FrameDropReason {
+	/// Replaced by a newer frame before the display refreshed, in [`PresentMode::Mailbox`].
+	Mailbox,
+	/// Replaced by a newer frame while queued, or while waiting on its acquire fence.
+	Queue,
+	/// Submitted while the session wasn't on any monitor.
+	Hidden,
+}
+
+/// Frames of a session shift dropped on `monitor_id` during the last second, for one reason.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FramesDroppedPayload {
+	pub monitor_id: String,
+	pub count: u32,
+	pub reason: FrameDropReason,
+}
+
+/// What shift noticed about the frames of a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PerformanceWarningKind {
+	/// The session kept submitting frames, but what they show didn't change. Likely the client
+	/// draws into a buffer other than the one it submits.
+	StaticContent,
+}
+
+/// Something about the frames a session submits on `monitor_id` that points at a bug in its
+/// client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PerformanceWarningPayload {
+	pub monitor_id: String,
+	pub kind: PerformanceWarningKind,
+	/// How long it went on before shift warned, in milliseconds.
+	pub duration_ms: u64,
+	/// Frames the session submitted meanwhile.
+	pub frames: u32,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BufferRequestPayload {
 	pub monitor_id: String,
 	pub buffer: BufferIndex,
+	/// `generation` of the link the buffer belongs to, 0 for the monitor's current link.
+	pub generation: u64,
+	/// Holds the buffer until every monitor of this `present_group` has one to show.
+	pub present_group: Option<u32>,
+}
+
+/// Monitors whose buffers flip together, for a scene spanning them. Tagged `buffer_request`s
+/// are held until each monitor of the group has one. Redefining a group rejects the requests it
+/// held; an empty `monitor_ids` removes it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PresentGroupPayload {
+	/// Chosen by the client, scoped to its session.
+	pub group: u32,
+	pub monitor_ids: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct BufferRequestAckPayload {
 	pub monitor_id: String,
 	pub buffer: BufferIndex,
+	/// `generation` of the link the buffer belongs to, 0 if it was untagged.
+	pub generation: u64,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct BufferReleasePayload {
 	pub monitor_id: String,
 	pub buffer: BufferIndex,
+	/// `generation` of the link the buffer belongs to, 0 if it was untagged.
+	pub generation: u64,
+}
+
+/// Most bytes of one frame on a seqpacket socket, its header and payload lines included. Readers
+/// reject longer ones as truncated, so payloads that can grow are split across frames.
+pub const MAX_FRAME_BYTES: usize = 4096;
+
+/// Most FDs one frame may carry, what readers make room for. More are dropped by the kernel and
+/// the frame is rejected as truncated.
+pub const MAX_FDS_PER_FRAME: usize = 8;
+
+/// Most releases one `buffer_releases` carries, so their fences fit in one frame. Longer batches
+/// are split across frames.
+pub const MAX_COALESCED_RELEASES: usize = MAX_FDS_PER_FRAME;
+
+/// The `buffer_release`s of one page flip, sent as one frame to clients that set
+/// `auth.coalesce_releases`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BufferReleasesPayload {
+	pub releases: Vec<BufferReleasePayload>,
+	/// Index into `releases` of each attached release fence, in FD order.
+	#[serde(default, skip_serializing_if = "Vec::is_empty")]
+	pub fences: Vec<usize>,
+}
+
+impl BufferReleasesPayload {
+	/// `buffer_releases` frames for `releases` and their fences, [`MAX_COALESCED_RELEASES`] each.
+	pub fn frames(
+		releases: impl IntoIterator<Item = (BufferReleasePayload, Option<OwnedFd>)>,
+	) -> Vec<TabMessageFrame> {
+		let mut releases = releases.into_iter().peekable();
+		let mut frames = Vec::new();
+		while releases.peek().is_some() {
+			let mut payload = Self::default();
+			let mut fds = Vec::new();
+			for (index, (release, fence)) in releases.by_ref().take(MAX_COALESCED_RELEASES).enumerate() {
+				if let Some(fence) = fence {
+					payload.fences.push(index);
+					fds.push(fence);
+				}
+				payload.releases.push(release);
+			}
+			let mut frame = TabMessageFrame::json(message_header::BUFFER_RELEASES, payload);
+			frame.fds = fds;
+			frames.push(frame);
+		}
+		frames
+	}
+
+	/// Pairs every release with its fence, if it came with one.
+	pub fn into_releases(
+		self,
+		release_fences: Vec<OwnedFd>,
+	) -> impl Iterator<Item = (BufferReleasePayload, Option<OwnedFd>)> {
+		let mut fences = self
+			.fences
+			.into_iter()
+			.zip(release_fences)
+			.collect::<BTreeMap<_, _>>();
+		self
+			.releases
+			.into_iter()
+			.enumerate()
+			.map(move |(index, release)| (release, fences.remove(&index)))
+	}
+}
+
+/// Raw payload of `buffer_request`, `buffer_request_ack` and `buffer_release`:
+/// `<monitor_id> <0|1> [generation]`, leaving out a 0 generation.
+pub fn buffer_args(monitor_id: &str, buffer: BufferIndex, generation: u64) -> String {
+	match generation {
+		0 => format!("{monitor_id} {}", buffer as u8),
+		generation => format!("{monitor_id} {} {generation}", buffer as u8),
+	}
+}
+
+/// Raw payload of `buffer_request`: [`buffer_args`], followed by `group=<id>` for a request held
+/// back by a `present_group`.
+pub fn buffer_request_args(
+	monitor_id: &str,
+	buffer: BufferIndex,
+	generation: u64,
+	present_group: Option<u32>,
+) -> String {
+	let args = buffer_args(monitor_id, buffer, generation);
+	match present_group {
+		Some(group) => format!("{args} group={group}"),
+		None => args,
+	}
+}
+
+/// The `buffer_args` of a `buffer_request`, and the group its `group=<id>` names.
+fn split_present_group(msg: &TabMessageFrame) -> Result<(Vec<&str>, Option<u32>), ProtocolError> {
+	let payload = msg
+		.payload
+		.as_deref()
+		.ok_or(ProtocolError::ExpectedPayload)?;
+	let mut args = payload.split_ascii_whitespace().collect::<Vec<_>>();
+	let Some(group) = args.last().and_then(|arg| arg.strip_prefix("group=")) else {
+		return Ok((args, None));
+	};
+	let group = group.parse().map_err(|_| {
+		ProtocolError::InvalidPayload(r#""buffer_request" requires group=<number>"#.into())
+	})?;
+	args.pop();
+	Ok((args, Some(group)))
+}
+
+fn parse_buffer_args(
+	msg: &TabMessageFrame,
+	name: &str,
+) -> Result<(String, BufferIndex, u64), ProtocolError> {
+	let payload = msg
+		.payload
+		.as_deref()
+		.ok_or(ProtocolError::ExpectedPayload)?;
+	parse_buffer_fields(payload.split_ascii_whitespace().collect(), name)
+}
+
+fn parse_buffer_fields(
+	split: Vec<&str>,
+	name: &str,
+) -> Result<(String, BufferIndex, u64), ProtocolError> {
+	let err = || {
+		ProtocolError::InvalidPayload(format!(
+			r#""{name}" requires <monitor_id> <0 or 1 (buffer index)> [generation]"#
+		))
+	};
+	let (monitor_id, buffer, generation) = match split[..] {
+		[monitor_id, buffer] => (monitor_id, buffer, "0"),
+		[monitor_id, buffer, generation] => (monitor_id, buffer, generation),
+		_ => return Err(err()),
+	};
+	Ok((
+		monitor_id.into(),
+		buffer.parse().map_err(|_| err())?,
+		generation.parse().map_err(|_| err())?,
+	))
 }
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 #[serde(tag = "kind", rename_all = "snake_case")]
 pub enum InputEventPayload {
 	PointerMotion {
@@ -335,6 +1076,7 @@ pub enum InputEventPayload {
 		unaccel_dx: f64,
 		unaccel_dy: f64,
 	},
+	/// `x_transformed` and `y_transformed` span 0 to 65535 across `monitor_id`.
 	PointerMotionAbsolute {
 		device: u32,
 		time_usec: u64,
@@ -342,6 +1084,9 @@ pub enum InputEventPayload {
 		y: f64,
 		x_transformed: f64,
 		y_transformed: f64,
+		/// Monitor the position falls on. Unset from senders that don't know the layout.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		monitor_id: Option<String>,
 	},
 	PointerButton {
 		device: u32,
@@ -362,6 +1107,9 @@ pub enum InputEventPayload {
 		time_usec: u64,
 		key: u32,
 		state: KeyState,
+		/// Sent again for a key held down, see [`KeyRepeatInfo`].
+		#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+		repeat: bool,
 	},
 	TouchDown {
 		device: u32,
@@ -489,34 +1237,76 @@ pub enum InputEventPayload {
 	},
 }
 
+impl InputEventPayload {
+	/// `device` of the event, `None` for `touch_frame` and `touch_cancel`, which carry none.
+	pub fn device(&self) -> Option<u32> {
+		match self {
+			Self::TouchFrame { .. } | Self::TouchCancel { .. } => None,
+			Self::PointerMotion { device, .. }
+			| Self::PointerMotionAbsolute { device, .. }
+			| Self::PointerButton { device, .. }
+			| Self::PointerAxis { device, .. }
+			| Self::Key { device, .. }
+			| Self::TouchDown { device, .. }
+			| Self::TouchUp { device, .. }
+			| Self::TouchMotion { device, .. }
+			| Self::TableToolProximity { device, .. }
+			| Self::TabletToolAxis { device, .. }
+			| Self::TabletToolTip { device, .. }
+			| Self::TabletToolButton { device, .. }
+			| Self::TablePadButton { device, .. }
+			| Self::TablePadRing { device, .. }
+			| Self::TablePadStrip { device, .. }
+			| Self::SwitchToggle { device, .. }
+			| Self::GestureSwipeBegin { device, .. }
+			| Self::GestureSwipeUpdate { device, .. }
+			| Self::GestureSwipeEnd { device, .. }
+			| Self::GesturePinchBegin { device, .. }
+			| Self::GesturePinchUpdate { device, .. }
+			| Self::GesturePinchEnd { device, .. }
+			| Self::GestureHoldBegin { device, .. }
+			| Self::GestureHoldEnd { device, .. } => Some(*device),
+		}
+	}
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum ButtonState {
 	Pressed,
 	Released,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum KeyState {
 	Pressed,
 	Released,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum TipState {
 	Down,
 	Up,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TouchContact {
 	pub id: i32,
 	pub x: f64,
 	pub y: f64,
+	/// 0 to 65535 across `monitor_id`.
 	pub x_transformed: f64,
 	pub y_transformed: f64,
+	/// Monitor the contact falls on. Unset from senders that don't know the layout.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub monitor_id: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TabletTool {
 	pub serial: u64,
 	pub tool_type: TabletToolType,
@@ -524,6 +1314,7 @@ pub struct TabletTool {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum TabletToolType {
 	Pen,
 	Eraser,
@@ -536,6 +1327,7 @@ pub enum TabletToolType {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TabletToolCapability {
 	pub pressure: bool,
 	pub distance: bool,
@@ -546,6 +1338,7 @@ pub struct TabletToolCapability {
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct TabletToolAxes {
 	pub x: f64,
 	pub y: f64,
@@ -560,12 +1353,14 @@ pub struct TabletToolAxes {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum AxisOrientation {
 	Vertical,
 	Horizontal,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum AxisSource {
 	Wheel,
 	Finger,
@@ -574,82 +1369,1685 @@ pub enum AxisSource {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum SwitchType {
 	Lid,
 	TabletMode,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub enum SwitchState {
 	On,
 	Off,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MonitorAddedPayload {
 	pub monitor: MonitorInfo,
+	/// State epoch after the change, see [`StateSyncPayload`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub epoch: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct MonitorRemovedPayload {
 	pub monitor_id: String,
 	pub name: String,
+	/// State epoch after the change, see [`StateSyncPayload`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub epoch: Option<u64>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A monitor changed mode. Buffers linked for it keep the old size until the client relinks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorChangedPayload {
+	pub monitor: MonitorInfo,
+	/// State epoch after the change, see [`StateSyncPayload`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub epoch: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionSwitchPayload {
 	pub session_id: String,
 	pub animation: Option<String>,
+	/// Whole milliseconds in `duration_ms` on the wire.
+	#[serde(rename = "duration_ms", alias = "duration", with = "duration_ms")]
+	#[cfg_attr(feature = "schema", schemars(with = "u64"))]
 	pub duration: Duration,
+	/// Easing applied to the animation progress. `None` is linear.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub easing: Option<Easing>,
+	/// Values for the animation's parameters, see `transitions`. Missing ones use the default.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub params: BTreeMap<String, f64>,
+}
+
+impl SessionSwitchPayload {
+	pub fn new(session_id: impl Into<String>, animation: Option<String>, duration: Duration) -> Self {
+		Self {
+			session_id: session_id.into(),
+			animation,
+			duration,
+			easing: None,
+			params: BTreeMap::new(),
+		}
+	}
+}
+
+/// A `Duration` as whole milliseconds. Older peers sent serde's `{ secs, nanos }` under
+/// `duration`, which is still accepted.
+mod duration_ms {
+	use std::time::Duration;
+
+	use serde::{Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_u64(u64::try_from(duration.as_millis()).unwrap_or(u64::MAX))
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+		#[derive(Deserialize)]
+		#[serde(untagged)]
+		enum Wire {
+			Millis(u64),
+			Legacy { secs: u64, nanos: u32 },
+		}
+		Ok(match Wire::deserialize(deserializer)? {
+			Wire::Millis(ms) => Duration::from_millis(ms),
+			Wire::Legacy { secs, nanos } => Duration::new(secs, nanos),
+		})
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+	#[default]
+	Linear,
+	EaseIn,
+	EaseOut,
+	EaseInOut,
+}
+
+impl Easing {
+	/// Maps linear progress in `0.0..=1.0` onto the curve.
+	pub fn apply(self, t: f64) -> f64 {
+		let t = t.clamp(0.0, 1.0);
+		match self {
+			Self::Linear => t,
+			Self::EaseIn => t * t * t,
+			Self::EaseOut => 1.0 - (1.0 - t).powi(3),
+			Self::EaseInOut => t * t * (3.0 - 2.0 * t),
+		}
+	}
+}
+
+/// A number an animation can be tuned with.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TransitionParam {
+	pub name: String,
+	pub description: String,
+	pub default: f64,
+	pub min: f64,
+	pub max: f64,
+}
+
+/// An animation `session_switch` accepts, and the parameters it takes.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TransitionInfo {
+	pub name: String,
+	pub params: Vec<TransitionParam>,
+}
+
+impl TransitionInfo {
+	/// Checks `params` against the schema: every name known, every value in range.
+	pub fn validate(&self, params: &BTreeMap<String, f64>) -> Result<(), String> {
+		for (name, value) in params {
+			let Some(param) = self.params.iter().find(|param| &param.name == name) else {
+				return Err(format!("{} has no parameter {name}", self.name));
+			};
+			if !(param.min..=param.max).contains(value) {
+				return Err(format!(
+					"{} parameter {name} must be within {}..={}",
+					self.name, param.min, param.max
+				));
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Longest name `transition_define` accepts.
+pub const MAX_TRANSITION_NAME_LEN: usize = 64;
+
+/// A session-switch animation built from keyframes, registered under `name`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TransitionDefinePayload {
+	pub name: String,
+	/// How the outgoing session is drawn, beneath `to`.
+	#[serde(default)]
+	pub from: Vec<KeyframeTrack>,
+	/// How the incoming session is drawn.
+	#[serde(default)]
+	pub to: Vec<KeyframeTrack>,
+}
+
+impl TransitionDefinePayload {
+	/// Checks the name, that every property is animated at most once per session, and that
+	/// keyframes are in order and in range.
+	pub fn validate(&self) -> Result<(), String> {
+		if self.name.is_empty()
+			|| self.name.len() > MAX_TRANSITION_NAME_LEN
+			|| !self
+				.name
+				.bytes()
+				.all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+		{
+			return Err(format!(
+				"transition names are 1 to {MAX_TRANSITION_NAME_LEN} letters, digits, '_' or '-'"
+			));
+		}
+		for (layer, tracks) in [("from", &self.from), ("to", &self.to)] {
+			for (index, track) in tracks.iter().enumerate() {
+				let property = track.property.name();
+				if tracks[..index].iter().any(|t| t.property == track.property) {
+					return Err(format!("{layer} animates {property} twice"));
+				}
+				if track.keyframes.is_empty() {
+					return Err(format!("{layer} {property} has no keyframes"));
+				}
+				let mut previous = None;
+				for keyframe in &track.keyframes {
+					if !(0.0..=1.0).contains(&keyframe.at) || previous.is_some_and(|at| keyframe.at <= at) {
+						return Err(format!(
+							"{layer} {property} keyframes must be in increasing order within 0..=1"
+						));
+					}
+					let range = track.property.range();
+					if !range.contains(&keyframe.value) {
+						return Err(format!(
+							"{layer} {property} values must be within {}..={}",
+							range.start(),
+							range.end()
+						));
+					}
+					previous = Some(keyframe.at);
+				}
+			}
+		}
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum KeyframeProperty {
+	/// Blur radius in pixels.
+	Blur,
+	Opacity,
+	/// Offset as a fraction of the monitor width.
+	TranslateX,
+	/// Offset as a fraction of the monitor height.
+	TranslateY,
+}
+
+impl KeyframeProperty {
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Blur => "blur",
+			Self::Opacity => "opacity",
+			Self::TranslateX => "translate_x",
+			Self::TranslateY => "translate_y",
+		}
+	}
+
+	/// The value while a property isn't animated.
+	pub fn default_value(self) -> f64 {
+		match self {
+			Self::Opacity => 1.0,
+			Self::Blur | Self::TranslateX | Self::TranslateY => 0.0,
+		}
+	}
+
+	pub fn range(self) -> RangeInclusive<f64> {
+		match self {
+			Self::Blur => 0.0..=200.0,
+			Self::Opacity => 0.0..=1.0,
+			Self::TranslateX | Self::TranslateY => -2.0..=2.0,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct KeyframeTrack {
+	pub property: KeyframeProperty,
+	pub keyframes: Vec<Keyframe>,
+}
+
+/// `value` at `at`, a fraction of the switch duration. Holds the first value before the first
+/// keyframe and the last one after the last.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Keyframe {
+	pub at: f64,
+	pub value: f64,
+	/// Curve from the previous keyframe to this one.
+	#[serde(default)]
+	pub easing: Easing,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionCreatePayload {
 	pub role: SessionRole,
 	pub display_name: Option<String>,
+	/// Shell command shift runs for the session, with `SHIFT_SESSION_TOKEN` set.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub command: Option<String>,
+	/// Extra environment for `command`.
+	#[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+	pub env: BTreeMap<String, String>,
+	/// User to run `command` as. Only honored when shift runs as root.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user: Option<String>,
+	/// Monitor the session is assigned to once it reports ready.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub monitor_id: Option<String>,
+}
+
+impl SessionCreatePayload {
+	/// A session the caller launches itself.
+	pub fn new(role: SessionRole, display_name: Option<String>) -> Self {
+		Self {
+			role,
+			display_name,
+			command: None,
+			env: BTreeMap::new(),
+			user: None,
+			monitor_id: None,
+		}
+	}
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionCreatedPayload {
 	pub session: SessionInfo,
 	pub token: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionReadyPayload {
 	pub session_id: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionStatePayload {
 	pub session: SessionInfo,
+	/// State epoch after the change, see [`StateSyncPayload`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub epoch: Option<u64>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionActivePayload {
 	pub session_id: String,
+	/// State epoch after the change, see [`StateSyncPayload`].
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub epoch: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionInactivePayload {
+	pub session_id: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionAwakePayload {
 	pub session_id: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct SessionSleepPayload {
 	pub session_id: String,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct ErrorPayload {
-	pub code: String,
-	pub message: Option<String>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionVisibilityPayload {
+	pub visible: bool,
 }
 
-pub use message_header::MessageHeader;
-pub mod message_header;
+/// Rectangle in monitor pixel coordinates, origin at the top-left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Rect {
+	pub x: i32,
+	pub y: i32,
+	pub width: i32,
+	pub height: i32,
+}
 
-mod error;
-pub use error::*;
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionPipPayload {
+	pub session_id: String,
+	pub monitor_id: String,
+	/// Where to draw the session on the monitor. `None` removes the overlay.
+	pub rect: Option<Rect>,
+}
 
-pub use crate::message_frame::{TabMessageFrame, TabMessageFrameReader};
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorHdrPayload {
+	pub monitor_id: String,
+	/// Static metadata to send to the display. `None` turns HDR signalling off.
+	pub metadata: Option<HdrMetadata>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum HdrEotf {
+	/// SMPTE ST 2084 (PQ).
+	Pq,
+	Hlg,
+}
+
+/// CTA-861.3 static metadata (type 1). Chromaticities are CIE 1931 `[x, y]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct HdrMetadata {
+	pub eotf: HdrEotf,
+	pub red: [f32; 2],
+	pub green: [f32; 2],
+	pub blue: [f32; 2],
+	pub white_point: [f32; 2],
+	/// Mastering display luminance, in nits.
+	pub max_luminance: u32,
+	pub min_luminance: f32,
+	/// Maximum content and frame-average light levels, in nits.
+	pub max_cll: u32,
+	pub max_fall: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorMirrorPayload {
+	/// Monitor whose output is copied. `None` makes the target show its own output again.
+	pub source_monitor: Option<String>,
+	pub target_monitor: String,
+}
+
+/// Accessibility color filter of a monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum MonitorFilter {
+	#[default]
+	None,
+	Grayscale,
+	Invert,
+	/// Simulates deuteranopia (no green cones), to check what colorblind users see.
+	Deuteranopia,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorFilterPayload {
+	pub monitor_id: String,
+	pub filter: MonitorFilter,
+}
+
+/// Largest `zoom_set` factor.
+pub const MAX_ZOOM: f32 = 16.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ZoomSetPayload {
+	pub monitor_id: String,
+	/// Magnification, from 1 (zoomed out) to [`MAX_ZOOM`].
+	pub factor: f32,
+	/// Monitor pixel that stays in place, `None` for the middle of the monitor.
+	#[serde(default)]
+	pub center: Option<[f32; 2]>,
+	/// Moves the center with the pointer while it is on the monitor.
+	#[serde(default, skip_serializing_if = "std::ops::Not::not")]
+	pub follow_pointer: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LayoutRegion {
+	pub session_id: String,
+	pub rect: Rect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorLayoutPayload {
+	pub monitor_id: String,
+	/// Regions drawn in order. An empty list restores the single active session layout.
+	pub regions: Vec<LayoutRegion>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionAssignMonitorPayload {
+	/// Session pinned to the monitor. `None` makes the monitor follow the active session again.
+	pub session_id: Option<String>,
+	pub monitor_id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FocusTarget {
+	Pointer,
+	Keyboard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct FocusPayload {
+	pub session_id: String,
+	pub target: FocusTarget,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PointerLockPayload {
+	pub enable: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct PointerLockStatePayload {
+	pub locked: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutModifier {
+	Ctrl,
+	Alt,
+	Shift,
+	Super,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ShortcutRegisterPayload {
+	pub id: String,
+	#[serde(default)]
+	pub modifiers: Vec<ShortcutModifier>,
+	/// evdev key code.
+	pub key: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ShortcutUnregisterPayload {
+	pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ShortcutTriggeredPayload {
+	pub id: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DebugHudPayload {
+	pub enabled: bool,
+}
+
+/// What shift draws beneath sessions. Exactly one field must be set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BackgroundSetPayload {
+	/// Solid color, `#rrggbb`.
+	#[serde(default)]
+	pub color: Option<String>,
+	/// Absolute path of an image shift can read, scaled to cover each monitor.
+	#[serde(default)]
+	pub image: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LogLevelPayload {
+	/// Log target (module path) to change. `None` changes the default level.
+	#[serde(default)]
+	pub target: Option<String>,
+	/// One of `off`, `error`, `warn`, `info`, `debug`, `trace`.
+	pub level: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LogDumpPayload {
+	/// Only return the most recent `limit` records.
+	#[serde(default)]
+	pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LogRecordsPayload {
+	/// JSON-encoded log records, oldest first.
+	pub records: Vec<String>,
+	/// More `log_records` frames follow for the same dump.
+	#[serde(default)]
+	pub more: bool,
+}
+
+impl LogRecordsPayload {
+	/// Splits a dump into `log_records` frames of at most [`MAX_FRAME_BYTES`], counting records
+	/// as they end up escaped in the payload. Records too large for a frame of their own are left
+	/// out.
+	pub fn split(records: Vec<String>) -> Vec<Self> {
+		let empty = Self {
+			records: Vec::new(),
+			more: true,
+		};
+		let budget = MAX_FRAME_BYTES - message_header::LOG_RECORDS.len() - 2 - json_len(&empty);
+		let mut parts = vec![empty.clone()];
+		let mut bytes = 0;
+		for record in records {
+			// Quoted and escaped, with the comma before it.
+			let len = json_len(&record) + 1;
+			if len > budget {
+				continue;
+			}
+			if bytes + len > budget {
+				parts.push(empty.clone());
+				bytes = 0;
+			}
+			bytes += len;
+			parts.last_mut().unwrap().records.push(record);
+		}
+		if let Some(last) = parts.last_mut() {
+			last.more = false;
+		}
+		parts
+	}
+}
+
+/// Logs the traffic of a session's connections, for clients that misbehave where no debugger
+/// can be attached.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct WireTapPayload {
+	pub session_id: String,
+	pub enabled: bool,
+	/// Also record payloads, with `token` fields redacted, instead of only their sizes.
+	#[serde(default)]
+	pub payloads: bool,
+	/// Write to a capture file per connection instead of shift's log.
+	#[serde(default)]
+	pub capture: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionsPayload {
+	pub sessions: Vec<SessionInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TransitionsPayload {
+	/// Sorted by name.
+	pub transitions: Vec<TransitionInfo>,
+}
+
+/// Server counters. The `*_per_sec` fields cover the last full second.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StatsPayload {
+	pub active_session: Option<String>,
+	pub connected_clients: u32,
+	pub sessions: u32,
+	pub pending_sessions: u32,
+	pub monitors: u32,
+	pub pending_buffer_requests: u32,
+	pub waiting_flip: u32,
+	pub swap_buffers_per_sec: u64,
+	pub frame_done_per_sec: u64,
+	pub frames_skipped_per_sec: u64,
+	/// Queues between shift's layers, empty from older servers.
+	#[serde(default)]
+	pub queues: Vec<QueueStats>,
+}
+
+/// One of shift's internal queues, see `stats`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct QueueStats {
+	pub name: String,
+	/// Messages waiting. For per-client queues, the fullest client's.
+	pub depth: u32,
+	pub capacity: u32,
+	/// Messages dropped to make room.
+	pub dropped: u64,
+	/// Sends that had to wait for room.
+	pub blocked: u64,
+}
+
+/// Everything an admin tracks, for rebuilding it after missing events. `epoch` goes up with every
+/// change to it; events that change it carry the epoch they brought shift to, so a gap means a
+/// change the client wasn't told about.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct StateSyncPayload {
+	pub epoch: u64,
+	pub monitors: Vec<MonitorInfo>,
+	/// As in `sessions`, sorted by id.
+	pub sessions: Vec<SessionInfo>,
+	/// Session on screen, as in `session_active`.
+	pub active_session: Option<String>,
+	/// Monitors split between sessions with `monitor_layout`.
+	pub layouts: Vec<MonitorLayoutPayload>,
+	/// Monitors pinned to a session with `session_assign_monitor`.
+	pub assignments: Vec<SessionAssignMonitorPayload>,
+	pub capabilities: ServerCapabilities,
+	/// More `state_sync` frames of the same `epoch` follow, with the rest of the lists.
+	#[serde(default)]
+	pub more: bool,
+}
+
+impl StateSyncPayload {
+	/// Splits the snapshot into `state_sync` frames of at most [`MAX_FRAME_BYTES`]. Every part
+	/// repeats `epoch`, `active_session` and `capabilities`; the lists are spread over them in
+	/// order. An entry too large for a frame of its own still gets one.
+	pub fn split(self) -> Vec<Self> {
+		let Self {
+			epoch,
+			monitors,
+			sessions,
+			active_session,
+			layouts,
+			assignments,
+			capabilities,
+			more: _,
+		} = self;
+		let empty = Self {
+			epoch,
+			monitors: Vec::new(),
+			sessions: Vec::new(),
+			active_session,
+			layouts: Vec::new(),
+			assignments: Vec::new(),
+			capabilities,
+			more: true,
+		};
+		let mut parts = StateSyncParts::new(empty);
+		for monitor in monitors {
+			parts.push(monitor, |part| &mut part.monitors);
+		}
+		for session in sessions {
+			parts.push(session, |part| &mut part.sessions);
+		}
+		for layout in layouts {
+			parts.push(layout, |part| &mut part.layouts);
+		}
+		for assignment in assignments {
+			parts.push(assignment, |part| &mut part.assignments);
+		}
+		parts.finish()
+	}
+
+	/// Appends the next part of a snapshot sent with [`Self::split`].
+	pub fn extend(&mut self, part: Self) {
+		self.monitors.extend(part.monitors);
+		self.sessions.extend(part.sessions);
+		self.layouts.extend(part.layouts);
+		self.assignments.extend(part.assignments);
+		self.more = part.more;
+	}
+}
+
+/// Fills `state_sync` parts up to the frame limit, see [`StateSyncPayload::split`].
+struct StateSyncParts {
+	empty: StateSyncPayload,
+	empty_bytes: usize,
+	parts: Vec<StateSyncPayload>,
+	/// Payload bytes of the last part.
+	bytes: usize,
+}
+
+impl StateSyncParts {
+	const PAYLOAD_BYTES: usize = MAX_FRAME_BYTES - message_header::STATE_SYNC.len() - 2;
+
+	fn new(empty: StateSyncPayload) -> Self {
+		let empty_bytes = json_len(&empty);
+		Self {
+			parts: vec![empty.clone()],
+			empty,
+			empty_bytes,
+			bytes: empty_bytes,
+		}
+	}
+
+	fn push<T: Serialize>(&mut self, entry: T, list: fn(&mut StateSyncPayload) -> &mut Vec<T>) {
+		// The comma before it, counted for the first entry of a list too.
+		let len = json_len(&entry) + 1;
+		if self.bytes + len > Self::PAYLOAD_BYTES && self.bytes > self.empty_bytes {
+			self.parts.push(self.empty.clone());
+			self.bytes = self.empty_bytes;
+		}
+		self.bytes += len;
+		list(self.parts.last_mut().unwrap()).push(entry);
+	}
+
+	fn finish(mut self) -> Vec<StateSyncPayload> {
+		if let Some(last) = self.parts.last_mut() {
+			last.more = false;
+		}
+		self.parts
+	}
+}
+
+fn json_len(value: &impl Serialize) -> usize {
+	serde_json::to_vec(value).map_or(0, |json| json.len())
+}
+
+/// What this server supports, see `state_sync`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ServerCapabilities {
+	pub protocol: String,
+	/// Payload compression algorithms, as in `hello`.
+	pub compression: Vec<String>,
+	/// Whether admins may `input_inject`.
+	pub input_inject: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CompositorHealthSubscribePayload {
+	pub enabled: bool,
+}
+
+/// Renderer heartbeat, sent about once a second to subscribed admins.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CompositorHealthPayload {
+	/// Render loop iterations in the last second, whether or not anything was drawn.
+	pub render_loops_per_sec: u32,
+	/// Acquire fences the renderer is still waiting on.
+	pub fence_wait_backlog: u32,
+	pub monitors: Vec<MonitorHealth>,
+	#[serde(default)]
+	pub gpu_memory: GpuMemoryStats,
+}
+
+/// Estimated GPU memory held by imported session buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct GpuMemoryStats {
+	pub used_bytes: u64,
+	/// Above this, buffers of sessions that aren't on screen are evicted.
+	pub budget_bytes: u64,
+	/// Swapchains evicted since shift started.
+	pub evictions: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct MonitorHealth {
+	pub monitor_id: String,
+	/// Time since the monitor last page flipped, `None` if it never did.
+	pub last_flip_age_ms: Option<u64>,
+}
+
+/// A session on screen stopped submitting frames, or started again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionUnresponsivePayload {
+	pub session_id: String,
+	/// `false` once the session submitted a frame again.
+	pub unresponsive: bool,
+	/// How long the session went without a `buffer_request`: so far while unresponsive, in total
+	/// once it recovered.
+	pub idle_ms: u64,
+}
+
+/// A session's client disconnected, sessions having no way to quit cleanly.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SessionCrashedPayload {
+	pub session_id: String,
+	/// How long shift keeps showing the session's last frame, 0 if it doesn't.
+	pub grace_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ScreenshotPayload {
+	pub monitor_id: String,
+}
+
+/// Describes the pixels in the memfd sent along with `screenshot_data`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ScreenshotDataPayload {
+	pub monitor_id: String,
+	pub width: i32,
+	pub height: i32,
+	pub stride: i32,
+	/// DRM fourcc of the pixels, currently always `DRM_FORMAT_ABGR8888` (R, G, B, A bytes).
+	pub fourcc: i32,
+}
+
+impl ScreenshotDataPayload {
+	/// `DRM_FORMAT_ABGR8888`.
+	pub const FOURCC_ABGR8888: i32 = 0x3432_4241;
+}
+
+/// Describes the sealed memfd sent along with `selection_offer`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SelectionOfferPayload {
+	pub mime_type: String,
+}
+
+/// Describes the sealed memfd sent along with `selection_data`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SelectionDataPayload {
+	pub mime_type: String,
+	pub size: u64,
+	/// Session that offered the data.
+	pub source_session_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SelectionPolicyPayload {
+	pub session_id: String,
+	/// Sessions only exchange selections within the same group. `None` puts the session back in
+	/// the group every session starts in.
+	pub group: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BacklightInfo {
+	/// Name of the device in `/sys/class/backlight`.
+	pub name: String,
+	/// Monitor the backlight lights, if shift could tell.
+	pub monitor_id: Option<String>,
+	/// Brightness from 0 to 1.
+	pub level: f64,
+	/// Raw brightness, from 0 to `max_brightness`.
+	pub brightness: u32,
+	pub max_brightness: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BacklightsPayload {
+	pub backlights: Vec<BacklightInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct BacklightSetPayload {
+	pub monitor_id: String,
+	/// Brightness from 0 to 1.
+	pub level: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceCalibrationPayload {
+	/// `device` of the device's input events.
+	pub device: u32,
+	/// First two rows of a 3x3 matrix applied to coordinates normalized to 0..1, row by row, like
+	/// libinput's `LIBINPUT_CALIBRATION_MATRIX`.
+	pub matrix: [f64; 6],
+}
+
+impl DeviceCalibrationPayload {
+	/// Leaves coordinates as they are.
+	pub const IDENTITY: [f64; 6] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum AccelProfile {
+	/// Pointer motion is scaled by a constant factor.
+	Flat,
+	/// Faster motion moves the pointer further.
+	Adaptive,
+}
+
+/// libinput settings of an input device. Unset fields are left as they are by
+/// `device_configure`, and not supported by the device in `device_configs`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceSettings {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub accel_profile: Option<AccelProfile>,
+	/// From -1 (slowest) to 1 (fastest).
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub accel_speed: Option<f64>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub natural_scroll: Option<bool>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub tap_to_click: Option<bool>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub left_handed: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceConfigurePayload {
+	/// `device` of the device's input events.
+	pub device: u32,
+	#[serde(flatten)]
+	pub settings: DeviceSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceGrabPayload {
+	/// `device` of the device's input events.
+	pub device: u32,
+	/// Session all of the device's events go to, regardless of focus. `None` releases the grab.
+	#[serde(default)]
+	pub session_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceConfig {
+	pub device: u32,
+	pub name: String,
+	/// Profiles `accel_profile` can be set to.
+	#[serde(default)]
+	pub accel_profiles: Vec<AccelProfile>,
+	#[serde(flatten)]
+	pub settings: DeviceSettings,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct DeviceConfigsPayload {
+	pub devices: Vec<DeviceConfig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct InputInjectPayload {
+	/// Routed like the events of a real device. Only pointer, key and touch events can be
+	/// injected.
+	pub event: InputEventPayload,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct SwitchEventsSubscribePayload {
+	pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct LidClosedPayload {
+	pub closed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct TabletModePayload {
+	pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ServerShutdownPayload {
+	/// Why shift is going away, e.g. `crash`.
+	pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ErrorPayload {
+	pub code: String,
+	pub message: Option<String>,
+}
+
+/// How many FDs a frame with `header` may carry. Frames outside the range are rejected.
+fn allowed_fds(header: &str) -> RangeInclusive<usize> {
+	match header {
+		message_header::FRAMEBUFFER_LINK => 2..=2,
+		message_header::BUFFER_REQUEST | message_header::BUFFER_RELEASE => 0..=1,
+		message_header::BUFFER_RELEASES => 0..=MAX_COALESCED_RELEASES,
+		message_header::SCREENSHOT_DATA
+		| message_header::SELECTION_OFFER
+		| message_header::SELECTION_DATA => 1..=1,
+		// Unknown headers may come from a newer peer, their FDs are accepted and closed.
+		header if !message_header::is_known(header) => 0..=usize::MAX,
+		_ => 0..=0,
+	}
+}
+
+pub use message_header::MessageHeader;
+pub mod message_header;
+
+mod error;
+pub use error::*;
+
+pub use crate::message_frame::{SharedFrame, TabMessageFrame, TabMessageFrameReader};
+
+#[cfg(test)]
+mod tests {
+	use std::{
+		io::{ErrorKind, Read},
+		os::unix::net::UnixStream,
+		time::Duration,
+	};
+
+	use super::*;
+
+	/// A socket end to attach to frames, and a probe that sees EOF once every copy is closed.
+	fn tracked_fd() -> (OwnedFd, UnixStream) {
+		let (attached, probe) = UnixStream::pair().unwrap();
+		probe
+			.set_read_timeout(Some(Duration::from_millis(50)))
+			.unwrap();
+		(attached.into(), probe)
+	}
+
+	fn assert_closed(mut probe: UnixStream) {
+		match probe.read(&mut [0u8; 1]) {
+			Ok(0) => {}
+			Ok(_) => panic!("unexpected data on probe socket"),
+			Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+				panic!("attached fd was leaked")
+			}
+			Err(e) => panic!("probe read failed: {e}"),
+		}
+	}
+
+	fn frame(header: &str, payload: Option<&str>, fds: Vec<OwnedFd>) -> TabMessageFrame {
+		TabMessageFrame {
+			header: header.into(),
+			payload: payload.map(str::to_string),
+			fds,
+		}
+	}
+
+	#[test]
+	fn unknown_message_fds_are_closed() {
+		let (fd, probe) = tracked_fd();
+		let message = TabMessage::try_from(frame("from_the_future", None, vec![fd])).unwrap();
+		let TabMessage::Unknown(frame) = message else {
+			panic!("expected an unknown message");
+		};
+		assert!(frame.fds.is_empty());
+		assert_closed(probe);
+	}
+
+	#[test]
+	fn unexpected_fds_are_rejected_and_closed() {
+		let (fd, probe) = tracked_fd();
+		let result = TabMessage::try_from(frame(message_header::PING, None, vec![fd]));
+		assert!(matches!(
+			result,
+			Err(ProtocolError::ExpectedFds {
+				expected: 0,
+				found: 1
+			})
+		));
+		assert_closed(probe);
+	}
+
+	#[test]
+	fn too_many_fds_are_rejected_and_closed() {
+		let (fds, probes): (Vec<_>, Vec<_>) = (0..3).map(|_| tracked_fd()).unzip();
+		let result = TabMessage::try_from(frame(message_header::BUFFER_REQUEST, Some("mon_1 0"), fds));
+		assert!(matches!(result, Err(ProtocolError::ExpectedFds { .. })));
+		for probe in probes {
+			assert_closed(probe);
+		}
+	}
+
+	#[test]
+	fn fds_of_malformed_payloads_are_closed() {
+		let (first, first_probe) = tracked_fd();
+		let (second, second_probe) = tracked_fd();
+		let result = TabMessage::try_from(frame(
+			message_header::FRAMEBUFFER_LINK,
+			Some("not json"),
+			vec![first, second],
+		));
+		assert!(matches!(result, Err(ProtocolError::Json(_))));
+		assert_closed(first_probe);
+		assert_closed(second_probe);
+	}
+
+	#[test]
+	fn accepted_fds_stay_open_until_dropped() {
+		let (fd, mut probe) = tracked_fd();
+		let message = TabMessage::try_from(frame(
+			message_header::BUFFER_REQUEST,
+			Some("mon_1 1"),
+			vec![fd],
+		))
+		.unwrap();
+		let TabMessage::BufferRequest { acquire_fence, .. } = message else {
+			panic!("expected a buffer request");
+		};
+		assert!(acquire_fence.is_some());
+		let err = probe.read(&mut [0u8; 1]).unwrap_err();
+		assert!(matches!(
+			err.kind(),
+			ErrorKind::WouldBlock | ErrorKind::TimedOut
+		));
+		drop(acquire_fence);
+		assert_closed(probe);
+	}
+
+	#[test]
+	fn reader_closes_fds_it_still_holds() {
+		let (fd, probe) = tracked_fd();
+		let mut reader = TabMessageFrameReader::new();
+		// Half a frame: the FD waits in the reader for the rest of the bytes.
+		reader.feed_chunk(b"buffer_request\n", vec![fd]).unwrap();
+		drop(reader);
+		assert_closed(probe);
+	}
+
+	#[test]
+	fn shared_frames_arrive_as_separate_frames() {
+		use crate::transport::Transport;
+		let (tx, rx) = UnixStream::pair().unwrap();
+		let large = "x".repeat(compression::COMPRESSION_THRESHOLD);
+		let frames = [
+			SharedFrame::new(frame(message_header::PING, None, Vec::new())).unwrap(),
+			SharedFrame::new(frame(
+				message_header::MONITOR_REMOVED,
+				Some(&large),
+				Vec::new(),
+			))
+			.unwrap(),
+		];
+		assert!(frames[1].bytes(true).len() < frames[1].bytes(false).len());
+		let bytes = frames.iter().map(|f| f.bytes(true)).collect::<Vec<_>>();
+		assert_eq!(tx.send_encoded(&bytes).unwrap(), bytes.len());
+
+		let mut reader = TabMessageFrameReader::new();
+		assert_eq!(
+			reader.read_framed(&rx).unwrap().header.0,
+			message_header::PING
+		);
+		let removed = reader.read_framed(&rx).unwrap();
+		assert_eq!(removed.header.0, message_header::MONITOR_REMOVED);
+		assert_eq!(removed.payload.as_deref(), Some(large.as_str()));
+		assert!(SharedFrame::new(frame(message_header::PING, None, vec![tracked_fd().0])).is_err());
+	}
+
+	#[test]
+	#[cfg(feature = "compression")]
+	fn corrupt_compressed_payloads_are_not_reported_as_oversized() {
+		use base64::Engine;
+		let base64 = base64::engine::general_purpose::STANDARD;
+		let plain = "x".repeat(compression::COMPRESSION_THRESHOLD);
+		let compressed = compression::compress(&plain).unwrap();
+		assert_eq!(compression::decompress(&compressed).unwrap(), plain);
+
+		let mut truncated = base64.decode(&compressed).unwrap();
+		truncated.truncate(truncated.len() / 2);
+		assert!(matches!(
+			compression::decompress(&base64.encode(truncated)),
+			Err(ProtocolError::InvalidPayload(_))
+		));
+		let oversized = compression::compress(&"x".repeat(compression::MAX_PAYLOAD_BYTES + 1)).unwrap();
+		assert!(matches!(
+			compression::decompress(&oversized),
+			Err(ProtocolError::PayloadTooLarge { .. })
+		));
+	}
+
+	#[test]
+	fn frames_the_socket_has_no_room_for_are_finished_later() {
+		use crate::transport::{AnyTransport, StreamTransport, Transport, flush_blocking};
+		let (tx, rx) = UnixStream::pair().unwrap();
+		let tx = AnyTransport::Stream(StreamTransport::from_connected(tx.into()));
+		let rx = AnyTransport::Stream(StreamTransport::from_connected(rx.into()));
+		tx.set_nonblocking(true).unwrap();
+		let large = "x".repeat(4 * 1024 * 1024);
+
+		tx.send_frame(&frame(
+			message_header::MONITOR_REMOVED,
+			Some(&large),
+			Vec::new(),
+		))
+		.unwrap();
+		assert!(matches!(tx.flush(), Err(ProtocolError::WouldBlock)));
+		assert!(matches!(
+			tx.send_frame(&frame(message_header::PING, None, Vec::new())),
+			Err(ProtocolError::WouldBlock)
+		));
+		let receiver = std::thread::spawn(move || {
+			let mut reader = TabMessageFrameReader::new();
+			reader.read_framed(&rx).unwrap()
+		});
+		flush_blocking(&tx).unwrap();
+		let removed = receiver.join().unwrap();
+		assert_eq!(removed.header.0, message_header::MONITOR_REMOVED);
+		assert_eq!(removed.payload.as_deref(), Some(large.as_str()));
+	}
+
+	#[test]
+	fn uploads_bigger_than_a_frame_are_split_into_rows() {
+		use crate::transport::Transport;
+		use base64::Engine;
+		let base64 = base64::engine::general_purpose::STANDARD;
+		let (tx, rx) = UnixStream::pair().unwrap();
+		let tx = transport::StreamTransport::from_connected(tx.into());
+		let rx = transport::StreamTransport::from_connected(rx.into());
+		let (width, height) = (2560, 1440);
+		let stride = width * 4 + 64;
+		let pixels = (0..stride as usize * height as usize)
+			.map(|i| i as u8)
+			.collect::<Vec<_>>();
+		assert!(base64.encode(&pixels).len() > compression::MAX_PAYLOAD_BYTES);
+
+		let uploads =
+			BufferUploadPayload::split("mon_1", BufferIndex::One, width, height, stride, &pixels);
+		assert!(uploads.len() > 1);
+		let sender = std::thread::spawn(move || {
+			for upload in uploads {
+				tx.send_frame(&TabMessageFrame::json(
+					message_header::BUFFER_UPLOAD,
+					upload,
+				))
+				.unwrap();
+			}
+		});
+		let mut reader = TabMessageFrameReader::new();
+		let mut received = Vec::new();
+		while received.len() < pixels.len() {
+			let frame = reader.read_framed(&rx).unwrap();
+			let Ok(TabMessage::BufferUpload(upload)) = TabMessage::try_from(frame) else {
+				panic!("expected buffer_upload");
+			};
+			assert_eq!(
+				(upload.width, upload.height, upload.stride),
+				(width, height, stride)
+			);
+			assert_eq!(upload.y as usize * stride as usize, received.len());
+			received.extend(base64.decode(upload.data).unwrap());
+		}
+		sender.join().unwrap();
+		assert!(received == pixels);
+	}
+
+	#[test]
+	fn buffer_messages_carry_an_optional_generation() {
+		assert_eq!(buffer_args("mon_1", BufferIndex::One, 0), "mon_1 1");
+		let payload = buffer_args("mon_1", BufferIndex::One, 7);
+		let message = TabMessage::try_from(frame(
+			message_header::BUFFER_RELEASE,
+			Some(&payload),
+			vec![],
+		))
+		.unwrap();
+		let TabMessage::BufferRelease { payload, .. } = message else {
+			panic!("expected a buffer release");
+		};
+		assert_eq!((payload.buffer, payload.generation), (BufferIndex::One, 7));
+		let legacy = TabMessage::try_from(frame(
+			message_header::BUFFER_REQUEST_ACK,
+			Some("mon_1 0"),
+			vec![],
+		));
+		assert!(matches!(
+			legacy,
+			Ok(TabMessage::BufferRequestAck(BufferRequestAckPayload {
+				generation: 0,
+				..
+			}))
+		));
+		let extra = TabMessage::try_from(frame(
+			message_header::BUFFER_REQUEST,
+			Some("mon_1 0 1 2"),
+			vec![],
+		));
+		assert!(matches!(extra, Err(ProtocolError::InvalidPayload(_))));
+	}
+
+	#[test]
+	fn buffer_requests_name_their_present_group() {
+		let payload = buffer_request_args("mon_1", BufferIndex::One, 0, Some(4));
+		assert_eq!(payload, "mon_1 1 group=4");
+		let message = TabMessage::try_from(frame(
+			message_header::BUFFER_REQUEST,
+			Some(&payload),
+			vec![],
+		))
+		.unwrap();
+		let TabMessage::BufferRequest { payload, .. } = message else {
+			panic!("expected a buffer request");
+		};
+		assert_eq!(
+			(payload.buffer, payload.generation, payload.present_group),
+			(BufferIndex::One, 0, Some(4))
+		);
+		for invalid in [
+			(message_header::BUFFER_REQUEST, "mon_1 1 3 group=x"),
+			(message_header::BUFFER_REQUEST, "mon_1 group=4"),
+			(message_header::BUFFER_RELEASE, "mon_1 1 group=4"),
+		] {
+			let message = TabMessage::try_from(frame(invalid.0, Some(invalid.1), vec![]));
+			assert!(matches!(message, Err(ProtocolError::InvalidPayload(_))));
+		}
+	}
+
+	#[test]
+	fn coalesced_releases_pair_fences_with_their_buffers() {
+		let release = |monitor_id: &str| BufferReleasePayload {
+			monitor_id: monitor_id.into(),
+			buffer: BufferIndex::Zero,
+			generation: 1,
+		};
+		let payload = BufferReleasesPayload {
+			releases: vec![release("mon_1"), release("mon_2")],
+			fences: vec![1],
+		};
+		let json = serde_json::to_string(&payload).unwrap();
+		let (fd, _probe) = tracked_fd();
+		let message = TabMessage::try_from(frame(
+			message_header::BUFFER_RELEASES,
+			Some(&json),
+			vec![fd],
+		))
+		.unwrap();
+		let TabMessage::BufferReleases {
+			payload,
+			release_fences,
+		} = message
+		else {
+			panic!("expected coalesced releases");
+		};
+		let fenced = payload
+			.into_releases(release_fences)
+			.map(|(release, fence)| (release.monitor_id, fence.is_some()))
+			.collect::<Vec<_>>();
+		assert_eq!(
+			fenced,
+			[("mon_1".to_string(), false), ("mon_2".to_string(), true)]
+		);
+
+		let missing = TabMessage::try_from(frame(message_header::BUFFER_RELEASES, Some(&json), vec![]));
+		assert!(matches!(missing, Err(ProtocolError::ExpectedFds { .. })));
+		let out_of_range = serde_json::to_string(&BufferReleasesPayload {
+			releases: vec![release("mon_1")],
+			fences: vec![3],
+		})
+		.unwrap();
+		let (fd, _probe) = tracked_fd();
+		let message = TabMessage::try_from(frame(
+			message_header::BUFFER_RELEASES,
+			Some(&out_of_range),
+			vec![fd],
+		));
+		assert!(matches!(message, Err(ProtocolError::InvalidPayload(_))));
+	}
+
+	#[test]
+	fn coalesced_releases_with_more_fences_than_a_frame_holds_are_split() {
+		use crate::transport::Transport;
+		use nix::sys::socket::{AddressFamily, SockFlag, SockType, socketpair};
+		let (tx, rx) = socketpair(
+			AddressFamily::Unix,
+			SockType::SeqPacket,
+			None,
+			SockFlag::empty(),
+		)
+		.unwrap();
+		let (tx, rx) = (UnixStream::from(tx), UnixStream::from(rx));
+		let mut probes = Vec::new();
+		let releases = (0..MAX_FDS_PER_FRAME * 2 + 1).map(|monitor| {
+			let (fence, probe) = tracked_fd();
+			probes.push(probe);
+			let release = BufferReleasePayload {
+				monitor_id: format!("mon_{monitor}"),
+				buffer: BufferIndex::One,
+				generation: 1,
+			};
+			(release, Some(fence))
+		});
+		let frames = BufferReleasesPayload::frames(releases.collect::<Vec<_>>());
+		assert_eq!(frames.len(), 3);
+		for frame in &frames {
+			tx.send_frame(frame).unwrap();
+		}
+		drop(frames);
+
+		let mut reader = TabMessageFrameReader::new();
+		let mut received = Vec::new();
+		while received.len() < probes.len() {
+			let frame = reader.read_framed(&rx).unwrap();
+			let Ok(TabMessage::BufferReleases {
+				payload,
+				release_fences,
+			}) = TabMessage::try_from(frame)
+			else {
+				panic!("expected coalesced releases");
+			};
+			received.extend(payload.into_releases(release_fences));
+		}
+		assert!(
+			received
+				.iter()
+				.enumerate()
+				.all(
+					|(monitor, (release, fence))| release.monitor_id == format!("mon_{monitor}")
+						&& fence.is_some()
+				)
+		);
+		drop(received);
+		probes.into_iter().for_each(assert_closed);
+	}
+
+	#[test]
+	fn state_sync_snapshots_are_split_into_frames_that_fit() {
+		let monitors = (0..4)
+			.map(|i| MonitorInfo {
+				id: format!("mon_{i:032}"),
+				width: 3840,
+				height: 2160,
+				refresh_rate: 144,
+				name: format!("DP-{i}"),
+				manufacturer: Some("Very Long Display Manufacturer Name Inc.".into()),
+				model: Some("UltraWide Professional Reference Monitor 4K HDR".into()),
+				serial: Some(format!("SN-{i:024}")),
+				width_mm: Some(600),
+				height_mm: Some(340),
+				non_desktop: false,
+				panel_orientation: Some(PanelOrientation::Normal),
+			})
+			.collect::<Vec<_>>();
+		let sessions = (0..16)
+			.map(|i| SessionInfo {
+				id: format!("ses_{i:032}"),
+				role: SessionRole::Session,
+				display_name: Some(format!("Session number {i} with a descriptive name")),
+				state: SessionLifecycle::Occupied,
+				attached: None,
+				visible: None,
+			})
+			.collect::<Vec<_>>();
+		let layouts = monitors
+			.iter()
+			.map(|monitor| MonitorLayoutPayload {
+				monitor_id: monitor.id.clone(),
+				regions: sessions[..4]
+					.iter()
+					.map(|session| LayoutRegion {
+						session_id: session.id.clone(),
+						rect: Rect {
+							x: 0,
+							y: 0,
+							width: 1920,
+							height: 1080,
+						},
+					})
+					.collect(),
+			})
+			.collect::<Vec<_>>();
+		let assignments = monitors
+			.iter()
+			.zip(&sessions)
+			.map(|(monitor, session)| SessionAssignMonitorPayload {
+				session_id: Some(session.id.clone()),
+				monitor_id: monitor.id.clone(),
+			})
+			.collect::<Vec<_>>();
+		let state = StateSyncPayload {
+			epoch: 7,
+			monitors,
+			sessions,
+			active_session: Some("ses_1".into()),
+			layouts,
+			assignments,
+			capabilities: ServerCapabilities {
+				protocol: PROTOCOL_VERSION.into(),
+				compression: vec!["zstd".into()],
+				input_inject: true,
+			},
+			more: false,
+		};
+		let whole = TabMessageFrame::json(message_header::STATE_SYNC, &state);
+		assert!(whole.encoded().len() > MAX_FRAME_BYTES);
+
+		let parts = state.clone().split();
+		assert!(parts.len() > 1);
+		let mut received = parts.into_iter().map(|part| {
+			let frame = TabMessageFrame::json(message_header::STATE_SYNC, &part);
+			assert!(frame.encoded().len() <= MAX_FRAME_BYTES);
+			let Ok(TabMessage::StateSync(part)) = TabMessage::try_from(frame) else {
+				panic!("expected state_sync");
+			};
+			assert_eq!(part.epoch, 7);
+			part
+		});
+		let mut merged = received.next().unwrap();
+		received.for_each(|part| merged.extend(part));
+		assert_eq!(merged, state);
+	}
+
+	#[test]
+	fn log_records_are_split_by_their_escaped_size() {
+		let record = serde_json::json!({
+			"timestamp": "2026-01-01T00:00:00Z",
+			"level": "INFO",
+			"target": "shift::server_layer",
+			"fields": { "message": "a \"quoted\" path C:\\x ".repeat(8) },
+		})
+		.to_string();
+		let records = vec![record; 64];
+		let too_large = "\"".repeat(MAX_FRAME_BYTES);
+		let mut dump = records.clone();
+		dump.insert(3, too_large);
+
+		let parts = LogRecordsPayload::split(dump);
+		assert!(parts.len() > 1);
+		let mut received = Vec::new();
+		for (i, part) in parts.iter().enumerate() {
+			assert_eq!(part.more, i + 1 < parts.len());
+			let frame = TabMessageFrame::json(message_header::LOG_RECORDS, part);
+			assert!(frame.encoded().len() <= MAX_FRAME_BYTES);
+			received.extend(part.records.iter().cloned());
+		}
+		assert_eq!(received, records);
+	}
+
+	#[test]
+	fn switch_durations_are_milliseconds_on_the_wire() {
+		let payload = SessionSwitchPayload::new("ses_1", None, Duration::from_millis(250));
+		let json = serde_json::to_value(&payload).unwrap();
+		assert_eq!(json["duration_ms"], 250);
+		assert!(json.get("duration").is_none());
+
+		let legacy: SessionSwitchPayload = serde_json::from_str(
+			r#"{"session_id":"ses_1","animation":null,"duration":{"secs":1,"nanos":500000000}}"#,
+		)
+		.unwrap();
+		assert_eq!(legacy.duration, Duration::from_millis(1500));
+	}
+
+	#[test]
+	fn transition_params_are_checked_against_the_schema() {
+		let blur = TransitionInfo {
+			name: "blur".into(),
+			params: vec![TransitionParam {
+				name: "radius".into(),
+				description: String::new(),
+				default: 60.0,
+				min: 0.0,
+				max: 200.0,
+			}],
+		};
+		assert!(blur.validate(&BTreeMap::new()).is_ok());
+		assert!(
+			blur
+				.validate(&BTreeMap::from([("radius".into(), 200.0)]))
+				.is_ok()
+		);
+		assert!(
+			blur
+				.validate(&BTreeMap::from([("radius".into(), -1.0)]))
+				.is_err()
+		);
+		assert!(
+			blur
+				.validate(&BTreeMap::from([("sigma".into(), 1.0)]))
+				.is_err()
+		);
+	}
+
+	#[cfg(feature = "schema")]
+	#[test]
+	fn schema_describes_every_message() {
+		let schema = schema::protocol_schema();
+		let messages = schema["messages"].as_object().unwrap();
+		assert_eq!(messages.len(), message_header::ALL.len());
+		assert_eq!(messages["ping"]["payload"], "none");
+		assert_eq!(messages["buffer_release"]["payload"], "text");
+		assert_eq!(messages["buffer_release"]["fds"]["max"], 1);
+		assert_eq!(
+			messages["session_switch"]["schema"]["$ref"],
+			"#/$defs/SessionSwitchPayload"
+		);
+		let switch = &schema["$defs"]["SessionSwitchPayload"];
+		assert_eq!(switch["properties"]["duration_ms"]["type"], "integer");
+		assert!(schema["$defs"]["InputEventPayload"].is_object());
+	}
+}
@@ -3,24 +3,45 @@ use nix::sys::socket::{ControlMessage, ControlMessageOwned, MsgFlags, recvmsg, s
 use serde::Serialize;
 use std::collections::VecDeque;
 use std::io::{ErrorKind, IoSlice, IoSliceMut};
-use std::os::fd::{AsRawFd, RawFd};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::Arc;
 
-use crate::{HelloPayload, MessageHeader, PROTOCOL_VERSION, ProtocolError};
+use crate::compression::{self, COMPRESSION_THRESHOLD, MAX_PAYLOAD_BYTES, ZSTD_FLAG};
+use crate::transport::Transport;
+use crate::{
+	HelloPayload, MAX_FDS_PER_FRAME, MAX_FRAME_BYTES, MessageHeader, PROTOCOL_VERSION, ProtocolError,
+};
 
 /// Raw framed Tab message: header line + payload line (strings) plus optional FDs.
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// The frame owns its FDs; they are closed when it is dropped, including after sending.
+#[derive(Debug)]
 pub struct TabMessageFrame {
 	pub header: MessageHeader,
 	pub payload: Option<String>,
-	pub fds: Vec<RawFd>,
+	pub fds: Vec<OwnedFd>,
 }
 fn would_block_err() -> std::io::Error {
 	std::io::Error::new(ErrorKind::WouldBlock, ProtocolError::WouldBlock)
 }
+/// Waits for the socket to take the rest of frames sent only in part, without blocking the thread.
+#[cfg(feature = "async")]
+async fn flush_async_fd<T: Transport>(
+	fd: &tokio::io::unix::AsyncFd<T>,
+) -> Result<(), ProtocolError> {
+	loop {
+		let mut guard = fd.writable().await?;
+		if let Ok(result) = guard.try_io(|_| match fd.get_ref().flush() {
+			Err(ProtocolError::WouldBlock) => Err(would_block_err()),
+			def => Ok(def),
+		}) {
+			return result?;
+		}
+	}
+}
 #[derive(Default)]
 pub struct TabMessageFrameReader {
 	pending_bytes: Vec<u8>,
-	pending_fds: Vec<RawFd>,
+	pending_fds: Vec<OwnedFd>,
 	ready_frames: VecDeque<TabMessageFrame>,
 }
 impl TabMessageFrameReader {
@@ -34,7 +55,11 @@ impl TabMessageFrameReader {
 		self.pop_ready()
 	}
 	#[tracing::instrument(skip_all)]
-	fn feed_chunk(&mut self, bytes: &[u8], mut fds: Vec<RawFd>) -> Result<(), ProtocolError> {
+	pub(crate) fn feed_chunk(
+		&mut self,
+		bytes: &[u8],
+		mut fds: Vec<OwnedFd>,
+	) -> Result<(), ProtocolError> {
 		if !bytes.is_empty() {
 			self.pending_bytes.extend_from_slice(bytes);
 		}
@@ -46,35 +71,50 @@ impl TabMessageFrameReader {
 	}
 	#[tracing::instrument(skip_all)]
 	fn process_pending(&mut self) -> Result<(), ProtocolError> {
+		let result = self.split_pending_frames();
+		if result.is_err() {
+			// The stream is unusable after a framing error, nothing will claim these.
+			self.pending_fds.clear();
+		}
+		result
+	}
+	fn split_pending_frames(&mut self) -> Result<(), ProtocolError> {
 		loop {
 			if self.pending_bytes.is_empty() {
 				break;
 			}
-			let fds_for_frame = self.pending_fds.clone();
-			match TabMessageFrame::parse_from_bytes(&self.pending_bytes, fds_for_frame)? {
-				Some((frame, used)) => {
+			match TabMessageFrame::parse_from_bytes(&self.pending_bytes)? {
+				Some((mut frame, used)) => {
 					self.pending_bytes.drain(..used);
-					self.pending_fds.clear();
+					frame.fds = std::mem::take(&mut self.pending_fds);
 					self.ready_frames.push_back(frame);
 				}
+				None if self.pending_bytes.len() > MAX_PAYLOAD_BYTES => {
+					return Err(ProtocolError::PayloadTooLarge {
+						limit: MAX_PAYLOAD_BYTES,
+					});
+				}
 				None => break,
 			}
 		}
 		Ok(())
 	}
 	#[tracing::instrument(skip_all)]
-	pub fn read_framed(&mut self, stream: &impl AsRawFd) -> Result<TabMessageFrame, ProtocolError> {
+	pub fn read_framed(
+		&mut self,
+		stream: &(impl Transport + ?Sized),
+	) -> Result<TabMessageFrame, ProtocolError> {
 		loop {
 			if let Some(frame) = self.pop_ready() {
 				return Ok(frame);
 			}
-			let (bytes, fds) = recv_into_vec(stream)?;
+			let (bytes, fds) = stream.recv_chunk()?;
 			self.feed_chunk(&bytes, fds)?;
 		}
 	}
 	#[cfg(feature = "async")]
 	#[tracing::instrument(skip_all)]
-	pub async fn read_frame_from_async_fd<T: AsRawFd>(
+	pub async fn read_frame_from_async_fd<T: Transport>(
 		&mut self,
 		fd: &tokio::io::unix::AsyncFd<T>,
 	) -> Result<TabMessageFrame, ProtocolError> {
@@ -93,13 +133,13 @@ impl TabMessageFrameReader {
 	}
 }
 #[tracing::instrument(skip_all)]
-fn recv_into_vec(stream: &impl AsRawFd) -> Result<(Vec<u8>, Vec<RawFd>), ProtocolError> {
-	let mut buf = [0u8; 4096];
-	let mut cmsg_space = nix::cmsg_space!([RawFd; 8]);
+pub(crate) fn recv_into_vec(stream: &impl AsFd) -> Result<(Vec<u8>, Vec<OwnedFd>), ProtocolError> {
+	let mut buf = [0u8; MAX_FRAME_BYTES];
+	let mut cmsg_space = nix::cmsg_space!([RawFd; MAX_FDS_PER_FRAME]);
 	let mut iov = [IoSliceMut::new(&mut buf)];
 	let msg = loop {
 		match recvmsg::<()>(
-			stream.as_raw_fd(),
+			stream.as_fd().as_raw_fd(),
 			&mut iov,
 			Some(&mut cmsg_space),
 			MsgFlags::empty(),
@@ -112,19 +152,28 @@ fn recv_into_vec(stream: &impl AsRawFd) -> Result<(Vec<u8>, Vec<RawFd>), Protoco
 			Ok(msg) => break Ok(msg),
 		}
 	}?;
-	if msg.bytes == 0 {
-		return Err(ProtocolError::UnexpectedEof);
-	}
-	if msg.flags.contains(MsgFlags::MSG_TRUNC) {
-		return Err(ProtocolError::Truncated);
-	}
 	let mut fds = Vec::new();
 	let mut c_iter = msg.cmsgs()?;
 	while let Some(cmsg) = c_iter.next() {
 		if let ControlMessageOwned::ScmRights(rights) = cmsg {
-			fds.extend(rights);
+			// SAFETY: SCM_RIGHTS installed these FDs in this process just now, nothing else has them.
+			fds.extend(
+				rights
+					.into_iter()
+					.map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+			);
 		}
 	}
+	if msg.bytes == 0 {
+		return Err(ProtocolError::UnexpectedEof);
+	}
+	// MSG_CTRUNC: more FDs were sent than fit in the control buffer, the kernel dropped the rest.
+	if msg
+		.flags
+		.intersects(MsgFlags::MSG_TRUNC | MsgFlags::MSG_CTRUNC)
+	{
+		return Err(ProtocolError::Truncated);
+	}
 	let bytes = msg.bytes;
 	let _ = msg;
 	let data = iov[0][..bytes].to_vec();
@@ -132,7 +181,7 @@ fn recv_into_vec(stream: &impl AsRawFd) -> Result<(Vec<u8>, Vec<RawFd>), Protoco
 }
 impl TabMessageFrame {
 	/// Write a framed TabMessageFrame to the provided stream using sendmsg/SCM_RIGHTS.
-	pub fn encode_and_send(&self, stream: &impl AsRawFd) -> Result<(), ProtocolError> {
+	pub fn encode_and_send(&self, stream: &impl AsFd) -> Result<(), ProtocolError> {
 		let (encoded_header, encoded_payload) = self.serialize();
 		let encoded_header = format!("{encoded_header}\n");
 		let encoded_payload = format!("{encoded_payload}\n");
@@ -140,12 +189,23 @@ impl TabMessageFrame {
 			IoSlice::new(encoded_header.as_bytes()),
 			IoSlice::new(encoded_payload.as_bytes()),
 		];
-		let cmsg = if self.fds.is_empty() {
+		let fds = self
+			.fds
+			.iter()
+			.map(|fd| fd.as_raw_fd())
+			.collect::<Vec<RawFd>>();
+		let cmsg = if fds.is_empty() {
 			vec![]
 		} else {
-			vec![ControlMessage::ScmRights(&self.fds)]
+			vec![ControlMessage::ScmRights(&fds)]
 		};
-		sendmsg::<()>(stream.as_raw_fd(), &iov, &cmsg, MsgFlags::empty(), None)?;
+		sendmsg::<()>(
+			stream.as_fd().as_raw_fd(),
+			&iov,
+			&cmsg,
+			MsgFlags::empty(),
+			None,
+		)?;
 		Ok(())
 	}
 	pub fn serialize(&self) -> (String, String) {
@@ -159,24 +219,29 @@ impl TabMessageFrame {
 		(header_line.to_string(), payload_line.to_string())
 	}
 
+	/// The header and payload lines as they go on the wire, without the FDs.
+	pub(crate) fn encoded(&self) -> Vec<u8> {
+		let (header, payload) = self.serialize();
+		format!("{header}\n{payload}\n").into_bytes()
+	}
+
 	/// Sends a message asynchronously
 	#[cfg(feature = "async")]
-	pub async fn send_frame_to_async_fd<T: AsRawFd>(
+	pub async fn send_frame_to_async_fd<T: Transport>(
 		&self,
 		fd: &tokio::io::unix::AsyncFd<T>,
 	) -> Result<(), ProtocolError> {
-		let packet = loop {
+		loop {
 			let mut guard = fd.writable().await?;
-			if let Ok(result) = guard.try_io(|_| match self.encode_and_send(fd) {
+			if let Ok(result) = guard.try_io(|_| match fd.get_ref().send_frame(self) {
 				Err(ProtocolError::WouldBlock) => Err(would_block_err()),
 				def => Ok(def),
 			}) {
-				break result?;
-			} else {
-				continue;
+				result??;
+				break;
 			}
-		}?;
-		return Ok(packet);
+		}
+		flush_async_fd(fd).await
 	}
 
 	#[tracing::instrument(skip_all)]
@@ -219,11 +284,26 @@ impl TabMessageFrame {
 		let payload = HelloPayload {
 			server: server.into(),
 			protocol: PROTOCOL_VERSION.to_string(),
+			compression: compression::supported(),
 		};
 		let json = serde_json::to_value(payload).expect("HelloPayload is serializable");
 		Self::json("hello", json)
 	}
 
+	/// Compresses the payload when it is large enough to be worth it, flagging it in the header line.
+	/// Only call this on connections that negotiated compression.
+	pub fn compressed(mut self) -> Result<Self, ProtocolError> {
+		let Some(payload) = self.payload.as_deref() else {
+			return Ok(self);
+		};
+		if payload.len() < COMPRESSION_THRESHOLD || self.header.0.ends_with(ZSTD_FLAG) {
+			return Ok(self);
+		}
+		self.payload = Some(compression::compress(payload)?);
+		self.header = format!("{}{ZSTD_FLAG}", self.header.0).into();
+		Ok(self)
+	}
+
 	pub fn expect_n_fds(&self, amount: u32) -> Result<(), ProtocolError> {
 		let found = self.fds.len() as u32;
 		if found == amount {
@@ -236,11 +316,10 @@ impl TabMessageFrame {
 		}
 	}
 
-	#[tracing::instrument(skip_all, fields(frame_size = bytes.len(), fds = fds.len()))]
-	pub fn parse_from_bytes(
-		bytes: &[u8],
-		fds: Vec<RawFd>,
-	) -> Result<Option<(Self, usize)>, ProtocolError> {
+	/// Parses the first frame in `bytes`, if complete. The frame comes without FDs, the caller
+	/// attaches the ones received with it.
+	#[tracing::instrument(skip_all, fields(frame_size = bytes.len()))]
+	pub fn parse_from_bytes(bytes: &[u8]) -> Result<Option<(Self, usize)>, ProtocolError> {
 		let Some(first_nl) = bytes.iter().position(|b| *b == b'\n') else {
 			return Ok(None);
 		};
@@ -251,17 +330,17 @@ impl TabMessageFrame {
 		let header_bytes = &bytes[..first_nl];
 		let payload_bytes = &bytes[first_nl + 1..second_nl];
 		let consumed = second_nl + 1;
-		let frame = Self::from_lines(header_bytes, payload_bytes, fds)?;
+		let frame = Self::from_lines(header_bytes, payload_bytes)?;
 		Ok(Some((frame, consumed)))
 	}
 
-	fn from_lines(
-		header_bytes: &[u8],
-		payload_bytes: &[u8],
-		fds: Vec<RawFd>,
-	) -> Result<Self, ProtocolError> {
-		let header = String::from_utf8(header_bytes.to_vec())?;
-		let payload_str = String::from_utf8(payload_bytes.to_vec())?;
+	fn from_lines(header_bytes: &[u8], payload_bytes: &[u8]) -> Result<Self, ProtocolError> {
+		let mut header = String::from_utf8(header_bytes.to_vec())?;
+		let mut payload_str = String::from_utf8(payload_bytes.to_vec())?;
+		if let Some(plain) = header.strip_suffix(ZSTD_FLAG) {
+			header = plain.to_string();
+			payload_str = compression::decompress(&payload_str)?;
+		}
 		Ok(Self {
 			header: header.into(),
 			payload: if payload_str == "\0\0\0\0" {
@@ -269,7 +348,68 @@ impl TabMessageFrame {
 			} else {
 				Some(payload_str)
 			},
-			fds,
+			fds: Vec::new(),
 		})
 	}
 }
+
+/// A frame encoded once to go out on many connections, like the monitor broadcasts. Clones share
+/// the bytes.
+#[derive(Debug, Clone)]
+pub struct SharedFrame {
+	plain: Arc<[u8]>,
+	/// Only when the payload is large enough to be compressed.
+	compressed: Option<Arc<[u8]>>,
+}
+
+impl SharedFrame {
+	/// Encodes a frame, which can't carry FDs.
+	pub fn new(frame: TabMessageFrame) -> Result<Self, ProtocolError> {
+		if !frame.fds.is_empty() {
+			return Err(ProtocolError::FdPassingUnsupported);
+		}
+		let compressible = cfg!(feature = "compression")
+			&& frame
+				.payload
+				.as_ref()
+				.is_some_and(|payload| payload.len() >= COMPRESSION_THRESHOLD);
+		let plain = frame.encoded().into();
+		let compressed = match compressible {
+			true => Some(frame.compressed()?.encoded().into()),
+			false => None,
+		};
+		Ok(Self { plain, compressed })
+	}
+
+	/// The bytes to send on a connection that did or didn't negotiate compression.
+	pub fn bytes(&self, compression: bool) -> &[u8] {
+		match &self.compressed {
+			Some(compressed) if compression => compressed,
+			_ => &self.plain,
+		}
+	}
+
+	/// Sends several frames with as few syscalls as the transport allows.
+	#[cfg(feature = "async")]
+	pub async fn send_all_to_async_fd<T: Transport>(
+		frames: &[Self],
+		compression: bool,
+		fd: &tokio::io::unix::AsyncFd<T>,
+	) -> Result<(), ProtocolError> {
+		let bytes = frames
+			.iter()
+			.map(|frame| frame.bytes(compression))
+			.collect::<Vec<_>>();
+		let mut bytes = &bytes[..];
+		while !bytes.is_empty() {
+			let mut guard = fd.writable().await?;
+			if let Ok(result) = guard.try_io(|_| match fd.get_ref().send_encoded(bytes) {
+				Err(ProtocolError::WouldBlock) => Err(would_block_err()),
+				def => Ok(def),
+			}) {
+				bytes = &bytes[result??..];
+			}
+		}
+		flush_async_fd(fd).await
+	}
+}
@@ -9,29 +9,102 @@ macro_rules! define_headers {
                 LOWER
             };
         )*
+
+        /// Every header this version of the protocol knows.
+        pub const ALL: &[&str] = &[$($name),*];
     };
 }
 
+pub fn is_known(header: &str) -> bool {
+	ALL.contains(&header)
+}
+
 define_headers! {
 		HELLO,
 		AUTH,
 		AUTH_OK,
 		AUTH_ERROR,
 		FRAMEBUFFER_LINK,
+		FRAMEBUFFER_LINK_FAILED,
+		RELINK_REQUEST,
+		BUFFERS_INVALIDATED,
+		BUFFER_UPLOAD,
 		BUFFER_REQUEST,
 		BUFFER_REQUEST_ACK,
 		BUFFER_RELEASE,
+		BUFFER_RELEASES,
+		PRESENT_GROUP,
+		FRAMES_SKIPPED,
+		FRAMES_DROPPED,
+		PERFORMANCE_WARNING,
 		INPUT_EVENT,
 		MONITOR_ADDED,
 		MONITOR_REMOVED,
+		MONITOR_CHANGED,
 		SESSION_SWITCH,
+		TRANSITION_DEFINE,
 		SESSION_CREATE,
 		SESSION_CREATED,
 		SESSION_READY,
 		SESSION_STATE,
 		SESSION_ACTIVE,
+		SESSION_INACTIVE,
 		SESSION_AWAKE,
 		SESSION_SLEEP,
+		SESSION_VISIBILITY,
+		SESSION_PIP,
+		MONITOR_LAYOUT,
+		MONITOR_HDR,
+		MONITOR_MIRROR,
+		MONITOR_FILTER,
+		ZOOM_SET,
+		SESSION_ASSIGN_MONITOR,
+		FOCUS_IN,
+		FOCUS_OUT,
+		POINTER_LOCK,
+		POINTER_LOCK_STATE,
+		SHORTCUT_REGISTER,
+		SHORTCUT_UNREGISTER,
+		SHORTCUT_TRIGGERED,
+		DEBUG_HUD,
+		BACKGROUND_SET,
+		SPLASH_ENDED,
+		RESUMED,
+		LOG_LEVEL,
+		LOG_DUMP,
+		LOG_RECORDS,
+		WIRE_TAP,
+		SESSION_LIST,
+		SESSIONS,
+		TRANSITIONS_LIST,
+		TRANSITIONS,
+		STATS_REQUEST,
+		STATS,
+		STATE_SYNC_REQUEST,
+		STATE_SYNC,
+		COMPOSITOR_HEALTH_SUBSCRIBE,
+		COMPOSITOR_HEALTH,
+		SESSION_UNRESPONSIVE,
+		SESSION_CRASHED,
+		SCREENSHOT,
+		SCREENSHOT_DATA,
+		SELECTION_OFFER,
+		SELECTION_REQUEST,
+		SELECTION_DATA,
+		SELECTION_POLICY,
+		BACKLIGHT_GET,
+		BACKLIGHTS,
+		BACKLIGHT_SET,
+		DEVICE_CALIBRATION,
+		DEVICE_CONFIGURE,
+		DEVICE_CONFIG_GET,
+		DEVICE_CONFIGS,
+		DEVICE_GRAB,
+		INPUT_INJECT,
+		SWITCH_EVENTS_SUBSCRIBE,
+		LID_CLOSED,
+		TABLET_MODE,
+		SERVER_SHUTDOWN,
 		ERROR,
 		PING,
 		PONG,
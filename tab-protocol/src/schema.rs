@@ -0,0 +1,159 @@
+//! Machine-readable description of the protocol, for implementations in other languages. Built
+//! with the `schema` feature; `tab-protocol dump-schema` prints it.
+//! - a frame is `<header>\n<payload>\n`, with FDs passed alongside as `SCM_RIGHTS`
+//! - `messages` maps every header to how its payload is encoded (`json`, `text` or `none`), the
+//!   JSON Schema of the payload and how many FDs the frame carries
+//! - payload types are JSON Schemas (draft 2020-12) under `$defs`, named after their Rust types
+
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde_json::{Map, Value, json};
+
+use crate::*;
+
+/// How a message's payload line is encoded.
+enum Payload {
+	/// An empty payload line.
+	None,
+	Json(Schema),
+	/// Plain text, `buffer_args` of the buffer messages.
+	Text(Schema),
+}
+
+/// The whole protocol as one JSON document, see the module docs.
+pub fn protocol_schema() -> Value {
+	let mut generator = SchemaGenerator::default();
+	let mut messages = Map::new();
+	for header in message_header::ALL {
+		let payload =
+			payload(header, &mut generator).unwrap_or_else(|| panic!("no payload schema for {header}"));
+		let (encoding, schema) = match payload {
+			Payload::None => ("none", Value::Null),
+			Payload::Json(schema) => ("json", schema.to_value()),
+			Payload::Text(schema) => ("text", schema.to_value()),
+		};
+		let fds = allowed_fds(header);
+		messages.insert(
+			header.to_string(),
+			json!({
+				"payload": encoding,
+				"schema": schema,
+				"fds": { "min": fds.start(), "max": fds.end() },
+			}),
+		);
+	}
+	json!({
+		"$schema": "https://json-schema.org/draft/2020-12/schema",
+		"protocol": PROTOCOL_VERSION,
+		"messages": messages,
+		"$defs": generator.take_definitions(true),
+	})
+}
+
+fn json<T: JsonSchema>(generator: &mut SchemaGenerator) -> Payload {
+	Payload::Json(generator.subschema_for::<T>())
+}
+
+fn buffer_args() -> Payload {
+	Payload::Text(json_schema!({
+		"type": "string",
+		"description": "<monitor_id> <0|1> [generation], leaving out a 0 generation",
+		"pattern": "^\\S+ [01]( [0-9]+)?$",
+	}))
+}
+
+fn buffer_request_args() -> Payload {
+	Payload::Text(json_schema!({
+		"type": "string",
+		"description": "<monitor_id> <0|1> [generation] [group=<present_group>], leaving out a 0 generation",
+		"pattern": "^\\S+ [01]( [0-9]+)?( group=[0-9]+)?$",
+	}))
+}
+
+/// The payload `header` carries, `None` for a header this version doesn't know.
+fn payload(header: &str, generator: &mut SchemaGenerator) -> Option<Payload> {
+	use message_header::*;
+
+	let g = generator;
+	Some(match header {
+		HELLO => json::<HelloPayload>(g),
+		AUTH => json::<AuthPayload>(g),
+		AUTH_OK => json::<AuthOkPayload>(g),
+		AUTH_ERROR => json::<AuthErrorPayload>(g),
+		FRAMEBUFFER_LINK => json::<FramebufferLinkPayload>(g),
+		FRAMEBUFFER_LINK_FAILED => json::<FramebufferLinkFailedPayload>(g),
+		RELINK_REQUEST => json::<RelinkRequestPayload>(g),
+		BUFFERS_INVALIDATED => json::<BuffersInvalidatedPayload>(g),
+		BUFFER_UPLOAD => json::<BufferUploadPayload>(g),
+		BUFFER_REQUEST => buffer_request_args(),
+		BUFFER_REQUEST_ACK | BUFFER_RELEASE => buffer_args(),
+		BUFFER_RELEASES => json::<BufferReleasesPayload>(g),
+		PRESENT_GROUP => json::<PresentGroupPayload>(g),
+		FRAMES_SKIPPED => json::<FramesSkippedPayload>(g),
+		FRAMES_DROPPED => json::<FramesDroppedPayload>(g),
+		PERFORMANCE_WARNING => json::<PerformanceWarningPayload>(g),
+		INPUT_EVENT => json::<InputEventPayload>(g),
+		MONITOR_ADDED => json::<MonitorAddedPayload>(g),
+		MONITOR_REMOVED => json::<MonitorRemovedPayload>(g),
+		MONITOR_CHANGED => json::<MonitorChangedPayload>(g),
+		SESSION_SWITCH => json::<SessionSwitchPayload>(g),
+		TRANSITION_DEFINE => json::<TransitionDefinePayload>(g),
+		SESSION_CREATE => json::<SessionCreatePayload>(g),
+		SESSION_CREATED => json::<SessionCreatedPayload>(g),
+		SESSION_READY => json::<SessionReadyPayload>(g),
+		SESSION_STATE => json::<SessionStatePayload>(g),
+		SESSION_ACTIVE => json::<SessionActivePayload>(g),
+		SESSION_INACTIVE => json::<SessionInactivePayload>(g),
+		SESSION_AWAKE => json::<SessionAwakePayload>(g),
+		SESSION_SLEEP => json::<SessionSleepPayload>(g),
+		SESSION_VISIBILITY => json::<SessionVisibilityPayload>(g),
+		SESSION_PIP => json::<SessionPipPayload>(g),
+		MONITOR_LAYOUT => json::<MonitorLayoutPayload>(g),
+		MONITOR_HDR => json::<MonitorHdrPayload>(g),
+		MONITOR_MIRROR => json::<MonitorMirrorPayload>(g),
+		MONITOR_FILTER => json::<MonitorFilterPayload>(g),
+		ZOOM_SET => json::<ZoomSetPayload>(g),
+		SESSION_ASSIGN_MONITOR => json::<SessionAssignMonitorPayload>(g),
+		FOCUS_IN | FOCUS_OUT => json::<FocusPayload>(g),
+		POINTER_LOCK => json::<PointerLockPayload>(g),
+		POINTER_LOCK_STATE => json::<PointerLockStatePayload>(g),
+		SHORTCUT_REGISTER => json::<ShortcutRegisterPayload>(g),
+		SHORTCUT_UNREGISTER => json::<ShortcutUnregisterPayload>(g),
+		SHORTCUT_TRIGGERED => json::<ShortcutTriggeredPayload>(g),
+		DEBUG_HUD => json::<DebugHudPayload>(g),
+		BACKGROUND_SET => json::<BackgroundSetPayload>(g),
+		LOG_LEVEL => json::<LogLevelPayload>(g),
+		LOG_DUMP => json::<LogDumpPayload>(g),
+		LOG_RECORDS => json::<LogRecordsPayload>(g),
+		WIRE_TAP => json::<WireTapPayload>(g),
+		SESSIONS => json::<SessionsPayload>(g),
+		TRANSITIONS => json::<TransitionsPayload>(g),
+		STATS => json::<StatsPayload>(g),
+		STATE_SYNC => json::<StateSyncPayload>(g),
+		COMPOSITOR_HEALTH_SUBSCRIBE => json::<CompositorHealthSubscribePayload>(g),
+		COMPOSITOR_HEALTH => json::<CompositorHealthPayload>(g),
+		SESSION_UNRESPONSIVE => json::<SessionUnresponsivePayload>(g),
+		SESSION_CRASHED => json::<SessionCrashedPayload>(g),
+		SCREENSHOT => json::<ScreenshotPayload>(g),
+		SCREENSHOT_DATA => json::<ScreenshotDataPayload>(g),
+		SELECTION_OFFER => json::<SelectionOfferPayload>(g),
+		SELECTION_DATA => json::<SelectionDataPayload>(g),
+		SELECTION_POLICY => json::<SelectionPolicyPayload>(g),
+		BACKLIGHTS => json::<BacklightsPayload>(g),
+		BACKLIGHT_SET => json::<BacklightSetPayload>(g),
+		DEVICE_CALIBRATION => json::<DeviceCalibrationPayload>(g),
+		DEVICE_CONFIGURE => json::<DeviceConfigurePayload>(g),
+		DEVICE_CONFIGS => json::<DeviceConfigsPayload>(g),
+		DEVICE_GRAB => json::<DeviceGrabPayload>(g),
+		INPUT_INJECT => json::<InputInjectPayload>(g),
+		SWITCH_EVENTS_SUBSCRIBE => json::<SwitchEventsSubscribePayload>(g),
+		LID_CLOSED => json::<LidClosedPayload>(g),
+		TABLET_MODE => json::<TabletModePayload>(g),
+		SERVER_SHUTDOWN => json::<ServerShutdownPayload>(g),
+		ERROR => json::<ErrorPayload>(g),
+		SPLASH_ENDED | RESUMED | SESSION_LIST | TRANSITIONS_LIST | STATS_REQUEST
+		| STATE_SYNC_REQUEST | SELECTION_REQUEST | BACKLIGHT_GET | DEVICE_CONFIG_GET | PING | PONG => {
+			Payload::None
+		}
+		_ => return None,
+	})
+}
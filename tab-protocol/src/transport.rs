@@ -0,0 +1,441 @@
+//! Byte transports Tab frames can travel over.
+//! - Unix domain sockets carry FDs next to frames (dma-bufs, fences)
+//! - TCP and vsock streams can't, so sessions on them present frames with `buffer_upload` instead
+
+use nix::errno::Errno;
+use nix::fcntl::{FcntlArg, OFlag, fcntl};
+use nix::poll::{PollFd, PollFlags, PollTimeout, poll};
+use nix::sys::socket::{
+	AddressFamily, Backlog, MsgFlags, MultiHeaders, SockFlag, SockType, VsockAddr, accept4, bind,
+	connect, listen, recv, send, sendmmsg, sendmsg, socket,
+};
+use std::fmt::{self, Display};
+use std::io::IoSlice;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, PoisonError};
+
+use crate::ProtocolError;
+use crate::message_frame::{TabMessageFrame, recv_into_vec};
+
+/// `VMADDR_CID_ANY`: listen on every vsock context id.
+const VMADDR_CID_ANY: u32 = u32::MAX;
+/// `VMADDR_CID_HOST`: the hypervisor host, as seen from a guest.
+const VMADDR_CID_HOST: u32 = 2;
+
+/// A connected byte stream that Tab frames can be sent over.
+pub trait Transport: AsRawFd {
+	/// Whether frames may carry file descriptors on this transport.
+	fn supports_fd_passing(&self) -> bool;
+	/// Sends one whole frame. Returns `WouldBlock` if nothing was written; a frame written only in
+	/// part is kept for [`Transport::flush`].
+	fn send_frame(&self, frame: &TabMessageFrame) -> Result<(), ProtocolError>;
+	/// Sends frames that are already encoded, see [`SharedFrame`](crate::SharedFrame), in as few
+	/// syscalls as the transport allows. Returns how many were sent, fewer than all once the
+	/// socket is full, or `WouldBlock` if none were. Like with `send_frame`, a frame written only
+	/// in part is kept for [`Transport::flush`].
+	fn send_encoded(&self, frames: &[&[u8]]) -> Result<usize, ProtocolError>;
+	/// Writes the rest of frames sent only in part. Returns `WouldBlock` while some is left, and
+	/// nothing else can be sent until then.
+	fn flush(&self) -> Result<(), ProtocolError>;
+	/// Receives the next chunk of bytes, plus any FDs that came with it.
+	fn recv_chunk(&self) -> Result<(Vec<u8>, Vec<OwnedFd>), ProtocolError>;
+}
+
+impl Transport for UnixStream {
+	fn supports_fd_passing(&self) -> bool {
+		true
+	}
+	fn send_frame(&self, frame: &TabMessageFrame) -> Result<(), ProtocolError> {
+		frame.encode_and_send(self)
+	}
+	fn send_encoded(&self, frames: &[&[u8]]) -> Result<usize, ProtocolError> {
+		send_messages(self.as_fd(), frames)
+	}
+	fn flush(&self) -> Result<(), ProtocolError> {
+		// Seqpacket messages go out whole or not at all.
+		Ok(())
+	}
+	fn recv_chunk(&self) -> Result<(Vec<u8>, Vec<OwnedFd>), ProtocolError> {
+		recv_into_vec(self)
+	}
+}
+
+/// Sends each frame as its own seqpacket message, as many as fit with one `sendmmsg`.
+fn send_messages(fd: BorrowedFd<'_>, frames: &[&[u8]]) -> Result<usize, ProtocolError> {
+	let mut sent_total = 0;
+	while sent_total < frames.len() {
+		let iovs = frames[sent_total..]
+			.iter()
+			.map(|frame| [IoSlice::new(frame)])
+			.collect::<Vec<_>>();
+		let addrs = vec![None; iovs.len()];
+		let mut headers = MultiHeaders::<()>::preallocate(iovs.len(), None);
+		match sendmmsg(
+			fd.as_raw_fd(),
+			&mut headers,
+			&iovs,
+			addrs,
+			[],
+			MsgFlags::MSG_NOSIGNAL,
+		) {
+			Ok(sent) => {
+				let sent = sent.count();
+				if sent == 0 {
+					return Err(ProtocolError::UnexpectedEof);
+				}
+				sent_total += sent;
+			}
+			Err(Errno::EINTR) => continue,
+			Err(Errno::EAGAIN) if sent_total == 0 => return Err(ProtocolError::WouldBlock),
+			Err(Errno::EAGAIN) => break,
+			Err(errno) => return Err(ProtocolError::Nix(errno)),
+		}
+	}
+	Ok(sent_total)
+}
+
+/// Waits until `transport` wrote the rest of frames sent only in part, for callers that may block
+/// their thread. Async ones wait for the socket to be writable between [`Transport::flush`]es.
+pub fn flush_blocking(transport: &(impl Transport + ?Sized)) -> Result<(), ProtocolError> {
+	loop {
+		match transport.flush() {
+			Err(ProtocolError::WouldBlock) => {}
+			result => return result,
+		}
+		// SAFETY: `transport` owns the fd and outlives the poll.
+		let fd = unsafe { BorrowedFd::borrow_raw(transport.as_raw_fd()) };
+		match poll(
+			&mut [PollFd::new(fd, PollFlags::POLLOUT)],
+			PollTimeout::NONE,
+		) {
+			Ok(_) | Err(Errno::EINTR) => {}
+			Err(errno) => return Err(ProtocolError::Nix(errno)),
+		}
+	}
+}
+
+/// TCP or vsock connection. Frames go over it as plain bytes, without FDs.
+#[derive(Debug)]
+pub struct StreamTransport {
+	fd: OwnedFd,
+	/// The rest of a frame the socket had no room for. A frame must never be left half-written,
+	/// so it goes out before anything else does.
+	unsent: Mutex<Vec<u8>>,
+}
+
+impl StreamTransport {
+	pub(crate) fn from_connected(fd: OwnedFd) -> Self {
+		Self {
+			fd,
+			unsent: Mutex::default(),
+		}
+	}
+
+	/// Writes all of `bytes`, gathered with `sendmsg`, after what's left of earlier frames.
+	fn write_all_vectored(&self, bytes: &[&[u8]]) -> Result<(), ProtocolError> {
+		let mut unsent = self.unsent.lock().unwrap_or_else(PoisonError::into_inner);
+		self.write_unsent(&mut unsent)?;
+		let mut iovs = bytes
+			.iter()
+			.map(|bytes| IoSlice::new(bytes))
+			.collect::<Vec<_>>();
+		let mut iovs = &mut iovs[..];
+		IoSlice::advance_slices(&mut iovs, 0);
+		let mut written_any = false;
+		while !iovs.is_empty() {
+			match sendmsg::<()>(self.as_raw_fd(), iovs, &[], MsgFlags::MSG_NOSIGNAL, None) {
+				Ok(0) => return Err(ProtocolError::UnexpectedEof),
+				Ok(n) => {
+					IoSlice::advance_slices(&mut iovs, n);
+					written_any = true;
+				}
+				Err(Errno::EINTR) => continue,
+				Err(Errno::EAGAIN) if !written_any => return Err(ProtocolError::WouldBlock),
+				Err(Errno::EAGAIN) => {
+					for iov in iovs.iter() {
+						unsent.extend_from_slice(iov);
+					}
+					return Ok(());
+				}
+				Err(errno) => return Err(ProtocolError::Nix(errno)),
+			}
+		}
+		Ok(())
+	}
+
+	fn write_unsent(&self, unsent: &mut Vec<u8>) -> Result<(), ProtocolError> {
+		while !unsent.is_empty() {
+			match send(self.as_raw_fd(), unsent, MsgFlags::MSG_NOSIGNAL) {
+				Ok(0) => return Err(ProtocolError::UnexpectedEof),
+				Ok(n) => {
+					unsent.drain(..n);
+				}
+				Err(Errno::EINTR) => continue,
+				Err(Errno::EAGAIN) => return Err(ProtocolError::WouldBlock),
+				Err(errno) => return Err(ProtocolError::Nix(errno)),
+			}
+		}
+		Ok(())
+	}
+}
+
+impl AsRawFd for StreamTransport {
+	fn as_raw_fd(&self) -> RawFd {
+		self.fd.as_raw_fd()
+	}
+}
+
+impl Transport for StreamTransport {
+	fn supports_fd_passing(&self) -> bool {
+		false
+	}
+	fn send_frame(&self, frame: &TabMessageFrame) -> Result<(), ProtocolError> {
+		if !frame.fds.is_empty() {
+			return Err(ProtocolError::FdPassingUnsupported);
+		}
+		self.write_all_vectored(&[&frame.encoded()])
+	}
+	fn send_encoded(&self, frames: &[&[u8]]) -> Result<usize, ProtocolError> {
+		self.write_all_vectored(frames)?;
+		Ok(frames.len())
+	}
+	fn flush(&self) -> Result<(), ProtocolError> {
+		let mut unsent = self.unsent.lock().unwrap_or_else(PoisonError::into_inner);
+		self.write_unsent(&mut unsent)
+	}
+	fn recv_chunk(&self) -> Result<(Vec<u8>, Vec<OwnedFd>), ProtocolError> {
+		let mut buf = vec![0u8; 64 * 1024];
+		let read = loop {
+			match recv(self.fd.as_raw_fd(), &mut buf, MsgFlags::empty()) {
+				Err(Errno::EINTR) => continue,
+				Err(Errno::EAGAIN) => return Err(ProtocolError::WouldBlock),
+				Err(errno) => return Err(ProtocolError::Nix(errno)),
+				Ok(0) => return Err(ProtocolError::UnexpectedEof),
+				Ok(read) => break read,
+			}
+		};
+		buf.truncate(read);
+		Ok((buf, Vec::new()))
+	}
+}
+
+/// Any of the transports a Tab connection can run on.
+#[derive(Debug)]
+pub enum AnyTransport {
+	Unix(UnixStream),
+	Stream(StreamTransport),
+}
+
+impl AnyTransport {
+	pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), ProtocolError> {
+		match self {
+			Self::Unix(stream) => stream.set_nonblocking(nonblocking)?,
+			Self::Stream(stream) => set_fd_nonblocking(stream.fd.as_raw_fd(), nonblocking)?,
+		}
+		Ok(())
+	}
+}
+
+impl AsRawFd for AnyTransport {
+	fn as_raw_fd(&self) -> RawFd {
+		match self {
+			Self::Unix(stream) => stream.as_raw_fd(),
+			Self::Stream(stream) => stream.as_raw_fd(),
+		}
+	}
+}
+
+impl Transport for AnyTransport {
+	fn supports_fd_passing(&self) -> bool {
+		match self {
+			Self::Unix(stream) => stream.supports_fd_passing(),
+			Self::Stream(stream) => stream.supports_fd_passing(),
+		}
+	}
+	fn send_frame(&self, frame: &TabMessageFrame) -> Result<(), ProtocolError> {
+		match self {
+			Self::Unix(stream) => stream.send_frame(frame),
+			Self::Stream(stream) => stream.send_frame(frame),
+		}
+	}
+	fn send_encoded(&self, frames: &[&[u8]]) -> Result<usize, ProtocolError> {
+		match self {
+			Self::Unix(stream) => stream.send_encoded(frames),
+			Self::Stream(stream) => stream.send_encoded(frames),
+		}
+	}
+	fn flush(&self) -> Result<(), ProtocolError> {
+		match self {
+			Self::Unix(stream) => stream.flush(),
+			Self::Stream(stream) => stream.flush(),
+		}
+	}
+	fn recv_chunk(&self) -> Result<(Vec<u8>, Vec<OwnedFd>), ProtocolError> {
+		match self {
+			Self::Unix(stream) => stream.recv_chunk(),
+			Self::Stream(stream) => stream.recv_chunk(),
+		}
+	}
+}
+
+fn set_fd_nonblocking(fd: RawFd, nonblocking: bool) -> Result<(), ProtocolError> {
+	let mut flags = OFlag::from_bits_retain(fcntl(fd, FcntlArg::F_GETFL)?);
+	flags.set(OFlag::O_NONBLOCK, nonblocking);
+	fcntl(fd, FcntlArg::F_SETFL(flags))?;
+	Ok(())
+}
+
+/// Where a Tab server listens or a client connects.
+///
+/// Written as `unix:<path>`, `tcp:<host>:<port>` or `vsock:<cid>:<port>`; a bare path means `unix:`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportAddr {
+	Unix(PathBuf),
+	Tcp(String),
+	Vsock { cid: u32, port: u32 },
+}
+
+impl FromStr for TransportAddr {
+	type Err = ProtocolError;
+
+	fn from_str(s: &str) -> Result<Self, ProtocolError> {
+		let invalid = || ProtocolError::InvalidAddress(s.to_string());
+		if let Some(path) = s.strip_prefix("unix:") {
+			return Ok(Self::Unix(path.into()));
+		}
+		if let Some(addr) = s.strip_prefix("tcp:") {
+			if addr.rsplit_once(':').is_none() {
+				return Err(invalid());
+			}
+			return Ok(Self::Tcp(addr.to_string()));
+		}
+		if let Some(addr) = s.strip_prefix("vsock:") {
+			let (cid, port) = addr.split_once(':').ok_or_else(invalid)?;
+			let cid = match cid {
+				"any" => VMADDR_CID_ANY,
+				"host" => VMADDR_CID_HOST,
+				cid => cid.parse().map_err(|_| invalid())?,
+			};
+			let port = port.parse().map_err(|_| invalid())?;
+			return Ok(Self::Vsock { cid, port });
+		}
+		Ok(Self::Unix(s.into()))
+	}
+}
+
+impl Display for TransportAddr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Unix(path) => write!(f, "unix:{}", path.display()),
+			Self::Tcp(addr) => write!(f, "tcp:{addr}"),
+			Self::Vsock { cid, port } => write!(f, "vsock:{cid}:{port}"),
+		}
+	}
+}
+
+fn resolve_tcp(addr: &str) -> Result<SocketAddr, ProtocolError> {
+	addr
+		.to_socket_addrs()?
+		.next()
+		.ok_or_else(|| ProtocolError::InvalidAddress(addr.to_string()))
+}
+
+fn stream_socket(family: AddressFamily) -> Result<OwnedFd, ProtocolError> {
+	Ok(socket(
+		family,
+		SockType::Stream,
+		SockFlag::SOCK_CLOEXEC,
+		None,
+	)?)
+}
+
+impl TransportAddr {
+	/// Whether connections on this address can pass FDs.
+	pub fn supports_fd_passing(&self) -> bool {
+		matches!(self, Self::Unix(_))
+	}
+
+	/// Opens a blocking connection to this address.
+	pub fn connect(&self) -> Result<AnyTransport, ProtocolError> {
+		match self {
+			Self::Unix(path) => Ok(AnyTransport::Unix(
+				crate::unix_socket_utils::connect_seqpacket(path)?,
+			)),
+			Self::Tcp(addr) => {
+				let stream = std::net::TcpStream::connect(resolve_tcp(addr)?)?;
+				stream.set_nodelay(true)?;
+				Ok(AnyTransport::Stream(StreamTransport::from_connected(
+					stream.into(),
+				)))
+			}
+			Self::Vsock { cid, port } => {
+				let fd = stream_socket(AddressFamily::Vsock)?;
+				connect(fd.as_raw_fd(), &VsockAddr::new(*cid, *port))?;
+				Ok(AnyTransport::Stream(StreamTransport::from_connected(fd)))
+			}
+		}
+	}
+
+	/// Binds a listener for a TCP or vsock address. Unix listeners are set up by the server itself.
+	pub fn bind_stream_listener(&self) -> Result<StreamListener, ProtocolError> {
+		let fd = match self {
+			Self::Unix(_) => return Err(ProtocolError::InvalidAddress(self.to_string())),
+			Self::Tcp(addr) => std::net::TcpListener::bind(resolve_tcp(addr)?)?.into(),
+			Self::Vsock { cid, port } => {
+				let fd = stream_socket(AddressFamily::Vsock)?;
+				bind(fd.as_raw_fd(), &VsockAddr::new(*cid, *port))?;
+				listen(&fd, Backlog::new(16)?)?;
+				fd
+			}
+		};
+		Ok(StreamListener {
+			fd,
+			tcp: matches!(self, Self::Tcp(_)),
+		})
+	}
+}
+
+/// Listening TCP or vsock socket.
+#[derive(Debug)]
+pub struct StreamListener {
+	fd: OwnedFd,
+	tcp: bool,
+}
+
+impl StreamListener {
+	/// Accepts a pending connection, returned in non-blocking mode.
+	pub fn accept(&self) -> Result<AnyTransport, ProtocolError> {
+		let fd = match accept4(
+			self.fd.as_raw_fd(),
+			SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC,
+		) {
+			Ok(fd) => fd,
+			Err(Errno::EAGAIN) => return Err(ProtocolError::WouldBlock),
+			Err(errno) => return Err(ProtocolError::Nix(errno)),
+		};
+		// SAFETY: accept4 just handed us ownership of this fd.
+		let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+		if self.tcp {
+			let stream = std::net::TcpStream::from(fd);
+			stream.set_nodelay(true)?;
+			return Ok(AnyTransport::Stream(StreamTransport::from_connected(
+				stream.into(),
+			)));
+		}
+		Ok(AnyTransport::Stream(StreamTransport::from_connected(fd)))
+	}
+
+	pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), ProtocolError> {
+		set_fd_nonblocking(self.fd.as_raw_fd(), nonblocking)
+	}
+}
+
+impl AsRawFd for StreamListener {
+	fn as_raw_fd(&self) -> RawFd {
+		self.fd.as_raw_fd()
+	}
+}
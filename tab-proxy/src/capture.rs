@@ -0,0 +1,111 @@
+//! Capture files: one JSON object per frame, in the order the proxy saw them.
+
+use std::{
+	fs::File,
+	io::{BufRead, BufReader, BufWriter, Write},
+	path::Path,
+	sync::Mutex,
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tab_protocol::TabMessageFrame;
+
+use crate::ProxyError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+	ClientToServer,
+	ServerToClient,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+	/// Microseconds since the Unix epoch.
+	pub time_us: u64,
+	/// Connection number, counted from 1 in accept order.
+	pub connection: u64,
+	pub direction: Direction,
+	pub header: String,
+	/// Payload, when it is valid JSON.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub payload: Option<Value>,
+	/// Payload, when it is not JSON.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub raw_payload: Option<String>,
+	/// FDs that came with the frame. Only the count is kept.
+	pub fds: usize,
+}
+
+impl CaptureRecord {
+	pub fn new(connection: u64, direction: Direction, frame: &TabMessageFrame) -> Self {
+		let (payload, raw_payload) = match frame.payload.as_deref() {
+			None => (None, None),
+			Some(text) => match serde_json::from_str(text) {
+				Ok(value) => (Some(value), None),
+				Err(_) => (None, Some(text.to_string())),
+			},
+		};
+		Self {
+			time_us: SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |d| d.as_micros() as u64),
+			connection,
+			direction,
+			header: frame.header.0.clone(),
+			payload,
+			raw_payload,
+			fds: frame.fds.len(),
+		}
+	}
+
+	/// Rebuilds the frame, without its FDs.
+	pub fn to_frame(&self) -> TabMessageFrame {
+		match (&self.payload, &self.raw_payload) {
+			(Some(value), _) => TabMessageFrame::raw(self.header.as_str(), value.to_string()),
+			(None, Some(text)) => TabMessageFrame::raw(self.header.as_str(), text.as_str()),
+			(None, None) => TabMessageFrame::no_payload(self.header.as_str()),
+		}
+	}
+}
+
+/// Appends records to a capture file, shared by every forwarding thread.
+pub struct CaptureWriter {
+	out: Mutex<BufWriter<File>>,
+}
+
+impl CaptureWriter {
+	pub fn create(path: &Path) -> Result<Self, ProxyError> {
+		Ok(Self {
+			out: Mutex::new(BufWriter::new(File::create(path)?)),
+		})
+	}
+
+	pub fn write(&self, record: &CaptureRecord) -> Result<(), ProxyError> {
+		let line = serde_json::to_string(record)?;
+		let mut out = self.out.lock().unwrap();
+		writeln!(out, "{line}")?;
+		// Flushed per record so a capture survives the proxy being killed.
+		out.flush()?;
+		Ok(())
+	}
+}
+
+pub fn read_capture(path: &Path) -> Result<Vec<CaptureRecord>, ProxyError> {
+	let reader = BufReader::new(File::open(path)?);
+	let mut records = Vec::new();
+	for (index, line) in reader.lines().enumerate() {
+		let line = line?;
+		if line.trim().is_empty() {
+			continue;
+		}
+		let record = serde_json::from_str(&line).map_err(|source| ProxyError::Capture {
+			line: index + 1,
+			source,
+		})?;
+		records.push(record);
+	}
+	Ok(records)
+}
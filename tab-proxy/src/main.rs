@@ -0,0 +1,140 @@
+//! tab-proxy: records Tab protocol traffic and replays it, to make protocol bugs reproducible.
+//!
+//! ```text
+//! tab-proxy record --listen /tmp/shift-proxy.sock [--upstream /tmp/shift.sock] --out capture.jsonl
+//! tab-proxy replay --listen /tmp/shift-proxy.sock --capture capture.jsonl [--connection N] [--strict] [--realtime]
+//! ```
+//!
+//! Point clients at the `--listen` socket instead of shift's.
+
+use std::{path::PathBuf, process::ExitCode};
+
+use tab_protocol::{ProtocolError, transport::TransportAddr};
+use thiserror::Error;
+
+mod capture;
+mod record;
+mod replay;
+
+const USAGE: &str = "usage:
+  tab-proxy record --listen <path> [--upstream <addr>] --out <file>
+  tab-proxy replay --listen <path> --capture <file> [--connection <n>] [--strict] [--realtime]";
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+	#[error("{0}")]
+	Usage(String),
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("protocol error: {0}")]
+	Protocol(#[from] ProtocolError),
+	#[error("json error: {0}")]
+	Json(#[from] serde_json::Error),
+	#[error("invalid capture record on line {line}: {source}")]
+	Capture {
+		line: usize,
+		source: serde_json::Error,
+	},
+	#[error("capture file is empty")]
+	EmptyCapture,
+	#[error("capture has no connection {0}")]
+	UnknownConnection(u64),
+}
+
+#[derive(Default)]
+struct Args {
+	listen: Option<PathBuf>,
+	upstream: Option<String>,
+	out: Option<PathBuf>,
+	capture: Option<PathBuf>,
+	connection: Option<u64>,
+	strict: bool,
+	realtime: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, ProxyError> {
+	let mut parsed = Args::default();
+	while let Some(arg) = args.next() {
+		let mut value = || {
+			args
+				.next()
+				.ok_or_else(|| ProxyError::Usage(format!("{arg} needs a value")))
+		};
+		match arg.as_str() {
+			"--listen" => parsed.listen = Some(value()?.into()),
+			"--upstream" => parsed.upstream = Some(value()?),
+			"--out" => parsed.out = Some(value()?.into()),
+			"--capture" => parsed.capture = Some(value()?.into()),
+			"--connection" => {
+				let value = value()?;
+				parsed.connection = Some(
+					value
+						.parse()
+						.map_err(|_| ProxyError::Usage(format!("invalid connection number: {value}")))?,
+				);
+			}
+			"--strict" => parsed.strict = true,
+			"--realtime" => parsed.realtime = true,
+			_ => return Err(ProxyError::Usage(format!("unknown argument: {arg}"))),
+		}
+	}
+	Ok(parsed)
+}
+
+fn run() -> Result<ExitCode, ProxyError> {
+	let mut args = std::env::args().skip(1);
+	let mode = args.next();
+	let args = parse_args(args)?;
+	let required = |value: Option<PathBuf>, name: &str| {
+		value.ok_or_else(|| ProxyError::Usage(format!("missing --{name}")))
+	};
+	match mode.as_deref() {
+		Some("record") => {
+			let upstream = args
+				.upstream
+				.or_else(|| std::env::var("SHIFT_SOCKET").ok())
+				.unwrap_or_else(|| "/tmp/shift.sock".into())
+				.parse::<TransportAddr>()?;
+			record::run(
+				&required(args.listen, "listen")?,
+				&upstream,
+				&required(args.out, "out")?,
+			)?;
+			Ok(ExitCode::SUCCESS)
+		}
+		Some("replay") => {
+			let mismatches = replay::run(
+				&required(args.listen, "listen")?,
+				&required(args.capture, "capture")?,
+				replay::ReplayOptions {
+					connection: args.connection,
+					strict: args.strict,
+					realtime: args.realtime,
+				},
+			)?;
+			Ok(if mismatches == 0 {
+				ExitCode::SUCCESS
+			} else {
+				ExitCode::FAILURE
+			})
+		}
+		_ => Err(ProxyError::Usage(USAGE.into())),
+	}
+}
+
+fn main() -> ExitCode {
+	tracing_subscriber::fmt()
+		.with_env_filter(
+			tracing_subscriber::EnvFilter::try_from_default_env()
+				.unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
+		)
+		.with_target(false)
+		.init();
+	match run() {
+		Ok(code) => code,
+		Err(e) => {
+			eprintln!("tab-proxy: {e}");
+			ExitCode::FAILURE
+		}
+	}
+}
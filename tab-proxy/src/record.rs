@@ -0,0 +1,111 @@
+//! Record mode: forwards every client connection to shift and logs the frames both ways.
+
+use std::{
+	os::{fd::AsRawFd, unix::net::UnixListener},
+	path::Path,
+	sync::Arc,
+	thread,
+};
+
+use nix::sys::socket::{Shutdown, shutdown};
+use tab_protocol::{
+	ProtocolError, TabMessageFrameReader,
+	transport::{AnyTransport, Transport, TransportAddr},
+};
+
+use crate::{
+	ProxyError,
+	capture::{CaptureRecord, CaptureWriter, Direction},
+};
+
+pub fn run(listen: &Path, upstream: &TransportAddr, out: &Path) -> Result<(), ProxyError> {
+	let _ = std::fs::remove_file(listen);
+	let listener = UnixListener::bind(listen)?;
+	let capture = Arc::new(CaptureWriter::create(out)?);
+	tracing::info!(listen = %listen.display(), %upstream, out = %out.display(), "recording");
+
+	let mut next_connection = 1;
+	for client in listener.incoming() {
+		let client = match client {
+			Ok(client) => AnyTransport::Unix(client),
+			Err(e) => {
+				tracing::warn!("failed to accept client: {e}");
+				continue;
+			}
+		};
+		let server = match upstream.connect() {
+			Ok(server) => server,
+			Err(e) => {
+				tracing::error!("failed to connect to {upstream}: {e}");
+				continue;
+			}
+		};
+		let connection = next_connection;
+		next_connection += 1;
+		tracing::info!(connection, "client connected");
+
+		let client = Arc::new(client);
+		let server = Arc::new(server);
+		let capture = Arc::clone(&capture);
+		thread::spawn(move || {
+			let to_client = {
+				let (client, server, capture) = (
+					Arc::clone(&client),
+					Arc::clone(&server),
+					Arc::clone(&capture),
+				);
+				thread::spawn(move || {
+					forward(
+						connection,
+						Direction::ServerToClient,
+						&server,
+						&client,
+						&capture,
+					)
+				})
+			};
+			forward(
+				connection,
+				Direction::ClientToServer,
+				&client,
+				&server,
+				&capture,
+			);
+			let _ = to_client.join();
+			tracing::info!(connection, "connection closed");
+		});
+	}
+	Ok(())
+}
+
+/// Pumps frames from `from` to `to` until either side goes away, then closes both.
+fn forward(
+	connection: u64,
+	direction: Direction,
+	from: &AnyTransport,
+	to: &AnyTransport,
+	capture: &CaptureWriter,
+) {
+	let mut reader = TabMessageFrameReader::new();
+	loop {
+		let frame = match reader.read_framed(from) {
+			Ok(frame) => frame,
+			Err(ProtocolError::UnexpectedEof) => break,
+			Err(e) => {
+				tracing::warn!(connection, ?direction, "read failed: {e}");
+				break;
+			}
+		};
+		if let Err(e) = capture.write(&CaptureRecord::new(connection, direction, &frame)) {
+			tracing::warn!(connection, "failed to write capture record: {e}");
+		}
+		// The proxy's own copies of forwarded FDs are closed when the frame drops.
+		if let Err(e) = to.send_frame(&frame) {
+			tracing::warn!(connection, ?direction, "send failed: {e}");
+			break;
+		}
+	}
+	// Wakes up the thread reading the other direction.
+	let _ = shutdown(from.as_raw_fd(), Shutdown::Both);
+	let _ = shutdown(to.as_raw_fd(), Shutdown::Both);
+}
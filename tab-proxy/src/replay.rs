@@ -0,0 +1,103 @@
+//! Replay mode: plays shift's side of a captured connection against a live client.
+//! - frames shift sent are sent again, in capture order
+//! - frames the client sent are awaited and compared against the capture
+//! - FDs can't be reproduced, frames that carried them are sent without
+
+use std::{os::unix::net::UnixListener, path::Path, thread, time::Duration};
+
+use tab_protocol::{
+	TabMessageFrame, TabMessageFrameReader,
+	transport::{AnyTransport, Transport},
+};
+
+use crate::{
+	ProxyError,
+	capture::{CaptureRecord, Direction, read_capture},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayOptions {
+	/// Connection to replay. Defaults to the first one in the capture.
+	pub connection: Option<u64>,
+	/// Also compare payloads of frames the client sends, not just headers.
+	pub strict: bool,
+	/// Keep the captured delays between frames shift sent.
+	pub realtime: bool,
+}
+
+/// Replays one connection and returns how many client frames didn't match the capture.
+pub fn run(listen: &Path, capture: &Path, options: ReplayOptions) -> Result<usize, ProxyError> {
+	let records = read_capture(capture)?;
+	let connection = options
+		.connection
+		.or_else(|| records.first().map(|r| r.connection))
+		.ok_or(ProxyError::EmptyCapture)?;
+	let records = records
+		.into_iter()
+		.filter(|r| r.connection == connection)
+		.collect::<Vec<_>>();
+	if records.is_empty() {
+		return Err(ProxyError::UnknownConnection(connection));
+	}
+
+	let _ = std::fs::remove_file(listen);
+	let listener = UnixListener::bind(listen)?;
+	tracing::info!(
+		listen = %listen.display(),
+		connection,
+		frames = records.len(),
+		"waiting for a client to replay against"
+	);
+	let (client, _) = listener.accept()?;
+	let client = AnyTransport::Unix(client);
+
+	let mut reader = TabMessageFrameReader::new();
+	let mut mismatches = 0;
+	let mut last_sent_us = None;
+	for (index, record) in records.iter().enumerate() {
+		match record.direction {
+			Direction::ServerToClient => {
+				if options.realtime
+					&& let Some(last) = last_sent_us
+				{
+					thread::sleep(Duration::from_micros(record.time_us.saturating_sub(last)));
+				}
+				last_sent_us = Some(record.time_us);
+				if record.fds > 0 {
+					tracing::warn!(
+						index,
+						header = %record.header,
+						fds = record.fds,
+						"captured frame carried FDs, sending it without"
+					);
+				}
+				client.send_frame(&record.to_frame())?;
+			}
+			Direction::ClientToServer => {
+				let frame = reader.read_framed(&client)?;
+				if let Some(reason) = compare(record, &frame, options.strict) {
+					mismatches += 1;
+					tracing::warn!(index, expected = %record.header, got = %frame.header.0, "{reason}");
+				}
+			}
+		}
+	}
+	tracing::info!(connection, mismatches, "replay finished");
+	Ok(mismatches)
+}
+
+fn compare(expected: &CaptureRecord, got: &TabMessageFrame, strict: bool) -> Option<&'static str> {
+	if expected.header != got.header.0 {
+		return Some("header mismatch");
+	}
+	if expected.fds != got.fds.len() {
+		return Some("fd count mismatch");
+	}
+	if strict {
+		let got = CaptureRecord::new(expected.connection, expected.direction, got);
+		if got.payload != expected.payload || got.raw_payload != expected.raw_payload {
+			return Some("payload mismatch");
+		}
+	}
+	None
+}